@@ -16,8 +16,8 @@ fn main() {
 
     let today = NaiveDate::from_ymd(2019, 12, 11);
     let bond1: Bond = serde_json::from_str(&data).unwrap();
-    let mut conn = Connection::open(":memory:").unwrap();
-    let mut db = SqliteDB{ conn: &mut conn };
+    let conn = Connection::open(":memory:").unwrap();
+    let mut db = SqliteDB::new(&conn);
     db.init().unwrap();
     let market = Market::new(&mut db);
     let cfs1 = bond1.rollout_cash_flows(1., &market).unwrap();
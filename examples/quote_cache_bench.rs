@@ -0,0 +1,113 @@
+//! Benchmark repeated last quote lookups with and without the quote cache
+//!
+//! Run with `cargo run --release --example quote_cache_bench [assets] [rounds]`
+use std::str::FromStr;
+use std::time::Instant;
+
+use chrono::{Duration, NaiveDate, TimeZone, Utc};
+use rusqlite::Connection;
+
+use finql_data::{
+    Asset, AssetHandler, CachedQuoteHandler, Currency, Quote, QuoteCacheConfig, QuoteHandler,
+    Ticker, TickerUsage,
+};
+use finql_sqlite::SqliteDB;
+
+/// Look up the last quote of each asset `rounds` times, as e.g. a valuation refreshed every
+/// few seconds does, and return the sum of all prices
+fn lookup_all(db: &mut dyn QuoteHandler, asset_ids: &[usize], rounds: usize) -> f64 {
+    let time = Utc.ymd(2021, 1, 1).and_hms(12, 0, 0);
+    let mut sum = 0.0;
+    for round in 0..rounds {
+        for asset_id in asset_ids {
+            let lookup_time = time + Duration::milliseconds(round as i64);
+            sum += db
+                .get_last_quote_before_by_id(*asset_id, lookup_time)
+                .unwrap()
+                .0
+                .price;
+        }
+    }
+    sum
+}
+
+fn main() {
+    let args: Vec<String> = std::env::args().collect();
+    let num_assets: usize = args.get(1).map_or(100, |n| n.parse().unwrap());
+    let rounds: usize = args.get(2).map_or(100, |n| n.parse().unwrap());
+
+    let conn = Connection::open(":memory:").unwrap();
+    let mut db = SqliteDB::new(&conn);
+    db.init().unwrap();
+    let start = NaiveDate::from_ymd(2016, 1, 1);
+    let end = NaiveDate::from_ymd(2020, 12, 31);
+
+    print!(
+        "Generating daily quotes from {} to {} for {} assets...",
+        start, end, num_assets
+    );
+    conn.execute_batch("BEGIN;").unwrap();
+    let mut asset_ids = Vec::new();
+    for i in 0..num_assets {
+        let asset_id = db
+            .insert_asset(&Asset::new(None, &format!("asset {}", i), None, None, None))
+            .unwrap();
+        let ticker_id = db
+            .insert_ticker(&Ticker {
+                id: None,
+                name: format!("TICK{}", i),
+                asset: asset_id,
+                source: "manual".to_string(),
+                priority: 1,
+                currency: Currency::from_str("EUR").unwrap(),
+                factor: 1.0,
+                source_url: None,
+                usage: TickerUsage::Both,
+                last_quote_time: None,
+                source_chain: Vec::new(),
+            })
+            .unwrap();
+        let mut date = start;
+        while date <= end {
+            db.insert_quote(&Quote {
+                id: None,
+                ticker: ticker_id,
+                price: 100.0 + (i % 10) as f64,
+                time: Utc.from_utc_datetime(&date.and_hms(17, 30, 0)),
+                volume: None,
+                quality_score: None,
+                source: None,
+                open: None,
+                high: None,
+                low: None,
+                bid: None,
+                ask: None,
+            })
+            .unwrap();
+            date = date.succ();
+        }
+        asset_ids.push(asset_id);
+    }
+    conn.execute_batch("COMMIT;").unwrap();
+    println!("ok");
+
+    let timer = Instant::now();
+    let uncached = lookup_all(&mut db, &asset_ids, rounds);
+    let uncached_time = timer.elapsed();
+    println!("Without cache: {:?}", uncached_time);
+
+    let mut cached_db = CachedQuoteHandler::new(db, QuoteCacheConfig::default());
+    let timer = Instant::now();
+    let cached = lookup_all(&mut cached_db, &asset_ids, rounds);
+    let cached_time = timer.elapsed();
+    println!("With cache:    {:?}", cached_time);
+
+    assert_eq!(uncached, cached);
+    let stats = cached_db.stats();
+    println!(
+        "Cache hits: {}, misses: {}, speedup: {:.1}",
+        stats.hits,
+        stats.misses,
+        uncached_time.as_secs_f64() / cached_time.as_secs_f64()
+    );
+}
@@ -0,0 +1,96 @@
+//! Benchmark loading a quote matrix via the bulk path compared to the sequential path
+//!
+//! Run with `cargo run --release --features rayon --example quote_matrix_bench [assets] [years]`
+use std::str::FromStr;
+use std::time::Instant;
+
+use chrono::{Duration, NaiveDate, TimeZone, Utc};
+use rusqlite::Connection;
+
+use finql::quote_matrix::{load_quote_matrix, load_quote_matrix_sequential};
+use finql_data::{Asset, AssetHandler, Currency, Quote, QuoteHandler, Ticker, TickerUsage};
+use finql_sqlite::SqliteDB;
+
+fn main() {
+    let args: Vec<String> = std::env::args().collect();
+    let num_assets: usize = args.get(1).map_or(200, |n| n.parse().unwrap());
+    let num_years: i64 = args.get(2).map_or(5, |n| n.parse().unwrap());
+
+    let conn = Connection::open(":memory:").unwrap();
+    let mut db = SqliteDB::new(&conn);
+    db.init().unwrap();
+    let start = NaiveDate::from_ymd(2015, 1, 1);
+    let end = start + Duration::days(365 * num_years);
+
+    print!(
+        "Generating {} years of daily quotes for {} assets...",
+        num_years, num_assets
+    );
+    conn.execute_batch("BEGIN;").unwrap();
+    let mut asset_ids = Vec::new();
+    for i in 0..num_assets {
+        let asset_id = db
+            .insert_asset(&Asset::new(None, &format!("asset {}", i), None, None, None))
+            .unwrap();
+        let ticker_id = db
+            .insert_ticker(&Ticker {
+                id: None,
+                name: format!("TICK{}", i),
+                asset: asset_id,
+                source: "manual".to_string(),
+                priority: 1,
+                currency: Currency::from_str("EUR").unwrap(),
+                factor: 1.0,
+                source_url: None,
+                usage: TickerUsage::Both,
+                last_quote_time: None,
+                source_chain: Vec::new(),
+            })
+            .unwrap();
+        let mut date = start;
+        let mut price = 100.0;
+        while date <= end {
+            price *= 1.0
+                + 0.01 * ((date.and_hms(0, 0, 0).timestamp() / 86400 + i as i64) % 7 - 3) as f64;
+            db.insert_quote(&Quote {
+                id: None,
+                ticker: ticker_id,
+                price,
+                time: Utc.from_utc_datetime(&date.and_hms(17, 30, 0)),
+                volume: None,
+                quality_score: None,
+                source: None,
+                open: None,
+                high: None,
+                low: None,
+                bid: None,
+                ask: None,
+            })
+            .unwrap();
+            date = date.succ();
+        }
+        asset_ids.push(asset_id);
+    }
+    conn.execute_batch("COMMIT;").unwrap();
+    println!("ok");
+
+    let timer = Instant::now();
+    let sequential = load_quote_matrix_sequential(&db, &asset_ids, start..=end).unwrap();
+    let sequential_time = timer.elapsed();
+    println!("Sequential load: {:?}", sequential_time);
+
+    let timer = Instant::now();
+    let bulk = load_quote_matrix(&db, &asset_ids, start..=end).unwrap();
+    let bulk_time = timer.elapsed();
+    println!("Bulk load:       {:?}", bulk_time);
+    println!(
+        "Speedup: {:.2}, {} dates, results are {}",
+        sequential_time.as_secs_f64() / bulk_time.as_secs_f64(),
+        bulk.dates.len(),
+        if bulk == sequential {
+            "equal"
+        } else {
+            "different"
+        }
+    );
+}
@@ -8,7 +8,7 @@ use rusqlite::Connection;
 use tokio_test::block_on;
 use chrono::{DateTime, NaiveDate, NaiveDateTime, Local, Utc, TimeZone};
 
-use finql_data::{Asset, Currency, Quote, Ticker};
+use finql_data::{Asset, Currency, Quote, Ticker, TickerUsage};
 use finql::fx_rates::{get_fx_rate, insert_fx_quote};
 use finql::market::Market;
 use finql::market_quotes::MarketDataSource;
@@ -48,6 +48,13 @@ fn quote_tests(market: &mut Market) {
             wkn: None,
             isin: None,
             note: None,
+            reference_currency: None,
+            distribution_policy: None,
+            hedged_to: None,
+            asset_class: None,
+            sector: None,
+            country: None,
+            expected_quote_frequency: None,
         })
         .unwrap();
     let siemens_id = market
@@ -58,6 +65,13 @@ fn quote_tests(market: &mut Market) {
             wkn: None,
             isin: None,
             note: None,
+            reference_currency: None,
+            distribution_policy: None,
+            hedged_to: None,
+            asset_class: None,
+            sector: None,
+            country: None,
+            expected_quote_frequency: None,
         })
         .unwrap();
     let bhp_id = market
@@ -68,6 +82,13 @@ fn quote_tests(market: &mut Market) {
             wkn: None,
             isin: None,
             note: None,
+            reference_currency: None,
+            distribution_policy: None,
+            hedged_to: None,
+            asset_class: None,
+            sector: None,
+            country: None,
+            expected_quote_frequency: None,
         })
         .unwrap();
 
@@ -87,6 +108,10 @@ fn quote_tests(market: &mut Market) {
         priority: 10,
         source: yahoo.to_string(),
         factor: 1.0,
+        source_url: Some("https://finance.yahoo.com/quote/BAS.DE".to_string()),
+        usage: TickerUsage::Both,
+        last_quote_time: None,
+        source_chain: Vec::new(),
     };
     let basf_id = market.db().insert_ticker(&basf).unwrap();
     // Get ticker back
@@ -100,6 +125,10 @@ fn quote_tests(market: &mut Market) {
         currency: eur,
         source: yahoo.to_string(),
         factor: 1.0,
+        source_url: None,
+        usage: TickerUsage::Both,
+        last_quote_time: None,
+        source_chain: Vec::new(),
     };
     let siemens_id = market.db().insert_ticker(&siemens).unwrap();
     // Insert another ticker, with other source
@@ -111,6 +140,10 @@ fn quote_tests(market: &mut Market) {
         currency: eur,
         source: MarketDataSource::Manual.to_string(),
         factor: 1.0,
+        source_url: None,
+        usage: TickerUsage::Both,
+        last_quote_time: None,
+        source_chain: Vec::new(),
     };
     let bhp_id = market.db().insert_ticker(&bhp).unwrap();
     println!("ok");
@@ -128,6 +161,16 @@ fn quote_tests(market: &mut Market) {
     } else {
         println!("failed");
     }
+    log("Get ticker by source url pattern...");
+    let tickers = market
+        .db()
+        .get_tickers_by_source_url_pattern("%finance.yahoo.com%")
+        .unwrap();
+    if tickers.len() == 1 && tickers[0].name == "BAS.DE" {
+        println!("ok");
+    } else {
+        println!("failed");
+    }
 
     // Don't need this ticker anymore, delete
     log("Delete ticker...");
@@ -143,6 +186,13 @@ fn quote_tests(market: &mut Market) {
         price: 67.35,
         time,
         volume: None,
+        quality_score: None,
+        source: None,
+        open: None,
+        high: None,
+        low: None,
+        bid: None,
+        ask: None,
     };
     market.db().insert_quote(&quote).unwrap();
     let time = make_time(2020, 1, 2, 20, 0, 0).unwrap();
@@ -152,6 +202,13 @@ fn quote_tests(market: &mut Market) {
         price: 68.29,
         time,
         volume: None,
+        quality_score: None,
+        source: None,
+        open: None,
+        high: None,
+        low: None,
+        bid: None,
+        ask: None,
     };
     market.db().insert_quote(&quote).unwrap();
     let time = make_time(2020, 1, 3, 20, 0, 0).unwrap();
@@ -161,6 +218,13 @@ fn quote_tests(market: &mut Market) {
         price: 67.27,
         time,
         volume: None,
+        quality_score: None,
+        source: None,
+        open: None,
+        high: None,
+        low: None,
+        bid: None,
+        ask: None,
     };
     market.db().insert_quote(&quote).unwrap();
     let time = make_time(2020, 1, 6, 20, 0, 0).unwrap();
@@ -170,6 +234,13 @@ fn quote_tests(market: &mut Market) {
         price: 66.27,
         time,
         volume: None,
+        quality_score: None,
+        source: None,
+        open: None,
+        high: None,
+        low: None,
+        bid: None,
+        ask: None,
     };
     market.db().insert_quote(&quote).unwrap();
     let time = make_time(2020, 1, 7, 20, 0, 0).unwrap();
@@ -179,6 +250,13 @@ fn quote_tests(market: &mut Market) {
         price: 66.30,
         time,
         volume: None,
+        quality_score: None,
+        source: None,
+        open: None,
+        high: None,
+        low: None,
+        bid: None,
+        ask: None,
     };
     market.db().insert_quote(&quote).unwrap();
     let time = make_time(2020, 1, 8, 20, 0, 0).unwrap();
@@ -188,6 +266,13 @@ fn quote_tests(market: &mut Market) {
         price: 65.73,
         time,
         volume: None,
+        quality_score: None,
+        source: None,
+        open: None,
+        high: None,
+        low: None,
+        bid: None,
+        ask: None,
     };
     let wrong_quote_id = market.db().insert_quote(&wrong_quote).unwrap();
     println!("ok");
@@ -206,6 +291,20 @@ fn quote_tests(market: &mut Market) {
     } else {
         println!("failed");
     }
+    log("get latest quotes for list of ticker...");
+    let latest = market
+        .db()
+        .get_latest_quotes_for_tickers(&[siemens_id, bhp_id, basf_id])
+        .unwrap();
+    if latest.len() == 2
+        && latest[0].0 == siemens_id
+        && latest[1].0 == basf_id
+        && (latest[1].1.price - 66.30).abs() < 1e-10
+    {
+        println!("ok");
+    } else {
+        println!("failed");
+    }
     // correct wrong quote
     log("update quote...");
     wrong_quote.id = Some(wrong_quote_id);
@@ -277,8 +376,8 @@ fn main() {
     );
     match args[1].as_str() {
         "memory" => {
-            let mut conn = Connection::open(":memory:").unwrap();
-            let mut db = SqliteDB{ conn: &mut conn };
+            let conn = Connection::open(":memory:").unwrap();
+            let mut db = SqliteDB::new(&conn);
             db.init().unwrap();
             let mut market = Market::new(&mut db);
             quote_tests(&mut market);
@@ -292,8 +391,8 @@ fn main() {
                     eprintln!("Apparently there exists already a file with this path.");
                     eprintln!("Please provide another path or remove the file, since a new database will be created.");
                 } else {
-                    let mut conn = Connection::open(path).unwrap();
-                    let mut db = SqliteDB{ conn: &mut conn };
+                    let conn = Connection::open(path).unwrap();
+                    let mut db = SqliteDB::new(&conn);
                     db.init().unwrap();
                     let mut market = Market::new(&mut db);            
                     quote_tests(&mut market);
@@ -310,7 +409,7 @@ fn main() {
             } else {
                 let connect_str = &args[2];
                 let mut conn = postgres::Client::connect(connect_str, postgres::NoTls).unwrap();
-                let mut db = PostgresDB{ conn: &mut conn };
+                let mut db = PostgresDB::new(&mut conn);
                 db.clean().unwrap();
                 let mut market = Market::new(&mut db);
                 quote_tests(&mut market);
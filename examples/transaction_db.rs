@@ -6,7 +6,7 @@ use std::str::FromStr;
 use rusqlite::Connection;
 use chrono::NaiveDate;
 
-use finql_data::{Asset, Currency, CashFlow, Transaction, TransactionHandler, TransactionType};
+use finql_data::{Asset, AssetSortKey, Currency, Page, CashFlow, Transaction, TransactionHandler, TransactionType};
 use finql_postgres::PostgresDB;
 use finql_sqlite::SqliteDB;
 
@@ -30,6 +30,16 @@ fn transaction_tests<DB: TransactionHandler>(db: &mut DB) {
     let _assets = db.get_all_assets().unwrap();
     println!("ok");
 
+    print!("Get first page of assets sorted by ISIN...");
+    let (assets, total) = db
+        .get_assets_page(AssetSortKey::Isin, true, Page { number: 0, size: 10 })
+        .unwrap();
+    if assets.len() == total.min(10) {
+        println!("ok");
+    } else {
+        println!("failed, got {} of {} assets", assets.len(), total);
+    }
+
     // put some cash into the account
     print!("Store cash transaction...");
     let eur = Currency::from_str("EUR").unwrap();
@@ -39,6 +49,8 @@ fn transaction_tests<DB: TransactionHandler>(db: &mut DB) {
         transaction_type: TransactionType::Cash,
         cash_flow,
         note: Some("start capital".to_string()),
+        execution_meta: None,
+        recorded_at: None,
     };
     let result = db.insert_transaction(&cash_in);
     match result {
@@ -57,6 +69,8 @@ fn transaction_tests<DB: TransactionHandler>(db: &mut DB) {
         },
         cash_flow,
         note: None,
+        execution_meta: None,
+        recorded_at: None,
     };
     let trans_id = db.insert_transaction(&asset_buy).unwrap();
     println!("ok");
@@ -70,6 +84,8 @@ fn transaction_tests<DB: TransactionHandler>(db: &mut DB) {
         },
         cash_flow: CashFlow::new(-30.0, eur, NaiveDate::from_ymd(2020, 01, 15)),
         note: None,
+        execution_meta: None,
+        recorded_at: None,
     };
     let _ = db.insert_transaction(&fee).unwrap();
     println!("ok");
@@ -81,6 +97,8 @@ fn transaction_tests<DB: TransactionHandler>(db: &mut DB) {
         transaction_type: TransactionType::Dividend { asset_id: 1 },
         cash_flow: CashFlow::new(90.0, eur, NaiveDate::from_ymd(2020, 01, 30)),
         note: None,
+        execution_meta: None,
+        recorded_at: None,
     };
     let dividend_id = db.insert_transaction(&dividend).unwrap();
     println!("ok");
@@ -94,6 +112,8 @@ fn transaction_tests<DB: TransactionHandler>(db: &mut DB) {
         },
         cash_flow: CashFlow::new(-40.0, eur, NaiveDate::from_ymd(2020, 01, 30)),
         note: None,
+        execution_meta: None,
+        recorded_at: None,
     };
     let _ = db.insert_transaction(&tax).unwrap();
     println!("ok");
@@ -118,8 +138,8 @@ fn main() {
     );
     match args[1].as_str() {
         "memory" => {
-            let mut conn = Connection::open(":memory:").unwrap();
-            let mut db = SqliteDB{ conn: &mut conn };
+            let conn = Connection::open(":memory:").unwrap();
+            let mut db = SqliteDB::new(&conn);
             db.init().unwrap();
             transaction_tests(&mut db);
         }
@@ -132,8 +152,8 @@ fn main() {
                     eprintln!("Apparently there exists already a file with this path.");
                     eprintln!("Please provide another path or remove the file, since a new database will be created.");
                 } else {
-                    let mut conn = Connection::open(path).unwrap();
-                    let mut db = SqliteDB{ conn: &mut conn };
+                    let conn = Connection::open(path).unwrap();
+                    let mut db = SqliteDB::new(&conn);
                     db.init().unwrap();
                     transaction_tests(&mut db);
                 }
@@ -148,7 +168,7 @@ fn main() {
             } else {
                 let connect_str = &args[2];
                 let mut conn = postgres::Client::connect(connect_str, postgres::NoTls).unwrap();
-                let mut db = PostgresDB{ conn: &mut conn };
+                let mut db = PostgresDB::new(&mut conn);
                 db.clean().unwrap();
                 transaction_tests(&mut db);
             }
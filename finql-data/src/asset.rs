@@ -1,7 +1,17 @@
 use super::{DataError, DataItem};
 ///! Implementation of a container for basic asset data
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+
+use chrono::NaiveDate;
 use serde::{Deserialize, Serialize};
 
+use crate::asset_handler::AssetHandler;
+use crate::currency::Currency;
+use crate::instrument::InstrumentTerms;
+use crate::quote_handler::QuoteHandler;
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AssetCategory {
     id: usize,
@@ -15,6 +25,64 @@ pub struct Asset {
     pub wkn: Option<String>,
     pub isin: Option<String>,
     pub note: Option<String>,
+    /// Currency the asset is valued in if its ticker are quoted in different currencies
+    pub reference_currency: Option<Currency>,
+    /// Whether the asset, e.g. a fund, pays out or reinvests its income
+    #[serde(default)]
+    pub distribution_policy: Option<DistributionPolicy>,
+    /// Currency the asset is hedged to, e.g. EUR for a EUR-hedged share class of a fund
+    /// investing in USD assets
+    #[serde(default)]
+    pub hedged_to: Option<Currency>,
+    /// Asset class, e.g. equity, bond or commodity
+    #[serde(default)]
+    pub asset_class: Option<String>,
+    #[serde(default)]
+    pub sector: Option<String>,
+    /// Country of risk, usually given as ISO 3166 country code
+    #[serde(default)]
+    pub country: Option<String>,
+    /// Time period quotes are expected at, e.g. `1W` for an open-ended real estate fund
+    /// priced weekly. If not set, the global maximum quote age applies.
+    #[serde(default)]
+    pub expected_quote_frequency: Option<String>,
+}
+
+/// Use of the income of a fund
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DistributionPolicy {
+    /// Income is reinvested, no dividends are paid
+    Accumulating,
+    /// Income is paid out as dividends
+    Distributing,
+    Unknown,
+}
+
+impl fmt::Display for DistributionPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Accumulating => write!(f, "accumulating"),
+            Self::Distributing => write!(f, "distributing"),
+            Self::Unknown => write!(f, "unknown"),
+        }
+    }
+}
+
+impl FromStr for DistributionPolicy {
+    type Err = DataError;
+
+    fn from_str(policy: &str) -> Result<DistributionPolicy, DataError> {
+        match policy {
+            "accumulating" => Ok(Self::Accumulating),
+            "distributing" => Ok(Self::Distributing),
+            "unknown" => Ok(Self::Unknown),
+            _ => Err(DataError::NotFound(format!(
+                "unknown distribution policy '{}'",
+                policy
+            ))),
+        }
+    }
 }
 
 impl Asset {
@@ -31,10 +99,268 @@ impl Asset {
             wkn,
             isin,
             note,
+            reference_currency: None,
+            distribution_policy: None,
+            hedged_to: None,
+            asset_class: None,
+            sector: None,
+            country: None,
+            expected_quote_frequency: None,
+        }
+    }
+
+    /// Set the currency quotes of the asset are converted into
+    pub fn with_reference_currency(mut self, currency: Currency) -> Asset {
+        self.reference_currency = Some(currency);
+        self
+    }
+
+    /// Set whether the asset pays out or reinvests its income
+    pub fn with_distribution_policy(mut self, policy: DistributionPolicy) -> Asset {
+        self.distribution_policy = Some(policy);
+        self
+    }
+
+    /// Set the currency the asset is hedged to
+    pub fn with_hedged_to(mut self, currency: Currency) -> Asset {
+        self.hedged_to = Some(currency);
+        self
+    }
+
+    /// Check whether the asset is known to never pay dividends since it reinvests its income
+    pub fn is_accumulating(&self) -> bool {
+        self.distribution_policy == Some(DistributionPolicy::Accumulating)
+    }
+
+    /// Fill in ISIN, WKN, note, classification, reference currency, distribution policy and hedge
+    /// currency from another asset where they are missing so far. Existing data is never
+    /// overwritten. Returns true if any field has been updated.
+    pub fn merge_from(&mut self, other: &Asset) -> bool {
+        let mut updated = false;
+        for (field, other_field) in [
+            (&mut self.isin, &other.isin),
+            (&mut self.wkn, &other.wkn),
+            (&mut self.note, &other.note),
+            (&mut self.asset_class, &other.asset_class),
+            (&mut self.sector, &other.sector),
+            (&mut self.country, &other.country),
+        ] {
+            if field.is_none() && other_field.is_some() {
+                *field = other_field.clone();
+                updated = true;
+            }
+        }
+        if self.reference_currency.is_none() && other.reference_currency.is_some() {
+            self.reference_currency = other.reference_currency;
+            updated = true;
+        }
+        if self.distribution_policy.is_none() && other.distribution_policy.is_some() {
+            self.distribution_policy = other.distribution_policy;
+            updated = true;
+        }
+        if self.hedged_to.is_none() && other.hedged_to.is_some() {
+            self.hedged_to = other.hedged_to;
+            updated = true;
+        }
+        updated
+    }
+}
+
+/// Key by which lists of assets can be sorted
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum AssetSortKey {
+    Name,
+    Isin,
+    Wkn,
+    Id,
+}
+
+impl AssetSortKey {
+    /// Name of the database column the key refers to
+    pub fn column(&self) -> &'static str {
+        match self {
+            Self::Name => "name",
+            Self::Isin => "isin",
+            Self::Wkn => "wkn",
+            Self::Id => "id",
+        }
+    }
+
+    /// SQL `ORDER BY` expression, assets without a value for the key are always listed last
+    /// and assets with equal values are ordered by id.
+    pub fn order_by(&self, ascending: bool) -> String {
+        let direction = if ascending { "ASC" } else { "DESC" };
+        match self {
+            Self::Id => format!("id {}", direction),
+            _ => format!(
+                "{col} IS NULL, {col} {dir}, id {dir}",
+                col = self.column(),
+                dir = direction
+            ),
+        }
+    }
+}
+
+/// Search for assets matching all of the given criteria, criteria set to `None` are ignored
+///
+/// ```
+/// use finql_data::asset::AssetSearchQuery;
+///
+/// let query = AssetSearchQuery::new()
+///     .name_contains("basf")
+///     .isin_prefix("DE")
+///     .has_quotes(true);
+/// assert_eq!(query.wkn_exact, None);
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct AssetSearchQuery {
+    /// Part of the asset name, compared case-insensitive
+    pub name_contains: Option<String>,
+    pub isin_prefix: Option<String>,
+    pub wkn_exact: Option<String>,
+    /// Whether the asset has (or has not) any ticker
+    pub has_tickers: Option<bool>,
+    /// Whether there are (or are not) any quotes for any ticker of the asset
+    pub has_quotes: Option<bool>,
+    /// Text the asset name contains or ISIN or WKN start with, compared case-insensitive,
+    /// e.g. for autocompletion
+    pub matches_any: Option<String>,
+    /// Maximum number of assets returned
+    pub limit: Option<usize>,
+}
+
+impl AssetSearchQuery {
+    /// Query matching all assets
+    pub fn new() -> AssetSearchQuery {
+        AssetSearchQuery::default()
+    }
+
+    pub fn name_contains(mut self, name: &str) -> AssetSearchQuery {
+        self.name_contains = Some(name.to_string());
+        self
+    }
+
+    pub fn isin_prefix(mut self, prefix: &str) -> AssetSearchQuery {
+        self.isin_prefix = Some(prefix.to_string());
+        self
+    }
+
+    pub fn wkn_exact(mut self, wkn: &str) -> AssetSearchQuery {
+        self.wkn_exact = Some(wkn.to_string());
+        self
+    }
+
+    pub fn has_tickers(mut self, has_tickers: bool) -> AssetSearchQuery {
+        self.has_tickers = Some(has_tickers);
+        self
+    }
+
+    pub fn has_quotes(mut self, has_quotes: bool) -> AssetSearchQuery {
+        self.has_quotes = Some(has_quotes);
+        self
+    }
+
+    pub fn matches_any(mut self, text: &str) -> AssetSearchQuery {
+        self.matches_any = Some(text.to_string());
+        self
+    }
+
+    pub fn limit(mut self, limit: usize) -> AssetSearchQuery {
+        self.limit = Some(limit);
+        self
+    }
+
+    /// Get all assets matching the query, ordered by name
+    pub fn execute(&self, handler: &mut dyn QuoteHandler) -> Result<Vec<Asset>, DataError> {
+        handler.search_assets(self)
+    }
+
+    /// SQL condition on the table `assets` for database backends, together with the values of
+    /// the query parameters. The placeholder of the n-th parameter (starting with 1) is given
+    /// by `placeholder`, e.g. `?1` for sqlite or `$1` for PostgreSQL.
+    pub fn sql_condition(&self, placeholder: impl Fn(usize) -> String) -> (String, Vec<String>) {
+        let mut conditions = Vec::new();
+        let mut params = Vec::new();
+        let mut add_param = |value: String| {
+            params.push(value);
+            placeholder(params.len())
+        };
+        if let Some(name) = &self.name_contains {
+            let param = add_param(format!("%{}%", escape_like(name)));
+            conditions.push(format!("LOWER(name) LIKE LOWER({}) ESCAPE '\\'", param));
+        }
+        if let Some(prefix) = &self.isin_prefix {
+            let param = add_param(format!("{}%", escape_like(prefix)));
+            conditions.push(format!("isin LIKE {} ESCAPE '\\'", param));
+        }
+        if let Some(wkn) = &self.wkn_exact {
+            let param = add_param(wkn.clone());
+            conditions.push(format!("wkn = {}", param));
+        }
+        if let Some(text) = &self.matches_any {
+            let contains = add_param(format!("%{}%", escape_like(text)));
+            let prefix = add_param(format!("{}%", escape_like(text)));
+            conditions.push(format!(
+                "(LOWER(name) LIKE LOWER({contains}) ESCAPE '\\' \
+                OR LOWER(isin) LIKE LOWER({prefix}) ESCAPE '\\' \
+                OR LOWER(wkn) LIKE LOWER({prefix}) ESCAPE '\\')",
+                contains = contains,
+                prefix = prefix
+            ));
+        }
+        let exists = |has: bool, query: &str| {
+            format!("{}EXISTS ({})", if has { "" } else { "NOT " }, query)
+        };
+        if let Some(has_tickers) = self.has_tickers {
+            conditions.push(exists(
+                has_tickers,
+                "SELECT 1 FROM ticker t WHERE t.asset_id = assets.id",
+            ));
+        }
+        if let Some(has_quotes) = self.has_quotes {
+            conditions.push(exists(
+                has_quotes,
+                "SELECT 1 FROM quotes q JOIN ticker t ON q.ticker_id = t.id \
+                WHERE t.asset_id = assets.id",
+            ));
+        }
+        if conditions.is_empty() {
+            conditions.push("1=1".to_string());
+        }
+        (conditions.join(" AND "), params)
+    }
+
+    /// SQL `LIMIT` clause restricting the number of assets, empty if there is no limit
+    pub fn sql_limit(&self) -> String {
+        match self.limit {
+            Some(limit) => format!(" LIMIT {}", limit),
+            None => String::new(),
         }
     }
 }
 
+/// Escape the wildcards of SQL `LIKE` patterns by a backslash
+fn escape_like(s: &str) -> String {
+    s.replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_")
+}
+
+/// Page of a list, starting with page number 0
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Page {
+    pub number: usize,
+    /// Maximum number of items per page
+    pub size: usize,
+}
+
+impl Page {
+    /// Number of items to skip before the page starts
+    pub fn offset(&self) -> usize {
+        self.number * self.size
+    }
+}
+
 impl DataItem for Asset {
     // get id or return error if id hasn't been set yet
     fn get_id(&self) -> Result<usize, DataError> {
@@ -58,3 +384,509 @@ impl DataItem for Asset {
         }
     }
 }
+
+/// Right of the option holder
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum OptionType {
+    Call,
+    Put,
+}
+
+impl fmt::Display for OptionType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Call => write!(f, "call"),
+            Self::Put => write!(f, "put"),
+        }
+    }
+}
+
+impl FromStr for OptionType {
+    type Err = DataError;
+
+    fn from_str(option_type: &str) -> Result<OptionType, DataError> {
+        match option_type {
+            "call" => Ok(Self::Call),
+            "put" => Ok(Self::Put),
+            _ => Err(DataError::NotFound(format!(
+                "unknown option type '{}'",
+                option_type
+            ))),
+        }
+    }
+}
+
+/// Contract terms of an exchange traded (european) option, stored alongside the option asset
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OptionTerms {
+    /// Id of the option asset
+    pub asset_id: usize,
+    /// Id of the underlying asset
+    pub underlying_id: usize,
+    pub strike: f64,
+    pub expiry: NaiveDate,
+    pub option_type: OptionType,
+    /// Number of units of the underlying delivered per option
+    pub contract_size: f64,
+}
+
+impl InstrumentTerms for OptionTerms {
+    const KIND: &'static str = "option";
+    const VERSION: u32 = 1;
+}
+
+/// Tolerance within which the weights of a currency exposure must add up to 1
+const EXPOSURE_TOLERANCE: f64 = 1e-6;
+
+/// Breakdown of the economic currency exposure of an asset, e.g. of a fund investing in
+/// foreign assets, as weights per currency that add up to 1
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CurrencyExposure {
+    pub asset_id: usize,
+    pub weights: Vec<(Currency, f64)>,
+}
+
+impl CurrencyExposure {
+    /// Check that weights are non-negative, each currency is listed at most once and
+    /// that all weights add up to 1
+    pub fn validate(&self) -> Result<(), DataError> {
+        let mut total = 0.0;
+        for (i, (currency, weight)) in self.weights.iter().enumerate() {
+            if !(weight.is_finite() && *weight >= 0.0) {
+                return Err(DataError::InvalidData(format!(
+                    "invalid weight {} of currency {}",
+                    weight, currency
+                )));
+            }
+            if self.weights[..i].iter().any(|(c, _)| c == currency) {
+                return Err(DataError::InvalidData(format!(
+                    "currency {} is listed more than once",
+                    currency
+                )));
+            }
+            total += weight;
+        }
+        if (total - 1.0).abs() > EXPOSURE_TOLERANCE {
+            return Err(DataError::InvalidData(format!(
+                "currency weights of asset {} add up to {} instead of 1",
+                self.asset_id, total
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// Asset held in the form of a certificate or depositary receipt (e.g. an ADR) that represents
+/// a fixed number of units of an underlying asset
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct AssetRepresentation {
+    pub asset_id: usize,
+    pub underlying_id: usize,
+    /// Number of units of the underlying represented by one unit of the asset
+    pub ratio: f64,
+}
+
+impl AssetRepresentation {
+    /// Check that the ratio is positive and the asset does not represent itself
+    pub fn validate(&self) -> Result<(), DataError> {
+        if !(self.ratio.is_finite() && self.ratio > 0.0) {
+            return Err(DataError::InvalidData(format!(
+                "invalid ratio {} of asset {} to its underlying",
+                self.ratio, self.asset_id
+            )));
+        }
+        if self.asset_id == self.underlying_id {
+            return Err(DataError::InvalidData(format!(
+                "asset {} can't represent itself",
+                self.asset_id
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// Kind of identifier assets are matched by, e.g. on import
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum IdentifierKind {
+    Isin,
+    Wkn,
+    /// Exact name
+    Name,
+    /// Name normalized by `normalize_asset_name`
+    NormalizedName,
+}
+
+/// Identifier kinds to match assets by, in order of priority. The first kind any asset
+/// matches by decides, if several assets match by that kind, the match is ambiguous.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct IdentifierPriority(pub Vec<IdentifierKind>);
+
+impl IdentifierPriority {
+    /// Match by ISIN, WKN or exact name, but never by normalized name
+    pub fn strict() -> Self {
+        Self(vec![
+            IdentifierKind::Isin,
+            IdentifierKind::Wkn,
+            IdentifierKind::Name,
+        ])
+    }
+
+    /// Match by ISIN, WKN or exact name and fall back to normalized names
+    pub fn fuzzy() -> Self {
+        Self(vec![
+            IdentifierKind::Isin,
+            IdentifierKind::Wkn,
+            IdentifierKind::Name,
+            IdentifierKind::NormalizedName,
+        ])
+    }
+}
+
+impl Default for IdentifierPriority {
+    fn default() -> Self {
+        Self::strict()
+    }
+}
+
+/// Legal forms stripped from the end of asset names by `normalize_asset_name`
+const LEGAL_FORMS: [&str; 18] = [
+    "ab", "ag", "asa", "co", "corp", "corporation", "gmbh", "inc", "kg", "kgaa", "llc", "ltd",
+    "nv", "oyj", "plc", "sa", "se", "spa",
+];
+
+/// Normalize an asset name for fuzzy matching: the name is converted to lower case,
+/// punctuation is removed and legal forms like "AG" or "Inc." are stripped from its end,
+/// e.g. "Bayer AG" and "BAYER. ag" are both normalized to "bayer".
+pub fn normalize_asset_name(name: &str) -> String {
+    let lower = name.to_lowercase();
+    let mut words: Vec<&str> = lower
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .collect();
+    while words.len() > 1 && LEGAL_FORMS.contains(words.last().unwrap()) {
+        words.pop();
+    }
+    words.join(" ")
+}
+
+/// In-memory index of all assets for fast lookup by id, ISIN, WKN or name
+/// without accessing the database
+#[derive(Debug, Clone, Default)]
+pub struct AssetIndex {
+    by_id: HashMap<usize, Asset>,
+    by_isin: HashMap<String, usize>,
+    by_wkn: HashMap<String, usize>,
+    by_name: HashMap<String, usize>,
+}
+
+impl AssetIndex {
+    /// Build index of all assets stored in the database
+    pub fn build(handler: &mut dyn AssetHandler) -> Result<Self, DataError> {
+        Ok(Self::from_assets(handler.get_all_assets()?))
+    }
+
+    /// Build index from a list of assets, assets without id are ignored
+    pub fn from_assets(assets: Vec<Asset>) -> Self {
+        let mut index = Self::default();
+        for asset in assets {
+            index.insert(asset);
+        }
+        index
+    }
+
+    /// Add or replace an asset in the index
+    fn insert(&mut self, asset: Asset) {
+        let id = match asset.id {
+            Some(id) => id,
+            None => return,
+        };
+        self.remove(id);
+        if let Some(isin) = &asset.isin {
+            self.by_isin.insert(isin.clone(), id);
+        }
+        if let Some(wkn) = &asset.wkn {
+            self.by_wkn.insert(wkn.clone(), id);
+        }
+        self.by_name.insert(asset.name.clone(), id);
+        self.by_id.insert(id, asset);
+    }
+
+    /// Remove an asset and all its keys from the index
+    fn remove(&mut self, id: usize) {
+        if let Some(asset) = self.by_id.remove(&id) {
+            if let Some(isin) = &asset.isin {
+                self.by_isin.remove(isin);
+            }
+            if let Some(wkn) = &asset.wkn {
+                self.by_wkn.remove(wkn);
+            }
+            self.by_name.remove(&asset.name);
+        }
+    }
+
+    /// Update index with the current assets in the database. Assets that have been
+    /// deleted are removed, new or changed assets are added or replaced.
+    pub fn refresh(&mut self, handler: &mut dyn AssetHandler) -> Result<(), DataError> {
+        self.update(handler.get_all_assets()?);
+        Ok(())
+    }
+
+    /// Update index to match the given complete list of assets
+    fn update(&mut self, assets: Vec<Asset>) {
+        let mut removed: Vec<usize> = self.by_id.keys().copied().collect();
+        removed.retain(|id| !assets.iter().any(|asset| asset.id == Some(*id)));
+        for id in removed {
+            self.remove(id);
+        }
+        for asset in assets {
+            self.insert(asset);
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.by_id.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.by_id.is_empty()
+    }
+
+    pub fn get_by_id(&self, id: usize) -> Option<&Asset> {
+        self.by_id.get(&id)
+    }
+
+    pub fn get_by_isin(&self, isin: &str) -> Option<&Asset> {
+        self.by_isin.get(isin).and_then(|id| self.by_id.get(id))
+    }
+
+    pub fn get_by_wkn(&self, wkn: &str) -> Option<&Asset> {
+        self.by_wkn.get(wkn).and_then(|id| self.by_id.get(id))
+    }
+
+    pub fn get_by_name(&self, name: &str) -> Option<&Asset> {
+        self.by_name.get(name).and_then(|id| self.by_id.get(id))
+    }
+
+    /// Find the asset the identifier refers to, trying the identifier kinds in order of the
+    /// given priority. Fails if several assets match by the first kind any asset matches by.
+    pub fn find(
+        &self,
+        identifier: &str,
+        priority: &IdentifierPriority,
+    ) -> Result<Option<&Asset>, DataError> {
+        for kind in &priority.0 {
+            let mut candidates: Vec<&Asset> = match kind {
+                IdentifierKind::Isin => self.get_by_isin(identifier).into_iter().collect(),
+                IdentifierKind::Wkn => self.get_by_wkn(identifier).into_iter().collect(),
+                IdentifierKind::Name => self.get_by_name(identifier).into_iter().collect(),
+                IdentifierKind::NormalizedName => {
+                    let name = normalize_asset_name(identifier);
+                    self.by_id
+                        .values()
+                        .filter(|asset| normalize_asset_name(&asset.name) == name)
+                        .collect()
+                }
+            };
+            match candidates.len() {
+                0 => continue,
+                1 => return Ok(candidates.pop()),
+                _ => {
+                    candidates.sort_by_key(|asset| asset.id);
+                    let names: Vec<String> = candidates
+                        .iter()
+                        .map(|asset| format!("'{}' (id {})", asset.name, asset.id.unwrap()))
+                        .collect();
+                    return Err(DataError::AmbiguousMatch(format!(
+                        "'{}' matches {}",
+                        identifier,
+                        names.join(", ")
+                    )));
+                }
+            }
+        }
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn asset_search_condition() {
+        let (condition, params) = AssetSearchQuery::new().sql_condition(|n| format!("?{}", n));
+        assert_eq!(condition, "1=1");
+        assert!(params.is_empty());
+        let query = AssetSearchQuery::new()
+            .name_contains("100%_")
+            .wkn_exact("BASF11")
+            .has_tickers(false);
+        let (condition, params) = query.sql_condition(|n| format!("${}", n));
+        assert_eq!(
+            condition,
+            "LOWER(name) LIKE LOWER($1) ESCAPE '\\' AND wkn = $2 \
+            AND NOT EXISTS (SELECT 1 FROM ticker t WHERE t.asset_id = assets.id)"
+        );
+        assert_eq!(params, vec!["%100\\%\\_%".to_string(), "BASF11".to_string()]);
+        assert_eq!(query.sql_limit(), "");
+
+        let query = AssetSearchQuery::new().matches_any("de0").limit(5);
+        let (condition, params) = query.sql_condition(|n| format!("?{}", n));
+        assert_eq!(
+            condition,
+            "(LOWER(name) LIKE LOWER(?1) ESCAPE '\\' OR LOWER(isin) LIKE LOWER(?2) ESCAPE '\\' \
+            OR LOWER(wkn) LIKE LOWER(?2) ESCAPE '\\')"
+        );
+        assert_eq!(params, vec!["%de0%".to_string(), "de0%".to_string()]);
+        assert_eq!(query.sql_limit(), " LIMIT 5");
+    }
+
+    #[test]
+    fn asset_sort_order() {
+        assert_eq!(AssetSortKey::Id.order_by(false), "id DESC");
+        assert_eq!(
+            AssetSortKey::Wkn.order_by(true),
+            "wkn IS NULL, wkn ASC, id ASC"
+        );
+        assert_eq!(Page { number: 3, size: 20 }.offset(), 60);
+    }
+
+    #[test]
+    fn asset_index_lookup_and_update() {
+        let basf = Asset::new(
+            Some(1),
+            "BASF AG",
+            Some("BASF11".to_string()),
+            Some("DE000BASF111".to_string()),
+            None,
+        );
+        let siemens = Asset::new(
+            Some(2),
+            "Siemens AG",
+            None,
+            Some("DE0007236101".to_string()),
+            None,
+        );
+        let mut index = AssetIndex::from_assets(vec![
+            basf.clone(),
+            siemens,
+            Asset::new(None, "not stored", None, None, None),
+        ]);
+        assert_eq!(index.len(), 2);
+        assert_eq!(index.get_by_isin("DE000BASF111").unwrap().id, Some(1));
+        assert_eq!(index.get_by_wkn("BASF11").unwrap().name, "BASF AG");
+        assert_eq!(index.get_by_name("Siemens AG").unwrap().id, Some(2));
+        assert!(index.get_by_wkn("723610").is_none());
+        assert!(index.get_by_name("not stored").is_none());
+
+        // Siemens gets deleted, BASF changes its WKN, a new asset is added
+        let mut basf = basf;
+        basf.wkn = Some("BASF12".to_string());
+        index.update(vec![
+            basf,
+            Asset::new(Some(3), "Apple Inc.", None, None, None),
+        ]);
+        assert_eq!(index.len(), 2);
+        assert!(index.get_by_id(2).is_none());
+        assert!(index.get_by_isin("DE0007236101").is_none());
+        assert!(index.get_by_wkn("BASF11").is_none());
+        assert_eq!(index.get_by_wkn("BASF12").unwrap().id, Some(1));
+        assert_eq!(index.get_by_name("Apple Inc.").unwrap().id, Some(3));
+    }
+
+    #[test]
+    fn match_near_duplicate_names() {
+        assert_eq!(normalize_asset_name("BAYER. ag"), "bayer");
+        assert_eq!(normalize_asset_name("Henkel AG & Co. KGaA"), "henkel");
+        assert_eq!(normalize_asset_name("Apple Inc."), "apple");
+        // a legal form alone is not stripped
+        assert_eq!(normalize_asset_name("SE"), "se");
+
+        let index = AssetIndex::from_assets(vec![
+            Asset::new(Some(1), "BASF AG", Some("BASF11".to_string()), None, None),
+            Asset::new(Some(2), "BASF SE", None, Some("DE000BASF111".to_string()), None),
+            Asset::new(Some(3), "Bayer AG", None, None, None),
+            // WKN equal to the name of another asset
+            Asset::new(Some(4), "Other", Some("Bayer AG".to_string()), None, None),
+        ]);
+        let strict = IdentifierPriority::strict();
+        let fuzzy = IdentifierPriority::fuzzy();
+        let found = |id: &str, priority| index.find(id, priority).unwrap().map(|a| a.id.unwrap());
+
+        assert_eq!(found("DE000BASF111", &strict), Some(2));
+        assert_eq!(found("BASF11", &strict), Some(1));
+        assert_eq!(found("BASF SE", &strict), Some(2));
+        assert_eq!(found("basf", &strict), None);
+        assert_eq!(found("bayer", &strict), None);
+        assert_eq!(found("BAYER ag", &fuzzy), Some(3));
+        // priority decides between different kinds of identifiers
+        assert_eq!(found("Bayer AG", &strict), Some(4));
+        let name_first = IdentifierPriority(vec![IdentifierKind::Name, IdentifierKind::Wkn]);
+        assert_eq!(found("Bayer AG", &name_first), Some(3));
+
+        match index.find("Basf", &fuzzy) {
+            Err(DataError::AmbiguousMatch(err)) => {
+                assert!(err.contains("'BASF AG' (id 1)"));
+                assert!(err.contains("'BASF SE' (id 2)"));
+            }
+            other => panic!("expected ambiguous match, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn merge_asset_data() {
+        let mut asset = Asset::new(Some(1), "BASF AG", Some("BASF11".to_string()), None, None);
+        let richer = Asset::new(
+            Some(2),
+            "BASF SE",
+            Some("BASF99".to_string()),
+            Some("DE000BASF111".to_string()),
+            None,
+        );
+        assert!(asset.merge_from(&richer));
+        assert_eq!(asset.id, Some(1));
+        assert_eq!(asset.name, "BASF AG");
+        assert_eq!(asset.wkn.as_deref(), Some("BASF11"));
+        assert_eq!(asset.isin.as_deref(), Some("DE000BASF111"));
+        assert_eq!(asset.note, None);
+        // nothing left to fill in
+        assert!(!asset.merge_from(&richer));
+        let eur = Currency::from_str("EUR").unwrap();
+        assert!(asset.merge_from(&richer.with_reference_currency(eur)));
+        assert_eq!(asset.reference_currency, Some(eur));
+        assert!(!asset.is_accumulating());
+        let fund = Asset::new(None, "BASF SE", None, None, None)
+            .with_distribution_policy(DistributionPolicy::Accumulating);
+        assert!(asset.merge_from(&fund));
+        assert!(asset.is_accumulating());
+    }
+
+    #[test]
+    fn validate_currency_exposure() {
+        let usd = Currency::from_str("USD").unwrap();
+        let eur = Currency::from_str("EUR").unwrap();
+        let exposure = |weights| CurrencyExposure {
+            asset_id: 1,
+            weights,
+        };
+        let within_tolerance = exposure(vec![(usd, 0.7), (eur, 0.3000001)]);
+        assert!(within_tolerance.validate().is_ok());
+        assert!(exposure(vec![(usd, 0.7), (eur, 0.2)]).validate().is_err());
+        assert!(exposure(vec![(usd, 1.2), (eur, -0.2)]).validate().is_err());
+        assert!(exposure(vec![(usd, 0.5), (usd, 0.5)]).validate().is_err());
+        assert!(exposure(vec![]).validate().is_err());
+    }
+
+    #[test]
+    fn validate_asset_representation() {
+        let representation = |underlying_id, ratio| AssetRepresentation {
+            asset_id: 1,
+            underlying_id,
+            ratio,
+        };
+        assert!(representation(2, 0.5).validate().is_ok());
+        assert!(representation(2, 0.0).validate().is_err());
+        assert!(representation(2, f64::NAN).validate().is_err());
+        assert!(representation(1, 1.0).validate().is_err());
+    }
+}
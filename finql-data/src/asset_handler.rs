@@ -1,11 +1,22 @@
 use super::DataError;
-use crate::asset::Asset;
+use crate::asset::{
+    Asset, AssetIndex, AssetRepresentation, AssetSearchQuery, AssetSortKey, CurrencyExposure,
+    IdentifierPriority, OptionTerms, Page,
+};
 use crate::currency::Currency;
+use crate::instrument::{decode_instrument, set_instrument, InstrumentRecord, InstrumentTerms};
 
 /// Handler for globally available data of transactions and related data
 pub trait AssetHandler {
     // insert, get, update and delete for assets
+    /// Insert a new asset and return its id. If an asset with the same ISIN, WKN or name
+    /// exists already, `DataError::DuplicateAsset` with the id of the existing asset is returned.
+    /// Several assets without ISIN or WKN are allowed.
     fn insert_asset(&mut self, asset: &Asset) -> Result<usize, DataError>;
+    /// Insert the asset unless it exists already and return the id of the new or existing asset,
+    /// see `get_asset_id`. If only another identifier than the one used by `get_asset_id`
+    /// conflicts with an existing asset, the existing asset's id is returned as well, unless
+    /// `rename_asset` is set. Then the asset is inserted with ` (NEW)` appended to its name.
     fn insert_asset_if_new(
         &mut self,
         asset: &Asset,
@@ -15,6 +26,7 @@ pub trait AssetHandler {
             Some(id) => Ok(id),
             None => match self.insert_asset(asset) {
                 Ok(id) => Ok(id),
+                Err(DataError::DuplicateAsset(id, _)) if !rename_asset => Ok(id),
                 Err(err) => {
                     if rename_asset {
                         let new_name = format!("{} (NEW)", asset.name);
@@ -24,6 +36,13 @@ pub trait AssetHandler {
                             wkn: asset.wkn.clone(),
                             isin: asset.isin.clone(),
                             note: asset.note.clone(),
+                            reference_currency: asset.reference_currency,
+                            distribution_policy: asset.distribution_policy,
+                            hedged_to: asset.hedged_to,
+                            asset_class: asset.asset_class.clone(),
+                            sector: asset.sector.clone(),
+                            country: asset.country.clone(),
+                            expected_quote_frequency: asset.expected_quote_frequency.clone(),
                         })
                     } else {
                         Err(err)
@@ -34,11 +53,175 @@ pub trait AssetHandler {
     }
     fn get_asset_id(&mut self, asset: &Asset) -> Option<usize>;
     fn get_asset_by_id(&mut self, id: usize) -> Result<Asset, DataError>;
-    fn get_asset_by_isin(&mut self, id: &str) -> Result<Asset, DataError>;
+    /// Get the asset with the given ISIN. If several assets have this ISIN, which the database
+    /// layout doesn't prevent, `DataError::AmbiguousMatch` is returned.
+    fn get_asset_by_isin(&mut self, isin: &str) -> Result<Asset, DataError>;
+    /// Get the asset with the given WKN, ambiguous matches are handled as by `get_asset_by_isin`
+    fn get_asset_by_wkn(&mut self, wkn: &str) -> Result<Asset, DataError>;
+
+    /// Get the asset the identifier refers to, matching ISIN, WKN or name in order of the
+    /// given priority, see `AssetIndex::find`
+    fn get_asset_by_any_identifier(
+        &mut self,
+        identifier: &str,
+        priority: &IdentifierPriority,
+    ) -> Result<Asset, DataError> {
+        AssetIndex::from_assets(self.get_all_assets()?)
+            .find(identifier, priority)?
+            .cloned()
+            .ok_or_else(|| DataError::NotFound(format!("no asset matches '{}'", identifier)))
+    }
     /// Return a list of all assets ordered by name 
     fn get_all_assets(&mut self) -> Result<Vec<Asset>, DataError>;
+    /// Return a list of all assets sorted by the given key
+    fn get_all_assets_sorted(
+        &mut self,
+        key: AssetSortKey,
+        ascending: bool,
+    ) -> Result<Vec<Asset>, DataError>;
+    /// Return a single page of the sorted list of assets together with the total number of assets
+    fn get_assets_page(
+        &mut self,
+        sort: AssetSortKey,
+        ascending: bool,
+        page: Page,
+    ) -> Result<(Vec<Asset>, usize), DataError>;
+    /// Return a list of all assets matching the search query, ordered by name
+    fn search_assets(&mut self, query: &AssetSearchQuery) -> Result<Vec<Asset>, DataError>;
     fn update_asset(&mut self, asset: &Asset) -> Result<(), DataError>;
+    /// Update the given assets and replace the currency exposures of the given assets within a
+    /// single database transaction, i.e. either all or none of the changes are stored
+    fn update_assets(
+        &mut self,
+        assets: &[Asset],
+        exposures: &[CurrencyExposure],
+    ) -> Result<(), DataError>;
     fn delete_asset(&mut self, id: usize) -> Result<(), DataError>;
     /// We assume here that a currency is an Asset with a three letter name and no ISIN nor WKN
     fn get_all_currencies(&mut self) -> Result<Vec<Currency>, DataError>;
+
+    /// Store the contract terms of an asset, replacing any terms previously stored for the
+    /// asset. Typed access is provided by `set_instrument` and `get_instrument`.
+    fn set_instrument_record(&mut self, record: &InstrumentRecord) -> Result<(), DataError>;
+    /// Get the contract terms of an asset, or None if there are none
+    fn get_instrument_record(
+        &mut self,
+        asset_id: usize,
+    ) -> Result<Option<InstrumentRecord>, DataError>;
+    /// Get the contract terms of all assets of the given kind of instrument, ordered by asset id
+    fn get_instrument_records(&mut self, kind: &str)
+        -> Result<Vec<InstrumentRecord>, DataError>;
+    fn delete_instrument_record(&mut self, asset_id: usize) -> Result<(), DataError>;
+
+    /// Store the contract terms of an option asset
+    fn insert_option_terms(&mut self, terms: &OptionTerms) -> Result<(), DataError> {
+        set_instrument(self, terms.asset_id, terms)
+    }
+    /// Get the contract terms of an asset, or None if the asset is not an option
+    fn get_option_terms(&mut self, asset_id: usize) -> Result<Option<OptionTerms>, DataError> {
+        match self.get_instrument_record(asset_id)? {
+            Some(record) if record.kind == OptionTerms::KIND => {
+                decode_instrument(record).map(Some)
+            }
+            _ => Ok(None),
+        }
+    }
+    fn delete_option_terms(&mut self, asset_id: usize) -> Result<(), DataError> {
+        if self.get_option_terms(asset_id)?.is_some() {
+            self.delete_instrument_record(asset_id)?;
+        }
+        Ok(())
+    }
+
+    /// Store the currency exposure breakdown of an asset, replacing any previously stored
+    /// breakdown. The exposure is validated before it is stored.
+    fn set_currency_exposure(&mut self, exposure: &CurrencyExposure) -> Result<(), DataError>;
+    /// Get the currency exposure breakdown of an asset, or None if there is none
+    fn get_currency_exposure(
+        &mut self,
+        asset_id: usize,
+    ) -> Result<Option<CurrencyExposure>, DataError>;
+    fn delete_currency_exposure(&mut self, asset_id: usize) -> Result<(), DataError>;
+
+    /// Store that an asset represents units of an underlying asset, replacing any previously
+    /// stored relationship. The relationship is validated and rejected if it would make the
+    /// asset (indirectly) represent itself.
+    fn set_asset_representation(
+        &mut self,
+        representation: &AssetRepresentation,
+    ) -> Result<(), DataError>;
+    /// Get the underlying an asset represents, or None if the asset is held directly
+    fn get_asset_representation(
+        &mut self,
+        asset_id: usize,
+    ) -> Result<Option<AssetRepresentation>, DataError>;
+    /// Get all assets representing the given underlying, ordered by asset id
+    fn get_representations_of(
+        &mut self,
+        underlying_id: usize,
+    ) -> Result<Vec<AssetRepresentation>, DataError>;
+    fn delete_asset_representation(&mut self, asset_id: usize) -> Result<(), DataError>;
+    /// Adjust the ratios of all assets representing the given underlying to a split of the
+    /// underlying, where `split_factor` is the number of new units per old unit, e.g. 2 for a
+    /// 2:1 split. Returns the adjusted relationships.
+    fn adjust_representations_for_split(
+        &mut self,
+        underlying_id: usize,
+        split_factor: f64,
+    ) -> Result<Vec<AssetRepresentation>, DataError> {
+        if !(split_factor.is_finite() && split_factor > 0.0) {
+            return Err(DataError::InvalidData(format!(
+                "invalid split factor {}",
+                split_factor
+            )));
+        }
+        let mut adjusted = Vec::new();
+        for mut representation in self.get_representations_of(underlying_id)? {
+            representation.ratio *= split_factor;
+            self.set_asset_representation(&representation)?;
+            adjusted.push(representation);
+        }
+        Ok(adjusted)
+    }
+}
+
+/// Check that storing `representation` would not create a circular relationship by following
+/// the chain of underlyings of its underlying. Intended to be called by implementations of
+/// `AssetHandler::set_asset_representation` before storing the relationship.
+pub fn check_representation_cycle<H: AssetHandler + ?Sized>(
+    db: &mut H,
+    representation: &AssetRepresentation,
+) -> Result<(), DataError> {
+    let mut chain = vec![representation.asset_id];
+    let mut current = representation.underlying_id;
+    loop {
+        if chain.contains(&current) {
+            return Err(DataError::InvalidData(format!(
+                "asset {} can't represent asset {}, the relationship would be circular",
+                representation.asset_id, representation.underlying_id
+            )));
+        }
+        chain.push(current);
+        match db.get_asset_representation(current)? {
+            Some(next) => current = next.underlying_id,
+            None => return Ok(()),
+        }
+    }
+}
+
+/// Update assets and store currency exposures one by one. This does not take care of
+/// atomicity, it is intended to be called by implementations of `AssetHandler::update_assets`
+/// within a database transaction.
+pub fn update_each_asset<H: AssetHandler + ?Sized>(
+    db: &mut H,
+    assets: &[Asset],
+    exposures: &[CurrencyExposure],
+) -> Result<(), DataError> {
+    for asset in assets {
+        db.update_asset(asset)?;
+    }
+    for exposure in exposures {
+        db.set_currency_exposure(exposure)?;
+    }
+    Ok(())
 }
@@ -6,6 +6,7 @@ use serde::{Deserialize, Serialize};
 use chrono::{DateTime, Utc, NaiveDate};
 
 use crate::currency::{Currency, CurrencyConverter, CurrencyError};
+use crate::DataError;
 
 /// Container for an amount of money in some currency
 #[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq)]
@@ -140,24 +141,15 @@ impl CashFlow {
     }
     /// Check, whether cash flows could be aggregated
     pub fn aggregatable(&self, cf: &CashFlow) -> bool {
-        if self.amount.currency != cf.amount.currency || self.date != cf.date {
-            false
-        } else {
-            true
-        }
+        self.amount.currency == cf.amount.currency && self.date == cf.date
     }
 
     /// Compare to cash flows for equality within a given absolute tolerance
     pub fn fuzzy_cash_flows_cmp_eq(&self, cf: &CashFlow, tol: f64) -> bool {
-        if !self.aggregatable(cf) 
-            || self.amount.amount.is_nan()
-            || cf.amount.amount.is_nan()
-            || (self.amount.amount - cf.amount.amount).abs() > tol
-        {
-            false
-        } else {
-            true
-        }
+        self.aggregatable(cf)
+            && !self.amount.amount.is_nan()
+            && !cf.amount.amount.is_nan()
+            && (self.amount.amount - cf.amount.amount).abs() <= tol
     }
 }
 
@@ -177,3 +169,102 @@ impl Neg for CashFlow {
         }
     }
 }
+
+/// Sum up the amounts of all cash flows, which must all be in the given currency
+pub fn sum_cash_flows(flows: &[CashFlow], currency: Currency) -> Result<CashAmount, DataError> {
+    let mut total = CashAmount {
+        amount: 0.0,
+        currency,
+    };
+    for cf in flows {
+        if cf.amount.currency != currency {
+            return Err(DataError::CurrencyMismatch(format!(
+                "{} instead of {}",
+                cf.amount.currency, currency
+            )));
+        }
+        total.amount += cf.amount.amount;
+    }
+    Ok(total)
+}
+
+/// Sum of all inflows minus the sum of all outflows, all flows must be in the given currency
+pub fn net_cash_flow(
+    inflows: &[CashFlow],
+    outflows: &[CashFlow],
+    currency: Currency,
+) -> Result<CashAmount, DataError> {
+    let mut total = sum_cash_flows(inflows, currency)?;
+    total.amount -= sum_cash_flows(outflows, currency)?.amount;
+    Ok(total)
+}
+
+/// Sum of all positive cash flows in the currency of the cash flows, which must be the same for all
+/// cash flows. Since the currency can't be determined otherwise, `flows` must not be empty.
+pub fn total_inflow(flows: &[CashFlow]) -> Result<CashAmount, DataError> {
+    let currency = match flows.first() {
+        Some(cf) => cf.amount.currency,
+        None => {
+            return Err(DataError::NotFound(
+                "can't determine currency of empty list of cash flows".to_string(),
+            ))
+        }
+    };
+    let mut total = CashAmount {
+        amount: 0.0,
+        currency,
+    };
+    for cf in flows {
+        if cf.amount.currency != currency {
+            return Err(DataError::CurrencyMismatch(format!(
+                "{} instead of {}",
+                cf.amount.currency, currency
+            )));
+        }
+        if cf.amount.amount > 0.0 {
+            total.amount += cf.amount.amount;
+        }
+    }
+    Ok(total)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use super::*;
+
+    fn flows(amounts: &[f64], currency: &str) -> Vec<CashFlow> {
+        let currency = Currency::from_str(currency).unwrap();
+        amounts
+            .iter()
+            .map(|amount| CashFlow::new(*amount, currency, NaiveDate::from_ymd(2020, 1, 15)))
+            .collect()
+    }
+
+    #[test]
+    fn sum_and_net_cash_flows() {
+        let eur = Currency::from_str("EUR").unwrap();
+        let inflows = flows(&[100.0, 50.0, -20.0], "EUR");
+        let outflows = flows(&[30.0, 5.5], "EUR");
+        assert!((sum_cash_flows(&inflows, eur).unwrap().amount - 130.0).abs() < 1e-10);
+        assert_eq!(sum_cash_flows(&[], eur).unwrap().amount, 0.0);
+        let net = net_cash_flow(&inflows, &outflows, eur).unwrap();
+        assert_eq!(net.currency, eur);
+        assert!((net.amount - 94.5).abs() < 1e-10);
+        assert!((total_inflow(&inflows).unwrap().amount - 150.0).abs() < 1e-10);
+        assert!(total_inflow(&[]).is_err());
+
+        let mut mixed = inflows.clone();
+        mixed.extend(flows(&[10.0], "USD"));
+        match sum_cash_flows(&mixed, eur) {
+            Err(DataError::CurrencyMismatch(_)) => {}
+            _ => panic!("expected currency mismatch"),
+        }
+        match net_cash_flow(&inflows, &flows(&[1.0], "USD"), eur) {
+            Err(DataError::CurrencyMismatch(_)) => {}
+            _ => panic!("expected currency mismatch"),
+        }
+        assert!(total_inflow(&mixed).is_err());
+    }
+}
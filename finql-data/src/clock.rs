@@ -0,0 +1,58 @@
+//! Source of the current time, to be injected into code depending on it
+
+use std::sync::Mutex;
+
+use chrono::{DateTime, FixedOffset, NaiveDate, Utc};
+
+/// Source of the current time
+///
+/// Library code must not call `Utc::now()` or `Local::now()` directly, but get the
+/// current time from a clock, which allows to inject fixed times in tests or for
+/// reproducible reports.
+pub trait Clock: Send + Sync {
+    /// Current time in UTC
+    fn now_utc(&self) -> DateTime<Utc>;
+
+    /// Current date in the time zone given by its offset to UTC
+    fn today(&self, offset: FixedOffset) -> NaiveDate {
+        self.now_utc().with_timezone(&offset).naive_local().date()
+    }
+}
+
+/// Clock returning the current system time
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now_utc(&self) -> DateTime<Utc> {
+        Utc::now()
+    }
+}
+
+/// Clock returning a given time that only changes when explicitly set or advanced
+#[derive(Debug)]
+pub struct MockClock {
+    time: Mutex<DateTime<Utc>>,
+}
+
+impl MockClock {
+    pub fn new(time: DateTime<Utc>) -> MockClock {
+        MockClock {
+            time: Mutex::new(time),
+        }
+    }
+
+    pub fn set(&self, time: DateTime<Utc>) {
+        *self.time.lock().unwrap() = time;
+    }
+
+    pub fn advance(&self, duration: chrono::Duration) {
+        *self.time.lock().unwrap() += duration;
+    }
+}
+
+impl Clock for MockClock {
+    fn now_utc(&self) -> DateTime<Utc> {
+        *self.time.lock().unwrap()
+    }
+}
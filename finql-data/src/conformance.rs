@@ -0,0 +1,1498 @@
+//! Checks every database backend must pass, run by the test suites of the backends.
+//!
+//! Each check is given an empty, initialized database. It stores an entity, updates every
+//! field and reads it back, and panics if any field differs from what has been written.
+//! This catches e.g. parameters bound to the wrong column of an `UPDATE` statement.
+
+use std::str::FromStr;
+
+use chrono::{DateTime, NaiveDate, TimeZone, Utc};
+
+use crate::{
+    Asset, AssetHandler, AssetRepresentation, AssetSearchQuery, CashFlow, Currency, CurrencyExposure, DataError, DataHandler, DistributionPolicy, FeeSchedule,
+    InstrumentRecord, JobRun, LotSelection, MergedReader, OptionTerms, OptionType, Order, OrderHandler, OrderSide, OrderSize,
+    OrderState, QuotaMonth, Quote, QuoteHandler, QuoteInsertion, QuoteQuery, RebalancingDecision, RebalancingProposal,
+    RebalancingTrade, ReportDefinition, ReportHandler, Ticker, TickerUsage, Transaction,
+    TransactionHandler, TransactionType,
+};
+
+fn currency(code: &str) -> Currency {
+    Currency::from_str(code).unwrap()
+}
+
+/// Time with whole seconds, which all backends store without loss of precision
+fn time(day: u32, hour: u32) -> DateTime<Utc> {
+    Utc.ymd(2021, 3, day).and_hms(hour, 30, 0)
+}
+
+fn insert_asset(db: &mut dyn AssetHandler, name: &str) -> usize {
+    db.insert_asset(&Asset::new(None, name, None, None, None))
+        .unwrap()
+}
+
+fn make_ticker(asset: usize, name: &str) -> Ticker {
+    Ticker {
+        id: None,
+        asset,
+        name: name.to_string(),
+        currency: currency("EUR"),
+        source: "manual".to_string(),
+        priority: 1,
+        factor: 1.0,
+        source_url: None,
+        usage: TickerUsage::Both,
+        last_quote_time: None,
+        source_chain: Vec::new(),
+    }
+}
+
+/// Updating an asset changes all its fields
+pub fn check_asset_update(db: &mut dyn AssetHandler) {
+    let id = db
+        .insert_asset(&Asset::new(
+            None,
+            "BASF AG",
+            Some("BASF11".to_string()),
+            Some("DE000BASF111".to_string()),
+            Some("chemicals".to_string()),
+        ))
+        .unwrap();
+    let mut asset = Asset::new(
+        Some(id),
+        "BASF SE",
+        Some("BASF12".to_string()),
+        Some("DE000BASF112".to_string()),
+        None,
+    );
+    asset.reference_currency = Some(currency("EUR"));
+    asset.distribution_policy = Some(DistributionPolicy::Accumulating);
+    asset.hedged_to = Some(currency("USD"));
+    asset.asset_class = Some("equity".to_string());
+    asset.sector = Some("materials".to_string());
+    asset.country = Some("DE".to_string());
+    asset.expected_quote_frequency = Some("1W".to_string());
+    db.update_asset(&asset).unwrap();
+
+    let stored = db.get_asset_by_id(id).unwrap();
+    assert_eq!(stored.id, Some(id));
+    assert_eq!(stored.name, asset.name);
+    assert_eq!(stored.wkn, asset.wkn);
+    assert_eq!(stored.isin, asset.isin);
+    assert_eq!(stored.note, asset.note);
+    assert_eq!(stored.reference_currency, asset.reference_currency);
+    assert_eq!(stored.distribution_policy, asset.distribution_policy);
+    assert_eq!(stored.hedged_to, asset.hedged_to);
+    assert_eq!(stored.asset_class, asset.asset_class);
+    assert_eq!(stored.sector, asset.sector);
+    assert_eq!(stored.country, asset.country);
+    assert_eq!(stored.expected_quote_frequency, asset.expected_quote_frequency);
+}
+
+/// Updating several assets at once either stores all changes or, if any of them fails,
+/// none of them
+pub fn check_assets_batch_update(db: &mut dyn AssetHandler) {
+    let id = insert_asset(db, "BASF AG");
+    let other_id = insert_asset(db, "Bayer AG");
+    let mut asset = db.get_asset_by_id(id).unwrap();
+    asset.sector = Some("materials".to_string());
+    let mut other = db.get_asset_by_id(other_id).unwrap();
+    other.country = Some("DE".to_string());
+    let exposure = CurrencyExposure {
+        asset_id: other_id,
+        weights: vec![(currency("EUR"), 0.6), (currency("USD"), 0.4)],
+    };
+    let invalid_exposure = CurrencyExposure {
+        asset_id: id,
+        weights: vec![(currency("EUR"), 0.5)],
+    };
+    assert!(db
+        .update_assets(&[asset.clone(), other.clone()], &[invalid_exposure])
+        .is_err());
+    assert_eq!(db.get_asset_by_id(id).unwrap().sector, None);
+    assert_eq!(db.get_asset_by_id(other_id).unwrap().country, None);
+
+    db.update_assets(&[asset, other], std::slice::from_ref(&exposure))
+        .unwrap();
+    assert_eq!(
+        db.get_asset_by_id(id).unwrap().sector.as_deref(),
+        Some("materials")
+    );
+    assert_eq!(
+        db.get_asset_by_id(other_id).unwrap().country.as_deref(),
+        Some("DE")
+    );
+    assert_eq!(db.get_currency_exposure(other_id).unwrap(), Some(exposure));
+}
+
+/// Searching assets by text matches names containing it and ISINs or WKNs starting with it,
+/// ignoring case and treating wildcards literally
+pub fn check_asset_search(db: &mut dyn AssetHandler) {
+    for (name, wkn, isin) in [
+        ("Snap Inc.", "A2DLMS", "US83304A1060"),
+        ("Apple Inc.", "865985", "US0378331005"),
+        ("Happy 100%", "A0B1C2", "DE000A0B1C22"),
+    ] {
+        db.insert_asset(&Asset::new(
+            None,
+            name,
+            Some(wkn.to_string()),
+            Some(isin.to_string()),
+            None,
+        ))
+        .unwrap();
+    }
+    let mut search = |query: AssetSearchQuery| -> Vec<String> {
+        db.search_assets(&query)
+            .unwrap()
+            .into_iter()
+            .map(|asset| asset.name)
+            .collect()
+    };
+    assert_eq!(
+        search(AssetSearchQuery::new().matches_any("app")),
+        vec!["Apple Inc.", "Happy 100%"]
+    );
+    assert_eq!(
+        search(AssetSearchQuery::new().matches_any("us0")),
+        vec!["Apple Inc."]
+    );
+    assert_eq!(
+        search(AssetSearchQuery::new().matches_any("a2dl")),
+        vec!["Snap Inc."]
+    );
+    assert_eq!(
+        search(AssetSearchQuery::new().matches_any("0%")),
+        vec!["Happy 100%"]
+    );
+    assert!(search(AssetSearchQuery::new().matches_any("_nap")).is_empty());
+    assert_eq!(
+        search(AssetSearchQuery::new().matches_any("inc").limit(1)),
+        vec!["Apple Inc."]
+    );
+}
+
+/// Assets are found by ISIN or WKN
+pub fn check_asset_by_identifier(db: &mut dyn AssetHandler) {
+    let apple = db
+        .insert_asset(&Asset::new(
+            None,
+            "Apple Inc.",
+            Some("865985".to_string()),
+            Some("US0378331005".to_string()),
+            None,
+        ))
+        .unwrap();
+    assert_eq!(
+        db.get_asset_by_isin("US0378331005").unwrap().id,
+        Some(apple)
+    );
+    assert_eq!(db.get_asset_by_wkn("865985").unwrap().id, Some(apple));
+    assert!(matches!(
+        db.get_asset_by_isin("865985"),
+        Err(DataError::NotFound(_))
+    ));
+    assert!(matches!(
+        db.get_asset_by_wkn("A2DLMS"),
+        Err(DataError::NotFound(_))
+    ));
+}
+
+/// Inserting an asset with the ISIN, WKN or name of an existing asset fails with the id of
+/// the existing asset, assets without ISIN and WKN are allowed
+pub fn check_asset_duplicates(db: &mut dyn AssetHandler) {
+    let apple = db
+        .insert_asset(&Asset::new(
+            None,
+            "Apple Inc.",
+            Some("865985".to_string()),
+            Some("US0378331005".to_string()),
+            None,
+        ))
+        .unwrap();
+    for (name, wkn, isin) in [
+        ("Apple", Some("A0RF5Z"), Some("US0378331005")),
+        ("Apple", Some("865985"), Some("US0378331006")),
+        ("Apple", Some("865985"), None),
+        ("Apple Inc.", None, None),
+    ] {
+        let asset = Asset::new(
+            None,
+            name,
+            wkn.map(|wkn| wkn.to_string()),
+            isin.map(|isin| isin.to_string()),
+            None,
+        );
+        match db.insert_asset(&asset) {
+            Err(DataError::DuplicateAsset(id, _)) => assert_eq!(id, apple),
+            result => panic!("duplicate of {:?} not detected: {:?}", asset, result),
+        }
+    }
+
+    let no_isin = insert_asset(db, "BASF AG");
+    let other_no_isin = insert_asset(db, "Siemens AG");
+    assert_ne!(no_isin, other_no_isin);
+    db.insert_asset(&Asset::new(
+        None,
+        "Bayer AG",
+        Some("BAY001".to_string()),
+        None,
+        None,
+    ))
+    .unwrap();
+    assert_eq!(db.get_all_assets().unwrap().len(), 4);
+
+    // the WKN conflicts, while the asset is searched by its new ISIN
+    let asset = Asset::new(
+        None,
+        "Apple",
+        Some("865985".to_string()),
+        Some("US0378331006".to_string()),
+        None,
+    );
+    assert_eq!(db.get_asset_id(&asset), None);
+    assert_eq!(db.insert_asset_if_new(&asset, false).unwrap(), apple);
+    assert_eq!(db.get_all_assets().unwrap().len(), 4);
+}
+
+/// Replacing an asset representation changes all its fields, circular relationships are
+/// rejected and leave the stored relationships unchanged
+pub fn check_asset_representation_update(db: &mut dyn AssetHandler) {
+    let share_id = insert_asset(db, "Toyota Motor Corp");
+    let adr_id = insert_asset(db, "Toyota Motor Corp ADR");
+    let other_id = insert_asset(db, "Toyota Motor Corp certificate");
+    assert_eq!(db.get_asset_representation(adr_id).unwrap(), None);
+
+    let adr = AssetRepresentation {
+        asset_id: adr_id,
+        underlying_id: other_id,
+        ratio: 1.0,
+    };
+    db.set_asset_representation(&adr).unwrap();
+    let adr = AssetRepresentation {
+        asset_id: adr_id,
+        underlying_id: share_id,
+        ratio: 2.0,
+    };
+    db.set_asset_representation(&adr).unwrap();
+    assert_eq!(db.get_asset_representation(adr_id).unwrap(), Some(adr));
+
+    let certificate = AssetRepresentation {
+        asset_id: other_id,
+        underlying_id: adr_id,
+        ratio: 0.1,
+    };
+    db.set_asset_representation(&certificate).unwrap();
+    assert_eq!(db.get_representations_of(share_id).unwrap(), vec![adr]);
+    assert_eq!(
+        db.get_representations_of(adr_id).unwrap(),
+        vec![certificate]
+    );
+
+    let circular = AssetRepresentation {
+        asset_id: share_id,
+        underlying_id: other_id,
+        ratio: 1.0,
+    };
+    assert!(db.set_asset_representation(&circular).is_err());
+    let circular = AssetRepresentation {
+        asset_id: adr_id,
+        underlying_id: other_id,
+        ratio: 1.0,
+    };
+    assert!(db.set_asset_representation(&circular).is_err());
+    assert_eq!(db.get_asset_representation(share_id).unwrap(), None);
+    assert_eq!(db.get_asset_representation(adr_id).unwrap(), Some(adr));
+
+    db.delete_asset_representation(adr_id).unwrap();
+    assert_eq!(db.get_asset_representation(adr_id).unwrap(), None);
+    assert_eq!(
+        db.get_asset_representation(other_id).unwrap(),
+        Some(certificate)
+    );
+}
+
+/// Updating a ticker changes all its fields, but leaves other tickers unchanged
+pub fn check_ticker_update(db: &mut dyn QuoteHandler) {
+    let asset = insert_asset(db, "BASF AG");
+    let other_asset = insert_asset(db, "BASF SE");
+    let id = db.insert_ticker(&make_ticker(asset, "BAS.DE")).unwrap();
+    let ticker = Ticker {
+        id: Some(id),
+        asset: other_asset,
+        name: "BAS.F".to_string(),
+        currency: currency("USD"),
+        source: "yahoo".to_string(),
+        priority: 7,
+        factor: 0.01,
+        source_url: Some("https://example.com/BAS".to_string()),
+        usage: TickerUsage::Charting,
+        last_quote_time: None,
+        source_chain: vec!["eod".to_string(), "stooq".to_string()],
+    };
+    // a ticker with all fields set which must neither be changed by inserting nor by updating
+    // another ticker
+    let mut other = Ticker {
+        id: None,
+        name: "BAS.L".to_string(),
+        source: "stooq".to_string(),
+        priority: 3,
+        factor: 100.0,
+        source_url: Some("https://example.com/BAS.L".to_string()),
+        usage: TickerUsage::Valuation,
+        source_chain: vec!["eod".to_string()],
+        ..ticker.clone()
+    };
+    other.id = Some(db.insert_ticker(&other).unwrap());
+    assert_ticker_eq(&db.get_ticker_by_id(other.id.unwrap()).unwrap(), &other);
+
+    db.update_ticker(&ticker).unwrap();
+    assert_ticker_eq(&db.get_ticker_by_id(id).unwrap(), &ticker);
+    assert_ticker_eq(&db.get_ticker_by_id(other.id.unwrap()).unwrap(), &other);
+}
+
+/// Ticker with the same name at different sources are distinct, inserting a ticker again
+/// for the same source returns the existing one
+pub fn check_insert_if_new_ticker(db: &mut dyn QuoteHandler) {
+    let asset = insert_asset(db, "Apple Inc.");
+    let yahoo = Ticker {
+        source: "yahoo".to_string(),
+        ..make_ticker(asset, "AAPL")
+    };
+    let alpha_vantage = Ticker {
+        source: "alpha_vantage".to_string(),
+        ..make_ticker(asset, "AAPL")
+    };
+    let yahoo_id = db.insert_if_new_ticker(&yahoo).unwrap();
+    let alpha_vantage_id = db.insert_if_new_ticker(&alpha_vantage).unwrap();
+    assert_ne!(yahoo_id, alpha_vantage_id);
+    assert_eq!(db.insert_if_new_ticker(&yahoo).unwrap(), yahoo_id);
+    assert_eq!(
+        db.get_ticker_id_for_source("AAPL", "alpha_vantage"),
+        Some(alpha_vantage_id)
+    );
+    assert_eq!(db.get_ticker_id_for_source("AAPL", "stooq"), None);
+    assert_eq!(db.get_all_ticker_for_asset(asset).unwrap().len(), 2);
+}
+
+fn assert_ticker_eq(stored: &Ticker, ticker: &Ticker) {
+    assert_eq!(stored.id, ticker.id);
+    assert_eq!(stored.asset, ticker.asset);
+    assert_eq!(stored.name, ticker.name);
+    assert_eq!(stored.currency, ticker.currency);
+    assert_eq!(stored.source, ticker.source);
+    assert_eq!(stored.priority, ticker.priority);
+    assert_eq!(stored.factor, ticker.factor);
+    assert_eq!(stored.source_url, ticker.source_url);
+    assert_eq!(stored.usage, ticker.usage);
+    assert_eq!(stored.source_chain, ticker.source_chain);
+}
+
+/// Updating a quote changes all its fields, but leaves other quotes unchanged
+pub fn check_quote_update(db: &mut dyn QuoteHandler) {
+    let asset = insert_asset(db, "BASF AG");
+    let ticker = db.insert_ticker(&make_ticker(asset, "BAS.DE")).unwrap();
+    let other_ticker = db.insert_ticker(&make_ticker(asset, "BAS.F")).unwrap();
+    let id = db
+        .insert_quote(&Quote {
+            id: None,
+            ticker,
+            price: 67.35,
+            time: time(1, 17),
+            volume: None,
+            quality_score: None,
+            source: None,
+            open: None,
+            high: None,
+            low: None,
+            bid: None,
+            ask: None,
+        })
+        .unwrap();
+    // the price must differ from all ids to detect ids written into the price
+    let quote = Quote {
+        id: Some(id),
+        ticker: other_ticker,
+        price: 68.5,
+        time: time(2, 9),
+        volume: Some(1200.),
+        quality_score: Some(0.75),
+        source: Some("eod".to_string()),
+        open: Some(67.5),
+        high: Some(69.25),
+        low: Some(67.0),
+        bid: Some(68.45),
+        ask: Some(68.55),
+    };
+    // a quote with all fields set which must neither be changed by inserting nor by updating
+    // another quote
+    let mut other = Quote {
+        id: None,
+        ticker,
+        price: 66.25,
+        time: time(3, 17),
+        volume: Some(800.),
+        quality_score: Some(0.5),
+        source: Some("stooq".to_string()),
+        open: Some(65.75),
+        high: Some(66.5),
+        low: Some(65.5),
+        bid: Some(66.2),
+        ask: Some(66.3),
+    };
+    other.id = Some(db.insert_quote(&other).unwrap());
+    db.update_quote(&quote).unwrap();
+
+    let stored = db.get_all_quotes_for_ticker(other_ticker).unwrap();
+    assert_eq!(stored.len(), 1);
+    assert_quote_eq(&stored[0], &quote);
+    let stored = db.get_all_quotes_for_ticker(ticker).unwrap();
+    assert_eq!(stored.len(), 1);
+    assert_quote_eq(&stored[0], &other);
+}
+
+fn assert_quote_eq(stored: &Quote, quote: &Quote) {
+    assert_eq!(stored.id, quote.id);
+    assert_eq!(stored.ticker, quote.ticker);
+    assert_eq!(stored.price, quote.price);
+    assert_eq!(stored.time, quote.time);
+    assert_eq!(stored.volume, quote.volume);
+    assert_eq!(stored.quality_score, quote.quality_score);
+    assert_eq!(stored.source, quote.source);
+    assert_eq!(stored.open, quote.open);
+    assert_eq!(stored.high, quote.high);
+    assert_eq!(stored.low, quote.low);
+    assert_eq!(stored.bid, quote.bid);
+    assert_eq!(stored.ask, quote.ask);
+}
+
+/// Updating a transaction changes all its fields except the time it has been recorded
+pub fn check_transaction_update(db: &mut dyn TransactionHandler) {
+    let asset_id = insert_asset(db, "BASF AG");
+    let other_asset = insert_asset(db, "BASF SE");
+    let buy = Transaction {
+        id: None,
+        transaction_type: TransactionType::Asset {
+            asset_id,
+            position: 10.,
+        },
+        cash_flow: CashFlow::new(-673.5, currency("EUR"), NaiveDate::from_ymd(2021, 3, 1)),
+        note: None,
+        execution_meta: None,
+        recorded_at: Some(time(1, 18)),
+    };
+    let buy_id = db.insert_transaction(&buy).unwrap();
+    let fee_id = db
+        .insert_transaction(&Transaction {
+            transaction_type: TransactionType::Fee {
+                transaction_ref: None,
+            },
+            cash_flow: CashFlow::new(-5., currency("EUR"), NaiveDate::from_ymd(2021, 3, 1)),
+            ..buy.clone()
+        })
+        .unwrap();
+
+    let mut transaction = Transaction {
+        id: Some(buy_id),
+        transaction_type: TransactionType::Asset {
+            asset_id: other_asset,
+            position: 12.,
+        },
+        cash_flow: CashFlow::new(-822., currency("USD"), NaiveDate::from_ymd(2021, 3, 2)),
+        note: Some("corrected".to_string()),
+        execution_meta: None,
+        recorded_at: Some(time(5, 12)),
+    };
+    transaction
+        .set_execution_meta(&serde_json::json!({"order_id": "A1"}))
+        .unwrap();
+    db.update_transaction(&transaction).unwrap();
+    let stored = db.get_transaction_by_id(buy_id).unwrap();
+    assert_eq!(stored.id, Some(buy_id));
+    match stored.transaction_type {
+        TransactionType::Asset { asset_id, position } => {
+            assert_eq!(asset_id, other_asset);
+            assert_eq!(position, 12.);
+        }
+        _ => panic!("wrong transaction type {:?}", stored.transaction_type),
+    }
+    assert_eq!(
+        stored.cash_flow.amount.amount,
+        transaction.cash_flow.amount.amount
+    );
+    assert_eq!(
+        stored.cash_flow.amount.currency,
+        transaction.cash_flow.amount.currency
+    );
+    assert_eq!(stored.cash_flow.date, transaction.cash_flow.date);
+    assert_eq!(stored.note, transaction.note);
+    assert_eq!(stored.execution_meta, transaction.execution_meta);
+    assert_eq!(stored.recorded_at, buy.recorded_at);
+
+    // references to other transactions are stored in a separate column
+    let fee = Transaction {
+        id: Some(fee_id),
+        transaction_type: TransactionType::Fee {
+            transaction_ref: Some(buy_id),
+        },
+        ..db.get_transaction_by_id(fee_id).unwrap()
+    };
+    db.update_transaction(&fee).unwrap();
+    match db.get_transaction_by_id(fee_id).unwrap().transaction_type {
+        TransactionType::Fee { transaction_ref } => assert_eq!(transaction_ref, Some(buy_id)),
+        other => panic!("wrong transaction type {:?}", other),
+    }
+}
+
+/// Updating an order changes all its fields
+pub fn check_order_update(db: &mut dyn OrderHandler) {
+    let asset_id = insert_asset(db, "BASF AG");
+    let other_asset = insert_asset(db, "BASF SE");
+    let id = db
+        .insert_order(&Order::new(
+            asset_id,
+            OrderSide::Buy,
+            OrderSize::Quantity(10.),
+            currency("EUR"),
+            Some(67.),
+            time(1, 9),
+        ))
+        .unwrap();
+    let order = Order {
+        id: Some(id),
+        asset_id: other_asset,
+        side: OrderSide::Sell,
+        size: OrderSize::Amount(500.),
+        currency: currency("USD"),
+        limit_price: None,
+        state: OrderState::Submitted,
+        created: time(2, 9),
+        updated: time(3, 10),
+        transaction_ids: Vec::new(),
+        note: Some("sell half".to_string()),
+    };
+    db.update_order(&order).unwrap();
+    assert_eq!(db.get_order_by_id(id).unwrap(), order);
+}
+
+/// Setting the fee schedule of an account again replaces all its fields
+pub fn check_fee_schedule_update(db: &mut dyn TransactionHandler) {
+    let schedule = FeeSchedule {
+        account_id: 1,
+        currency: currency("EUR"),
+        rate_pa: 0.001,
+        min_fee: Some(5.),
+        max_fee: None,
+        fixed_fee: 0.,
+        billing_period: "3M".to_string(),
+        start: NaiveDate::from_ymd(2021, 1, 1),
+    };
+    db.set_fee_schedule(&schedule).unwrap();
+    let schedule = FeeSchedule {
+        account_id: 1,
+        currency: currency("USD"),
+        rate_pa: 0.002,
+        min_fee: None,
+        max_fee: Some(50.),
+        fixed_fee: 2.5,
+        billing_period: "1M".to_string(),
+        start: NaiveDate::from_ymd(2021, 4, 1),
+    };
+    db.set_fee_schedule(&schedule).unwrap();
+    assert_eq!(db.get_fee_schedule(1).unwrap(), Some(schedule));
+    assert_eq!(db.get_fee_schedule(2).unwrap(), None);
+    db.delete_fee_schedule(1).unwrap();
+    assert_eq!(db.get_fee_schedule(1).unwrap(), None);
+}
+
+/// Quotes in a time range include quotes exactly at the boundaries and are ordered by time
+pub fn check_quotes_in_range(db: &mut dyn QuoteHandler) {
+    let asset = insert_asset(db, "BASF AG");
+    let ticker = db.insert_ticker(&make_ticker(asset, "BAS.DE")).unwrap();
+    let other_ticker = db.insert_ticker(&make_ticker(asset, "BAS.F")).unwrap();
+    // insert out of order to detect missing ordering
+    for (day, hour) in [(3, 17), (1, 9), (4, 9), (2, 9), (1, 17), (3, 9)] {
+        db.insert_quote(&Quote {
+            id: None,
+            ticker,
+            price: 60.0 + day as f64,
+            time: time(day, hour),
+            volume: None,
+            quality_score: None,
+            source: None,
+            open: None,
+            high: None,
+            low: None,
+            bid: None,
+            ask: None,
+        })
+        .unwrap();
+    }
+    db.insert_quote(&Quote {
+        id: None,
+        ticker: other_ticker,
+        price: 61.0,
+        time: time(2, 9),
+        volume: None,
+        quality_score: None,
+        source: None,
+        open: None,
+        high: None,
+        low: None,
+        bid: None,
+        ask: None,
+    })
+    .unwrap();
+
+    let quotes = db
+        .get_quotes_in_range(ticker, time(1, 17), time(3, 9))
+        .unwrap();
+    let times: Vec<DateTime<Utc>> = quotes.iter().map(|q| q.time).collect();
+    assert_eq!(times, vec![time(1, 17), time(2, 9), time(3, 9)]);
+    assert!(quotes.iter().all(|q| q.ticker == ticker));
+    assert!(db
+        .get_quotes_in_range(ticker, time(5, 9), time(6, 9))
+        .unwrap()
+        .is_empty());
+}
+
+/// Quotes of an asset can be retrieved per ticker source
+pub fn check_quotes_for_source(db: &mut dyn QuoteHandler) {
+    let asset = insert_asset(db, "BASF AG");
+    let other_asset = insert_asset(db, "Siemens AG");
+    let manual = db.insert_ticker(&make_ticker(asset, "BAS.DE")).unwrap();
+    let yahoo = db
+        .insert_ticker(&Ticker {
+            source: "yahoo".to_string(),
+            ..make_ticker(asset, "BAS.F")
+        })
+        .unwrap();
+    let other_manual = db
+        .insert_ticker(&make_ticker(other_asset, "SIE.DE"))
+        .unwrap();
+    for (ticker, day, price) in [
+        (manual, 2, 62.0),
+        (yahoo, 2, 62.5),
+        (manual, 1, 61.0),
+        (yahoo, 1, 61.5),
+        (other_manual, 1, 120.0),
+    ] {
+        db.insert_quote(&Quote {
+            id: None,
+            ticker,
+            price,
+            time: time(day, 17),
+            volume: None,
+            quality_score: None,
+            source: None,
+            open: None,
+            high: None,
+            low: None,
+            bid: None,
+            ask: None,
+        })
+        .unwrap();
+    }
+
+    let quotes = db
+        .get_all_quotes_for_ticker_and_source(asset, "manual")
+        .unwrap();
+    let prices: Vec<(usize, f64)> = quotes.iter().map(|q| (q.ticker, q.price)).collect();
+    assert_eq!(prices, vec![(manual, 61.0), (manual, 62.0)]);
+    let quotes = db
+        .get_all_quotes_for_ticker_and_source(asset, "yahoo")
+        .unwrap();
+    let prices: Vec<(usize, f64)> = quotes.iter().map(|q| (q.ticker, q.price)).collect();
+    assert_eq!(prices, vec![(yahoo, 61.5), (yahoo, 62.5)]);
+    assert!(db
+        .get_all_quotes_for_ticker_and_source(other_asset, "yahoo")
+        .unwrap()
+        .is_empty());
+}
+
+/// The last quote before a time is the latest quote on or before that time, among quotes of
+/// the same time the quote of the ticker with the lowest priority value
+pub fn check_last_quote_before(db: &mut dyn QuoteHandler) {
+    let asset = insert_asset(db, "BASF AG");
+    let secondary = db
+        .insert_ticker(&Ticker {
+            priority: 2,
+            ..make_ticker(asset, "BAS.F")
+        })
+        .unwrap();
+    let primary = db.insert_ticker(&make_ticker(asset, "BAS.DE")).unwrap();
+    for (ticker, day, price) in [
+        (secondary, 1, 61.0),
+        (primary, 2, 62.0),
+        (secondary, 3, 63.5),
+        (primary, 3, 63.0),
+    ] {
+        db.insert_quote(&Quote {
+            id: None,
+            ticker,
+            price,
+            time: time(day, 17),
+            volume: None,
+            quality_score: None,
+            source: None,
+            open: None,
+            high: None,
+            low: None,
+            bid: Some(price - 0.25),
+            ask: Some(price + 0.25),
+        })
+        .unwrap();
+    }
+
+    assert!(db.get_last_quote_before("BASF AG", time(1, 9)).is_err());
+    for (cutoff, ticker, price) in [
+        (time(1, 17), secondary, 61.0),
+        (time(2, 9), secondary, 61.0),
+        (time(2, 17), primary, 62.0),
+        (time(3, 9), primary, 62.0),
+        (time(3, 17), primary, 63.0),
+        (time(20, 9), primary, 63.0),
+    ] {
+        let (quote, quote_currency) = db.get_last_quote_before("BASF AG", cutoff).unwrap();
+        assert_eq!(
+            (quote.ticker, quote.price),
+            (ticker, price),
+            "at {}",
+            cutoff
+        );
+        assert_eq!(quote_currency, currency("EUR"));
+        assert_eq!(
+            (quote.bid, quote.ask),
+            (Some(price - 0.25), Some(price + 0.25))
+        );
+        let (quote, _) = db.get_last_quote_before_by_id(asset, cutoff).unwrap();
+        assert_eq!(
+            (quote.ticker, quote.price),
+            (ticker, price),
+            "at {}",
+            cutoff
+        );
+    }
+}
+
+/// The latest quotes of several ticker are the quotes with the latest time per ticker,
+/// independent of the order of insertion, the priority of the ticker or quotes of other
+/// ticker at the same time. Ticker without quotes are skipped, the result follows the order
+/// of the requested ticker ids.
+pub fn check_latest_quotes_for_tickers(db: &mut dyn QuoteHandler) {
+    let asset = insert_asset(db, "BASF AG");
+    let other_asset = insert_asset(db, "Siemens AG");
+    let primary = db.insert_ticker(&make_ticker(asset, "BAS.DE")).unwrap();
+    // same priority as the primary ticker
+    let secondary = db.insert_ticker(&make_ticker(asset, "BAS.F")).unwrap();
+    let no_quotes = db.insert_ticker(&make_ticker(asset, "BAS.MU")).unwrap();
+    let other = db.insert_ticker(&make_ticker(other_asset, "SIE.DE")).unwrap();
+    // the latest quote of a ticker is not always inserted last
+    for (ticker, day, price) in [
+        (primary, 3, 63.0),
+        (primary, 1, 61.0),
+        (secondary, 3, 63.5),
+        (secondary, 2, 62.5),
+        (other, 2, 102.0),
+        (other, 1, 101.0),
+    ] {
+        db.insert_quote(&Quote {
+            id: None,
+            ticker,
+            price,
+            time: time(day, 17),
+            volume: None,
+            quality_score: None,
+            source: None,
+            open: None,
+            high: None,
+            low: None,
+            bid: None,
+            ask: None,
+        })
+        .unwrap();
+    }
+
+    let latest = |db: &mut dyn QuoteHandler, ticker_ids: &[usize]| {
+        db.get_latest_quotes_for_tickers(ticker_ids)
+            .unwrap()
+            .into_iter()
+            .map(|(id, quote)| {
+                assert_eq!(id, quote.ticker);
+                (id, quote.price, quote.time)
+            })
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(
+        latest(db, &[no_quotes, other, secondary, primary]),
+        vec![
+            (other, 102.0, time(2, 17)),
+            (secondary, 63.5, time(3, 17)),
+            (primary, 63.0, time(3, 17)),
+        ]
+    );
+    assert_eq!(
+        latest(db, &[primary, secondary]),
+        vec![(primary, 63.0, time(3, 17)), (secondary, 63.5, time(3, 17))]
+    );
+    assert!(latest(db, &[no_quotes]).is_empty());
+    assert!(latest(db, &[]).is_empty());
+}
+
+/// Quotes can be deleted per ticker, the number of deleted quotes is returned and the cached
+/// time of the last quote follows the remaining quotes
+pub fn check_delete_quotes(db: &mut dyn QuoteHandler) {
+    let asset = insert_asset(db, "BASF AG");
+    let ticker = db.insert_ticker(&make_ticker(asset, "BAS.DE")).unwrap();
+    let other_ticker = db.insert_ticker(&make_ticker(asset, "BAS.F")).unwrap();
+    for (ticker, day, hour) in [
+        (ticker, 1, 9),
+        (ticker, 1, 17),
+        (ticker, 2, 9),
+        (ticker, 2, 17),
+        (other_ticker, 1, 9),
+    ] {
+        db.insert_quote(&Quote {
+            id: None,
+            ticker,
+            price: 61.0,
+            time: time(day, hour),
+            volume: None,
+            quality_score: None,
+            source: None,
+            open: None,
+            high: None,
+            low: None,
+            bid: None,
+            ask: None,
+        })
+        .unwrap();
+    }
+
+    assert_eq!(db.delete_quotes_before(ticker, time(2, 9)).unwrap(), 2);
+    let times: Vec<DateTime<Utc>> = db
+        .get_all_quotes_for_ticker(ticker)
+        .unwrap()
+        .iter()
+        .map(|q| q.time)
+        .collect();
+    assert_eq!(times, vec![time(2, 9), time(2, 17)]);
+    assert_eq!(db.delete_quotes_before(ticker, time(2, 9)).unwrap(), 0);
+
+    assert_eq!(db.delete_quotes_for_ticker(ticker).unwrap(), 2);
+    assert!(db.get_all_quotes_for_ticker(ticker).unwrap().is_empty());
+    assert_eq!(db.get_ticker_by_id(ticker).unwrap().last_quote_time, None);
+    assert_eq!(db.delete_quotes_for_ticker(ticker).unwrap(), 0);
+    assert_eq!(db.get_all_quotes_for_ticker(other_ticker).unwrap().len(), 1);
+}
+
+/// Deleting the history removes the quotes, transactions and lot selections given, either all
+/// or none of them
+pub fn check_delete_history(db: &mut dyn DataHandler) {
+    let asset_id = insert_asset(db, "BASF AG");
+    let ticker = db.insert_ticker(&make_ticker(asset_id, "BAS.DE")).unwrap();
+    let mut quote_ids = Vec::new();
+    for day in 1..3 {
+        quote_ids.push(
+            db.insert_quote(&Quote {
+                id: None,
+                ticker,
+                price: 61.0,
+                time: time(day, 17),
+                volume: None,
+                quality_score: None,
+                source: None,
+                open: None,
+                high: None,
+                low: None,
+                bid: None,
+                ask: None,
+            })
+            .unwrap(),
+        );
+    }
+    let buy = Transaction {
+        id: None,
+        transaction_type: TransactionType::Asset {
+            asset_id,
+            position: 10.,
+        },
+        cash_flow: CashFlow::new(-610., currency("EUR"), NaiveDate::from_ymd(2021, 3, 1)),
+        note: None,
+        execution_meta: None,
+        recorded_at: Some(time(1, 18)),
+    };
+    let buy_id = db.insert_transaction(&buy).unwrap();
+    let fee_id = db
+        .insert_transaction(&Transaction {
+            transaction_type: TransactionType::Fee {
+                transaction_ref: Some(buy_id),
+            },
+            cash_flow: CashFlow::new(-5., currency("EUR"), NaiveDate::from_ymd(2021, 3, 1)),
+            ..buy.clone()
+        })
+        .unwrap();
+    let sell_id = db
+        .insert_transaction(&Transaction {
+            transaction_type: TransactionType::Asset {
+                asset_id,
+                position: -10.,
+            },
+            cash_flow: CashFlow::new(620., currency("EUR"), NaiveDate::from_ymd(2021, 3, 2)),
+            ..buy.clone()
+        })
+        .unwrap();
+    db.insert_lot_selection(
+        sell_id,
+        &[LotSelection {
+            buy_transaction_id: buy_id,
+            quantity: 10.,
+        }],
+    )
+    .unwrap();
+    let cash_id = db
+        .insert_transaction(&Transaction {
+            transaction_type: TransactionType::Cash,
+            cash_flow: CashFlow::new(1000., currency("EUR"), NaiveDate::from_ymd(2021, 3, 1)),
+            ..buy.clone()
+        })
+        .unwrap();
+
+    db.delete_history(&quote_ids[..1], &[buy_id, fee_id, sell_id])
+        .unwrap();
+    let quotes = db.get_all_quotes_for_ticker(ticker).unwrap();
+    assert_eq!(quotes.len(), 1);
+    assert_eq!(quotes[0].id, Some(quote_ids[1]));
+    let transactions = db.get_all_transactions().unwrap();
+    assert_eq!(transactions.len(), 1);
+    assert_eq!(transactions[0].id, Some(cash_id));
+    assert!(db.get_lot_selection(sell_id).unwrap().is_empty());
+
+    // deleting an unknown quote fails, nothing is deleted
+    assert!(db
+        .delete_history(&[quote_ids[1], quote_ids[1] + 100], &[cash_id])
+        .is_err());
+    assert_eq!(db.get_all_quotes_for_ticker(ticker).unwrap().len(), 1);
+    assert_eq!(db.get_all_transactions().unwrap().len(), 1);
+}
+
+/// A merged reader combines assets with the same ISIN, translates the ids of all handlers
+/// consistently, selects the latest quote of all handlers and rejects all changes
+pub fn check_merged_reader(first: &mut dyn DataHandler, second: &mut dyn DataHandler) {
+    let apple_isin = "US0378331005".to_string();
+    let apple = first
+        .insert_asset(&Asset::new(
+            None,
+            "Apple",
+            None,
+            Some(apple_isin.clone()),
+            None,
+        ))
+        .unwrap();
+    let basf = first
+        .insert_asset(&Asset::new(
+            None,
+            "BASF AG",
+            None,
+            Some("DE000BASF111".to_string()),
+            None,
+        ))
+        .unwrap();
+    // assets are inserted in another order to get colliding ids
+    insert_asset(second, "Siemens AG");
+    let second_apple = second
+        .insert_asset(&Asset::new(
+            None,
+            "Apple",
+            None,
+            Some(apple_isin.clone()),
+            None,
+        ))
+        .unwrap();
+
+    let quote = |ticker, time, price| Quote {
+        id: None,
+        ticker,
+        price,
+        time,
+        volume: None,
+        quality_score: None,
+        source: None,
+        open: None,
+        high: None,
+        low: None,
+        bid: None,
+        ask: None,
+    };
+    let ticker = first.insert_ticker(&make_ticker(apple, "AAPL")).unwrap();
+    first
+        .insert_quote(&quote(ticker, time(1, 17), 100.))
+        .unwrap();
+    let ticker = first.insert_ticker(&make_ticker(basf, "BAS.DE")).unwrap();
+    first
+        .insert_quote(&quote(ticker, time(2, 17), 61.))
+        .unwrap();
+    let ticker = second
+        .insert_ticker(&make_ticker(second_apple, "APC.F"))
+        .unwrap();
+    second
+        .insert_quote(&quote(ticker, time(1, 9), 99.))
+        .unwrap();
+    second
+        .insert_quote(&quote(ticker, time(3, 17), 110.))
+        .unwrap();
+
+    let buy = Transaction {
+        id: None,
+        transaction_type: TransactionType::Asset {
+            asset_id: basf,
+            position: 10.,
+        },
+        cash_flow: CashFlow::new(-610., currency("EUR"), NaiveDate::from_ymd(2021, 3, 1)),
+        note: None,
+        execution_meta: None,
+        recorded_at: Some(time(1, 18)),
+    };
+    let buy_id = first.insert_transaction(&buy).unwrap();
+    first
+        .insert_transaction(&Transaction {
+            transaction_type: TransactionType::Fee {
+                transaction_ref: Some(buy_id),
+            },
+            cash_flow: CashFlow::new(-5., currency("EUR"), NaiveDate::from_ymd(2021, 3, 1)),
+            ..buy.clone()
+        })
+        .unwrap();
+    let second_buy_id = second
+        .insert_transaction(&Transaction {
+            transaction_type: TransactionType::Asset {
+                asset_id: second_apple,
+                position: 5.,
+            },
+            ..buy.clone()
+        })
+        .unwrap();
+    let sell_id = second
+        .insert_transaction(&Transaction {
+            transaction_type: TransactionType::Asset {
+                asset_id: second_apple,
+                position: -5.,
+            },
+            cash_flow: CashFlow::new(650., currency("EUR"), NaiveDate::from_ymd(2021, 3, 3)),
+            ..buy.clone()
+        })
+        .unwrap();
+    second
+        .insert_lot_selection(
+            sell_id,
+            &[LotSelection {
+                buy_transaction_id: second_buy_id,
+                quantity: 5.,
+            }],
+        )
+        .unwrap();
+
+    assert!(MergedReader::new(Vec::new()).is_err());
+    let mut merged = MergedReader::new(vec![first, second]).unwrap();
+
+    let assets = merged.get_all_assets().unwrap();
+    let names: Vec<&str> = assets.iter().map(|a| a.name.as_str()).collect();
+    assert_eq!(names, vec!["Apple", "BASF AG", "Siemens AG"]);
+    let apple = assets[0].id.unwrap();
+    let basf = assets[1].id.unwrap();
+    assert_eq!(
+        merged.get_asset_by_isin(&apple_isin).unwrap().id,
+        Some(apple)
+    );
+    assert_eq!(merged.get_asset_by_id(basf).unwrap().name, "BASF AG");
+
+    let tickers = merged.get_all_ticker_for_asset(apple).unwrap();
+    let mut ticker_names: Vec<&str> = tickers.iter().map(|t| t.name.as_str()).collect();
+    ticker_names.sort_unstable();
+    assert_eq!(ticker_names, vec!["AAPL", "APC.F"]);
+    for ticker in &tickers {
+        assert_eq!(ticker.asset, apple);
+        let id = ticker.id.unwrap();
+        assert_eq!(merged.get_ticker_by_id(id).unwrap().name, ticker.name);
+        for quote in merged.get_all_quotes_for_ticker(id).unwrap() {
+            assert_eq!(quote.ticker, id);
+        }
+    }
+    assert_eq!(merged.get_all_ticker().unwrap().len(), 3);
+
+    // the latest quote of all handlers is selected
+    for (cutoff, ticker, price) in [
+        (time(1, 12), "APC.F", 99.),
+        (time(2, 9), "AAPL", 100.),
+        (time(20, 9), "APC.F", 110.),
+    ] {
+        let (quote, _) = merged.get_last_quote_before("Apple", cutoff).unwrap();
+        assert_eq!(quote.price, price, "at {}", cutoff);
+        assert_eq!(merged.get_ticker_by_id(quote.ticker).unwrap().name, ticker);
+        let (quote, _) = merged.get_last_quote_before_by_id(apple, cutoff).unwrap();
+        assert_eq!(quote.price, price, "at {}", cutoff);
+    }
+    assert!(merged.get_last_quote_before("Apple", time(1, 8)).is_err());
+
+    // paging visits all quotes once, in order of the translated ids
+    let mut quote_ids = Vec::new();
+    let mut after_id = None;
+    loop {
+        let page = merged
+            .get_quotes_page(&QuoteQuery::new(), after_id, 1)
+            .unwrap();
+        match page.as_slice() {
+            [] => break,
+            [quote] => {
+                quote_ids.push(quote.id.unwrap());
+                after_id = quote.id;
+            }
+            _ => panic!("page exceeds limit"),
+        }
+    }
+    assert_eq!(quote_ids.len(), 4);
+    assert!(quote_ids.windows(2).all(|ids| ids[0] < ids[1]));
+
+    let transactions = merged.get_all_transactions().unwrap();
+    assert_eq!(transactions.len(), 4);
+    let fee_ref = match transactions[1].transaction_type {
+        TransactionType::Fee { transaction_ref } => transaction_ref.unwrap(),
+        _ => panic!("fee expected"),
+    };
+    assert_eq!(Some(fee_ref), transactions[0].id);
+    assert!(matches!(
+        merged.get_transaction_by_id(fee_ref).unwrap().transaction_type,
+        TransactionType::Asset { asset_id, .. } if asset_id == basf
+    ));
+    assert!(matches!(
+        transactions[3].transaction_type,
+        TransactionType::Asset { asset_id, .. } if asset_id == apple
+    ));
+    let lots = merged
+        .get_lot_selection(transactions[3].id.unwrap())
+        .unwrap();
+    assert_eq!(lots.len(), 1);
+    assert_eq!(Some(lots[0].buy_transaction_id), transactions[2].id);
+
+    // all changes are rejected
+    assert!(merged
+        .insert_asset(&Asset::new(None, "Daimler AG", None, None, None))
+        .is_err());
+    assert!(merged.delete_asset(basf).is_err());
+    assert!(merged
+        .insert_quote(&quote(quote_ids[0], time(4, 9), 1.))
+        .is_err());
+    assert!(merged.delete_transaction(fee_ref).is_err());
+    assert!(merged.delete_history(&quote_ids, &[fee_ref]).is_err());
+    assert_eq!(merged.get_all_assets().unwrap().len(), 3);
+    assert_eq!(merged.get_all_transactions().unwrap().len(), 4);
+}
+
+/// Bulk inserted quotes get ids in the given order and are stored either all or not at all
+pub fn check_insert_quotes(db: &mut dyn QuoteHandler) {
+    let asset = insert_asset(db, "BASF AG");
+    let ticker = db.insert_ticker(&make_ticker(asset, "BAS.DE")).unwrap();
+    let other_ticker = db.insert_ticker(&make_ticker(asset, "BAS.F")).unwrap();
+    let make_quote = |ticker, day, price| Quote {
+        id: None,
+        ticker,
+        price,
+        time: time(day, 17),
+        volume: Some(1000.0 * day as f64),
+        quality_score: None,
+        source: Some("manual".to_string()),
+        open: Some(price),
+        high: Some(price + 1.0),
+        low: Some(price - 0.5),
+        bid: None,
+        ask: None,
+    };
+    let quotes = vec![
+        make_quote(ticker, 2, 62.0),
+        make_quote(other_ticker, 1, 61.5),
+        make_quote(ticker, 1, 61.0),
+        make_quote(ticker, 3, 63.0),
+    ];
+    let ids = db.insert_quotes(&quotes).unwrap();
+    assert_eq!(ids.len(), quotes.len());
+    let mut stored = db.get_all_quotes_for_ticker(ticker).unwrap();
+    stored.extend(db.get_all_quotes_for_ticker(other_ticker).unwrap());
+    for (id, quote) in ids.iter().zip(&quotes) {
+        let stored = stored.iter().find(|q| q.id == Some(*id)).unwrap();
+        assert_eq!(stored.ticker, quote.ticker);
+        assert_eq!(stored.price, quote.price);
+        assert_eq!(stored.time, quote.time);
+        assert_eq!(stored.volume, quote.volume);
+        assert_eq!(stored.source, quote.source);
+        assert_eq!(stored.high, quote.high);
+        assert_eq!(stored.low, quote.low);
+    }
+    assert_eq!(
+        db.get_ticker_by_id(ticker).unwrap().last_quote_time,
+        Some(time(3, 17))
+    );
+    assert!(db.insert_quotes(&[]).unwrap().is_empty());
+
+    // the invalid quote rolls back all others
+    assert!(db
+        .insert_quotes(&[make_quote(ticker, 4, 64.0), make_quote(ticker, 5, -1.0)])
+        .is_err());
+    assert_eq!(db.get_all_quotes_for_ticker(ticker).unwrap().len(), 3);
+    assert_eq!(
+        db.get_ticker_by_id(ticker).unwrap().last_quote_time,
+        Some(time(3, 17))
+    );
+}
+
+/// Inserting a quote of the same ticker and time again returns the existing quote unchanged
+pub fn check_insert_quote_if_new(db: &mut dyn QuoteHandler) {
+    let asset = insert_asset(db, "BASF AG");
+    let ticker = db.insert_ticker(&make_ticker(asset, "BAS.DE")).unwrap();
+    let other_ticker = db.insert_ticker(&make_ticker(asset, "BAS.F")).unwrap();
+    let quote = Quote {
+        id: None,
+        ticker,
+        price: 61.0,
+        time: time(2, 17),
+        volume: Some(1200.0),
+        quality_score: None,
+        source: None,
+        open: None,
+        high: None,
+        low: None,
+        bid: None,
+        ask: None,
+    };
+    let inserted = db.insert_quote_if_new(&quote).unwrap();
+    assert!(inserted.is_new());
+    let again = db
+        .insert_quote_if_new(&Quote {
+            price: 62.0,
+            ..quote.clone()
+        })
+        .unwrap();
+    assert_eq!(again, QuoteInsertion::Existing(inserted.id()));
+    let quotes = db.get_all_quotes_for_ticker(ticker).unwrap();
+    assert_eq!(quotes.len(), 1);
+    assert_eq!(quotes[0].price, 61.0);
+    assert_eq!(
+        db.get_ticker_by_id(ticker).unwrap().last_quote_time,
+        Some(time(2, 17))
+    );
+
+    let other = db
+        .insert_quote_if_new(&Quote {
+            ticker: other_ticker,
+            ..quote.clone()
+        })
+        .unwrap();
+    assert!(other.is_new());
+    assert_ne!(other.id(), inserted.id());
+    // a plain insert of the duplicate is rejected by the unique key
+    assert!(db.insert_quote(&quote).is_err());
+}
+
+/// Setting the rounding digits of a currency again replaces the previous setting
+pub fn check_rounding_digits_update(db: &mut dyn QuoteHandler) {
+    db.set_rounding_digits(currency("JPY"), 1).unwrap();
+    db.set_rounding_digits(currency("JPY"), 0).unwrap();
+    db.set_rounding_digits(currency("XAU"), 4).unwrap();
+    assert_eq!(db.get_rounding_digits(currency("JPY")), 0);
+    assert_eq!(db.get_rounding_digits(currency("XAU")), 4);
+    assert_eq!(db.get_rounding_digits(currency("EUR")), 2);
+}
+
+/// Quota usage is added up per source and month
+pub fn check_quota_usage(db: &mut dyn QuoteHandler) {
+    let march = QuotaMonth {
+        year: 2021,
+        month: 3,
+    };
+    assert_eq!(db.get_quota_usage("eod", march).unwrap(), 0);
+    db.add_quota_usage("eod", march, 2).unwrap();
+    db.add_quota_usage("eod", march, 1).unwrap();
+    db.add_quota_usage("eod", march.next(), 5).unwrap();
+    db.add_quota_usage("yahoo", march, 7).unwrap();
+    assert_eq!(db.get_quota_usage("eod", march).unwrap(), 3);
+    assert_eq!(db.get_quota_usage("eod", march.next()).unwrap(), 5);
+    assert_eq!(db.get_quota_usage("yahoo", march).unwrap(), 7);
+}
+
+/// Storing a report definition with the same name again replaces version and payload
+pub fn check_report_definition_update(db: &mut dyn ReportHandler) {
+    let definition = ReportDefinition {
+        name: "monthly income".to_string(),
+        version: 1,
+        payload: serde_json::json!({"report": "income"}),
+    };
+    db.set_report_definition(&definition).unwrap();
+    let other = ReportDefinition {
+        name: "contributions".to_string(),
+        version: 1,
+        payload: serde_json::json!({"report": "contribution"}),
+    };
+    db.set_report_definition(&other).unwrap();
+    let definition = ReportDefinition {
+        name: "monthly income".to_string(),
+        version: 2,
+        payload: serde_json::json!({"report": "income", "base_currency": "EUR"}),
+    };
+    db.set_report_definition(&definition).unwrap();
+    assert_eq!(
+        db.get_report_definition("monthly income").unwrap(),
+        Some(definition.clone())
+    );
+    assert_eq!(db.get_report_definition("yearly income").unwrap(), None);
+    assert_eq!(
+        db.get_all_report_definitions().unwrap(),
+        vec![other, definition]
+    );
+    db.delete_report_definition("contributions").unwrap();
+    assert_eq!(db.get_all_report_definitions().unwrap().len(), 1);
+
+    let run = JobRun {
+        id: None,
+        job: "report:monthly income".to_string(),
+        started: time(1, 9),
+        finished: time(1, 10),
+        success: false,
+        message: Some("database error".to_string()),
+    };
+    let id = db.insert_job_run(&run).unwrap();
+    let later_run = JobRun {
+        id: None,
+        started: time(2, 9),
+        finished: time(2, 10),
+        success: true,
+        message: None,
+        ..run.clone()
+    };
+    let later_id = db.insert_job_run(&later_run).unwrap();
+    let runs = db.get_job_runs("report:monthly income").unwrap();
+    assert_eq!(
+        runs,
+        vec![
+            JobRun {
+                id: Some(later_id),
+                ..later_run
+            },
+            JobRun {
+                id: Some(id),
+                ..run
+            }
+        ]
+    );
+    assert!(db.get_job_runs("report:contributions").unwrap().is_empty());
+}
+
+/// A rebalancing decision is read back with its proposal and linked transactions
+pub fn check_rebalancing_decision(db: &mut dyn ReportHandler) {
+    let asset_id = insert_asset(db, "BASF AG");
+    let other_asset = insert_asset(db, "Siemens AG");
+    let mut transaction_ids = Vec::new();
+    for (asset_id, position, amount) in [(asset_id, -5.0, 310.0), (other_asset, 2.0, -250.0)] {
+        let id = db
+            .insert_transaction(&Transaction {
+                id: None,
+                transaction_type: TransactionType::Asset { asset_id, position },
+                cash_flow: CashFlow::new(amount, currency("EUR"), NaiveDate::from_ymd(2021, 3, 2)),
+                note: None,
+                execution_meta: None,
+                recorded_at: None,
+            })
+            .unwrap();
+        transaction_ids.push(id);
+    }
+    // linked transactions are returned in ascending order
+    transaction_ids.reverse();
+    let decision = RebalancingDecision {
+        id: None,
+        proposal: RebalancingProposal {
+            time: time(1, 17),
+            currency: currency("EUR"),
+            positions: vec![(asset_id, 10.0), (other_asset, 1.0)],
+            cash: 100.5,
+            trades: vec![
+                RebalancingTrade {
+                    asset_id,
+                    position: -5.0,
+                },
+                RebalancingTrade {
+                    asset_id: other_asset,
+                    position: 2.0,
+                },
+            ],
+        },
+        transaction_ids: transaction_ids.clone(),
+    };
+    let id = db.insert_rebalancing_decision(&decision).unwrap();
+    let other_id = db
+        .insert_rebalancing_decision(&RebalancingDecision {
+            transaction_ids: Vec::new(),
+            ..decision.clone()
+        })
+        .unwrap();
+    assert_ne!(id, other_id);
+
+    let stored = db.get_rebalancing_decision(id).unwrap();
+    transaction_ids.sort_unstable();
+    assert_eq!(
+        stored,
+        RebalancingDecision {
+            id: Some(id),
+            transaction_ids,
+            ..decision
+        }
+    );
+    assert!(db
+        .get_rebalancing_decision(other_id)
+        .unwrap()
+        .transaction_ids
+        .is_empty());
+    assert!(db.get_rebalancing_decision(other_id + 1).is_err());
+}
+
+/// Setting the contract terms of an asset again replaces kind, version and payload
+pub fn check_instrument_record_update(db: &mut dyn AssetHandler) {
+    let asset_id = insert_asset(db, "Bund 2031");
+    let other_id = insert_asset(db, "BASF Call");
+    let record = InstrumentRecord {
+        asset_id,
+        kind: "loan".to_string(),
+        version: 1,
+        payload: serde_json::json!({"amount": 1000.0}),
+    };
+    db.set_instrument_record(&record).unwrap();
+    let record = InstrumentRecord {
+        asset_id,
+        kind: "bond".to_string(),
+        version: 3,
+        payload: serde_json::json!({"coupon": {"rate": 0.5}, "isin": null}),
+    };
+    db.set_instrument_record(&record).unwrap();
+    assert_eq!(db.get_instrument_record(asset_id).unwrap(), Some(record.clone()));
+    assert_eq!(db.get_instrument_record(other_id).unwrap(), None);
+
+    let terms = OptionTerms {
+        asset_id: other_id,
+        underlying_id: asset_id,
+        strike: 60.,
+        expiry: NaiveDate::from_ymd(2021, 12, 17),
+        option_type: OptionType::Put,
+        contract_size: 100.,
+    };
+    db.insert_option_terms(&terms).unwrap();
+    let stored = db.get_option_terms(other_id).unwrap().unwrap();
+    assert_eq!(stored.expiry, terms.expiry);
+    assert_eq!(stored.option_type, OptionType::Put);
+    assert!(db.get_option_terms(asset_id).unwrap().is_none());
+    assert_eq!(db.get_instrument_records("bond").unwrap(), vec![record]);
+    assert_eq!(db.get_instrument_records("option").unwrap().len(), 1);
+
+    // deleting option terms keeps the terms of other kinds of instruments
+    db.delete_option_terms(asset_id).unwrap();
+    assert!(db.get_instrument_record(asset_id).unwrap().is_some());
+    db.delete_option_terms(other_id).unwrap();
+    assert!(db.get_option_terms(other_id).unwrap().is_none());
+    db.delete_instrument_record(asset_id).unwrap();
+    assert!(db.get_instrument_records("bond").unwrap().is_empty());
+}
@@ -97,6 +97,19 @@ impl FromStr for Currency {
     }
 }
 
+/// Normal form of stored currency codes, i.e. upper case without surrounding white space
+pub fn normalize_currency_code(curr: &str) -> String {
+    curr.trim().to_ascii_uppercase()
+}
+
+impl Currency {
+    /// Parse a currency code read from the database. Other than `from_str`, this accepts codes
+    /// in lower case or with surrounding white space, as written by some early import scripts.
+    pub fn from_stored(curr: &str) -> Result<Currency, CurrencyError> {
+        Currency::from_str(&normalize_currency_code(curr))
+    }
+}
+
 impl Serialize for Currency {
     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
     where
@@ -178,6 +191,23 @@ mod tests {
         assert_eq!(currency, Err(CurrencyError::InvalidCharacter));
     }
 
+    #[test]
+    fn read_stored_currency() {
+        let eur = Currency::from_str("EUR").unwrap();
+        assert_eq!(Currency::from_stored("eur "), Ok(eur));
+        assert_eq!(Currency::from_stored(" EUR"), Ok(eur));
+        assert_eq!(
+            Currency::from_str("eur "),
+            Err(CurrencyError::InvalidLength)
+        );
+        assert_eq!(Currency::from_stored("jpy").unwrap().rounding_digits, 0);
+        assert_eq!(
+            Currency::from_stored("EU R"),
+            Err(CurrencyError::InvalidCharacter)
+        );
+        assert_eq!(normalize_currency_code(" usd\t"), "USD");
+    }
+
     #[test]
     fn deserialize_currency() {
         let input = r#""EUR""#;
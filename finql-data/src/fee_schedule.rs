@@ -0,0 +1,67 @@
+//! Fee schedules of accounts, e.g. custody fees charged by a broker
+
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+
+use crate::currency::Currency;
+use crate::DataError;
+
+/// Periodic fees charged for an account. Each billing period, the fee consists of a percentage
+/// of the value of the account, bounded by the minimum and maximum fee, plus a fixed amount.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FeeSchedule {
+    pub account_id: usize,
+    /// Currency of the fees and the account value
+    pub currency: Currency,
+    /// Fee per year as fraction of the account value, e.g. 0.001 for 0.1% p.a.
+    pub rate_pa: f64,
+    /// Minimum of the percentage fee per billing period
+    pub min_fee: Option<f64>,
+    /// Maximum of the percentage fee per billing period
+    pub max_fee: Option<f64>,
+    /// Fixed fee per billing period
+    pub fixed_fee: f64,
+    /// Length of a billing period as time period string, e.g. `3M` for quarterly billing
+    pub billing_period: String,
+    /// First day of the first billing period
+    pub start: NaiveDate,
+}
+
+impl FeeSchedule {
+    /// Check that the rate is between 0 and 1, fees are not negative and the minimum fee does
+    /// not exceed the maximum fee
+    pub fn validate(&self) -> Result<(), DataError> {
+        if !(0. ..1.).contains(&self.rate_pa) {
+            return Err(DataError::InvalidData(format!(
+                "fee rate {} is not between 0 and 1",
+                self.rate_pa
+            )));
+        }
+        let is_amount = |amount: f64| amount >= 0. && amount.is_finite();
+        for amount in [self.min_fee, self.max_fee, Some(self.fixed_fee)]
+            .iter()
+            .flatten()
+        {
+            if !is_amount(*amount) {
+                return Err(DataError::InvalidData(format!(
+                    "fee {} must not be negative",
+                    amount
+                )));
+            }
+        }
+        if let (Some(min_fee), Some(max_fee)) = (self.min_fee, self.max_fee) {
+            if min_fee > max_fee {
+                return Err(DataError::InvalidData(format!(
+                    "minimum fee {} exceeds maximum fee {}",
+                    min_fee, max_fee
+                )));
+            }
+        }
+        if self.billing_period.is_empty() {
+            return Err(DataError::InvalidData(
+                "billing period must not be empty".to_string(),
+            ));
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,193 @@
+//! Contract terms of instruments, e.g. bonds, options or loans, stored alongside their assets
+//!
+//! The terms of all instrument types are stored in a single table as JSON payload, tagged by
+//! the kind of instrument and the version of the payload layout. Typed access is provided by
+//! `set_instrument` and `get_instrument` for all types implementing `InstrumentTerms`.
+
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::asset_handler::AssetHandler;
+use crate::DataError;
+
+/// Contract terms of an asset as stored in the database
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct InstrumentRecord {
+    pub asset_id: usize,
+    /// Kind of instrument, see `InstrumentTerms::KIND`
+    pub kind: String,
+    /// Version of the layout of the payload, see `InstrumentTerms::VERSION`
+    pub version: u32,
+    pub payload: Value,
+}
+
+/// Contract terms of a type of instruments which can be stored by `set_instrument`
+pub trait InstrumentTerms: Serialize + DeserializeOwned {
+    /// Tag identifying the kind of instrument, e.g. `bond`
+    const KIND: &'static str;
+    /// Version of the layout of the serialized terms. Whenever the layout changes
+    /// incompatibly, the version is increased and `upgrade` is extended accordingly.
+    const VERSION: u32;
+
+    /// Convert a payload of the given version to the layout of the next version. Terms whose
+    /// layout never changed keep this default, which rejects all older versions.
+    fn upgrade(version: u32, _payload: Value) -> Result<Value, DataError> {
+        Err(DataError::VersionMismatch(format!(
+            "no upgrade of {} terms from version {} available",
+            Self::KIND,
+            version
+        )))
+    }
+}
+
+/// Store the contract terms of an asset, replacing any terms previously stored for the asset
+pub fn set_instrument<T: InstrumentTerms, H: AssetHandler + ?Sized>(
+    db: &mut H,
+    asset_id: usize,
+    terms: &T,
+) -> Result<(), DataError> {
+    let payload =
+        serde_json::to_value(terms).map_err(|e| DataError::InvalidData(e.to_string()))?;
+    db.set_instrument_record(&InstrumentRecord {
+        asset_id,
+        kind: T::KIND.to_string(),
+        version: T::VERSION,
+        payload,
+    })
+}
+
+/// Get the contract terms of an asset, or None if no terms are stored for the asset.
+/// Terms of another kind are rejected as invalid data. Terms stored with an older version are
+/// upgraded, terms stored with a newer version are rejected as version mismatch.
+pub fn get_instrument<T: InstrumentTerms, H: AssetHandler + ?Sized>(
+    db: &mut H,
+    asset_id: usize,
+) -> Result<Option<T>, DataError> {
+    db.get_instrument_record(asset_id)?
+        .map(|record| decode_instrument(record))
+        .transpose()
+}
+
+/// Convert a stored record to terms of type `T`, upgrading older versions
+pub fn decode_instrument<T: InstrumentTerms>(record: InstrumentRecord) -> Result<T, DataError> {
+    if record.kind != T::KIND {
+        return Err(DataError::InvalidData(format!(
+            "asset {} has {} terms instead of {} terms",
+            record.asset_id,
+            record.kind,
+            T::KIND
+        )));
+    }
+    if record.version > T::VERSION {
+        return Err(DataError::VersionMismatch(format!(
+            "{} terms of asset {} have version {}, but only version {} is supported",
+            T::KIND,
+            record.asset_id,
+            record.version,
+            T::VERSION
+        )));
+    }
+    let asset_id = record.asset_id;
+    let mut payload = record.payload;
+    for version in record.version..T::VERSION {
+        payload = T::upgrade(version, payload)?;
+    }
+    serde_json::from_value(payload).map_err(|e| {
+        DataError::InvalidData(format!(
+            "invalid {} terms of asset {}: {}",
+            T::KIND,
+            asset_id,
+            e
+        ))
+    })
+}
+
+/// Upgrade all stored terms of type `T` with an older version to the current version.
+/// Returns the number of upgraded records.
+pub fn migrate_instruments<T: InstrumentTerms, H: AssetHandler + ?Sized>(
+    db: &mut H,
+) -> Result<usize, DataError> {
+    let mut count = 0;
+    for record in db.get_instrument_records(T::KIND)? {
+        if record.version < T::VERSION {
+            let asset_id = record.asset_id;
+            let terms: T = decode_instrument(record)?;
+            set_instrument(db, asset_id, &terms)?;
+            count += 1;
+        }
+    }
+    Ok(count)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    /// Terms whose rate has been given in percent in version 1
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Deposit {
+        rate: f64,
+        currency: String,
+    }
+
+    impl InstrumentTerms for Deposit {
+        const KIND: &'static str = "deposit";
+        const VERSION: u32 = 2;
+
+        fn upgrade(version: u32, payload: Value) -> Result<Value, DataError> {
+            match version {
+                1 => Ok(json!({
+                    "rate": payload["rate_percent"].as_f64().unwrap_or_default() / 100.,
+                    "currency": payload["currency"],
+                })),
+                _ => Err(DataError::VersionMismatch(format!(
+                    "unknown deposit version {}",
+                    version
+                ))),
+            }
+        }
+    }
+
+    fn record(kind: &str, version: u32, payload: Value) -> InstrumentRecord {
+        InstrumentRecord {
+            asset_id: 1,
+            kind: kind.to_string(),
+            version,
+            payload,
+        }
+    }
+
+    #[test]
+    fn decode_checks_kind_and_version() {
+        let current = record("deposit", 2, json!({"rate": 0.02, "currency": "EUR"}));
+        let deposit: Deposit = decode_instrument(current).unwrap();
+        assert_eq!(
+            deposit,
+            Deposit {
+                rate: 0.02,
+                currency: "EUR".to_string()
+            }
+        );
+
+        let old = record("deposit", 1, json!({"rate_percent": 2.5, "currency": "USD"}));
+        let deposit: Deposit = decode_instrument(old).unwrap();
+        assert!((deposit.rate - 0.025).abs() < 1e-12);
+        assert_eq!(deposit.currency, "USD");
+
+        let newer = record("deposit", 3, json!({"rate": 0.02, "currency": "EUR"}));
+        let err = decode_instrument::<Deposit>(newer).unwrap_err();
+        assert!(err.is_version_mismatch());
+        let unknown = record("deposit", 0, json!({}));
+        assert!(decode_instrument::<Deposit>(unknown)
+            .unwrap_err()
+            .is_version_mismatch());
+
+        let bond = record("bond", 2, json!({"rate": 0.02, "currency": "EUR"}));
+        let err = decode_instrument::<Deposit>(bond).unwrap_err();
+        assert!(err.is_invalid());
+        let broken = record("deposit", 2, json!({"rate": "high"}));
+        assert!(decode_instrument::<Deposit>(broken).unwrap_err().is_invalid());
+    }
+}
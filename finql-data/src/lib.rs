@@ -1,6 +1,8 @@
 ///! Implementation of a data handler trait to deal with global data
 use std::fmt;
 
+use chrono::{DateTime, Utc};
+
 pub mod asset_handler;
 pub mod quote_handler;
 pub mod transaction_handler;
@@ -8,16 +10,50 @@ pub mod asset;
 pub mod transaction;
 pub mod currency;
 pub mod cash_flow;
+pub mod clock;
 pub mod quote;
+pub mod quote_cache;
+pub mod merged_reader;
+pub mod shared_handler;
+pub mod quota;
+pub mod order;
+pub mod fee_schedule;
+pub mod instrument;
+pub mod report_definition;
+pub mod rebalancing;
+#[cfg(feature = "conformance")]
+pub mod conformance;
 
-pub use asset::Asset;
+pub use asset::{
+    Asset, AssetIndex, AssetRepresentation, AssetSearchQuery, AssetSortKey, CurrencyExposure,
+    DistributionPolicy, IdentifierKind, IdentifierPriority, OptionTerms, OptionType, Page,
+};
 pub use asset_handler::AssetHandler;
-pub use quote::{Quote, Ticker};
-pub use quote_handler::QuoteHandler;
-pub use transaction::{Transaction, TransactionType};
+pub use quote::{
+    parse_source_chain, refresh_ticker, refresh_ticker_from_chain, FetchedQuotes, Quote,
+    QuoteProvider, QuoteProviderRegistry, QuoteQuery, RawQuote, Ticker, TickerRefresh,
+    TickerUsage, QUALITY_SCORE_PREFERENCE,
+};
+pub use quote_cache::{CachedQuoteHandler, QuoteCacheConfig, QuoteCacheStats};
+pub use merged_reader::MergedReader;
+pub use shared_handler::{HandlerSource, SharedHandler};
+pub use quota::QuotaMonth;
+pub use quote_handler::{QuoteHandler, QuoteInsertion, QuoteReader, QuoteWriter};
+pub use transaction::{CashDirection, LotSelection, RawTransaction, Transaction, TransactionType};
 pub use transaction_handler::TransactionHandler;
-pub use currency::{Currency, CurrencyConverter, CurrencyError};
+pub use order::{
+    Order, OrderError, OrderHandler, OrderSide, OrderSize, OrderState, TransactionDraft,
+};
+pub use currency::{normalize_currency_code, Currency, CurrencyConverter, CurrencyError};
 pub use cash_flow::{CashAmount, CashFlow};
+pub use clock::{Clock, MockClock, SystemClock};
+pub use fee_schedule::FeeSchedule;
+pub use instrument::{
+    decode_instrument, get_instrument, migrate_instruments, set_instrument, InstrumentRecord,
+    InstrumentTerms,
+};
+pub use report_definition::{JobRun, ReportDefinition, ReportHandler};
+pub use rebalancing::{RebalancingDecision, RebalancingProposal, RebalancingTrade};
 
 #[derive(Debug)]
 pub enum DataError {
@@ -27,6 +63,19 @@ pub enum DataError {
     DeleteFailed(String),
     InsertFailed(String),
     InvalidTransaction(String),
+    CurrencyMismatch(String),
+    InvalidData(String),
+    /// Several objects match a lookup equally well, e.g. assets with similar names
+    AmbiguousMatch(String),
+    /// Stored data has a layout version which can't be read, e.g. written by a newer version
+    VersionMismatch(String),
+    /// The database could not be opened with the given credentials, e.g. a wrong encryption key
+    AuthFailed(String),
+    /// An asset with the same ISIN, WKN or name exists already, given by its id
+    DuplicateAsset(usize, String),
+    /// Several quotes of the same ticker and time are stored, see
+    /// `SchemaHandler::collapse_duplicate_quotes`
+    DuplicateQuotes(String),
 }
 
 impl std::error::Error for DataError {
@@ -35,22 +84,340 @@ impl std::error::Error for DataError {
     }
 }
 
+impl DataError {
+    /// Name of the error variant, e.g. for classification of errors in logs
+    pub fn variant_name(&self) -> &'static str {
+        match self {
+            Self::DataAccessFailure(_) => "DataAccessFailure",
+            Self::NotFound(_) => "NotFound",
+            Self::UpdateFailed(_) => "UpdateFailed",
+            Self::DeleteFailed(_) => "DeleteFailed",
+            Self::InsertFailed(_) => "InsertFailed",
+            Self::InvalidTransaction(_) => "InvalidTransaction",
+            Self::CurrencyMismatch(_) => "CurrencyMismatch",
+            Self::InvalidData(_) => "InvalidData",
+            Self::AmbiguousMatch(_) => "AmbiguousMatch",
+            Self::VersionMismatch(_) => "VersionMismatch",
+            Self::AuthFailed(_) => "AuthFailed",
+            Self::DuplicateAsset(_, _) => "DuplicateAsset",
+            Self::DuplicateQuotes(_) => "DuplicateQuotes",
+        }
+    }
+
+    /// Check whether the requested object does not exist
+    pub fn is_not_found(&self) -> bool {
+        matches!(self, Self::NotFound(_))
+    }
+
+    /// Check whether inserting or updating an object failed since it would violate a uniqueness
+    /// constraint, e.g. an asset with the same ISIN already exists. Since the database backends
+    /// report this only by their error message, the messages of sqlite and PostgreSQL are checked.
+    pub fn is_duplicate(&self) -> bool {
+        match self {
+            Self::DuplicateAsset(_, _) => true,
+            Self::InsertFailed(err) | Self::UpdateFailed(err) => {
+                err.contains("UNIQUE constraint failed")
+                    || err.contains("duplicate key value violates unique constraint")
+            }
+            _ => false,
+        }
+    }
+
+    /// Check whether the error has been caused by invalid data, e.g. an inconsistent transaction
+    pub fn is_invalid(&self) -> bool {
+        matches!(self, Self::InvalidData(_) | Self::InvalidTransaction(_))
+    }
+
+    /// Check whether inserting a new object failed for any reason
+    pub fn is_insert_failed(&self) -> bool {
+        matches!(self, Self::InsertFailed(_))
+    }
+
+    /// Check whether stored data could not be read because of its layout version
+    pub fn is_version_mismatch(&self) -> bool {
+        matches!(self, Self::VersionMismatch(_))
+    }
+
+    /// Check whether access to the database has been denied, e.g. due to a wrong key
+    pub fn is_auth_failed(&self) -> bool {
+        matches!(self, Self::AuthFailed(_))
+    }
+}
+
 impl fmt::Display for DataError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: ", self.variant_name())?;
         match self {
             Self::DataAccessFailure(err) => write!(f, "connection to database failed: {}", err),
-            Self::NotFound(err) => write!(f, "could found request object in database: {}", err),
+            Self::NotFound(err) => write!(f, "could not find requested object in database: {}", err),
             Self::UpdateFailed(err) => write!(f, "update of object in database failed: {}", err),
             Self::DeleteFailed(err) => write!(f, "removing object from database failed: {}", err),
             Self::InsertFailed(err) => write!(f, "inserting object to database failed: {}", err),
             Self::InvalidTransaction(err) => write!(f, "invalid transaction type: {}", err),
+            Self::CurrencyMismatch(err) => write!(f, "currencies do not match: {}", err),
+            Self::InvalidData(err) => write!(f, "object violates data constraints: {}", err),
+            Self::AmbiguousMatch(err) => write!(f, "no unique match: {}", err),
+            Self::VersionMismatch(err) => write!(f, "unsupported data version: {}", err),
+            Self::AuthFailed(err) => write!(f, "access to database denied: {}", err),
+            Self::DuplicateAsset(id, err) => {
+                write!(f, "asset exists already with id {}: {}", id, err)
+            }
+            Self::DuplicateQuotes(err) => {
+                write!(f, "several quotes of the same ticker and time: {}", err)
+            }
+        }
+    }
+}
+
+#[cfg(feature = "http")]
+impl DataError {
+    /// HTTP status code best describing the error, e.g. for REST API error handlers.
+    /// Violations of uniqueness constraints are reported as conflict, invalid input as
+    /// bad request and all other failures as internal server error.
+    pub fn as_http_status_code(&self) -> u16 {
+        if self.is_duplicate() {
+            return 409;
+        }
+        match self {
+            Self::NotFound(_) => 404,
+            Self::AuthFailed(_) => 401,
+            Self::InvalidTransaction(_)
+            | Self::InvalidData(_)
+            | Self::CurrencyMismatch(_)
+            | Self::AmbiguousMatch(_) => 400,
+            _ => 500,
+        }
+    }
+
+    /// JSON body for HTTP error responses of the form
+    /// `{"error": "NotFound", "message": "..."}`
+    pub fn as_http_error_body(&self) -> String {
+        let error = if self.is_duplicate() {
+            "Duplicate"
+        } else {
+            self.variant_name()
+        };
+        format!(
+            "{{\"error\": \"{}\", \"message\": \"{}\"}}",
+            error,
+            escape_json(self.message())
+        )
+    }
+
+    /// Error message without the variant name
+    fn message(&self) -> &str {
+        match self {
+            Self::DataAccessFailure(err)
+            | Self::NotFound(err)
+            | Self::UpdateFailed(err)
+            | Self::DeleteFailed(err)
+            | Self::InsertFailed(err)
+            | Self::InvalidTransaction(err)
+            | Self::CurrencyMismatch(err)
+            | Self::InvalidData(err)
+            | Self::AmbiguousMatch(err)
+            | Self::VersionMismatch(err)
+            | Self::AuthFailed(err)
+            | Self::DuplicateAsset(_, err)
+            | Self::DuplicateQuotes(err) => err,
         }
     }
 }
 
+/// Escape a string to be used as JSON string value
+#[cfg(feature = "http")]
+fn escape_json(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            c if (c as u32) < 0x20 => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
 pub trait DataItem {
     // get id or return error if id hasn't been set yet
     fn get_id(&self) -> Result<usize, DataError>;
     // set id or return error if id has already been set
     fn set_id(&mut self, id: usize) -> Result<(), DataError>;
 }
+
+/// Version of the layout of all stored objects, increased with every change of the database
+/// schema or of the serialization of the data types, e.g. in backups
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// Row of a settings table, e.g. of the rounding digits per currency, whose key is also used
+/// by a newer row of the same table
+#[derive(Debug, Clone, PartialEq)]
+pub struct DuplicateSetting {
+    pub table: String,
+    pub key: String,
+    pub id: usize,
+}
+
+/// Quote superseded by a newer quote of the same ticker and time
+#[derive(Debug, Clone, PartialEq)]
+pub struct DuplicateQuote {
+    pub ticker_id: usize,
+    pub time: DateTime<Utc>,
+    /// Id of the superseded quote
+    pub id: usize,
+}
+
+/// Currency code stored in a form other than `normalize_currency_code` returns, e.g. `eur `
+#[derive(Debug, Clone, PartialEq)]
+pub struct UnnormalizedCurrency {
+    pub table: String,
+    /// Id of the row
+    pub id: usize,
+    /// Currency code as stored
+    pub currency: String,
+}
+
+/// Maintenance of the database layout
+pub trait SchemaHandler {
+    /// Create all missing tables and migrate existing ones to the current layout.
+    /// Running this on an up-to-date database has no effect. Fails with
+    /// `DataError::DuplicateAsset` if several assets share an ISIN or WKN and with
+    /// `DataError::DuplicateQuotes` if several quotes share the ticker and time.
+    fn init_schema(&mut self) -> Result<(), DataError>;
+
+    /// Find all rows of settings tables superseded by a newer row with the same key. Such
+    /// duplicates only exist in databases created without unique keys on these tables.
+    fn find_duplicate_settings(&mut self) -> Result<Vec<DuplicateSetting>, DataError>;
+
+    /// Delete all duplicates found by `find_duplicate_settings`, i.e. keep the newest row per
+    /// key, and add the unique keys missing because of the duplicates. Either all duplicates
+    /// are deleted or, on error, none. Returns the deleted rows.
+    fn collapse_duplicate_settings(&mut self) -> Result<Vec<DuplicateSetting>, DataError>;
+
+    /// Find all quotes superseded by a newer quote of the same ticker and time. Such duplicates
+    /// only exist in databases created without the unique key on ticker and time.
+    fn find_duplicate_quotes(&mut self) -> Result<Vec<DuplicateQuote>, DataError>;
+
+    /// Delete all duplicates found by `find_duplicate_quotes`, i.e. keep the newest quote per
+    /// ticker and time, and add the unique key on ticker and time. Either all duplicates are
+    /// deleted or, on error, none. Returns the deleted quotes.
+    fn collapse_duplicate_quotes(&mut self) -> Result<Vec<DuplicateQuote>, DataError>;
+
+    /// Find all currency codes of ticker and transactions not stored in normal form
+    fn find_unnormalized_currencies(&mut self) -> Result<Vec<UnnormalizedCurrency>, DataError>;
+
+    /// Rewrite all currency codes found by `find_unnormalized_currencies` in normal form.
+    /// Either all codes are rewritten or, on error, none. Returns the rewritten rows.
+    fn normalize_currencies(&mut self) -> Result<Vec<UnnormalizedCurrency>, DataError>;
+}
+
+/// Handler for all asset, quote and transaction data of a database
+pub trait DataHandler: QuoteHandler + TransactionHandler {
+    /// Delete the given quotes and transactions, e.g. after they have been archived. Lot
+    /// selections and links of orders and rebalancing decisions referring to the transactions
+    /// are deleted as well. Either all are deleted or, on error, none.
+    fn delete_history(
+        &mut self,
+        quote_ids: &[usize],
+        transaction_ids: &[usize],
+    ) -> Result<(), DataError>;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn data_error_display() {
+        let err = DataError::InsertFailed("UNIQUE constraint failed: assets.isin".to_string());
+        assert_eq!(err.variant_name(), "InsertFailed");
+        assert_eq!(
+            err.to_string(),
+            "InsertFailed: inserting object to database failed: UNIQUE constraint failed: assets.isin"
+        );
+        let err = DataError::NotFound("no rows".to_string());
+        assert_eq!(err.variant_name(), "NotFound");
+        assert!(err.to_string().starts_with("NotFound: "));
+    }
+
+    #[test]
+    fn data_error_predicates() {
+        let sqlite_dup =
+            DataError::InsertFailed("UNIQUE constraint failed: assets.isin".to_string());
+        let postgres_dup = DataError::UpdateFailed(
+            "db error: ERROR: duplicate key value violates unique constraint \"assets_isin_key\""
+                .to_string(),
+        );
+        let insert_failed = DataError::InsertFailed("database is locked".to_string());
+        let not_found = DataError::NotFound("no rows".to_string());
+        let invalid_data = DataError::InvalidData("negative position".to_string());
+        let invalid_trans = DataError::InvalidTransaction("unknown type".to_string());
+        let access_failed = DataError::DataAccessFailure("no connection".to_string());
+        let delete_failed = DataError::DeleteFailed("UNIQUE constraint failed".to_string());
+        let mismatch = DataError::CurrencyMismatch("USD instead of EUR".to_string());
+
+        assert!(not_found.is_not_found());
+        assert!(sqlite_dup.is_duplicate() && postgres_dup.is_duplicate());
+        let duplicate_asset = DataError::DuplicateAsset(3, "ISIN 'US0378331005'".to_string());
+        assert!(duplicate_asset.is_duplicate() && !duplicate_asset.is_insert_failed());
+        assert_eq!(
+            duplicate_asset.to_string(),
+            "DuplicateAsset: asset exists already with id 3: ISIN 'US0378331005'"
+        );
+        assert!(sqlite_dup.is_insert_failed() && insert_failed.is_insert_failed());
+        assert!(!insert_failed.is_duplicate() && !postgres_dup.is_insert_failed());
+        assert!(invalid_data.is_invalid() && invalid_trans.is_invalid());
+        for err in &[
+            &access_failed,
+            &delete_failed,
+            &mismatch,
+            &not_found,
+            &invalid_data,
+        ] {
+            assert!(!err.is_duplicate() && !err.is_insert_failed());
+        }
+        for err in &[
+            &access_failed,
+            &delete_failed,
+            &mismatch,
+            &sqlite_dup,
+            &insert_failed,
+        ] {
+            assert!(!err.is_not_found() && !err.is_invalid());
+        }
+    }
+
+    #[cfg(feature = "http")]
+    #[test]
+    fn data_error_http_mapping() {
+        let duplicate =
+            DataError::InsertFailed("UNIQUE constraint failed: assets.isin".to_string());
+        assert_eq!(duplicate.as_http_status_code(), 409);
+        assert_eq!(
+            duplicate.as_http_error_body(),
+            r#"{"error": "Duplicate", "message": "UNIQUE constraint failed: assets.isin"}"#
+        );
+        let not_found = DataError::NotFound("no rows".to_string());
+        assert_eq!(not_found.as_http_status_code(), 404);
+        assert_eq!(
+            not_found.as_http_error_body(),
+            r#"{"error": "NotFound", "message": "no rows"}"#
+        );
+        let invalid = DataError::InvalidTransaction("unknown type \"z\"\n\u{1}".to_string());
+        assert_eq!(invalid.as_http_status_code(), 400);
+        assert_eq!(
+            invalid.as_http_error_body(),
+            r#"{"error": "InvalidTransaction", "message": "unknown type \"z\"\n\u0001"}"#
+        );
+        let body: serde_json::Value = serde_json::from_str(&invalid.as_http_error_body()).unwrap();
+        assert_eq!(body["message"], "unknown type \"z\"\n\u{1}");
+        assert_eq!(
+            DataError::InsertFailed("database is locked".to_string()).as_http_status_code(),
+            500
+        );
+    }
+}
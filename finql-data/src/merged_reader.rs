@@ -0,0 +1,833 @@
+//! Read-only view of several databases as if they were one
+//!
+//! `MergedReader` combines an ordered list of data handlers, e.g. a database and its archive
+//! or the databases of two machines, and answers all read requests of the asset, quote and
+//! transaction handlers from all of them. All requests that would change data are rejected.
+//!
+//! Ids of different handlers collide, therefore all ids are translated. Ticker, quotes and
+//! transactions of handler `i` out of `n` handlers with id `id` get the id `id * n + i`.
+//! Assets with the same ISIN are merged into one asset, which gets the id of the asset in
+//! the first handler containing it. Its data is taken from this handler, while its ticker,
+//! quotes and transactions are read from all handlers.
+//!
+//! Payloads of instrument records are returned as stored, i.e. ids of assets within the
+//! payload, e.g. the underlying of option terms, are not translated.
+use std::collections::{BTreeMap, HashMap};
+
+use chrono::{DateTime, Utc};
+
+use crate::asset::{
+    Asset, AssetRepresentation, AssetSearchQuery, AssetSortKey, CurrencyExposure, Page,
+};
+use crate::asset_handler::AssetHandler;
+use crate::currency::Currency;
+use crate::fee_schedule::FeeSchedule;
+use crate::instrument::InstrumentRecord;
+use crate::quota::QuotaMonth;
+use crate::quote::{Quote, QuoteQuery, Ticker, TickerUsage};
+use crate::quote_handler::{QuoteHandler, QuoteInsertion};
+use crate::transaction::{LotSelection, Transaction, TransactionType};
+use crate::transaction_handler::TransactionHandler;
+use crate::{DataError, DataHandler, DataItem};
+
+/// Error returned for all requests that would change data
+fn read_only<T>(operation: &str) -> Result<T, DataError> {
+    Err(DataError::DataAccessFailure(format!(
+        "{} rejected, merged data is read-only",
+        operation
+    )))
+}
+
+/// Read-only view of several data handlers, see the module documentation
+pub struct MergedReader<'a> {
+    handlers: Vec<&'a mut dyn DataHandler>,
+    /// Merged assets ordered by name, with translated ids
+    assets: Vec<Asset>,
+    /// Handler index and id within that handler of all assets merged into an asset,
+    /// by id of the merged asset
+    asset_sources: BTreeMap<usize, Vec<(usize, usize)>>,
+    /// Id of the merged asset by handler index and id within that handler
+    asset_ids: HashMap<(usize, usize), usize>,
+}
+
+impl<'a> MergedReader<'a> {
+    /// Combine the given handlers, earlier handlers take precedence for the data of assets
+    /// contained in several handlers
+    pub fn new(handlers: Vec<&'a mut dyn DataHandler>) -> Result<MergedReader<'a>, DataError> {
+        if handlers.is_empty() {
+            return Err(DataError::InvalidData(
+                "at least one handler is required".to_string(),
+            ));
+        }
+        let mut reader = MergedReader {
+            handlers,
+            assets: Vec::new(),
+            asset_sources: BTreeMap::new(),
+            asset_ids: HashMap::new(),
+        };
+        let mut by_isin: HashMap<String, usize> = HashMap::new();
+        for index in 0..reader.handlers.len() {
+            for asset in reader.handlers[index].get_all_assets()? {
+                let local_id = asset.get_id()?;
+                let known = asset
+                    .isin
+                    .as_ref()
+                    .and_then(|isin| by_isin.get(isin).cloned());
+                let id = match known {
+                    Some(id) => id,
+                    None => {
+                        let id = reader.merged_id(index, local_id);
+                        if let Some(isin) = &asset.isin {
+                            by_isin.insert(isin.clone(), id);
+                        }
+                        reader.assets.push(Asset {
+                            id: Some(id),
+                            ..asset
+                        });
+                        id
+                    }
+                };
+                reader
+                    .asset_sources
+                    .entry(id)
+                    .or_default()
+                    .push((index, local_id));
+                reader.asset_ids.insert((index, local_id), id);
+            }
+        }
+        reader.assets.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(reader)
+    }
+
+    /// Translate the id of a ticker, quote or transaction of the given handler
+    fn merged_id(&self, index: usize, id: usize) -> usize {
+        id * self.handlers.len() + index
+    }
+
+    /// Handler index and id within that handler of a ticker, quote or transaction
+    fn local_id(&self, id: usize) -> (usize, usize) {
+        (id % self.handlers.len(), id / self.handlers.len())
+    }
+
+    fn merged_asset_id(&self, index: usize, id: usize) -> usize {
+        match self.asset_ids.get(&(index, id)) {
+            Some(id) => *id,
+            None => self.merged_id(index, id),
+        }
+    }
+
+    /// Handler indices and ids within these handlers of all assets merged into the given asset
+    fn asset_sources(&self, asset_id: usize) -> Result<Vec<(usize, usize)>, DataError> {
+        self.asset_sources
+            .get(&asset_id)
+            .cloned()
+            .ok_or_else(|| DataError::NotFound(format!("no asset with id {}", asset_id)))
+    }
+
+    fn merged_asset(&self, asset_id: usize) -> Result<&Asset, DataError> {
+        self.assets
+            .iter()
+            .find(|asset| asset.id == Some(asset_id))
+            .ok_or_else(|| DataError::NotFound(format!("no asset with id {}", asset_id)))
+    }
+
+    /// Merged assets matching any of the given assets of the given handler, without duplicates
+    fn merged_assets(&self, assets: Vec<(usize, Asset)>) -> Result<Vec<Asset>, DataError> {
+        let mut merged: Vec<Asset> = Vec::new();
+        for (index, asset) in assets {
+            let id = self.merged_asset_id(index, asset.get_id()?);
+            if !merged.iter().any(|asset| asset.id == Some(id)) {
+                merged.push(self.merged_asset(id)?.clone());
+            }
+        }
+        Ok(merged)
+    }
+
+    fn merged_ticker(&self, index: usize, ticker: Ticker) -> Result<Ticker, DataError> {
+        Ok(Ticker {
+            id: Some(self.merged_id(index, ticker.get_id()?)),
+            asset: self.merged_asset_id(index, ticker.asset),
+            ..ticker
+        })
+    }
+
+    fn merged_tickers(&self, index: usize, tickers: Vec<Ticker>) -> Result<Vec<Ticker>, DataError> {
+        tickers
+            .into_iter()
+            .map(|ticker| self.merged_ticker(index, ticker))
+            .collect()
+    }
+
+    /// Collect the translated ticker of all handlers
+    fn all_tickers(
+        &mut self,
+        get: impl Fn(&mut dyn DataHandler) -> Result<Vec<Ticker>, DataError>,
+    ) -> Result<Vec<Ticker>, DataError> {
+        let mut tickers = Vec::new();
+        for index in 0..self.handlers.len() {
+            let local = get(&mut *self.handlers[index])?;
+            tickers.extend(self.merged_tickers(index, local)?);
+        }
+        Ok(tickers)
+    }
+
+    fn merged_quote(&self, index: usize, quote: Quote) -> Quote {
+        Quote {
+            id: quote.id.map(|id| self.merged_id(index, id)),
+            ticker: self.merged_id(index, quote.ticker),
+            ..quote
+        }
+    }
+
+    fn merged_quotes(&self, index: usize, quotes: Vec<Quote>) -> Vec<Quote> {
+        quotes
+            .into_iter()
+            .map(|quote| self.merged_quote(index, quote))
+            .collect()
+    }
+
+    /// Select the latest of the quotes found by `get` in the given handlers, the quote of
+    /// the earlier handler is taken if several quotes have the same time
+    fn latest_quote(
+        &mut self,
+        sources: &[(usize, usize)],
+        get: impl Fn(&mut dyn DataHandler, usize) -> Result<(Quote, Currency), DataError>,
+    ) -> Result<(Quote, Currency), DataError> {
+        let mut latest: Option<(Quote, Currency)> = None;
+        let mut error = None;
+        for (index, id) in sources {
+            match get(&mut *self.handlers[*index], *id) {
+                Ok((quote, currency)) => {
+                    if !matches!(&latest, Some((l, _)) if quote.time <= l.time) {
+                        latest = Some((self.merged_quote(*index, quote), currency));
+                    }
+                }
+                Err(err) => error = Some(err),
+            }
+        }
+        match (latest, error) {
+            (Some(latest), _) => Ok(latest),
+            (None, Some(err)) => Err(err),
+            (None, None) => Err(DataError::NotFound("no quote found".to_string())),
+        }
+    }
+
+    fn merged_transaction(
+        &self,
+        index: usize,
+        transaction: Transaction,
+    ) -> Result<Transaction, DataError> {
+        let merged_ref = |id: Option<usize>| id.map(|id| self.merged_id(index, id));
+        let transaction_type = match transaction.transaction_type {
+            TransactionType::Cash => TransactionType::Cash,
+            TransactionType::Asset { asset_id, position } => TransactionType::Asset {
+                asset_id: self.merged_asset_id(index, asset_id),
+                position,
+            },
+            TransactionType::Dividend { asset_id } => TransactionType::Dividend {
+                asset_id: self.merged_asset_id(index, asset_id),
+            },
+            TransactionType::Interest { asset_id } => TransactionType::Interest {
+                asset_id: self.merged_asset_id(index, asset_id),
+            },
+            TransactionType::Tax { transaction_ref } => TransactionType::Tax {
+                transaction_ref: merged_ref(transaction_ref),
+            },
+            TransactionType::Fee { transaction_ref } => TransactionType::Fee {
+                transaction_ref: merged_ref(transaction_ref),
+            },
+            TransactionType::Transfer {
+                asset_id,
+                position,
+                transfer_ref,
+            } => TransactionType::Transfer {
+                asset_id: self.merged_asset_id(index, asset_id),
+                position,
+                transfer_ref: merged_ref(transfer_ref),
+            },
+        };
+        Ok(Transaction {
+            id: Some(self.merged_id(index, transaction.get_id()?)),
+            transaction_type,
+            ..transaction
+        })
+    }
+
+    fn merged_transactions(
+        &self,
+        index: usize,
+        transactions: Vec<Transaction>,
+    ) -> Result<Vec<Transaction>, DataError> {
+        transactions
+            .into_iter()
+            .map(|transaction| self.merged_transaction(index, transaction))
+            .collect()
+    }
+}
+
+/// Order of assets by the given key as `AssetSortKey::order_by` defines it
+fn sort_assets(assets: &mut [Asset], key: AssetSortKey, ascending: bool) {
+    assets.sort_by(|a, b| {
+        let value = |asset: &Asset| match key {
+            AssetSortKey::Name => Some(asset.name.clone()),
+            AssetSortKey::Isin => asset.isin.clone(),
+            AssetSortKey::Wkn => asset.wkn.clone(),
+            AssetSortKey::Id => None,
+        };
+        let (a_value, b_value) = (value(a), value(b));
+        let order = match (&a_value, &b_value) {
+            (Some(_), None) => return std::cmp::Ordering::Less,
+            (None, Some(_)) => return std::cmp::Ordering::Greater,
+            _ => a_value.cmp(&b_value).then(a.id.cmp(&b.id)),
+        };
+        if ascending {
+            order
+        } else {
+            order.reverse()
+        }
+    });
+}
+
+impl AssetHandler for MergedReader<'_> {
+    fn insert_asset(&mut self, _asset: &Asset) -> Result<usize, DataError> {
+        read_only("inserting asset")
+    }
+    fn get_asset_id(&mut self, asset: &Asset) -> Option<usize> {
+        for index in 0..self.handlers.len() {
+            if let Some(id) = self.handlers[index].get_asset_id(asset) {
+                return Some(self.merged_asset_id(index, id));
+            }
+        }
+        None
+    }
+    fn get_asset_by_id(&mut self, id: usize) -> Result<Asset, DataError> {
+        Ok(self.merged_asset(id)?.clone())
+    }
+    fn get_asset_by_isin(&mut self, isin: &str) -> Result<Asset, DataError> {
+        let mut found = Vec::new();
+        for index in 0..self.handlers.len() {
+            match self.handlers[index].get_asset_by_isin(isin) {
+                Ok(asset) => found.push((index, asset)),
+                Err(DataError::NotFound(_)) => {}
+                Err(err) => return Err(err),
+            }
+        }
+        match self.merged_assets(found)?.as_slice() {
+            [] => Err(DataError::NotFound(format!(
+                "no asset with isin '{}'",
+                isin
+            ))),
+            [asset] => Ok(asset.clone()),
+            assets => Err(DataError::AmbiguousMatch(format!(
+                "{} assets with isin '{}'",
+                assets.len(),
+                isin
+            ))),
+        }
+    }
+    fn get_asset_by_wkn(&mut self, wkn: &str) -> Result<Asset, DataError> {
+        let mut found = Vec::new();
+        for index in 0..self.handlers.len() {
+            match self.handlers[index].get_asset_by_wkn(wkn) {
+                Ok(asset) => found.push((index, asset)),
+                Err(DataError::NotFound(_)) => {}
+                Err(err) => return Err(err),
+            }
+        }
+        match self.merged_assets(found)?.as_slice() {
+            [] => Err(DataError::NotFound(format!("no asset with wkn '{}'", wkn))),
+            [asset] => Ok(asset.clone()),
+            assets => Err(DataError::AmbiguousMatch(format!(
+                "{} assets with wkn '{}'",
+                assets.len(),
+                wkn
+            ))),
+        }
+    }
+    fn get_all_assets(&mut self) -> Result<Vec<Asset>, DataError> {
+        Ok(self.assets.clone())
+    }
+    fn get_all_assets_sorted(
+        &mut self,
+        key: AssetSortKey,
+        ascending: bool,
+    ) -> Result<Vec<Asset>, DataError> {
+        let mut assets = self.assets.clone();
+        sort_assets(&mut assets, key, ascending);
+        Ok(assets)
+    }
+    fn get_assets_page(
+        &mut self,
+        sort: AssetSortKey,
+        ascending: bool,
+        page: Page,
+    ) -> Result<(Vec<Asset>, usize), DataError> {
+        let assets = self.get_all_assets_sorted(sort, ascending)?;
+        let total = assets.len();
+        Ok((
+            assets
+                .into_iter()
+                .skip(page.offset())
+                .take(page.size)
+                .collect(),
+            total,
+        ))
+    }
+    /// Search each handler and combine the results, i.e. conditions on ticker and quotes are
+    /// checked for each handler separately
+    fn search_assets(&mut self, query: &AssetSearchQuery) -> Result<Vec<Asset>, DataError> {
+        let mut found = Vec::new();
+        for index in 0..self.handlers.len() {
+            for asset in self.handlers[index].search_assets(query)? {
+                found.push((index, asset));
+            }
+        }
+        let mut assets = self.merged_assets(found)?;
+        assets.sort_by(|a, b| a.name.cmp(&b.name));
+        if let Some(limit) = query.limit {
+            assets.truncate(limit);
+        }
+        Ok(assets)
+    }
+    fn update_asset(&mut self, _asset: &Asset) -> Result<(), DataError> {
+        read_only("updating asset")
+    }
+    fn update_assets(
+        &mut self,
+        _assets: &[Asset],
+        _exposures: &[CurrencyExposure],
+    ) -> Result<(), DataError> {
+        read_only("updating assets")
+    }
+    fn delete_asset(&mut self, _id: usize) -> Result<(), DataError> {
+        read_only("deleting asset")
+    }
+    fn get_all_currencies(&mut self) -> Result<Vec<Currency>, DataError> {
+        let mut currencies: Vec<Currency> = Vec::new();
+        for handler in self.handlers.iter_mut() {
+            for currency in handler.get_all_currencies()? {
+                if !currencies.contains(&currency) {
+                    currencies.push(currency);
+                }
+            }
+        }
+        Ok(currencies)
+    }
+
+    fn set_instrument_record(&mut self, _record: &InstrumentRecord) -> Result<(), DataError> {
+        read_only("storing instrument record")
+    }
+    fn get_instrument_record(
+        &mut self,
+        asset_id: usize,
+    ) -> Result<Option<InstrumentRecord>, DataError> {
+        for (index, id) in self.asset_sources(asset_id)? {
+            if let Some(record) = self.handlers[index].get_instrument_record(id)? {
+                return Ok(Some(InstrumentRecord { asset_id, ..record }));
+            }
+        }
+        Ok(None)
+    }
+    fn get_instrument_records(&mut self, kind: &str) -> Result<Vec<InstrumentRecord>, DataError> {
+        let mut records: Vec<InstrumentRecord> = Vec::new();
+        for index in 0..self.handlers.len() {
+            for record in self.handlers[index].get_instrument_records(kind)? {
+                let asset_id = self.merged_asset_id(index, record.asset_id);
+                if !records.iter().any(|r| r.asset_id == asset_id) {
+                    records.push(InstrumentRecord { asset_id, ..record });
+                }
+            }
+        }
+        records.sort_by_key(|record| record.asset_id);
+        Ok(records)
+    }
+    fn delete_instrument_record(&mut self, _asset_id: usize) -> Result<(), DataError> {
+        read_only("deleting instrument record")
+    }
+
+    fn set_currency_exposure(&mut self, _exposure: &CurrencyExposure) -> Result<(), DataError> {
+        read_only("storing currency exposure")
+    }
+    fn get_currency_exposure(
+        &mut self,
+        asset_id: usize,
+    ) -> Result<Option<CurrencyExposure>, DataError> {
+        for (index, id) in self.asset_sources(asset_id)? {
+            if let Some(exposure) = self.handlers[index].get_currency_exposure(id)? {
+                return Ok(Some(CurrencyExposure {
+                    asset_id,
+                    ..exposure
+                }));
+            }
+        }
+        Ok(None)
+    }
+    fn delete_currency_exposure(&mut self, _asset_id: usize) -> Result<(), DataError> {
+        read_only("deleting currency exposure")
+    }
+
+    fn set_asset_representation(
+        &mut self,
+        _representation: &AssetRepresentation,
+    ) -> Result<(), DataError> {
+        read_only("storing asset representation")
+    }
+    fn get_asset_representation(
+        &mut self,
+        asset_id: usize,
+    ) -> Result<Option<AssetRepresentation>, DataError> {
+        for (index, id) in self.asset_sources(asset_id)? {
+            if let Some(representation) = self.handlers[index].get_asset_representation(id)? {
+                return Ok(Some(AssetRepresentation {
+                    asset_id,
+                    underlying_id: self.merged_asset_id(index, representation.underlying_id),
+                    ratio: representation.ratio,
+                }));
+            }
+        }
+        Ok(None)
+    }
+    fn get_representations_of(
+        &mut self,
+        underlying_id: usize,
+    ) -> Result<Vec<AssetRepresentation>, DataError> {
+        let mut representations: Vec<AssetRepresentation> = Vec::new();
+        for (index, id) in self.asset_sources(underlying_id)? {
+            for representation in self.handlers[index].get_representations_of(id)? {
+                let asset_id = self.merged_asset_id(index, representation.asset_id);
+                if !representations.iter().any(|r| r.asset_id == asset_id) {
+                    representations.push(AssetRepresentation {
+                        asset_id,
+                        underlying_id,
+                        ratio: representation.ratio,
+                    });
+                }
+            }
+        }
+        representations.sort_by_key(|representation| representation.asset_id);
+        Ok(representations)
+    }
+    fn delete_asset_representation(&mut self, _asset_id: usize) -> Result<(), DataError> {
+        read_only("deleting asset representation")
+    }
+}
+
+impl QuoteHandler for MergedReader<'_> {
+    fn insert_ticker(&mut self, _ticker: &Ticker) -> Result<usize, DataError> {
+        read_only("inserting ticker")
+    }
+    fn get_ticker_id(&mut self, ticker: &str) -> Option<usize> {
+        for index in 0..self.handlers.len() {
+            if let Some(id) = self.handlers[index].get_ticker_id(ticker) {
+                return Some(self.merged_id(index, id));
+            }
+        }
+        None
+    }
+    fn get_ticker_id_for_source(&mut self, name: &str, source: &str) -> Option<usize> {
+        for index in 0..self.handlers.len() {
+            if let Some(id) = self.handlers[index].get_ticker_id_for_source(name, source) {
+                return Some(self.merged_id(index, id));
+            }
+        }
+        None
+    }
+    fn get_ticker_by_id(&mut self, id: usize) -> Result<Ticker, DataError> {
+        let (index, local_id) = self.local_id(id);
+        let ticker = self.handlers[index].get_ticker_by_id(local_id)?;
+        self.merged_ticker(index, ticker)
+    }
+    fn get_all_ticker(&mut self) -> Result<Vec<Ticker>, DataError> {
+        self.all_tickers(|handler| handler.get_all_ticker())
+    }
+    fn get_all_ticker_for_source(&mut self, source: &str) -> Result<Vec<Ticker>, DataError> {
+        self.all_tickers(|handler| handler.get_all_ticker_for_source(source))
+    }
+    fn get_all_ticker_for_asset(&mut self, asset_id: usize) -> Result<Vec<Ticker>, DataError> {
+        let mut tickers = Vec::new();
+        for (index, id) in self.asset_sources(asset_id)? {
+            let local = self.handlers[index].get_all_ticker_for_asset(id)?;
+            tickers.extend(self.merged_tickers(index, local)?);
+        }
+        Ok(tickers)
+    }
+    fn get_tickers_by_source_url_pattern(
+        &mut self,
+        url_pattern: &str,
+    ) -> Result<Vec<Ticker>, DataError> {
+        self.all_tickers(|handler| handler.get_tickers_by_source_url_pattern(url_pattern))
+    }
+    fn update_ticker(&mut self, _ticker: &Ticker) -> Result<(), DataError> {
+        read_only("updating ticker")
+    }
+    fn delete_ticker(&mut self, _id: usize) -> Result<(), DataError> {
+        read_only("deleting ticker")
+    }
+
+    fn insert_quote(&mut self, _quote: &Quote) -> Result<usize, DataError> {
+        read_only("inserting quote")
+    }
+    fn insert_quote_if_new(&mut self, _quote: &Quote) -> Result<QuoteInsertion, DataError> {
+        read_only("inserting quote")
+    }
+    /// Get the latest quote of all handlers, if several handlers have quotes of that time,
+    /// the quote of the first of these handlers is returned
+    fn get_last_quote_before_for_usage(
+        &mut self,
+        asset_name: &str,
+        time: DateTime<Utc>,
+        usage: TickerUsage,
+    ) -> Result<(Quote, Currency), DataError> {
+        let sources: Vec<(usize, usize)> = (0..self.handlers.len()).map(|i| (i, 0)).collect();
+        self.latest_quote(&sources, |handler, _| {
+            handler.get_last_quote_before_for_usage(asset_name, time, usage)
+        })
+    }
+    /// Get the latest quote of all handlers containing the asset, see
+    /// `get_last_quote_before_for_usage`
+    fn get_last_quote_before_by_id_for_usage(
+        &mut self,
+        asset_id: usize,
+        time: DateTime<Utc>,
+        usage: TickerUsage,
+    ) -> Result<(Quote, Currency), DataError> {
+        let sources = self.asset_sources(asset_id)?;
+        self.latest_quote(&sources, |handler, id| {
+            handler.get_last_quote_before_by_id_for_usage(id, time, usage)
+        })
+    }
+    fn get_all_quotes_for_ticker(&mut self, ticker_id: usize) -> Result<Vec<Quote>, DataError> {
+        let (index, local_id) = self.local_id(ticker_id);
+        let quotes = self.handlers[index].get_all_quotes_for_ticker(local_id)?;
+        Ok(self.merged_quotes(index, quotes))
+    }
+    fn get_all_quotes_for_ticker_and_source(
+        &mut self,
+        asset_id: usize,
+        source: &str,
+    ) -> Result<Vec<Quote>, DataError> {
+        let mut quotes = Vec::new();
+        for (index, id) in self.asset_sources(asset_id)? {
+            let local = self.handlers[index].get_all_quotes_for_ticker_and_source(id, source)?;
+            quotes.extend(self.merged_quotes(index, local));
+        }
+        quotes.sort_by_key(|quote| quote.time);
+        Ok(quotes)
+    }
+    fn get_quotes_above_quality(
+        &mut self,
+        ticker_id: usize,
+        min_quality: f64,
+    ) -> Result<Vec<Quote>, DataError> {
+        let (index, local_id) = self.local_id(ticker_id);
+        let quotes = self.handlers[index].get_quotes_above_quality(local_id, min_quality)?;
+        Ok(self.merged_quotes(index, quotes))
+    }
+    fn get_quotes_in_range(
+        &mut self,
+        ticker_id: usize,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+    ) -> Result<Vec<Quote>, DataError> {
+        let (index, local_id) = self.local_id(ticker_id);
+        let quotes = self.handlers[index].get_quotes_in_range(local_id, start, end)?;
+        Ok(self.merged_quotes(index, quotes))
+    }
+    fn get_quotes_page(
+        &mut self,
+        query: &QuoteQuery,
+        after_id: Option<usize>,
+        limit: usize,
+    ) -> Result<Vec<Quote>, DataError> {
+        let n = self.handlers.len();
+        let mut quotes = Vec::new();
+        for index in 0..n {
+            let mut local_query = query.clone();
+            if let Some(ticker_ids) = &query.ticker_ids {
+                let local_ids: Vec<usize> = ticker_ids
+                    .iter()
+                    .filter(|id| *id % n == index)
+                    .map(|id| id / n)
+                    .collect();
+                if local_ids.is_empty() {
+                    continue;
+                }
+                local_query.ticker_ids = Some(local_ids);
+            }
+            // largest local id translated to at most `after_id`
+            let local_after = match after_id {
+                Some(after_id) if after_id >= index => Some((after_id - index) / n),
+                _ => None,
+            };
+            let local = self.handlers[index].get_quotes_page(&local_query, local_after, limit)?;
+            quotes.extend(self.merged_quotes(index, local));
+        }
+        quotes.sort_by_key(|quote| quote.id);
+        quotes.truncate(limit);
+        Ok(quotes)
+    }
+    fn get_latest_quotes_for_tickers(
+        &mut self,
+        ticker_ids: &[usize],
+    ) -> Result<Vec<(usize, Quote)>, DataError> {
+        let n = self.handlers.len();
+        let mut latest = HashMap::new();
+        for index in 0..n {
+            let local_ids: Vec<usize> = ticker_ids
+                .iter()
+                .filter(|id| *id % n == index)
+                .map(|id| id / n)
+                .collect();
+            if local_ids.is_empty() {
+                continue;
+            }
+            for (id, quote) in self.handlers[index].get_latest_quotes_for_tickers(&local_ids)? {
+                latest.insert(self.merged_id(index, id), self.merged_quote(index, quote));
+            }
+        }
+        Ok(ticker_ids
+            .iter()
+            .filter_map(|id| latest.remove(id).map(|quote| (*id, quote)))
+            .collect())
+    }
+    fn update_quote(&mut self, _quote: &Quote) -> Result<(), DataError> {
+        read_only("updating quote")
+    }
+    fn delete_quote(&mut self, _id: usize) -> Result<(), DataError> {
+        read_only("deleting quote")
+    }
+    fn delete_quotes_for_ticker(&mut self, _ticker_id: usize) -> Result<usize, DataError> {
+        read_only("deleting quotes")
+    }
+    fn delete_quotes_before(
+        &mut self,
+        _ticker_id: usize,
+        _time: DateTime<Utc>,
+    ) -> Result<usize, DataError> {
+        read_only("deleting quotes")
+    }
+    fn store_quotes(&mut self, _quotes: &[Quote]) -> Result<Vec<usize>, DataError> {
+        read_only("storing quotes")
+    }
+    fn insert_quotes(&mut self, _quotes: &[Quote]) -> Result<Vec<usize>, DataError> {
+        read_only("inserting quotes")
+    }
+    fn get_stale_tickers(&mut self, before: DateTime<Utc>) -> Result<Vec<Ticker>, DataError> {
+        self.all_tickers(|handler| handler.get_stale_tickers(before))
+    }
+    /// Rounding conventions are taken from the first handler
+    fn get_rounding_digits(&mut self, currency: Currency) -> i32 {
+        self.handlers[0].get_rounding_digits(currency)
+    }
+    fn set_rounding_digits(&mut self, _currency: Currency, _digits: i32) -> Result<(), DataError> {
+        read_only("setting rounding digits")
+    }
+    fn add_quota_usage(
+        &mut self,
+        _source: &str,
+        _month: QuotaMonth,
+        _calls: u64,
+    ) -> Result<(), DataError> {
+        read_only("adding quota usage")
+    }
+    /// Total quota usage recorded by all handlers
+    fn get_quota_usage(&mut self, source: &str, month: QuotaMonth) -> Result<u64, DataError> {
+        let mut usage = 0;
+        for handler in self.handlers.iter_mut() {
+            usage += handler.get_quota_usage(source, month)?;
+        }
+        Ok(usage)
+    }
+}
+
+impl TransactionHandler for MergedReader<'_> {
+    fn insert_transaction(&mut self, _transaction: &Transaction) -> Result<usize, DataError> {
+        read_only("inserting transaction")
+    }
+    fn get_transaction_by_id(&mut self, id: usize) -> Result<Transaction, DataError> {
+        let (index, local_id) = self.local_id(id);
+        let transaction = self.handlers[index].get_transaction_by_id(local_id)?;
+        self.merged_transaction(index, transaction)
+    }
+    /// Get the transactions of all handlers, in order of the handlers
+    fn get_all_transactions(&mut self) -> Result<Vec<Transaction>, DataError> {
+        let mut transactions = Vec::new();
+        for index in 0..self.handlers.len() {
+            let local = self.handlers[index].get_all_transactions()?;
+            transactions.extend(self.merged_transactions(index, local)?);
+        }
+        Ok(transactions)
+    }
+    fn update_transaction(&mut self, _transaction: &Transaction) -> Result<(), DataError> {
+        read_only("updating transaction")
+    }
+    fn delete_transaction(&mut self, _id: usize) -> Result<(), DataError> {
+        read_only("deleting transaction")
+    }
+    fn find_transaction_by_order_id(
+        &mut self,
+        order_id: &str,
+    ) -> Result<Vec<Transaction>, DataError> {
+        let mut transactions = Vec::new();
+        for index in 0..self.handlers.len() {
+            let local = self.handlers[index].find_transaction_by_order_id(order_id)?;
+            transactions.extend(self.merged_transactions(index, local)?);
+        }
+        Ok(transactions)
+    }
+
+    fn insert_lot_selection(
+        &mut self,
+        _sell_transaction_id: usize,
+        _lots: &[LotSelection],
+    ) -> Result<(), DataError> {
+        read_only("inserting lot selection")
+    }
+    fn insert_transaction_with_lot_selection(
+        &mut self,
+        _transaction: &Transaction,
+        _lots: &[LotSelection],
+    ) -> Result<usize, DataError> {
+        read_only("inserting transaction")
+    }
+    fn get_lot_selection(
+        &mut self,
+        sell_transaction_id: usize,
+    ) -> Result<Vec<LotSelection>, DataError> {
+        let (index, local_id) = self.local_id(sell_transaction_id);
+        Ok(self.handlers[index]
+            .get_lot_selection(local_id)?
+            .into_iter()
+            .map(|lot| LotSelection {
+                buy_transaction_id: self.merged_id(index, lot.buy_transaction_id),
+                quantity: lot.quantity,
+            })
+            .collect())
+    }
+
+    fn set_fee_schedule(&mut self, _schedule: &FeeSchedule) -> Result<(), DataError> {
+        read_only("storing fee schedule")
+    }
+    /// Get the fee schedule of the first handler that has one for the account
+    fn get_fee_schedule(&mut self, account_id: usize) -> Result<Option<FeeSchedule>, DataError> {
+        for handler in self.handlers.iter_mut() {
+            if let Some(schedule) = handler.get_fee_schedule(account_id)? {
+                return Ok(Some(schedule));
+            }
+        }
+        Ok(None)
+    }
+    fn delete_fee_schedule(&mut self, _account_id: usize) -> Result<(), DataError> {
+        read_only("deleting fee schedule")
+    }
+}
+
+impl DataHandler for MergedReader<'_> {
+    fn delete_history(
+        &mut self,
+        _quote_ids: &[usize],
+        _transaction_ids: &[usize],
+    ) -> Result<(), DataError> {
+        read_only("deleting history")
+    }
+}
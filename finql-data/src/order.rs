@@ -0,0 +1,468 @@
+//! Orders recorded before they are executed, e.g. intended or pending trades
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::currency::Currency;
+use crate::transaction::{Transaction, TransactionType};
+use crate::transaction_handler::TransactionHandler;
+use crate::DataError;
+
+/// Direction of an order
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum OrderSide {
+    Buy,
+    Sell,
+}
+
+impl fmt::Display for OrderSide {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Buy => write!(f, "buy"),
+            Self::Sell => write!(f, "sell"),
+        }
+    }
+}
+
+impl FromStr for OrderSide {
+    type Err = DataError;
+
+    fn from_str(side: &str) -> Result<OrderSide, DataError> {
+        match side {
+            "buy" => Ok(Self::Buy),
+            "sell" => Ok(Self::Sell),
+            _ => Err(DataError::InvalidData(format!(
+                "unknown order side '{}'",
+                side
+            ))),
+        }
+    }
+}
+
+/// Size of an order, either as number of units or as cash amount in the order currency
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum OrderSize {
+    Quantity(f64),
+    Amount(f64),
+}
+
+impl OrderSize {
+    /// Construct the order size from the stored columns, exactly one of them must be given
+    pub fn from_columns(quantity: Option<f64>, amount: Option<f64>) -> Result<Self, DataError> {
+        match (quantity, amount) {
+            (Some(quantity), None) => Ok(Self::Quantity(quantity)),
+            (None, Some(amount)) => Ok(Self::Amount(amount)),
+            _ => Err(DataError::InvalidData(
+                "order requires either quantity or amount".to_string(),
+            )),
+        }
+    }
+
+    pub fn quantity(&self) -> Option<f64> {
+        match self {
+            Self::Quantity(quantity) => Some(*quantity),
+            Self::Amount(_) => None,
+        }
+    }
+
+    pub fn amount(&self) -> Option<f64> {
+        match self {
+            Self::Quantity(_) => None,
+            Self::Amount(amount) => Some(*amount),
+        }
+    }
+}
+
+/// Life cycle state of an order
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum OrderState {
+    Draft,
+    Submitted,
+    Filled,
+    Cancelled,
+}
+
+impl OrderState {
+    /// Check whether changing from this state to `next` is allowed. Drafts may be submitted
+    /// or cancelled, submitted orders may be filled or cancelled. Filled and cancelled
+    /// orders are final.
+    pub fn can_transition_to(&self, next: OrderState) -> bool {
+        matches!(
+            (self, next),
+            (Self::Draft, Self::Submitted)
+                | (Self::Draft, Self::Cancelled)
+                | (Self::Submitted, Self::Filled)
+                | (Self::Submitted, Self::Cancelled)
+        )
+    }
+
+    /// Orders that are neither filled nor cancelled yet
+    pub fn is_pending(&self) -> bool {
+        matches!(self, Self::Draft | Self::Submitted)
+    }
+}
+
+impl fmt::Display for OrderState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Draft => write!(f, "draft"),
+            Self::Submitted => write!(f, "submitted"),
+            Self::Filled => write!(f, "filled"),
+            Self::Cancelled => write!(f, "cancelled"),
+        }
+    }
+}
+
+impl FromStr for OrderState {
+    type Err = DataError;
+
+    fn from_str(state: &str) -> Result<OrderState, DataError> {
+        match state {
+            "draft" => Ok(Self::Draft),
+            "submitted" => Ok(Self::Submitted),
+            "filled" => Ok(Self::Filled),
+            "cancelled" => Ok(Self::Cancelled),
+            _ => Err(DataError::InvalidData(format!(
+                "unknown order state '{}'",
+                state
+            ))),
+        }
+    }
+}
+
+/// Errors related to orders
+#[derive(Debug)]
+pub enum OrderError {
+    /// The requested change of the order state is not allowed
+    InvalidTransition {
+        from: OrderState,
+        to: OrderState,
+    },
+    /// The execution does not match the order
+    InvalidExecution(String),
+    DBError(DataError),
+}
+
+impl fmt::Display for OrderError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidTransition { from, to } => {
+                write!(f, "order state can't change from {} to {}", from, to)
+            }
+            Self::InvalidExecution(err) => write!(f, "invalid order execution: {}", err),
+            Self::DBError(_) => write!(f, "database error"),
+        }
+    }
+}
+
+impl Error for OrderError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::DBError(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<DataError> for OrderError {
+    fn from(error: DataError) -> Self {
+        Self::DBError(error)
+    }
+}
+
+/// Order to buy or sell an asset
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Order {
+    pub id: Option<usize>,
+    pub asset_id: usize,
+    pub side: OrderSide,
+    pub size: OrderSize,
+    /// Currency of the limit price and, if given, the order amount
+    pub currency: Currency,
+    pub limit_price: Option<f64>,
+    pub state: OrderState,
+    pub created: DateTime<Utc>,
+    pub updated: DateTime<Utc>,
+    /// Ids of the transactions resulting from filling the order
+    pub transaction_ids: Vec<usize>,
+    pub note: Option<String>,
+}
+
+impl Order {
+    /// Create a new draft order
+    pub fn new(
+        asset_id: usize,
+        side: OrderSide,
+        size: OrderSize,
+        currency: Currency,
+        limit_price: Option<f64>,
+        time: DateTime<Utc>,
+    ) -> Order {
+        Order {
+            id: None,
+            asset_id,
+            side,
+            size,
+            currency,
+            limit_price,
+            state: OrderState::Draft,
+            created: time,
+            updated: time,
+            transaction_ids: Vec::new(),
+            note: None,
+        }
+    }
+
+    /// Change the order state, if the transition is allowed
+    pub fn transition(&mut self, state: OrderState, time: DateTime<Utc>) -> Result<(), OrderError> {
+        if !self.state.can_transition_to(state) {
+            return Err(OrderError::InvalidTransition {
+                from: self.state,
+                to: state,
+            });
+        }
+        self.state = state;
+        self.updated = time;
+        Ok(())
+    }
+
+    /// Change in position if the order would be filled, negative for sell orders.
+    /// Orders given by amount are converted with the limit price. Returns `None`
+    /// for orders that are not pending or whose position can't be determined.
+    pub fn hypothetical_position(&self) -> Option<f64> {
+        if !self.state.is_pending() {
+            return None;
+        }
+        let quantity = match self.size {
+            OrderSize::Quantity(quantity) => quantity,
+            OrderSize::Amount(amount) => amount / self.limit_price.filter(|p| *p > 0.0)?,
+        };
+        match self.side {
+            OrderSide::Buy => Some(quantity),
+            OrderSide::Sell => Some(-quantity),
+        }
+    }
+}
+
+/// Group of transactions executing an order, i.e. the asset transaction and
+/// related fees or taxes
+#[derive(Debug, Clone)]
+pub struct TransactionDraft {
+    pub trade: Transaction,
+    /// Fee and tax transactions, their reference is set to the trade when stored
+    pub charges: Vec<Transaction>,
+}
+
+impl TransactionDraft {
+    /// Check that the draft executes the given order
+    pub fn validate_for(&self, order: &Order) -> Result<(), OrderError> {
+        match self.trade.transaction_type {
+            TransactionType::Asset { asset_id, position } => {
+                if asset_id != order.asset_id {
+                    return Err(OrderError::InvalidExecution(format!(
+                        "trade is in asset {}, but order is in asset {}",
+                        asset_id, order.asset_id
+                    )));
+                }
+                let expected = match order.side {
+                    OrderSide::Buy => position > 0.0,
+                    OrderSide::Sell => position < 0.0,
+                };
+                if !expected {
+                    return Err(OrderError::InvalidExecution(format!(
+                        "position {} does not match {} order",
+                        position, order.side
+                    )));
+                }
+            }
+            _ => {
+                return Err(OrderError::InvalidExecution(
+                    "trade must be an asset transaction".to_string(),
+                ))
+            }
+        }
+        for charge in &self.charges {
+            match charge.transaction_type {
+                TransactionType::Fee { .. } | TransactionType::Tax { .. } => {}
+                _ => {
+                    return Err(OrderError::InvalidExecution(
+                        "charges must be fee or tax transactions".to_string(),
+                    ))
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Handler for orders
+pub trait OrderHandler: TransactionHandler {
+    // insert, get, update and delete for orders
+    fn insert_order(&mut self, order: &Order) -> Result<usize, DataError>;
+    fn get_order_by_id(&mut self, id: usize) -> Result<Order, DataError>;
+    fn get_all_orders(&mut self) -> Result<Vec<Order>, DataError>;
+    fn update_order(&mut self, order: &Order) -> Result<(), DataError>;
+    fn delete_order(&mut self, id: usize) -> Result<(), DataError>;
+
+    /// Insert the transactions of the execution and mark the order as filled, linking the
+    /// new transactions. Either all changes are stored or none of them.
+    /// Returns the ids of the new transactions, starting with the trade.
+    fn fill_order(
+        &mut self,
+        order_id: usize,
+        execution: &TransactionDraft,
+        time: DateTime<Utc>,
+    ) -> Result<Vec<usize>, OrderError>;
+
+    /// Change the state of a stored order, e.g. to submit or cancel it
+    fn set_order_state(
+        &mut self,
+        order_id: usize,
+        state: OrderState,
+        time: DateTime<Utc>,
+    ) -> Result<(), OrderError> {
+        let mut order = self.get_order_by_id(order_id)?;
+        order.transition(state, time)?;
+        self.update_order(&order)?;
+        Ok(())
+    }
+
+    /// Get all orders that are neither filled nor cancelled
+    fn get_pending_orders(&mut self) -> Result<Vec<Order>, DataError> {
+        Ok(self
+            .get_all_orders()?
+            .into_iter()
+            .filter(|order| order.state.is_pending())
+            .collect())
+    }
+}
+
+/// Store the execution of an order and mark it as filled. This does not take care of atomicity,
+/// it is intended to be called by implementations of `OrderHandler::fill_order` within a
+/// database transaction.
+pub fn store_order_fill<H: OrderHandler + ?Sized>(
+    db: &mut H,
+    order_id: usize,
+    execution: &TransactionDraft,
+    time: DateTime<Utc>,
+) -> Result<Vec<usize>, OrderError> {
+    let mut order = db.get_order_by_id(order_id)?;
+    order.transition(OrderState::Filled, time)?;
+    execution.validate_for(&order)?;
+    let trade_id = db.insert_transaction(&execution.trade)?;
+    let mut ids = vec![trade_id];
+    for charge in &execution.charges {
+        let mut charge = charge.clone();
+        charge.transaction_type = match charge.transaction_type {
+            TransactionType::Fee { .. } => TransactionType::Fee {
+                transaction_ref: Some(trade_id),
+            },
+            _ => TransactionType::Tax {
+                transaction_ref: Some(trade_id),
+            },
+        };
+        ids.push(db.insert_transaction(&charge)?);
+    }
+    order.transaction_ids = ids.clone();
+    db.update_order(&order)?;
+    Ok(ids)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cash_flow::CashFlow;
+    use chrono::{NaiveDate, TimeZone};
+
+    #[test]
+    fn order_state_transitions() {
+        let eur = Currency::from_str("EUR").unwrap();
+        let time = Utc.ymd(2021, 3, 1).and_hms(9, 0, 0);
+        let mut order = Order::new(
+            1,
+            OrderSide::Buy,
+            OrderSize::Quantity(10.0),
+            eur,
+            None,
+            time,
+        );
+        assert!(matches!(
+            order.transition(OrderState::Filled, time),
+            Err(OrderError::InvalidTransition {
+                from: OrderState::Draft,
+                to: OrderState::Filled
+            })
+        ));
+        let later = Utc.ymd(2021, 3, 2).and_hms(9, 0, 0);
+        order.transition(OrderState::Submitted, later).unwrap();
+        assert_eq!(order.updated, later);
+        assert_eq!(order.hypothetical_position(), Some(10.0));
+        order.transition(OrderState::Cancelled, later).unwrap();
+        assert_eq!(order.hypothetical_position(), None);
+        assert!(order.transition(OrderState::Submitted, later).is_err());
+
+        let sell = Order::new(
+            1,
+            OrderSide::Sell,
+            OrderSize::Amount(500.0),
+            eur,
+            Some(50.0),
+            time,
+        );
+        assert_eq!(sell.hypothetical_position(), Some(-10.0));
+        assert_eq!(
+            OrderState::from_str("submitted").unwrap(),
+            OrderState::Submitted
+        );
+    }
+
+    #[test]
+    fn validate_execution() {
+        let eur = Currency::from_str("EUR").unwrap();
+        let time = Utc.ymd(2021, 3, 1).and_hms(9, 0, 0);
+        let order = Order::new(
+            1,
+            OrderSide::Sell,
+            OrderSize::Quantity(10.0),
+            eur,
+            None,
+            time,
+        );
+        let date = NaiveDate::from_ymd(2021, 3, 2);
+        let mut draft = TransactionDraft {
+            trade: Transaction {
+                id: None,
+                transaction_type: TransactionType::Asset {
+                    asset_id: 1,
+                    position: -10.0,
+                },
+                cash_flow: CashFlow::new(500.0, eur, date),
+                note: None,
+                execution_meta: None,
+                recorded_at: None,
+            },
+            charges: vec![Transaction {
+                id: None,
+                transaction_type: TransactionType::Fee {
+                    transaction_ref: None,
+                },
+                cash_flow: CashFlow::new(-5.0, eur, date),
+                note: None,
+                execution_meta: None,
+                recorded_at: None,
+            }],
+        };
+        assert!(draft.validate_for(&order).is_ok());
+        draft.trade.transaction_type = TransactionType::Asset {
+            asset_id: 1,
+            position: 10.0,
+        };
+        assert!(matches!(
+            draft.validate_for(&order),
+            Err(OrderError::InvalidExecution(_))
+        ));
+    }
+}
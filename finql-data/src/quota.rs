@@ -0,0 +1,104 @@
+//! Accounting of calls to market data providers against monthly quotas
+//!
+//! Many market data plans limit the number of requests per calendar month. Calls are counted
+//! per source and month and persisted, so that the usage is known across runs. Months are
+//! always calendar months in UTC, independent of the local time zone.
+
+use std::fmt;
+use std::str::FromStr;
+
+use chrono::{DateTime, Datelike, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::DataError;
+
+/// Calendar month in UTC provider calls are accounted for
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct QuotaMonth {
+    pub year: i32,
+    /// Month of the year, starting with 1 for January
+    pub month: u32,
+}
+
+impl QuotaMonth {
+    /// Month the given time falls into in UTC
+    pub fn of(time: DateTime<Utc>) -> QuotaMonth {
+        QuotaMonth {
+            year: time.year(),
+            month: time.month(),
+        }
+    }
+
+    /// Month following this one, i.e. the month the quota is reset
+    pub fn next(&self) -> QuotaMonth {
+        if self.month == 12 {
+            QuotaMonth {
+                year: self.year + 1,
+                month: 1,
+            }
+        } else {
+            QuotaMonth {
+                year: self.year,
+                month: self.month + 1,
+            }
+        }
+    }
+}
+
+impl fmt::Display for QuotaMonth {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:04}-{:02}", self.year, self.month)
+    }
+}
+
+impl FromStr for QuotaMonth {
+    type Err = DataError;
+
+    /// Parse a month given as `YYYY-MM`
+    fn from_str(month: &str) -> Result<QuotaMonth, DataError> {
+        let invalid = || DataError::InvalidData(format!("invalid quota month '{}'", month));
+        let mut parts = month.splitn(2, '-');
+        let year = parts
+            .next()
+            .and_then(|year| year.parse().ok())
+            .ok_or_else(invalid)?;
+        let month_of_year = parts
+            .next()
+            .and_then(|month| month.parse().ok())
+            .filter(|month| (1..=12).contains(month))
+            .ok_or_else(invalid)?;
+        Ok(QuotaMonth {
+            year,
+            month: month_of_year,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{FixedOffset, TimeZone};
+
+    #[test]
+    fn month_rollover_in_utc() {
+        let march = QuotaMonth {
+            year: 2021,
+            month: 3,
+        };
+        assert_eq!(
+            QuotaMonth::of(Utc.ymd(2021, 3, 31).and_hms(23, 59, 59)),
+            march
+        );
+        // already April in Berlin, but still March in UTC
+        let berlin = FixedOffset::east(2 * 3600)
+            .ymd(2021, 4, 1)
+            .and_hms(1, 30, 0)
+            .with_timezone(&Utc);
+        assert_eq!(QuotaMonth::of(berlin), march);
+        assert_eq!(march.next().to_string(), "2021-04");
+        let december = QuotaMonth::from_str("2021-12").unwrap();
+        assert_eq!(december.next().to_string(), "2022-01");
+        assert!(QuotaMonth::from_str("2021-13").is_err());
+        assert!(QuotaMonth::from_str("March").is_err());
+    }
+}
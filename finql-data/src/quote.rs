@@ -1,8 +1,14 @@
 ///! Implementation of a container for basic asset data
-use chrono::{DateTime, Utc};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::str::FromStr;
+use std::sync::Mutex;
+
+use chrono::{DateTime, Duration, NaiveDate, Utc};
 use serde::{Deserialize, Serialize};
 
-use crate::currency::Currency;
+use crate::currency::{Currency, CurrencyConverter};
+use crate::quote_handler::{QuoteHandler, QuoteInsertion};
 use super::{DataError, DataItem};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -12,17 +18,622 @@ pub struct Ticker {
     pub name: String,
     pub currency: Currency,
     pub source: String,
+    /// Ticker with lower values are preferred if several ticker of an asset have quotes of
+    /// the same time
     pub priority: i32,
     pub factor: f64,
+    /// Optional documentation where the quotes of this ticker come from
+    pub source_url: Option<String>,
+    /// Purpose the quotes of this ticker should be used for
+    #[serde(default)]
+    pub usage: TickerUsage,
+    /// Time of the latest quote stored for this ticker, cached by the database on every
+    /// change of its quotes. Ignored when inserting or updating a ticker.
+    #[serde(default)]
+    pub last_quote_time: Option<DateTime<Utc>>,
+    /// Sources to fall back to, in this order, if quotes can't be fetched from `source`
+    #[serde(default)]
+    pub source_chain: Vec<String>,
+}
+
+/// Purpose of a ticker's quotes, e.g. official closing prices for valuation
+/// and realtime quotes for intraday charts
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TickerUsage {
+    Valuation,
+    Charting,
+    #[default]
+    Both,
+}
+
+impl TickerUsage {
+    /// Check whether quotes of a ticker with this usage may be used for the requested purpose
+    pub fn serves(&self, requested: TickerUsage) -> bool {
+        *self == Self::Both || requested == Self::Both || *self == requested
+    }
+}
+
+impl fmt::Display for TickerUsage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Valuation => write!(f, "valuation"),
+            Self::Charting => write!(f, "charting"),
+            Self::Both => write!(f, "both"),
+        }
+    }
+}
+
+impl FromStr for TickerUsage {
+    type Err = DataError;
+
+    fn from_str(usage: &str) -> Result<TickerUsage, DataError> {
+        match usage {
+            "valuation" => Ok(Self::Valuation),
+            "charting" => Ok(Self::Charting),
+            "both" => Ok(Self::Both),
+            _ => Err(DataError::NotFound(format!(
+                "unknown ticker usage '{}'",
+                usage
+            ))),
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Quote {
     pub id: Option<usize>,
     pub ticker: usize,
+    /// Last price, i.e. the closing price if the quote covers a period like a trading day
     pub price: f64,
     pub time: DateTime<Utc>,
     pub volume: Option<f64>,
+    /// Reliability of the quote between 0 (unreliable) and 1, e.g. lower for delayed data
+    #[serde(default)]
+    pub quality_score: Option<f64>,
+    /// Source the quote has been fetched from, which is not necessarily the ticker's primary
+    /// source if the ticker has a source chain
+    #[serde(default)]
+    pub source: Option<String>,
+    /// Opening price of the period the quote covers, e.g. the trading day
+    #[serde(default)]
+    pub open: Option<f64>,
+    /// Highest price of the period the quote covers
+    #[serde(default)]
+    pub high: Option<f64>,
+    /// Lowest price of the period the quote covers
+    #[serde(default)]
+    pub low: Option<f64>,
+    /// Best bid price at the time of the quote
+    #[serde(default)]
+    pub bid: Option<f64>,
+    /// Best ask price at the time of the quote
+    #[serde(default)]
+    pub ask: Option<f64>,
+}
+
+/// Minimum difference of quality scores for a quote to be preferred over a quote of the same
+/// time from a ticker with better priority
+pub const QUALITY_SCORE_PREFERENCE: f64 = 0.1;
+
+/// Filter on quotes, by default matching all quotes
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct QuoteQuery {
+    /// Only quotes of the given ticker, if set
+    pub ticker_ids: Option<Vec<usize>>,
+    /// Only quotes at or after the given time, if set
+    pub start: Option<DateTime<Utc>>,
+    /// Only quotes at or before the given time, if set
+    pub end: Option<DateTime<Utc>>,
+}
+
+impl QuoteQuery {
+    /// Query matching all quotes
+    pub fn new() -> QuoteQuery {
+        QuoteQuery::default()
+    }
+
+    pub fn ticker_ids(mut self, ticker_ids: &[usize]) -> QuoteQuery {
+        self.ticker_ids = Some(ticker_ids.to_vec());
+        self
+    }
+
+    pub fn start(mut self, start: DateTime<Utc>) -> QuoteQuery {
+        self.start = Some(start);
+        self
+    }
+
+    pub fn end(mut self, end: DateTime<Utc>) -> QuoteQuery {
+        self.end = Some(end);
+        self
+    }
+
+    /// Check whether a quote passes the filter
+    pub fn matches(&self, quote: &Quote) -> bool {
+        !matches!(&self.ticker_ids, Some(ids) if !ids.contains(&quote.ticker))
+            && !matches!(self.start, Some(start) if quote.time < start)
+            && !matches!(self.end, Some(end) if quote.time > end)
+    }
+}
+
+/// Quote as stored in the database with the time not yet parsed, used for bulk loading
+#[derive(Debug, Clone)]
+pub struct RawQuote {
+    /// Time of the quote in RFC 3339 format
+    pub time: String,
+    pub price: f64,
+    /// Priority of the quote's ticker
+    pub priority: i32,
+}
+
+impl Quote {
+    /// Construct a quote at midnight UTC of the given date, e.g. for end of day prices
+    pub fn from_price_point(ticker: usize, date: NaiveDate, price: f64) -> Quote {
+        Quote {
+            id: None,
+            ticker,
+            price,
+            time: DateTime::from_utc(date.and_hms(0, 0, 0), Utc),
+            volume: None,
+            quality_score: None,
+            source: None,
+            open: None,
+            high: None,
+            low: None,
+            bid: None,
+            ask: None,
+        }
+    }
+
+    /// Select one of several quotes of the same time, given in order of preference of their
+    /// ticker, e.g. by priority. A later quote is only preferred if its quality score exceeds
+    /// the score of the currently selected one by more than `QUALITY_SCORE_PREFERENCE`.
+    /// Quotes without quality score are selected by order only.
+    pub fn select_preferred<T>(candidates: Vec<(Quote, T)>) -> Option<(Quote, T)> {
+        let mut candidates = candidates.into_iter();
+        let mut selected = candidates.next()?;
+        for candidate in candidates {
+            if let (Some(score), Some(selected_score)) =
+                (candidate.0.quality_score, selected.0.quality_score)
+            {
+                if score > selected_score + QUALITY_SCORE_PREFERENCE {
+                    selected = candidate;
+                }
+            }
+        }
+        Some(selected)
+    }
+
+    /// Date and price multiplied by `factor`, as used by analytics working on price series
+    pub fn to_price_point(&self, factor: f64) -> (NaiveDate, f64) {
+        (self.time.naive_utc().date(), self.price * factor)
+    }
+
+    /// Copy of the quote with all prices multiplied by `factor`, e.g. the ticker's factor
+    pub fn to_adjusted_quote(&self, factor: f64) -> Quote {
+        let mut quote = self.clone();
+        quote.scale_prices(factor);
+        quote
+    }
+
+    /// Multiply the price and, if given, opening, high, low, bid and ask prices by `factor`
+    pub fn scale_prices(&mut self, factor: f64) {
+        self.price *= factor;
+        let prices = [
+            &mut self.open,
+            &mut self.high,
+            &mut self.low,
+            &mut self.bid,
+            &mut self.ask,
+        ];
+        for price in IntoIterator::into_iter(prices).flatten() {
+            *price *= factor;
+        }
+    }
+
+    /// Convert the price of a quote given in `currency` into `target` currency, using the
+    /// exchange rate at the time of the quote
+    pub fn convert_to(
+        &self,
+        currency: Currency,
+        target: Currency,
+        currency_converter: &mut dyn CurrencyConverter,
+    ) -> Result<Quote, DataError> {
+        if currency == target {
+            return Ok(self.clone());
+        }
+        let fx_rate = currency_converter
+            .fx_rate(currency, target, self.time)
+            .map_err(|e| {
+                DataError::CurrencyMismatch(format!(
+                    "no exchange rate from {} to {}: {}",
+                    currency, target, e
+                ))
+            })?;
+        Ok(self.to_adjusted_quote(fx_rate))
+    }
+}
+
+/// Parse a comma separated list of sources, e.g. `yahoo,eod,stooq`. Fails if any source is
+/// empty or listed more than once.
+pub fn parse_source_chain(chain: &str) -> Result<Vec<String>, DataError> {
+    let mut sources: Vec<String> = Vec::new();
+    for source in chain.split(',').map(str::trim) {
+        if source.is_empty() {
+            return Err(DataError::InvalidData(format!(
+                "empty source in source chain '{}'",
+                chain
+            )));
+        }
+        if sources.iter().any(|s| s == source) {
+            return Err(DataError::InvalidData(format!(
+                "source '{}' is listed more than once in source chain '{}'",
+                source, chain
+            )));
+        }
+        sources.push(source.to_string());
+    }
+    Ok(sources)
+}
+
+impl Ticker {
+    /// All sources of the ticker in order of precedence, i.e. the primary source followed by
+    /// the source chain
+    pub fn sources(&self) -> impl Iterator<Item = &str> {
+        std::iter::once(self.source.as_str()).chain(self.source_chain.iter().map(String::as_str))
+    }
+
+    /// Set primary source and fallback sources from a comma separated list like
+    /// `yahoo,eod,stooq`, see `parse_source_chain`
+    pub fn set_source_chain(&mut self, chain: &str) -> Result<(), DataError> {
+        let mut sources = parse_source_chain(chain)?.into_iter();
+        // parse_source_chain returns at least one source
+        self.source = sources.next().unwrap_or_default();
+        self.source_chain = sources.collect();
+        Ok(())
+    }
+
+    /// Check business rules that must hold before a ticker is stored in the database
+    pub fn validate(&self) -> Result<(), DataError> {
+        if self.name.is_empty() {
+            return Err(DataError::InvalidData("ticker name must not be empty".to_string()));
+        }
+        if self.source.is_empty() {
+            return Err(DataError::InvalidData(format!(
+                "source of ticker '{}' must not be empty",
+                self.name
+            )));
+        }
+        if self.factor.is_nan() || self.factor <= 0.0 {
+            return Err(DataError::InvalidData(format!(
+                "factor of ticker '{}' must be positive, but is {}",
+                self.name, self.factor
+            )));
+        }
+        if self.priority < 0 {
+            return Err(DataError::InvalidData(format!(
+                "priority of ticker '{}' must not be negative, but is {}",
+                self.name, self.priority
+            )));
+        }
+        if !self.source_chain.is_empty() {
+            parse_source_chain(&self.sources().collect::<Vec<_>>().join(","))?;
+        }
+        Ok(())
+    }
+}
+
+impl Quote {
+    /// Check business rules that must hold before a quote is stored in the database
+    pub fn validate(&self) -> Result<(), DataError> {
+        if !self.price.is_finite() || self.price <= 0.0 {
+            return Err(DataError::InvalidData(format!(
+                "quote price must be positive and finite, but is {}",
+                self.price
+            )));
+        }
+        if let Some(score) = self.quality_score {
+            if !(0.0..=1.0).contains(&score) {
+                return Err(DataError::InvalidData(format!(
+                    "quality score must be between 0 and 1, but is {}",
+                    score
+                )));
+            }
+        }
+        for (name, price) in &[
+            ("open", self.open),
+            ("high", self.high),
+            ("low", self.low),
+            ("bid", self.bid),
+            ("ask", self.ask),
+        ] {
+            if let Some(price) = price {
+                if !price.is_finite() || *price <= 0.0 {
+                    return Err(DataError::InvalidData(format!(
+                        "{} price must be positive and finite, but is {}",
+                        name, price
+                    )));
+                }
+            }
+        }
+        if let (Some(low), Some(high)) = (self.low, self.high) {
+            if low > high {
+                return Err(DataError::InvalidData(format!(
+                    "low price {} exceeds high price {}",
+                    low, high
+                )));
+            }
+        }
+        if let (Some(bid), Some(ask)) = (self.bid, self.ask) {
+            if bid > ask {
+                return Err(DataError::InvalidData(format!(
+                    "bid price {} exceeds ask price {}",
+                    bid, ask
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Source of market quotes, e.g. a web service
+pub trait QuoteProvider {
+    /// Fetch all quotes of the ticker between start and end date (both inclusive)
+    fn fetch_quotes(
+        &self,
+        ticker: &Ticker,
+        start: NaiveDate,
+        end: NaiveDate,
+    ) -> Result<Vec<Quote>, DataError>;
+    /// Check whether the provider is able to fetch quotes for tickers of the given source
+    fn supports_source(&self, source: &str) -> bool;
+}
+
+/// Quotes fetched for a ticker together with the source that supplied them
+#[derive(Debug, Clone)]
+pub struct FetchedQuotes {
+    /// Source the quotes have been fetched from
+    pub source: String,
+    pub quotes: Vec<Quote>,
+    /// Sources tried before without success, together with the error message
+    pub failed_sources: Vec<(String, String)>,
+}
+
+impl FetchedQuotes {
+    /// Check whether the quotes have been supplied by a fallback source
+    pub fn used_fallback(&self) -> bool {
+        !self.failed_sources.is_empty()
+    }
+}
+
+/// Collection of quote providers, quotes are fetched by the first provider
+/// supporting the ticker's source
+#[derive(Default)]
+pub struct QuoteProviderRegistry {
+    providers: Vec<Box<dyn QuoteProvider>>,
+    /// Delay in minutes of the intraday quotes per source, e.g. 15 for free Yahoo quotes
+    delays: HashMap<String, u32>,
+    /// Soft limit of provider calls per calendar month per source
+    quota_limits: HashMap<String, u64>,
+    /// Provider calls per source not yet added to the persisted quota usage
+    pending_calls: Mutex<HashMap<String, u64>>,
+    /// Sources not called anymore since their quota is used up
+    deferred: Mutex<HashSet<String>>,
+}
+
+impl QuoteProviderRegistry {
+    pub fn new() -> QuoteProviderRegistry {
+        QuoteProviderRegistry::default()
+    }
+
+    /// Add a provider, providers registered earlier take precedence
+    pub fn register(&mut self, provider: Box<dyn QuoteProvider>) {
+        self.providers.push(provider);
+    }
+
+    /// Get the first provider supporting the given source
+    pub fn provider_for(&self, source: &str) -> Option<&dyn QuoteProvider> {
+        self.providers
+            .iter()
+            .find(|provider| provider.supports_source(source))
+            .map(|provider| provider.as_ref())
+    }
+
+    /// Set the delay of the intraday quotes of a source, i.e. the quotes fetched reflect
+    /// prices `minutes` before the fetch time
+    pub fn set_delay_minutes(&mut self, source: &str, minutes: u32) {
+        self.delays.insert(source.to_string(), minutes);
+    }
+
+    /// Delay of the intraday quotes of a source, zero for realtime sources
+    pub fn delay_minutes(&self, source: &str) -> u32 {
+        self.delays.get(source).copied().unwrap_or(0)
+    }
+
+    /// Set the soft limit of provider calls per calendar month of a source, e.g. the number of
+    /// requests included in a market data plan. The limit is not enforced by the registry,
+    /// but sources over their limit are expected to be deferred, see `defer_source`.
+    pub fn set_quota_limit(&mut self, source: &str, calls: u64) {
+        self.quota_limits.insert(source.to_string(), calls);
+    }
+
+    /// Soft limit of provider calls per month of a source, None if unlimited
+    pub fn quota_limit(&self, source: &str) -> Option<u64> {
+        self.quota_limits.get(source).copied()
+    }
+
+    /// Stop calling the provider of a source, e.g. since its quota is used up. Fetching quotes
+    /// from a deferred source fails, i.e. ticker fall back to their source chain.
+    pub fn defer_source(&self, source: &str) {
+        self.deferred.lock().unwrap().insert(source.to_string());
+    }
+
+    /// Call the provider of a deferred source again
+    pub fn resume_source(&self, source: &str) {
+        self.deferred.lock().unwrap().remove(source);
+    }
+
+    pub fn is_deferred(&self, source: &str) -> bool {
+        self.deferred.lock().unwrap().contains(source)
+    }
+
+    /// Take the number of provider calls per source made since the last call of this method,
+    /// ordered by source, e.g. to add them to the persisted quota usage
+    pub fn take_pending_calls(&self) -> Vec<(String, u64)> {
+        let mut calls: Vec<(String, u64)> = self.pending_calls.lock().unwrap().drain().collect();
+        calls.sort();
+        calls
+    }
+
+    /// Parse a source chain like `yahoo,eod,stooq` and check that there is a provider for
+    /// each of its sources
+    pub fn validate_source_chain(&self, chain: &str) -> Result<Vec<String>, DataError> {
+        let sources = parse_source_chain(chain)?;
+        if let Some(source) = sources.iter().find(|s| !self.supports_source(s)) {
+            return Err(DataError::NotFound(format!(
+                "no quote provider for source '{}'",
+                source
+            )));
+        }
+        Ok(sources)
+    }
+
+    /// Fetch quotes of the ticker from its primary source or, if that fails, from the sources of
+    /// its source chain in order, until a source succeeds. Sources without provider and deferred
+    /// sources count as failed. Each quote is marked with the source it has been fetched from.
+    /// If all sources fail, the error of the last source is returned. Every call of a provider
+    /// is counted, see `take_pending_calls`.
+    pub fn fetch_quotes_from_chain(
+        &self,
+        ticker: &Ticker,
+        start: NaiveDate,
+        end: NaiveDate,
+    ) -> Result<FetchedQuotes, DataError> {
+        let mut failed_sources = Vec::new();
+        let mut last_error = None;
+        for source in ticker.sources() {
+            let result = if self.is_deferred(source) {
+                Err(DataError::DataAccessFailure(format!(
+                    "source '{}' is deferred",
+                    source
+                )))
+            } else {
+                self.provider_for(source)
+                    .ok_or_else(|| {
+                        DataError::NotFound(format!("no quote provider for source '{}'", source))
+                    })
+                    .and_then(|provider| {
+                        *self
+                            .pending_calls
+                            .lock()
+                            .unwrap()
+                            .entry(source.to_string())
+                            .or_insert(0) += 1;
+                        provider.fetch_quotes(ticker, start, end)
+                    })
+            };
+            match result {
+                Ok(mut quotes) => {
+                    for quote in &mut quotes {
+                        quote.source = Some(source.to_string());
+                    }
+                    return Ok(FetchedQuotes {
+                        source: source.to_string(),
+                        quotes,
+                        failed_sources,
+                    });
+                }
+                Err(err) => {
+                    failed_sources.push((source.to_string(), err.to_string()));
+                    last_error = Some(err);
+                }
+            }
+        }
+        // a ticker has at least one source
+        Err(last_error.unwrap_or_else(|| DataError::NotFound("ticker has no source".to_string())))
+    }
+}
+
+impl QuoteProvider for QuoteProviderRegistry {
+    fn fetch_quotes(
+        &self,
+        ticker: &Ticker,
+        start: NaiveDate,
+        end: NaiveDate,
+    ) -> Result<Vec<Quote>, DataError> {
+        Ok(self.fetch_quotes_from_chain(ticker, start, end)?.quotes)
+    }
+
+    fn supports_source(&self, source: &str) -> bool {
+        self.provider_for(source).is_some()
+    }
+}
+
+/// Outcome of refreshing the quotes of a ticker
+#[derive(Debug, Clone)]
+pub struct TickerRefresh {
+    /// Number of new quotes stored
+    pub count: usize,
+    /// Source the quotes have been fetched from
+    pub source: String,
+    /// Sources of the ticker's source chain tried before without success, together with the
+    /// error message
+    pub failed_sources: Vec<(String, String)>,
+}
+
+/// Fetch all quotes of the ticker newer than the latest stored quote up to and including
+/// `until` and store them, with prices scaled by the ticker's factor. If no quotes have been
+/// stored yet, only quotes of `until` are fetched. Returns the number of new quotes.
+///
+/// Quotes of the day `until` are considered intraday quotes stamped with the fetch time. If
+/// the ticker's source is delayed, their time is moved back by the delay to the time the
+/// prices are effective, see `QuoteProviderRegistry::set_delay_minutes`.
+pub fn refresh_ticker(
+    ticker: &Ticker,
+    handler: &mut dyn QuoteHandler,
+    registry: &QuoteProviderRegistry,
+    until: NaiveDate,
+) -> Result<usize, DataError> {
+    Ok(refresh_ticker_from_chain(ticker, handler, registry, until)?.count)
+}
+
+/// Refresh the quotes of the ticker like `refresh_ticker`, falling back to the sources of the
+/// ticker's source chain if its primary source fails, see
+/// `QuoteProviderRegistry::fetch_quotes_from_chain`. Reports which source supplied the quotes.
+pub fn refresh_ticker_from_chain(
+    ticker: &Ticker,
+    handler: &mut dyn QuoteHandler,
+    registry: &QuoteProviderRegistry,
+    until: NaiveDate,
+) -> Result<TickerRefresh, DataError> {
+    let ticker_id = ticker.get_id()?;
+    let last_time = handler
+        .get_all_quotes_for_ticker(ticker_id)?
+        .into_iter()
+        .map(|quote| quote.time)
+        .max();
+    let start = last_time.map_or(until, |time| time.naive_utc().date());
+    let fetched = registry.fetch_quotes_from_chain(ticker, start, until)?;
+    let delay = Duration::minutes(registry.delay_minutes(&fetched.source) as i64);
+    let mut count = 0;
+    for mut quote in fetched.quotes {
+        if quote.time.naive_utc().date() == until {
+            quote.time -= delay;
+        }
+        if matches!(last_time, Some(time) if quote.time <= time) {
+            continue;
+        }
+        quote.ticker = ticker_id;
+        quote.scale_prices(ticker.factor);
+        if let QuoteInsertion::Inserted(_) = handler.insert_quote_if_new(&quote)? {
+            count += 1;
+        }
+    }
+    Ok(TickerRefresh {
+        count,
+        source: fetched.source,
+        failed_sources: fetched.failed_sources,
+    })
 }
 
 impl DataItem for Quote {
@@ -72,3 +683,398 @@ impl DataItem for Ticker {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use super::*;
+
+    fn valid_ticker() -> Ticker {
+        Ticker {
+            id: None,
+            asset: 1,
+            name: "BAS.DE".to_string(),
+            currency: Currency::from_str("EUR").unwrap(),
+            source: "yahoo".to_string(),
+            priority: 10,
+            factor: 1.0,
+            source_url: None,
+            usage: TickerUsage::Both,
+            last_quote_time: None,
+            source_chain: Vec::new(),
+        }
+    }
+
+    fn is_invalid_data<T>(result: Result<T, DataError>) -> bool {
+        matches!(result, Err(DataError::InvalidData(_)))
+    }
+
+    #[test]
+    fn quote_price_points() {
+        let date = NaiveDate::from_ymd(2021, 3, 2);
+        let quote = Quote::from_price_point(3, date, 50.0);
+        assert_eq!(quote.time.to_rfc3339(), "2021-03-02T00:00:00+00:00");
+        assert_eq!(quote.to_price_point(0.01), (date, 0.5));
+        let quote = Quote {
+            open: Some(40.0),
+            bid: Some(49.5),
+            ..quote
+        };
+        let adjusted = quote.to_adjusted_quote(2.0);
+        assert_eq!(adjusted.price, 100.0);
+        assert_eq!((adjusted.open, adjusted.high), (Some(80.0), None));
+        assert_eq!(adjusted.bid, Some(99.0));
+        assert_eq!((adjusted.ticker, adjusted.time), (3, quote.time));
+    }
+
+    /// Converter with a fixed USD/EUR exchange rate
+    struct UsdEurConverter;
+
+    impl CurrencyConverter for UsdEurConverter {
+        fn fx_rate(
+            &mut self,
+            foreign: Currency,
+            domestic: Currency,
+            _time: DateTime<Utc>,
+        ) -> Result<f64, crate::CurrencyError> {
+            match (foreign.to_string().as_str(), domestic.to_string().as_str()) {
+                ("USD", "EUR") => Ok(0.8),
+                _ => Err(crate::CurrencyError::ConversionFailed),
+            }
+        }
+    }
+
+    #[test]
+    fn convert_quotes_of_alternating_sources() {
+        let eur = Currency::from_str("EUR").unwrap();
+        let usd = Currency::from_str("USD").unwrap();
+        // the winning source alternates between a USD and a EUR ticker
+        let quotes = [
+            (1, 125.0, usd),
+            (2, 101.0, eur),
+            (1, 127.5, usd),
+            (2, 102.5, eur),
+        ];
+        let series: Vec<(NaiveDate, f64)> = quotes
+            .iter()
+            .enumerate()
+            .map(|(day, (ticker, price, currency))| {
+                let date = NaiveDate::from_ymd(2021, 1, 4 + day as u32);
+                Quote::from_price_point(*ticker, date, *price)
+                    .convert_to(*currency, eur, &mut UsdEurConverter)
+                    .unwrap()
+                    .to_price_point(1.0)
+            })
+            .collect();
+        let prices: Vec<f64> = series.iter().map(|(_, price)| *price).collect();
+        assert_eq!(prices, vec![100.0, 101.0, 102.0, 102.5]);
+
+        let quote = Quote::from_price_point(2, NaiveDate::from_ymd(2021, 1, 5), 101.0);
+        assert!(matches!(
+            quote.convert_to(eur, usd, &mut UsdEurConverter),
+            Err(DataError::CurrencyMismatch(_))
+        ));
+    }
+
+    #[test]
+    fn ticker_validation() {
+        assert!(valid_ticker().validate().is_ok());
+
+        let mut ticker = valid_ticker();
+        ticker.name = String::new();
+        assert!(is_invalid_data(ticker.validate()));
+
+        let mut ticker = valid_ticker();
+        ticker.source = String::new();
+        assert!(is_invalid_data(ticker.validate()));
+
+        for factor in &[0.0, -1.0, f64::NAN] {
+            let mut ticker = valid_ticker();
+            ticker.factor = *factor;
+            assert!(is_invalid_data(ticker.validate()));
+        }
+
+        let mut ticker = valid_ticker();
+        ticker.priority = -1;
+        assert!(is_invalid_data(ticker.validate()));
+        ticker.priority = 0;
+        assert!(ticker.validate().is_ok());
+    }
+
+    #[test]
+    fn quote_validation() {
+        let mut quote = Quote {
+            id: None,
+            ticker: 1,
+            price: 67.35,
+            time: Utc::now(),
+            volume: None,
+            quality_score: None,
+            source: None,
+            open: None,
+            high: None,
+            low: None,
+            bid: None,
+            ask: None,
+        };
+        assert!(quote.validate().is_ok());
+        for price in &[0.0, -1.0, f64::NAN, f64::INFINITY] {
+            quote.price = *price;
+            assert!(is_invalid_data(quote.validate()));
+        }
+        quote.price = 67.35;
+        for score in &[-0.1, 1.1, f64::NAN] {
+            quote.quality_score = Some(*score);
+            assert!(is_invalid_data(quote.validate()));
+        }
+        quote.quality_score = Some(1.0);
+        assert!(quote.validate().is_ok());
+
+        quote.open = Some(66.0);
+        quote.high = Some(68.0);
+        quote.low = Some(65.5);
+        quote.bid = Some(67.3);
+        quote.ask = Some(67.4);
+        assert!(quote.validate().is_ok());
+        quote.low = Some(68.5);
+        assert!(is_invalid_data(quote.validate()));
+        quote.low = Some(65.5);
+        quote.bid = Some(67.5);
+        assert!(is_invalid_data(quote.validate()));
+        quote.bid = Some(-67.3);
+        assert!(is_invalid_data(quote.validate()));
+    }
+
+    #[test]
+    fn select_preferred_quote() {
+        let quote = |ticker, quality_score| Quote {
+            id: None,
+            ticker,
+            price: 67.35,
+            time: Utc::now(),
+            volume: None,
+            quality_score,
+            source: None,
+            open: None,
+            high: None,
+            low: None,
+            bid: None,
+            ask: None,
+        };
+        let selected = |candidates: Vec<(Quote, ())>| {
+            Quote::select_preferred(candidates).map(|(quote, _)| quote.ticker)
+        };
+        assert_eq!(selected(vec![]), None);
+        // without scores, the first candidate wins
+        assert_eq!(
+            selected(vec![(quote(1, None), ()), (quote(2, Some(1.0)), ())]),
+            Some(1)
+        );
+        // a small quality advantage does not override the order
+        assert_eq!(
+            selected(vec![(quote(1, Some(0.8)), ()), (quote(2, Some(0.85)), ())]),
+            Some(1)
+        );
+        assert_eq!(
+            selected(vec![
+                (quote(1, Some(0.5)), ()),
+                (quote(2, Some(0.7)), ()),
+                (quote(3, Some(0.75)), ()),
+            ]),
+            Some(2)
+        );
+    }
+
+    /// Provider answering with a single quote whose price identifies the provider
+    struct FixedProvider {
+        source: &'static str,
+        price: f64,
+    }
+
+    impl QuoteProvider for FixedProvider {
+        fn fetch_quotes(
+            &self,
+            ticker: &Ticker,
+            start: NaiveDate,
+            _end: NaiveDate,
+        ) -> Result<Vec<Quote>, DataError> {
+            Ok(vec![Quote {
+                id: None,
+                ticker: ticker.id.unwrap_or_default(),
+                price: self.price,
+                time: DateTime::<Utc>::from_utc(start.and_hms(0, 0, 0), Utc),
+                volume: None,
+                quality_score: None,
+                source: None,
+                open: None,
+                high: None,
+                low: None,
+                bid: None,
+                ask: None,
+            }])
+        }
+
+        fn supports_source(&self, source: &str) -> bool {
+            source == self.source
+        }
+    }
+
+    #[test]
+    fn provider_registry_dispatch() {
+        let mut registry = QuoteProviderRegistry::new();
+        for (source, price) in &[("yahoo", 1.0), ("comdirect", 2.0), ("yahoo", 3.0)] {
+            registry.register(Box::new(FixedProvider {
+                source,
+                price: *price,
+            }));
+        }
+        let date = NaiveDate::from_ymd(2021, 1, 4);
+        let mut ticker = valid_ticker();
+        let quotes = registry.fetch_quotes(&ticker, date, date).unwrap();
+        assert_eq!(quotes[0].price, 1.0);
+        ticker.source = "comdirect".to_string();
+        let quotes = registry.fetch_quotes(&ticker, date, date).unwrap();
+        assert_eq!(quotes[0].price, 2.0);
+        ticker.source = "gurufocus".to_string();
+        assert!(!registry.supports_source("gurufocus"));
+        assert!(matches!(
+            registry.fetch_quotes(&ticker, date, date),
+            Err(DataError::NotFound(_))
+        ));
+    }
+
+    /// Provider which knows no symbol at all
+    struct UnknownSymbolProvider {
+        source: &'static str,
+    }
+
+    impl QuoteProvider for UnknownSymbolProvider {
+        fn fetch_quotes(
+            &self,
+            ticker: &Ticker,
+            _start: NaiveDate,
+            _end: NaiveDate,
+        ) -> Result<Vec<Quote>, DataError> {
+            Err(DataError::NotFound(format!("symbol not found: {}", ticker.name)))
+        }
+
+        fn supports_source(&self, source: &str) -> bool {
+            source == self.source
+        }
+    }
+
+    #[test]
+    fn source_chain_fallback() {
+        let mut registry = QuoteProviderRegistry::new();
+        registry.register(Box::new(UnknownSymbolProvider { source: "yahoo" }));
+        registry.register(Box::new(FixedProvider {
+            source: "eod",
+            price: 2.0,
+        }));
+        registry.register(Box::new(FixedProvider {
+            source: "stooq",
+            price: 3.0,
+        }));
+        let date = NaiveDate::from_ymd(2021, 1, 4);
+        let mut ticker = valid_ticker();
+        assert!(registry.fetch_quotes_from_chain(&ticker, date, date).is_err());
+
+        ticker.set_source_chain("yahoo, eod,stooq").unwrap();
+        assert_eq!(ticker.source, "yahoo");
+        assert_eq!(ticker.source_chain, vec!["eod", "stooq"]);
+        assert!(ticker.validate().is_ok());
+        let fetched = registry.fetch_quotes_from_chain(&ticker, date, date).unwrap();
+        assert_eq!(fetched.source, "eod");
+        assert!(fetched.used_fallback());
+        assert_eq!(fetched.failed_sources[0].0, "yahoo");
+        assert_eq!(fetched.quotes[0].price, 2.0);
+        assert_eq!(fetched.quotes[0].source.as_deref(), Some("eod"));
+
+        // sources without provider are skipped as well
+        ticker.set_source_chain("gurufocus,stooq").unwrap();
+        let fetched = registry.fetch_quotes_from_chain(&ticker, date, date).unwrap();
+        assert_eq!(fetched.source, "stooq");
+        assert_eq!(fetched.failed_sources.len(), 1);
+
+        assert_eq!(
+            registry.validate_source_chain("yahoo,eod").unwrap(),
+            vec!["yahoo", "eod"]
+        );
+        assert!(matches!(
+            registry.validate_source_chain("yahoo,gurufocus"),
+            Err(DataError::NotFound(_))
+        ));
+        assert!(is_invalid_data(parse_source_chain("yahoo,,eod")));
+        assert!(is_invalid_data(parse_source_chain("yahoo,eod,yahoo")));
+        ticker.source_chain = vec!["gurufocus".to_string()];
+        assert!(is_invalid_data(ticker.validate()));
+    }
+
+    #[test]
+    fn deferred_sources_and_call_counts() {
+        let mut registry = QuoteProviderRegistry::new();
+        registry.register(Box::new(UnknownSymbolProvider { source: "yahoo" }));
+        registry.register(Box::new(FixedProvider {
+            source: "eod",
+            price: 2.0,
+        }));
+        registry.set_quota_limit("eod", 100_000);
+        assert_eq!(registry.quota_limit("eod"), Some(100_000));
+        assert_eq!(registry.quota_limit("yahoo"), None);
+        let date = NaiveDate::from_ymd(2021, 1, 4);
+        let mut ticker = valid_ticker();
+        ticker.set_source_chain("eod,yahoo,gurufocus").unwrap();
+
+        registry
+            .fetch_quotes_from_chain(&ticker, date, date)
+            .unwrap();
+        registry
+            .fetch_quotes_from_chain(&ticker, date, date)
+            .unwrap();
+        registry.defer_source("eod");
+        assert!(registry.is_deferred("eod"));
+        assert!(registry
+            .fetch_quotes_from_chain(&ticker, date, date)
+            .is_err());
+        // failed calls count as well, sources without provider and deferred sources don't
+        assert_eq!(
+            registry.take_pending_calls(),
+            vec![("eod".to_string(), 2), ("yahoo".to_string(), 1)]
+        );
+        assert!(registry.take_pending_calls().is_empty());
+
+        registry.resume_source("eod");
+        assert!(registry
+            .fetch_quotes_from_chain(&ticker, date, date)
+            .is_ok());
+    }
+
+    #[test]
+    fn source_delays() {
+        let mut registry = QuoteProviderRegistry::new();
+        registry.set_delay_minutes("yahoo", 15);
+        assert_eq!(registry.delay_minutes("yahoo"), 15);
+        assert_eq!(registry.delay_minutes("comdirect"), 0);
+        registry.set_delay_minutes("yahoo", 0);
+        assert_eq!(registry.delay_minutes("yahoo"), 0);
+    }
+
+    #[test]
+    fn ticker_usage() {
+        use TickerUsage::*;
+        assert!(Both.serves(Valuation) && Both.serves(Charting));
+        assert!(Valuation.serves(Valuation) && !Valuation.serves(Charting));
+        assert!(Charting.serves(Both) && !Charting.serves(Valuation));
+        for usage in &[Valuation, Charting, Both] {
+            assert_eq!(TickerUsage::from_str(&usage.to_string()).unwrap(), *usage);
+        }
+        // ticker stored without usage default to both
+        let ticker: Ticker = serde_json::from_str(
+            r#"{"id":null,"asset":1,"name":"BAS.DE","currency":"EUR","source":"yahoo",
+            "priority":10,"factor":1.0,"source_url":null}"#,
+        )
+        .unwrap();
+        assert_eq!(ticker.usage, Both);
+    }
+}
@@ -0,0 +1,597 @@
+//! Caching decorator for quote handlers
+//!
+//! Valuation, alerts and charts often ask for the same latest quotes within a short time.
+//! `CachedQuoteHandler` wraps any `QuoteHandler` and keeps the results of the last quote
+//! lookups by asset id and of the latest quotes by ticker in LRU caches of bounded size.
+//! Quotes inserted, updated or deleted through the decorator invalidate the affected entries,
+//! changes by other processes are picked up after the configured time to live.
+
+use std::collections::{BTreeMap, HashMap};
+use std::hash::Hash;
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Utc};
+
+use crate::asset::{
+    Asset, AssetRepresentation, AssetSearchQuery, AssetSortKey, CurrencyExposure, Page,
+};
+use crate::asset_handler::AssetHandler;
+use crate::currency::Currency;
+use crate::instrument::InstrumentRecord;
+use crate::quota::QuotaMonth;
+use crate::quote::{Quote, QuoteQuery, Ticker, TickerUsage};
+use crate::quote_handler::{QuoteHandler, QuoteInsertion};
+use crate::DataError;
+
+/// Settings of a `CachedQuoteHandler`
+#[derive(Debug, Clone, Copy)]
+pub struct QuoteCacheConfig {
+    /// Maximum number of entries of each cache
+    pub capacity: usize,
+    /// Time after which a cached entry is discarded
+    pub ttl: Duration,
+    /// Lookup times are truncated to multiples of this resolution, i.e. lookups within the same
+    /// interval share a cache entry. Quotes of the current interval are therefore not seen
+    /// before the next interval starts.
+    pub time_resolution: chrono::Duration,
+}
+
+impl Default for QuoteCacheConfig {
+    fn default() -> Self {
+        QuoteCacheConfig {
+            capacity: 1000,
+            ttl: Duration::from_secs(60),
+            time_resolution: chrono::Duration::minutes(1),
+        }
+    }
+}
+
+/// Number of lookups answered from the cache and from the wrapped handler
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct QuoteCacheStats {
+    pub hits: u64,
+    pub misses: u64,
+}
+
+struct LruEntry<V> {
+    value: V,
+    created: Instant,
+    /// Tick of the last use, key of the entry in `Lru::order`
+    used: u64,
+}
+
+/// Least recently used cache with a maximum number of entries and a time to live
+struct Lru<K, V> {
+    capacity: usize,
+    ttl: Duration,
+    entries: HashMap<K, LruEntry<V>>,
+    order: BTreeMap<u64, K>,
+    tick: u64,
+}
+
+impl<K: Clone + Eq + Hash, V: Clone> Lru<K, V> {
+    fn new(capacity: usize, ttl: Duration) -> Self {
+        Lru {
+            capacity,
+            ttl,
+            entries: HashMap::new(),
+            order: BTreeMap::new(),
+            tick: 0,
+        }
+    }
+
+    fn get(&mut self, key: &K) -> Option<V> {
+        let entry = self.entries.get_mut(key)?;
+        if entry.created.elapsed() > self.ttl {
+            self.remove(key);
+            return None;
+        }
+        self.order.remove(&entry.used);
+        self.tick += 1;
+        entry.used = self.tick;
+        self.order.insert(self.tick, key.clone());
+        Some(entry.value.clone())
+    }
+
+    fn insert(&mut self, key: K, value: V) {
+        if self.capacity == 0 {
+            return;
+        }
+        self.remove(&key);
+        if self.entries.len() >= self.capacity {
+            if let Some((_, oldest)) = self.order.pop_first() {
+                self.entries.remove(&oldest);
+            }
+        }
+        self.tick += 1;
+        self.order.insert(self.tick, key.clone());
+        self.entries.insert(
+            key,
+            LruEntry {
+                value,
+                created: Instant::now(),
+                used: self.tick,
+            },
+        );
+    }
+
+    fn remove(&mut self, key: &K) {
+        if let Some(entry) = self.entries.remove(key) {
+            self.order.remove(&entry.used);
+        }
+    }
+
+    /// Remove all entries for which `keep` returns false
+    fn retain<F: Fn(&K, &V) -> bool>(&mut self, keep: F) {
+        let order = &mut self.order;
+        self.entries.retain(|key, entry| {
+            let retained = keep(key, &entry.value);
+            if !retained {
+                order.remove(&entry.used);
+            }
+            retained
+        });
+    }
+
+    fn clear(&mut self) {
+        self.entries.clear();
+        self.order.clear();
+    }
+
+    fn len(&self) -> usize {
+        self.entries.len()
+    }
+}
+
+/// Key of a last quote lookup, the time is truncated to the cache's time resolution
+type LastQuoteKey = (usize, TickerUsage, DateTime<Utc>);
+
+/// Decorator caching the last quote lookups by asset id and latest quotes by ticker of the
+/// wrapped handler. All other methods are passed through.
+pub struct CachedQuoteHandler<T: QuoteHandler> {
+    inner: T,
+    config: QuoteCacheConfig,
+    last_quotes: Lru<LastQuoteKey, (Quote, Currency)>,
+    /// Latest quote by ticker id, None if the ticker has no quotes
+    latest_quotes: Lru<usize, Option<Quote>>,
+    stats: QuoteCacheStats,
+}
+
+impl<T: QuoteHandler> CachedQuoteHandler<T> {
+    pub fn new(inner: T, config: QuoteCacheConfig) -> Self {
+        CachedQuoteHandler {
+            inner,
+            config,
+            last_quotes: Lru::new(config.capacity, config.ttl),
+            latest_quotes: Lru::new(config.capacity, config.ttl),
+            stats: QuoteCacheStats::default(),
+        }
+    }
+
+    /// Access the wrapped handler, e.g. for methods of other handler traits
+    pub fn inner(&self) -> &T {
+        &self.inner
+    }
+
+    pub fn into_inner(self) -> T {
+        self.inner
+    }
+
+    pub fn stats(&self) -> QuoteCacheStats {
+        self.stats
+    }
+
+    /// Number of cached last quote lookups and latest quotes
+    pub fn len(&self) -> usize {
+        self.last_quotes.len() + self.latest_quotes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Discard all cached entries, e.g. after the database has been changed by other means
+    pub fn clear(&mut self) {
+        self.last_quotes.clear();
+        self.latest_quotes.clear();
+    }
+
+    /// Truncate a time to a multiple of the time resolution
+    fn truncate(&self, time: DateTime<Utc>) -> DateTime<Utc> {
+        let resolution = self.config.time_resolution.num_seconds().max(1);
+        time - chrono::Duration::seconds(time.timestamp().rem_euclid(resolution))
+            - chrono::Duration::nanoseconds(time.timestamp_subsec_nanos() as i64)
+    }
+
+    /// Discard all entries quotes of the given ticker may contribute to. If the ticker can't
+    /// be found, all entries are discarded.
+    fn invalidate_ticker(&mut self, ticker_id: usize) {
+        self.latest_quotes.remove(&ticker_id);
+        match self.inner.get_ticker_by_id(ticker_id) {
+            Ok(ticker) => self.last_quotes.retain(|key, _| key.0 != ticker.asset),
+            Err(_) => self.last_quotes.clear(),
+        }
+    }
+
+    /// Discard all entries containing the quote with the given id
+    fn invalidate_quote(&mut self, quote_id: usize) {
+        self.last_quotes
+            .retain(|_, (quote, _)| quote.id != Some(quote_id));
+        self.latest_quotes
+            .retain(|_, quote| !matches!(quote, Some(quote) if quote.id == Some(quote_id)));
+    }
+
+    /// Discard all entries affected by inserting or updating the given quotes
+    fn invalidate_quotes(&mut self, quotes: &[Quote]) {
+        let mut ticker_ids: Vec<usize> = quotes.iter().map(|quote| quote.ticker).collect();
+        ticker_ids.sort_unstable();
+        ticker_ids.dedup();
+        for ticker_id in ticker_ids {
+            self.invalidate_ticker(ticker_id);
+        }
+        for quote in quotes {
+            if let Some(id) = quote.id {
+                self.invalidate_quote(id);
+            }
+        }
+    }
+}
+
+impl<T: QuoteHandler> AssetHandler for CachedQuoteHandler<T> {
+    fn insert_asset(&mut self, asset: &Asset) -> Result<usize, DataError> {
+        self.inner.insert_asset(asset)
+    }
+    fn get_asset_id(&mut self, asset: &Asset) -> Option<usize> {
+        self.inner.get_asset_id(asset)
+    }
+    fn get_asset_by_id(&mut self, id: usize) -> Result<Asset, DataError> {
+        self.inner.get_asset_by_id(id)
+    }
+    fn get_asset_by_isin(&mut self, isin: &str) -> Result<Asset, DataError> {
+        self.inner.get_asset_by_isin(isin)
+    }
+    fn get_asset_by_wkn(&mut self, wkn: &str) -> Result<Asset, DataError> {
+        self.inner.get_asset_by_wkn(wkn)
+    }
+    fn get_all_assets(&mut self) -> Result<Vec<Asset>, DataError> {
+        self.inner.get_all_assets()
+    }
+    fn get_all_assets_sorted(
+        &mut self,
+        key: AssetSortKey,
+        ascending: bool,
+    ) -> Result<Vec<Asset>, DataError> {
+        self.inner.get_all_assets_sorted(key, ascending)
+    }
+    fn get_assets_page(
+        &mut self,
+        sort: AssetSortKey,
+        ascending: bool,
+        page: Page,
+    ) -> Result<(Vec<Asset>, usize), DataError> {
+        self.inner.get_assets_page(sort, ascending, page)
+    }
+    fn search_assets(&mut self, query: &AssetSearchQuery) -> Result<Vec<Asset>, DataError> {
+        self.inner.search_assets(query)
+    }
+    fn update_asset(&mut self, asset: &Asset) -> Result<(), DataError> {
+        self.inner.update_asset(asset)
+    }
+    fn update_assets(
+        &mut self,
+        assets: &[Asset],
+        exposures: &[CurrencyExposure],
+    ) -> Result<(), DataError> {
+        self.inner.update_assets(assets, exposures)
+    }
+    fn delete_asset(&mut self, id: usize) -> Result<(), DataError> {
+        self.inner.delete_asset(id)
+    }
+    fn get_all_currencies(&mut self) -> Result<Vec<Currency>, DataError> {
+        self.inner.get_all_currencies()
+    }
+    fn set_instrument_record(&mut self, record: &InstrumentRecord) -> Result<(), DataError> {
+        self.inner.set_instrument_record(record)
+    }
+    fn get_instrument_record(
+        &mut self,
+        asset_id: usize,
+    ) -> Result<Option<InstrumentRecord>, DataError> {
+        self.inner.get_instrument_record(asset_id)
+    }
+    fn get_instrument_records(&mut self, kind: &str) -> Result<Vec<InstrumentRecord>, DataError> {
+        self.inner.get_instrument_records(kind)
+    }
+    fn delete_instrument_record(&mut self, asset_id: usize) -> Result<(), DataError> {
+        self.inner.delete_instrument_record(asset_id)
+    }
+    fn set_currency_exposure(&mut self, exposure: &CurrencyExposure) -> Result<(), DataError> {
+        self.inner.set_currency_exposure(exposure)
+    }
+    fn get_currency_exposure(
+        &mut self,
+        asset_id: usize,
+    ) -> Result<Option<CurrencyExposure>, DataError> {
+        self.inner.get_currency_exposure(asset_id)
+    }
+    fn delete_currency_exposure(&mut self, asset_id: usize) -> Result<(), DataError> {
+        self.inner.delete_currency_exposure(asset_id)
+    }
+    fn set_asset_representation(
+        &mut self,
+        representation: &AssetRepresentation,
+    ) -> Result<(), DataError> {
+        self.inner.set_asset_representation(representation)
+    }
+    fn get_asset_representation(
+        &mut self,
+        asset_id: usize,
+    ) -> Result<Option<AssetRepresentation>, DataError> {
+        self.inner.get_asset_representation(asset_id)
+    }
+    fn get_representations_of(
+        &mut self,
+        underlying_id: usize,
+    ) -> Result<Vec<AssetRepresentation>, DataError> {
+        self.inner.get_representations_of(underlying_id)
+    }
+    fn delete_asset_representation(&mut self, asset_id: usize) -> Result<(), DataError> {
+        self.inner.delete_asset_representation(asset_id)
+    }
+}
+
+impl<T: QuoteHandler> QuoteHandler for CachedQuoteHandler<T> {
+    fn insert_ticker(&mut self, ticker: &Ticker) -> Result<usize, DataError> {
+        self.inner.insert_ticker(ticker)
+    }
+    fn get_ticker_id(&mut self, ticker: &str) -> Option<usize> {
+        self.inner.get_ticker_id(ticker)
+    }
+    fn get_ticker_id_for_source(&mut self, name: &str, source: &str) -> Option<usize> {
+        self.inner.get_ticker_id_for_source(name, source)
+    }
+    fn get_ticker_by_id(&mut self, id: usize) -> Result<Ticker, DataError> {
+        self.inner.get_ticker_by_id(id)
+    }
+    fn get_all_ticker(&mut self) -> Result<Vec<Ticker>, DataError> {
+        self.inner.get_all_ticker()
+    }
+    fn get_all_ticker_for_source(&mut self, source: &str) -> Result<Vec<Ticker>, DataError> {
+        self.inner.get_all_ticker_for_source(source)
+    }
+    fn get_all_ticker_for_asset(&mut self, asset_id: usize) -> Result<Vec<Ticker>, DataError> {
+        self.inner.get_all_ticker_for_asset(asset_id)
+    }
+    fn get_tickers_by_source_url_pattern(
+        &mut self,
+        url_pattern: &str,
+    ) -> Result<Vec<Ticker>, DataError> {
+        self.inner.get_tickers_by_source_url_pattern(url_pattern)
+    }
+
+    /// Changes of ticker, e.g. of the asset or priority, may affect any entry, therefore the
+    /// whole cache is discarded
+    fn update_ticker(&mut self, ticker: &Ticker) -> Result<(), DataError> {
+        let result = self.inner.update_ticker(ticker);
+        self.clear();
+        result
+    }
+    fn delete_ticker(&mut self, id: usize) -> Result<(), DataError> {
+        let result = self.inner.delete_ticker(id);
+        self.clear();
+        result
+    }
+
+    fn insert_quote(&mut self, quote: &Quote) -> Result<usize, DataError> {
+        let result = self.inner.insert_quote(quote);
+        self.invalidate_ticker(quote.ticker);
+        result
+    }
+    fn insert_quote_if_new(&mut self, quote: &Quote) -> Result<QuoteInsertion, DataError> {
+        let result = self.inner.insert_quote_if_new(quote);
+        self.invalidate_ticker(quote.ticker);
+        result
+    }
+
+    /// Last quote lookups are not cached by asset name
+    fn get_last_quote_before_for_usage(
+        &mut self,
+        asset_name: &str,
+        time: DateTime<Utc>,
+        usage: TickerUsage,
+    ) -> Result<(Quote, Currency), DataError> {
+        self.inner
+            .get_last_quote_before_for_usage(asset_name, time, usage)
+    }
+
+    /// Look up the last quote on or before `time` truncated to the cache's time resolution
+    fn get_last_quote_before_by_id_for_usage(
+        &mut self,
+        asset_id: usize,
+        time: DateTime<Utc>,
+        usage: TickerUsage,
+    ) -> Result<(Quote, Currency), DataError> {
+        let key = (asset_id, usage, self.truncate(time));
+        if let Some(last_quote) = self.last_quotes.get(&key) {
+            self.stats.hits += 1;
+            return Ok(last_quote);
+        }
+        self.stats.misses += 1;
+        let last_quote = self
+            .inner
+            .get_last_quote_before_by_id_for_usage(asset_id, key.2, usage)?;
+        self.last_quotes.insert(key, last_quote.clone());
+        Ok(last_quote)
+    }
+
+    fn get_all_quotes_for_ticker(&mut self, ticker_id: usize) -> Result<Vec<Quote>, DataError> {
+        self.inner.get_all_quotes_for_ticker(ticker_id)
+    }
+    fn get_all_quotes_for_ticker_and_source(
+        &mut self,
+        asset_id: usize,
+        source: &str,
+    ) -> Result<Vec<Quote>, DataError> {
+        self.inner
+            .get_all_quotes_for_ticker_and_source(asset_id, source)
+    }
+    fn get_quotes_above_quality(
+        &mut self,
+        ticker_id: usize,
+        min_quality: f64,
+    ) -> Result<Vec<Quote>, DataError> {
+        self.inner.get_quotes_above_quality(ticker_id, min_quality)
+    }
+    fn get_quotes_in_range(
+        &mut self,
+        ticker_id: usize,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+    ) -> Result<Vec<Quote>, DataError> {
+        self.inner.get_quotes_in_range(ticker_id, start, end)
+    }
+    fn get_quotes_page(
+        &mut self,
+        query: &QuoteQuery,
+        after_id: Option<usize>,
+        limit: usize,
+    ) -> Result<Vec<Quote>, DataError> {
+        self.inner.get_quotes_page(query, after_id, limit)
+    }
+
+    /// Ticker not in the cache are looked up by a single call of the wrapped handler
+    fn get_latest_quotes_for_tickers(
+        &mut self,
+        ticker_ids: &[usize],
+    ) -> Result<Vec<(usize, Quote)>, DataError> {
+        let mut latest = HashMap::new();
+        let mut missing = Vec::new();
+        for ticker_id in ticker_ids {
+            match self.latest_quotes.get(ticker_id) {
+                Some(quote) => {
+                    self.stats.hits += 1;
+                    latest.insert(*ticker_id, quote);
+                }
+                None => {
+                    self.stats.misses += 1;
+                    missing.push(*ticker_id);
+                }
+            }
+        }
+        missing.sort_unstable();
+        missing.dedup();
+        if !missing.is_empty() {
+            let mut found: HashMap<usize, Quote> = self
+                .inner
+                .get_latest_quotes_for_tickers(&missing)?
+                .into_iter()
+                .collect();
+            for ticker_id in missing {
+                let quote = found.remove(&ticker_id);
+                self.latest_quotes.insert(ticker_id, quote.clone());
+                latest.insert(ticker_id, quote);
+            }
+        }
+        Ok(ticker_ids
+            .iter()
+            .filter_map(|id| latest[id].clone().map(|quote| (*id, quote)))
+            .collect())
+    }
+
+    fn update_quote(&mut self, quote: &Quote) -> Result<(), DataError> {
+        let result = self.inner.update_quote(quote);
+        self.invalidate_quotes(std::slice::from_ref(quote));
+        result
+    }
+    fn delete_quote(&mut self, id: usize) -> Result<(), DataError> {
+        let result = self.inner.delete_quote(id);
+        self.invalidate_quote(id);
+        result
+    }
+    fn delete_quotes_for_ticker(&mut self, ticker_id: usize) -> Result<usize, DataError> {
+        let result = self.inner.delete_quotes_for_ticker(ticker_id);
+        self.invalidate_ticker(ticker_id);
+        result
+    }
+    fn delete_quotes_before(
+        &mut self,
+        ticker_id: usize,
+        time: DateTime<Utc>,
+    ) -> Result<usize, DataError> {
+        let result = self.inner.delete_quotes_before(ticker_id, time);
+        self.invalidate_ticker(ticker_id);
+        result
+    }
+    fn store_quotes(&mut self, quotes: &[Quote]) -> Result<Vec<usize>, DataError> {
+        let result = self.inner.store_quotes(quotes);
+        self.invalidate_quotes(quotes);
+        result
+    }
+    fn insert_quotes(&mut self, quotes: &[Quote]) -> Result<Vec<usize>, DataError> {
+        let result = self.inner.insert_quotes(quotes);
+        self.invalidate_quotes(quotes);
+        result
+    }
+    fn get_stale_tickers(&mut self, before: DateTime<Utc>) -> Result<Vec<Ticker>, DataError> {
+        self.inner.get_stale_tickers(before)
+    }
+    fn get_rounding_digits(&mut self, currency: Currency) -> i32 {
+        self.inner.get_rounding_digits(currency)
+    }
+    fn set_rounding_digits(&mut self, currency: Currency, digits: i32) -> Result<(), DataError> {
+        self.inner.set_rounding_digits(currency, digits)
+    }
+    fn add_quota_usage(
+        &mut self,
+        source: &str,
+        month: QuotaMonth,
+        calls: u64,
+    ) -> Result<(), DataError> {
+        self.inner.add_quota_usage(source, month, calls)
+    }
+    fn get_quota_usage(&mut self, source: &str, month: QuotaMonth) -> Result<u64, DataError> {
+        self.inner.get_quota_usage(source, month)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn evict_least_recently_used() {
+        let mut lru = Lru::new(2, Duration::from_secs(60));
+        lru.insert(1, "one");
+        lru.insert(2, "two");
+        assert_eq!(lru.get(&1), Some("one"));
+        // 2 is the least recently used entry now
+        lru.insert(3, "three");
+        assert_eq!(lru.len(), 2);
+        assert_eq!(lru.get(&2), None);
+        assert_eq!(lru.get(&1), Some("one"));
+        assert_eq!(lru.get(&3), Some("three"));
+        // replacing an entry does not evict another one
+        lru.insert(3, "drei");
+        assert_eq!(lru.len(), 2);
+        assert_eq!(lru.get(&3), Some("drei"));
+
+        lru.retain(|key, _| *key != 1);
+        assert_eq!(lru.get(&1), None);
+        lru.insert(4, "four");
+        lru.insert(5, "five");
+        assert_eq!(lru.len(), 2);
+        assert_eq!(lru.get(&4), Some("four"));
+    }
+
+    #[test]
+    fn expire_after_ttl() {
+        let mut lru = Lru::new(10, Duration::from_millis(1));
+        lru.insert(1, "one");
+        std::thread::sleep(Duration::from_millis(10));
+        assert_eq!(lru.get(&1), None);
+        assert_eq!(lru.len(), 0);
+        let mut disabled = Lru::new(0, Duration::from_secs(60));
+        disabled.insert(1, "one");
+        assert_eq!(disabled.get(&1), None);
+    }
+}
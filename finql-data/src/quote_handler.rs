@@ -1,19 +1,24 @@
 ///! Data handler trait for market quotes
 
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, NaiveDate, Utc};
 
 use super::AssetHandler;
-use super::DataError;
-use crate::currency::Currency;
-use crate::quote::{Quote, Ticker};
+use super::{DataError, DataItem};
+use crate::asset::Asset;
+use crate::currency::{Currency, CurrencyConverter};
+use crate::quota::QuotaMonth;
+use crate::quote::{Quote, QuoteQuery, RawQuote, Ticker, TickerUsage};
 
 /// Handler for globally available market quotes data
 pub trait QuoteHandler: AssetHandler {
     // insert, get, update and delete for market data sources
     fn insert_ticker(&mut self, ticker: &Ticker) -> Result<usize, DataError>;
     fn get_ticker_id(&mut self, ticker: &str) -> Option<usize>;
+    /// Get the id of the ticker with the given name at the given source, if there is any
+    fn get_ticker_id_for_source(&mut self, name: &str, source: &str) -> Option<usize>;
+    /// Insert the ticker unless there is already a ticker with the same name and source
     fn insert_if_new_ticker(&mut self, ticker: &Ticker) -> Result<usize, DataError> {
-        match self.get_ticker_id(&ticker.name) {
+        match self.get_ticker_id_for_source(&ticker.name, &ticker.source) {
             Some(id) => Ok(id),
             None => self.insert_ticker(ticker),
         }
@@ -25,38 +30,626 @@ pub trait QuoteHandler: AssetHandler {
         source: &str,
     ) -> Result<Vec<Ticker>, DataError>;
 
+    /// Get the ticker of the given source whose name is the given symbol, if there is any
+    fn get_ticker_by_source_symbol(
+        &mut self,
+        source: &str,
+        symbol: &str,
+    ) -> Result<Option<Ticker>, DataError> {
+        Ok(self
+            .get_all_ticker_for_source(source)?
+            .into_iter()
+            .find(|ticker| ticker.name == symbol))
+    }
+
     /// Get all ticker that belong to a given asset specified by its asset ID
     fn get_all_ticker_for_asset(
         &mut self,
         asset_id: usize,
     ) -> Result<Vec<Ticker>, DataError>;
 
+    /// Get all ticker whose source URL matches the given SQL `LIKE` pattern
+    fn get_tickers_by_source_url_pattern(
+        &mut self,
+        url_pattern: &str,
+    ) -> Result<Vec<Ticker>, DataError>;
+
     fn update_ticker(&mut self, ticker: &Ticker) -> Result<(), DataError>;
     fn delete_ticker(&mut self, id: usize) -> Result<(), DataError>;
 
     /// Insert, get, update and delete for market data sources
+    ///
+    /// Inserting a quote fails if a quote of the same ticker and time is stored already, see
+    /// `insert_quote_if_new` to skip such quotes.
     fn insert_quote(&mut self, quote: &Quote) -> Result<usize, DataError>;
+    /// Insert a quote unless a quote of the same ticker and time is stored already, e.g. when
+    /// the same quotes are fetched again. Existing quotes are left unchanged. This relies on
+    /// the unique key on ticker and time. Databases created by older versions with several
+    /// quotes of the same ticker and time get this key once the duplicates have been removed,
+    /// see `SchemaHandler::collapse_duplicate_quotes`.
+    fn insert_quote_if_new(&mut self, quote: &Quote) -> Result<QuoteInsertion, DataError>;
 
-    /// Get the last quote in database for a specific asset name on or before the given time
+    /// Get the last quote in database for a specific asset name on or before the given time,
+    /// considering only ticker usable for valuation
     fn get_last_quote_before(
         &mut self,
         asset_name: &str,
         time: DateTime<Utc>,
+    ) -> Result<(Quote, Currency), DataError> {
+        self.get_last_quote_before_for_usage(asset_name, time, TickerUsage::Valuation)
+    }
+
+    /// Get the last quote in database for a specific asset name on or before the given time,
+    /// considering only ticker serving the given usage. If several ticker have quotes at that
+    /// time, the quote is selected by `Quote::select_preferred` in order of ticker priority.
+    fn get_last_quote_before_for_usage(
+        &mut self,
+        asset_name: &str,
+        time: DateTime<Utc>,
+        usage: TickerUsage,
     ) -> Result<(Quote, Currency), DataError>;
 
-    /// Get the last quote in database for a specific asset id on or before the given time
+    /// Get the last quote in database for a specific asset id on or before the given time,
+    /// considering only ticker usable for valuation
     fn get_last_quote_before_by_id(
         &mut self,
         asset_id: usize,
         time: DateTime<Utc>,
+    ) -> Result<(Quote, Currency), DataError> {
+        self.get_last_quote_before_by_id_for_usage(asset_id, time, TickerUsage::Valuation)
+    }
+
+    /// Get the last quote in database for a specific asset id on or before the given time,
+    /// considering only ticker serving the given usage. If several ticker have quotes at that
+    /// time, the quote is selected by `Quote::select_preferred` in order of ticker priority.
+    fn get_last_quote_before_by_id_for_usage(
+        &mut self,
+        asset_id: usize,
+        time: DateTime<Utc>,
+        usage: TickerUsage,
     ) -> Result<(Quote, Currency), DataError>;
 
+    /// Get the last quote of an asset on or before the given time, considering only ticker
+    /// usable for valuation. If the asset has a reference currency, the quote is converted into
+    /// it. Therefore, the currency of the result does not depend on which ticker provides the
+    /// latest quote, even if the ticker of an asset are quoted in different currencies.
+    fn get_best_quote_before(
+        &mut self,
+        asset_id: usize,
+        time: DateTime<Utc>,
+        currency_converter: &mut dyn CurrencyConverter,
+    ) -> Result<(Quote, Currency), DataError> {
+        let (quote, currency) = self.get_last_quote_before_by_id(asset_id, time)?;
+        match self.get_asset_by_id(asset_id)?.reference_currency {
+            Some(reference) => Ok((
+                quote.convert_to(currency, reference, currency_converter)?,
+                reference,
+            )),
+            None => Ok((quote, currency)),
+        }
+    }
+
+    /// Derive the last quote of an asset held as certificate or depositary receipt from the last
+    /// quote of its underlying on or before the given time, i.e. the underlying's price times the
+    /// ratio of the asset to its underlying. The price is converted into the reference currency
+    /// of the asset or, if it has none, into the currency of its first valuation ticker. This
+    /// allows to value such assets if no quotes of their own are available. Underlyings
+    /// without quotes are in turn valued by their own underlying, if any.
+    fn get_best_quote_via_underlying_before(
+        &mut self,
+        asset_id: usize,
+        time: DateTime<Utc>,
+        currency_converter: &mut dyn CurrencyConverter,
+    ) -> Result<(Quote, Currency), DataError> {
+        let representation = self.get_asset_representation(asset_id)?.ok_or_else(|| {
+            DataError::NotFound(format!(
+                "asset {} does not represent an underlying asset",
+                asset_id
+            ))
+        })?;
+        let underlying_id = representation.underlying_id;
+        let (quote, currency) =
+            match self.get_best_quote_before(underlying_id, time, currency_converter) {
+                Ok(quote) => quote,
+                Err(err) => {
+                    if self.get_asset_representation(underlying_id)?.is_none() {
+                        return Err(err);
+                    }
+                    self.get_best_quote_via_underlying_before(
+                        underlying_id,
+                        time,
+                        currency_converter,
+                    )?
+                }
+            };
+        let quote = Quote {
+            price: quote.price * representation.ratio,
+            ..quote
+        };
+        let target = match self.get_asset_by_id(asset_id)?.reference_currency {
+            Some(reference) => Some(reference),
+            None => self
+                .get_all_ticker_for_asset_and_usage(asset_id, TickerUsage::Valuation)?
+                .first()
+                .map(|ticker| ticker.currency),
+        };
+        match target {
+            Some(target) => Ok((
+                quote.convert_to(currency, target, currency_converter)?,
+                target,
+            )),
+            None => Ok((quote, currency)),
+        }
+    }
+
+    /// Consistency check for quote currencies: get all assets whose valuation ticker are quoted
+    /// in different currencies, but which have no reference currency to convert quotes into
+    fn get_assets_with_mixed_currencies(&mut self) -> Result<Vec<Asset>, DataError> {
+        let mut mixed = Vec::new();
+        for asset in self.get_all_assets()? {
+            if asset.reference_currency.is_some() {
+                continue;
+            }
+            let ticker =
+                self.get_all_ticker_for_asset_and_usage(asset.get_id()?, TickerUsage::Valuation)?;
+            if ticker.iter().any(|t| t.currency != ticker[0].currency) {
+                mixed.push(asset);
+            }
+        }
+        Ok(mixed)
+    }
+
+    /// Get all ticker of an asset serving the given usage, ordered by priority
+    fn get_all_ticker_for_asset_and_usage(
+        &mut self,
+        asset_id: usize,
+        usage: TickerUsage,
+    ) -> Result<Vec<Ticker>, DataError> {
+        let mut ticker = self.get_all_ticker_for_asset(asset_id)?;
+        ticker.retain(|t| t.usage.serves(usage));
+        ticker.sort_by_key(|t| t.priority);
+        Ok(ticker)
+    }
+
     fn get_all_quotes_for_ticker(&mut self, ticker_id: usize) -> Result<Vec<Quote>, DataError>;
+
+    /// Get all quotes of an asset from ticker of the given source (i.e. `Ticker::source`, not
+    /// `Quote::source`), ordered by time. The ticker each quote came from is given by
+    /// `Quote::ticker`. This allows comparing the quotes of different market data sources.
+    fn get_all_quotes_for_ticker_and_source(
+        &mut self,
+        asset_id: usize,
+        source: &str,
+    ) -> Result<Vec<Quote>, DataError>;
+
+    /// Get all quotes of a ticker with a quality score of at least `min_quality`, ordered by
+    /// time. Quotes without quality score are never returned.
+    fn get_quotes_above_quality(
+        &mut self,
+        ticker_id: usize,
+        min_quality: f64,
+    ) -> Result<Vec<Quote>, DataError>;
+
+    /// Get all quotes of a ticker between `start` and `end` (both inclusive), ordered by time
+    fn get_quotes_in_range(
+        &mut self,
+        ticker_id: usize,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+    ) -> Result<Vec<Quote>, DataError>;
+
+    /// Get up to `limit` quotes matching the query with an id greater than `after_id`, ordered
+    /// by id. Passing the id of the last quote of a page as `after_id` yields the next page,
+    /// which allows reading any number of quotes with constant memory.
+    fn get_quotes_page(
+        &mut self,
+        query: &QuoteQuery,
+        after_id: Option<usize>,
+        limit: usize,
+    ) -> Result<Vec<Quote>, DataError>;
+
+    /// Get the most recent quote for each of the given ticker ids in a single query.
+    /// Ticker without any quote are not part of the result, all others are returned
+    /// in the same order as given by `ticker_ids`.
+    fn get_latest_quotes_for_tickers(
+        &mut self,
+        ticker_ids: &[usize],
+    ) -> Result<Vec<(usize, Quote)>, DataError>;
+
     fn update_quote(&mut self, quote: &Quote) -> Result<(), DataError>;
     fn delete_quote(&mut self, id: usize) -> Result<(), DataError>;
+    /// Delete all quotes of a ticker, returns the number of deleted quotes
+    fn delete_quotes_for_ticker(&mut self, ticker_id: usize) -> Result<usize, DataError>;
+    /// Delete all quotes of a ticker before (but not at) the given time, e.g. to prune old
+    /// intraday quotes. Returns the number of deleted quotes.
+    fn delete_quotes_before(
+        &mut self,
+        ticker_id: usize,
+        time: DateTime<Utc>,
+    ) -> Result<usize, DataError>;
+
+    /// Store several quotes within a single database transaction, i.e. either all or none of
+    /// them are stored. Quotes without id are inserted, quotes with id are updated. Returns
+    /// the ids of the quotes in the given order.
+    fn store_quotes(&mut self, quotes: &[Quote]) -> Result<Vec<usize>, DataError>;
+
+    /// Insert many new quotes at once, e.g. for bulk imports of historic prices. This is much
+    /// faster than calling `insert_quote` for each quote. All quotes are inserted within a
+    /// single database transaction, i.e. if any quote fails, none is stored. Ids of the given
+    /// quotes are ignored, the ids of the new quotes are returned in the given order.
+    fn insert_quotes(&mut self, quotes: &[Quote]) -> Result<Vec<usize>, DataError>;
+
+    /// Get all ticker without any quote or whose latest quote is older than `before`.
+    /// Uses the cached `Ticker::last_quote_time` and does not need to scan the quotes.
+    fn get_stale_tickers(&mut self, before: DateTime<Utc>) -> Result<Vec<Ticker>, DataError>;
 
     // Get and set cash rounding conventions by currency
     // This method never throws, if currency could not be found in table, return 2 by default instead
     fn get_rounding_digits(&mut self, currency: Currency) -> i32;
     fn set_rounding_digits(&mut self, currency: Currency, digits: i32) -> Result<(), DataError>;
+
+    /// Add `calls` provider calls to the quota usage of a source in the given month. The
+    /// counter is incremented by the database, so concurrent updates don't lose increments.
+    fn add_quota_usage(
+        &mut self,
+        source: &str,
+        month: QuotaMonth,
+        calls: u64,
+    ) -> Result<(), DataError>;
+    /// Number of provider calls of a source recorded in the given month, zero if none
+    fn get_quota_usage(&mut self, source: &str, month: QuotaMonth) -> Result<u64, DataError>;
+}
+
+/// Read-only access to market quotes data
+///
+/// In contrast to `QuoteHandler`, all methods take `&self` and therefore allow
+/// multiple concurrent readers, e.g. behind an `RwLock`, without serializing
+/// reads behind a single mutex, see `SharedHandler` for handlers with several
+/// reader connections. Asset handlers implementing `QuoteReader` and
+/// `QuoteWriter` implement `QuoteHandler` by the blanket implementation below.
+pub trait QuoteReader {
+    fn get_ticker_id(&self, ticker: &str) -> Option<usize>;
+    fn get_ticker_id_for_source(&self, name: &str, source: &str) -> Option<usize>;
+    fn get_ticker_by_id(&self, id: usize) -> Result<Ticker, DataError>;
+    fn get_all_ticker(&self) -> Result<Vec<Ticker>, DataError>;
+    fn get_all_ticker_for_source(&self, source: &str) -> Result<Vec<Ticker>, DataError>;
+
+    /// Get all ticker that belong to a given asset specified by its asset ID
+    fn get_all_ticker_for_asset(&self, asset_id: usize) -> Result<Vec<Ticker>, DataError>;
+
+    /// Get all ticker whose source URL matches the given SQL `LIKE` pattern
+    fn get_tickers_by_source_url_pattern(
+        &self,
+        url_pattern: &str,
+    ) -> Result<Vec<Ticker>, DataError>;
+
+    /// Get the last quote in database for a specific asset name on or before the given time,
+    /// considering only ticker serving the given usage
+    fn get_last_quote_before_for_usage(
+        &self,
+        asset_name: &str,
+        time: DateTime<Utc>,
+        usage: TickerUsage,
+    ) -> Result<(Quote, Currency), DataError>;
+
+    /// Get the last quote in database for a specific asset name on or before the given time,
+    /// considering only ticker usable for valuation
+    fn get_last_quote_before(
+        &self,
+        asset_name: &str,
+        time: DateTime<Utc>,
+    ) -> Result<(Quote, Currency), DataError> {
+        self.get_last_quote_before_for_usage(asset_name, time, TickerUsage::Valuation)
+    }
+
+    /// Get the last quote in database for a specific asset id on or before the given time,
+    /// considering only ticker serving the given usage
+    fn get_last_quote_before_by_id_for_usage(
+        &self,
+        asset_id: usize,
+        time: DateTime<Utc>,
+        usage: TickerUsage,
+    ) -> Result<(Quote, Currency), DataError>;
+
+    /// Get the last quote in database for a specific asset id on or before the given time,
+    /// considering only ticker usable for valuation
+    fn get_last_quote_before_by_id(
+        &self,
+        asset_id: usize,
+        time: DateTime<Utc>,
+    ) -> Result<(Quote, Currency), DataError> {
+        self.get_last_quote_before_by_id_for_usage(asset_id, time, TickerUsage::Valuation)
+    }
+
+    fn get_all_quotes_for_ticker(&self, ticker_id: usize) -> Result<Vec<Quote>, DataError>;
+
+    /// Get the quotes of all ticker of an asset serving the given usage with dates (in UTC)
+    /// between `start` and `end` (both inclusive), in no particular order. Quote times are not
+    /// parsed, which allows bulk loads to do the parsing in parallel. Backends should
+    /// override the default implementation, which decodes all quotes of the asset.
+    fn get_raw_quotes_for_asset(
+        &self,
+        asset_id: usize,
+        usage: TickerUsage,
+        start: NaiveDate,
+        end: NaiveDate,
+    ) -> Result<Vec<RawQuote>, DataError> {
+        let mut raw_quotes = Vec::new();
+        for ticker in self.get_all_ticker_for_asset(asset_id)? {
+            if !ticker.usage.serves(usage) {
+                continue;
+            }
+            for quote in self.get_all_quotes_for_ticker(ticker.get_id()?)? {
+                let date = quote.time.naive_utc().date();
+                if date >= start && date <= end {
+                    raw_quotes.push(RawQuote {
+                        time: quote.time.to_rfc3339(),
+                        price: quote.price,
+                        priority: ticker.priority,
+                    });
+                }
+            }
+        }
+        Ok(raw_quotes)
+    }
+
+    /// Get all quotes of an asset from ticker of the given source, see `QuoteHandler`
+    fn get_all_quotes_for_ticker_and_source(
+        &self,
+        asset_id: usize,
+        source: &str,
+    ) -> Result<Vec<Quote>, DataError>;
+
+    /// Get all quotes of a ticker with a quality score of at least `min_quality`, see
+    /// `QuoteHandler`
+    fn get_quotes_above_quality(
+        &self,
+        ticker_id: usize,
+        min_quality: f64,
+    ) -> Result<Vec<Quote>, DataError>;
+
+    /// Get all quotes of a ticker between `start` and `end`, see `QuoteHandler`
+    fn get_quotes_in_range(
+        &self,
+        ticker_id: usize,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+    ) -> Result<Vec<Quote>, DataError>;
+
+    /// Get up to `limit` quotes matching the query with an id greater than `after_id`, see
+    /// `QuoteHandler`
+    fn get_quotes_page(
+        &self,
+        query: &QuoteQuery,
+        after_id: Option<usize>,
+        limit: usize,
+    ) -> Result<Vec<Quote>, DataError>;
+
+    /// Get the most recent quote for each of the given ticker ids, see `QuoteHandler`
+    fn get_latest_quotes_for_tickers(
+        &self,
+        ticker_ids: &[usize],
+    ) -> Result<Vec<(usize, Quote)>, DataError>;
+
+    /// Get all ticker without any quote or whose latest quote is older than `before`
+    fn get_stale_tickers(&self, before: DateTime<Utc>) -> Result<Vec<Ticker>, DataError>;
+
+    /// Get cash rounding convention for currency, 2 digits by default
+    fn get_rounding_digits(&self, currency: Currency) -> i32;
+
+    /// Number of provider calls of a source recorded in the given month, zero if none
+    fn get_quota_usage(&self, source: &str, month: QuotaMonth) -> Result<u64, DataError>;
+}
+
+/// Write access to market quotes data, the counterpart of `QuoteReader`
+///
+/// See `QuoteHandler` for the documentation of the methods.
+pub trait QuoteWriter {
+    fn insert_ticker(&mut self, ticker: &Ticker) -> Result<usize, DataError>;
+    fn update_ticker(&mut self, ticker: &Ticker) -> Result<(), DataError>;
+    fn delete_ticker(&mut self, id: usize) -> Result<(), DataError>;
+
+    fn insert_quote(&mut self, quote: &Quote) -> Result<usize, DataError>;
+    fn insert_quote_if_new(&mut self, quote: &Quote) -> Result<QuoteInsertion, DataError>;
+    fn update_quote(&mut self, quote: &Quote) -> Result<(), DataError>;
+    fn delete_quote(&mut self, id: usize) -> Result<(), DataError>;
+    fn delete_quotes_for_ticker(&mut self, ticker_id: usize) -> Result<usize, DataError>;
+    fn delete_quotes_before(
+        &mut self,
+        ticker_id: usize,
+        time: DateTime<Utc>,
+    ) -> Result<usize, DataError>;
+    fn store_quotes(&mut self, quotes: &[Quote]) -> Result<Vec<usize>, DataError>;
+    fn insert_quotes(&mut self, quotes: &[Quote]) -> Result<Vec<usize>, DataError>;
+
+    fn set_rounding_digits(&mut self, currency: Currency, digits: i32) -> Result<(), DataError>;
+    fn add_quota_usage(
+        &mut self,
+        source: &str,
+        month: QuotaMonth,
+        calls: u64,
+    ) -> Result<(), DataError>;
+}
+
+impl<T: AssetHandler + QuoteReader + QuoteWriter> QuoteHandler for T {
+    fn insert_ticker(&mut self, ticker: &Ticker) -> Result<usize, DataError> {
+        QuoteWriter::insert_ticker(self, ticker)
+    }
+    fn get_ticker_id(&mut self, ticker: &str) -> Option<usize> {
+        QuoteReader::get_ticker_id(self, ticker)
+    }
+    fn get_ticker_id_for_source(&mut self, name: &str, source: &str) -> Option<usize> {
+        QuoteReader::get_ticker_id_for_source(self, name, source)
+    }
+    fn get_ticker_by_id(&mut self, id: usize) -> Result<Ticker, DataError> {
+        QuoteReader::get_ticker_by_id(self, id)
+    }
+    fn get_all_ticker(&mut self) -> Result<Vec<Ticker>, DataError> {
+        QuoteReader::get_all_ticker(self)
+    }
+    fn get_all_ticker_for_source(&mut self, source: &str) -> Result<Vec<Ticker>, DataError> {
+        QuoteReader::get_all_ticker_for_source(self, source)
+    }
+    fn get_all_ticker_for_asset(&mut self, asset_id: usize) -> Result<Vec<Ticker>, DataError> {
+        QuoteReader::get_all_ticker_for_asset(self, asset_id)
+    }
+    fn get_tickers_by_source_url_pattern(
+        &mut self,
+        url_pattern: &str,
+    ) -> Result<Vec<Ticker>, DataError> {
+        QuoteReader::get_tickers_by_source_url_pattern(self, url_pattern)
+    }
+    fn update_ticker(&mut self, ticker: &Ticker) -> Result<(), DataError> {
+        QuoteWriter::update_ticker(self, ticker)
+    }
+    fn delete_ticker(&mut self, id: usize) -> Result<(), DataError> {
+        QuoteWriter::delete_ticker(self, id)
+    }
+
+    fn insert_quote(&mut self, quote: &Quote) -> Result<usize, DataError> {
+        QuoteWriter::insert_quote(self, quote)
+    }
+    fn insert_quote_if_new(&mut self, quote: &Quote) -> Result<QuoteInsertion, DataError> {
+        QuoteWriter::insert_quote_if_new(self, quote)
+    }
+    fn get_last_quote_before_for_usage(
+        &mut self,
+        asset_name: &str,
+        time: DateTime<Utc>,
+        usage: TickerUsage,
+    ) -> Result<(Quote, Currency), DataError> {
+        QuoteReader::get_last_quote_before_for_usage(self, asset_name, time, usage)
+    }
+    fn get_last_quote_before_by_id_for_usage(
+        &mut self,
+        asset_id: usize,
+        time: DateTime<Utc>,
+        usage: TickerUsage,
+    ) -> Result<(Quote, Currency), DataError> {
+        QuoteReader::get_last_quote_before_by_id_for_usage(self, asset_id, time, usage)
+    }
+    fn get_all_quotes_for_ticker(&mut self, ticker_id: usize) -> Result<Vec<Quote>, DataError> {
+        QuoteReader::get_all_quotes_for_ticker(self, ticker_id)
+    }
+    fn get_all_quotes_for_ticker_and_source(
+        &mut self,
+        asset_id: usize,
+        source: &str,
+    ) -> Result<Vec<Quote>, DataError> {
+        QuoteReader::get_all_quotes_for_ticker_and_source(self, asset_id, source)
+    }
+    fn get_quotes_above_quality(
+        &mut self,
+        ticker_id: usize,
+        min_quality: f64,
+    ) -> Result<Vec<Quote>, DataError> {
+        QuoteReader::get_quotes_above_quality(self, ticker_id, min_quality)
+    }
+    fn get_quotes_in_range(
+        &mut self,
+        ticker_id: usize,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+    ) -> Result<Vec<Quote>, DataError> {
+        QuoteReader::get_quotes_in_range(self, ticker_id, start, end)
+    }
+    fn get_quotes_page(
+        &mut self,
+        query: &QuoteQuery,
+        after_id: Option<usize>,
+        limit: usize,
+    ) -> Result<Vec<Quote>, DataError> {
+        QuoteReader::get_quotes_page(self, query, after_id, limit)
+    }
+    fn get_latest_quotes_for_tickers(
+        &mut self,
+        ticker_ids: &[usize],
+    ) -> Result<Vec<(usize, Quote)>, DataError> {
+        QuoteReader::get_latest_quotes_for_tickers(self, ticker_ids)
+    }
+    fn update_quote(&mut self, quote: &Quote) -> Result<(), DataError> {
+        QuoteWriter::update_quote(self, quote)
+    }
+    fn delete_quote(&mut self, id: usize) -> Result<(), DataError> {
+        QuoteWriter::delete_quote(self, id)
+    }
+    fn delete_quotes_for_ticker(&mut self, ticker_id: usize) -> Result<usize, DataError> {
+        QuoteWriter::delete_quotes_for_ticker(self, ticker_id)
+    }
+    fn delete_quotes_before(
+        &mut self,
+        ticker_id: usize,
+        time: DateTime<Utc>,
+    ) -> Result<usize, DataError> {
+        QuoteWriter::delete_quotes_before(self, ticker_id, time)
+    }
+    fn store_quotes(&mut self, quotes: &[Quote]) -> Result<Vec<usize>, DataError> {
+        QuoteWriter::store_quotes(self, quotes)
+    }
+    fn insert_quotes(&mut self, quotes: &[Quote]) -> Result<Vec<usize>, DataError> {
+        QuoteWriter::insert_quotes(self, quotes)
+    }
+    fn get_stale_tickers(&mut self, before: DateTime<Utc>) -> Result<Vec<Ticker>, DataError> {
+        QuoteReader::get_stale_tickers(self, before)
+    }
+    fn get_rounding_digits(&mut self, currency: Currency) -> i32 {
+        QuoteReader::get_rounding_digits(self, currency)
+    }
+    fn set_rounding_digits(&mut self, currency: Currency, digits: i32) -> Result<(), DataError> {
+        QuoteWriter::set_rounding_digits(self, currency, digits)
+    }
+    fn add_quota_usage(
+        &mut self,
+        source: &str,
+        month: QuotaMonth,
+        calls: u64,
+    ) -> Result<(), DataError> {
+        QuoteWriter::add_quota_usage(self, source, month, calls)
+    }
+    fn get_quota_usage(&mut self, source: &str, month: QuotaMonth) -> Result<u64, DataError> {
+        QuoteReader::get_quota_usage(self, source, month)
+    }
+}
+
+/// Result of `QuoteHandler::insert_quote_if_new`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum QuoteInsertion {
+    /// The quote has been inserted with the given id
+    Inserted(usize),
+    /// Nothing has been written since a quote of the same ticker and time with the given id
+    /// exists already
+    Existing(usize),
+}
+
+impl QuoteInsertion {
+    /// Id of the new or the existing quote
+    pub fn id(&self) -> usize {
+        match self {
+            Self::Inserted(id) | Self::Existing(id) => *id,
+        }
+    }
+
+    pub fn is_new(&self) -> bool {
+        matches!(self, Self::Inserted(_))
+    }
+}
+
+/// Insert quotes without id and update quotes with id one by one. This does not take care of
+/// atomicity, it is intended to be called by implementations of `QuoteHandler::store_quotes`
+/// within a database transaction.
+pub fn store_each_quote<H: QuoteHandler + ?Sized>(
+    db: &mut H,
+    quotes: &[Quote],
+) -> Result<Vec<usize>, DataError> {
+    let mut ids = Vec::with_capacity(quotes.len());
+    for quote in quotes {
+        match quote.id {
+            Some(id) => {
+                db.update_quote(quote)?;
+                ids.push(id);
+            }
+            None => ids.push(db.insert_quote(quote)?),
+        }
+    }
+    Ok(ids)
 }
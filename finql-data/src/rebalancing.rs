@@ -0,0 +1,41 @@
+//! Rebalancing decisions recorded for later evaluation
+//!
+//! A decision stores a snapshot of the accepted rebalancing proposal, including the positions
+//! held before the trades, and links the transactions that implemented it. This allows to
+//! compare afterwards what happened with what would have happened without rebalancing.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::currency::Currency;
+
+/// Trade proposed to rebalance a portfolio
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct RebalancingTrade {
+    pub asset_id: usize,
+    /// Change of the position, negative for sells
+    pub position: f64,
+}
+
+/// Snapshot of a rebalancing proposal as it has been presented for decision
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RebalancingProposal {
+    /// Time the proposal has been made, the positions are the ones held at that time
+    pub time: DateTime<Utc>,
+    /// Currency the portfolio is valued in
+    pub currency: Currency,
+    /// Positions before the trades as pairs of asset id and position
+    pub positions: Vec<(usize, f64)>,
+    /// Cash balance before the trades in the portfolio currency
+    pub cash: f64,
+    pub trades: Vec<RebalancingTrade>,
+}
+
+/// Accepted rebalancing proposal together with the transactions that implemented it
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RebalancingDecision {
+    pub id: Option<usize>,
+    pub proposal: RebalancingProposal,
+    /// Ids of the transactions implementing the proposal, e.g. trades and their fees
+    pub transaction_ids: Vec<usize>,
+}
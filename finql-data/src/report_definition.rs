@@ -0,0 +1,60 @@
+//! Named report definitions and the log of job runs, e.g. of saved reports
+//!
+//! Report definitions are stored as JSON payload tagged with the version of the payload layout.
+//! Interpreting and upgrading the payload is left to the application running the reports.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::quote_handler::QuoteHandler;
+use crate::rebalancing::RebalancingDecision;
+use crate::transaction_handler::TransactionHandler;
+use crate::DataError;
+
+/// Report definition as stored in the database
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ReportDefinition {
+    /// Unique name of the definition
+    pub name: String,
+    /// Version of the layout of the payload
+    pub version: u32,
+    pub payload: Value,
+}
+
+/// Run of a job, e.g. of a saved report, recorded for later inspection
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct JobRun {
+    pub id: Option<usize>,
+    /// Name of the job, e.g. `report:monthly income`
+    pub job: String,
+    pub started: DateTime<Utc>,
+    pub finished: DateTime<Utc>,
+    pub success: bool,
+    /// Error message of failed runs
+    pub message: Option<String>,
+}
+
+/// Handler for report definitions, job runs and rebalancing decisions
+pub trait ReportHandler: QuoteHandler + TransactionHandler {
+    /// Store a report definition, replacing any definition with the same name
+    fn set_report_definition(&mut self, definition: &ReportDefinition) -> Result<(), DataError>;
+    /// Get a report definition by name, or None if there is none
+    fn get_report_definition(&mut self, name: &str)
+        -> Result<Option<ReportDefinition>, DataError>;
+    /// Get all report definitions ordered by name
+    fn get_all_report_definitions(&mut self) -> Result<Vec<ReportDefinition>, DataError>;
+    fn delete_report_definition(&mut self, name: &str) -> Result<(), DataError>;
+
+    fn insert_job_run(&mut self, run: &JobRun) -> Result<usize, DataError>;
+    /// Get all runs of a job, latest first
+    fn get_job_runs(&mut self, job: &str) -> Result<Vec<JobRun>, DataError>;
+
+    /// Store a rebalancing decision and link its transactions, which must be stored already
+    fn insert_rebalancing_decision(
+        &mut self,
+        decision: &RebalancingDecision,
+    ) -> Result<usize, DataError>;
+    /// Get a rebalancing decision by id, linked transaction ids are in ascending order
+    fn get_rebalancing_decision(&mut self, id: usize) -> Result<RebalancingDecision, DataError>;
+}
@@ -0,0 +1,351 @@
+//! Quote handler to be shared between threads
+//!
+//! Database handlers borrow their connection, which prevents sharing them between threads.
+//! `SharedHandler` owns the connection instead, via a `HandlerSource` implemented by each
+//! backend, and locks it for the duration of each request. Reads take `&self` by
+//! `QuoteReader`, so that a single handler can be shared by many threads within an `Arc`,
+//! while writes by `QuoteWriter` take `&mut self`, e.g. by the write guard of an `RwLock`.
+//! A connection serves one request at a time, therefore reads only run concurrently if the
+//! handler is given additional reader connections by `with_readers`; otherwise all requests
+//! are serialized on the single connection.
+//! By the blanket implementation of `QuoteHandler`, the shared handler can be used wherever
+//! a quote handler is expected.
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Mutex, PoisonError, TryLockError};
+
+use chrono::{DateTime, Utc};
+
+use crate::asset::{
+    Asset, AssetRepresentation, AssetSearchQuery, AssetSortKey, CurrencyExposure, Page,
+};
+use crate::asset_handler::AssetHandler;
+use crate::currency::Currency;
+use crate::instrument::InstrumentRecord;
+use crate::quota::QuotaMonth;
+use crate::quote::{Quote, QuoteQuery, Ticker, TickerUsage};
+use crate::quote_handler::{QuoteHandler, QuoteInsertion, QuoteReader, QuoteWriter};
+use crate::DataError;
+
+/// Owner of a database connection, which provides a handler using this connection
+pub trait HandlerSource: Send {
+    /// Call `f` with a handler using the owned connection
+    fn with_handler<R>(&mut self, f: impl FnOnce(&mut dyn QuoteHandler) -> R) -> R;
+}
+
+/// Handler owning its connections, which is `Send` and `Sync` if the connections are `Send`
+pub struct SharedHandler<S> {
+    /// Connection for all writes and for reads if there are no reader connections
+    source: Mutex<S>,
+    /// Connections to the same database used for reads only
+    readers: Vec<Mutex<S>>,
+    /// Reader connection to wait for if all of them are busy
+    next_reader: AtomicUsize,
+}
+
+impl<S: HandlerSource> SharedHandler<S> {
+    pub fn new(source: S) -> SharedHandler<S> {
+        Self::with_readers(source, Vec::new())
+    }
+
+    /// Handler writing by `source` and distributing reads over `readers`, which must be
+    /// connected to the same database. Reads of different threads run concurrently as long
+    /// as there is an idle reader connection.
+    pub fn with_readers(source: S, readers: Vec<S>) -> SharedHandler<S> {
+        SharedHandler {
+            source: Mutex::new(source),
+            readers: readers.into_iter().map(Mutex::new).collect(),
+            next_reader: AtomicUsize::new(0),
+        }
+    }
+
+    /// Release the owned write connection, reader connections are closed
+    pub fn into_inner(self) -> S {
+        self.source
+            .into_inner()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Call `f` with a handler using the write connection. No lock is required, since
+    /// `&mut self` guarantees exclusive access.
+    fn with_writer<R>(&mut self, f: impl FnOnce(&mut dyn QuoteHandler) -> R) -> R {
+        self.source
+            .get_mut()
+            .unwrap_or_else(PoisonError::into_inner)
+            .with_handler(f)
+    }
+
+    /// Call `f` with a handler using an idle reader connection, or wait for one if all of
+    /// them are busy. Without reader connections, requests of other threads wait until `f`
+    /// has returned. A panic of another thread while using a connection does not prevent
+    /// further requests, since each request is done by a single statement or within a
+    /// database transaction.
+    fn with_reader<R>(&self, f: impl FnOnce(&mut dyn QuoteHandler) -> R) -> R {
+        if self.readers.is_empty() {
+            return self
+                .source
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .with_handler(f);
+        }
+        let start = self.next_reader.fetch_add(1, Ordering::Relaxed);
+        let count = self.readers.len();
+        let idle = (0..count).find_map(|i| match self.readers[(start + i) % count].try_lock() {
+            Ok(reader) => Some(reader),
+            Err(TryLockError::Poisoned(err)) => Some(err.into_inner()),
+            Err(TryLockError::WouldBlock) => None,
+        });
+        let mut reader = match idle {
+            Some(reader) => reader,
+            None => self.readers[start % count]
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner),
+        };
+        reader.with_handler(f)
+    }
+}
+
+impl<S: HandlerSource> AssetHandler for SharedHandler<S> {
+    fn insert_asset(&mut self, asset: &Asset) -> Result<usize, DataError> {
+        self.with_writer(|db| db.insert_asset(asset))
+    }
+    fn get_asset_id(&mut self, asset: &Asset) -> Option<usize> {
+        self.with_writer(|db| db.get_asset_id(asset))
+    }
+    fn get_asset_by_id(&mut self, id: usize) -> Result<Asset, DataError> {
+        self.with_writer(|db| db.get_asset_by_id(id))
+    }
+    fn get_asset_by_isin(&mut self, isin: &str) -> Result<Asset, DataError> {
+        self.with_writer(|db| db.get_asset_by_isin(isin))
+    }
+    fn get_asset_by_wkn(&mut self, wkn: &str) -> Result<Asset, DataError> {
+        self.with_writer(|db| db.get_asset_by_wkn(wkn))
+    }
+    fn get_all_assets(&mut self) -> Result<Vec<Asset>, DataError> {
+        self.with_writer(|db| db.get_all_assets())
+    }
+    fn get_all_assets_sorted(
+        &mut self,
+        key: AssetSortKey,
+        ascending: bool,
+    ) -> Result<Vec<Asset>, DataError> {
+        self.with_writer(|db| db.get_all_assets_sorted(key, ascending))
+    }
+    fn get_assets_page(
+        &mut self,
+        sort: AssetSortKey,
+        ascending: bool,
+        page: Page,
+    ) -> Result<(Vec<Asset>, usize), DataError> {
+        self.with_writer(|db| db.get_assets_page(sort, ascending, page))
+    }
+    fn search_assets(&mut self, query: &AssetSearchQuery) -> Result<Vec<Asset>, DataError> {
+        self.with_writer(|db| db.search_assets(query))
+    }
+    fn update_asset(&mut self, asset: &Asset) -> Result<(), DataError> {
+        self.with_writer(|db| db.update_asset(asset))
+    }
+    fn update_assets(
+        &mut self,
+        assets: &[Asset],
+        exposures: &[CurrencyExposure],
+    ) -> Result<(), DataError> {
+        self.with_writer(|db| db.update_assets(assets, exposures))
+    }
+    fn delete_asset(&mut self, id: usize) -> Result<(), DataError> {
+        self.with_writer(|db| db.delete_asset(id))
+    }
+    fn get_all_currencies(&mut self) -> Result<Vec<Currency>, DataError> {
+        self.with_writer(|db| db.get_all_currencies())
+    }
+    fn set_instrument_record(&mut self, record: &InstrumentRecord) -> Result<(), DataError> {
+        self.with_writer(|db| db.set_instrument_record(record))
+    }
+    fn get_instrument_record(
+        &mut self,
+        asset_id: usize,
+    ) -> Result<Option<InstrumentRecord>, DataError> {
+        self.with_writer(|db| db.get_instrument_record(asset_id))
+    }
+    fn get_instrument_records(&mut self, kind: &str) -> Result<Vec<InstrumentRecord>, DataError> {
+        self.with_writer(|db| db.get_instrument_records(kind))
+    }
+    fn delete_instrument_record(&mut self, asset_id: usize) -> Result<(), DataError> {
+        self.with_writer(|db| db.delete_instrument_record(asset_id))
+    }
+    fn set_currency_exposure(&mut self, exposure: &CurrencyExposure) -> Result<(), DataError> {
+        self.with_writer(|db| db.set_currency_exposure(exposure))
+    }
+    fn get_currency_exposure(
+        &mut self,
+        asset_id: usize,
+    ) -> Result<Option<CurrencyExposure>, DataError> {
+        self.with_writer(|db| db.get_currency_exposure(asset_id))
+    }
+    fn delete_currency_exposure(&mut self, asset_id: usize) -> Result<(), DataError> {
+        self.with_writer(|db| db.delete_currency_exposure(asset_id))
+    }
+    fn set_asset_representation(
+        &mut self,
+        representation: &AssetRepresentation,
+    ) -> Result<(), DataError> {
+        self.with_writer(|db| db.set_asset_representation(representation))
+    }
+    fn get_asset_representation(
+        &mut self,
+        asset_id: usize,
+    ) -> Result<Option<AssetRepresentation>, DataError> {
+        self.with_writer(|db| db.get_asset_representation(asset_id))
+    }
+    fn get_representations_of(
+        &mut self,
+        underlying_id: usize,
+    ) -> Result<Vec<AssetRepresentation>, DataError> {
+        self.with_writer(|db| db.get_representations_of(underlying_id))
+    }
+    fn delete_asset_representation(&mut self, asset_id: usize) -> Result<(), DataError> {
+        self.with_writer(|db| db.delete_asset_representation(asset_id))
+    }
+}
+
+impl<S: HandlerSource> QuoteReader for SharedHandler<S> {
+    fn get_ticker_id(&self, ticker: &str) -> Option<usize> {
+        self.with_reader(|db| db.get_ticker_id(ticker))
+    }
+    fn get_ticker_id_for_source(&self, name: &str, source: &str) -> Option<usize> {
+        self.with_reader(|db| db.get_ticker_id_for_source(name, source))
+    }
+    fn get_ticker_by_id(&self, id: usize) -> Result<Ticker, DataError> {
+        self.with_reader(|db| db.get_ticker_by_id(id))
+    }
+    fn get_all_ticker(&self) -> Result<Vec<Ticker>, DataError> {
+        self.with_reader(|db| db.get_all_ticker())
+    }
+    fn get_all_ticker_for_source(&self, source: &str) -> Result<Vec<Ticker>, DataError> {
+        self.with_reader(|db| db.get_all_ticker_for_source(source))
+    }
+    fn get_all_ticker_for_asset(&self, asset_id: usize) -> Result<Vec<Ticker>, DataError> {
+        self.with_reader(|db| db.get_all_ticker_for_asset(asset_id))
+    }
+    fn get_tickers_by_source_url_pattern(
+        &self,
+        url_pattern: &str,
+    ) -> Result<Vec<Ticker>, DataError> {
+        self.with_reader(|db| db.get_tickers_by_source_url_pattern(url_pattern))
+    }
+    fn get_last_quote_before_for_usage(
+        &self,
+        asset_name: &str,
+        time: DateTime<Utc>,
+        usage: TickerUsage,
+    ) -> Result<(Quote, Currency), DataError> {
+        self.with_reader(|db| db.get_last_quote_before_for_usage(asset_name, time, usage))
+    }
+    fn get_last_quote_before_by_id_for_usage(
+        &self,
+        asset_id: usize,
+        time: DateTime<Utc>,
+        usage: TickerUsage,
+    ) -> Result<(Quote, Currency), DataError> {
+        self.with_reader(|db| db.get_last_quote_before_by_id_for_usage(asset_id, time, usage))
+    }
+    fn get_all_quotes_for_ticker(&self, ticker_id: usize) -> Result<Vec<Quote>, DataError> {
+        self.with_reader(|db| db.get_all_quotes_for_ticker(ticker_id))
+    }
+    fn get_all_quotes_for_ticker_and_source(
+        &self,
+        asset_id: usize,
+        source: &str,
+    ) -> Result<Vec<Quote>, DataError> {
+        self.with_reader(|db| db.get_all_quotes_for_ticker_and_source(asset_id, source))
+    }
+    fn get_quotes_above_quality(
+        &self,
+        ticker_id: usize,
+        min_quality: f64,
+    ) -> Result<Vec<Quote>, DataError> {
+        self.with_reader(|db| db.get_quotes_above_quality(ticker_id, min_quality))
+    }
+    fn get_quotes_in_range(
+        &self,
+        ticker_id: usize,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+    ) -> Result<Vec<Quote>, DataError> {
+        self.with_reader(|db| db.get_quotes_in_range(ticker_id, start, end))
+    }
+    fn get_quotes_page(
+        &self,
+        query: &QuoteQuery,
+        after_id: Option<usize>,
+        limit: usize,
+    ) -> Result<Vec<Quote>, DataError> {
+        self.with_reader(|db| db.get_quotes_page(query, after_id, limit))
+    }
+    fn get_latest_quotes_for_tickers(
+        &self,
+        ticker_ids: &[usize],
+    ) -> Result<Vec<(usize, Quote)>, DataError> {
+        self.with_reader(|db| db.get_latest_quotes_for_tickers(ticker_ids))
+    }
+    fn get_stale_tickers(&self, before: DateTime<Utc>) -> Result<Vec<Ticker>, DataError> {
+        self.with_reader(|db| db.get_stale_tickers(before))
+    }
+    fn get_rounding_digits(&self, currency: Currency) -> i32 {
+        self.with_reader(|db| db.get_rounding_digits(currency))
+    }
+    fn get_quota_usage(&self, source: &str, month: QuotaMonth) -> Result<u64, DataError> {
+        self.with_reader(|db| db.get_quota_usage(source, month))
+    }
+}
+
+impl<S: HandlerSource> QuoteWriter for SharedHandler<S> {
+    fn insert_ticker(&mut self, ticker: &Ticker) -> Result<usize, DataError> {
+        self.with_writer(|db| db.insert_ticker(ticker))
+    }
+    fn update_ticker(&mut self, ticker: &Ticker) -> Result<(), DataError> {
+        self.with_writer(|db| db.update_ticker(ticker))
+    }
+    fn delete_ticker(&mut self, id: usize) -> Result<(), DataError> {
+        self.with_writer(|db| db.delete_ticker(id))
+    }
+    fn insert_quote(&mut self, quote: &Quote) -> Result<usize, DataError> {
+        self.with_writer(|db| db.insert_quote(quote))
+    }
+    fn insert_quote_if_new(&mut self, quote: &Quote) -> Result<QuoteInsertion, DataError> {
+        self.with_writer(|db| db.insert_quote_if_new(quote))
+    }
+    fn update_quote(&mut self, quote: &Quote) -> Result<(), DataError> {
+        self.with_writer(|db| db.update_quote(quote))
+    }
+    fn delete_quote(&mut self, id: usize) -> Result<(), DataError> {
+        self.with_writer(|db| db.delete_quote(id))
+    }
+    fn delete_quotes_for_ticker(&mut self, ticker_id: usize) -> Result<usize, DataError> {
+        self.with_writer(|db| db.delete_quotes_for_ticker(ticker_id))
+    }
+    fn delete_quotes_before(
+        &mut self,
+        ticker_id: usize,
+        time: DateTime<Utc>,
+    ) -> Result<usize, DataError> {
+        self.with_writer(|db| db.delete_quotes_before(ticker_id, time))
+    }
+    fn store_quotes(&mut self, quotes: &[Quote]) -> Result<Vec<usize>, DataError> {
+        self.with_writer(|db| db.store_quotes(quotes))
+    }
+    fn insert_quotes(&mut self, quotes: &[Quote]) -> Result<Vec<usize>, DataError> {
+        self.with_writer(|db| db.insert_quotes(quotes))
+    }
+    fn set_rounding_digits(&mut self, currency: Currency, digits: i32) -> Result<(), DataError> {
+        self.with_writer(|db| db.set_rounding_digits(currency, digits))
+    }
+    fn add_quota_usage(
+        &mut self,
+        source: &str,
+        month: QuotaMonth,
+        calls: u64,
+    ) -> Result<(), DataError> {
+        self.with_writer(|db| db.add_quota_usage(source, month, calls))
+    }
+}
@@ -1,8 +1,13 @@
 ///! Implementation of basic transaction types
 
+use std::collections::{BTreeMap, HashMap};
+
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
 use super::{DataError, DataItem};
-use crate::cash_flow::CashFlow;
+use crate::cash_flow::{CashAmount, CashFlow};
+use crate::currency::Currency;
 
 /// Type of transaction
 #[derive(Debug, Copy, Clone, Serialize, Deserialize)]
@@ -13,6 +18,26 @@ pub enum TransactionType {
     Interest { asset_id: usize },
     Tax { transaction_ref: Option<usize> },
     Fee { transaction_ref: Option<usize> },
+    /// Leg of a transfer of an asset position between accounts. The outgoing leg has a negative
+    /// position, the incoming leg a positive one and refers to the outgoing leg by `transfer_ref`.
+    /// Transfers don't realize gains, the lots of the outgoing leg are carried over to the
+    /// incoming leg with their original purchase dates and prices.
+    Transfer { asset_id: usize, position: f64, transfer_ref: Option<usize> },
+}
+
+/// Direction of the cash flow of a transaction from the perspective of the account holder
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub enum CashDirection {
+    Inflow,
+    Outflow,
+    Neutral,
+}
+
+/// Explicit choice of (part of) a lot, i.e. a previous buy transaction, to be closed by a sell transaction
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct LotSelection {
+    pub buy_transaction_id: usize,
+    pub quantity: f64,
 }
 
 /// Basic transaction data
@@ -23,9 +48,60 @@ pub struct Transaction {
     pub transaction_type: TransactionType,
     pub cash_flow: CashFlow,
     pub note: Option<String>,
+    /// Structured execution details as delivered by the broker, e.g. venue, execution time
+    /// or order id, see `set_execution_meta`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub execution_meta: Option<serde_json::Value>,
+    /// Time the transaction has been entered into the database, set automatically at insert
+    /// if not given and never changed by updates. Allows to reproduce positions as they were
+    /// known at a past date, see `is_known_at`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub recorded_at: Option<DateTime<Utc>>,
 }
 
+/// Key of the broker's order id in the execution meta data, see
+/// `TransactionHandler::find_transaction_by_order_id`
+pub const ORDER_ID_KEY: &str = "order_id";
+
 impl Transaction {
+    /// Attach execution meta data, which must serialize to a JSON object. The broker's order id
+    /// should be stored in the field `order_id` to be able to search for it.
+    pub fn set_execution_meta<T: Serialize>(&mut self, meta: &T) -> Result<(), DataError> {
+        let meta = serde_json::to_value(meta).map_err(|e| DataError::InvalidData(e.to_string()))?;
+        if !meta.is_object() {
+            return Err(DataError::InvalidData(
+                "execution meta data must be a JSON object".to_string(),
+            ));
+        }
+        self.execution_meta = Some(meta);
+        Ok(())
+    }
+
+    /// Get the execution meta data as given type, if there are any
+    pub fn get_execution_meta<T: DeserializeOwned>(&self) -> Result<Option<T>, DataError> {
+        self.execution_meta
+            .as_ref()
+            .map(|meta| serde_json::from_value(meta.clone()))
+            .transpose()
+            .map_err(|e| DataError::InvalidData(e.to_string()))
+    }
+
+    /// Check whether the transaction has been entered by the given time. Transactions never
+    /// stored to a database are considered to be unknown.
+    pub fn is_known_at(&self, knowledge_time: DateTime<Utc>) -> bool {
+        self.recorded_at
+            .is_some_and(|recorded_at| recorded_at <= knowledge_time)
+    }
+
+    /// Get the broker's order id from the execution meta data, numbers are converted to strings
+    pub fn order_id(&self) -> Option<String> {
+        match self.execution_meta.as_ref()?.get(ORDER_ID_KEY)? {
+            serde_json::Value::String(id) => Some(id.clone()),
+            serde_json::Value::Number(id) => Some(id.to_string()),
+            _ => None,
+        }
+    }
+
     /// Assign or change transaction's asset_id, if possible
     /// This is often required for transactions on new assets
     pub fn set_asset_id(&mut self, asset_id: usize) {
@@ -36,6 +112,15 @@ impl Transaction {
             } => TransactionType::Asset { asset_id, position },
             TransactionType::Dividend { asset_id: _ } => TransactionType::Dividend { asset_id },
             TransactionType::Interest { asset_id: _ } => TransactionType::Interest { asset_id },
+            TransactionType::Transfer {
+                asset_id: _,
+                position,
+                transfer_ref,
+            } => TransactionType::Transfer {
+                asset_id,
+                position,
+                transfer_ref,
+            },
             _ => self.transaction_type,
         }
     }
@@ -49,9 +134,165 @@ impl Transaction {
             TransactionType::Fee { transaction_ref: _ } => TransactionType::Fee {
                 transaction_ref: Some(trans_ref),
             },
+            TransactionType::Transfer {
+                asset_id,
+                position,
+                transfer_ref: _,
+            } => TransactionType::Transfer {
+                asset_id,
+                position,
+                transfer_ref: Some(trans_ref),
+            },
             _ => self.transaction_type,
         }
     }
+
+    /// Check whether the transaction buys (i.e. increases the position in) an asset
+    pub fn is_buy(&self) -> bool {
+        matches!(self.transaction_type, TransactionType::Asset { position, .. } if position > 0.0)
+    }
+
+    /// Check whether the transaction sells (i.e. decreases the position in) an asset
+    pub fn is_sell(&self) -> bool {
+        matches!(self.transaction_type, TransactionType::Asset { position, .. } if position < 0.0)
+    }
+
+    /// Check whether the transaction is a dividend or interest payment
+    pub fn is_income(&self) -> bool {
+        matches!(
+            self.transaction_type,
+            TransactionType::Dividend { .. } | TransactionType::Interest { .. }
+        )
+    }
+
+    /// Direction of the cash flow implied by the transaction type. Buys, fees and taxes
+    /// are outflows, sells, dividends and interest are inflows. The direction of pure cash
+    /// transactions is given by the sign of the cash amount.
+    pub fn cash_direction(&self) -> CashDirection {
+        match self.transaction_type {
+            TransactionType::Asset { .. } if self.is_buy() => CashDirection::Outflow,
+            TransactionType::Asset { .. } if self.is_sell() => CashDirection::Inflow,
+            TransactionType::Asset { .. } => CashDirection::Neutral,
+            TransactionType::Dividend { .. } | TransactionType::Interest { .. } => {
+                CashDirection::Inflow
+            }
+            TransactionType::Tax { .. } | TransactionType::Fee { .. } => CashDirection::Outflow,
+            TransactionType::Transfer { .. } => CashDirection::Neutral,
+            TransactionType::Cash => {
+                let amount = self.cash_flow.amount.amount;
+                if amount > 0.0 {
+                    CashDirection::Inflow
+                } else if amount < 0.0 {
+                    CashDirection::Outflow
+                } else {
+                    CashDirection::Neutral
+                }
+            }
+        }
+    }
+
+    /// Check whether the transaction changes the cash balance, which is the case for all
+    /// transactions except transfers of asset positions and those without cash flow
+    pub fn affects_cash(&self) -> bool {
+        self.cash_direction() != CashDirection::Neutral
+    }
+
+    /// Sign of the cash flow by economic convention, i.e. `1.0` for inflows like sells,
+    /// dividends and interest, `-1.0` for outflows like buys, fees and taxes and `0.0`
+    /// for transactions that don't affect cash, see `cash_direction`
+    pub fn cash_sign(&self) -> f64 {
+        match self.cash_direction() {
+            CashDirection::Inflow => 1.0,
+            CashDirection::Outflow => -1.0,
+            CashDirection::Neutral => 0.0,
+        }
+    }
+
+    /// Total cash amount of the transaction including all fees and taxes in `related`
+    /// that refer to this transaction. All related fees and taxes must be in the same
+    /// currency as the transaction itself.
+    pub fn total_cost(&self, related: &[Transaction]) -> Result<CashAmount, DataError> {
+        let mut total = self.cash_flow.amount;
+        let id = match self.id {
+            Some(id) => id,
+            None => return Ok(total),
+        };
+        for trans in related {
+            match trans.transaction_type {
+                TransactionType::Fee {
+                    transaction_ref: Some(trans_ref),
+                }
+                | TransactionType::Tax {
+                    transaction_ref: Some(trans_ref),
+                } if trans_ref == id => {
+                    if trans.cash_flow.amount.currency != total.currency {
+                        return Err(DataError::CurrencyMismatch(format!(
+                            "{} instead of {}",
+                            trans.cash_flow.amount.currency, total.currency
+                        )));
+                    }
+                    total.amount += trans.cash_flow.amount.amount;
+                }
+                _ => {}
+            }
+        }
+        Ok(total)
+    }
+
+    /// Split an asset transaction into several lots, given as pairs of the fraction of the
+    /// total position and the unit price of the respective lot. The fractions must add up to 1.
+    /// Each lot keeps the date and note of the original transaction, the note is extended
+    /// by the number of the lot. The resulting transactions have no id yet.
+    pub fn split(self, lots: &[(f64, CashAmount)]) -> Result<Vec<Transaction>, DataError> {
+        let (asset_id, position) = match self.transaction_type {
+            TransactionType::Asset { asset_id, position } => (asset_id, position),
+            _ => {
+                return Err(DataError::InvalidTransaction(
+                    "only asset transactions can be split into lots".to_string(),
+                ))
+            }
+        };
+        let total_fraction: f64 = lots.iter().map(|(fraction, _)| fraction).sum();
+        if (total_fraction - 1.0).abs() > 1e-6 {
+            return Err(DataError::InvalidTransaction(format!(
+                "fractions of lots add up to {} instead of 1",
+                total_fraction
+            )));
+        }
+        let currency = self.cash_flow.amount.currency;
+        let num_lots = lots.len();
+        lots.iter()
+            .enumerate()
+            .map(|(i, (fraction, unit_price))| {
+                if unit_price.currency != currency {
+                    return Err(DataError::CurrencyMismatch(format!(
+                        "{} instead of {}",
+                        unit_price.currency, currency
+                    )));
+                }
+                let lot_position = fraction * position;
+                let lot_note = format!("lot {}/{}", i + 1, num_lots);
+                Ok(Transaction {
+                    id: None,
+                    transaction_type: TransactionType::Asset {
+                        asset_id,
+                        position: lot_position,
+                    },
+                    cash_flow: CashFlow::new(
+                        -lot_position * unit_price.amount,
+                        currency,
+                        self.cash_flow.date,
+                    ),
+                    note: Some(match &self.note {
+                        Some(note) => format!("{} ({})", note, lot_note),
+                        None => lot_note,
+                    }),
+                    execution_meta: self.execution_meta.clone(),
+                    recorded_at: None,
+                })
+            })
+            .collect()
+    }
 }
 
 impl DataItem for Transaction {
@@ -77,3 +318,542 @@ impl DataItem for Transaction {
         }
     }
 }
+
+/// Group transactions by the id of the asset they refer to. Transactions without
+/// an asset reference (i.e. cash, tax and fee transactions) are grouped under key `0`.
+pub fn group_transactions_by_asset(
+    transactions: &[Transaction],
+) -> HashMap<usize, Vec<&Transaction>> {
+    let mut groups: HashMap<usize, Vec<&Transaction>> = HashMap::new();
+    for transaction in transactions {
+        let asset_id = match transaction.transaction_type {
+            TransactionType::Asset { asset_id, .. }
+            | TransactionType::Dividend { asset_id }
+            | TransactionType::Interest { asset_id }
+            | TransactionType::Transfer { asset_id, .. } => asset_id,
+            _ => 0,
+        };
+        groups.entry(asset_id).or_default().push(transaction);
+    }
+    groups
+}
+
+/// Group transactions by cash flow date, transactions of a single date keep their original order
+pub fn group_transactions_by_date(
+    transactions: &[Transaction],
+) -> BTreeMap<NaiveDate, Vec<&Transaction>> {
+    let mut groups: BTreeMap<NaiveDate, Vec<&Transaction>> = BTreeMap::new();
+    for transaction in transactions {
+        groups
+            .entry(transaction.cash_flow.date)
+            .or_default()
+            .push(transaction);
+    }
+    groups
+}
+
+/// Transaction as stored in a database table, i.e. with the transaction type flattened
+/// into a type code and optional columns for the type specific values
+#[derive(Debug, Clone, PartialEq)]
+pub struct RawTransaction {
+    pub id: Option<i32>,
+    pub trans_type: String,
+    pub asset: Option<i32>,
+    pub cash_amount: f64,
+    pub cash_currency: String,
+    pub cash_date: NaiveDate,
+    pub related_trans: Option<i32>,
+    pub position: Option<f64>,
+    pub note: Option<String>,
+    pub execution_meta: Option<serde_json::Value>,
+    pub recorded_at: Option<DateTime<Utc>>,
+}
+
+/// Raw transaction type constants
+const CASH: &str = "c";
+const ASSET: &str = "a";
+const DIVIDEND: &str = "d";
+const INTEREST: &str = "i";
+const TAX: &str = "t";
+const FEE: &str = "f";
+const TRANSFER: &str = "x";
+
+impl RawTransaction {
+    /// Check that the type code is known, all columns required by the transaction type are set,
+    /// the currency is valid and the cash amount is finite
+    pub fn validate(&self) -> Result<(), DataError> {
+        let (needs_asset, needs_position) = match self.trans_type.as_str() {
+            CASH | TAX | FEE => (false, false),
+            DIVIDEND | INTEREST => (true, false),
+            ASSET | TRANSFER => (true, true),
+            unknown => {
+                return Err(DataError::InvalidTransaction(format!(
+                    "unknown transaction type '{}'",
+                    unknown
+                )))
+            }
+        };
+        if needs_asset && self.asset.is_none() {
+            return Err(DataError::InvalidTransaction(
+                "missing asset id".to_string(),
+            ));
+        }
+        if needs_position && self.position.is_none() {
+            return Err(DataError::InvalidTransaction(
+                "missing position value".to_string(),
+            ));
+        }
+        Currency::from_stored(&self.cash_currency)
+            .map_err(|e| DataError::InvalidData(e.to_string()))?;
+        if !self.cash_amount.is_finite() {
+            return Err(DataError::InvalidData(format!(
+                "cash amount must be finite, but is {}",
+                self.cash_amount
+            )));
+        }
+        Ok(())
+    }
+
+    pub fn to_transaction(&self) -> Result<Transaction, DataError> {
+        self.validate()?;
+        let currency = Currency::from_stored(&self.cash_currency)
+            .map_err(|e| DataError::InvalidData(e.to_string()))?;
+        let cash_flow = CashFlow {
+            amount: CashAmount {
+                amount: self.cash_amount,
+                currency,
+            },
+            date: self.cash_date,
+        };
+        // required columns are checked by `validate`
+        let asset_id = self.asset.unwrap_or_default() as usize;
+        let position = self.position.unwrap_or_default();
+        let related_trans = self.related_trans.map(|x| x as usize);
+        let transaction_type = match self.trans_type.as_str() {
+            ASSET => TransactionType::Asset { asset_id, position },
+            DIVIDEND => TransactionType::Dividend { asset_id },
+            INTEREST => TransactionType::Interest { asset_id },
+            TAX => TransactionType::Tax {
+                transaction_ref: related_trans,
+            },
+            FEE => TransactionType::Fee {
+                transaction_ref: related_trans,
+            },
+            TRANSFER => TransactionType::Transfer {
+                asset_id,
+                position,
+                transfer_ref: related_trans,
+            },
+            _ => TransactionType::Cash,
+        };
+        Ok(Transaction {
+            id: self.id.map(|x| x as usize),
+            transaction_type,
+            cash_flow,
+            note: self.note.clone(),
+            execution_meta: self.execution_meta.clone(),
+            recorded_at: self.recorded_at,
+        })
+    }
+
+    pub fn from_transaction(transaction: &Transaction) -> RawTransaction {
+        let mut raw_transaction = RawTransaction {
+            id: transaction.id.map(|x| x as i32),
+            trans_type: String::new(),
+            asset: None,
+            cash_amount: transaction.cash_flow.amount.amount,
+            cash_currency: transaction.cash_flow.amount.currency.to_string(),
+            cash_date: transaction.cash_flow.date,
+            related_trans: None,
+            position: None,
+            note: transaction.note.clone(),
+            execution_meta: transaction.execution_meta.clone(),
+            recorded_at: transaction.recorded_at,
+        };
+        match transaction.transaction_type {
+            TransactionType::Cash => raw_transaction.trans_type = CASH.to_string(),
+            TransactionType::Asset { asset_id, position } => {
+                raw_transaction.trans_type = ASSET.to_string();
+                raw_transaction.asset = Some(asset_id as i32);
+                raw_transaction.position = Some(position);
+            }
+            TransactionType::Dividend { asset_id } => {
+                raw_transaction.trans_type = DIVIDEND.to_string();
+                raw_transaction.asset = Some(asset_id as i32);
+            }
+            TransactionType::Interest { asset_id } => {
+                raw_transaction.trans_type = INTEREST.to_string();
+                raw_transaction.asset = Some(asset_id as i32);
+            }
+            TransactionType::Tax { transaction_ref } => {
+                raw_transaction.trans_type = TAX.to_string();
+                raw_transaction.related_trans = transaction_ref.map(|x| x as i32);
+            }
+            TransactionType::Fee { transaction_ref } => {
+                raw_transaction.trans_type = FEE.to_string();
+                raw_transaction.related_trans = transaction_ref.map(|x| x as i32);
+            }
+            TransactionType::Transfer {
+                asset_id,
+                position,
+                transfer_ref,
+            } => {
+                raw_transaction.trans_type = TRANSFER.to_string();
+                raw_transaction.asset = Some(asset_id as i32);
+                raw_transaction.position = Some(position);
+                raw_transaction.related_trans = transfer_ref.map(|x| x as i32);
+            }
+        };
+        raw_transaction
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use super::*;
+    use crate::currency::Currency;
+
+    fn make_transaction(
+        id: usize,
+        transaction_type: TransactionType,
+        amount: f64,
+        currency: &str,
+    ) -> Transaction {
+        Transaction {
+            id: Some(id),
+            transaction_type,
+            cash_flow: CashFlow::new(
+                amount,
+                Currency::from_str(currency).unwrap(),
+                NaiveDate::from_ymd(2020, 1, 15),
+            ),
+            note: None,
+            execution_meta: None,
+            recorded_at: None,
+        }
+    }
+
+    #[test]
+    fn total_cost_with_fees_and_taxes() {
+        let buy = make_transaction(
+            1,
+            TransactionType::Asset {
+                asset_id: 1,
+                position: 10.0,
+            },
+            -9_000.0,
+            "EUR",
+        );
+        let related = vec![
+            make_transaction(2, TransactionType::Fee { transaction_ref: Some(1) }, -30.0, "EUR"),
+            make_transaction(3, TransactionType::Tax { transaction_ref: Some(1) }, -5.5, "EUR"),
+            // fee of some other transaction must be ignored
+            make_transaction(4, TransactionType::Fee { transaction_ref: Some(5) }, -10.0, "EUR"),
+        ];
+        let total = buy.total_cost(&related).unwrap();
+        assert_eq!(total.currency.to_string(), "EUR");
+        assert!((total.amount + 9_035.5).abs() < 1e-10);
+
+        let related = vec![make_transaction(
+            2,
+            TransactionType::Fee { transaction_ref: Some(1) },
+            -30.0,
+            "USD",
+        )];
+        match buy.total_cost(&related) {
+            Err(DataError::CurrencyMismatch(_)) => {}
+            _ => panic!("expected currency mismatch"),
+        }
+    }
+
+    #[test]
+    fn group_transactions() {
+        let mut transactions = vec![
+            make_transaction(1, TransactionType::Cash, 10_000.0, "EUR"),
+            make_transaction(
+                2,
+                TransactionType::Asset {
+                    asset_id: 1,
+                    position: 10.0,
+                },
+                -9_000.0,
+                "EUR",
+            ),
+            make_transaction(
+                3,
+                TransactionType::Fee {
+                    transaction_ref: Some(2),
+                },
+                -30.0,
+                "EUR",
+            ),
+            make_transaction(4, TransactionType::Dividend { asset_id: 1 }, 50.0, "EUR"),
+            make_transaction(5, TransactionType::Interest { asset_id: 2 }, 5.0, "EUR"),
+        ];
+        transactions[3].cash_flow.date = NaiveDate::from_ymd(2020, 6, 1);
+
+        let by_asset = group_transactions_by_asset(&transactions);
+        assert_eq!(by_asset.len(), 3);
+        let ids =
+            |group: &Vec<&Transaction>| group.iter().map(|t| t.id.unwrap()).collect::<Vec<_>>();
+        assert_eq!(ids(&by_asset[&0]), vec![1, 3]);
+        assert_eq!(ids(&by_asset[&1]), vec![2, 4]);
+        assert_eq!(ids(&by_asset[&2]), vec![5]);
+
+        let by_date = group_transactions_by_date(&transactions);
+        let dates: Vec<_> = by_date.keys().copied().collect();
+        assert_eq!(
+            dates,
+            vec![
+                NaiveDate::from_ymd(2020, 1, 15),
+                NaiveDate::from_ymd(2020, 6, 1)
+            ]
+        );
+        assert_eq!(by_date[&dates[0]].len(), 4);
+        assert_eq!(by_date[&dates[1]][0].id, Some(4));
+    }
+
+    #[test]
+    fn transaction_classification() {
+        let buy = make_transaction(
+            1,
+            TransactionType::Asset {
+                asset_id: 1,
+                position: 10.0,
+            },
+            -9_000.0,
+            "EUR",
+        );
+        let sell = make_transaction(
+            2,
+            TransactionType::Asset {
+                asset_id: 1,
+                position: -5.0,
+            },
+            5_000.0,
+            "EUR",
+        );
+        let dividend = make_transaction(3, TransactionType::Dividend { asset_id: 1 }, 50.0, "EUR");
+        let fee = make_transaction(
+            4,
+            TransactionType::Fee {
+                transaction_ref: Some(1),
+            },
+            -10.0,
+            "EUR",
+        );
+        let withdrawal = make_transaction(5, TransactionType::Cash, -100.0, "EUR");
+        let transfer = make_transaction(
+            6,
+            TransactionType::Transfer {
+                asset_id: 1,
+                position: 5.0,
+                transfer_ref: Some(2),
+            },
+            0.0,
+            "EUR",
+        );
+
+        assert!(buy.is_buy() && !buy.is_sell() && !buy.is_income());
+        assert!(sell.is_sell() && !sell.is_buy());
+        assert!(dividend.is_income() && !dividend.is_buy() && !dividend.is_sell());
+        assert!(!fee.is_income());
+        assert_eq!(buy.cash_direction(), CashDirection::Outflow);
+        assert_eq!(sell.cash_direction(), CashDirection::Inflow);
+        assert_eq!(dividend.cash_direction(), CashDirection::Inflow);
+        assert_eq!(fee.cash_direction(), CashDirection::Outflow);
+        assert_eq!(withdrawal.cash_direction(), CashDirection::Outflow);
+        assert!(!transfer.is_buy() && !transfer.is_sell() && !transfer.is_income());
+        assert_eq!(transfer.cash_direction(), CashDirection::Neutral);
+
+        let tax = make_transaction(
+            7,
+            TransactionType::Tax {
+                transaction_ref: Some(3),
+            },
+            -12.5,
+            "EUR",
+        );
+        let interest = make_transaction(8, TransactionType::Interest { asset_id: 2 }, 3.0, "EUR");
+        let deposit = make_transaction(9, TransactionType::Cash, 1_000.0, "EUR");
+        for (transaction, sign) in &[
+            (&buy, -1.0),
+            (&sell, 1.0),
+            (&dividend, 1.0),
+            (&interest, 1.0),
+            (&fee, -1.0),
+            (&tax, -1.0),
+            (&deposit, 1.0),
+            (&withdrawal, -1.0),
+        ] {
+            assert!(transaction.affects_cash());
+            assert_eq!(transaction.cash_sign(), *sign);
+            // sign convention agrees with the stored cash amounts
+            assert!(transaction.cash_sign() * transaction.cash_flow.amount.amount > 0.0);
+        }
+        assert!(!transfer.affects_cash());
+        assert_eq!(transfer.cash_sign(), 0.0);
+    }
+
+    #[test]
+    fn split_into_lots() {
+        let price = |amount| CashAmount {
+            amount,
+            currency: Currency::from_str("EUR").unwrap(),
+        };
+        let mut buy = make_transaction(
+            1,
+            TransactionType::Asset {
+                asset_id: 1,
+                position: 100.0,
+            },
+            -1_010.0,
+            "EUR",
+        );
+        buy.note = Some("limit orders".to_string());
+        let lots = buy
+            .clone()
+            .split(&[(0.3, price(10.0)), (0.7, price(10.2))])
+            .unwrap();
+        assert_eq!(lots.len(), 2);
+        assert_eq!(lots[0].id, None);
+        assert!(matches!(
+            lots[1].transaction_type,
+            TransactionType::Asset { asset_id: 1, position } if (position - 70.0).abs() < 1e-10
+        ));
+        assert!((lots[0].cash_flow.amount.amount + 300.0).abs() < 1e-10);
+        assert!((lots[1].cash_flow.amount.amount + 714.0).abs() < 1e-10);
+        assert_eq!(lots[1].cash_flow.date, NaiveDate::from_ymd(2020, 1, 15));
+        assert_eq!(lots[1].note.as_deref(), Some("limit orders (lot 2/2)"));
+
+        let half = price(10.0);
+        assert!(matches!(
+            buy.clone().split(&[(0.5, half), (0.4, half)]),
+            Err(DataError::InvalidTransaction(_))
+        ));
+        let usd = CashAmount {
+            amount: 10.0,
+            currency: Currency::from_str("USD").unwrap(),
+        };
+        assert!(matches!(
+            buy.split(&[(0.5, half), (0.5, usd)]),
+            Err(DataError::CurrencyMismatch(_))
+        ));
+        let fee = make_transaction(2, TransactionType::Cash, -1.0, "EUR");
+        assert!(matches!(
+            fee.split(&[(1.0, half)]),
+            Err(DataError::InvalidTransaction(_))
+        ));
+    }
+
+    #[test]
+    fn raw_transaction_round_trip() {
+        let transfer = make_transaction(
+            7,
+            TransactionType::Transfer {
+                asset_id: 2,
+                position: 15.0,
+                transfer_ref: Some(6),
+            },
+            0.0,
+            "EUR",
+        );
+        let raw = RawTransaction::from_transaction(&transfer);
+        assert_eq!(raw.trans_type, TRANSFER);
+        assert_eq!(raw.related_trans, Some(6));
+        match raw.to_transaction().unwrap().transaction_type {
+            TransactionType::Transfer {
+                asset_id,
+                position,
+                transfer_ref,
+            } => {
+                assert_eq!(asset_id, 2);
+                assert_eq!(position, 15.0);
+                assert_eq!(transfer_ref, Some(6));
+            }
+            _ => panic!("transfer expected"),
+        }
+        let fee = make_transaction(
+            8,
+            TransactionType::Fee {
+                transaction_ref: Some(7),
+            },
+            -1.0,
+            "EUR",
+        );
+        let raw = RawTransaction::from_transaction(&fee);
+        assert!(matches!(
+            raw.to_transaction().unwrap().transaction_type,
+            TransactionType::Fee {
+                transaction_ref: Some(7)
+            }
+        ));
+    }
+
+    #[test]
+    fn validate_raw_transaction() {
+        let buy = make_transaction(
+            1,
+            TransactionType::Asset {
+                asset_id: 1,
+                position: 10.0,
+            },
+            -1000.0,
+            "EUR",
+        );
+        let raw = RawTransaction::from_transaction(&buy);
+        assert!(raw.validate().is_ok());
+        let mut invalid = raw.clone();
+        invalid.position = None;
+        assert!(matches!(
+            invalid.validate(),
+            Err(DataError::InvalidTransaction(_))
+        ));
+        let mut invalid = raw.clone();
+        invalid.trans_type = "z".to_string();
+        assert!(matches!(
+            invalid.to_transaction(),
+            Err(DataError::InvalidTransaction(_))
+        ));
+        let mut invalid = raw.clone();
+        invalid.cash_currency = "EURO".to_string();
+        assert!(matches!(invalid.validate(), Err(DataError::InvalidData(_))));
+        let mut invalid = raw;
+        invalid.cash_amount = f64::NAN;
+        assert!(invalid.validate().unwrap_err().is_invalid());
+    }
+
+    #[test]
+    fn execution_meta_accessors() {
+        #[derive(Debug, PartialEq, Serialize, Deserialize)]
+        struct Execution {
+            order_id: u64,
+            venue: String,
+        }
+
+        let mut trans = make_transaction(1, TransactionType::Cash, 100.0, "EUR");
+        assert_eq!(trans.order_id(), None);
+        assert!(trans.get_execution_meta::<Execution>().unwrap().is_none());
+        assert!(trans.set_execution_meta(&"not an object").is_err());
+        let execution = Execution {
+            order_id: 4711,
+            venue: "XETR".to_string(),
+        };
+        trans.set_execution_meta(&execution).unwrap();
+        assert_eq!(trans.order_id().as_deref(), Some("4711"));
+        assert_eq!(
+            trans.get_execution_meta::<Execution>().unwrap(),
+            Some(execution)
+        );
+        let json = serde_json::to_string(&trans).unwrap();
+        let trans: Transaction = serde_json::from_str(&json).unwrap();
+        assert_eq!(trans.order_id().as_deref(), Some("4711"));
+        let raw = RawTransaction::from_transaction(&trans);
+        assert_eq!(
+            raw.to_transaction().unwrap().execution_meta,
+            trans.execution_meta
+        );
+    }
+}
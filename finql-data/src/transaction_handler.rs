@@ -1,6 +1,7 @@
 use super::AssetHandler;
 use super::DataError;
-use crate::transaction::Transaction;
+use crate::fee_schedule::FeeSchedule;
+use crate::transaction::{LotSelection, Transaction};
 
 /// Handler for globally available data of transactions and related data
 pub trait TransactionHandler: AssetHandler {
@@ -10,4 +11,32 @@ pub trait TransactionHandler: AssetHandler {
     fn get_all_transactions(&mut self) -> Result<Vec<Transaction>, DataError>;
     fn update_transaction(&mut self, transaction: &Transaction) -> Result<(), DataError>;
     fn delete_transaction(&mut self, id: usize) -> Result<(), DataError>;
+    /// Get all transactions whose execution meta data refer to the given broker's order id,
+    /// e.g. multiple partial executions of the same order
+    fn find_transaction_by_order_id(&mut self, order_id: &str)
+        -> Result<Vec<Transaction>, DataError>;
+
+    /// Store the explicit selection of lots that are closed by a sell transaction
+    fn insert_lot_selection(
+        &mut self,
+        sell_transaction_id: usize,
+        lots: &[LotSelection],
+    ) -> Result<(), DataError>;
+    /// Insert a sell transaction together with the lots it closes, see `insert_lot_selection`.
+    /// Either both are stored or none of them. Returns the id of the new transaction.
+    fn insert_transaction_with_lot_selection(
+        &mut self,
+        transaction: &Transaction,
+        lots: &[LotSelection],
+    ) -> Result<usize, DataError>;
+    /// Get the lots explicitly selected for a sell transaction, empty if none have been selected
+    fn get_lot_selection(&mut self, sell_transaction_id: usize)
+        -> Result<Vec<LotSelection>, DataError>;
+
+    /// Store the fee schedule of an account, replacing any previously stored schedule of that
+    /// account. The schedule is validated before it is stored.
+    fn set_fee_schedule(&mut self, schedule: &FeeSchedule) -> Result<(), DataError>;
+    /// Get the fee schedule of an account, or None if there is none
+    fn get_fee_schedule(&mut self, account_id: usize) -> Result<Option<FeeSchedule>, DataError>;
+    fn delete_fee_schedule(&mut self, account_id: usize) -> Result<(), DataError>;
 }
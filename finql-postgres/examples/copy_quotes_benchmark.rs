@@ -0,0 +1,83 @@
+//! Compare inserting quotes one by one with bulk loading via the binary `COPY` protocol.
+//!
+//! Requires a test database whose content is deleted, e.g. run with
+//! `FINQL_POSTGRES_TEST_URL="host=localhost user=postgres dbname=finql_test" cargo run --release --features bulk_copy --example copy_quotes_benchmark [rows]`
+use std::str::FromStr;
+use std::time::Instant;
+
+use chrono::{Duration, TimeZone, Utc};
+
+use finql_data::{Asset, AssetHandler, Currency, Quote, QuoteHandler, Ticker, TickerUsage};
+use finql_postgres::PostgresDB;
+
+fn main() {
+    let url = std::env::var("FINQL_POSTGRES_TEST_URL")
+        .expect("FINQL_POSTGRES_TEST_URL must point to a test database");
+    let rows: usize = std::env::args()
+        .nth(1)
+        .map(|n| n.parse().expect("number of rows expected"))
+        .unwrap_or(100_000);
+    let mut conn = postgres::Client::connect(&url, postgres::NoTls).unwrap();
+    let mut db = PostgresDB::new(&mut conn);
+    db.clean().unwrap();
+    let asset_id = db
+        .insert_asset(&Asset::new(None, "BASF AG", None, None, None))
+        .unwrap();
+    let ticker_id = db
+        .insert_ticker(&Ticker {
+            id: None,
+            name: "BAS.DE".to_string(),
+            asset: asset_id,
+            source: "manual".to_string(),
+            priority: 1,
+            currency: Currency::from_str("EUR").unwrap(),
+            factor: 1.0,
+            source_url: None,
+            usage: TickerUsage::Both,
+            last_quote_time: None,
+            source_chain: Vec::new(),
+        })
+        .unwrap();
+    let start = Utc.ymd(2000, 1, 3).and_hms(9, 0, 0);
+    let quotes: Vec<Quote> = (0..rows)
+        .map(|i| Quote {
+            id: None,
+            ticker: ticker_id,
+            price: 50.0 + (i % 1000) as f64 / 100.0,
+            time: start + Duration::minutes(i as i64),
+            volume: Some(1000.0),
+            quality_score: None,
+            source: None,
+            open: None,
+            high: None,
+            low: None,
+            bid: None,
+            ask: None,
+        })
+        .collect();
+
+    let timer = Instant::now();
+    db.conn.get_mut().batch_execute("BEGIN").unwrap();
+    for quote in &quotes {
+        db.insert_quote(quote).unwrap();
+    }
+    db.conn.get_mut().batch_execute("COMMIT").unwrap();
+    let insert_time = timer.elapsed();
+    db.conn.get_mut().batch_execute("DELETE FROM quotes").unwrap();
+
+    let timer = Instant::now();
+    let copied = db.copy_quotes_from_slice(&quotes).unwrap();
+    let copy_time = timer.elapsed();
+    assert_eq!(copied, rows);
+
+    println!("{} quotes", rows);
+    println!(
+        "insert_quote:           {:>8.3}s",
+        insert_time.as_secs_f64()
+    );
+    println!("copy_quotes_from_slice: {:>8.3}s", copy_time.as_secs_f64());
+    println!(
+        "speedup:                {:>8.1}x",
+        insert_time.as_secs_f64() / copy_time.as_secs_f64()
+    );
+}
@@ -1,21 +1,161 @@
 use std::str::FromStr;
 
-use finql_data::asset::Asset;
-use finql_data::{AssetHandler, DataError};
+use postgres::types::ToSql;
+use postgres::Row;
+
+use finql_data::asset::{
+    Asset, AssetRepresentation, AssetSearchQuery, AssetSortKey, CurrencyExposure,
+    DistributionPolicy, Page,
+};
+use finql_data::asset_handler::{check_representation_cycle, update_each_asset};
+use finql_data::{AssetHandler, DataError, InstrumentRecord};
 use finql_data::currency::Currency;
 
 use super::PostgresDB;
 
+/// Construct the contract terms of an asset from a row of the `instrument_terms` table
+fn instrument_record_from_row(row: &Row) -> InstrumentRecord {
+    let asset_id: i32 = row.get(0);
+    let version: i32 = row.get(2);
+    InstrumentRecord {
+        asset_id: asset_id as usize,
+        kind: row.get(1),
+        version: version as u32,
+        payload: row.get(3),
+    }
+}
+
+/// Columns to select to construct an asset by `asset_from_row`
+const ASSET_COLUMNS: &str = "id, name, wkn, isin, note, reference_currency, distribution_policy,
+    hedged_to, asset_class, sector, country, expected_quote_frequency";
+
+/// Construct an asset from a row containing the columns given by `ASSET_COLUMNS`
+fn asset_from_row(row: &Row) -> Result<Asset, DataError> {
+    let id: i32 = row.get(0);
+    let reference_currency: Option<String> = row.get(5);
+    let reference_currency = reference_currency
+        .map(|currency| Currency::from_str(&currency))
+        .transpose()
+        .map_err(|e| DataError::InvalidData(e.to_string()))?;
+    let distribution_policy: Option<String> = row.get(6);
+    let distribution_policy = distribution_policy
+        .map(|policy| DistributionPolicy::from_str(&policy))
+        .transpose()
+        .map_err(|e| DataError::InvalidData(e.to_string()))?;
+    let hedged_to: Option<String> = row.get(7);
+    let hedged_to = hedged_to
+        .map(|currency| Currency::from_str(&currency))
+        .transpose()
+        .map_err(|e| DataError::InvalidData(e.to_string()))?;
+    Ok(Asset {
+        id: Some(id as usize),
+        name: row.get(1),
+        wkn: row.get(2),
+        isin: row.get(3),
+        note: row.get(4),
+        reference_currency,
+        distribution_policy,
+        hedged_to,
+        asset_class: row.get(8),
+        sector: row.get(9),
+        country: row.get(10),
+        expected_quote_frequency: row.get(11),
+    })
+}
+
 /// Handler for globally available data
+impl PostgresDB<'_> {
+    /// Get all assets returned by the given query selecting the columns `ASSET_COLUMNS`
+    fn query_assets(
+        &mut self,
+        query: &str,
+        params: &[&(dyn ToSql + Sync)],
+    ) -> Result<Vec<Asset>, DataError> {
+        let mut assets = Vec::new();
+        for row in self
+            .conn
+            .get_mut()
+            .query(query, params)
+            .map_err(|e| DataError::NotFound(e.to_string()))?
+        {
+            assets.push(asset_from_row(&row)?);
+        }
+        Ok(assets)
+    }
+
+    /// Get the only asset with the given value in the identifier column `isin` or `wkn`
+    fn query_unique_asset(&mut self, column: &str, value: &str) -> Result<Asset, DataError> {
+        let mut assets = self.query_assets(
+            &format!("SELECT {} FROM assets WHERE {}=$1", ASSET_COLUMNS, column),
+            &[&value],
+        )?;
+        match assets.len() {
+            0 => Err(DataError::NotFound(format!(
+                "no asset with {} '{}'",
+                column, value
+            ))),
+            1 => Ok(assets.remove(0)),
+            n => Err(DataError::AmbiguousMatch(format!(
+                "{} assets with {} '{}'",
+                n, column, value
+            ))),
+        }
+    }
+
+    /// Error of a failed insert of the asset, `DataError::DuplicateAsset` if an asset with the
+    /// same ISIN, WKN or name exists already
+    fn insert_asset_error(&mut self, asset: &Asset, err: String) -> DataError {
+        let existing = self.query_assets(
+            &format!(
+                "SELECT {} FROM assets WHERE isin=$1 OR wkn=$2 OR name=$3",
+                ASSET_COLUMNS
+            ),
+            &[&asset.isin, &asset.wkn, &asset.name],
+        );
+        let existing = match existing {
+            Ok(existing) => existing,
+            Err(_) => return DataError::InsertFailed(err),
+        };
+        for existing in existing {
+            if let Some(id) = existing.id {
+                let conflict = if asset.isin.is_some() && existing.isin == asset.isin {
+                    format!("ISIN '{}'", existing.isin.unwrap_or_default())
+                } else if asset.wkn.is_some() && existing.wkn == asset.wkn {
+                    format!("WKN '{}'", existing.wkn.unwrap_or_default())
+                } else {
+                    format!("name '{}'", existing.name)
+                };
+                return DataError::DuplicateAsset(id, conflict);
+            }
+        }
+        DataError::InsertFailed(err)
+    }
+}
+
 impl AssetHandler for PostgresDB<'_> {
     fn insert_asset(&mut self, asset: &Asset) -> Result<usize, DataError> {
         let row = self
             .conn
+            .get_mut()
             .query_one(
-                "INSERT INTO assets (name, wkn, isin, note) VALUES ($1, $2, $3, $4) RETURNING id",
-                &[&asset.name, &asset.wkn, &asset.isin, &asset.note],
+                "INSERT INTO assets (name, wkn, isin, note, reference_currency, distribution_policy,
+                hedged_to, asset_class, sector, country, expected_quote_frequency)
+                VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11) RETURNING id",
+                &[
+                    &asset.name,
+                    &asset.wkn,
+                    &asset.isin,
+                    &asset.note,
+                    &asset.reference_currency.map(|c| c.to_string()),
+                    &asset.distribution_policy.map(|p| p.to_string()),
+                    &asset.hedged_to.map(|c| c.to_string()),
+                    &asset.asset_class,
+                    &asset.sector,
+                    &asset.country,
+                    &asset.expected_quote_frequency,
+                ],
             )
-            .map_err(|e| DataError::InsertFailed(e.to_string()))?;
+            .map_err(|e| self.insert_asset_error(asset, e.to_string()))?;
         let id: i32 = row.get(0);
         Ok(id as usize)
     }
@@ -23,12 +163,15 @@ impl AssetHandler for PostgresDB<'_> {
     fn get_asset_id(&mut self, asset: &Asset) -> Option<usize> {
         let row = if let Some(isin) = &asset.isin {
             self.conn
+                .get_mut()
                 .query_one("SELECT id FROM assets WHERE isin=$1", &[&isin])
         } else if let Some(wkn) = &asset.wkn {
             self.conn
+                .get_mut()
                 .query_one("SELECT id FROM assets WHERE wkn=$1", &[&wkn])
         } else {
             self.conn
+                .get_mut()
                 .query_one("SELECT id FROM assets WHERE name=$1", &[&asset.name])
         };
         match row {
@@ -43,56 +186,83 @@ impl AssetHandler for PostgresDB<'_> {
     fn get_asset_by_id(&mut self, id: usize) -> Result<Asset, DataError> {
         let row = self
             .conn
+            .get_mut()
             .query_one(
-                "SELECT name, wkn, isin, note FROM assets WHERE id=$1",
+                format!("SELECT {} FROM assets WHERE id=$1", ASSET_COLUMNS).as_str(),
                 &[&(id as i32)],
             )
             .map_err(|e| DataError::NotFound(e.to_string()))?;
-        Ok(Asset {
-            id: Some(id),
-            name: row.get(0),
-            wkn: row.get(1),
-            isin: row.get(2),
-            note: row.get(3),
-        })
+        asset_from_row(&row)
     }
 
     fn get_asset_by_isin(&mut self, isin: &str) -> Result<Asset, DataError> {
+        self.query_unique_asset("isin", isin)
+    }
+
+    fn get_asset_by_wkn(&mut self, wkn: &str) -> Result<Asset, DataError> {
+        self.query_unique_asset("wkn", wkn)
+    }
+
+    fn get_all_assets(&mut self) -> Result<Vec<Asset>, DataError> {
+        self.query_assets(
+            &format!("SELECT {} FROM assets ORDER BY name", ASSET_COLUMNS),
+            &[],
+        )
+    }
+
+    fn get_all_assets_sorted(
+        &mut self,
+        key: AssetSortKey,
+        ascending: bool,
+    ) -> Result<Vec<Asset>, DataError> {
+        self.query_assets(
+            &format!(
+                "SELECT {} FROM assets ORDER BY {}",
+                ASSET_COLUMNS,
+                key.order_by(ascending)
+            ),
+            &[],
+        )
+    }
+
+    fn get_assets_page(
+        &mut self,
+        sort: AssetSortKey,
+        ascending: bool,
+        page: Page,
+    ) -> Result<(Vec<Asset>, usize), DataError> {
         let row = self
             .conn
-            .query_one(
-                "SELECT id, name, wkn, note FROM assets WHERE isin=$1",
-                &[&isin.to_string()],
-            )
+            .get_mut()
+            .query_one("SELECT COUNT(*) FROM assets", &[])
             .map_err(|e| DataError::NotFound(e.to_string()))?;
-        let id: i32 = row.get(0);
-        Ok(Asset {
-            id: Some(id as usize),
-            name: row.get(1),
-            wkn: row.get(2),
-            isin: Some(isin.to_string()),
-            note: row.get(3),
-        })
+        let total: i64 = row.get(0);
+        let assets = self.query_assets(
+            &format!(
+                "SELECT {} FROM assets ORDER BY {} LIMIT {} OFFSET {}",
+                ASSET_COLUMNS,
+                sort.order_by(ascending),
+                page.size,
+                page.offset()
+            ),
+            &[],
+        )?;
+        Ok((assets, total as usize))
     }
 
-    fn get_all_assets(&mut self) -> Result<Vec<Asset>, DataError> {
-        let mut assets = Vec::new();
-        for row in self
-            .conn
-            .query("SELECT id, name, wkn, isin, note FROM assets ORDER BY name", &[])
-            .map_err(|e| DataError::NotFound(e.to_string()))?
-        {
-            let id: i32 = row.get(0);
-            let id = Some(id as usize);
-            assets.push(Asset {
-                id,
-                name: row.get(1),
-                wkn: row.get(2),
-                isin: row.get(3),
-                note: row.get(4),
-            });
-        }
-        Ok(assets)
+    fn search_assets(&mut self, query: &AssetSearchQuery) -> Result<Vec<Asset>, DataError> {
+        let (condition, params) = query.sql_condition(|n| format!("${}", n));
+        let params: Vec<&(dyn ToSql + Sync)> =
+            params.iter().map(|p| p as &(dyn ToSql + Sync)).collect();
+        self.query_assets(
+            &format!(
+                "SELECT {} FROM assets WHERE {} ORDER BY name{}",
+                ASSET_COLUMNS,
+                condition,
+                query.sql_limit()
+            ),
+            &params,
+        )
     }
 
     fn update_asset(&mut self, asset: &Asset) -> Result<(), DataError> {
@@ -103,17 +273,58 @@ impl AssetHandler for PostgresDB<'_> {
         }
         let id = asset.id.unwrap() as i32;
         self.conn
+            .get_mut()
             .execute(
-                "UPDATE assets SET name=$2, wkn=$3, isin=$4, note=$5 
-                WHERE id=$1;",
-                &[&id, &asset.name, &asset.wkn, &asset.isin, &asset.note],
+                "UPDATE assets SET name=$2, wkn=$3, isin=$4, note=$5, reference_currency=$6,
+                distribution_policy=$7, hedged_to=$8, asset_class=$9, sector=$10, country=$11,
+                expected_quote_frequency=$12 WHERE id=$1;",
+                &[
+                    &id,
+                    &asset.name,
+                    &asset.wkn,
+                    &asset.isin,
+                    &asset.note,
+                    &asset.reference_currency.map(|c| c.to_string()),
+                    &asset.distribution_policy.map(|p| p.to_string()),
+                    &asset.hedged_to.map(|c| c.to_string()),
+                    &asset.asset_class,
+                    &asset.sector,
+                    &asset.country,
+                    &asset.expected_quote_frequency,
+                ],
             )
             .map_err(|e| DataError::InsertFailed(e.to_string()))?;
         Ok(())
     }
 
+    fn update_assets(
+        &mut self,
+        assets: &[Asset],
+        exposures: &[CurrencyExposure],
+    ) -> Result<(), DataError> {
+        self.conn
+            .get_mut()
+            .batch_execute("BEGIN")
+            .map_err(|e| DataError::DataAccessFailure(e.to_string()))?;
+        match update_each_asset(self, assets, exposures) {
+            Ok(()) => self
+                .conn
+                .get_mut()
+                .batch_execute("COMMIT")
+                .map_err(|e| DataError::DataAccessFailure(e.to_string())),
+            Err(err) => {
+                self.conn
+                    .get_mut()
+                    .batch_execute("ROLLBACK")
+                    .map_err(|e| DataError::DataAccessFailure(e.to_string()))?;
+                Err(err)
+            }
+        }
+    }
+
     fn delete_asset(&mut self, id: usize) -> Result<(), DataError> {
         self.conn
+            .get_mut()
             .execute("DELETE FROM assets WHERE id=$1;", &[&(id as i32)])
             .map_err(|e| DataError::InsertFailed(e.to_string()))?;
         Ok(())
@@ -123,6 +334,7 @@ impl AssetHandler for PostgresDB<'_> {
         let mut currencies = Vec::new();
         for row in self
             .conn
+            .get_mut()
             .query("SELECT name FROM assets WHERE isin IS NULL AND wkn IS NULL AND length(name)=3", &[])
             .map_err(|e| DataError::NotFound(e.to_string()))?
         {
@@ -133,4 +345,187 @@ impl AssetHandler for PostgresDB<'_> {
         }
         Ok(currencies)
     }
+
+    fn set_instrument_record(&mut self, record: &InstrumentRecord) -> Result<(), DataError> {
+        self.conn
+            .get_mut()
+            .execute(
+                "INSERT INTO instrument_terms (asset_id, kind, version, payload)
+                VALUES ($1, $2, $3, $4)
+                ON CONFLICT (asset_id) DO UPDATE
+                SET kind=EXCLUDED.kind, version=EXCLUDED.version, payload=EXCLUDED.payload",
+                &[
+                    &(record.asset_id as i32),
+                    &record.kind,
+                    &(record.version as i32),
+                    &record.payload,
+                ],
+            )
+            .map_err(|e| DataError::InsertFailed(e.to_string()))?;
+        Ok(())
+    }
+
+    fn get_instrument_record(
+        &mut self,
+        asset_id: usize,
+    ) -> Result<Option<InstrumentRecord>, DataError> {
+        let row = self
+            .conn
+            .get_mut()
+            .query_opt(
+                "SELECT asset_id, kind, version, payload FROM instrument_terms WHERE asset_id=$1",
+                &[&(asset_id as i32)],
+            )
+            .map_err(|e| DataError::NotFound(e.to_string()))?;
+        Ok(row.map(|row| instrument_record_from_row(&row)))
+    }
+
+    fn get_instrument_records(&mut self, kind: &str) -> Result<Vec<InstrumentRecord>, DataError> {
+        let rows = self
+            .conn
+            .get_mut()
+            .query(
+                "SELECT asset_id, kind, version, payload FROM instrument_terms
+                WHERE kind=$1 ORDER BY asset_id",
+                &[&kind],
+            )
+            .map_err(|e| DataError::NotFound(e.to_string()))?;
+        Ok(rows.iter().map(instrument_record_from_row).collect())
+    }
+
+    fn delete_instrument_record(&mut self, asset_id: usize) -> Result<(), DataError> {
+        self.conn
+            .get_mut()
+            .execute(
+                "DELETE FROM instrument_terms WHERE asset_id=$1;",
+                &[&(asset_id as i32)],
+            )
+            .map_err(|e| DataError::DeleteFailed(e.to_string()))?;
+        Ok(())
+    }
+
+    fn set_currency_exposure(&mut self, exposure: &CurrencyExposure) -> Result<(), DataError> {
+        exposure.validate()?;
+        self.delete_currency_exposure(exposure.asset_id)?;
+        for (currency, weight) in &exposure.weights {
+            self.conn
+                .get_mut()
+                .execute(
+                    "INSERT INTO currency_exposures (asset_id, currency, weight) VALUES ($1, $2, $3)",
+                    &[&(exposure.asset_id as i32), &currency.to_string(), weight],
+                )
+                .map_err(|e| DataError::InsertFailed(e.to_string()))?;
+        }
+        Ok(())
+    }
+
+    fn get_currency_exposure(
+        &mut self,
+        asset_id: usize,
+    ) -> Result<Option<CurrencyExposure>, DataError> {
+        let mut weights = Vec::new();
+        for row in self
+            .conn
+            .get_mut()
+            .query(
+                "SELECT currency, weight FROM currency_exposures WHERE asset_id=$1 ORDER BY currency",
+                &[&(asset_id as i32)],
+            )
+            .map_err(|e| DataError::NotFound(e.to_string()))?
+        {
+            let currency: String = row.get(0);
+            let currency =
+                Currency::from_str(&currency).map_err(|e| DataError::NotFound(e.to_string()))?;
+            weights.push((currency, row.get(1)));
+        }
+        if weights.is_empty() {
+            Ok(None)
+        } else {
+            Ok(Some(CurrencyExposure { asset_id, weights }))
+        }
+    }
+
+    fn delete_currency_exposure(&mut self, asset_id: usize) -> Result<(), DataError> {
+        self.conn
+            .get_mut()
+            .execute(
+                "DELETE FROM currency_exposures WHERE asset_id=$1;",
+                &[&(asset_id as i32)],
+            )
+            .map_err(|e| DataError::DeleteFailed(e.to_string()))?;
+        Ok(())
+    }
+
+    fn set_asset_representation(
+        &mut self,
+        representation: &AssetRepresentation,
+    ) -> Result<(), DataError> {
+        representation.validate()?;
+        check_representation_cycle(self, representation)?;
+        self.delete_asset_representation(representation.asset_id)?;
+        self.conn
+            .get_mut()
+            .execute(
+                "INSERT INTO asset_representations (asset_id, underlying_id, ratio) VALUES ($1, $2, $3)",
+                &[
+                    &(representation.asset_id as i32),
+                    &(representation.underlying_id as i32),
+                    &representation.ratio,
+                ],
+            )
+            .map_err(|e| DataError::InsertFailed(e.to_string()))?;
+        Ok(())
+    }
+
+    fn get_asset_representation(
+        &mut self,
+        asset_id: usize,
+    ) -> Result<Option<AssetRepresentation>, DataError> {
+        let row = self
+            .conn
+            .get_mut()
+            .query_opt(
+                "SELECT asset_id, underlying_id, ratio FROM asset_representations WHERE asset_id=$1",
+                &[&(asset_id as i32)],
+            )
+            .map_err(|e| DataError::NotFound(e.to_string()))?;
+        Ok(row.map(|row| asset_representation_from_row(&row)))
+    }
+
+    fn get_representations_of(
+        &mut self,
+        underlying_id: usize,
+    ) -> Result<Vec<AssetRepresentation>, DataError> {
+        let rows = self
+            .conn
+            .get_mut()
+            .query(
+                "SELECT asset_id, underlying_id, ratio FROM asset_representations WHERE underlying_id=$1 ORDER BY asset_id",
+                &[&(underlying_id as i32)],
+            )
+            .map_err(|e| DataError::NotFound(e.to_string()))?;
+        Ok(rows.iter().map(asset_representation_from_row).collect())
+    }
+
+    fn delete_asset_representation(&mut self, asset_id: usize) -> Result<(), DataError> {
+        self.conn
+            .get_mut()
+            .execute(
+                "DELETE FROM asset_representations WHERE asset_id=$1;",
+                &[&(asset_id as i32)],
+            )
+            .map_err(|e| DataError::DeleteFailed(e.to_string()))?;
+        Ok(())
+    }
+}
+
+/// Construct an asset representation from a row of the `asset_representations` table
+fn asset_representation_from_row(row: &Row) -> AssetRepresentation {
+    let asset_id: i32 = row.get(0);
+    let underlying_id: i32 = row.get(1);
+    AssetRepresentation {
+        asset_id: asset_id as usize,
+        underlying_id: underlying_id as usize,
+        ratio: row.get(2),
+    }
 }
@@ -1,45 +1,239 @@
 ///! Implementation of PostgreSQL data handler
 
+use std::cell::RefCell;
+
 use postgres::{Client,error::Error};
+use finql_data::{
+    normalize_currency_code, Clock, DataError, DataHandler, DuplicateQuote, DuplicateSetting, HandlerSource,
+    QuoteHandler, SchemaHandler, SharedHandler, SystemClock, TransactionHandler,
+    UnnormalizedCurrency,
+};
 
 pub mod asset_handler;
 pub mod quote_handler;
 pub mod transaction_handler;
+pub mod order_handler;
+pub mod report_handler;
+
+/// Settings tables as pairs of table name and key column, the key must be unique
+const SETTINGS_TABLES: &[(&str, &str)] = &[("rounding_digits", "currency")];
+
+/// Columns referring to transactions as pairs of table name and column, except of the
+/// references between transactions
+const TRANSACTION_LINKS: &[(&str, &str)] = &[
+    ("lot_selections", "sell_trans_id"),
+    ("lot_selections", "buy_trans_id"),
+    ("order_transactions", "transaction_id"),
+    ("rebalancing_transactions", "transaction_id"),
+];
+
+/// Columns containing currency codes as pairs of table name and column
+const CURRENCY_COLUMNS: &[(&str, &str)] =
+    &[("ticker", "currency"), ("transactions", "cash_currency")];
 
 /// Struct to handle connections to sqlite3 databases
 pub struct PostgresDB<'a> {
-    /// conn is made public to allow extending this struct outside of the library
-    pub conn: &'a mut Client,
+    /// conn is made public to allow extending this struct outside of the library. The client
+    /// is wrapped in a `RefCell`, since reads by `QuoteReader` take `&self`; methods taking
+    /// `&mut self` access it by `conn.get_mut()`.
+    pub conn: RefCell<&'a mut Client>,
+    /// Source of the time new transactions are recorded at
+    pub clock: &'a dyn Clock,
+}
+
+impl<'a> PostgresDB<'a> {
+    /// Handle the given client, recording transactions at the system time
+    pub fn new(conn: &'a mut Client) -> PostgresDB<'a> {
+        Self::with_clock(conn, &SystemClock)
+    }
+
+    /// Handle the given client, recording transactions at the time given by `clock`
+    pub fn with_clock(conn: &'a mut Client, clock: &'a dyn Clock) -> PostgresDB<'a> {
+        PostgresDB {
+            conn: RefCell::new(conn),
+            clock,
+        }
+    }
 }
 
+/// PostgreSQL client owned by a handler, see `SharedPostgresDB`
+pub struct OwnedPostgresDB {
+    pub client: Client,
+}
+
+impl HandlerSource for OwnedPostgresDB {
+    fn with_handler<R>(&mut self, f: impl FnOnce(&mut dyn QuoteHandler) -> R) -> R {
+        f(&mut PostgresDB::new(&mut self.client))
+    }
+}
+
+/// PostgreSQL quote handler owning its client, which can be shared between threads. A client
+/// runs one query at a time, therefore reads run concurrently only if further clients are
+/// given as readers by `SharedHandler::with_readers`.
+pub type SharedPostgresDB = SharedHandler<OwnedPostgresDB>;
+
 impl PostgresDB<'_> {
     /// Clean database by dropping all tables and than run init
     pub fn clean(&mut self) -> Result<(), Error> {
         self.conn
+            .get_mut()
+            .execute("DROP TABLE IF EXISTS rebalancing_transactions", &[])?;
+        self.conn
+            .get_mut()
+            .execute("DROP TABLE IF EXISTS rebalancing_decisions", &[])?;
+        self.conn
+            .get_mut()
+            .execute("DROP TABLE IF EXISTS order_transactions", &[])?;
+        self.conn.get_mut().execute("DROP TABLE IF EXISTS orders", &[])?;
+        self.conn
+            .get_mut()
+            .execute("DROP TABLE IF EXISTS lot_selections", &[])?;
+        self.conn
+            .get_mut()
+            .execute("DROP TABLE IF EXISTS fee_schedules", &[])?;
+        self.conn
+            .get_mut()
             .execute("DROP TABLE IF EXISTS transactions", &[])?;
-        self.conn.execute("DROP TABLE IF EXISTS quotes", &[])?;
-        self.conn.execute("DROP TABLE IF EXISTS ticker", &[])?;
         self.conn
+            .get_mut()
+            .execute("DROP TABLE IF EXISTS instrument_terms", &[])?;
+        self.conn
+            .get_mut()
+            .execute("DROP TABLE IF EXISTS option_terms", &[])?;
+        self.conn
+            .get_mut()
+            .execute("DROP TABLE IF EXISTS currency_exposures", &[])?;
+        self.conn
+            .get_mut()
+            .execute("DROP TABLE IF EXISTS asset_representations", &[])?;
+        self.conn.get_mut().execute("DROP TABLE IF EXISTS quota_usage", &[])?;
+        self.conn.get_mut().execute("DROP TABLE IF EXISTS quotes", &[])?;
+        self.conn.get_mut().execute("DROP TABLE IF EXISTS ticker", &[])?;
+        self.conn
+            .get_mut()
             .execute("DROP TYPE IF EXISTS market_data_source", &[])?;
-        self.conn.execute("DROP TABLE IF EXISTS assets", &[])?;
+        self.conn.get_mut().execute("DROP TABLE IF EXISTS assets", &[])?;
         self.conn
+            .get_mut()
             .execute("DROP TABLE IF EXISTS rounding_digits", &[])?;
-        self.init()
+        self.conn
+            .get_mut()
+            .execute("DROP TABLE IF EXISTS report_definitions", &[])?;
+        self.conn.get_mut().execute("DROP TABLE IF EXISTS job_runs", &[])?;
+        self.create_tables()
     }
 
     /// Initialize new database by creating table
-    pub fn init(&mut self) -> Result<(), Error> {
-        self.conn.execute(
+    ///
+    /// Fails with `DataError::DuplicateAsset` if several assets of a legacy database share an
+    /// ISIN or WKN, which must be resolved before the unique keys on these identifiers are added.
+    /// Likewise, fails with `DataError::DuplicateQuotes` if several quotes share the ticker and
+    /// time, see `collapse_duplicate_quotes`.
+    pub fn init(&mut self) -> Result<(), DataError> {
+        let to_data_error = |e: Error| DataError::DataAccessFailure(e.to_string());
+        let has_assets: bool = self
+            .conn
+            .get_mut()
+            .query_one(
+                "SELECT EXISTS (SELECT 1 FROM information_schema.tables
+                WHERE table_name='assets')",
+                &[],
+            )
+            .map_err(to_data_error)?
+            .get(0);
+        if has_assets {
+            let duplicates = self.duplicate_asset_identifiers().map_err(to_data_error)?;
+            if let Some((id, _)) = duplicates.first() {
+                let id = *id;
+                let conflicts: Vec<String> =
+                    duplicates.into_iter().map(|(_, conflict)| conflict).collect();
+                return Err(DataError::DuplicateAsset(id, conflicts.join("; ")));
+            }
+        }
+        let has_quotes_without_key: bool = self
+            .conn
+            .get_mut()
+            .query_one(
+                "SELECT EXISTS (SELECT 1 FROM information_schema.tables
+                WHERE table_name='quotes') AND NOT EXISTS (SELECT 1 FROM pg_indexes
+                WHERE tablename = 'quotes' AND indexname = 'quotes_ticker_time_unique')",
+                &[],
+            )
+            .map_err(to_data_error)?
+            .get(0);
+        if has_quotes_without_key {
+            let duplicates = self.duplicate_quotes().map_err(to_data_error)?;
+            if let Some(duplicate) = duplicates.first() {
+                return Err(DataError::DuplicateQuotes(format!(
+                    "{} superseded in total, the first is quote {} of ticker {} at {}",
+                    duplicates.len(),
+                    duplicate.id,
+                    duplicate.ticker_id,
+                    duplicate.time
+                )));
+            }
+        }
+        self.create_tables().map_err(to_data_error)
+    }
+
+    fn create_tables(&mut self) -> Result<(), Error> {
+        self.conn.get_mut().execute(
             "CREATE TABLE IF NOT EXISTS assets (
                 id SERIAL PRIMARY KEY,
                 name TEXT NOT NULL UNIQUE,
-                wkn TEXT UNIQUE,
-                isin TEXT UNIQUE,
-                note TEXT
+                wkn TEXT,
+                isin TEXT,
+                note TEXT,
+                reference_currency TEXT,
+                distribution_policy TEXT,
+                hedged_to TEXT,
+                asset_class TEXT,
+                sector TEXT,
+                country TEXT,
+                expected_quote_frequency TEXT
             )",
             &[],
         )?;
-        self.conn.execute(
+        self.conn.get_mut().execute(
+            "CREATE TABLE IF NOT EXISTS instrument_terms (
+                asset_id INTEGER PRIMARY KEY,
+                kind TEXT NOT NULL,
+                version INTEGER NOT NULL,
+                payload JSONB NOT NULL,
+                FOREIGN KEY(asset_id) REFERENCES assets(id)
+            )",
+            &[],
+        )?;
+        self.conn.get_mut().execute(
+            "CREATE TABLE IF NOT EXISTS currency_exposures (
+                asset_id INTEGER NOT NULL,
+                currency TEXT NOT NULL,
+                weight FLOAT8 NOT NULL,
+                PRIMARY KEY(asset_id, currency),
+                FOREIGN KEY(asset_id) REFERENCES assets(id)
+            )",
+            &[],
+        )?;
+        self.conn.get_mut().execute(
+            "CREATE TABLE IF NOT EXISTS quota_usage (
+                source TEXT NOT NULL,
+                month TEXT NOT NULL,
+                calls BIGINT NOT NULL,
+                PRIMARY KEY(source, month)
+            )",
+            &[],
+        )?;
+        self.conn.get_mut().execute(
+            "CREATE TABLE IF NOT EXISTS asset_representations (
+                asset_id INTEGER PRIMARY KEY,
+                underlying_id INTEGER NOT NULL,
+                ratio FLOAT8 NOT NULL,
+                FOREIGN KEY(asset_id) REFERENCES assets(id),
+                FOREIGN KEY(underlying_id) REFERENCES assets(id)
+            )",
+            &[],
+        )?;
+        self.conn.get_mut().execute(
             "CREATE TABLE IF NOT EXISTS transactions (
                 id SERIAL PRIMARY KEY,
                 trans_type TEXT NOT NULL,
@@ -50,12 +244,65 @@ impl PostgresDB<'_> {
                 related_trans INTEGER,
                 position FLOAT8,
                 note TEXT,
+                execution_meta JSONB,
+                recorded_at TIMESTAMP WITH TIME ZONE,
                 FOREIGN KEY(asset_id) REFERENCES assets(id),
                 FOREIGN KEY(related_trans) REFERENCES transactions(id)
             );",
             &[],
         )?;
-        self.conn.execute(
+        self.conn.get_mut().execute(
+            "CREATE TABLE IF NOT EXISTS lot_selections (
+                id SERIAL PRIMARY KEY,
+                sell_trans_id INTEGER NOT NULL,
+                buy_trans_id INTEGER NOT NULL,
+                quantity FLOAT8 NOT NULL,
+                FOREIGN KEY(sell_trans_id) REFERENCES transactions(id),
+                FOREIGN KEY(buy_trans_id) REFERENCES transactions(id)
+            );",
+            &[],
+        )?;
+        self.conn.get_mut().execute(
+            "CREATE TABLE IF NOT EXISTS fee_schedules (
+                account_id INTEGER PRIMARY KEY,
+                currency TEXT NOT NULL,
+                rate_pa FLOAT8 NOT NULL,
+                min_fee FLOAT8,
+                max_fee FLOAT8,
+                fixed_fee FLOAT8 NOT NULL,
+                billing_period TEXT NOT NULL,
+                start_date DATE NOT NULL
+            );",
+            &[],
+        )?;
+        self.conn.get_mut().execute(
+            "CREATE TABLE IF NOT EXISTS orders (
+                id SERIAL PRIMARY KEY,
+                asset_id INTEGER NOT NULL,
+                side TEXT NOT NULL,
+                quantity FLOAT8,
+                amount FLOAT8,
+                currency TEXT NOT NULL,
+                limit_price FLOAT8,
+                state TEXT NOT NULL,
+                created TIMESTAMP WITH TIME ZONE NOT NULL,
+                updated TIMESTAMP WITH TIME ZONE NOT NULL,
+                note TEXT,
+                FOREIGN KEY(asset_id) REFERENCES assets(id)
+            );",
+            &[],
+        )?;
+        self.conn.get_mut().execute(
+            "CREATE TABLE IF NOT EXISTS order_transactions (
+                order_id INTEGER NOT NULL,
+                transaction_id INTEGER NOT NULL,
+                PRIMARY KEY(order_id, transaction_id),
+                FOREIGN KEY(order_id) REFERENCES orders(id),
+                FOREIGN KEY(transaction_id) REFERENCES transactions(id)
+            );",
+            &[],
+        )?;
+        self.conn.get_mut().execute(
             "CREATE TABLE IF NOT EXISTS ticker (
                 id SERIAL PRIMARY KEY,
                 name TEXT NOT NULL,
@@ -64,28 +311,1013 @@ impl PostgresDB<'_> {
                 priority INTEGER NOT NULL,
                 currency TEXT NOT NULL,
                 factor FLOAT8 NOT NULL DEFAULT 1.0,
+                source_url TEXT,
+                usage TEXT NOT NULL DEFAULT 'both',
+                last_quote_time TIMESTAMP WITH TIME ZONE,
+                source_chain TEXT,
                 FOREIGN KEY(asset_id) REFERENCES assets(id) 
             );",
             &[],
         )?;
-        self.conn.execute(
+        self.conn.get_mut().execute(
             "CREATE TABLE IF NOT EXISTS quotes (
                 id SERIAL PRIMARY KEY,
                 ticker_id INTEGER NOT NULL,
                 price FLOAT8 NOT NULL,
                 time TIMESTAMP WITH TIME ZONE NOT NULL,
                 volume FLOAT8,
+                quality_score FLOAT8,
+                source TEXT,
+                open FLOAT8,
+                high FLOAT8,
+                low FLOAT8,
+                bid FLOAT8,
+                ask FLOAT8,
                 FOREIGN KEY(ticker_id) REFERENCES ticker(id) );",
             &[],
         )?;
-        self.conn.execute(
+        self.conn.get_mut().execute(
             "CREATE TABLE IF NOT EXISTS rounding_digits (
                 id SERIAL PRIMARY KEY,
                 currency TEXT NOT NULL UNIQUE,
                 digits INT NOT NULL);",
             &[],
         )?;
+        self.conn.get_mut().execute(
+            "CREATE TABLE IF NOT EXISTS report_definitions (
+                name TEXT PRIMARY KEY,
+                version INTEGER NOT NULL,
+                payload JSONB NOT NULL
+            );",
+            &[],
+        )?;
+        self.conn.get_mut().execute(
+            "CREATE TABLE IF NOT EXISTS job_runs (
+                id SERIAL PRIMARY KEY,
+                job TEXT NOT NULL,
+                started TIMESTAMP WITH TIME ZONE NOT NULL,
+                finished TIMESTAMP WITH TIME ZONE NOT NULL,
+                success BOOLEAN NOT NULL,
+                message TEXT
+            );",
+            &[],
+        )?;
+        self.conn.get_mut().execute(
+            "CREATE TABLE IF NOT EXISTS rebalancing_decisions (
+                id SERIAL PRIMARY KEY,
+                proposal JSONB NOT NULL
+            );",
+            &[],
+        )?;
+        self.conn.get_mut().execute(
+            "CREATE TABLE IF NOT EXISTS rebalancing_transactions (
+                decision_id INTEGER NOT NULL,
+                transaction_id INTEGER NOT NULL,
+                PRIMARY KEY(decision_id, transaction_id),
+                FOREIGN KEY(decision_id) REFERENCES rebalancing_decisions(id),
+                FOREIGN KEY(transaction_id) REFERENCES transactions(id)
+            );",
+            &[],
+        )?;
+        self.migrate()
+    }
 
+    /// Update tables of databases created with previous versions to the current layout
+    pub fn migrate(&mut self) -> Result<(), Error> {
+        self.conn
+            .get_mut()
+            .execute("ALTER TABLE ticker ADD COLUMN IF NOT EXISTS source_url TEXT", &[])?;
+        self.conn.get_mut().execute(
+            "ALTER TABLE ticker ADD COLUMN IF NOT EXISTS usage TEXT NOT NULL DEFAULT 'both'",
+            &[],
+        )?;
+        self.conn.get_mut().execute(
+            "ALTER TABLE assets ADD COLUMN IF NOT EXISTS reference_currency TEXT",
+            &[],
+        )?;
+        self.conn.get_mut().execute(
+            "ALTER TABLE assets ADD COLUMN IF NOT EXISTS hedged_to TEXT",
+            &[],
+        )?;
+        for column in ["asset_class", "sector", "country", "expected_quote_frequency"] {
+            self.conn.get_mut().execute(
+                format!("ALTER TABLE assets ADD COLUMN IF NOT EXISTS {} TEXT", column).as_str(),
+                &[],
+            )?;
+        }
+        self.conn.get_mut().execute(
+            "ALTER TABLE transactions ADD COLUMN IF NOT EXISTS execution_meta JSONB",
+            &[],
+        )?;
+        self.conn.get_mut().execute(
+            "ALTER TABLE transactions ADD COLUMN IF NOT EXISTS recorded_at TIMESTAMP WITH TIME ZONE",
+            &[],
+        )?;
+        // the entry time of legacy transactions is unknown, assume the cash date
+        self.conn.get_mut().execute(
+            "UPDATE transactions SET recorded_at = cash_date::timestamp AT TIME ZONE 'UTC'
+            WHERE recorded_at IS NULL",
+            &[],
+        )?;
+        self.conn.get_mut().execute(
+            "ALTER TABLE quotes ADD COLUMN IF NOT EXISTS quality_score FLOAT8",
+            &[],
+        )?;
+        self.conn.get_mut().execute(
+            "ALTER TABLE quotes ADD COLUMN IF NOT EXISTS source TEXT",
+            &[],
+        )?;
+        for column in &["open", "high", "low", "bid", "ask"] {
+            self.conn.get_mut().execute(
+                format!(
+                    "ALTER TABLE quotes ADD COLUMN IF NOT EXISTS {} FLOAT8",
+                    column
+                )
+                .as_str(),
+                &[],
+            )?;
+        }
+        self.conn.get_mut().execute(
+            "ALTER TABLE ticker ADD COLUMN IF NOT EXISTS source_chain TEXT",
+            &[],
+        )?;
+        let has_distribution_policy: bool = self
+            .conn
+            .get_mut()
+            .query_one(
+                "SELECT EXISTS (SELECT 1 FROM information_schema.columns
+                WHERE table_name='assets' AND column_name='distribution_policy')",
+                &[],
+            )?
+            .get(0);
+        if !has_distribution_policy {
+            self.conn.get_mut().execute(
+                "ALTER TABLE assets ADD COLUMN distribution_policy TEXT",
+                &[],
+            )?;
+            self.conn.get_mut().execute(
+                "UPDATE assets SET distribution_policy = 'unknown'",
+                &[],
+            )?;
+        }
+        let has_last_quote_time: bool = self
+            .conn
+            .get_mut()
+            .query_one(
+                "SELECT EXISTS (SELECT 1 FROM information_schema.columns
+                WHERE table_name='ticker' AND column_name='last_quote_time')",
+                &[],
+            )?
+            .get(0);
+        if !has_last_quote_time {
+            self.conn.get_mut().execute(
+                "ALTER TABLE ticker ADD COLUMN last_quote_time TIMESTAMP WITH TIME ZONE",
+                &[],
+            )?;
+            self.conn.get_mut().execute(
+                "UPDATE ticker SET last_quote_time =
+                (SELECT MAX(time) FROM quotes WHERE ticker_id = ticker.id)",
+                &[],
+            )?;
+        }
+        let has_option_terms: bool = self
+            .conn
+            .get_mut()
+            .query_one(
+                "SELECT EXISTS (SELECT 1 FROM information_schema.tables
+                WHERE table_name='option_terms')",
+                &[],
+            )?
+            .get(0);
+        if has_option_terms {
+            // move option terms to the generic instrument terms in the layout of `OptionTerms`
+            self.conn.get_mut().execute(
+                "INSERT INTO instrument_terms (asset_id, kind, version, payload)
+                SELECT asset_id, 'option', 1, jsonb_build_object(
+                    'asset_id', asset_id,
+                    'underlying_id', underlying_id,
+                    'strike', strike,
+                    'expiry', to_char(expiry, 'YYYY-MM-DD'),
+                    'option_type', initcap(option_type),
+                    'contract_size', contract_size)
+                FROM option_terms
+                ON CONFLICT DO NOTHING",
+                &[],
+            )?;
+            self.conn.get_mut().execute("DROP TABLE option_terms", &[])?;
+        }
+        // legacy quotes tables with several quotes of the same ticker and time are rejected by
+        // `init` beforehand, see `collapse_duplicate_quotes`
+        self.add_quotes_key()?;
+        // legacy assets tables with duplicate identifiers are rejected by `init` beforehand
+        for column in ["isin", "wkn"] {
+            self.conn.get_mut().execute(
+                format!(
+                    "CREATE UNIQUE INDEX IF NOT EXISTS assets_{column}_unique
+                    ON assets ({column}) WHERE {column} IS NOT NULL",
+                    column = column
+                )
+                .as_str(),
+                &[],
+            )?;
+        }
+        // legacy settings tables with duplicate keys are left as they are until repaired,
+        // see `collapse_duplicate_settings`
+        if self.duplicate_settings()?.is_empty() {
+            self.add_settings_keys()?;
+        }
+        Ok(())
+    }
+
+    /// Groups of assets sharing an ISIN or WKN, each given by the lowest asset id of the group
+    /// and a description like `ISIN 'DE000BASF111' of assets 1, 4`. Such duplicates only exist
+    /// in databases created without unique keys on these identifiers.
+    fn duplicate_asset_identifiers(&mut self) -> Result<Vec<(usize, String)>, Error> {
+        let mut duplicates = Vec::new();
+        for column in ["isin", "wkn"] {
+            let rows = self.conn.get_mut().query(
+                format!(
+                    "SELECT {column}, array_agg(id ORDER BY id) FROM assets
+                    WHERE {column} IS NOT NULL
+                    GROUP BY {column} HAVING COUNT(*) > 1 ORDER BY MIN(id)",
+                    column = column
+                )
+                .as_str(),
+                &[],
+            )?;
+            for row in rows {
+                let identifier: String = row.get(0);
+                let ids: Vec<i32> = row.get(1);
+                let id_list: Vec<String> = ids.iter().map(|id| id.to_string()).collect();
+                duplicates.push((
+                    ids[0] as usize,
+                    format!(
+                        "{} '{}' of assets {}",
+                        column.to_uppercase(),
+                        identifier,
+                        id_list.join(", ")
+                    ),
+                ));
+            }
+        }
+        Ok(duplicates)
+    }
+
+    /// Quotes superseded by a newer quote of the same ticker and time
+    fn duplicate_quotes(&mut self) -> Result<Vec<DuplicateQuote>, Error> {
+        let rows = self.conn.get_mut().query(
+            "SELECT id, ticker_id, time FROM quotes q WHERE EXISTS
+            (SELECT 1 FROM quotes n WHERE n.ticker_id = q.ticker_id AND n.time = q.time
+            AND n.id > q.id)
+            ORDER BY id",
+            &[],
+        )?;
+        let mut duplicates = Vec::new();
+        for row in rows {
+            let id: i32 = row.get(0);
+            let ticker_id: i32 = row.get(1);
+            duplicates.push(DuplicateQuote {
+                ticker_id: ticker_id as usize,
+                time: row.get(2),
+                id: id as usize,
+            });
+        }
+        Ok(duplicates)
+    }
+
+    /// Add the unique key on ticker and time of the quotes table
+    fn add_quotes_key(&mut self) -> Result<(), Error> {
+        self.conn.get_mut().execute(
+            "CREATE UNIQUE INDEX IF NOT EXISTS quotes_ticker_time_unique ON quotes (ticker_id, time)",
+            &[],
+        )?;
+        Ok(())
+    }
+
+    /// Delete the quotes superseded by a newer quote of the same ticker and time and add the
+    /// unique key
+    fn delete_duplicate_quotes(&mut self) -> Result<Vec<DuplicateQuote>, Error> {
+        let duplicates = self.duplicate_quotes()?;
+        for duplicate in &duplicates {
+            self.conn.get_mut().execute(
+                "DELETE FROM quotes WHERE id=$1",
+                &[&(duplicate.id as i32)],
+            )?;
+        }
+        self.add_quotes_key()?;
+        Ok(duplicates)
+    }
+
+    /// Rows of the settings tables superseded by a newer row with the same key
+    fn duplicate_settings(&mut self) -> Result<Vec<DuplicateSetting>, Error> {
+        let mut duplicates = Vec::new();
+        for (table, key) in SETTINGS_TABLES {
+            let rows = self.conn.get_mut().query(
+                format!(
+                    "SELECT id, {key} FROM {table} r WHERE EXISTS
+                    (SELECT 1 FROM {table} n WHERE n.{key} = r.{key} AND n.id > r.id)
+                    ORDER BY id",
+                    table = table,
+                    key = key
+                )
+                .as_str(),
+                &[],
+            )?;
+            for row in rows {
+                let id: i32 = row.get(0);
+                duplicates.push(DuplicateSetting {
+                    table: table.to_string(),
+                    key: row.get(1),
+                    id: id as usize,
+                });
+            }
+        }
+        Ok(duplicates)
+    }
+
+    /// Add unique indices on the keys of the settings tables, which are missing in tables
+    /// created by older versions
+    fn add_settings_keys(&mut self) -> Result<(), Error> {
+        for (table, key) in SETTINGS_TABLES {
+            self.conn.get_mut().execute(
+                format!(
+                    "CREATE UNIQUE INDEX IF NOT EXISTS {table}_{key} ON {table} ({key})",
+                    table = table,
+                    key = key
+                )
+                .as_str(),
+                &[],
+            )?;
+        }
         Ok(())
     }
+
+    /// Delete the duplicates of the settings tables and add the unique keys
+    fn delete_duplicate_settings(&mut self) -> Result<Vec<DuplicateSetting>, Error> {
+        let duplicates = self.duplicate_settings()?;
+        for duplicate in &duplicates {
+            self.conn.get_mut().execute(
+                format!("DELETE FROM {} WHERE id=$1", duplicate.table).as_str(),
+                &[&(duplicate.id as i32)],
+            )?;
+        }
+        self.add_settings_keys()?;
+        Ok(duplicates)
+    }
+
+    /// Currency codes of the currency columns not stored in normal form
+    fn unnormalized_currencies(&mut self) -> Result<Vec<UnnormalizedCurrency>, Error> {
+        let mut unnormalized = Vec::new();
+        for (table, column) in CURRENCY_COLUMNS {
+            let rows = self.conn.get_mut().query(
+                format!(
+                    "SELECT id, {column} FROM {table} ORDER BY id",
+                    table = table,
+                    column = column
+                )
+                .as_str(),
+                &[],
+            )?;
+            for row in rows {
+                let id: i32 = row.get(0);
+                let currency: String = row.get(1);
+                if normalize_currency_code(&currency) != currency {
+                    unnormalized.push(UnnormalizedCurrency {
+                        table: table.to_string(),
+                        id: id as usize,
+                        currency,
+                    });
+                }
+            }
+        }
+        Ok(unnormalized)
+    }
+
+    /// Rewrite the currency codes not stored in normal form
+    fn rewrite_unnormalized_currencies(&mut self) -> Result<Vec<UnnormalizedCurrency>, Error> {
+        let unnormalized = self.unnormalized_currencies()?;
+        for (table, column) in CURRENCY_COLUMNS {
+            for row in unnormalized.iter().filter(|row| row.table == *table) {
+                self.conn.get_mut().execute(
+                    format!("UPDATE {} SET {}=$2 WHERE id=$1", table, column).as_str(),
+                    &[&(row.id as i32), &normalize_currency_code(&row.currency)],
+                )?;
+            }
+        }
+        Ok(unnormalized)
+    }
+
+    /// Delete quotes and transactions including the rows linked to the transactions.
+    /// Transactions are deleted latest first, since they may only refer to earlier ones.
+    fn delete_history_rows(
+        &mut self,
+        quote_ids: &[usize],
+        transaction_ids: &[usize],
+    ) -> Result<(), DataError> {
+        for id in quote_ids {
+            self.delete_quote(*id)?;
+        }
+        let mut transaction_ids = transaction_ids.to_vec();
+        transaction_ids.sort_unstable_by(|a, b| b.cmp(a));
+        for id in transaction_ids {
+            for (table, column) in TRANSACTION_LINKS {
+                self.conn
+                    .get_mut()
+                    .execute(
+                        format!("DELETE FROM {} WHERE {}=$1", table, column).as_str(),
+                        &[&(id as i32)],
+                    )
+                    .map_err(|e| DataError::DeleteFailed(e.to_string()))?;
+            }
+            self.delete_transaction(id)?;
+        }
+        Ok(())
+    }
+
+    /// Run the query with `EXPLAIN ANALYZE` and return the resulting query plan, one line per plan row.
+    /// Be aware that the query is actually executed, i.e. data modifying statements take effect.
+    #[cfg(feature = "debug_queries")]
+    pub fn explain_query(
+        &mut self,
+        sql: &str,
+        params: &[&(dyn postgres::types::ToSql + Sync)],
+    ) -> Result<String, DataError> {
+        let rows = self
+            .conn
+            .get_mut()
+            .query(format!("EXPLAIN ANALYZE {}", sql).as_str(), params)
+            .map_err(|e| DataError::DataAccessFailure(e.to_string()))?;
+        let plan: Vec<String> = rows.iter().map(|row| row.get(0)).collect();
+        Ok(plan.join("\n"))
+    }
+}
+
+impl SchemaHandler for PostgresDB<'_> {
+    fn init_schema(&mut self) -> Result<(), DataError> {
+        self.init()
+    }
+
+    fn find_duplicate_settings(&mut self) -> Result<Vec<DuplicateSetting>, DataError> {
+        self.duplicate_settings()
+            .map_err(|e| DataError::DataAccessFailure(e.to_string()))
+    }
+
+    fn collapse_duplicate_settings(&mut self) -> Result<Vec<DuplicateSetting>, DataError> {
+        self.conn
+            .get_mut()
+            .batch_execute("BEGIN")
+            .map_err(|e| DataError::DataAccessFailure(e.to_string()))?;
+        match self.delete_duplicate_settings() {
+            Ok(duplicates) => {
+                self.conn
+                    .get_mut()
+                    .batch_execute("COMMIT")
+                    .map_err(|e| DataError::DataAccessFailure(e.to_string()))?;
+                Ok(duplicates)
+            }
+            Err(err) => {
+                self.conn
+                    .get_mut()
+                    .batch_execute("ROLLBACK")
+                    .map_err(|e| DataError::DataAccessFailure(e.to_string()))?;
+                Err(DataError::DataAccessFailure(err.to_string()))
+            }
+        }
+    }
+
+    fn find_duplicate_quotes(&mut self) -> Result<Vec<DuplicateQuote>, DataError> {
+        self.duplicate_quotes()
+            .map_err(|e| DataError::DataAccessFailure(e.to_string()))
+    }
+
+    fn collapse_duplicate_quotes(&mut self) -> Result<Vec<DuplicateQuote>, DataError> {
+        self.conn
+            .get_mut()
+            .batch_execute("BEGIN")
+            .map_err(|e| DataError::DataAccessFailure(e.to_string()))?;
+        match self.delete_duplicate_quotes() {
+            Ok(duplicates) => {
+                self.conn
+                    .get_mut()
+                    .batch_execute("COMMIT")
+                    .map_err(|e| DataError::DataAccessFailure(e.to_string()))?;
+                Ok(duplicates)
+            }
+            Err(err) => {
+                self.conn
+                    .get_mut()
+                    .batch_execute("ROLLBACK")
+                    .map_err(|e| DataError::DataAccessFailure(e.to_string()))?;
+                Err(DataError::DataAccessFailure(err.to_string()))
+            }
+        }
+    }
+
+    fn find_unnormalized_currencies(&mut self) -> Result<Vec<UnnormalizedCurrency>, DataError> {
+        self.unnormalized_currencies()
+            .map_err(|e| DataError::DataAccessFailure(e.to_string()))
+    }
+
+    fn normalize_currencies(&mut self) -> Result<Vec<UnnormalizedCurrency>, DataError> {
+        self.conn
+            .get_mut()
+            .batch_execute("BEGIN")
+            .map_err(|e| DataError::DataAccessFailure(e.to_string()))?;
+        match self.rewrite_unnormalized_currencies() {
+            Ok(unnormalized) => {
+                self.conn
+                    .get_mut()
+                    .batch_execute("COMMIT")
+                    .map_err(|e| DataError::DataAccessFailure(e.to_string()))?;
+                Ok(unnormalized)
+            }
+            Err(err) => {
+                self.conn
+                    .get_mut()
+                    .batch_execute("ROLLBACK")
+                    .map_err(|e| DataError::DataAccessFailure(e.to_string()))?;
+                Err(DataError::DataAccessFailure(err.to_string()))
+            }
+        }
+    }
+}
+
+impl DataHandler for PostgresDB<'_> {
+    fn delete_history(
+        &mut self,
+        quote_ids: &[usize],
+        transaction_ids: &[usize],
+    ) -> Result<(), DataError> {
+        self.conn
+            .get_mut()
+            .batch_execute("BEGIN")
+            .map_err(|e| DataError::DataAccessFailure(e.to_string()))?;
+        match self.delete_history_rows(quote_ids, transaction_ids) {
+            Ok(()) => {
+                self.conn
+                    .get_mut()
+                    .batch_execute("COMMIT")
+                    .map_err(|e| DataError::DataAccessFailure(e.to_string()))?;
+                Ok(())
+            }
+            Err(err) => {
+                self.conn
+                    .get_mut()
+                    .batch_execute("ROLLBACK")
+                    .map_err(|e| DataError::DataAccessFailure(e.to_string()))?;
+                Err(err)
+            }
+        }
+    }
+}
+
+#[cfg(all(test, feature = "debug_queries"))]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    use chrono::{TimeZone, Utc};
+    use finql_data::{Asset, AssetHandler, Currency, Quote, QuoteHandler, Ticker, TickerUsage};
+
+    /// Requires a test database, e.g. run with
+    /// `FINQL_POSTGRES_TEST_URL="host=localhost user=postgres dbname=finql_test" cargo test --features debug_queries -- --ignored`
+    #[test]
+    #[ignore]
+    fn explain_last_quote_query() {
+        let url = std::env::var("FINQL_POSTGRES_TEST_URL")
+            .expect("FINQL_POSTGRES_TEST_URL must point to a test database");
+        let mut conn = postgres::Client::connect(&url, postgres::NoTls).unwrap();
+        let mut db = PostgresDB::new(&mut conn);
+        db.clean().unwrap();
+        let asset_id = db
+            .insert_asset(&Asset::new(None, "BASF AG", None, None, None))
+            .unwrap();
+        let ticker_id = db
+            .insert_ticker(&Ticker {
+                id: None,
+                name: "BAS.DE".to_string(),
+                asset: asset_id,
+                source: "manual".to_string(),
+                priority: 1,
+                currency: Currency::from_str("EUR").unwrap(),
+                factor: 1.0,
+                source_url: None,
+                usage: TickerUsage::Both,
+                last_quote_time: None,
+                source_chain: Vec::new(),
+            })
+            .unwrap();
+        db.insert_quote(&Quote {
+            id: None,
+            ticker: ticker_id,
+            price: 67.35,
+            time: Utc.ymd(2020, 1, 15).and_hms(18, 0, 0),
+            volume: None,
+            quality_score: None,
+            source: None,
+            open: None,
+            high: None,
+            low: None,
+            bid: None,
+            ask: None,
+        })
+        .unwrap();
+
+        let time = Utc.ymd(2020, 2, 1).and_hms(0, 0, 0);
+        let plan = db
+            .explain_query(
+                quote_handler::LAST_QUOTE_BEFORE_QUERY,
+                &[&"BASF AG", &time, &"valuation"],
+            )
+            .unwrap();
+        assert!(plan.lines().count() > 1);
+        assert!(plan.contains("Seq Scan") || plan.contains("Index Scan"));
+    }
+}
+
+#[cfg(test)]
+mod conformance_tests {
+    use super::*;
+    use finql_data::{conformance, Asset, AssetHandler, Quote, QuoteInsertion};
+
+    fn with_new_db(check: impl FnOnce(&mut PostgresDB)) {
+        let url = std::env::var("FINQL_POSTGRES_TEST_URL")
+            .expect("FINQL_POSTGRES_TEST_URL must point to a test database");
+        let mut conn = postgres::Client::connect(&url, postgres::NoTls).unwrap();
+        let mut db = PostgresDB::new(&mut conn);
+        db.clean().unwrap();
+        check(&mut db);
+    }
+
+    /// Requires a test database, e.g. run with
+    /// `FINQL_POSTGRES_TEST_URL="host=localhost user=postgres dbname=finql_test" cargo test -- --ignored`
+    #[test]
+    #[ignore]
+    fn updates_change_all_fields() {
+        with_new_db(|db| conformance::check_asset_update(db));
+        with_new_db(|db| conformance::check_assets_batch_update(db));
+        with_new_db(|db| conformance::check_asset_search(db));
+        with_new_db(|db| conformance::check_asset_by_identifier(db));
+        with_new_db(|db| conformance::check_asset_duplicates(db));
+        with_new_db(|db| conformance::check_asset_representation_update(db));
+        with_new_db(|db| conformance::check_ticker_update(db));
+        with_new_db(|db| conformance::check_insert_if_new_ticker(db));
+        with_new_db(|db| conformance::check_quote_update(db));
+        with_new_db(|db| conformance::check_quotes_in_range(db));
+        with_new_db(|db| conformance::check_quotes_for_source(db));
+        with_new_db(|db| conformance::check_last_quote_before(db));
+        with_new_db(|db| conformance::check_latest_quotes_for_tickers(db));
+        with_new_db(|db| conformance::check_insert_quotes(db));
+        with_new_db(|db| conformance::check_delete_quotes(db));
+        with_new_db(|db| conformance::check_insert_quote_if_new(db));
+        with_new_db(|db| conformance::check_transaction_update(db));
+        with_new_db(|db| conformance::check_delete_history(db));
+        with_new_db(|db| conformance::check_order_update(db));
+        with_new_db(|db| conformance::check_fee_schedule_update(db));
+        with_new_db(|db| conformance::check_instrument_record_update(db));
+        with_new_db(|db| conformance::check_report_definition_update(db));
+        with_new_db(|db| conformance::check_rebalancing_decision(db));
+        with_new_db(|db| conformance::check_rounding_digits_update(db));
+        with_new_db(|db| conformance::check_quota_usage(db));
+    }
+
+    /// Requires a test database, see `updates_change_all_fields`
+    #[test]
+    #[ignore]
+    fn shared_updates_change_all_fields() {
+        let with_new_shared_db = |check: &dyn Fn(&mut SharedPostgresDB)| {
+            let url = std::env::var("FINQL_POSTGRES_TEST_URL")
+                .expect("FINQL_POSTGRES_TEST_URL must point to a test database");
+            let mut client = postgres::Client::connect(&url, postgres::NoTls).unwrap();
+            PostgresDB::new(&mut client).clean().unwrap();
+            check(&mut SharedPostgresDB::new(OwnedPostgresDB { client }));
+        };
+        with_new_shared_db(&|db| conformance::check_asset_update(db));
+        with_new_shared_db(&|db| conformance::check_assets_batch_update(db));
+        with_new_shared_db(&|db| conformance::check_asset_search(db));
+        with_new_shared_db(&|db| conformance::check_asset_by_identifier(db));
+        with_new_shared_db(&|db| conformance::check_asset_duplicates(db));
+        with_new_shared_db(&|db| conformance::check_asset_representation_update(db));
+        with_new_shared_db(&|db| conformance::check_ticker_update(db));
+        with_new_shared_db(&|db| conformance::check_insert_if_new_ticker(db));
+        with_new_shared_db(&|db| conformance::check_quote_update(db));
+        with_new_shared_db(&|db| conformance::check_quotes_in_range(db));
+        with_new_shared_db(&|db| conformance::check_quotes_for_source(db));
+        with_new_shared_db(&|db| conformance::check_last_quote_before(db));
+        with_new_shared_db(&|db| conformance::check_latest_quotes_for_tickers(db));
+        with_new_shared_db(&|db| conformance::check_insert_quotes(db));
+        with_new_shared_db(&|db| conformance::check_delete_quotes(db));
+        with_new_shared_db(&|db| conformance::check_insert_quote_if_new(db));
+        with_new_shared_db(&|db| conformance::check_instrument_record_update(db));
+        with_new_shared_db(&|db| conformance::check_rounding_digits_update(db));
+        with_new_shared_db(&|db| conformance::check_quota_usage(db));
+    }
+
+    /// Requires a test database, see `updates_change_all_fields`
+    #[test]
+    #[ignore]
+    fn collapse_duplicate_settings() {
+        with_new_db(|db| {
+            // rounding digits table of a legacy database without unique key
+            db.conn
+                .get_mut()
+                .batch_execute(
+                    "DROP TABLE rounding_digits;
+                    CREATE TABLE rounding_digits (
+                        id SERIAL PRIMARY KEY,
+                        currency TEXT NOT NULL,
+                        digits INT NOT NULL);
+                    INSERT INTO rounding_digits (currency, digits) VALUES
+                        ('JPY', 2), ('XAU', 4), ('JPY', 1), ('JPY', 0);",
+                )
+                .unwrap();
+            db.init().unwrap();
+            let duplicates = db.find_duplicate_settings().unwrap();
+            let ids: Vec<usize> = duplicates.iter().map(|d| d.id).collect();
+            assert_eq!(ids, vec![1, 3]);
+            assert!(duplicates.iter().all(|d| d.key == "JPY"));
+
+            assert_eq!(db.collapse_duplicate_settings().unwrap(), duplicates);
+            assert!(db.find_duplicate_settings().unwrap().is_empty());
+            let rows = db
+                .conn
+                .get_mut()
+                .query("SELECT id FROM rounding_digits ORDER BY id", &[])
+                .unwrap();
+            let ids: Vec<i32> = rows.iter().map(|row| row.get(0)).collect();
+            assert_eq!(ids, vec![2, 4]);
+            // the unique key is in place now
+            assert!(db
+                .conn
+                .get_mut()
+                .execute(
+                    "INSERT INTO rounding_digits (currency, digits) VALUES ('XAU', 3)",
+                    &[]
+                )
+                .is_err());
+        });
+    }
+
+    /// Requires a test database, see `updates_change_all_fields`
+    #[test]
+    #[ignore]
+    fn report_legacy_duplicate_asset_identifiers() {
+        with_new_db(|db| {
+            // assets of a legacy database without unique keys on ISIN and WKN
+            db.conn
+                .get_mut()
+                .batch_execute(
+                    "DROP INDEX assets_isin_unique;
+                    DROP INDEX assets_wkn_unique;
+                    INSERT INTO assets (name, wkn, isin) VALUES
+                        ('BASF AG', 'BASF11', 'DE000BASF111'), ('BASF SE', NULL, 'DE000BASF111'),
+                        ('Siemens AG', '723610', NULL), ('Siemens', '723610', NULL),
+                        ('Deutsche Bank AG', NULL, NULL), ('Deutsche Bank', NULL, NULL);",
+                )
+                .unwrap();
+            match db.init() {
+                Err(DataError::DuplicateAsset(id, conflicts)) => {
+                    assert_eq!(id, 1);
+                    assert_eq!(
+                        conflicts,
+                        "ISIN 'DE000BASF111' of assets 1, 2; WKN '723610' of assets 3, 4"
+                    );
+                }
+                result => panic!("expected duplicate assets, got {:?}", result),
+            }
+
+            db.conn
+                .get_mut()
+                .execute("DELETE FROM assets WHERE id IN (2, 4)", &[])
+                .unwrap();
+            db.init().unwrap();
+            assert!(db
+                .conn
+                .get_mut()
+                .execute(
+                    "INSERT INTO assets (name, isin) VALUES ('BASF', 'DE000BASF111')",
+                    &[]
+                )
+                .is_err());
+        });
+    }
+
+    /// Requires a test database, see `updates_change_all_fields`
+    #[test]
+    #[ignore]
+    fn collapse_legacy_duplicate_quotes() {
+        with_new_db(|db| {
+            let asset_id = db
+                .insert_asset(&Asset::new(None, "BASF AG", None, None, None))
+                .unwrap();
+            // quotes of a legacy database without unique key, with the same quote inserted twice
+            db.conn
+                .get_mut()
+                .batch_execute(&format!(
+                    "INSERT INTO ticker (name, asset_id, source, priority, currency, factor)
+                        VALUES ('BAS.DE', {}, 'manual', 1, 'EUR', 1.0);
+                    DROP INDEX quotes_ticker_time_unique;
+                    INSERT INTO quotes (ticker_id, price, time) VALUES
+                        (1, 61.0, '2021-03-02 17:30:00+00'), (1, 61.5, '2021-03-02 17:30:00+00'),
+                        (1, 62.0, '2021-03-03 17:30:00+00');",
+                    asset_id
+                ))
+                .unwrap();
+            // the duplicates are kept until explicitly removed
+            match db.init() {
+                Err(DataError::DuplicateQuotes(err)) => assert_eq!(
+                    err,
+                    "1 superseded in total, the first is quote 1 of ticker 1 at 2021-03-02 17:30:00 UTC"
+                ),
+                result => panic!("expected duplicate quotes, got {:?}", result),
+            }
+            let duplicates = db.find_duplicate_quotes().unwrap();
+            assert_eq!(duplicates.len(), 1);
+            assert_eq!((duplicates[0].id, duplicates[0].ticker_id), (1, 1));
+            assert_eq!(db.collapse_duplicate_quotes().unwrap(), duplicates);
+            assert!(db.find_duplicate_quotes().unwrap().is_empty());
+            db.init().unwrap();
+            let quotes = db.get_all_quotes_for_ticker(1).unwrap();
+            let prices: Vec<(usize, f64)> = quotes
+                .iter()
+                .map(|quote| (quote.id.unwrap(), quote.price))
+                .collect();
+            assert_eq!(prices, vec![(2, 61.5), (3, 62.0)]);
+
+            // plain inserts of quotes with the same ticker and time fail now
+            let quote = Quote {
+                id: None,
+                ..quotes[0].clone()
+            };
+            assert!(db.insert_quote(&quote).is_err());
+            assert_eq!(
+                db.insert_quote_if_new(&quote).unwrap(),
+                QuoteInsertion::Existing(2)
+            );
+        });
+    }
+
+    /// Requires a test database, see `updates_change_all_fields`
+    #[test]
+    #[ignore]
+    fn normalize_stored_currencies() {
+        use std::str::FromStr;
+
+        use chrono::NaiveDate;
+        use finql_data::{
+            Asset, AssetHandler, CashFlow, Currency, QuoteHandler, Ticker, TickerUsage,
+            Transaction, TransactionHandler, TransactionType,
+        };
+
+        with_new_db(|db| {
+            let eur = Currency::from_str("EUR").unwrap();
+            let asset = db
+                .insert_asset(&Asset::new(None, "BASF", None, None, None))
+                .unwrap();
+            let ticker_id = db
+                .insert_ticker(&Ticker {
+                    id: None,
+                    name: "BAS.DE".to_string(),
+                    asset,
+                    source: "manual".to_string(),
+                    priority: 1,
+                    currency: eur,
+                    factor: 1.0,
+                    source_url: None,
+                    usage: TickerUsage::Both,
+                    last_quote_time: None,
+                    source_chain: Vec::new(),
+                })
+                .unwrap();
+            let transaction_id = db
+                .insert_transaction(&Transaction {
+                    id: None,
+                    transaction_type: TransactionType::Cash,
+                    cash_flow: CashFlow::new(1000.0, eur, NaiveDate::from_ymd(2021, 3, 1)),
+                    note: None,
+                    execution_meta: None,
+                    recorded_at: None,
+                })
+                .unwrap();
+            // as written by early import scripts
+            db.conn
+                .get_mut()
+                .batch_execute(
+                    "UPDATE ticker SET currency='eur ';
+                    UPDATE transactions SET cash_currency=' Eur';",
+                )
+                .unwrap();
+
+            assert_eq!(db.get_ticker_by_id(ticker_id).unwrap().currency, eur);
+            let transaction = db.get_transaction_by_id(transaction_id).unwrap();
+            assert_eq!(transaction.cash_flow.amount.currency, eur);
+            let unnormalized = db.find_unnormalized_currencies().unwrap();
+            let rows: Vec<(&str, usize, &str)> = unnormalized
+                .iter()
+                .map(|u| (u.table.as_str(), u.id, u.currency.as_str()))
+                .collect();
+            assert_eq!(
+                rows,
+                vec![
+                    ("ticker", ticker_id, "eur "),
+                    ("transactions", transaction_id, " Eur")
+                ]
+            );
+
+            assert_eq!(db.normalize_currencies().unwrap(), unnormalized);
+            assert!(db.find_unnormalized_currencies().unwrap().is_empty());
+            let row = db
+                .conn
+                .get_mut()
+                .query_one(
+                    "SELECT t.currency, r.cash_currency FROM ticker t, transactions r",
+                    &[],
+                )
+                .unwrap();
+            let currencies: (String, String) = (row.get(0), row.get(1));
+            assert_eq!(currencies, ("EUR".to_string(), "EUR".to_string()));
+        });
+    }
+
+    /// Requires a test database, see `updates_change_all_fields`
+    #[test]
+    #[ignore]
+    fn concurrent_readers_with_own_clients() {
+        use std::sync::{Arc, RwLock};
+        use std::thread;
+
+        use chrono::{Duration, TimeZone, Utc};
+        use finql_data::{Currency, QuoteReader, Ticker, TickerUsage};
+
+        let url = std::env::var("FINQL_POSTGRES_TEST_URL")
+            .expect("FINQL_POSTGRES_TEST_URL must point to a test database");
+        let connect = || postgres::Client::connect(&url, postgres::NoTls).unwrap();
+        let mut client = connect();
+        let ticker_id = {
+            let mut db = PostgresDB::new(&mut client);
+            db.clean().unwrap();
+            let asset_id = db
+                .insert_asset(&Asset::new(None, "BASF AG", None, None, None))
+                .unwrap();
+            db.insert_ticker(&Ticker {
+                id: None,
+                name: "BAS.DE".to_string(),
+                asset: asset_id,
+                source: "manual".to_string(),
+                priority: 1,
+                currency: "EUR".parse::<Currency>().unwrap(),
+                factor: 1.0,
+                source_url: None,
+                usage: TickerUsage::Both,
+                last_quote_time: None,
+                source_chain: Vec::new(),
+            })
+            .unwrap()
+        };
+        let readers = (0..3).map(|_| OwnedPostgresDB { client: connect() }).collect();
+        let db = Arc::new(RwLock::new(SharedPostgresDB::with_readers(
+            OwnedPostgresDB { client },
+            readers,
+        )));
+
+        let num_quotes = 20;
+        let reader_threads: Vec<_> = (0..4)
+            .map(|_| {
+                let db = Arc::clone(&db);
+                thread::spawn(move || {
+                    let mut seen = 0;
+                    while seen < num_quotes {
+                        let db = db.read().unwrap();
+                        let quotes = db.get_all_quotes_for_ticker(ticker_id).unwrap();
+                        assert!(quotes.len() >= seen);
+                        seen = quotes.len();
+                    }
+                })
+            })
+            .collect();
+        for i in 0..num_quotes {
+            db.write()
+                .unwrap()
+                .insert_quote(&Quote {
+                    id: None,
+                    ticker: ticker_id,
+                    price: 60.0 + i as f64,
+                    time: Utc.ymd(2021, 3, 1).and_hms(17, 30, 0) + Duration::days(i as i64),
+                    volume: None,
+                    quality_score: None,
+                    source: None,
+                    open: None,
+                    high: None,
+                    low: None,
+                    bid: None,
+                    ask: None,
+                })
+                .unwrap();
+        }
+        for reader in reader_threads {
+            reader.join().unwrap();
+        }
+        let db = db.read().unwrap();
+        assert_eq!(db.get_all_quotes_for_ticker(ticker_id).unwrap().len(), num_quotes);
+    }
 }
@@ -0,0 +1,218 @@
+//! Implementation of PostgreSQL order handler
+use std::str::FromStr;
+
+use chrono::{DateTime, Utc};
+use postgres::Row;
+
+use finql_data::currency::Currency;
+use finql_data::order::{
+    store_order_fill, Order, OrderError, OrderHandler, OrderSide, OrderSize, OrderState,
+    TransactionDraft,
+};
+use finql_data::DataError;
+
+use super::PostgresDB;
+
+const ORDER_COLUMNS: &str = "id, asset_id, side, quantity, amount, currency, limit_price, \
+    state, created, updated, note";
+
+impl PostgresDB<'_> {
+    fn order_from_row(&mut self, row: &Row) -> Result<Order, DataError> {
+        let id: i32 = row.get(0);
+        let asset_id: i32 = row.get(1);
+        let side: String = row.get(2);
+        let currency: String = row.get(5);
+        let state: String = row.get(7);
+        Ok(Order {
+            id: Some(id as usize),
+            asset_id: asset_id as usize,
+            side: OrderSide::from_str(&side)?,
+            size: OrderSize::from_columns(row.get(3), row.get(4))?,
+            currency: Currency::from_str(&currency)
+                .map_err(|e| DataError::InvalidData(e.to_string()))?,
+            limit_price: row.get(6),
+            state: OrderState::from_str(&state)?,
+            created: row.get(8),
+            updated: row.get(9),
+            transaction_ids: self.get_order_transactions(id)?,
+            note: row.get(10),
+        })
+    }
+
+    fn get_order_transactions(&mut self, order_id: i32) -> Result<Vec<usize>, DataError> {
+        let mut ids = Vec::new();
+        for row in self
+            .conn
+            .get_mut()
+            .query(
+                "SELECT transaction_id FROM order_transactions
+                WHERE order_id=$1 ORDER BY transaction_id",
+                &[&order_id],
+            )
+            .map_err(|e| DataError::NotFound(e.to_string()))?
+        {
+            let id: i32 = row.get(0);
+            ids.push(id as usize);
+        }
+        Ok(ids)
+    }
+
+    fn set_order_transactions(&mut self, order_id: i32, ids: &[usize]) -> Result<(), DataError> {
+        self.conn
+            .get_mut()
+            .execute(
+                "DELETE FROM order_transactions WHERE order_id=$1",
+                &[&order_id],
+            )
+            .map_err(|e| DataError::UpdateFailed(e.to_string()))?;
+        for id in ids {
+            self.conn
+                .get_mut()
+                .execute(
+                    "INSERT INTO order_transactions (order_id, transaction_id) VALUES ($1, $2)",
+                    &[&order_id, &(*id as i32)],
+                )
+                .map_err(|e| DataError::UpdateFailed(e.to_string()))?;
+        }
+        Ok(())
+    }
+}
+
+impl OrderHandler for PostgresDB<'_> {
+    fn insert_order(&mut self, order: &Order) -> Result<usize, DataError> {
+        let row = self
+            .conn
+            .get_mut()
+            .query_one(
+                "INSERT INTO orders (asset_id, side, quantity, amount, currency, limit_price,
+                state, created, updated, note)
+                VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10) RETURNING id",
+                &[
+                    &(order.asset_id as i32),
+                    &order.side.to_string(),
+                    &order.size.quantity(),
+                    &order.size.amount(),
+                    &order.currency.to_string(),
+                    &order.limit_price,
+                    &order.state.to_string(),
+                    &order.created,
+                    &order.updated,
+                    &order.note,
+                ],
+            )
+            .map_err(|e| DataError::InsertFailed(e.to_string()))?;
+        let id: i32 = row.get(0);
+        self.set_order_transactions(id, &order.transaction_ids)
+            .map_err(|e| DataError::InsertFailed(e.to_string()))?;
+        Ok(id as usize)
+    }
+
+    fn get_order_by_id(&mut self, id: usize) -> Result<Order, DataError> {
+        let row = self
+            .conn
+            .get_mut()
+            .query_one(
+                format!("SELECT {} FROM orders WHERE id=$1", ORDER_COLUMNS).as_str(),
+                &[&(id as i32)],
+            )
+            .map_err(|e| DataError::NotFound(e.to_string()))?;
+        self.order_from_row(&row)
+    }
+
+    fn get_all_orders(&mut self) -> Result<Vec<Order>, DataError> {
+        let rows = self
+            .conn
+            .get_mut()
+            .query(
+                format!("SELECT {} FROM orders ORDER BY id", ORDER_COLUMNS).as_str(),
+                &[],
+            )
+            .map_err(|e| DataError::NotFound(e.to_string()))?;
+        let mut orders = Vec::new();
+        for row in rows {
+            orders.push(self.order_from_row(&row)?);
+        }
+        Ok(orders)
+    }
+
+    fn update_order(&mut self, order: &Order) -> Result<(), DataError> {
+        let id = order
+            .id
+            .ok_or_else(|| DataError::NotFound("not yet stored to database".to_string()))?
+            as i32;
+        self.conn
+            .get_mut()
+            .execute(
+                "UPDATE orders SET
+                asset_id=$2,
+                side=$3,
+                quantity=$4,
+                amount=$5,
+                currency=$6,
+                limit_price=$7,
+                state=$8,
+                created=$9,
+                updated=$10,
+                note=$11
+            WHERE id=$1",
+                &[
+                    &id,
+                    &(order.asset_id as i32),
+                    &order.side.to_string(),
+                    &order.size.quantity(),
+                    &order.size.amount(),
+                    &order.currency.to_string(),
+                    &order.limit_price,
+                    &order.state.to_string(),
+                    &order.created,
+                    &order.updated,
+                    &order.note,
+                ],
+            )
+            .map_err(|e| DataError::UpdateFailed(e.to_string()))?;
+        self.set_order_transactions(id, &order.transaction_ids)
+    }
+
+    fn delete_order(&mut self, id: usize) -> Result<(), DataError> {
+        self.conn
+            .get_mut()
+            .execute(
+                "DELETE FROM order_transactions WHERE order_id=$1",
+                &[&(id as i32)],
+            )
+            .map_err(|e| DataError::DeleteFailed(e.to_string()))?;
+        self.conn
+            .get_mut()
+            .execute("DELETE FROM orders WHERE id=$1", &[&(id as i32)])
+            .map_err(|e| DataError::DeleteFailed(e.to_string()))?;
+        Ok(())
+    }
+
+    fn fill_order(
+        &mut self,
+        order_id: usize,
+        execution: &TransactionDraft,
+        time: DateTime<Utc>,
+    ) -> Result<Vec<usize>, OrderError> {
+        self.conn
+            .get_mut()
+            .batch_execute("BEGIN")
+            .map_err(|e| DataError::DataAccessFailure(e.to_string()))?;
+        match store_order_fill(self, order_id, execution, time) {
+            Ok(ids) => {
+                self.conn
+                    .get_mut()
+                    .batch_execute("COMMIT")
+                    .map_err(|e| DataError::DataAccessFailure(e.to_string()))?;
+                Ok(ids)
+            }
+            Err(err) => {
+                self.conn
+                    .get_mut()
+                    .batch_execute("ROLLBACK")
+                    .map_err(|e| DataError::DataAccessFailure(e.to_string()))?;
+                Err(err)
+            }
+        }
+    }
+}
@@ -1,167 +1,286 @@
 ///! Implementation for quote handler with Sqlite3 database as backend
+use std::collections::BTreeMap;
 use std::str::FromStr;
 use chrono::{DateTime, Utc};
+use postgres::{types::ToSql, Row};
+#[cfg(feature = "bulk_copy")]
+use postgres::{binary_copy::BinaryCopyInWriter, types::Type};
 
 use finql_data::currency::Currency;
-use finql_data::{DataError, QuoteHandler};
-use finql_data::quote::{Quote, Ticker};
+use finql_data::quote_handler::store_each_quote;
+use finql_data::{DataError, QuotaMonth, QuoteInsertion, QuoteReader, QuoteWriter};
+use finql_data::quote::{parse_source_chain, Quote, QuoteQuery, Ticker, TickerUsage};
 
 use super::PostgresDB;
 
-/// Sqlite implementation of quote handler
-impl QuoteHandler for PostgresDB<'_> {
-    // insert, get, update and delete for market data sources
-    fn insert_ticker(&mut self, ticker: &Ticker) -> Result<usize, DataError> {
-        let row = self
+/// Query for the last quotes of an asset given by name on or before a given time,
+/// considering only ticker serving the given usage. All quotes of the latest time are
+/// returned in order of ticker priority.
+pub(crate) const LAST_QUOTE_BEFORE_QUERY: &str =
+    "WITH candidates AS (
+        SELECT q.id, q.ticker_id, q.price, q.time, q.volume, q.quality_score, q.source, q.open,
+        q.high, q.low, q.bid, q.ask, t.currency, t.priority
+        FROM quotes q, ticker t, assets a
+        WHERE a.name=$1 AND t.asset_id=a.id AND t.id=q.ticker_id AND q.time<= $2
+        AND (t.usage=$3 OR t.usage='both' OR $3='both'))
+    SELECT * FROM candidates WHERE time=(SELECT MAX(time) FROM candidates)
+    ORDER BY priority ASC";
+
+/// Query for the last quotes of an asset given by id, see `LAST_QUOTE_BEFORE_QUERY`
+const LAST_QUOTE_BEFORE_BY_ID_QUERY: &str =
+    "WITH candidates AS (
+        SELECT q.id, q.ticker_id, q.price, q.time, q.volume, q.quality_score, q.source, q.open,
+        q.high, q.low, q.bid, q.ask, t.currency, t.priority
+        FROM quotes q, ticker t
+        WHERE t.asset_id=$1 AND t.id=q.ticker_id AND q.time<= $2
+        AND (t.usage=$3 OR t.usage='both' OR $3='both'))
+    SELECT * FROM candidates WHERE time=(SELECT MAX(time) FROM candidates)
+    ORDER BY priority ASC";
+
+/// Columns to select to construct a quote by `quote_from_row`
+const QUOTE_COLUMNS: &str =
+    "id, ticker_id, price, time, volume, quality_score, source, open, high, low, bid, ask";
+
+/// Construct a quote from a row starting with the columns given by `QUOTE_COLUMNS`
+fn quote_from_row(row: &Row) -> Quote {
+    let id: i32 = row.get(0);
+    let ticker: i32 = row.get(1);
+    Quote {
+        id: Some(id as usize),
+        ticker: ticker as usize,
+        price: row.get(2),
+        time: row.get(3),
+        volume: row.get(4),
+        quality_score: row.get(5),
+        source: row.get(6),
+        open: row.get(7),
+        high: row.get(8),
+        low: row.get(9),
+        bid: row.get(10),
+        ask: row.get(11),
+    }
+}
+
+/// Value of the `source_chain` column of a ticker, NULL if the ticker has no fallback sources
+fn source_chain_column(ticker: &Ticker) -> Option<String> {
+    Some(ticker.source_chain.join(",")).filter(|chain| !chain.is_empty())
+}
+
+/// Columns to select to construct a ticker by `ticker_from_row`
+const TICKER_COLUMNS: &str = "id, name, asset_id, priority, source, currency, factor, source_url,
+    usage, last_quote_time, source_chain";
+
+/// Construct a ticker from a row containing the columns given by `TICKER_COLUMNS`
+fn ticker_from_row(row: &Row) -> Result<Ticker, DataError> {
+    let id: i32 = row.get(0);
+    let asset: i32 = row.get(2);
+    let currency: String = row.get(5);
+    let currency =
+        Currency::from_stored(&currency).map_err(|e| DataError::NotFound(e.to_string()))?;
+    let source_chain: Option<String> = row.get(10);
+    let source_chain = source_chain
+        .map(|chain| parse_source_chain(&chain))
+        .transpose()?
+        .unwrap_or_default();
+    Ok(Ticker {
+        id: Some(id as usize),
+        name: row.get(1),
+        asset: asset as usize,
+        priority: row.get(3),
+        source: row.get(4),
+        currency,
+        factor: row.get(6),
+        source_url: row.get(7),
+        usage: TickerUsage::from_str(row.get(8))?,
+        last_quote_time: row.get(9),
+        source_chain,
+    })
+}
+
+impl PostgresDB<'_> {
+    /// Get all ticker matching the given condition
+    fn query_ticker(
+        &self,
+        condition: &str,
+        params: &[&(dyn ToSql + Sync)],
+    ) -> Result<Vec<Ticker>, DataError> {
+        let rows = self
             .conn
-            .query_one(
-                "INSERT INTO ticker (name, asset_id, source, priority, currency, factor) 
-                VALUES ($1, $2, $3, $4, $5, $6) RETURNING id",
-                &[
-                    &ticker.name,
-                    &(ticker.asset as i32),
-                    &(ticker.source.to_string()),
-                    &ticker.priority,
-                    &(ticker.currency.to_string()),
-                    &ticker.factor,
-                ],
+            .borrow_mut()
+            .query(
+                format!("SELECT {} FROM ticker WHERE {};", TICKER_COLUMNS, condition).as_str(),
+                params,
             )
-            .map_err(|e| DataError::InsertFailed(e.to_string()))?;
-        let id: i32 = row.get(0);
-        Ok(id as usize)
+            .map_err(|e| DataError::NotFound(e.to_string()))?;
+        rows.iter().map(ticker_from_row).collect()
     }
 
-    fn get_ticker_id(&mut self, ticker: &str) -> Option<usize> {
-        let row = self
+    /// Get all quotes matching the given condition, ordered by time
+    fn query_quotes(
+        &self,
+        condition: &str,
+        params: &[&(dyn ToSql + Sync)],
+    ) -> Result<Vec<Quote>, DataError> {
+        let rows = self
             .conn
-            .query_one("SELECT id FROM ticker WHERE name=$1", &[&ticker]);
-        match row {
-            Ok(row) => {
-                let id: i32 = row.get(0);
-                Some(id as usize)
-            }
-            _ => None,
-        }
+            .borrow_mut()
+            .query(
+                format!(
+                    "SELECT {} FROM quotes WHERE {} ORDER BY time ASC;",
+                    QUOTE_COLUMNS, condition
+                )
+                .as_str(),
+                params,
+            )
+            .map_err(|e| DataError::NotFound(e.to_string()))?;
+        Ok(rows.iter().map(quote_from_row).collect())
     }
 
-    fn get_ticker_by_id(&mut self, id: usize) -> Result<Ticker, DataError> {
-        let row = self
+    /// Get the last quote and its currency by one of the last quote queries, selected by
+    /// `Quote::select_preferred` among all quotes of the latest time
+    fn query_last_quote_before(
+        &self,
+        query: &str,
+        params: &[&(dyn ToSql + Sync)],
+    ) -> Result<(Quote, Currency), DataError> {
+        let rows = self
             .conn
-            .query_one(
-                "SELECT name, asset_id, source, priority, currency, factor FROM ticker WHERE id=$1;",
-                &[&(id as i32)],
-            )
+            .borrow_mut()
+            .query(query, params)
             .map_err(|e| DataError::NotFound(e.to_string()))?;
-        let name: String = row.get(0);
-        let asset: i32 = row.get(1);
-        let source: String = row.get(2);
-        let currency: String = row.get(4);
+        let candidates = rows
+            .iter()
+            .map(|row| (quote_from_row(row), row.get::<_, String>(12)))
+            .collect();
+        let (quote, currency) = Quote::select_preferred(candidates)
+            .ok_or_else(|| DataError::NotFound("no quote found".to_string()))?;
         let currency =
-            Currency::from_str(&currency).map_err(|e| DataError::NotFound(e.to_string()))?;
-        Ok(Ticker {
-            id: Some(id),
-            name,
-            asset: asset as usize,
-            source,
-            priority: row.get(3),
-            currency,
-            factor: row.get(5),
-        })
-    }
-    fn get_all_ticker(&mut self) -> Result<Vec<Ticker>, DataError> {
-        let mut all_ticker = Vec::new();
-        for row in self
-            .conn
-            .query(
-                "SELECT id, name, asset_id, priority, source, currency, factor FROM ticker",
-                &[],
+            Currency::from_stored(&currency).map_err(|e| DataError::NotFound(e.to_string()))?;
+        Ok((quote, currency))
+    }
+
+    /// Recalculate the time of the latest quote of the given ticker, e.g. after quotes have
+    /// been changed or deleted
+    fn refresh_last_quote_time(&mut self, ticker_ids: &[i32]) -> Result<(), DataError> {
+        self.conn
+            .get_mut()
+            .execute(
+                "UPDATE ticker SET last_quote_time =
+                (SELECT MAX(time) FROM quotes WHERE ticker_id = ticker.id) WHERE id = ANY($1)",
+                &[&ticker_ids],
             )
-            .map_err(|e| DataError::NotFound(e.to_string()))?
-        {
-            let id: i32 = row.get(0);
-            let asset: i32 = row.get(2);
-            let source: String = row.get(4);
-            let currency: String = row.get(5);
-            let currency =
-                Currency::from_str(&currency).map_err(|e| DataError::NotFound(e.to_string()))?;
-            let factor: f64 = row.get(6);
-            all_ticker.push(Ticker {
-                id: Some(id as usize),
-                name: row.get(1),
-                asset: asset as usize,
-                source,
-                priority: row.get(3),
-                currency,
-                factor,
-            });
-        }
-        Ok(all_ticker)
+            .map_err(|e| DataError::UpdateFailed(e.to_string()))?;
+        Ok(())
     }
 
-    fn get_all_ticker_for_source(
-        &mut self,
-        source: &str,
-    ) -> Result<Vec<Ticker>, DataError> {
-        let mut all_ticker = Vec::new();
-        for row in self
+    /// Insert all quotes with a single multi-row statement. This does not take care of
+    /// atomicity beyond the statement itself, it is intended to be called within a database
+    /// transaction.
+    fn insert_all_quotes(&mut self, quotes: &[Quote]) -> Result<Vec<usize>, DataError> {
+        let ticker_ids: Vec<i32> = quotes.iter().map(|q| q.ticker as i32).collect();
+        let prices: Vec<f64> = quotes.iter().map(|q| q.price).collect();
+        let times: Vec<DateTime<Utc>> = quotes.iter().map(|q| q.time).collect();
+        let volumes: Vec<Option<f64>> = quotes.iter().map(|q| q.volume).collect();
+        let quality_scores: Vec<Option<f64>> = quotes.iter().map(|q| q.quality_score).collect();
+        let sources: Vec<Option<String>> = quotes.iter().map(|q| q.source.clone()).collect();
+        let opens: Vec<Option<f64>> = quotes.iter().map(|q| q.open).collect();
+        let highs: Vec<Option<f64>> = quotes.iter().map(|q| q.high).collect();
+        let lows: Vec<Option<f64>> = quotes.iter().map(|q| q.low).collect();
+        let bids: Vec<Option<f64>> = quotes.iter().map(|q| q.bid).collect();
+        let asks: Vec<Option<f64>> = quotes.iter().map(|q| q.ask).collect();
+        let rows = self
             .conn
+            .get_mut()
             .query(
-                "SELECT id, name, asset_id, priority, currency, factor FROM ticker WHERE source=$1;",
-                &[&(source.to_string())],
+                "INSERT INTO quotes (ticker_id, price, time, volume, quality_score, source,
+                    open, high, low, bid, ask)
+                SELECT * FROM UNNEST($1::INT4[], $2::FLOAT8[], $3::TIMESTAMPTZ[], $4::FLOAT8[],
+                    $5::FLOAT8[], $6::TEXT[], $7::FLOAT8[], $8::FLOAT8[], $9::FLOAT8[],
+                    $10::FLOAT8[], $11::FLOAT8[])
+                RETURNING id",
+                &[
+                    &ticker_ids,
+                    &prices,
+                    &times,
+                    &volumes,
+                    &quality_scores,
+                    &sources,
+                    &opens,
+                    &highs,
+                    &lows,
+                    &bids,
+                    &asks,
+                ],
             )
-            .map_err(|e| DataError::NotFound(e.to_string()))?
-        {
-            let id: i32 = row.get(0);
-            let asset: i32 = row.get(2);
-            let currency: String = row.get(4);
-            let currency =
-                Currency::from_str(&currency).map_err(|e| DataError::NotFound(e.to_string()))?;
-            let factor: f64 = row.get(5);
-            all_ticker.push(Ticker {
-                id: Some(id as usize),
-                name: row.get(1),
-                asset: asset as usize,
-                source: source.to_string(),
-                priority: row.get(3),
-                currency,
-                factor,
-            });
-        }
-        Ok(all_ticker)
+            .map_err(|e| DataError::InsertFailed(e.to_string()))?;
+        // ids are assigned in the order of the input rows, but RETURNING doesn't guarantee
+        // to preserve this order
+        let mut ids: Vec<usize> = rows
+            .iter()
+            .map(|row| row.get::<_, i32>(0) as usize)
+            .collect();
+        ids.sort_unstable();
+        let mut ticker_ids = ticker_ids;
+        ticker_ids.sort_unstable();
+        ticker_ids.dedup();
+        self.refresh_last_quote_time(&ticker_ids)?;
+        Ok(ids)
     }
 
-    fn get_all_ticker_for_asset(
-        &mut self,
-        asset_id: usize,
-    ) -> Result<Vec<Ticker>, DataError> {
-        let mut all_ticker = Vec::new();
-        for row in self
-            .conn
-            .query(
-                "SELECT id, name, source, priority, currency, factor FROM ticker WHERE asset_id=$1;",
-                &[&(asset_id as i32)],
+    /// Set the time of the latest quote of the quote's ticker to the time of the new quote,
+    /// unless the ticker has a later quote already
+    fn advance_last_quote_time(&mut self, quote: &Quote) -> Result<(), DataError> {
+        self.conn
+            .get_mut()
+            .execute(
+                "UPDATE ticker SET last_quote_time=$1
+                WHERE id=$2 AND (last_quote_time IS NULL OR last_quote_time < $1)",
+                &[&quote.time, &(quote.ticker as i32)],
             )
-            .map_err(|e| DataError::NotFound(e.to_string()))?
-        {
-            let id: i32 = row.get(0);
-            let source: String = row.get(2);
-            let currency: String = row.get(4);
-            let currency =
-                Currency::from_str(&currency).map_err(|e| DataError::NotFound(e.to_string()))?;
-            let factor: f64 = row.get(5);
-            all_ticker.push(Ticker {
-                id: Some(id as usize),
-                name: row.get(1),
-                asset: asset_id,
-                source,
-                priority: row.get(3),
-                currency,
-                factor,
-            });
-        }
-        Ok(all_ticker)
+            .map_err(|e| DataError::UpdateFailed(e.to_string()))?;
+        Ok(())
+    }
+
+    /// Id of the ticker a stored quote belongs to
+    fn ticker_id_of_quote(&mut self, quote_id: i32) -> Result<i32, DataError> {
+        let row = self
+            .conn
+            .get_mut()
+            .query_one("SELECT ticker_id FROM quotes WHERE id=$1", &[&quote_id])
+            .map_err(|e| DataError::NotFound(e.to_string()))?;
+        Ok(row.get(0))
     }
+}
 
+/// PostgreSQL implementation of writing quotes, `QuoteHandler` is implemented by `QuoteReader`
+/// and `QuoteWriter`
+impl QuoteWriter for PostgresDB<'_> {
+    // insert, get, update and delete for market data sources
+    fn insert_ticker(&mut self, ticker: &Ticker) -> Result<usize, DataError> {
+        ticker.validate()?;
+        let row = self
+            .conn
+            .get_mut()
+            .query_one(
+                "INSERT INTO ticker (name, asset_id, source, priority, currency, factor, source_url, usage,
+                source_chain)
+                VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9) RETURNING id",
+                &[
+                    &ticker.name,
+                    &(ticker.asset as i32),
+                    &(ticker.source.to_string()),
+                    &ticker.priority,
+                    &(ticker.currency.to_string()),
+                    &ticker.factor,
+                    &ticker.source_url,
+                    &ticker.usage.to_string(),
+                    &source_chain_column(ticker),
+                ],
+            )
+            .map_err(|e| DataError::InsertFailed(e.to_string()))?;
+        let id: i32 = row.get(0);
+        Ok(id as usize)
+    }
 
     fn update_ticker(&mut self, ticker: &Ticker) -> Result<(), DataError> {
         if ticker.id.is_none() {
@@ -171,8 +290,10 @@ impl QuoteHandler for PostgresDB<'_> {
         }
         let id = ticker.id.unwrap() as i32;
         self.conn
+            .get_mut()
             .execute(
-                "UPDATE ticker SET name=$2, asset_id=$3, source=$4, priority=$5, currency=$6, factor=$7
+                "UPDATE ticker SET name=$2, asset_id=$3, source=$4, priority=$5, currency=$6, factor=$7, source_url=$8, usage=$9,
+                source_chain=$10
                 WHERE id=$1",
                 &[
                     &id,
@@ -182,6 +303,9 @@ impl QuoteHandler for PostgresDB<'_> {
                     &ticker.priority,
                     &ticker.currency.to_string(),
                     &ticker.factor,
+                    &ticker.source_url,
+                    &ticker.usage.to_string(),
+                    &source_chain_column(ticker),
                 ],
             )
             .map_err(|e| DataError::InsertFailed(e.to_string()))?;
@@ -190,6 +314,7 @@ impl QuoteHandler for PostgresDB<'_> {
 
     fn delete_ticker(&mut self, id: usize) -> Result<(), DataError> {
         self.conn
+            .get_mut()
             .execute("DELETE FROM ticker WHERE id=$1;", &[&(id as i32)])
             .map_err(|e| DataError::InsertFailed(e.to_string()))?;
         Ok(())
@@ -197,117 +322,78 @@ impl QuoteHandler for PostgresDB<'_> {
 
     // insert, get, update and delete for market data sources
     fn insert_quote(&mut self, quote: &Quote) -> Result<usize, DataError> {
+        quote.validate()?;
         let row = self
             .conn
+            .get_mut()
             .query_one(
-                "INSERT INTO quotes (ticker_id, price, time, volume) 
-                VALUES ($1, $2, $3, $4) RETURNING id",
+                "INSERT INTO quotes (ticker_id, price, time, volume, quality_score, source,
+                open, high, low, bid, ask)
+                VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11) RETURNING id",
                 &[
                     &(quote.ticker as i32),
                     &quote.price,
                     &quote.time,
                     &quote.volume,
+                    &quote.quality_score,
+                    &quote.source,
+                    &quote.open,
+                    &quote.high,
+                    &quote.low,
+                    &quote.bid,
+                    &quote.ask,
                 ],
             )
             .map_err(|e| DataError::InsertFailed(e.to_string()))?;
         let id: i32 = row.get(0);
+        self.advance_last_quote_time(quote)?;
         Ok(id as usize)
     }
 
-    fn get_last_quote_before(
-        &mut self,
-        asset_name: &str,
-        time: DateTime<Utc>,
-    ) -> Result<(Quote, Currency), DataError> {
-        let row = self
-            .conn
-            .query_one(
-                "SELECT q.id, q.ticker_id, q.price, q.time, q.volume, t.currency, t.priority
-                FROM quotes q, ticker t, assets a 
-                WHERE a.name=$1 AND t.asset_id=a.id AND t.id=q.ticker_id AND q.time<= $2
-                ORDER BY q.time DESC, t.priority ASC LIMIT 1",
-                &[&asset_name, &time],
-            )
-            .map_err(|e| DataError::NotFound(e.to_string()))?;
-
-        let id: i32 = row.get(0);
-        let ticker: i32 = row.get(1);
-        let price: f64 = row.get(2);
-        let time: DateTime<Utc> = row.get(3);
-        let volume: Option<f64> = row.get(4);
-        let currency: String = row.get(5);
-        let currency =
-            Currency::from_str(&currency).map_err(|e| DataError::NotFound(e.to_string()))?;
-        Ok((
-            Quote {
-                id: Some(id as usize),
-                ticker: ticker as usize,
-                price,
-                time,
-                volume,
-            },
-            currency,
-        ))
-    }
-
-    fn get_last_quote_before_by_id(
-        &mut self,
-        asset_id: usize,
-        time: DateTime<Utc>,
-    ) -> Result<(Quote, Currency), DataError> {
+    fn insert_quote_if_new(&mut self, quote: &Quote) -> Result<QuoteInsertion, DataError> {
+        quote.validate()?;
         let row = self
             .conn
-            .query_one(
-                "SELECT q.id, q.ticker_id, q.price, q.time, q.volume, t.currency, t.priority
-                FROM quotes q, ticker t
-                WHERE t.asset_id=$1 AND t.id=q.ticker_id AND q.time<= $2
-                ORDER BY q.time DESC, t.priority ASC LIMIT 1",
-                &[&(asset_id as i32), &time],
-            )
-            .map_err(|e| DataError::NotFound(e.to_string()))?;
-
-        let id: i32 = row.get(0);
-        let ticker: i32 = row.get(1);
-        let price: f64 = row.get(2);
-        let time: DateTime<Utc> = row.get(3);
-        let volume: Option<f64> = row.get(4);
-        let currency: String = row.get(5);
-        let currency =
-            Currency::from_str(&currency).map_err(|e| DataError::NotFound(e.to_string()))?;
-        Ok((
-            Quote {
-                id: Some(id as usize),
-                ticker: ticker as usize,
-                price,
-                time,
-                volume,
-            },
-            currency,
-        ))
-    }
-
-    fn get_all_quotes_for_ticker(&mut self, ticker_id: usize) -> Result<Vec<Quote>, DataError> {
-        let mut quotes = Vec::new();
-        for row in self
-            .conn
-            .query(
-                "SELECT id, price, time, volume FROM quotes 
-                WHERE ticker_id=$1 ORDER BY time ASC;",
-                &[&(ticker_id as i32)],
+            .get_mut()
+            .query_opt(
+                "INSERT INTO quotes (ticker_id, price, time, volume, quality_score, source,
+                open, high, low, bid, ask)
+                VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11)
+                ON CONFLICT (ticker_id, time) DO NOTHING RETURNING id",
+                &[
+                    &(quote.ticker as i32),
+                    &quote.price,
+                    &quote.time,
+                    &quote.volume,
+                    &quote.quality_score,
+                    &quote.source,
+                    &quote.open,
+                    &quote.high,
+                    &quote.low,
+                    &quote.bid,
+                    &quote.ask,
+                ],
             )
-            .map_err(|e| DataError::NotFound(e.to_string()))?
-        {
-            let id: i32 = row.get(0);
-            let time: DateTime<Utc> = row.get(2);
-            quotes.push(Quote {
-                id: Some(id as usize),
-                ticker: ticker_id,
-                price: row.get(1),
-                time,
-                volume: row.get(3),
-            });
+            .map_err(|e| DataError::InsertFailed(e.to_string()))?;
+        match row {
+            Some(row) => {
+                let id: i32 = row.get(0);
+                self.advance_last_quote_time(quote)?;
+                Ok(QuoteInsertion::Inserted(id as usize))
+            }
+            None => {
+                let row = self
+                    .conn
+                    .get_mut()
+                    .query_one(
+                        "SELECT id FROM quotes WHERE ticker_id=$1 AND time=$2",
+                        &[&(quote.ticker as i32), &quote.time],
+                    )
+                    .map_err(|e| DataError::NotFound(e.to_string()))?;
+                let id: i32 = row.get(0);
+                Ok(QuoteInsertion::Existing(id as usize))
+            }
         }
-        Ok(quotes)
     }
 
     fn update_quote(&mut self, quote: &Quote) -> Result<(), DataError> {
@@ -317,9 +403,12 @@ impl QuoteHandler for PostgresDB<'_> {
             ));
         }
         let id = quote.id.unwrap() as i32;
+        let old_ticker_id = self.ticker_id_of_quote(id)?;
         self.conn
+            .get_mut()
             .execute(
-                "UPDATE quotes SET ticker_id=$2, price=$3, time=$4, volume=$5
+                "UPDATE quotes SET ticker_id=$2, price=$3, time=$4, volume=$5, quality_score=$6,
+                source=$7, open=$8, high=$9, low=$10, bid=$11, ask=$12
                 WHERE id=$1",
                 &[
                     &id,
@@ -327,27 +416,359 @@ impl QuoteHandler for PostgresDB<'_> {
                     &quote.price,
                     &quote.time,
                     &quote.volume,
+                    &quote.quality_score,
+                    &quote.source,
+                    &quote.open,
+                    &quote.high,
+                    &quote.low,
+                    &quote.bid,
+                    &quote.ask,
                 ],
             )
             .map_err(|e| DataError::InsertFailed(e.to_string()))?;
-        Ok(())
+        self.refresh_last_quote_time(&[old_ticker_id, quote.ticker as i32])
     }
 
     fn delete_quote(&mut self, id: usize) -> Result<(), DataError> {
+        let ticker_id = self.ticker_id_of_quote(id as i32)?;
         self.conn
+            .get_mut()
             .execute("DELETE FROM quotes WHERE id=$1;", &[&(id as i32)])
             .map_err(|e| DataError::InsertFailed(e.to_string()))?;
+        self.refresh_last_quote_time(&[ticker_id])
+    }
+
+    fn delete_quotes_for_ticker(&mut self, ticker_id: usize) -> Result<usize, DataError> {
+        let deleted = self
+            .conn
+            .get_mut()
+            .execute(
+                "DELETE FROM quotes WHERE ticker_id=$1;",
+                &[&(ticker_id as i32)],
+            )
+            .map_err(|e| DataError::DeleteFailed(e.to_string()))?;
+        self.refresh_last_quote_time(&[ticker_id as i32])?;
+        Ok(deleted as usize)
+    }
+
+    fn delete_quotes_before(
+        &mut self,
+        ticker_id: usize,
+        time: DateTime<Utc>,
+    ) -> Result<usize, DataError> {
+        let deleted = self
+            .conn
+            .get_mut()
+            .execute(
+                "DELETE FROM quotes WHERE ticker_id=$1 AND time < $2;",
+                &[&(ticker_id as i32), &time],
+            )
+            .map_err(|e| DataError::DeleteFailed(e.to_string()))?;
+        self.refresh_last_quote_time(&[ticker_id as i32])?;
+        Ok(deleted as usize)
+    }
+
+    fn store_quotes(&mut self, quotes: &[Quote]) -> Result<Vec<usize>, DataError> {
+        self.conn
+            .get_mut()
+            .batch_execute("BEGIN")
+            .map_err(|e| DataError::DataAccessFailure(e.to_string()))?;
+        match store_each_quote(self, quotes) {
+            Ok(ids) => {
+                self.conn
+                    .get_mut()
+                    .batch_execute("COMMIT")
+                    .map_err(|e| DataError::DataAccessFailure(e.to_string()))?;
+                Ok(ids)
+            }
+            Err(err) => {
+                self.conn
+                    .get_mut()
+                    .batch_execute("ROLLBACK")
+                    .map_err(|e| DataError::DataAccessFailure(e.to_string()))?;
+                Err(err)
+            }
+        }
+    }
+
+    fn insert_quotes(&mut self, quotes: &[Quote]) -> Result<Vec<usize>, DataError> {
+        for quote in quotes {
+            quote.validate()?;
+        }
+        if quotes.is_empty() {
+            return Ok(Vec::new());
+        }
+        self.conn
+            .get_mut()
+            .batch_execute("BEGIN")
+            .map_err(|e| DataError::DataAccessFailure(e.to_string()))?;
+        match self.insert_all_quotes(quotes) {
+            Ok(ids) => {
+                self.conn
+                    .get_mut()
+                    .batch_execute("COMMIT")
+                    .map_err(|e| DataError::DataAccessFailure(e.to_string()))?;
+                Ok(ids)
+            }
+            Err(err) => {
+                self.conn
+                    .get_mut()
+                    .batch_execute("ROLLBACK")
+                    .map_err(|e| DataError::DataAccessFailure(e.to_string()))?;
+                Err(err)
+            }
+        }
+    }
+
+    fn set_rounding_digits(&mut self, currency: Currency, digits: i32) -> Result<(), DataError> {
+        let _row = self
+            .conn
+            .get_mut()
+            .execute(
+                "INSERT INTO rounding_digits (currency, digits) VALUES ($1, $2)
+                ON CONFLICT (currency) DO UPDATE SET digits=$2",
+                &[&currency.to_string(), &digits],
+            )
+            .map_err(|e| DataError::InsertFailed(e.to_string()))?;
+        Ok(())
+    }
+
+    fn add_quota_usage(
+        &mut self,
+        source: &str,
+        month: QuotaMonth,
+        calls: u64,
+    ) -> Result<(), DataError> {
+        self.conn
+            .get_mut()
+            .execute(
+                "INSERT INTO quota_usage (source, month, calls) VALUES ($1, $2, $3)
+                ON CONFLICT (source, month) DO UPDATE SET calls=quota_usage.calls+excluded.calls",
+                &[&source, &month.to_string(), &(calls as i64)],
+            )
+            .map_err(|e| DataError::InsertFailed(e.to_string()))?;
         Ok(())
     }
+}
+
+/// PostgreSQL implementation of read-only quote access
+impl QuoteReader for PostgresDB<'_> {
+    fn get_ticker_id(&self, ticker: &str) -> Option<usize> {
+        let row = self
+            .conn
+            .borrow_mut()
+            .query_one("SELECT id FROM ticker WHERE name=$1", &[&ticker]);
+        match row {
+            Ok(row) => {
+                let id: i32 = row.get(0);
+                Some(id as usize)
+            }
+            _ => None,
+        }
+    }
+
+    fn get_ticker_id_for_source(&self, name: &str, source: &str) -> Option<usize> {
+        let row = self.conn.borrow_mut().query_one(
+            "SELECT id FROM ticker WHERE name=$1 AND source=$2",
+            &[&name, &source],
+        );
+        match row {
+            Ok(row) => {
+                let id: i32 = row.get(0);
+                Some(id as usize)
+            }
+            _ => None,
+        }
+    }
+
+    fn get_ticker_by_id(&self, id: usize) -> Result<Ticker, DataError> {
+        let row = self
+            .conn
+            .borrow_mut()
+            .query_one(
+                format!("SELECT {} FROM ticker WHERE id=$1;", TICKER_COLUMNS).as_str(),
+                &[&(id as i32)],
+            )
+            .map_err(|e| DataError::NotFound(e.to_string()))?;
+        ticker_from_row(&row)
+    }
+
+    fn get_all_ticker(&self) -> Result<Vec<Ticker>, DataError> {
+        self.query_ticker("TRUE", &[])
+    }
+
+    fn get_all_ticker_for_source(
+        &self,
+        source: &str,
+    ) -> Result<Vec<Ticker>, DataError> {
+        self.query_ticker("source=$1", &[&source])
+    }
+
+    fn get_all_ticker_for_asset(
+        &self,
+        asset_id: usize,
+    ) -> Result<Vec<Ticker>, DataError> {
+        self.query_ticker("asset_id=$1", &[&(asset_id as i32)])
+    }
+
+    fn get_tickers_by_source_url_pattern(
+        &self,
+        url_pattern: &str,
+    ) -> Result<Vec<Ticker>, DataError> {
+        self.query_ticker("source_url LIKE $1", &[&url_pattern])
+    }
+
+    fn get_last_quote_before_for_usage(
+        &self,
+        asset_name: &str,
+        time: DateTime<Utc>,
+        usage: TickerUsage,
+    ) -> Result<(Quote, Currency), DataError> {
+        self.query_last_quote_before(
+            LAST_QUOTE_BEFORE_QUERY,
+            &[&asset_name, &time, &usage.to_string()],
+        )
+    }
+
+    fn get_last_quote_before_by_id_for_usage(
+        &self,
+        asset_id: usize,
+        time: DateTime<Utc>,
+        usage: TickerUsage,
+    ) -> Result<(Quote, Currency), DataError> {
+        self.query_last_quote_before(
+            LAST_QUOTE_BEFORE_BY_ID_QUERY,
+            &[&(asset_id as i32), &time, &usage.to_string()],
+        )
+    }
+
+    fn get_all_quotes_for_ticker(&self, ticker_id: usize) -> Result<Vec<Quote>, DataError> {
+        self.query_quotes("ticker_id=$1", &[&(ticker_id as i32)])
+    }
+
+    fn get_all_quotes_for_ticker_and_source(
+        &self,
+        asset_id: usize,
+        source: &str,
+    ) -> Result<Vec<Quote>, DataError> {
+        self.query_quotes(
+            "ticker_id IN (SELECT id FROM ticker WHERE asset_id=$1 AND source=$2)",
+            &[&(asset_id as i32), &source],
+        )
+    }
+
+    fn get_quotes_above_quality(
+        &self,
+        ticker_id: usize,
+        min_quality: f64,
+    ) -> Result<Vec<Quote>, DataError> {
+        self.query_quotes(
+            "ticker_id=$1 AND quality_score >= $2",
+            &[&(ticker_id as i32), &min_quality],
+        )
+    }
+
+    fn get_quotes_in_range(
+        &self,
+        ticker_id: usize,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+    ) -> Result<Vec<Quote>, DataError> {
+        self.query_quotes(
+            "ticker_id=$1 AND time >= $2 AND time <= $3",
+            &[&(ticker_id as i32), &start, &end],
+        )
+    }
+
+    fn get_quotes_page(
+        &self,
+        query: &QuoteQuery,
+        after_id: Option<usize>,
+        limit: usize,
+    ) -> Result<Vec<Quote>, DataError> {
+        let after_id = after_id.map_or(0, |id| id as i32);
+        let limit = limit as i64;
+        let ticker_ids: Option<Vec<i32>> = query
+            .ticker_ids
+            .as_ref()
+            .map(|ids| ids.iter().map(|id| *id as i32).collect());
+        let mut conditions = vec!["id > $1".to_string()];
+        let mut params: Vec<&(dyn ToSql + Sync)> = vec![&after_id, &limit];
+        if let Some(ticker_ids) = &ticker_ids {
+            params.push(ticker_ids);
+            conditions.push(format!("ticker_id = ANY(${})", params.len()));
+        }
+        if let Some(start) = &query.start {
+            params.push(start);
+            conditions.push(format!("time >= ${}", params.len()));
+        }
+        if let Some(end) = &query.end {
+            params.push(end);
+            conditions.push(format!("time <= ${}", params.len()));
+        }
+        let rows = self
+            .conn
+            .borrow_mut()
+            .query(
+                format!(
+                    "SELECT {} FROM quotes WHERE {} ORDER BY id LIMIT $2;",
+                    QUOTE_COLUMNS,
+                    conditions.join(" AND ")
+                )
+                .as_str(),
+                &params,
+            )
+            .map_err(|e| DataError::NotFound(e.to_string()))?;
+        Ok(rows.iter().map(quote_from_row).collect())
+    }
 
-    fn get_rounding_digits(&mut self, currency: Currency) -> i32 {
-        let rows = self.conn.query(
-            "SELECT digits FROM rounding_digits WHERE currency=$1;",
+    fn get_latest_quotes_for_tickers(
+        &self,
+        ticker_ids: &[usize],
+    ) -> Result<Vec<(usize, Quote)>, DataError> {
+        if ticker_ids.is_empty() {
+            return Ok(Vec::new());
+        }
+        let ids: Vec<i32> = ticker_ids.iter().map(|id| *id as i32).collect();
+        let mut latest = BTreeMap::new();
+        for row in self
+            .conn
+            .borrow_mut()
+            .query(
+                format!(
+                    "SELECT DISTINCT ON (ticker_id) {} FROM quotes
+                    WHERE ticker_id = ANY($1) ORDER BY ticker_id, time DESC, id DESC;",
+                    QUOTE_COLUMNS
+                )
+                .as_str(),
+                &[&ids],
+            )
+            .map_err(|e| DataError::NotFound(e.to_string()))?
+        {
+            let quote = quote_from_row(&row);
+            latest.insert(quote.ticker, quote);
+        }
+        let mut quotes = Vec::new();
+        for ticker_id in ticker_ids {
+            if let Some(quote) = latest.get(ticker_id) {
+                quotes.push((*ticker_id, quote.clone()));
+            }
+        }
+        Ok(quotes)
+    }
+
+    fn get_stale_tickers(&self, before: DateTime<Utc>) -> Result<Vec<Ticker>, DataError> {
+        self.query_ticker("last_quote_time IS NULL OR last_quote_time < $1", &[&before])
+    }
+
+    fn get_rounding_digits(&self, currency: Currency) -> i32 {
+        let rows = self.conn.borrow_mut().query(
+            "SELECT digits FROM rounding_digits WHERE currency=$1 ORDER BY id DESC LIMIT 1;",
             &[&currency.to_string()],
         );
         match rows {
             Ok(row_vec) => {
-                if row_vec.len() > 0 {
+                if !row_vec.is_empty() {
                     let digits: i32 = row_vec[0].get(0);
                     digits
                 } else {
@@ -358,14 +779,78 @@ impl QuoteHandler for PostgresDB<'_> {
         }
     }
 
-    fn set_rounding_digits(&mut self, currency: Currency, digits: i32) -> Result<(), DataError> {
-        let _row = self
+    fn get_quota_usage(&self, source: &str, month: QuotaMonth) -> Result<u64, DataError> {
+        let row = self
             .conn
-            .execute(
-                "INSERT INTO rounding_digits (currency, digits) VALUES ($1, $2)",
-                &[&currency.to_string(), &digits],
+            .borrow_mut()
+            .query_one(
+                "SELECT COALESCE(SUM(calls), 0)::BIGINT FROM quota_usage WHERE source=$1 AND month=$2",
+                &[&source, &month.to_string()],
+            )
+            .map_err(|e| DataError::NotFound(e.to_string()))?;
+        let calls: i64 = row.get(0);
+        Ok(calls as u64)
+    }
+}
+
+#[cfg(feature = "bulk_copy")]
+impl PostgresDB<'_> {
+    /// Insert quotes via the binary `COPY FROM STDIN` protocol, which is much faster than
+    /// inserting them one by one for large data sets. All quotes are validated before any of
+    /// them is sent, and the copy is atomic, i.e. either all quotes are stored or none.
+    /// Returns the number of rows copied.
+    pub fn copy_quotes_from_slice(&mut self, quotes: &[Quote]) -> Result<usize, DataError> {
+        for quote in quotes {
+            quote.validate()?;
+        }
+        let sink = self
+            .conn
+            .get_mut()
+            .copy_in(
+                "COPY quotes (ticker_id, price, time, volume, quality_score, source,
+                open, high, low, bid, ask) FROM STDIN BINARY",
             )
             .map_err(|e| DataError::InsertFailed(e.to_string()))?;
-        Ok(())
+        let mut writer = BinaryCopyInWriter::new(
+            sink,
+            &[
+                Type::INT4,
+                Type::FLOAT8,
+                Type::TIMESTAMPTZ,
+                Type::FLOAT8,
+                Type::FLOAT8,
+                Type::TEXT,
+                Type::FLOAT8,
+                Type::FLOAT8,
+                Type::FLOAT8,
+                Type::FLOAT8,
+                Type::FLOAT8,
+            ],
+        );
+        for quote in quotes {
+            writer
+                .write(&[
+                    &(quote.ticker as i32),
+                    &quote.price,
+                    &quote.time,
+                    &quote.volume,
+                    &quote.quality_score,
+                    &quote.source,
+                    &quote.open,
+                    &quote.high,
+                    &quote.low,
+                    &quote.bid,
+                    &quote.ask,
+                ])
+                .map_err(|e| DataError::InsertFailed(e.to_string()))?;
+        }
+        let rows = writer
+            .finish()
+            .map_err(|e| DataError::InsertFailed(e.to_string()))?;
+        let mut ticker_ids: Vec<i32> = quotes.iter().map(|q| q.ticker as i32).collect();
+        ticker_ids.sort_unstable();
+        ticker_ids.dedup();
+        self.refresh_last_quote_time(&ticker_ids)?;
+        Ok(rows as usize)
     }
 }
@@ -0,0 +1,181 @@
+//! Implementation of PostgreSQL report handler
+use postgres::Row;
+
+use finql_data::rebalancing::RebalancingDecision;
+use finql_data::report_definition::{JobRun, ReportDefinition, ReportHandler};
+use finql_data::DataError;
+
+use super::PostgresDB;
+
+/// Construct a report definition from a row of the `report_definitions` table
+fn report_definition_from_row(row: &Row) -> ReportDefinition {
+    let version: i32 = row.get(1);
+    ReportDefinition {
+        name: row.get(0),
+        version: version as u32,
+        payload: row.get(2),
+    }
+}
+
+/// Construct a job run from a row of the `job_runs` table
+fn job_run_from_row(row: &Row) -> JobRun {
+    let id: i32 = row.get(0);
+    JobRun {
+        id: Some(id as usize),
+        job: row.get(1),
+        started: row.get(2),
+        finished: row.get(3),
+        success: row.get(4),
+        message: row.get(5),
+    }
+}
+
+impl ReportHandler for PostgresDB<'_> {
+    fn set_report_definition(&mut self, definition: &ReportDefinition) -> Result<(), DataError> {
+        self.conn
+            .get_mut()
+            .execute(
+                "INSERT INTO report_definitions (name, version, payload)
+                VALUES ($1, $2, $3)
+                ON CONFLICT (name) DO UPDATE
+                SET version=EXCLUDED.version, payload=EXCLUDED.payload",
+                &[
+                    &definition.name,
+                    &(definition.version as i32),
+                    &definition.payload,
+                ],
+            )
+            .map_err(|e| DataError::InsertFailed(e.to_string()))?;
+        Ok(())
+    }
+
+    fn get_report_definition(
+        &mut self,
+        name: &str,
+    ) -> Result<Option<ReportDefinition>, DataError> {
+        let row = self
+            .conn
+            .get_mut()
+            .query_opt(
+                "SELECT name, version, payload FROM report_definitions WHERE name=$1",
+                &[&name],
+            )
+            .map_err(|e| DataError::NotFound(e.to_string()))?;
+        Ok(row.map(|row| report_definition_from_row(&row)))
+    }
+
+    fn get_all_report_definitions(&mut self) -> Result<Vec<ReportDefinition>, DataError> {
+        let rows = self
+            .conn
+            .get_mut()
+            .query(
+                "SELECT name, version, payload FROM report_definitions ORDER BY name",
+                &[],
+            )
+            .map_err(|e| DataError::NotFound(e.to_string()))?;
+        Ok(rows.iter().map(report_definition_from_row).collect())
+    }
+
+    fn delete_report_definition(&mut self, name: &str) -> Result<(), DataError> {
+        self.conn
+            .get_mut()
+            .execute("DELETE FROM report_definitions WHERE name=$1;", &[&name])
+            .map_err(|e| DataError::DeleteFailed(e.to_string()))?;
+        Ok(())
+    }
+
+    fn insert_job_run(&mut self, run: &JobRun) -> Result<usize, DataError> {
+        let row = self
+            .conn
+            .get_mut()
+            .query_one(
+                "INSERT INTO job_runs (job, started, finished, success, message)
+                VALUES ($1, $2, $3, $4, $5) RETURNING id",
+                &[
+                    &run.job,
+                    &run.started,
+                    &run.finished,
+                    &run.success,
+                    &run.message,
+                ],
+            )
+            .map_err(|e| DataError::InsertFailed(e.to_string()))?;
+        let id: i32 = row.get(0);
+        Ok(id as usize)
+    }
+
+    fn get_job_runs(&mut self, job: &str) -> Result<Vec<JobRun>, DataError> {
+        let rows = self
+            .conn
+            .get_mut()
+            .query(
+                "SELECT id, job, started, finished, success, message FROM job_runs
+                WHERE job=$1 ORDER BY started DESC, id DESC",
+                &[&job],
+            )
+            .map_err(|e| DataError::NotFound(e.to_string()))?;
+        Ok(rows.iter().map(job_run_from_row).collect())
+    }
+
+    fn insert_rebalancing_decision(
+        &mut self,
+        decision: &RebalancingDecision,
+    ) -> Result<usize, DataError> {
+        let proposal = serde_json::to_value(&decision.proposal)
+            .map_err(|e| DataError::InvalidData(e.to_string()))?;
+        let row = self
+            .conn
+            .get_mut()
+            .query_one(
+                "INSERT INTO rebalancing_decisions (proposal) VALUES ($1) RETURNING id",
+                &[&proposal],
+            )
+            .map_err(|e| DataError::InsertFailed(e.to_string()))?;
+        let id: i32 = row.get(0);
+        for transaction_id in &decision.transaction_ids {
+            self.conn
+                .get_mut()
+                .execute(
+                    "INSERT INTO rebalancing_transactions (decision_id, transaction_id)
+                    VALUES ($1, $2)",
+                    &[&id, &(*transaction_id as i32)],
+                )
+                .map_err(|e| DataError::InsertFailed(e.to_string()))?;
+        }
+        Ok(id as usize)
+    }
+
+    fn get_rebalancing_decision(&mut self, id: usize) -> Result<RebalancingDecision, DataError> {
+        let row = self
+            .conn
+            .get_mut()
+            .query_one(
+                "SELECT proposal FROM rebalancing_decisions WHERE id=$1",
+                &[&(id as i32)],
+            )
+            .map_err(|e| DataError::NotFound(e.to_string()))?;
+        let proposal = serde_json::from_value(row.get(0))
+            .map_err(|e| DataError::InvalidData(e.to_string()))?;
+        let rows = self
+            .conn
+            .get_mut()
+            .query(
+                "SELECT transaction_id FROM rebalancing_transactions
+                WHERE decision_id=$1 ORDER BY transaction_id",
+                &[&(id as i32)],
+            )
+            .map_err(|e| DataError::NotFound(e.to_string()))?;
+        let transaction_ids = rows
+            .iter()
+            .map(|row| {
+                let id: i32 = row.get(0);
+                id as usize
+            })
+            .collect();
+        Ok(RebalancingDecision {
+            id: Some(id),
+            proposal,
+            transaction_ids,
+        })
+    }
+}
@@ -1,126 +1,57 @@
 use std::str::FromStr;
-use chrono::NaiveDate;
 
-use finql_data::currency::Currency;
-use finql_data::{DataError, TransactionHandler};
-use finql_data::cash_flow::{CashAmount, CashFlow};
-use finql_data::transaction::{Transaction, TransactionType};
+use postgres::types::ToSql;
+use postgres::Row;
 
-use super::PostgresDB;
+use finql_data::{Currency, DataError, FeeSchedule, TransactionHandler};
+use finql_data::transaction::{LotSelection, RawTransaction, Transaction, ORDER_ID_KEY};
 
+use super::PostgresDB;
 
-pub struct RawTransaction {
-    pub id: Option<i32>,
-    pub trans_type: String,
-    pub asset: Option<i32>,
-    pub cash_amount: f64,
-    pub cash_currency: String,
-    pub cash_date: NaiveDate,
-    pub related_trans: Option<i32>,
-    pub position: Option<f64>,
-    pub note: Option<String>,
-}
+const TRANSACTION_COLUMNS: &str = "id, trans_type, asset_id, cash_amount, cash_currency, \
+    cash_date, related_trans, position, note, execution_meta, recorded_at";
 
-/// Raw transaction type constants
-const CASH: &str = "c";
-const ASSET: &str = "a";
-const DIVIDEND: &str = "d";
-const INTEREST: &str = "i";
-const TAX: &str = "t";
-const FEE: &str = "f";
-
-impl RawTransaction {
-    pub fn to_transaction(&self) -> Result<Transaction, DataError> {
-        let currency = Currency::from_str(&self.cash_currency)
-            .map_err(|e| DataError::InsertFailed(e.to_string()))?;
-        let id = self.id.map(|x| x as usize);
-        let cash_flow = CashFlow {
-            amount: CashAmount {
-                amount: self.cash_amount,
-                currency,
-            },
-            date: self.cash_date,
-        };
-        let note = self.note.clone();
-        let transaction_type = match self.trans_type.as_str() {
-            CASH => TransactionType::Cash,
-            ASSET => TransactionType::Asset {
-                asset_id: self.asset.ok_or(DataError::InvalidTransaction(
-                    "missing asset id".to_string(),
-                ))? as usize,
-                position: self.position.ok_or(DataError::InvalidTransaction(
-                    "missing position value".to_string(),
-                ))?,
-            },
-            DIVIDEND => TransactionType::Dividend {
-                asset_id: self.asset.ok_or(DataError::InvalidTransaction(
-                    "missing asset id".to_string(),
-                ))? as usize,
-            },
-            INTEREST => TransactionType::Interest {
-                asset_id: self.asset.ok_or(DataError::InvalidTransaction(
-                    "missing asset id".to_string(),
-                ))? as usize,
-            },
-            TAX => TransactionType::Tax {
-                transaction_ref: self.related_trans.map(|x| x as usize),
-            },
-            FEE => TransactionType::Fee {
-                transaction_ref: self.related_trans.map(|x| x as usize),
-            },
-            unknown => {
-                return Err(DataError::InvalidTransaction(unknown.to_string()));
-            }
-        };
-        Ok(Transaction {
-            id,
-            transaction_type,
-            cash_flow,
-            note,
-        })
+/// Construct a raw transaction from a row containing the columns given by `TRANSACTION_COLUMNS`
+fn raw_transaction_from_row(row: &Row) -> RawTransaction {
+    RawTransaction {
+        id: row.get(0),
+        trans_type: row.get(1),
+        asset: row.get(2),
+        cash_amount: row.get(3),
+        cash_currency: row.get(4),
+        cash_date: row.get(5),
+        related_trans: row.get(6),
+        position: row.get(7),
+        note: row.get(8),
+        execution_meta: row.get(9),
+        recorded_at: row.get(10),
     }
+}
 
-    pub fn from_transaction(transaction: &Transaction) -> RawTransaction {
-        let id = transaction.id.map(|x| x as i32);
-        let cash_amount = transaction.cash_flow.amount.amount;
-        let cash_currency = transaction.cash_flow.amount.currency.to_string();
-        let note = transaction.note.clone();
-        let mut raw_transaction = RawTransaction {
-            id,
-            trans_type: String::new(),
-            asset: None,
-            cash_amount,
-            cash_currency,
-            cash_date: transaction.cash_flow.date,
-            related_trans: None,
-            position: None,
-            note,
-        };
-        match transaction.transaction_type {
-            TransactionType::Cash => raw_transaction.trans_type = CASH.to_string(),
-            TransactionType::Asset { asset_id, position } => {
-                raw_transaction.trans_type = ASSET.to_string();
-                raw_transaction.asset = Some(asset_id as i32);
-                raw_transaction.position = Some(position);
-            }
-            TransactionType::Dividend { asset_id } => {
-                raw_transaction.trans_type = DIVIDEND.to_string();
-                raw_transaction.asset = Some(asset_id as i32);
-            }
-            TransactionType::Interest { asset_id } => {
-                raw_transaction.trans_type = INTEREST.to_string();
-                raw_transaction.asset = Some(asset_id as i32);
-            }
-            TransactionType::Tax { transaction_ref } => {
-                raw_transaction.trans_type = TAX.to_string();
-                raw_transaction.related_trans = transaction_ref.map(|x| x as i32);
-            }
-            TransactionType::Fee { transaction_ref } => {
-                raw_transaction.trans_type = FEE.to_string();
-                raw_transaction.related_trans = transaction_ref.map(|x| x as i32);
-            }
-        };
-        raw_transaction
+impl PostgresDB<'_> {
+    /// Get all transactions matching the given SQL condition
+    fn query_transactions(
+        &mut self,
+        condition: &str,
+        params: &[&(dyn ToSql + Sync)],
+    ) -> Result<Vec<Transaction>, DataError> {
+        let mut transactions = Vec::new();
+        for row in self
+            .conn
+            .get_mut()
+            .query(
+                format!(
+                    "SELECT {} FROM transactions WHERE {}",
+                    TRANSACTION_COLUMNS, condition
+                )
+                .as_str(),
+                params,
+            )
+            .map_err(|e| DataError::NotFound(e.to_string()))?
+        {
+            transactions.push(raw_transaction_from_row(&row).to_transaction()?);
+        }
+        Ok(transactions)
     }
 }
 
@@ -129,13 +60,18 @@ impl TransactionHandler for PostgresDB<'_> {
     // insert, get, update and delete for transactions
     fn insert_transaction(&mut self, transaction: &Transaction) -> Result<usize, DataError> {
         let transaction = RawTransaction::from_transaction(transaction);
+        transaction.validate()?;
+        let recorded_at = transaction
+            .recorded_at
+            .unwrap_or_else(|| self.clock.now_utc());
         let row = self
             .conn
+            .get_mut()
             .query_one(
                 "INSERT INTO transactions (trans_type, asset_id, cash_amount, 
                 cash_currency, cash_date, related_trans, position,
-                note) 
-                VALUES ($1, $2, $3, $4, $5, $6, $7, $8) RETURNING id",
+                note, execution_meta, recorded_at)
+                VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10) RETURNING id",
                 &[
                     &transaction.trans_type,
                     &transaction.asset,
@@ -145,6 +81,8 @@ impl TransactionHandler for PostgresDB<'_> {
                     &transaction.related_trans,
                     &transaction.position,
                     &transaction.note,
+                    &transaction.execution_meta,
+                    &recorded_at,
                 ],
             )
             .map_err(|e| DataError::InsertFailed(e.to_string()))?;
@@ -155,54 +93,21 @@ impl TransactionHandler for PostgresDB<'_> {
     fn get_transaction_by_id(&mut self, id: usize) -> Result<Transaction, DataError> {
         let row = self
             .conn
+            .get_mut()
             .query_one(
-                "SELECT trans_type, asset_id, 
-        cash_amount, cash_currency, cash_date, related_trans, position, note 
-        FROM transactions
-        WHERE id=$1",
+                format!(
+                    "SELECT {} FROM transactions WHERE id=$1",
+                    TRANSACTION_COLUMNS
+                )
+                .as_str(),
                 &[&(id as i32)],
             )
             .map_err(|e| DataError::NotFound(e.to_string()))?;
-        let transaction = RawTransaction {
-            id: Some(id as i32),
-            trans_type: row.get(0),
-            asset: row.get(1),
-            cash_amount: row.get(2),
-            cash_currency: row.get(3),
-            cash_date: row.get(4),
-            related_trans: row.get(5),
-            position: row.get(6),
-            note: row.get(7),
-        };
-        Ok(transaction.to_transaction()?)
+        raw_transaction_from_row(&row).to_transaction()
     }
 
     fn get_all_transactions(&mut self) -> Result<Vec<Transaction>, DataError> {
-        let mut transactions = Vec::new();
-        for row in self
-            .conn
-            .query(
-                "SELECT id, trans_type, asset_id, 
-        cash_amount, cash_currency, cash_date, related_trans, position, note 
-        FROM transactions",
-                &[],
-            )
-            .map_err(|e| DataError::NotFound(e.to_string()))?
-        {
-            let transaction = RawTransaction {
-                id: row.get(0),
-                trans_type: row.get(1),
-                asset: row.get(2),
-                cash_amount: row.get(3),
-                cash_currency: row.get(4),
-                cash_date: row.get(5),
-                related_trans: row.get(6),
-                position: row.get(7),
-                note: row.get(8),
-            };
-            transactions.push(transaction.to_transaction()?);
-        }
-        Ok(transactions)
+        self.query_transactions("TRUE", &[])
     }
 
     fn update_transaction(&mut self, transaction: &Transaction) -> Result<(), DataError> {
@@ -213,7 +118,10 @@ impl TransactionHandler for PostgresDB<'_> {
         }
         let id = transaction.id.unwrap() as i32;
         let transaction = RawTransaction::from_transaction(transaction);
+        transaction.validate()?;
+        // the time the transaction has been recorded is kept
         self.conn
+            .get_mut()
             .execute(
                 "UPDATE transactions SET 
                 trans_type=$2, 
@@ -223,7 +131,8 @@ impl TransactionHandler for PostgresDB<'_> {
                 cash_date=$6,
                 related_trans=$7,
                 position=$8,
-                note=$9
+                note=$9,
+                execution_meta=$10
             WHERE id=$1",
                 &[
                     &id,
@@ -235,16 +144,170 @@ impl TransactionHandler for PostgresDB<'_> {
                     &transaction.related_trans,
                     &transaction.position,
                     &transaction.note,
+                    &transaction.execution_meta,
                 ],
             )
             .map_err(|e| DataError::InsertFailed(e.to_string()))?;
         Ok(())
     }
 
+    fn find_transaction_by_order_id(
+        &mut self,
+        order_id: &str,
+    ) -> Result<Vec<Transaction>, DataError> {
+        self.query_transactions(
+            "execution_meta->>$1 = $2 ORDER BY id",
+            &[&ORDER_ID_KEY, &order_id],
+        )
+    }
+
     fn delete_transaction(&mut self, id: usize) -> Result<(), DataError> {
         self.conn
+            .get_mut()
             .execute("DELETE FROM transactions WHERE id=$1;", &[&(id as i32)])
             .map_err(|e| DataError::InsertFailed(e.to_string()))?;
         Ok(())
     }
+
+    fn insert_lot_selection(
+        &mut self,
+        sell_transaction_id: usize,
+        lots: &[LotSelection],
+    ) -> Result<(), DataError> {
+        for lot in lots {
+            self.conn
+                .get_mut()
+                .execute(
+                    "INSERT INTO lot_selections (sell_trans_id, buy_trans_id, quantity)
+                    VALUES ($1, $2, $3)",
+                    &[
+                        &(sell_transaction_id as i32),
+                        &(lot.buy_transaction_id as i32),
+                        &lot.quantity,
+                    ],
+                )
+                .map_err(|e| DataError::InsertFailed(e.to_string()))?;
+        }
+        Ok(())
+    }
+
+    fn insert_transaction_with_lot_selection(
+        &mut self,
+        transaction: &Transaction,
+        lots: &[LotSelection],
+    ) -> Result<usize, DataError> {
+        self.conn
+            .get_mut()
+            .batch_execute("BEGIN")
+            .map_err(|e| DataError::DataAccessFailure(e.to_string()))?;
+        let result = self.insert_transaction(transaction).and_then(|id| {
+            self.insert_lot_selection(id, lots)?;
+            Ok(id)
+        });
+        match result {
+            Ok(id) => {
+                self.conn
+                    .get_mut()
+                    .batch_execute("COMMIT")
+                    .map_err(|e| DataError::DataAccessFailure(e.to_string()))?;
+                Ok(id)
+            }
+            Err(err) => {
+                self.conn
+                    .get_mut()
+                    .batch_execute("ROLLBACK")
+                    .map_err(|e| DataError::DataAccessFailure(e.to_string()))?;
+                Err(err)
+            }
+        }
+    }
+
+    fn get_lot_selection(
+        &mut self,
+        sell_transaction_id: usize,
+    ) -> Result<Vec<LotSelection>, DataError> {
+        let mut lots = Vec::new();
+        for row in self
+            .conn
+            .get_mut()
+            .query(
+                "SELECT buy_trans_id, quantity FROM lot_selections
+                WHERE sell_trans_id=$1 ORDER BY id",
+                &[&(sell_transaction_id as i32)],
+            )
+            .map_err(|e| DataError::NotFound(e.to_string()))?
+        {
+            let buy_transaction_id: i32 = row.get(0);
+            lots.push(LotSelection {
+                buy_transaction_id: buy_transaction_id as usize,
+                quantity: row.get(1),
+            });
+        }
+        Ok(lots)
+    }
+
+    fn set_fee_schedule(&mut self, schedule: &FeeSchedule) -> Result<(), DataError> {
+        schedule.validate()?;
+        self.conn
+            .get_mut()
+            .execute(
+                "INSERT INTO fee_schedules (account_id, currency, rate_pa, min_fee, max_fee,
+                fixed_fee, billing_period, start_date)
+                VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+                ON CONFLICT (account_id) DO UPDATE SET currency=$2, rate_pa=$3, min_fee=$4,
+                max_fee=$5, fixed_fee=$6, billing_period=$7, start_date=$8",
+                &[
+                    &(schedule.account_id as i32),
+                    &schedule.currency.to_string(),
+                    &schedule.rate_pa,
+                    &schedule.min_fee,
+                    &schedule.max_fee,
+                    &schedule.fixed_fee,
+                    &schedule.billing_period,
+                    &schedule.start,
+                ],
+            )
+            .map_err(|e| DataError::InsertFailed(e.to_string()))?;
+        Ok(())
+    }
+
+    fn get_fee_schedule(&mut self, account_id: usize) -> Result<Option<FeeSchedule>, DataError> {
+        let row = self
+            .conn
+            .get_mut()
+            .query_opt(
+                "SELECT currency, rate_pa, min_fee, max_fee, fixed_fee, billing_period, start_date
+                FROM fee_schedules WHERE account_id=$1",
+                &[&(account_id as i32)],
+            )
+            .map_err(|e| DataError::NotFound(e.to_string()))?;
+        match row {
+            Some(row) => {
+                let currency: String = row.get(0);
+                Ok(Some(FeeSchedule {
+                    account_id,
+                    currency: Currency::from_str(&currency)
+                        .map_err(|e| DataError::NotFound(e.to_string()))?,
+                    rate_pa: row.get(1),
+                    min_fee: row.get(2),
+                    max_fee: row.get(3),
+                    fixed_fee: row.get(4),
+                    billing_period: row.get(5),
+                    start: row.get(6),
+                }))
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn delete_fee_schedule(&mut self, account_id: usize) -> Result<(), DataError> {
+        self.conn
+            .get_mut()
+            .execute(
+                "DELETE FROM fee_schedules WHERE account_id=$1;",
+                &[&(account_id as i32)],
+            )
+            .map_err(|e| DataError::DeleteFailed(e.to_string()))?;
+        Ok(())
+    }
 }
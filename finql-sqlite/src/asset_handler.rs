@@ -1,21 +1,158 @@
 ///! Implementation of sqlite3 data handler
 
 use std::str::FromStr;
-use rusqlite::{params, Row, NO_PARAMS};
+use rusqlite::types::{ToSql, Type};
+use rusqlite::{params, OptionalExtension, Row, NO_PARAMS};
 
 use super::SqliteDB;
-use finql_data::asset::Asset;
-use finql_data::{AssetHandler, DataError};
+use finql_data::asset::{
+    Asset, AssetRepresentation, AssetSearchQuery, AssetSortKey, CurrencyExposure,
+    DistributionPolicy, Page,
+};
+use finql_data::asset_handler::{check_representation_cycle, update_each_asset};
+use finql_data::{AssetHandler, DataError, InstrumentRecord};
 use finql_data::currency::Currency;
 
+/// Construct the contract terms of an asset from a row of the `instrument_terms` table
+fn instrument_record_from_row(row: &Row) -> rusqlite::Result<InstrumentRecord> {
+    let asset_id: i64 = row.get(0)?;
+    let payload: String = row.get(3)?;
+    let payload = serde_json::from_str(&payload)
+        .map_err(|e| rusqlite::Error::FromSqlConversionFailure(3, Type::Text, Box::new(e)))?;
+    Ok(InstrumentRecord {
+        asset_id: asset_id as usize,
+        kind: row.get(1)?,
+        version: row.get(2)?,
+        payload,
+    })
+}
+
+/// Columns to select to construct an asset by `asset_from_row`
+const ASSET_COLUMNS: &str = "id, name, wkn, isin, note, reference_currency, distribution_policy,
+    hedged_to, asset_class, sector, country, expected_quote_frequency";
+
+/// Construct an asset from a row containing the columns given by `ASSET_COLUMNS`
+fn asset_from_row(row: &Row) -> rusqlite::Result<Asset> {
+    let id: i64 = row.get(0)?;
+    let reference_currency: Option<String> = row.get(5)?;
+    let reference_currency = reference_currency
+        .map(|currency| Currency::from_str(&currency))
+        .transpose()
+        .map_err(|e| rusqlite::Error::FromSqlConversionFailure(5, Type::Text, Box::new(e)))?;
+    let distribution_policy: Option<String> = row.get(6)?;
+    let distribution_policy = distribution_policy
+        .map(|policy| DistributionPolicy::from_str(&policy))
+        .transpose()
+        .map_err(|e| rusqlite::Error::FromSqlConversionFailure(6, Type::Text, Box::new(e)))?;
+    let hedged_to: Option<String> = row.get(7)?;
+    let hedged_to = hedged_to
+        .map(|currency| Currency::from_str(&currency))
+        .transpose()
+        .map_err(|e| rusqlite::Error::FromSqlConversionFailure(7, Type::Text, Box::new(e)))?;
+    Ok(Asset {
+        id: Some(id as usize),
+        name: row.get(1)?,
+        wkn: row.get(2)?,
+        isin: row.get(3)?,
+        note: row.get(4)?,
+        reference_currency,
+        distribution_policy,
+        hedged_to,
+        asset_class: row.get(8)?,
+        sector: row.get(9)?,
+        country: row.get(10)?,
+        expected_quote_frequency: row.get(11)?,
+    })
+}
+
+impl SqliteDB<'_> {
+    /// Get all assets returned by the given query selecting the columns `ASSET_COLUMNS`
+    fn query_assets(&self, query: &str, params: &[&dyn ToSql]) -> Result<Vec<Asset>, DataError> {
+        let mut stmt = self
+            .conn
+            .prepare(query)
+            .map_err(|e| DataError::NotFound(e.to_string()))?;
+        let asset_map = stmt
+            .query_map(params, asset_from_row)
+            .map_err(|e| DataError::NotFound(e.to_string()))?;
+        let mut assets = Vec::new();
+        for asset in asset_map {
+            assets.push(asset.map_err(|e| DataError::NotFound(e.to_string()))?);
+        }
+        Ok(assets)
+    }
+
+    /// Get the only asset with the given value in the identifier column `isin` or `wkn`
+    fn query_unique_asset(&self, column: &str, value: &str) -> Result<Asset, DataError> {
+        let mut assets = self.query_assets(
+            &format!("SELECT {} FROM assets WHERE {}=?1", ASSET_COLUMNS, column),
+            &[&value],
+        )?;
+        match assets.len() {
+            0 => Err(DataError::NotFound(format!(
+                "no asset with {} '{}'",
+                column, value
+            ))),
+            1 => Ok(assets.remove(0)),
+            n => Err(DataError::AmbiguousMatch(format!(
+                "{} assets with {} '{}'",
+                n, column, value
+            ))),
+        }
+    }
+
+    /// Error of a failed insert of the asset, `DataError::DuplicateAsset` if an asset with the
+    /// same ISIN, WKN or name exists already
+    fn insert_asset_error(&self, asset: &Asset, err: String) -> DataError {
+        let existing = self.query_assets(
+            &format!(
+                "SELECT {} FROM assets WHERE isin=?1 OR wkn=?2 OR name=?3",
+                ASSET_COLUMNS
+            ),
+            &[&asset.isin, &asset.wkn, &asset.name],
+        );
+        let existing = match existing {
+            Ok(existing) => existing,
+            Err(_) => return DataError::InsertFailed(err),
+        };
+        for existing in existing {
+            if let Some(id) = existing.id {
+                let conflict = if asset.isin.is_some() && existing.isin == asset.isin {
+                    format!("ISIN '{}'", existing.isin.unwrap_or_default())
+                } else if asset.wkn.is_some() && existing.wkn == asset.wkn {
+                    format!("WKN '{}'", existing.wkn.unwrap_or_default())
+                } else {
+                    format!("name '{}'", existing.name)
+                };
+                return DataError::DuplicateAsset(id, conflict);
+            }
+        }
+        DataError::InsertFailed(err)
+    }
+}
+
 impl AssetHandler for SqliteDB<'_> {
     fn insert_asset(&mut self, asset: &Asset) -> Result<usize, DataError> {
         self.conn
             .execute(
-                "INSERT INTO assets (name, wkn, isin, note) VALUES (?1, ?2, ?3, ?4)",
-                params![asset.name, asset.wkn, asset.isin, asset.note],
+                "INSERT INTO assets (name, wkn, isin, note, reference_currency, distribution_policy,
+                hedged_to, asset_class, sector, country, expected_quote_frequency)
+                VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)",
+                params![
+                    asset.name,
+                    asset.wkn,
+                    asset.isin,
+                    asset.note,
+                    asset.reference_currency.map(|c| c.to_string()),
+                    asset.distribution_policy.map(|p| p.to_string()),
+                    asset.hedged_to.map(|c| c.to_string()),
+                    asset.asset_class,
+                    asset.sector,
+                    asset.country,
+                    asset.expected_quote_frequency
+                ],
             )
-            .map_err(|e| DataError::InsertFailed(e.to_string()))?;
+            .map_err(|e| self.insert_asset_error(asset, e.to_string()))?;
         let id = self
             .conn
             .query_row(
@@ -52,68 +189,79 @@ impl AssetHandler for SqliteDB<'_> {
         let asset = self
             .conn
             .query_row(
-                "SELECT name, wkn, isin, note FROM assets
-        WHERE id=?;",
-                &[id as i64],
-                |row| {
-                    Ok(Asset {
-                        id: Some(id),
-                        name: row.get(0)?,
-                        wkn: row.get(1)?,
-                        isin: row.get(2)?,
-                        note: row.get(3)?,
-                    })
-                },
+                &format!("SELECT {} FROM assets WHERE id=?;", ASSET_COLUMNS),
+                [id as i64],
+                asset_from_row,
             )
             .map_err(|e| DataError::NotFound(e.to_string()))?;
         Ok(asset)
     }
 
     fn get_asset_by_isin(&mut self, isin: &str) -> Result<Asset, DataError> {
-        let asset = self
-            .conn
-            .query_row(
-                "SELECT id, name, wkn, note FROM assets
-        WHERE isin=?;",
-                &[isin],
-                |row| {
-                    let id: i32 = row.get(0)?;
-                    Ok(Asset {
-                        id: Some(id as usize),
-                        name: row.get(1)?,
-                        wkn: row.get(2)?,
-                        isin: Some(isin.to_string()),
-                        note: row.get(3)?,
-                    })
-                },
-            )
-            .map_err(|e| DataError::NotFound(e.to_string()))?;
-        Ok(asset)
+        self.query_unique_asset("isin", isin)
+    }
+
+    fn get_asset_by_wkn(&mut self, wkn: &str) -> Result<Asset, DataError> {
+        self.query_unique_asset("wkn", wkn)
     }
 
     fn get_all_assets(&mut self) -> Result<Vec<Asset>, DataError> {
-        let mut stmt = self
+        self.query_assets(
+            &format!("SELECT {} FROM assets ORDER BY name;", ASSET_COLUMNS),
+            NO_PARAMS,
+        )
+    }
+
+    fn get_all_assets_sorted(
+        &mut self,
+        key: AssetSortKey,
+        ascending: bool,
+    ) -> Result<Vec<Asset>, DataError> {
+        self.query_assets(
+            &format!(
+                "SELECT {} FROM assets ORDER BY {};",
+                ASSET_COLUMNS,
+                key.order_by(ascending)
+            ),
+            NO_PARAMS,
+        )
+    }
+
+    fn get_assets_page(
+        &mut self,
+        sort: AssetSortKey,
+        ascending: bool,
+        page: Page,
+    ) -> Result<(Vec<Asset>, usize), DataError> {
+        let total: i64 = self
             .conn
-            .prepare("SELECT id, name, wkn, isin, note FROM assets ORDER BY name;")
-            .map_err(|e| DataError::NotFound(e.to_string()))?;
-        let asset_map = stmt
-            .query_map(NO_PARAMS, |row| {
-                let id: i64 = row.get(0)?;
-                let id = Some(id as usize);
-                Ok(Asset {
-                    id,
-                    name: row.get(1)?,
-                    wkn: row.get(2)?,
-                    isin: row.get(3)?,
-                    note: row.get(4)?,
-                })
-            })
+            .query_row("SELECT COUNT(*) FROM assets;", NO_PARAMS, |row| row.get(0))
             .map_err(|e| DataError::NotFound(e.to_string()))?;
-        let mut assets = Vec::new();
-        for asset in asset_map {
-            assets.push(asset.unwrap());
-        }
-        Ok(assets)
+        let assets = self.query_assets(
+            &format!(
+                "SELECT {} FROM assets ORDER BY {} LIMIT {} OFFSET {};",
+                ASSET_COLUMNS,
+                sort.order_by(ascending),
+                page.size,
+                page.offset()
+            ),
+            NO_PARAMS,
+        )?;
+        Ok((assets, total as usize))
+    }
+
+    fn search_assets(&mut self, query: &AssetSearchQuery) -> Result<Vec<Asset>, DataError> {
+        let (condition, params) = query.sql_condition(|n| format!("?{}", n));
+        let params: Vec<&dyn ToSql> = params.iter().map(|p| p as &dyn ToSql).collect();
+        self.query_assets(
+            &format!(
+                "SELECT {} FROM assets WHERE {} ORDER BY name{};",
+                ASSET_COLUMNS,
+                condition,
+                query.sql_limit()
+            ),
+            &params,
+        )
     }
 
     fn update_asset(&mut self, asset: &Asset) -> Result<(), DataError> {
@@ -125,14 +273,50 @@ impl AssetHandler for SqliteDB<'_> {
         let id = asset.id.unwrap() as i64;
         self.conn
             .execute(
-                "UPDATE assets SET name=?2, wkn=?3, isin=?4, note=?5 
-                WHERE id=?1;",
-                params![id, asset.name, asset.wkn, asset.isin, asset.note],
+                "UPDATE assets SET name=?2, wkn=?3, isin=?4, note=?5, reference_currency=?6,
+                distribution_policy=?7, hedged_to=?8, asset_class=?9, sector=?10, country=?11,
+                expected_quote_frequency=?12 WHERE id=?1;",
+                params![
+                    id,
+                    asset.name,
+                    asset.wkn,
+                    asset.isin,
+                    asset.note,
+                    asset.reference_currency.map(|c| c.to_string()),
+                    asset.distribution_policy.map(|p| p.to_string()),
+                    asset.hedged_to.map(|c| c.to_string()),
+                    asset.asset_class,
+                    asset.sector,
+                    asset.country,
+                    asset.expected_quote_frequency
+                ],
             )
             .map_err(|e| DataError::InsertFailed(e.to_string()))?;
         Ok(())
     }
 
+    fn update_assets(
+        &mut self,
+        assets: &[Asset],
+        exposures: &[CurrencyExposure],
+    ) -> Result<(), DataError> {
+        self.conn
+            .execute_batch("BEGIN;")
+            .map_err(|e| DataError::DataAccessFailure(e.to_string()))?;
+        match update_each_asset(self, assets, exposures) {
+            Ok(()) => self
+                .conn
+                .execute_batch("COMMIT;")
+                .map_err(|e| DataError::DataAccessFailure(e.to_string())),
+            Err(err) => {
+                self.conn
+                    .execute_batch("ROLLBACK;")
+                    .map_err(|e| DataError::DataAccessFailure(e.to_string()))?;
+                Err(err)
+            }
+        }
+    }
+
     fn delete_asset(&mut self, id: usize) -> Result<(), DataError> {
         self.conn
             .execute("DELETE FROM assets WHERE id=?1;", params![id as i64])
@@ -160,4 +344,467 @@ impl AssetHandler for SqliteDB<'_> {
         }
         Ok(currencies)
     }
+
+    fn set_instrument_record(&mut self, record: &InstrumentRecord) -> Result<(), DataError> {
+        self.conn
+            .execute(
+                "INSERT OR REPLACE INTO instrument_terms (asset_id, kind, version, payload)
+                VALUES (?1, ?2, ?3, ?4)",
+                params![
+                    record.asset_id as i64,
+                    record.kind,
+                    record.version,
+                    record.payload.to_string()
+                ],
+            )
+            .map_err(|e| DataError::InsertFailed(e.to_string()))?;
+        Ok(())
+    }
+
+    fn get_instrument_record(
+        &mut self,
+        asset_id: usize,
+    ) -> Result<Option<InstrumentRecord>, DataError> {
+        self.conn
+            .query_row(
+                "SELECT asset_id, kind, version, payload FROM instrument_terms WHERE asset_id=?1",
+                params![asset_id as i64],
+                instrument_record_from_row,
+            )
+            .optional()
+            .map_err(|e| DataError::NotFound(e.to_string()))
+    }
+
+    fn get_instrument_records(&mut self, kind: &str) -> Result<Vec<InstrumentRecord>, DataError> {
+        let mut stmt = self
+            .conn
+            .prepare(
+                "SELECT asset_id, kind, version, payload FROM instrument_terms
+                WHERE kind=?1 ORDER BY asset_id",
+            )
+            .map_err(|e| DataError::NotFound(e.to_string()))?;
+        let records = stmt
+            .query_map(params![kind], instrument_record_from_row)
+            .map_err(|e| DataError::NotFound(e.to_string()))?;
+        records
+            .collect::<rusqlite::Result<Vec<InstrumentRecord>>>()
+            .map_err(|e| DataError::NotFound(e.to_string()))
+    }
+
+    fn delete_instrument_record(&mut self, asset_id: usize) -> Result<(), DataError> {
+        self.conn
+            .execute(
+                "DELETE FROM instrument_terms WHERE asset_id=?1;",
+                params![asset_id as i64],
+            )
+            .map_err(|e| DataError::DeleteFailed(e.to_string()))?;
+        Ok(())
+    }
+
+    fn set_currency_exposure(&mut self, exposure: &CurrencyExposure) -> Result<(), DataError> {
+        exposure.validate()?;
+        self.delete_currency_exposure(exposure.asset_id)?;
+        for (currency, weight) in &exposure.weights {
+            self.conn
+                .execute(
+                    "INSERT INTO currency_exposures (asset_id, currency, weight) VALUES (?1, ?2, ?3)",
+                    params![exposure.asset_id as i64, currency.to_string(), weight],
+                )
+                .map_err(|e| DataError::InsertFailed(e.to_string()))?;
+        }
+        Ok(())
+    }
+
+    fn get_currency_exposure(
+        &mut self,
+        asset_id: usize,
+    ) -> Result<Option<CurrencyExposure>, DataError> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT currency, weight FROM currency_exposures WHERE asset_id=? ORDER BY currency")
+            .map_err(|e| DataError::NotFound(e.to_string()))?;
+        let rows = stmt
+            .query_map(params![asset_id as i64], |row| {
+                let currency: String = row.get(0)?;
+                let weight: f64 = row.get(1)?;
+                Ok((currency, weight))
+            })
+            .map_err(|e| DataError::NotFound(e.to_string()))?;
+        let mut weights = Vec::new();
+        for row in rows {
+            let (currency, weight) = row.map_err(|e| DataError::NotFound(e.to_string()))?;
+            let currency =
+                Currency::from_str(&currency).map_err(|e| DataError::NotFound(e.to_string()))?;
+            weights.push((currency, weight));
+        }
+        if weights.is_empty() {
+            Ok(None)
+        } else {
+            Ok(Some(CurrencyExposure { asset_id, weights }))
+        }
+    }
+
+    fn delete_currency_exposure(&mut self, asset_id: usize) -> Result<(), DataError> {
+        self.conn
+            .execute(
+                "DELETE FROM currency_exposures WHERE asset_id=?1;",
+                params![asset_id as i64],
+            )
+            .map_err(|e| DataError::DeleteFailed(e.to_string()))?;
+        Ok(())
+    }
+
+    fn set_asset_representation(
+        &mut self,
+        representation: &AssetRepresentation,
+    ) -> Result<(), DataError> {
+        representation.validate()?;
+        check_representation_cycle(self, representation)?;
+        self.delete_asset_representation(representation.asset_id)?;
+        self.conn
+            .execute(
+                "INSERT INTO asset_representations (asset_id, underlying_id, ratio) VALUES (?1, ?2, ?3)",
+                params![
+                    representation.asset_id as i64,
+                    representation.underlying_id as i64,
+                    representation.ratio
+                ],
+            )
+            .map_err(|e| DataError::InsertFailed(e.to_string()))?;
+        Ok(())
+    }
+
+    fn get_asset_representation(
+        &mut self,
+        asset_id: usize,
+    ) -> Result<Option<AssetRepresentation>, DataError> {
+        self.conn
+            .query_row(
+                "SELECT asset_id, underlying_id, ratio FROM asset_representations WHERE asset_id=?",
+                params![asset_id as i64],
+                asset_representation_from_row,
+            )
+            .optional()
+            .map_err(|e| DataError::NotFound(e.to_string()))
+    }
+
+    fn get_representations_of(
+        &mut self,
+        underlying_id: usize,
+    ) -> Result<Vec<AssetRepresentation>, DataError> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT asset_id, underlying_id, ratio FROM asset_representations WHERE underlying_id=? ORDER BY asset_id")
+            .map_err(|e| DataError::NotFound(e.to_string()))?;
+        let representations = stmt
+            .query_map(params![underlying_id as i64], asset_representation_from_row)
+            .map_err(|e| DataError::NotFound(e.to_string()))?;
+        representations
+            .collect::<rusqlite::Result<Vec<AssetRepresentation>>>()
+            .map_err(|e| DataError::NotFound(e.to_string()))
+    }
+
+    fn delete_asset_representation(&mut self, asset_id: usize) -> Result<(), DataError> {
+        self.conn
+            .execute(
+                "DELETE FROM asset_representations WHERE asset_id=?1;",
+                params![asset_id as i64],
+            )
+            .map_err(|e| DataError::DeleteFailed(e.to_string()))?;
+        Ok(())
+    }
+}
+
+/// Construct an asset representation from a row of the `asset_representations` table
+fn asset_representation_from_row(row: &Row) -> rusqlite::Result<AssetRepresentation> {
+    let asset_id: i64 = row.get(0)?;
+    let underlying_id: i64 = row.get(1)?;
+    Ok(AssetRepresentation {
+        asset_id: asset_id as usize,
+        underlying_id: underlying_id as usize,
+        ratio: row.get(2)?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{TimeZone, Utc};
+    use rusqlite::Connection;
+
+    use finql_data::{Quote, QuoteHandler, Ticker, TickerUsage};
+
+    fn names(assets: Vec<Asset>) -> Vec<String> {
+        assets.into_iter().map(|asset| asset.name).collect()
+    }
+
+    #[test]
+    fn search_assets_by_multiple_fields() {
+        let conn = Connection::open(":memory:").unwrap();
+        let mut db = SqliteDB::new(&conn);
+        db.init().unwrap();
+        let basf = db
+            .insert_asset(&Asset::new(
+                None,
+                "BASF AG",
+                Some("BASF11".to_string()),
+                Some("DE000BASF111".to_string()),
+                None,
+            ))
+            .unwrap();
+        db.insert_asset(&Asset::new(
+            None,
+            "Basel 100%",
+            None,
+            Some("CH0012345678".to_string()),
+            None,
+        ))
+        .unwrap();
+        let siemens = db
+            .insert_asset(&Asset::new(
+                None,
+                "Siemens AG",
+                Some("723610".to_string()),
+                Some("DE0007236101".to_string()),
+                None,
+            ))
+            .unwrap();
+        for (asset, name) in &[(basf, "BAS.DE"), (siemens, "SIE.DE")] {
+            db.insert_ticker(&Ticker {
+                id: None,
+                name: name.to_string(),
+                asset: *asset,
+                source: "manual".to_string(),
+                priority: 1,
+                currency: Currency::from_str("EUR").unwrap(),
+                factor: 1.0,
+                source_url: None,
+                usage: TickerUsage::Both,
+                last_quote_time: None,
+                source_chain: Vec::new(),
+            })
+            .unwrap();
+        }
+        db.insert_quote(&Quote {
+            id: None,
+            ticker: 1,
+            price: 60.0,
+            time: Utc.ymd(2021, 3, 1).and_hms(17, 30, 0),
+            volume: None,
+            quality_score: None,
+            source: None,
+            open: None,
+            high: None,
+            low: None,
+            bid: None,
+            ask: None,
+        })
+        .unwrap();
+
+        let db: &mut dyn QuoteHandler = &mut db;
+        let all = AssetSearchQuery::new().execute(db).unwrap();
+        assert_eq!(names(all), vec!["BASF AG", "Basel 100%", "Siemens AG"]);
+        let query = AssetSearchQuery::new().name_contains("bas");
+        assert_eq!(names(query.execute(db).unwrap()), vec!["BASF AG", "Basel 100%"]);
+        let query = query.isin_prefix("DE");
+        assert_eq!(names(query.execute(db).unwrap()), vec!["BASF AG"]);
+        // wildcards are matched literally
+        let query = AssetSearchQuery::new().name_contains("0%");
+        assert_eq!(names(query.execute(db).unwrap()), vec!["Basel 100%"]);
+        let query = AssetSearchQuery::new().isin_prefix("_E");
+        assert!(query.execute(db).unwrap().is_empty());
+        let query = AssetSearchQuery::new().wkn_exact("723610");
+        assert_eq!(names(query.execute(db).unwrap()), vec!["Siemens AG"]);
+        let query = AssetSearchQuery::new().has_tickers(false);
+        assert_eq!(names(query.execute(db).unwrap()), vec!["Basel 100%"]);
+        let query = AssetSearchQuery::new().has_tickers(true).has_quotes(false);
+        assert_eq!(names(query.execute(db).unwrap()), vec!["Siemens AG"]);
+        let query = AssetSearchQuery::new().has_quotes(true);
+        assert_eq!(names(query.execute(db).unwrap()), vec!["BASF AG"]);
+    }
+
+    #[test]
+    fn ambiguous_identifiers() {
+        let conn = Connection::open(":memory:").unwrap();
+        let mut db = SqliteDB::new(&conn);
+        db.init().unwrap();
+        // assets table of a database created without unique identifiers
+        conn.execute_batch(
+            "DROP TABLE assets;
+            CREATE TABLE assets (
+                id INTEGER PRIMARY KEY,
+                name TEXT NOT NULL,
+                wkn TEXT,
+                isin TEXT,
+                note TEXT,
+                reference_currency TEXT,
+                distribution_policy TEXT,
+                hedged_to TEXT,
+                asset_class TEXT,
+                sector TEXT,
+                country TEXT,
+                expected_quote_frequency TEXT
+            );
+            INSERT INTO assets (name, wkn, isin) VALUES
+                ('BASF AG', 'BASF11', 'DE000BASF111'),
+                ('BASF SE', 'BASF11', 'DE000BASF111'),
+                ('Siemens AG', '723610', 'DE0007236101');",
+        )
+        .unwrap();
+        // the unique keys can't be added while there are duplicates
+        match db.init() {
+            Err(DataError::DuplicateAsset(id, conflicts)) => {
+                assert_eq!(id, 1);
+                assert_eq!(
+                    conflicts,
+                    "ISIN 'DE000BASF111' of assets 1, 2; WKN 'BASF11' of assets 1, 2"
+                );
+            }
+            result => panic!("expected duplicate assets, got {:?}", result),
+        }
+        assert!(!db.has_index("assets_isin_unique").unwrap());
+        assert!(matches!(
+            db.get_asset_by_isin("DE000BASF111"),
+            Err(DataError::AmbiguousMatch(_))
+        ));
+        assert!(matches!(
+            db.get_asset_by_wkn("BASF11"),
+            Err(DataError::AmbiguousMatch(_))
+        ));
+        assert_eq!(db.get_asset_by_wkn("723610").unwrap().name, "Siemens AG");
+
+        conn.execute("DELETE FROM assets WHERE name='BASF SE'", NO_PARAMS)
+            .unwrap();
+        db.init().unwrap();
+        assert!(db.has_index("assets_isin_unique").unwrap());
+        assert!(matches!(
+            db.insert_asset(&Asset::new(
+                None,
+                "BASF SE",
+                Some("BASF11".to_string()),
+                None,
+                None
+            )),
+            Err(DataError::DuplicateAsset(1, _))
+        ));
+    }
+
+    #[test]
+    fn migrate_option_terms_table() {
+        let conn = Connection::open(":memory:").unwrap();
+        let mut db = SqliteDB::new(&conn);
+        db.init().unwrap();
+        let underlying_id = db
+            .insert_asset(&Asset::new(None, "BASF AG", None, None, None))
+            .unwrap();
+        let option_id = db
+            .insert_asset(&Asset::new(None, "BASF Call", None, None, None))
+            .unwrap();
+        conn.execute(
+            "CREATE TABLE option_terms (
+                asset_id INTEGER PRIMARY KEY,
+                underlying_id INTEGER NOT NULL,
+                strike REAL NOT NULL,
+                expiry TEXT NOT NULL,
+                option_type TEXT NOT NULL,
+                contract_size REAL NOT NULL DEFAULT 1.0
+            )",
+            NO_PARAMS,
+        )
+        .unwrap();
+        conn.execute(
+            "INSERT INTO option_terms VALUES (?1, ?2, 52.0, '2021-06-01', 'call', 100.0)",
+            params![option_id as i64, underlying_id as i64],
+        )
+        .unwrap();
+
+        db.init().unwrap();
+        let terms = db.get_option_terms(option_id).unwrap().unwrap();
+        assert_eq!(terms.underlying_id, underlying_id);
+        assert_eq!(terms.expiry, chrono::NaiveDate::from_ymd(2021, 6, 1));
+        assert_eq!(terms.contract_size, 100.);
+        let err = conn
+            .execute("SELECT * FROM option_terms", NO_PARAMS)
+            .unwrap_err();
+        assert!(err.to_string().contains("no such table"));
+    }
+
+    #[test]
+    fn upgrade_instrument_versions() {
+        use finql_data::{get_instrument, migrate_instruments, set_instrument, InstrumentTerms};
+        use serde_json::{json, Value};
+
+        /// Terms of version 2, version 1 had the rate in percent and no day count convention
+        #[derive(Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+        struct Deposit {
+            rate: f64,
+            day_count: String,
+        }
+
+        impl InstrumentTerms for Deposit {
+            const KIND: &'static str = "deposit";
+            const VERSION: u32 = 2;
+
+            fn upgrade(version: u32, payload: Value) -> Result<Value, DataError> {
+                match version {
+                    1 => Ok(json!({
+                        "rate": payload["rate"].as_f64().unwrap_or_default() / 100.,
+                        "day_count": "act/365",
+                    })),
+                    _ => Err(DataError::VersionMismatch(format!("version {}", version))),
+                }
+            }
+        }
+
+        let conn = Connection::open(":memory:").unwrap();
+        let mut db = SqliteDB::new(&conn);
+        db.init().unwrap();
+        let mut ids = Vec::new();
+        for name in &["Deposit 1", "Deposit 2", "Deposit 3"] {
+            ids.push(
+                db.insert_asset(&Asset::new(None, name, None, None, None))
+                    .unwrap(),
+            );
+        }
+        let old = |asset_id, rate| InstrumentRecord {
+            asset_id,
+            kind: "deposit".to_string(),
+            version: 1,
+            payload: json!({ "rate": rate }),
+        };
+        db.set_instrument_record(&old(ids[0], 2.)).unwrap();
+        db.set_instrument_record(&old(ids[1], 3.)).unwrap();
+        let current = Deposit {
+            rate: 0.01,
+            day_count: "30/360".to_string(),
+        };
+        set_instrument(&mut db, ids[2], &current).unwrap();
+
+        // old versions are upgraded on read, but stay unchanged in the database
+        let deposit: Deposit = get_instrument(&mut db, ids[0]).unwrap().unwrap();
+        assert_eq!(deposit.rate, 0.02);
+        assert_eq!(deposit.day_count, "act/365");
+        assert_eq!(db.get_instrument_record(ids[0]).unwrap().unwrap().version, 1);
+        assert_eq!(migrate_instruments::<Deposit, _>(&mut db).unwrap(), 2);
+        let record = db.get_instrument_record(ids[1]).unwrap().unwrap();
+        assert_eq!(record.version, 2);
+        assert_eq!(record.payload, json!({"rate": 0.03, "day_count": "act/365"}));
+        assert_eq!(migrate_instruments::<Deposit, _>(&mut db).unwrap(), 0);
+        let deposit: Option<Deposit> = get_instrument(&mut db, ids[2]).unwrap();
+        assert_eq!(deposit, Some(current));
+
+        // terms written by a newer version are rejected, also by the migration
+        let mut newer = old(ids[0], 2.);
+        newer.version = 3;
+        db.set_instrument_record(&newer).unwrap();
+        let err = get_instrument::<Deposit, _>(&mut db, ids[0]).unwrap_err();
+        assert!(err.is_version_mismatch());
+        newer.version = 0;
+        db.set_instrument_record(&newer).unwrap();
+        assert!(migrate_instruments::<Deposit, _>(&mut db)
+            .unwrap_err()
+            .is_version_mismatch());
+        // option terms of the asset can't be read as deposit
+        assert!(db.get_option_terms(ids[0]).unwrap().is_none());
+    }
 }
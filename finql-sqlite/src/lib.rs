@@ -1,32 +1,213 @@
 ///! Implementation of sqlite3 data handler
 
-use rusqlite::{Connection,NO_PARAMS};
-use chrono::{DateTime, Utc};
+#[cfg(feature = "sqlcipher")]
+use std::path::Path;
+use std::str::FromStr;
 
-use finql_data::{CurrencyConverter, Currency, CurrencyError, QuoteHandler};
+use rusqlite::types::Type;
+use rusqlite::{params, Connection, OptionalExtension, NO_PARAMS};
+use chrono::{DateTime, NaiveDate, Utc};
+
+use finql_data::{
+    normalize_currency_code, Clock, CurrencyConverter, Currency, CurrencyError, DataError,
+    DataHandler, DuplicateQuote, DuplicateSetting, HandlerSource, QuoteHandler, SchemaHandler, SharedHandler,
+    SystemClock, TransactionHandler, UnnormalizedCurrency,
+};
+use finql_data::{InstrumentTerms, OptionTerms, OptionType};
+
+use crate::quote_handler::to_time;
 
-mod raw_transaction;
 pub mod asset_handler;
 pub mod quote_handler;
 pub mod transaction_handler;
+pub mod order_handler;
+pub mod report_handler;
+
+/// Settings tables as pairs of table name and key column, the key must be unique
+const SETTINGS_TABLES: &[(&str, &str)] = &[("rounding_digits", "currency")];
+
+/// Columns referring to transactions as pairs of table name and column, except of the
+/// references between transactions
+const TRANSACTION_LINKS: &[(&str, &str)] = &[
+    ("lot_selections", "sell_trans_id"),
+    ("lot_selections", "buy_trans_id"),
+    ("order_transactions", "transaction_id"),
+    ("rebalancing_transactions", "transaction_id"),
+];
+
+/// Columns containing currency codes as pairs of table name and column
+const CURRENCY_COLUMNS: &[(&str, &str)] =
+    &[("ticker", "currency"), ("transactions", "cash_currency")];
 
 /// Struct to handle connections to sqlite3 databases
 pub struct SqliteDB<'a> {
     /// conn is made public to allow extending this struct outside of the library
     pub conn: &'a Connection,
+    /// Source of the time new transactions are recorded at
+    pub clock: &'a dyn Clock,
 }
 
-impl<'a> SqliteDB<'_> {
+impl<'a> SqliteDB<'a> {
+    /// Handle the given connection, recording transactions at the system time
+    pub fn new(conn: &'a Connection) -> SqliteDB<'a> {
+        Self::with_clock(conn, &SystemClock)
+    }
+
+    /// Handle the given connection, recording transactions at the time given by `clock`
+    pub fn with_clock(conn: &'a Connection, clock: &'a dyn Clock) -> SqliteDB<'a> {
+        SqliteDB { conn, clock }
+    }
+}
+
+/// Sqlite connection owned by a handler, see `SharedSqliteDB`
+#[derive(Debug)]
+pub struct OwnedSqliteDB {
+    pub conn: Connection,
+}
+
+impl OwnedSqliteDB {
+    /// Handler of the owned connection
+    pub fn handler(&self) -> SqliteDB<'_> {
+        SqliteDB::new(&self.conn)
+    }
+}
+
+impl HandlerSource for OwnedSqliteDB {
+    fn with_handler<R>(&mut self, f: impl FnOnce(&mut dyn QuoteHandler) -> R) -> R {
+        f(&mut SqliteDB::new(&self.conn))
+    }
+}
+
+/// Sqlite quote handler owning its connection, which can be shared between threads, e.g.
+/// as `Arc<RwLock<SharedSqliteDB>>` with reads by `QuoteReader` behind the read lock. Reads
+/// run concurrently if further connections to the same database file are given as readers
+/// by `SharedHandler::with_readers`.
+pub type SharedSqliteDB = SharedHandler<OwnedSqliteDB>;
+
+impl SqliteDB<'_> {
+    /// Open a database encrypted by SQLCipher with the given key, creating and initializing the
+    /// database if it does not exist. The handler is given by `handler` of the returned
+    /// connection, which may also be shared as `SharedSqliteDB`. A wrong key is reported as
+    /// `DataError::AuthFailed`.
+    #[cfg(feature = "sqlcipher")]
+    pub fn open_encrypted<P: AsRef<Path>>(path: P, key: &str) -> Result<OwnedSqliteDB, DataError> {
+        let conn =
+            Connection::open(path).map_err(|e| DataError::DataAccessFailure(e.to_string()))?;
+        conn.pragma_update(None, "key", &key)
+            .map_err(|e| DataError::DataAccessFailure(e.to_string()))?;
+        // the key is only checked when the database is read for the first time
+        conn.query_row("SELECT count(*) FROM sqlite_master", NO_PARAMS, |_| Ok(()))
+            .map_err(|e| match e {
+                rusqlite::Error::SqliteFailure(err, _)
+                    if err.code == rusqlite::ErrorCode::NotADatabase =>
+                {
+                    DataError::AuthFailed("wrong key or database is not encrypted".to_string())
+                }
+                e => DataError::DataAccessFailure(e.to_string()),
+            })?;
+        let db = OwnedSqliteDB { conn };
+        db.handler().init()?;
+        Ok(db)
+    }
+
+    /// Encrypt the database opened by `open_encrypted` with a new key
+    #[cfg(feature = "sqlcipher")]
+    pub fn change_key(&self, new_key: &str) -> Result<(), DataError> {
+        self.conn
+            .pragma_update(None, "rekey", &new_key)
+            .map_err(|e| DataError::UpdateFailed(e.to_string()))
+    }
 
     /// Initialize new database by creating table, fill
-    pub fn init(&self) -> rusqlite::Result<()> {
+    ///
+    /// Fails with `DataError::DuplicateAsset` if several assets of a legacy database share an
+    /// ISIN or WKN, which must be resolved before the unique keys on these identifiers are added.
+    /// Likewise, fails with `DataError::DuplicateQuotes` if several quotes share the ticker and
+    /// time, see `collapse_duplicate_quotes`.
+    pub fn init(&self) -> Result<(), DataError> {
+        let to_data_error = |e: rusqlite::Error| DataError::DataAccessFailure(e.to_string());
+        if self.has_table("assets").map_err(to_data_error)? {
+            let duplicates = self.duplicate_asset_identifiers().map_err(to_data_error)?;
+            if let Some((id, _)) = duplicates.first() {
+                let id = *id;
+                let conflicts: Vec<String> =
+                    duplicates.into_iter().map(|(_, conflict)| conflict).collect();
+                return Err(DataError::DuplicateAsset(id, conflicts.join("; ")));
+            }
+        }
+        if self.has_table("quotes").map_err(to_data_error)?
+            && !self
+                .has_index("quotes_ticker_time_unique")
+                .map_err(to_data_error)?
+        {
+            let duplicates = self.duplicate_quotes().map_err(to_data_error)?;
+            if let Some(duplicate) = duplicates.first() {
+                return Err(DataError::DuplicateQuotes(format!(
+                    "{} superseded in total, the first is quote {} of ticker {} at {}",
+                    duplicates.len(),
+                    duplicate.id,
+                    duplicate.ticker_id,
+                    duplicate.time
+                )));
+            }
+        }
+        self.create_tables().map_err(to_data_error)
+    }
+
+    fn create_tables(&self) -> rusqlite::Result<()> {
         self.conn.execute(
             "CREATE TABLE IF NOT EXISTS assets (
                 id INTEGER PRIMARY KEY,
                 name TEXT NOT NULL UNIQUE,
-                wkn TEXT UNIQUE,
-                isin TEXT UNIQUE,
-                note TEXT
+                wkn TEXT,
+                isin TEXT,
+                note TEXT,
+                reference_currency TEXT,
+                distribution_policy TEXT,
+                hedged_to TEXT,
+                asset_class TEXT,
+                sector TEXT,
+                country TEXT,
+                expected_quote_frequency TEXT
+            )",
+            NO_PARAMS,
+        )?;
+        self.conn.execute(
+            "CREATE TABLE IF NOT EXISTS instrument_terms (
+                asset_id INTEGER PRIMARY KEY,
+                kind TEXT NOT NULL,
+                version INTEGER NOT NULL,
+                payload TEXT NOT NULL,
+                FOREIGN KEY(asset_id) REFERENCES assets(id)
+            )",
+            NO_PARAMS,
+        )?;
+        self.conn.execute(
+            "CREATE TABLE IF NOT EXISTS currency_exposures (
+                asset_id INTEGER NOT NULL,
+                currency TEXT NOT NULL,
+                weight REAL NOT NULL,
+                PRIMARY KEY(asset_id, currency),
+                FOREIGN KEY(asset_id) REFERENCES assets(id)
+            )",
+            NO_PARAMS,
+        )?;
+        self.conn.execute(
+            "CREATE TABLE IF NOT EXISTS quota_usage (
+                source TEXT NOT NULL,
+                month TEXT NOT NULL,
+                calls INTEGER NOT NULL,
+                PRIMARY KEY(source, month)
+            )",
+            NO_PARAMS,
+        )?;
+        self.conn.execute(
+            "CREATE TABLE IF NOT EXISTS asset_representations (
+                asset_id INTEGER PRIMARY KEY,
+                underlying_id INTEGER NOT NULL,
+                ratio REAL NOT NULL,
+                FOREIGN KEY(asset_id) REFERENCES assets(id),
+                FOREIGN KEY(underlying_id) REFERENCES assets(id)
             )",
             NO_PARAMS,
         )?;
@@ -41,11 +222,64 @@ impl<'a> SqliteDB<'_> {
                 related_trans KEY,
                 position REAL,
                 note TEXT,
+                execution_meta TEXT,
+                recorded_at TEXT,
                 FOREIGN KEY(asset_id) REFERENCES assets(id),
                 FOREIGN KEY(related_trans) REFERENCES transactions(id)
             );",
             NO_PARAMS,
         )?;
+        self.conn.execute(
+            "CREATE TABLE IF NOT EXISTS lot_selections (
+                id INTEGER PRIMARY KEY,
+                sell_trans_id INTEGER NOT NULL,
+                buy_trans_id INTEGER NOT NULL,
+                quantity REAL NOT NULL,
+                FOREIGN KEY(sell_trans_id) REFERENCES transactions(id),
+                FOREIGN KEY(buy_trans_id) REFERENCES transactions(id)
+            );",
+            NO_PARAMS,
+        )?;
+        self.conn.execute(
+            "CREATE TABLE IF NOT EXISTS fee_schedules (
+                account_id INTEGER PRIMARY KEY,
+                currency TEXT NOT NULL,
+                rate_pa REAL NOT NULL,
+                min_fee REAL,
+                max_fee REAL,
+                fixed_fee REAL NOT NULL,
+                billing_period TEXT NOT NULL,
+                start_date TEXT NOT NULL
+            );",
+            NO_PARAMS,
+        )?;
+        self.conn.execute(
+            "CREATE TABLE IF NOT EXISTS orders (
+                id INTEGER PRIMARY KEY,
+                asset_id INTEGER NOT NULL,
+                side TEXT NOT NULL,
+                quantity REAL,
+                amount REAL,
+                currency TEXT NOT NULL,
+                limit_price REAL,
+                state TEXT NOT NULL,
+                created TEXT NOT NULL,
+                updated TEXT NOT NULL,
+                note TEXT,
+                FOREIGN KEY(asset_id) REFERENCES assets(id)
+            );",
+            NO_PARAMS,
+        )?;
+        self.conn.execute(
+            "CREATE TABLE IF NOT EXISTS order_transactions (
+                order_id INTEGER NOT NULL,
+                transaction_id INTEGER NOT NULL,
+                PRIMARY KEY(order_id, transaction_id),
+                FOREIGN KEY(order_id) REFERENCES orders(id),
+                FOREIGN KEY(transaction_id) REFERENCES transactions(id)
+            );",
+            NO_PARAMS,
+        )?;
         self.conn.execute(
             "CREATE TABLE IF NOT EXISTS ticker (
                 id INTEGER PRIMARY KEY,
@@ -55,6 +289,10 @@ impl<'a> SqliteDB<'_> {
                 priority INTEGER NOT NULL,
                 currency TEXT NOT NULL,
                 factor REAL NOT NULL DEFAULT 1.0,
+                source_url TEXT,
+                usage TEXT NOT NULL DEFAULT 'both',
+                last_quote_time TEXT,
+                source_chain TEXT,
                 FOREIGN KEY(asset_id) REFERENCES assets(id) 
             );",
             NO_PARAMS,
@@ -66,6 +304,13 @@ impl<'a> SqliteDB<'_> {
                 price REAL NOT NULL,
                 time TEXT NOT NULL,
                 volume REAL,
+                quality_score REAL,
+                source TEXT,
+                open REAL,
+                high REAL,
+                low REAL,
+                bid REAL,
+                ask REAL,
                 FOREIGN KEY(ticker_id) REFERENCES ticker(id) );",
             NO_PARAMS,
         )?;
@@ -76,8 +321,564 @@ impl<'a> SqliteDB<'_> {
                 digits INTEGER NOT NULL);",
             NO_PARAMS,
         )?;
+        self.conn.execute(
+            "CREATE TABLE IF NOT EXISTS report_definitions (
+                name TEXT PRIMARY KEY,
+                version INTEGER NOT NULL,
+                payload TEXT NOT NULL
+            );",
+            NO_PARAMS,
+        )?;
+        self.conn.execute(
+            "CREATE TABLE IF NOT EXISTS job_runs (
+                id INTEGER PRIMARY KEY,
+                job TEXT NOT NULL,
+                started TEXT NOT NULL,
+                finished TEXT NOT NULL,
+                success INTEGER NOT NULL,
+                message TEXT
+            );",
+            NO_PARAMS,
+        )?;
+        self.conn.execute(
+            "CREATE TABLE IF NOT EXISTS rebalancing_decisions (
+                id INTEGER PRIMARY KEY,
+                proposal TEXT NOT NULL
+            );",
+            NO_PARAMS,
+        )?;
+        self.conn.execute(
+            "CREATE TABLE IF NOT EXISTS rebalancing_transactions (
+                decision_id INTEGER NOT NULL,
+                transaction_id INTEGER NOT NULL,
+                PRIMARY KEY(decision_id, transaction_id),
+                FOREIGN KEY(decision_id) REFERENCES rebalancing_decisions(id),
+                FOREIGN KEY(transaction_id) REFERENCES transactions(id)
+            );",
+            NO_PARAMS,
+        )?;
+        self.migrate()
+    }
+
+    /// Update tables of databases created with previous versions to the current layout
+    pub fn migrate(&self) -> rusqlite::Result<()> {
+        if !self.has_column("ticker", "source_url")? {
+            self.conn
+                .execute("ALTER TABLE ticker ADD COLUMN source_url TEXT", NO_PARAMS)?;
+        }
+        if !self.has_column("ticker", "usage")? {
+            self.conn.execute(
+                "ALTER TABLE ticker ADD COLUMN usage TEXT NOT NULL DEFAULT 'both'",
+                NO_PARAMS,
+            )?;
+        }
+        if !self.has_column("assets", "reference_currency")? {
+            self.conn.execute(
+                "ALTER TABLE assets ADD COLUMN reference_currency TEXT",
+                NO_PARAMS,
+            )?;
+        }
+        if !self.has_column("assets", "distribution_policy")? {
+            self.conn.execute(
+                "ALTER TABLE assets ADD COLUMN distribution_policy TEXT",
+                NO_PARAMS,
+            )?;
+            self.conn.execute(
+                "UPDATE assets SET distribution_policy = 'unknown'",
+                NO_PARAMS,
+            )?;
+        }
+        if !self.has_column("assets", "hedged_to")? {
+            self.conn
+                .execute("ALTER TABLE assets ADD COLUMN hedged_to TEXT", NO_PARAMS)?;
+        }
+        for column in ["asset_class", "sector", "country", "expected_quote_frequency"] {
+            if !self.has_column("assets", column)? {
+                self.conn.execute(
+                    &format!("ALTER TABLE assets ADD COLUMN {} TEXT", column),
+                    NO_PARAMS,
+                )?;
+            }
+        }
+        if !self.has_column("transactions", "execution_meta")? {
+            self.conn.execute(
+                "ALTER TABLE transactions ADD COLUMN execution_meta TEXT",
+                NO_PARAMS,
+            )?;
+        }
+        if !self.has_column("transactions", "recorded_at")? {
+            self.conn.execute(
+                "ALTER TABLE transactions ADD COLUMN recorded_at TEXT",
+                NO_PARAMS,
+            )?;
+            // the entry time of legacy transactions is unknown, assume the cash date
+            self.conn.execute(
+                "UPDATE transactions SET recorded_at = cash_date || 'T00:00:00+00:00'",
+                NO_PARAMS,
+            )?;
+        }
+        if !self.has_column("quotes", "quality_score")? {
+            self.conn.execute(
+                "ALTER TABLE quotes ADD COLUMN quality_score REAL",
+                NO_PARAMS,
+            )?;
+        }
+        if !self.has_column("quotes", "source")? {
+            self.conn
+                .execute("ALTER TABLE quotes ADD COLUMN source TEXT", NO_PARAMS)?;
+        }
+        for column in &["open", "high", "low", "bid", "ask"] {
+            if !self.has_column("quotes", column)? {
+                self.conn.execute(
+                    &format!("ALTER TABLE quotes ADD COLUMN {} REAL", column),
+                    NO_PARAMS,
+                )?;
+            }
+        }
+        if !self.has_column("ticker", "source_chain")? {
+            self.conn
+                .execute("ALTER TABLE ticker ADD COLUMN source_chain TEXT", NO_PARAMS)?;
+        }
+        if !self.has_column("ticker", "last_quote_time")? {
+            self.conn.execute(
+                "ALTER TABLE ticker ADD COLUMN last_quote_time TEXT",
+                NO_PARAMS,
+            )?;
+            self.conn.execute(
+                "UPDATE ticker SET last_quote_time = \
+                (SELECT MAX(time) FROM quotes WHERE ticker_id = ticker.id)",
+                NO_PARAMS,
+            )?;
+        }
+        self.conn.execute(
+            "CREATE INDEX IF NOT EXISTS quotes_ticker_time ON quotes (ticker_id, time)",
+            NO_PARAMS,
+        )?;
+        // legacy quotes tables with several quotes of the same ticker and time are rejected by
+        // `init` beforehand, see `collapse_duplicate_quotes`
+        self.add_quotes_key()?;
+        // legacy assets tables with duplicate identifiers are rejected by `init` beforehand
+        for column in ["isin", "wkn"] {
+            self.conn.execute(
+                &format!(
+                    "CREATE UNIQUE INDEX IF NOT EXISTS assets_{column}_unique
+                    ON assets ({column}) WHERE {column} IS NOT NULL",
+                    column = column
+                ),
+                NO_PARAMS,
+            )?;
+        }
+        if self.has_table("option_terms")? {
+            self.migrate_option_terms()?;
+        }
+        // legacy settings tables with duplicate keys are left as they are until repaired,
+        // see `collapse_duplicate_settings`
+        if self.duplicate_settings()?.is_empty() {
+            self.add_settings_keys()?;
+        }
+        Ok(())
+    }
+
+    /// Groups of assets sharing an ISIN or WKN, each given by the lowest asset id of the group
+    /// and a description like `ISIN 'DE000BASF111' of assets 1, 4`. Such duplicates only exist
+    /// in databases created without unique keys on these identifiers.
+    fn duplicate_asset_identifiers(&self) -> rusqlite::Result<Vec<(usize, String)>> {
+        let mut duplicates = Vec::new();
+        for column in ["isin", "wkn"] {
+            let mut stmt = self.conn.prepare(&format!(
+                "SELECT {column}, GROUP_CONCAT(id) FROM assets WHERE {column} IS NOT NULL
+                GROUP BY {column} HAVING COUNT(*) > 1 ORDER BY MIN(id)",
+                column = column
+            ))?;
+            let rows = stmt.query_map(NO_PARAMS, |row| {
+                let identifier: String = row.get(0)?;
+                let ids: String = row.get(1)?;
+                Ok((identifier, ids))
+            })?;
+            for row in rows {
+                let (identifier, ids) = row?;
+                let mut ids: Vec<usize> = ids.split(',').filter_map(|id| id.parse().ok()).collect();
+                ids.sort_unstable();
+                let id_list: Vec<String> = ids.iter().map(|id| id.to_string()).collect();
+                duplicates.push((
+                    ids[0],
+                    format!(
+                        "{} '{}' of assets {}",
+                        column.to_uppercase(),
+                        identifier,
+                        id_list.join(", ")
+                    ),
+                ));
+            }
+        }
+        Ok(duplicates)
+    }
+
+    /// Quotes superseded by a newer quote of the same ticker and time
+    fn duplicate_quotes(&self) -> rusqlite::Result<Vec<DuplicateQuote>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT id, ticker_id, time FROM quotes q WHERE EXISTS
+            (SELECT 1 FROM quotes n WHERE n.ticker_id = q.ticker_id AND n.time = q.time
+            AND n.id > q.id)
+            ORDER BY id",
+        )?;
+        let rows = stmt.query_map(NO_PARAMS, |row| {
+            let id: i64 = row.get(0)?;
+            let ticker_id: i64 = row.get(1)?;
+            let time: String = row.get(2)?;
+            let time = to_time(&time).map_err(|e| {
+                rusqlite::Error::FromSqlConversionFailure(2, Type::Text, Box::new(e))
+            })?;
+            Ok(DuplicateQuote {
+                ticker_id: ticker_id as usize,
+                time,
+                id: id as usize,
+            })
+        })?;
+        let mut duplicates = Vec::new();
+        for row in rows {
+            duplicates.push(row?);
+        }
+        Ok(duplicates)
+    }
+
+    /// Add the unique key on ticker and time of the quotes table
+    fn add_quotes_key(&self) -> rusqlite::Result<()> {
+        self.conn.execute(
+            "CREATE UNIQUE INDEX IF NOT EXISTS quotes_ticker_time_unique ON quotes (ticker_id, time)",
+            NO_PARAMS,
+        )?;
+        Ok(())
+    }
+
+    /// Delete the quotes superseded by a newer quote of the same ticker and time and add the
+    /// unique key
+    fn delete_duplicate_quotes(&self) -> rusqlite::Result<Vec<DuplicateQuote>> {
+        let duplicates = self.duplicate_quotes()?;
+        for duplicate in &duplicates {
+            self.conn.execute(
+                "DELETE FROM quotes WHERE id=?1",
+                params![duplicate.id as i64],
+            )?;
+        }
+        self.add_quotes_key()?;
+        Ok(duplicates)
+    }
+
+    /// Rows of the settings tables superseded by a newer row with the same key
+    fn duplicate_settings(&self) -> rusqlite::Result<Vec<DuplicateSetting>> {
+        let mut duplicates = Vec::new();
+        for (table, key) in SETTINGS_TABLES {
+            let mut stmt = self.conn.prepare(&format!(
+                "SELECT id, {key} FROM {table} r WHERE EXISTS
+                (SELECT 1 FROM {table} n WHERE n.{key} = r.{key} AND n.id > r.id)
+                ORDER BY id",
+                table = table,
+                key = key
+            ))?;
+            let rows = stmt.query_map(NO_PARAMS, |row| {
+                let id: i64 = row.get(0)?;
+                Ok(DuplicateSetting {
+                    table: table.to_string(),
+                    key: row.get(1)?,
+                    id: id as usize,
+                })
+            })?;
+            for row in rows {
+                duplicates.push(row?);
+            }
+        }
+        Ok(duplicates)
+    }
+
+    /// Add unique indices on the keys of the settings tables, which are missing in tables
+    /// created by older versions
+    fn add_settings_keys(&self) -> rusqlite::Result<()> {
+        for (table, key) in SETTINGS_TABLES {
+            self.conn.execute(
+                &format!(
+                    "CREATE UNIQUE INDEX IF NOT EXISTS {table}_{key} ON {table} ({key})",
+                    table = table,
+                    key = key
+                ),
+                NO_PARAMS,
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Delete the duplicates of the settings tables and add the unique keys
+    fn delete_duplicate_settings(&self) -> rusqlite::Result<Vec<DuplicateSetting>> {
+        let duplicates = self.duplicate_settings()?;
+        for duplicate in &duplicates {
+            self.conn.execute(
+                &format!("DELETE FROM {} WHERE id=?1", duplicate.table),
+                params![duplicate.id as i64],
+            )?;
+        }
+        self.add_settings_keys()?;
+        Ok(duplicates)
+    }
+
+    /// Currency codes of the currency columns not stored in normal form
+    fn unnormalized_currencies(&self) -> rusqlite::Result<Vec<UnnormalizedCurrency>> {
+        let mut unnormalized = Vec::new();
+        for (table, column) in CURRENCY_COLUMNS {
+            let mut stmt = self.conn.prepare(&format!(
+                "SELECT id, {column} FROM {table} ORDER BY id",
+                table = table,
+                column = column
+            ))?;
+            let rows = stmt.query_map(NO_PARAMS, |row| {
+                let id: i64 = row.get(0)?;
+                Ok(UnnormalizedCurrency {
+                    table: table.to_string(),
+                    id: id as usize,
+                    currency: row.get(1)?,
+                })
+            })?;
+            for row in rows {
+                let row = row?;
+                if normalize_currency_code(&row.currency) != row.currency {
+                    unnormalized.push(row);
+                }
+            }
+        }
+        Ok(unnormalized)
+    }
+
+    /// Rewrite the currency codes not stored in normal form
+    fn rewrite_unnormalized_currencies(&self) -> rusqlite::Result<Vec<UnnormalizedCurrency>> {
+        let unnormalized = self.unnormalized_currencies()?;
+        for (table, column) in CURRENCY_COLUMNS {
+            for row in unnormalized.iter().filter(|row| row.table == *table) {
+                self.conn.execute(
+                    &format!("UPDATE {} SET {}=?2 WHERE id=?1", table, column),
+                    params![row.id as i64, normalize_currency_code(&row.currency)],
+                )?;
+            }
+        }
+        Ok(unnormalized)
+    }
+
+    /// Delete quotes and transactions including the rows linked to the transactions.
+    /// Transactions are deleted latest first, since they may only refer to earlier ones.
+    fn delete_history_rows(
+        &mut self,
+        quote_ids: &[usize],
+        transaction_ids: &[usize],
+    ) -> Result<(), DataError> {
+        for id in quote_ids {
+            self.delete_quote(*id)?;
+        }
+        let mut transaction_ids = transaction_ids.to_vec();
+        transaction_ids.sort_unstable_by(|a, b| b.cmp(a));
+        for id in transaction_ids {
+            for (table, column) in TRANSACTION_LINKS {
+                self.conn
+                    .execute(
+                        &format!("DELETE FROM {} WHERE {}=?1", table, column),
+                        params![id as i64],
+                    )
+                    .map_err(|e| DataError::DeleteFailed(e.to_string()))?;
+            }
+            self.delete_transaction(id)?;
+        }
+        Ok(())
+    }
+
+    /// Move the option terms of the former `option_terms` table to the `instrument_terms` table
+    fn migrate_option_terms(&self) -> rusqlite::Result<()> {
+        let mut stmt = self.conn.prepare(
+            "SELECT asset_id, underlying_id, strike, expiry, option_type, contract_size
+            FROM option_terms",
+        )?;
+        let terms = stmt.query_map(NO_PARAMS, |row| {
+            let asset_id: i64 = row.get(0)?;
+            let underlying_id: i64 = row.get(1)?;
+            let expiry: String = row.get(3)?;
+            let expiry = NaiveDate::parse_from_str(&expiry, "%Y-%m-%d").map_err(|e| {
+                rusqlite::Error::FromSqlConversionFailure(3, Type::Text, Box::new(e))
+            })?;
+            let option_type: String = row.get(4)?;
+            let option_type = OptionType::from_str(&option_type).map_err(|e| {
+                rusqlite::Error::FromSqlConversionFailure(4, Type::Text, Box::new(e))
+            })?;
+            Ok(OptionTerms {
+                asset_id: asset_id as usize,
+                underlying_id: underlying_id as usize,
+                strike: row.get(2)?,
+                expiry,
+                option_type,
+                contract_size: row.get(5)?,
+            })
+        })?;
+        for terms in terms {
+            let terms = terms?;
+            let payload = serde_json::to_string(&terms)
+                .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?;
+            self.conn.execute(
+                "INSERT OR IGNORE INTO instrument_terms (asset_id, kind, version, payload)
+                VALUES (?1, ?2, ?3, ?4)",
+                params![
+                    terms.asset_id as i64,
+                    OptionTerms::KIND,
+                    OptionTerms::VERSION,
+                    payload
+                ],
+            )?;
+        }
+        self.conn.execute("DROP TABLE option_terms", NO_PARAMS)?;
         Ok(())
     }
+
+    /// Check whether the database contains a table of the given name
+    fn has_table(&self, table: &str) -> rusqlite::Result<bool> {
+        self.conn
+            .query_row(
+                "SELECT name FROM sqlite_master WHERE type='table' AND name=?1",
+                params![table],
+                |_| Ok(()),
+            )
+            .optional()
+            .map(|row| row.is_some())
+    }
+
+    /// Check whether an index of the given name exists
+    fn has_index(&self, index: &str) -> rusqlite::Result<bool> {
+        self.conn
+            .query_row(
+                "SELECT name FROM sqlite_master WHERE type='index' AND name=?1",
+                params![index],
+                |_| Ok(()),
+            )
+            .optional()
+            .map(|row| row.is_some())
+    }
+
+    /// Check whether a table contains a column of the given name
+    fn has_column(&self, table: &str, column: &str) -> rusqlite::Result<bool> {
+        let mut stmt = self
+            .conn
+            .prepare(&format!("PRAGMA table_info({})", table))?;
+        let columns = stmt.query_map(NO_PARAMS, |row| {
+            let name: String = row.get(1)?;
+            Ok(name)
+        })?;
+        for name in columns {
+            if name? == column {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+}
+
+impl SchemaHandler for SqliteDB<'_> {
+    fn init_schema(&mut self) -> Result<(), DataError> {
+        self.init()
+    }
+
+    fn find_duplicate_settings(&mut self) -> Result<Vec<DuplicateSetting>, DataError> {
+        self.duplicate_settings()
+            .map_err(|e| DataError::DataAccessFailure(e.to_string()))
+    }
+
+    fn collapse_duplicate_settings(&mut self) -> Result<Vec<DuplicateSetting>, DataError> {
+        self.conn
+            .execute_batch("BEGIN;")
+            .map_err(|e| DataError::DataAccessFailure(e.to_string()))?;
+        match self.delete_duplicate_settings() {
+            Ok(duplicates) => {
+                self.conn
+                    .execute_batch("COMMIT;")
+                    .map_err(|e| DataError::DataAccessFailure(e.to_string()))?;
+                Ok(duplicates)
+            }
+            Err(err) => {
+                self.conn
+                    .execute_batch("ROLLBACK;")
+                    .map_err(|e| DataError::DataAccessFailure(e.to_string()))?;
+                Err(DataError::DataAccessFailure(err.to_string()))
+            }
+        }
+    }
+
+    fn find_duplicate_quotes(&mut self) -> Result<Vec<DuplicateQuote>, DataError> {
+        self.duplicate_quotes()
+            .map_err(|e| DataError::DataAccessFailure(e.to_string()))
+    }
+
+    fn collapse_duplicate_quotes(&mut self) -> Result<Vec<DuplicateQuote>, DataError> {
+        self.conn
+            .execute_batch("BEGIN;")
+            .map_err(|e| DataError::DataAccessFailure(e.to_string()))?;
+        match self.delete_duplicate_quotes() {
+            Ok(duplicates) => {
+                self.conn
+                    .execute_batch("COMMIT;")
+                    .map_err(|e| DataError::DataAccessFailure(e.to_string()))?;
+                Ok(duplicates)
+            }
+            Err(err) => {
+                self.conn
+                    .execute_batch("ROLLBACK;")
+                    .map_err(|e| DataError::DataAccessFailure(e.to_string()))?;
+                Err(DataError::DataAccessFailure(err.to_string()))
+            }
+        }
+    }
+
+    fn find_unnormalized_currencies(&mut self) -> Result<Vec<UnnormalizedCurrency>, DataError> {
+        self.unnormalized_currencies()
+            .map_err(|e| DataError::DataAccessFailure(e.to_string()))
+    }
+
+    fn normalize_currencies(&mut self) -> Result<Vec<UnnormalizedCurrency>, DataError> {
+        self.conn
+            .execute_batch("BEGIN;")
+            .map_err(|e| DataError::DataAccessFailure(e.to_string()))?;
+        match self.rewrite_unnormalized_currencies() {
+            Ok(unnormalized) => {
+                self.conn
+                    .execute_batch("COMMIT;")
+                    .map_err(|e| DataError::DataAccessFailure(e.to_string()))?;
+                Ok(unnormalized)
+            }
+            Err(err) => {
+                self.conn
+                    .execute_batch("ROLLBACK;")
+                    .map_err(|e| DataError::DataAccessFailure(e.to_string()))?;
+                Err(DataError::DataAccessFailure(err.to_string()))
+            }
+        }
+    }
+}
+
+impl DataHandler for SqliteDB<'_> {
+    fn delete_history(
+        &mut self,
+        quote_ids: &[usize],
+        transaction_ids: &[usize],
+    ) -> Result<(), DataError> {
+        self.conn
+            .execute_batch("BEGIN;")
+            .map_err(|e| DataError::DataAccessFailure(e.to_string()))?;
+        match self.delete_history_rows(quote_ids, transaction_ids) {
+            Ok(()) => {
+                self.conn
+                    .execute_batch("COMMIT;")
+                    .map_err(|e| DataError::DataAccessFailure(e.to_string()))?;
+                Ok(())
+            }
+            Err(err) => {
+                self.conn
+                    .execute_batch("ROLLBACK;")
+                    .map_err(|e| DataError::DataAccessFailure(e.to_string()))?;
+                Err(err)
+            }
+        }
+    }
 }
 
 impl CurrencyConverter for SqliteDB<'_> {
@@ -101,4 +902,396 @@ impl CurrencyConverter for SqliteDB<'_> {
         }
         Err(CurrencyError::ConversionFailed)
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use finql_data::{conformance, CachedQuoteHandler, Quote, QuoteCacheConfig, QuoteInsertion};
+
+    fn with_new_db(check: &dyn Fn(&mut SqliteDB)) {
+        let conn = Connection::open(":memory:").unwrap();
+        let mut db = SqliteDB::new(&conn);
+        db.init().unwrap();
+        check(&mut db);
+    }
+
+    /// Run all conformance checks, each on a new database provided by `with_db`
+    fn check_conformance(with_db: impl Fn(&dyn Fn(&mut SqliteDB))) {
+        with_db(&|db| conformance::check_asset_update(db));
+        with_db(&|db| conformance::check_assets_batch_update(db));
+        with_db(&|db| conformance::check_asset_search(db));
+        with_db(&|db| conformance::check_asset_by_identifier(db));
+        with_db(&|db| conformance::check_asset_duplicates(db));
+        with_db(&|db| conformance::check_asset_representation_update(db));
+        with_db(&|db| conformance::check_ticker_update(db));
+        with_db(&|db| conformance::check_insert_if_new_ticker(db));
+        with_db(&|db| conformance::check_quote_update(db));
+        with_db(&|db| conformance::check_quotes_in_range(db));
+        with_db(&|db| conformance::check_quotes_for_source(db));
+        with_db(&|db| conformance::check_last_quote_before(db));
+        with_db(&|db| conformance::check_latest_quotes_for_tickers(db));
+        with_db(&|db| conformance::check_insert_quotes(db));
+        with_db(&|db| conformance::check_delete_quotes(db));
+        with_db(&|db| conformance::check_insert_quote_if_new(db));
+        with_db(&|db| conformance::check_transaction_update(db));
+        with_db(&|db| conformance::check_delete_history(db));
+        with_db(&|db| conformance::check_order_update(db));
+        with_db(&|db| conformance::check_fee_schedule_update(db));
+        with_db(&|db| conformance::check_instrument_record_update(db));
+        with_db(&|db| conformance::check_report_definition_update(db));
+        with_db(&|db| conformance::check_rebalancing_decision(db));
+        with_db(&|db| conformance::check_rounding_digits_update(db));
+        with_db(&|db| conformance::check_quota_usage(db));
+    }
+
+    #[test]
+    fn updates_change_all_fields() {
+        check_conformance(with_new_db);
+    }
+
+    #[test]
+    fn merged_reader_combines_databases() {
+        let first_conn = Connection::open(":memory:").unwrap();
+        let mut first = SqliteDB::new(&first_conn);
+        first.init().unwrap();
+        let second_conn = Connection::open(":memory:").unwrap();
+        let mut second = SqliteDB::new(&second_conn);
+        second.init().unwrap();
+        conformance::check_merged_reader(&mut first, &mut second);
+    }
+
+    /// The quote cache passes all checks of asset and quote handlers
+    #[test]
+    fn cached_updates_change_all_fields() {
+        let with_db = |check: &dyn Fn(&mut CachedQuoteHandler<SqliteDB>)| {
+            let conn = Connection::open(":memory:").unwrap();
+            let db = SqliteDB::new(&conn);
+            db.init().unwrap();
+            check(&mut CachedQuoteHandler::new(db, QuoteCacheConfig::default()));
+        };
+        with_db(&|db| conformance::check_asset_update(db));
+        with_db(&|db| conformance::check_assets_batch_update(db));
+        with_db(&|db| conformance::check_asset_search(db));
+        with_db(&|db| conformance::check_asset_by_identifier(db));
+        with_db(&|db| conformance::check_asset_duplicates(db));
+        with_db(&|db| conformance::check_asset_representation_update(db));
+        with_db(&|db| conformance::check_ticker_update(db));
+        with_db(&|db| conformance::check_insert_if_new_ticker(db));
+        with_db(&|db| conformance::check_quote_update(db));
+        with_db(&|db| conformance::check_quotes_in_range(db));
+        with_db(&|db| conformance::check_quotes_for_source(db));
+        with_db(&|db| conformance::check_last_quote_before(db));
+        with_db(&|db| conformance::check_latest_quotes_for_tickers(db));
+        with_db(&|db| conformance::check_insert_quotes(db));
+        with_db(&|db| conformance::check_delete_quotes(db));
+        with_db(&|db| conformance::check_insert_quote_if_new(db));
+        with_db(&|db| conformance::check_instrument_record_update(db));
+        with_db(&|db| conformance::check_rounding_digits_update(db));
+        with_db(&|db| conformance::check_quota_usage(db));
+    }
+
+    /// The handler shared between threads passes all checks of asset and quote handlers
+    #[test]
+    fn shared_updates_change_all_fields() {
+        let with_db = |check: &dyn Fn(&mut SharedSqliteDB)| {
+            let conn = Connection::open(":memory:").unwrap();
+            SqliteDB::new(&conn).init().unwrap();
+            check(&mut SharedSqliteDB::new(OwnedSqliteDB { conn }));
+        };
+        with_db(&|db| conformance::check_asset_update(db));
+        with_db(&|db| conformance::check_assets_batch_update(db));
+        with_db(&|db| conformance::check_asset_search(db));
+        with_db(&|db| conformance::check_asset_by_identifier(db));
+        with_db(&|db| conformance::check_asset_duplicates(db));
+        with_db(&|db| conformance::check_asset_representation_update(db));
+        with_db(&|db| conformance::check_ticker_update(db));
+        with_db(&|db| conformance::check_insert_if_new_ticker(db));
+        with_db(&|db| conformance::check_quote_update(db));
+        with_db(&|db| conformance::check_quotes_in_range(db));
+        with_db(&|db| conformance::check_quotes_for_source(db));
+        with_db(&|db| conformance::check_last_quote_before(db));
+        with_db(&|db| conformance::check_latest_quotes_for_tickers(db));
+        with_db(&|db| conformance::check_insert_quotes(db));
+        with_db(&|db| conformance::check_delete_quotes(db));
+        with_db(&|db| conformance::check_insert_quote_if_new(db));
+        with_db(&|db| conformance::check_instrument_record_update(db));
+        with_db(&|db| conformance::check_rounding_digits_update(db));
+        with_db(&|db| conformance::check_quota_usage(db));
+    }
+
+    /// Run the check on a new encrypted database in a temporary file
+    #[cfg(feature = "sqlcipher")]
+    fn with_new_encrypted_db(check: &dyn Fn(&mut SqliteDB)) {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        static COUNT: AtomicUsize = AtomicUsize::new(0);
+        let path = std::env::temp_dir().join(format!(
+            "finql_encrypted_{}_{}.db",
+            std::process::id(),
+            COUNT.fetch_add(1, Ordering::SeqCst)
+        ));
+        {
+            let db = SqliteDB::open_encrypted(&path, "secret").unwrap();
+            check(&mut db.handler());
+        }
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[cfg(feature = "sqlcipher")]
+    #[test]
+    fn encrypted_updates_change_all_fields() {
+        check_conformance(with_new_encrypted_db);
+    }
+
+    #[cfg(feature = "sqlcipher")]
+    #[test]
+    fn open_encrypted_with_wrong_key() {
+        let path = std::env::temp_dir().join(format!("finql_rekey_{}.db", std::process::id()));
+        {
+            let db = SqliteDB::open_encrypted(&path, "old secret").unwrap();
+            let mut db = db.handler();
+            db.set_rounding_digits(Currency::from_str("JPY").unwrap(), 0)
+                .unwrap();
+            db.change_key("new secret").unwrap();
+        }
+        let err = SqliteDB::open_encrypted(&path, "old secret").unwrap_err();
+        assert!(err.is_auth_failed());
+        let db = SqliteDB::open_encrypted(&path, "new secret").unwrap();
+        assert_eq!(
+            db.handler()
+                .get_rounding_digits(Currency::from_str("JPY").unwrap()),
+            0
+        );
+        drop(db);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[cfg(feature = "sqlcipher")]
+    #[test]
+    fn open_encrypted_round_trip() {
+        use finql_data::{Asset, AssetHandler};
+
+        let path = std::env::temp_dir().join(format!("finql_reopen_{}.db", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        {
+            let db = SqliteDB::open_encrypted(&path, "secret").unwrap();
+            db.handler()
+                .insert_asset(&Asset::new(None, "BASF AG", None, None, None))
+                .unwrap();
+        }
+        // the file can't be read without a key
+        let conn = Connection::open(&path).unwrap();
+        assert!(conn
+            .query_row("SELECT count(*) FROM sqlite_master", NO_PARAMS, |_| Ok(()))
+            .is_err());
+        drop(conn);
+        let err = SqliteDB::open_encrypted(&path, "wrong secret").unwrap_err();
+        assert!(err.is_auth_failed());
+
+        let db = SqliteDB::open_encrypted(&path, "secret").unwrap();
+        let assets = db.handler().get_all_assets().unwrap();
+        let names: Vec<&str> = assets.iter().map(|asset| asset.name.as_str()).collect();
+        assert_eq!(names, vec!["BASF AG"]);
+        drop(db);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn collapse_duplicate_settings() {
+        let conn = Connection::open(":memory:").unwrap();
+        // rounding digits table of a legacy database without unique key
+        conn.execute_batch(
+            "CREATE TABLE rounding_digits (
+                id INTEGER PRIMARY KEY,
+                currency TEXT NOT NULL,
+                digits INTEGER NOT NULL);
+            INSERT INTO rounding_digits (currency, digits) VALUES
+                ('JPY', 2), ('XAU', 4), ('JPY', 1), ('JPY', 0);",
+        )
+        .unwrap();
+        let mut db = SqliteDB::new(&conn);
+        db.init().unwrap();
+        let duplicates = db.find_duplicate_settings().unwrap();
+        let ids: Vec<usize> = duplicates.iter().map(|d| d.id).collect();
+        assert_eq!(ids, vec![1, 3]);
+        assert!(duplicates
+            .iter()
+            .all(|d| d.table == "rounding_digits" && d.key == "JPY"));
+        // the newest row is used while the duplicates are not yet removed
+        let jpy = Currency::from_str("JPY").unwrap();
+        assert_eq!(db.get_rounding_digits(jpy), 0);
+
+        assert_eq!(db.collapse_duplicate_settings().unwrap(), duplicates);
+        assert!(db.find_duplicate_settings().unwrap().is_empty());
+        assert_eq!(db.get_rounding_digits(jpy), 0);
+        let xau = Currency::from_str("XAU").unwrap();
+        assert_eq!(db.get_rounding_digits(xau), 4);
+        // the unique key is in place now
+        assert!(conn
+            .execute(
+                "INSERT INTO rounding_digits (currency, digits) VALUES ('XAU', 3)",
+                NO_PARAMS
+            )
+            .is_err());
+        db.set_rounding_digits(xau, 3).unwrap();
+        assert_eq!(db.get_rounding_digits(xau), 3);
+        assert!(db.collapse_duplicate_settings().unwrap().is_empty());
+    }
+
+    #[test]
+    fn normalize_stored_currencies() {
+        use finql_data::{
+            Asset, AssetHandler, CashFlow, Ticker, TickerUsage, Transaction, TransactionHandler,
+            TransactionType,
+        };
+
+        let conn = Connection::open(":memory:").unwrap();
+        let mut db = SqliteDB::new(&conn);
+        db.init().unwrap();
+        let eur = Currency::from_str("EUR").unwrap();
+        let asset = db
+            .insert_asset(&Asset::new(None, "BASF", None, None, None))
+            .unwrap();
+        let ticker_id = db
+            .insert_ticker(&Ticker {
+                id: None,
+                name: "BAS.DE".to_string(),
+                asset,
+                source: "manual".to_string(),
+                priority: 1,
+                currency: eur,
+                factor: 1.0,
+                source_url: None,
+                usage: TickerUsage::Both,
+                last_quote_time: None,
+                source_chain: Vec::new(),
+            })
+            .unwrap();
+        let transaction_id = db
+            .insert_transaction(&Transaction {
+                id: None,
+                transaction_type: TransactionType::Cash,
+                cash_flow: CashFlow::new(1000.0, eur, NaiveDate::from_ymd(2021, 3, 1)),
+                note: None,
+                execution_meta: None,
+                recorded_at: None,
+            })
+            .unwrap();
+        // as written by early import scripts
+        conn.execute_batch(
+            "UPDATE ticker SET currency='eur ';
+            UPDATE transactions SET cash_currency=' Eur';",
+        )
+        .unwrap();
+
+        assert_eq!(db.get_ticker_by_id(ticker_id).unwrap().currency, eur);
+        let transaction = db.get_transaction_by_id(transaction_id).unwrap();
+        assert_eq!(transaction.cash_flow.amount.currency, eur);
+        let unnormalized = db.find_unnormalized_currencies().unwrap();
+        assert_eq!(
+            unnormalized,
+            vec![
+                UnnormalizedCurrency {
+                    table: "ticker".to_string(),
+                    id: ticker_id,
+                    currency: "eur ".to_string(),
+                },
+                UnnormalizedCurrency {
+                    table: "transactions".to_string(),
+                    id: transaction_id,
+                    currency: " Eur".to_string(),
+                },
+            ]
+        );
+
+        assert_eq!(db.normalize_currencies().unwrap(), unnormalized);
+        assert!(db.find_unnormalized_currencies().unwrap().is_empty());
+        let currency: String = conn
+            .query_row("SELECT currency FROM ticker", NO_PARAMS, |row| row.get(0))
+            .unwrap();
+        assert_eq!(currency, "EUR");
+        let currency: String = conn
+            .query_row("SELECT cash_currency FROM transactions", NO_PARAMS, |row| {
+                row.get(0)
+            })
+            .unwrap();
+        assert_eq!(currency, "EUR");
+        assert!(db.normalize_currencies().unwrap().is_empty());
+    }
+
+    #[test]
+    fn collapse_legacy_duplicate_quotes() {
+        let conn = Connection::open(":memory:").unwrap();
+        // quotes table of a legacy database with the same quote inserted twice
+        conn.execute_batch(
+            "CREATE TABLE quotes (
+                id INTEGER PRIMARY KEY,
+                ticker_id INTEGER NOT NULL,
+                price REAL NOT NULL,
+                time TEXT NOT NULL,
+                volume REAL);
+            INSERT INTO quotes (ticker_id, price, time) VALUES
+                (1, 61.0, '2021-03-02T17:30:00+00:00'), (1, 61.5, '2021-03-02T17:30:00+00:00'),
+                (1, 62.0, '2021-03-03T17:30:00+00:00');",
+        )
+        .unwrap();
+        let mut db = SqliteDB::new(&conn);
+        // the duplicates are kept until explicitly removed
+        match db.init() {
+            Err(DataError::DuplicateQuotes(err)) => assert_eq!(
+                err,
+                "1 superseded in total, the first is quote 1 of ticker 1 at 2021-03-02 17:30:00 UTC"
+            ),
+            result => panic!("expected duplicate quotes, got {:?}", result),
+        }
+        let duplicates = db.find_duplicate_quotes().unwrap();
+        assert_eq!(duplicates.len(), 1);
+        assert_eq!((duplicates[0].id, duplicates[0].ticker_id), (1, 1));
+        assert_eq!(db.collapse_duplicate_quotes().unwrap(), duplicates);
+        assert!(db.find_duplicate_quotes().unwrap().is_empty());
+        db.init().unwrap();
+        let quotes = db.get_all_quotes_for_ticker(1).unwrap();
+        let prices: Vec<(usize, f64)> = quotes
+            .iter()
+            .map(|quote| (quote.id.unwrap(), quote.price))
+            .collect();
+        assert_eq!(prices, vec![(2, 61.5), (3, 62.0)]);
+        assert!(db.has_index("quotes_ticker_time_unique").unwrap());
+
+        // plain inserts of quotes with the same ticker and time fail now
+        let quote = Quote {
+            id: None,
+            ..quotes[0].clone()
+        };
+        assert!(db.insert_quote(&quote).is_err());
+        assert_eq!(
+            db.insert_quote_if_new(&quote).unwrap(),
+            QuoteInsertion::Existing(2)
+        );
+    }
+
+    #[test]
+    fn load_legacy_quotes_without_price_points() {
+        let conn = Connection::open(":memory:").unwrap();
+        conn.execute_batch(
+            "CREATE TABLE quotes (
+                id INTEGER PRIMARY KEY,
+                ticker_id INTEGER NOT NULL,
+                price REAL NOT NULL,
+                time TEXT NOT NULL,
+                volume REAL);
+            INSERT INTO quotes (ticker_id, price, time) VALUES
+                (1, 61.0, '2021-03-02T17:30:00+00:00');",
+        )
+        .unwrap();
+        let mut db = SqliteDB::new(&conn);
+        db.init().unwrap();
+        let quotes = db.get_all_quotes_for_ticker(1).unwrap();
+        assert_eq!(quotes.len(), 1);
+        assert_eq!(quotes[0].price, 61.0);
+        assert_eq!(quotes[0].open, None);
+        assert_eq!(quotes[0].high, None);
+        assert_eq!(quotes[0].low, None);
+        assert_eq!(quotes[0].bid, None);
+        assert_eq!(quotes[0].ask, None);
+    }
+}
@@ -0,0 +1,353 @@
+//! Implementation of sqlite3 order handler
+use std::str::FromStr;
+
+use chrono::{DateTime, Utc};
+use rusqlite::{params, Row, NO_PARAMS};
+
+use finql_data::currency::Currency;
+use finql_data::order::{
+    store_order_fill, Order, OrderError, OrderHandler, OrderSide, OrderSize, OrderState,
+    TransactionDraft,
+};
+use finql_data::DataError;
+
+use super::quote_handler::to_time;
+use super::SqliteDB;
+
+/// Order as stored in the database
+struct RawOrder {
+    id: i64,
+    asset_id: i64,
+    side: String,
+    quantity: Option<f64>,
+    amount: Option<f64>,
+    currency: String,
+    limit_price: Option<f64>,
+    state: String,
+    created: String,
+    updated: String,
+    note: Option<String>,
+}
+
+const ORDER_COLUMNS: &str = "id, asset_id, side, quantity, amount, currency, limit_price, \
+    state, created, updated, note";
+
+impl RawOrder {
+    fn from_row(row: &Row) -> rusqlite::Result<RawOrder> {
+        Ok(RawOrder {
+            id: row.get(0)?,
+            asset_id: row.get(1)?,
+            side: row.get(2)?,
+            quantity: row.get(3)?,
+            amount: row.get(4)?,
+            currency: row.get(5)?,
+            limit_price: row.get(6)?,
+            state: row.get(7)?,
+            created: row.get(8)?,
+            updated: row.get(9)?,
+            note: row.get(10)?,
+        })
+    }
+
+    fn to_order(&self, transaction_ids: Vec<usize>) -> Result<Order, DataError> {
+        Ok(Order {
+            id: Some(self.id as usize),
+            asset_id: self.asset_id as usize,
+            side: OrderSide::from_str(&self.side)?,
+            size: OrderSize::from_columns(self.quantity, self.amount)?,
+            currency: Currency::from_str(&self.currency)
+                .map_err(|e| DataError::InvalidData(e.to_string()))?,
+            limit_price: self.limit_price,
+            state: OrderState::from_str(&self.state)?,
+            created: to_time(&self.created)?,
+            updated: to_time(&self.updated)?,
+            transaction_ids,
+            note: self.note.clone(),
+        })
+    }
+}
+
+impl SqliteDB<'_> {
+    fn get_order_transactions(&self, order_id: i64) -> Result<Vec<usize>, DataError> {
+        let mut stmt = self
+            .conn
+            .prepare(
+                "SELECT transaction_id FROM order_transactions
+                WHERE order_id=? ORDER BY transaction_id;",
+            )
+            .map_err(|e| DataError::NotFound(e.to_string()))?;
+        let id_map = stmt
+            .query_map(params![order_id], |row| {
+                let id: i64 = row.get(0)?;
+                Ok(id as usize)
+            })
+            .map_err(|e| DataError::NotFound(e.to_string()))?;
+        let mut ids = Vec::new();
+        for id in id_map {
+            ids.push(id.map_err(|e| DataError::NotFound(e.to_string()))?);
+        }
+        Ok(ids)
+    }
+
+    fn set_order_transactions(&self, order_id: i64, ids: &[usize]) -> Result<(), DataError> {
+        self.conn
+            .execute(
+                "DELETE FROM order_transactions WHERE order_id=?1;",
+                params![order_id],
+            )
+            .map_err(|e| DataError::UpdateFailed(e.to_string()))?;
+        for id in ids {
+            self.conn
+                .execute(
+                    "INSERT INTO order_transactions (order_id, transaction_id) VALUES (?1, ?2);",
+                    params![order_id, *id as i64],
+                )
+                .map_err(|e| DataError::UpdateFailed(e.to_string()))?;
+        }
+        Ok(())
+    }
+}
+
+impl OrderHandler for SqliteDB<'_> {
+    fn insert_order(&mut self, order: &Order) -> Result<usize, DataError> {
+        self.conn
+            .execute(
+                "INSERT INTO orders (asset_id, side, quantity, amount, currency, limit_price,
+                state, created, updated, note)
+                VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10);",
+                params![
+                    order.asset_id as i64,
+                    order.side.to_string(),
+                    order.size.quantity(),
+                    order.size.amount(),
+                    order.currency.to_string(),
+                    order.limit_price,
+                    order.state.to_string(),
+                    order.created.to_rfc3339(),
+                    order.updated.to_rfc3339(),
+                    order.note
+                ],
+            )
+            .map_err(|e| DataError::InsertFailed(e.to_string()))?;
+        let id = self.conn.last_insert_rowid();
+        self.set_order_transactions(id, &order.transaction_ids)
+            .map_err(|e| DataError::InsertFailed(e.to_string()))?;
+        Ok(id as usize)
+    }
+
+    fn get_order_by_id(&mut self, id: usize) -> Result<Order, DataError> {
+        let order = self
+            .conn
+            .query_row(
+                &format!("SELECT {} FROM orders WHERE id=?;", ORDER_COLUMNS),
+                params![id as i64],
+                RawOrder::from_row,
+            )
+            .map_err(|e| DataError::NotFound(e.to_string()))?;
+        order.to_order(self.get_order_transactions(order.id)?)
+    }
+
+    fn get_all_orders(&mut self) -> Result<Vec<Order>, DataError> {
+        let mut stmt = self
+            .conn
+            .prepare(&format!(
+                "SELECT {} FROM orders ORDER BY id;",
+                ORDER_COLUMNS
+            ))
+            .map_err(|e| DataError::NotFound(e.to_string()))?;
+        let order_map = stmt
+            .query_map(NO_PARAMS, RawOrder::from_row)
+            .map_err(|e| DataError::NotFound(e.to_string()))?;
+        let mut orders = Vec::new();
+        for order in order_map {
+            let order = order.map_err(|e| DataError::NotFound(e.to_string()))?;
+            orders.push(order.to_order(self.get_order_transactions(order.id)?)?);
+        }
+        Ok(orders)
+    }
+
+    fn update_order(&mut self, order: &Order) -> Result<(), DataError> {
+        let id = order
+            .id
+            .ok_or_else(|| DataError::NotFound("not yet stored to database".to_string()))?
+            as i64;
+        self.conn
+            .execute(
+                "UPDATE orders SET
+                asset_id=?2,
+                side=?3,
+                quantity=?4,
+                amount=?5,
+                currency=?6,
+                limit_price=?7,
+                state=?8,
+                created=?9,
+                updated=?10,
+                note=?11
+            WHERE id=?1;",
+                params![
+                    id,
+                    order.asset_id as i64,
+                    order.side.to_string(),
+                    order.size.quantity(),
+                    order.size.amount(),
+                    order.currency.to_string(),
+                    order.limit_price,
+                    order.state.to_string(),
+                    order.created.to_rfc3339(),
+                    order.updated.to_rfc3339(),
+                    order.note
+                ],
+            )
+            .map_err(|e| DataError::UpdateFailed(e.to_string()))?;
+        self.set_order_transactions(id, &order.transaction_ids)
+    }
+
+    fn delete_order(&mut self, id: usize) -> Result<(), DataError> {
+        self.conn
+            .execute(
+                "DELETE FROM order_transactions WHERE order_id=?1;",
+                params![id as i64],
+            )
+            .map_err(|e| DataError::DeleteFailed(e.to_string()))?;
+        self.conn
+            .execute("DELETE FROM orders WHERE id=?1;", params![id as i64])
+            .map_err(|e| DataError::DeleteFailed(e.to_string()))?;
+        Ok(())
+    }
+
+    fn fill_order(
+        &mut self,
+        order_id: usize,
+        execution: &TransactionDraft,
+        time: DateTime<Utc>,
+    ) -> Result<Vec<usize>, OrderError> {
+        self.conn
+            .execute_batch("BEGIN;")
+            .map_err(|e| DataError::DataAccessFailure(e.to_string()))?;
+        match store_order_fill(self, order_id, execution, time) {
+            Ok(ids) => {
+                self.conn
+                    .execute_batch("COMMIT;")
+                    .map_err(|e| DataError::DataAccessFailure(e.to_string()))?;
+                Ok(ids)
+            }
+            Err(err) => {
+                self.conn
+                    .execute_batch("ROLLBACK;")
+                    .map_err(|e| DataError::DataAccessFailure(e.to_string()))?;
+                Err(err)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{NaiveDate, TimeZone};
+    use rusqlite::Connection;
+
+    use finql_data::asset::Asset;
+    use finql_data::cash_flow::CashFlow;
+    use finql_data::transaction::{Transaction, TransactionType};
+    use finql_data::{AssetHandler, TransactionHandler};
+
+    fn trade(asset_id: usize, position: f64, amount: f64, currency: Currency) -> Transaction {
+        Transaction {
+            id: None,
+            transaction_type: TransactionType::Asset { asset_id, position },
+            cash_flow: CashFlow::new(amount, currency, NaiveDate::from_ymd(2021, 3, 2)),
+            note: None,
+            execution_meta: None,
+            recorded_at: None,
+        }
+    }
+
+    #[test]
+    fn order_life_cycle() {
+        let conn = Connection::open(":memory:").unwrap();
+        let mut db = SqliteDB::new(&conn);
+        db.init().unwrap();
+        let eur = Currency::from_str("EUR").unwrap();
+        let asset_id = db
+            .insert_asset(&Asset::new(None, "BASF AG", None, None, None))
+            .unwrap();
+        let time = Utc.ymd(2021, 3, 1).and_hms(9, 0, 0);
+        let mut order = Order::new(
+            asset_id,
+            OrderSide::Buy,
+            OrderSize::Amount(1000.0),
+            eur,
+            Some(50.0),
+            time,
+        );
+        order.note = Some("rebalancing".to_string());
+        let order_id = db.insert_order(&order).unwrap();
+        order.id = Some(order_id);
+        assert_eq!(db.get_order_by_id(order_id).unwrap(), order);
+
+        // drafts can't be filled, nothing is stored
+        let execution = TransactionDraft {
+            trade: trade(asset_id, 20.0, -1000.0, eur),
+            charges: vec![Transaction {
+                id: None,
+                transaction_type: TransactionType::Fee {
+                    transaction_ref: None,
+                },
+                cash_flow: CashFlow::new(-4.95, eur, NaiveDate::from_ymd(2021, 3, 2)),
+                note: None,
+                execution_meta: None,
+                recorded_at: None,
+            }],
+        };
+        let fill_time = Utc.ymd(2021, 3, 2).and_hms(10, 0, 0);
+        assert!(matches!(
+            db.fill_order(order_id, &execution, fill_time),
+            Err(OrderError::InvalidTransition {
+                from: OrderState::Draft,
+                to: OrderState::Filled
+            })
+        ));
+        db.set_order_state(order_id, OrderState::Submitted, fill_time)
+            .unwrap();
+        assert_eq!(db.get_pending_orders().unwrap().len(), 1);
+
+        let wrong = TransactionDraft {
+            trade: execution.trade.clone(),
+            charges: vec![trade(asset_id, 1.0, -50.0, eur)],
+        };
+        assert!(matches!(
+            db.fill_order(order_id, &wrong, fill_time),
+            Err(OrderError::InvalidExecution(_))
+        ));
+
+        // if linking the order fails, the inserted transactions are rolled back
+        conn.execute("DROP TABLE order_transactions", NO_PARAMS)
+            .unwrap();
+        assert!(db.fill_order(order_id, &execution, fill_time).is_err());
+        assert!(db.get_all_transactions().unwrap().is_empty());
+        db.init().unwrap();
+        let order = db.get_order_by_id(order_id).unwrap();
+        assert_eq!(order.state, OrderState::Submitted);
+
+        let ids = db.fill_order(order_id, &execution, fill_time).unwrap();
+        assert_eq!(ids.len(), 2);
+        let order = db.get_order_by_id(order_id).unwrap();
+        assert_eq!(order.state, OrderState::Filled);
+        assert_eq!(order.transaction_ids, ids);
+        assert_eq!(order.updated, fill_time);
+        match db.get_transaction_by_id(ids[1]).unwrap().transaction_type {
+            TransactionType::Fee { transaction_ref } => assert_eq!(transaction_ref, Some(ids[0])),
+            _ => panic!("fee expected"),
+        }
+        assert!(db.get_pending_orders().unwrap().is_empty());
+        assert!(matches!(
+            db.set_order_state(order_id, OrderState::Cancelled, fill_time),
+            Err(OrderError::InvalidTransition { .. })
+        ));
+
+        db.delete_order(order_id).unwrap();
+        assert!(db.get_all_orders().unwrap().is_empty());
+    }
+}
@@ -1,16 +1,90 @@
 ///! Implementation for quote handler with Sqlite3 database as backend
 
+use std::collections::BTreeMap;
 use std::str::FromStr;
 
 use chrono::{DateTime, NaiveDate, NaiveDateTime, Utc, Local, TimeZone};
+use rusqlite::types::{ToSql, Type};
 use rusqlite::{params, Row, NO_PARAMS};
 
 use finql_data::Currency;
-use finql_data::{DataError, QuoteHandler};
-use finql_data::{Quote, Ticker};
+use finql_data::quote_handler::store_each_quote;
+use finql_data::{DataError, DataItem, QuotaMonth, QuoteInsertion, QuoteReader};
+use finql_data::{parse_source_chain, Quote, QuoteQuery, RawQuote, Ticker, TickerUsage};
 
 use super::SqliteDB;
 
+/// Columns to select to construct a ticker by `ticker_from_row`
+const TICKER_COLUMNS: &str = "id, name, asset_id, priority, source, currency, factor, source_url,
+    usage, last_quote_time, source_chain";
+
+/// Columns to select to construct a quote by `quote_from_row`
+const QUOTE_COLUMNS: &str =
+    "id, ticker_id, price, time, volume, quality_score, source, open, high, low, bid, ask";
+
+/// Construct a quote from a row starting with the columns given by `QUOTE_COLUMNS`
+fn quote_from_row(row: &Row) -> rusqlite::Result<Quote> {
+    let id: i64 = row.get(0)?;
+    let ticker: i64 = row.get(1)?;
+    let time: String = row.get(3)?;
+    let time = to_time(&time)
+        .map_err(|e| rusqlite::Error::FromSqlConversionFailure(3, Type::Text, Box::new(e)))?;
+    Ok(Quote {
+        id: Some(id as usize),
+        ticker: ticker as usize,
+        price: row.get(2)?,
+        time,
+        volume: row.get(4)?,
+        quality_score: row.get(5)?,
+        source: row.get(6)?,
+        open: row.get(7)?,
+        high: row.get(8)?,
+        low: row.get(9)?,
+        bid: row.get(10)?,
+        ask: row.get(11)?,
+    })
+}
+
+/// Construct a ticker from a row containing the columns given by `TICKER_COLUMNS`
+fn ticker_from_row(row: &Row) -> rusqlite::Result<Ticker> {
+    let id: i64 = row.get(0)?;
+    let asset: i64 = row.get(2)?;
+    let currency: String = row.get(5)?;
+    let currency = Currency::from_stored(&currency)
+        .map_err(|e| rusqlite::Error::FromSqlConversionFailure(5, Type::Text, Box::new(e)))?;
+    let usage: String = row.get(8)?;
+    let usage = TickerUsage::from_str(&usage)
+        .map_err(|e| rusqlite::Error::FromSqlConversionFailure(8, Type::Text, Box::new(e)))?;
+    let last_quote_time: Option<String> = row.get(9)?;
+    let last_quote_time = last_quote_time
+        .map(|time| to_time(&time))
+        .transpose()
+        .map_err(|e| rusqlite::Error::FromSqlConversionFailure(9, Type::Text, Box::new(e)))?;
+    let source_chain: Option<String> = row.get(10)?;
+    let source_chain = source_chain
+        .map(|chain| parse_source_chain(&chain))
+        .transpose()
+        .map_err(|e| rusqlite::Error::FromSqlConversionFailure(10, Type::Text, Box::new(e)))?
+        .unwrap_or_default();
+    Ok(Ticker {
+        id: Some(id as usize),
+        name: row.get(1)?,
+        asset: asset as usize,
+        priority: row.get(3)?,
+        source: row.get(4)?,
+        currency,
+        factor: row.get(6)?,
+        source_url: row.get(7)?,
+        usage,
+        last_quote_time,
+        source_chain,
+    })
+}
+
+/// Value of the `source_chain` column of a ticker, NULL if the ticker has no fallback sources
+fn source_chain_column(ticker: &Ticker) -> Option<String> {
+    Some(ticker.source_chain.join(",")).filter(|chain| !chain.is_empty())
+}
 
 /// Convert string to DateTime<Utc>
 pub fn to_time(time: &str) -> Result<DateTime<Utc>, DataError> {
@@ -32,20 +106,185 @@ pub fn make_time(
 ) -> Option<DateTime<Utc>> {
     let time: NaiveDateTime = NaiveDate::from_ymd(year, month, day).and_hms(hour, minute, second);
     let time = Local.from_local_datetime(&time).single();
-    match time {
-        Some(time) => Some(DateTime::from(time)),
-        None => None,
-    }
+    time.map(DateTime::from)
 }
 
+impl SqliteDB<'_> {
+    /// Get all ticker matching the given condition
+    fn query_ticker(
+        &self,
+        condition: &str,
+        params: &[&dyn ToSql],
+    ) -> Result<Vec<Ticker>, DataError> {
+        let mut stmt = self
+            .conn
+            .prepare(&format!(
+                "SELECT {} FROM ticker WHERE {};",
+                TICKER_COLUMNS, condition
+            ))
+            .map_err(|e| DataError::NotFound(e.to_string()))?;
+        let ticker_map = stmt
+            .query_map(params, ticker_from_row)
+            .map_err(|e| DataError::NotFound(e.to_string()))?;
+        let mut all_ticker = Vec::new();
+        for ticker in ticker_map {
+            all_ticker.push(ticker.map_err(|e| DataError::NotFound(e.to_string()))?);
+        }
+        Ok(all_ticker)
+    }
+
+    /// Get all quotes matching the given condition, ordered by time
+    fn query_quotes(
+        &self,
+        condition: &str,
+        params: &[&dyn ToSql],
+    ) -> Result<Vec<Quote>, DataError> {
+        let mut stmt = self
+            .conn
+            .prepare(&format!(
+                "SELECT {} FROM quotes WHERE {} ORDER BY time ASC;",
+                QUOTE_COLUMNS, condition
+            ))
+            .map_err(|e| DataError::NotFound(e.to_string()))?;
+        let quotes_map = stmt
+            .query_map(params, quote_from_row)
+            .map_err(|e| DataError::NotFound(e.to_string()))?;
+        let mut quotes = Vec::new();
+        for quote in quotes_map {
+            quotes.push(quote.map_err(|e| DataError::NotFound(e.to_string()))?);
+        }
+        Ok(quotes)
+    }
+
+    /// Get the last quote and its currency given the tables and conditions of the query,
+    /// which refer to quotes as `q` and ticker as `t`. Among quotes of the latest time, the
+    /// quote is selected by `Quote::select_preferred` in order of ticker priority.
+    fn query_last_quote_before(
+        &self,
+        from_where: &str,
+        params: &[&dyn ToSql],
+    ) -> Result<(Quote, Currency), DataError> {
+        let mut stmt = self
+            .conn
+            .prepare(&format!(
+                "SELECT q.id, q.ticker_id, q.price, q.time, q.volume, q.quality_score, q.source,
+                q.open, q.high, q.low, q.bid, q.ask, t.currency
+                FROM {}
+                ORDER BY q.time DESC, t.priority ASC",
+                from_where
+            ))
+            .map_err(|e| DataError::NotFound(e.to_string()))?;
+        let mut rows = stmt
+            .query(params)
+            .map_err(|e| DataError::NotFound(e.to_string()))?;
+        let mut candidates: Vec<(Quote, String)> = Vec::new();
+        while let Some(row) = rows.next().map_err(|e| DataError::NotFound(e.to_string()))? {
+            let quote = quote_from_row(row).map_err(|e| DataError::NotFound(e.to_string()))?;
+            if let Some((first, _)) = candidates.first() {
+                if first.time != quote.time {
+                    break;
+                }
+            }
+            let currency: String = row
+                .get(12)
+                .map_err(|e| DataError::NotFound(e.to_string()))?;
+            candidates.push((quote, currency));
+        }
+        let (quote, currency) = Quote::select_preferred(candidates)
+            .ok_or_else(|| DataError::NotFound("no quote found".to_string()))?;
+        let currency =
+            Currency::from_stored(&currency).map_err(|e| DataError::NotFound(e.to_string()))?;
+        Ok((quote, currency))
+    }
+
+    /// Recalculate the time of the latest quote of a ticker, e.g. after quotes have been
+    /// changed or deleted
+    fn refresh_last_quote_time(&self, ticker_id: i64) -> Result<(), DataError> {
+        self.conn
+            .execute(
+                "UPDATE ticker SET last_quote_time = \
+                (SELECT MAX(time) FROM quotes WHERE ticker_id = ticker.id) WHERE id=?1",
+                params![ticker_id],
+            )
+            .map_err(|e| DataError::UpdateFailed(e.to_string()))?;
+        Ok(())
+    }
+
+    /// Insert quotes one by one using a cached prepared statement. This does not take care of
+    /// atomicity, it is intended to be called within a database transaction.
+    fn insert_each_quote(&self, quotes: &[Quote]) -> Result<Vec<usize>, DataError> {
+        let mut stmt = self
+            .conn
+            .prepare_cached(
+                "INSERT INTO quotes (ticker_id, price, time, volume, quality_score, source,
+                open, high, low, bid, ask)
+                VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+            )
+            .map_err(|e| DataError::InsertFailed(e.to_string()))?;
+        let mut ids = Vec::with_capacity(quotes.len());
+        for quote in quotes {
+            quote.validate()?;
+            let id = stmt
+                .insert(params![
+                    quote.ticker as i64,
+                    quote.price,
+                    quote.time.to_rfc3339(),
+                    quote.volume,
+                    quote.quality_score,
+                    quote.source,
+                    quote.open,
+                    quote.high,
+                    quote.low,
+                    quote.bid,
+                    quote.ask,
+                ])
+                .map_err(|e| DataError::InsertFailed(e.to_string()))?;
+            ids.push(id as usize);
+        }
+        let mut ticker_ids: Vec<i64> = quotes.iter().map(|q| q.ticker as i64).collect();
+        ticker_ids.sort_unstable();
+        ticker_ids.dedup();
+        for ticker_id in ticker_ids {
+            self.refresh_last_quote_time(ticker_id)?;
+        }
+        Ok(ids)
+    }
+
+    /// Set the time of the latest quote of the quote's ticker to the time of the new quote,
+    /// unless the ticker has a later quote already
+    fn advance_last_quote_time(&self, quote: &Quote) -> Result<(), DataError> {
+        self.conn
+            .execute(
+                "UPDATE ticker SET last_quote_time=?1 \
+                WHERE id=?2 AND (last_quote_time IS NULL OR last_quote_time < ?1)",
+                params![quote.time.to_rfc3339(), quote.ticker as i64],
+            )
+            .map_err(|e| DataError::UpdateFailed(e.to_string()))?;
+        Ok(())
+    }
 
-/// Sqlite implementation of quote handler
-impl QuoteHandler for SqliteDB<'_> {
+    /// Id of the ticker a stored quote belongs to
+    fn ticker_id_of_quote(&self, quote_id: i64) -> Result<i64, DataError> {
+        self.conn
+            .query_row(
+                "SELECT ticker_id FROM quotes WHERE id=?1",
+                params![quote_id],
+                |row| row.get(0),
+            )
+            .map_err(|e| DataError::NotFound(e.to_string()))
+    }
+}
+
+/// Sqlite implementation of writing quotes, `QuoteHandler` is implemented by `QuoteReader`
+/// and `QuoteWriter`
+impl finql_data::QuoteWriter for SqliteDB<'_> {
     // insert, get, update and delete for market data sources
     fn insert_ticker(&mut self, ticker: &Ticker) -> Result<usize, DataError> {
+        ticker.validate()?;
         self.conn
             .execute(
-                "INSERT INTO ticker (name, asset_id, source, priority, currency, factor) VALUES (?, ?, ?, ?, ?, ?)",
+                "INSERT INTO ticker (name, asset_id, source, priority, currency, factor, source_url, usage,
+                source_chain) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)",
                 params![
                     ticker.name,
                     ticker.asset as i64,
@@ -53,6 +292,9 @@ impl QuoteHandler for SqliteDB<'_> {
                     ticker.priority,
                     ticker.currency.to_string(),
                     ticker.factor,
+                    ticker.source_url,
+                    ticker.usage.to_string(),
+                    source_chain_column(ticker),
                 ],
             )
             .map_err(|e| DataError::InsertFailed(e.to_string()))?;
@@ -71,159 +313,6 @@ impl QuoteHandler for SqliteDB<'_> {
         Ok(id)
     }
 
-    fn get_ticker_id(&mut self, ticker: &str) -> Option<usize> {
-        let get_id = |row: &Row| -> rusqlite::Result<i64> { row.get(0) };
-        let id = self.conn.query_row(
-            "SELECT id FROM ticker WHERE name=?",
-            params![ticker],
-            get_id,
-        );
-        match id {
-            Ok(id) => Some(id as usize),
-            _ => None,
-        }
-    }
-
-    fn get_ticker_by_id(&mut self, id: usize) -> Result<Ticker, DataError> {
-        let (name, asset, source, priority, currency, factor) = self
-            .conn
-            .query_row(
-                "SELECT name, asset_id, source, priority, currency, factor FROM ticker WHERE id=?;",
-                params![id as i64],
-                |row| {
-                    let name: String = row.get(0)?;
-                    let asset: i64 = row.get(1)?;
-                    let source: String = row.get(2)?;
-                    let priority: i32 = row.get(3)?;
-                    let currency: String = row.get(4)?;
-                    let factor: f64 = row.get(5)?;
-                    Ok((name, asset, source, priority, currency, factor))
-                },
-            )
-            .map_err(|e| DataError::NotFound(e.to_string()))?;
-        let currency =
-            Currency::from_str(&currency).map_err(|e| DataError::NotFound(e.to_string()))?;
-        Ok(Ticker {
-            id: Some(id),
-            name,
-            asset: asset as usize,
-            source,
-            priority,
-            currency,
-            factor,
-        })
-    }
-
-    fn get_all_ticker(&mut self) -> Result<Vec<Ticker>, DataError> {
-        let mut stmt = self
-            .conn
-            .prepare("SELECT id, name, asset_id, priority, source, currency, factor FROM ticker;")
-            .map_err(|e| DataError::NotFound(e.to_string()))?;
-        let ticker_map = stmt
-            .query_map(NO_PARAMS, |row| {
-                let id: i64 = row.get(0)?;
-                let name: String = row.get(1)?;
-                let asset: i64 = row.get(2)?;
-                let priority: i32 = row.get(3)?;
-                let source: String = row.get(4)?;
-                let currency: String = row.get(5)?;
-                let factor: f64 = row.get(6)?;
-                Ok((id, name, asset, priority, source, currency, factor))
-            })
-            .map_err(|e| DataError::NotFound(e.to_string()))?;
-        let mut all_ticker = Vec::new();
-        for ticker in ticker_map {
-            let (id, name, asset, priority, source, currency, factor) = ticker.unwrap();
-            let currency =
-                Currency::from_str(&currency).map_err(|e| DataError::NotFound(e.to_string()))?;
-            all_ticker.push(Ticker {
-                id: Some(id as usize),
-                name,
-                asset: asset as usize,
-                source,
-                priority,
-                currency,
-                factor,
-            });
-        }
-        Ok(all_ticker)
-    }
-
-    fn get_all_ticker_for_source(
-        &mut self,
-        source: &str,
-    ) -> Result<Vec<Ticker>, DataError> {
-        let mut stmt = self
-            .conn
-            .prepare(
-                "SELECT id, name, asset_id, priority, currency, factor FROM ticker WHERE source=?;",
-            )
-            .map_err(|e| DataError::NotFound(e.to_string()))?;
-        let ticker_map = stmt
-            .query_map(params![source.to_string()], |row| {
-                let id: i64 = row.get(0)?;
-                let name: String = row.get(1)?;
-                let asset: i64 = row.get(2)?;
-                let priority: i32 = row.get(3)?;
-                let currency: String = row.get(4)?;
-                let factor: f64 = row.get(5)?;
-                Ok((id, name, asset, priority, currency, factor))
-            })
-            .map_err(|e| DataError::NotFound(e.to_string()))?;
-        let mut all_ticker = Vec::new();
-        for ticker in ticker_map {
-            let (id, name, asset, priority, currency, factor) = ticker.unwrap();
-            let currency =
-                Currency::from_str(&currency).map_err(|e| DataError::NotFound(e.to_string()))?;
-            all_ticker.push(Ticker {
-                id: Some(id as usize),
-                name,
-                asset: asset as usize,
-                source: source.to_string(),
-                priority,
-                currency,
-                factor,
-            });
-        }
-        Ok(all_ticker)
-    }
-    fn get_all_ticker_for_asset(
-        &mut self,
-        asset_id: usize,
-    ) -> Result<Vec<Ticker>, DataError> {
-        let mut stmt = self
-            .conn
-            .prepare("SELECT id, name, priority, source, currency, factor FROM ticker WHERE asset_id=?;")
-            .map_err(|e| DataError::NotFound(e.to_string()))?;
-        let ticker_map = stmt
-            .query_map(params![asset_id as i32], |row| {
-                let id: i64 = row.get(0)?;
-                let name: String = row.get(1)?;
-                let priority: i32 = row.get(2)?;
-                let source: String = row.get(3)?;
-                let currency: String = row.get(4)?;
-                let factor: f64 = row.get(5)?;
-                Ok((id, name, priority, source, currency, factor))
-            })
-            .map_err(|e| DataError::NotFound(e.to_string()))?;
-        let mut all_ticker = Vec::new();
-        for ticker in ticker_map {
-            let (id, name, priority, source, currency, factor) = ticker.unwrap();
-            let currency =
-                Currency::from_str(&currency).map_err(|e| DataError::NotFound(e.to_string()))?;
-            all_ticker.push(Ticker {
-                id: Some(id as usize),
-                name,
-                asset: asset_id,
-                source,
-                priority,
-                currency,
-                factor,
-            });
-        }
-        Ok(all_ticker)
-    }
-
     fn update_ticker(&mut self, ticker: &Ticker) -> Result<(), DataError> {
         if ticker.id.is_none() {
             return Err(DataError::NotFound(
@@ -233,7 +322,8 @@ impl QuoteHandler for SqliteDB<'_> {
         let id = ticker.id.unwrap() as i64;
         self.conn
             .execute(
-                "UPDATE ticker SET name=?2, asset_id=?3, source=?4, priority=?5, currency=?6, factor=?7
+                "UPDATE ticker SET name=?2, asset_id=?3, source=?4, priority=?5, currency=?6, factor=?7, source_url=?8, usage=?9,
+                source_chain=?10
                 WHERE id=?1",
                 params![
                     id,
@@ -243,6 +333,9 @@ impl QuoteHandler for SqliteDB<'_> {
                     ticker.priority,
                     ticker.currency.to_string(),
                     ticker.factor,
+                    ticker.source_url,
+                    ticker.usage.to_string(),
+                    source_chain_column(ticker),
                 ],
             )
             .map_err(|e| DataError::InsertFailed(e.to_string()))?;
@@ -257,14 +350,24 @@ impl QuoteHandler for SqliteDB<'_> {
 
     // insert, get, update and delete for market data sources
     fn insert_quote(&mut self, quote: &Quote) -> Result<usize, DataError> {
+        quote.validate()?;
         self.conn
             .execute(
-                "INSERT INTO quotes (ticker_id, price, time, volume) VALUES (?, ?, ?, ?)",
+                "INSERT INTO quotes (ticker_id, price, time, volume, quality_score, source,
+                open, high, low, bid, ask)
+                VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
                 params![
                     quote.ticker as i64,
                     quote.price,
                     quote.time.to_rfc3339(),
-                    quote.volume
+                    quote.volume,
+                    quote.quality_score,
+                    quote.source,
+                    quote.open,
+                    quote.high,
+                    quote.low,
+                    quote.bid,
+                    quote.ask,
                 ],
             )
             .map_err(|e| DataError::InsertFailed(e.to_string()))?;
@@ -275,118 +378,47 @@ impl QuoteHandler for SqliteDB<'_> {
                 Ok(id as usize)
             })
             .map_err(|e| DataError::NotFound(e.to_string()))?;
+        self.advance_last_quote_time(quote)?;
         Ok(id)
     }
-    fn get_last_quote_before(
-        &mut self,
-        asset_name: &str,
-        time: DateTime<Utc>,
-    ) -> Result<(Quote, Currency), DataError> {
-        let time = time.to_rfc3339();
-        let row = self
-            .conn
-            .query_row(
-                "SELECT q.id, q.ticker_id, q.price, q.time, q.volume, t.currency, t.priority
-                FROM quotes q, ticker t, assets a 
-                WHERE a.name=? AND t.asset_id=a.id AND t.id=q.ticker_id AND q.time<= ?
-                ORDER BY q.time, t.priority DESC LIMIT 1",
-                params![asset_name, time],
-                |row| {
-                    let id: i64 = row.get(0)?;
-                    let ticker: i64 = row.get(1)?;
-                    let price: f64 = row.get(2)?;
-                    let time: String = row.get(3)?;
-                    let volume: Option<f64> = row.get(4)?;
-                    let currency: String = row.get(5)?;
-                    Ok((id, ticker, price, time, volume, currency))
-                },
-            )
-            .map_err(|e| DataError::NotFound(e.to_string()))?;
-        let (id, ticker, price, time, volume, currency) = row;
-        let currency =
-            Currency::from_str(&currency).map_err(|e| DataError::NotFound(e.to_string()))?;
-        let time = to_time(&time).map_err(|e| DataError::NotFound(e.to_string()))?;
-        Ok((
-            Quote {
-                id: Some(id as usize),
-                ticker: ticker as usize,
-                price,
-                time,
-                volume,
-            },
-            currency,
-        ))
-    }
-    fn get_last_quote_before_by_id(
-        &mut self,
-        asset_id: usize,
-        time: DateTime<Utc>,
-    ) -> Result<(Quote, Currency), DataError> {
-        let time = time.to_rfc3339();
-        let row = self
+    fn insert_quote_if_new(&mut self, quote: &Quote) -> Result<QuoteInsertion, DataError> {
+        quote.validate()?;
+        let inserted = self
             .conn
-            .query_row(
-                "SELECT q.id, q.ticker_id, q.price, q.time, q.volume, t.currency, t.priority
-                FROM quotes q, ticker t 
-                WHERE t.asset_id=? AND t.id=q.ticker_id AND q.time<= ?
-                ORDER BY q.time, t.priority DESC LIMIT 1",
-                params![asset_id as i64, time],
-                |row| {
-                    let id: i64 = row.get(0)?;
-                    let ticker: i64 = row.get(1)?;
-                    let price: f64 = row.get(2)?;
-                    let time: String = row.get(3)?;
-                    let volume: Option<f64> = row.get(4)?;
-                    let currency: String = row.get(5)?;
-                    Ok((id, ticker, price, time, volume, currency))
-                },
-            )
-            .map_err(|e| DataError::NotFound(e.to_string()))?;
-        let (id, ticker, price, time, volume, currency) = row;
-        let currency =
-            Currency::from_str(&currency).map_err(|e| DataError::NotFound(e.to_string()))?;
-        let time = to_time(&time).map_err(|e| DataError::NotFound(e.to_string()))?;
-        Ok((
-            Quote {
-                id: Some(id as usize),
-                ticker: ticker as usize,
-                price,
-                time,
-                volume,
-            },
-            currency,
-        ))
-    }
-    fn get_all_quotes_for_ticker(&mut self, ticker_id: usize) -> Result<Vec<Quote>, DataError> {
-        let mut stmt = self
-            .conn
-            .prepare(
-                "SELECT id, price, time, volume FROM quotes 
-            WHERE ticker_id=? ORDER BY time ASC;",
+            .execute(
+                "INSERT INTO quotes (ticker_id, price, time, volume, quality_score, source,
+                open, high, low, bid, ask)
+                VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+                ON CONFLICT (ticker_id, time) DO NOTHING",
+                params![
+                    quote.ticker as i64,
+                    quote.price,
+                    quote.time.to_rfc3339(),
+                    quote.volume,
+                    quote.quality_score,
+                    quote.source,
+                    quote.open,
+                    quote.high,
+                    quote.low,
+                    quote.bid,
+                    quote.ask,
+                ],
             )
-            .map_err(|e| DataError::NotFound(e.to_string()))?;
-        let quotes_map = stmt
-            .query_map(params![ticker_id as i64], |row| {
-                let id: i64 = row.get(0)?;
-                let price: f64 = row.get(1)?;
-                let time: String = row.get(2)?;
-                let volume: Option<f64> = row.get(3)?;
-                Ok((id, price, time, volume))
-            })
-            .map_err(|e| DataError::NotFound(e.to_string()))?;
-        let mut quotes = Vec::new();
-        for quote in quotes_map {
-            let (id, price, time, volume) = quote.unwrap();
-            let time = to_time(&time)?;
-            quotes.push(Quote {
-                id: Some(id as usize),
-                ticker: ticker_id,
-                price,
-                time,
-                volume,
-            });
+            .map_err(|e| DataError::InsertFailed(e.to_string()))?;
+        if inserted == 0 {
+            let id: i64 = self
+                .conn
+                .query_row(
+                    "SELECT id FROM quotes WHERE ticker_id=?1 AND time=?2",
+                    params![quote.ticker as i64, quote.time.to_rfc3339()],
+                    |row| row.get(0),
+                )
+                .map_err(|e| DataError::NotFound(e.to_string()))?;
+            return Ok(QuoteInsertion::Existing(id as usize));
         }
-        Ok(quotes)
+        let id = self.conn.last_insert_rowid() as usize;
+        self.advance_last_quote_time(quote)?;
+        Ok(QuoteInsertion::Inserted(id))
     }
 
     fn update_quote(&mut self, quote: &Quote) -> Result<(), DataError> {
@@ -396,33 +428,316 @@ impl QuoteHandler for SqliteDB<'_> {
             ));
         }
         let id = quote.id.unwrap() as i64;
+        let old_ticker_id = self.ticker_id_of_quote(id)?;
         self.conn
             .execute(
-                "UPDATE quotes SET ticker_id=?2, price=?2, time=?4, volume=?5
+                "UPDATE quotes SET ticker_id=?2, price=?3, time=?4, volume=?5, quality_score=?6,
+                source=?7, open=?8, high=?9, low=?10, bid=?11, ask=?12
                 WHERE id=?1",
                 params![
                     id,
                     quote.ticker as i64,
                     quote.price,
                     quote.time.to_rfc3339(),
-                    quote.volume
+                    quote.volume,
+                    quote.quality_score,
+                    quote.source,
+                    quote.open,
+                    quote.high,
+                    quote.low,
+                    quote.bid,
+                    quote.ask,
                 ],
             )
             .map_err(|e| DataError::InsertFailed(e.to_string()))?;
+        self.refresh_last_quote_time(old_ticker_id)?;
+        if old_ticker_id != quote.ticker as i64 {
+            self.refresh_last_quote_time(quote.ticker as i64)?;
+        }
         Ok(())
     }
     fn delete_quote(&mut self, id: usize) -> Result<(), DataError> {
+        let ticker_id = self.ticker_id_of_quote(id as i64)?;
         self.conn
             .execute("DELETE FROM quotes WHERE id=?1;", params![id as i64])
             .map_err(|e| DataError::InsertFailed(e.to_string()))?;
+        self.refresh_last_quote_time(ticker_id)
+    }
+
+    fn delete_quotes_for_ticker(&mut self, ticker_id: usize) -> Result<usize, DataError> {
+        let deleted = self
+            .conn
+            .execute(
+                "DELETE FROM quotes WHERE ticker_id=?1;",
+                params![ticker_id as i64],
+            )
+            .map_err(|e| DataError::DeleteFailed(e.to_string()))?;
+        self.refresh_last_quote_time(ticker_id as i64)?;
+        Ok(deleted)
+    }
+
+    fn delete_quotes_before(
+        &mut self,
+        ticker_id: usize,
+        time: DateTime<Utc>,
+    ) -> Result<usize, DataError> {
+        // times are stored in RFC 3339 format, i.e. they can be compared as strings
+        let deleted = self
+            .conn
+            .execute(
+                "DELETE FROM quotes WHERE ticker_id=?1 AND time < ?2;",
+                params![ticker_id as i64, time.to_rfc3339()],
+            )
+            .map_err(|e| DataError::DeleteFailed(e.to_string()))?;
+        self.refresh_last_quote_time(ticker_id as i64)?;
+        Ok(deleted)
+    }
+
+    fn store_quotes(&mut self, quotes: &[Quote]) -> Result<Vec<usize>, DataError> {
+        self.conn
+            .execute_batch("BEGIN;")
+            .map_err(|e| DataError::DataAccessFailure(e.to_string()))?;
+        match store_each_quote(self, quotes) {
+            Ok(ids) => {
+                self.conn
+                    .execute_batch("COMMIT;")
+                    .map_err(|e| DataError::DataAccessFailure(e.to_string()))?;
+                Ok(ids)
+            }
+            Err(err) => {
+                self.conn
+                    .execute_batch("ROLLBACK;")
+                    .map_err(|e| DataError::DataAccessFailure(e.to_string()))?;
+                Err(err)
+            }
+        }
+    }
+
+    fn insert_quotes(&mut self, quotes: &[Quote]) -> Result<Vec<usize>, DataError> {
+        self.conn
+            .execute_batch("BEGIN;")
+            .map_err(|e| DataError::DataAccessFailure(e.to_string()))?;
+        match self.insert_each_quote(quotes) {
+            Ok(ids) => {
+                self.conn
+                    .execute_batch("COMMIT;")
+                    .map_err(|e| DataError::DataAccessFailure(e.to_string()))?;
+                Ok(ids)
+            }
+            Err(err) => {
+                self.conn
+                    .execute_batch("ROLLBACK;")
+                    .map_err(|e| DataError::DataAccessFailure(e.to_string()))?;
+                Err(err)
+            }
+        }
+    }
+
+    fn set_rounding_digits(&mut self, currency: Currency, digits: i32) -> Result<(), DataError> {
+        self.conn
+            .execute(
+                "INSERT OR REPLACE INTO rounding_digits (currency, digits) VALUES (?1, ?2)",
+                params![currency.to_string(), digits],
+            )
+            .map_err(|e| DataError::InsertFailed(e.to_string()))?;
+        Ok(())
+    }
+
+    fn add_quota_usage(
+        &mut self,
+        source: &str,
+        month: QuotaMonth,
+        calls: u64,
+    ) -> Result<(), DataError> {
+        self.conn
+            .execute(
+                "INSERT INTO quota_usage (source, month, calls) VALUES (?1, ?2, ?3)
+                ON CONFLICT (source, month) DO UPDATE SET calls=calls+excluded.calls",
+                params![source, month.to_string(), calls as i64],
+            )
+            .map_err(|e| DataError::InsertFailed(e.to_string()))?;
         Ok(())
     }
+}
+
+/// Sqlite implementation of read-only quote access
+impl QuoteReader for SqliteDB<'_> {
+    fn get_ticker_id(&self, ticker: &str) -> Option<usize> {
+        let get_id = |row: &Row| -> rusqlite::Result<i64> { row.get(0) };
+        let id = self.conn.query_row(
+            "SELECT id FROM ticker WHERE name=?",
+            params![ticker],
+            get_id,
+        );
+        match id {
+            Ok(id) => Some(id as usize),
+            _ => None,
+        }
+    }
+
+    fn get_ticker_id_for_source(&self, name: &str, source: &str) -> Option<usize> {
+        let get_id = |row: &Row| -> rusqlite::Result<i64> { row.get(0) };
+        let id = self.conn.query_row(
+            "SELECT id FROM ticker WHERE name=? AND source=?",
+            params![name, source],
+            get_id,
+        );
+        match id {
+            Ok(id) => Some(id as usize),
+            _ => None,
+        }
+    }
+
+    fn get_ticker_by_id(&self, id: usize) -> Result<Ticker, DataError> {
+        self.conn
+            .query_row(
+                &format!("SELECT {} FROM ticker WHERE id=?;", TICKER_COLUMNS),
+                params![id as i64],
+                ticker_from_row,
+            )
+            .map_err(|e| DataError::NotFound(e.to_string()))
+    }
+
+    fn get_all_ticker(&self) -> Result<Vec<Ticker>, DataError> {
+        self.query_ticker("1=1", NO_PARAMS)
+    }
+
+    fn get_all_ticker_for_source(
+        &self,
+        source: &str,
+    ) -> Result<Vec<Ticker>, DataError> {
+        self.query_ticker("source=?", &[&source])
+    }
+
+    fn get_all_ticker_for_asset(
+        &self,
+        asset_id: usize,
+    ) -> Result<Vec<Ticker>, DataError> {
+        self.query_ticker("asset_id=?", &[&(asset_id as i64)])
+    }
 
-    fn get_rounding_digits(&mut self, currency: Currency) -> i32 {
+    fn get_tickers_by_source_url_pattern(
+        &self,
+        url_pattern: &str,
+    ) -> Result<Vec<Ticker>, DataError> {
+        self.query_ticker("source_url LIKE ?", &[&url_pattern])
+    }
+
+    fn get_last_quote_before_for_usage(
+        &self,
+        asset_name: &str,
+        time: DateTime<Utc>,
+        usage: TickerUsage,
+    ) -> Result<(Quote, Currency), DataError> {
+        self.query_last_quote_before(
+            "quotes q, ticker t, assets a
+            WHERE a.name=?1 AND t.asset_id=a.id AND t.id=q.ticker_id AND q.time<= ?2
+            AND (t.usage=?3 OR t.usage='both' OR ?3='both')",
+            &[&asset_name, &time.to_rfc3339(), &usage.to_string()],
+        )
+    }
+    fn get_last_quote_before_by_id_for_usage(
+        &self,
+        asset_id: usize,
+        time: DateTime<Utc>,
+        usage: TickerUsage,
+    ) -> Result<(Quote, Currency), DataError> {
+        self.query_last_quote_before(
+            "quotes q, ticker t
+            WHERE t.asset_id=?1 AND t.id=q.ticker_id AND q.time<= ?2
+            AND (t.usage=?3 OR t.usage='both' OR ?3='both')",
+            &[&(asset_id as i64), &time.to_rfc3339(), &usage.to_string()],
+        )
+    }
+    fn get_all_quotes_for_ticker(&self, ticker_id: usize) -> Result<Vec<Quote>, DataError> {
+        self.query_quotes("ticker_id=?", &[&(ticker_id as i64)])
+    }
+
+    fn get_raw_quotes_for_asset(
+        &self,
+        asset_id: usize,
+        usage: TickerUsage,
+        start: NaiveDate,
+        end: NaiveDate,
+    ) -> Result<Vec<RawQuote>, DataError> {
+        // Query quotes per ticker, since joining with the ticker table makes sqlite
+        // build a temporary index on all quotes each time.
+        let mut stmt = self
+            .conn
+            .prepare(
+                "SELECT time, price FROM quotes
+                WHERE ticker_id=?1 AND time >= ?2 AND time < ?3;",
+            )
+            .map_err(|e| DataError::NotFound(e.to_string()))?;
+        // times are stored in RFC 3339 format, i.e. they can be compared as strings
+        let start = start.format("%Y-%m-%d").to_string();
+        let end = end.succ().format("%Y-%m-%d").to_string();
+        let mut raw_quotes = Vec::new();
+        for ticker in QuoteReader::get_all_ticker_for_asset(self, asset_id)? {
+            if !ticker.usage.serves(usage) {
+                continue;
+            }
+            let priority = ticker.priority;
+            let rows = stmt
+                .query_map(params![ticker.get_id()? as i64, start, end], |row| {
+                    Ok(RawQuote {
+                        time: row.get(0)?,
+                        price: row.get(1)?,
+                        priority,
+                    })
+                })
+                .map_err(|e| DataError::NotFound(e.to_string()))?;
+            for row in rows {
+                raw_quotes.push(row.map_err(|e| DataError::NotFound(e.to_string()))?);
+            }
+        }
+        Ok(raw_quotes)
+    }
+
+    fn get_latest_quotes_for_tickers(
+        &self,
+        ticker_ids: &[usize],
+    ) -> Result<Vec<(usize, Quote)>, DataError> {
+        if ticker_ids.is_empty() {
+            return Ok(Vec::new());
+        }
+        let placeholders = vec!["?"; ticker_ids.len()].join(", ");
+        let mut stmt = self
+            .conn
+            .prepare(&format!(
+                "SELECT q.id, q.ticker_id, q.price, q.time, q.volume, q.quality_score, q.source,
+                q.open, q.high, q.low, q.bid, q.ask
+                FROM quotes q
+                JOIN (SELECT ticker_id, MAX(time) AS max_time FROM quotes
+                    WHERE ticker_id IN ({}) GROUP BY ticker_id) m
+                ON q.ticker_id=m.ticker_id AND q.time=m.max_time
+                ORDER BY q.id DESC;",
+                placeholders
+            ))
+            .map_err(|e| DataError::NotFound(e.to_string()))?;
+        let ids: Vec<i64> = ticker_ids.iter().map(|id| *id as i64).collect();
+        let quotes_map = stmt
+            .query_map(ids, quote_from_row)
+            .map_err(|e| DataError::NotFound(e.to_string()))?;
+        let mut latest = BTreeMap::new();
+        for quote in quotes_map {
+            let quote = quote.map_err(|e| DataError::NotFound(e.to_string()))?;
+            latest.entry(quote.ticker).or_insert(quote);
+        }
+        let mut quotes = Vec::new();
+        for ticker_id in ticker_ids {
+            if let Some(quote) = latest.get(ticker_id) {
+                quotes.push((*ticker_id, quote.clone()));
+            }
+        }
+        Ok(quotes)
+    }
+
+    fn get_rounding_digits(&self, currency: Currency) -> i32 {
         let digits = self
             .conn
             .query_row(
-                "SELECT digits FROM rounding_digits WHERE currency=?;",
+                "SELECT digits FROM rounding_digits WHERE currency=? ORDER BY id DESC LIMIT 1;",
                 params![currency.to_string()],
                 |row| {
                     let digits: i32 = row.get(0)?;
@@ -430,19 +745,923 @@ impl QuoteHandler for SqliteDB<'_> {
                 },
             )
             .map_err(|e| DataError::NotFound(e.to_string()));
-        match digits {
-            Ok(digits) => digits,
-            Err(_) => 2,
+        digits.unwrap_or(2)
+    }
+
+    fn get_all_quotes_for_ticker_and_source(
+        &self,
+        asset_id: usize,
+        source: &str,
+    ) -> Result<Vec<Quote>, DataError> {
+        self.query_quotes(
+            "ticker_id IN (SELECT id FROM ticker WHERE asset_id=?1 AND source=?2)",
+            &[&(asset_id as i64), &source],
+        )
+    }
+
+    fn get_quotes_above_quality(
+        &self,
+        ticker_id: usize,
+        min_quality: f64,
+    ) -> Result<Vec<Quote>, DataError> {
+        self.query_quotes(
+            "ticker_id=?1 AND quality_score >= ?2",
+            &[&(ticker_id as i64), &min_quality],
+        )
+    }
+
+    fn get_quotes_in_range(
+        &self,
+        ticker_id: usize,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+    ) -> Result<Vec<Quote>, DataError> {
+        // times are stored in RFC 3339 format, i.e. they can be compared as strings
+        self.query_quotes(
+            "ticker_id=?1 AND time >= ?2 AND time <= ?3",
+            &[&(ticker_id as i64), &start.to_rfc3339(), &end.to_rfc3339()],
+        )
+    }
+
+    fn get_quotes_page(
+        &self,
+        query: &QuoteQuery,
+        after_id: Option<usize>,
+        limit: usize,
+    ) -> Result<Vec<Quote>, DataError> {
+        let after_id = after_id.map_or(0, |id| id as i64);
+        let limit = limit as i64;
+        // times are stored in RFC 3339 format, i.e. they can be compared as strings
+        let start = query.start.map(|time| time.to_rfc3339());
+        let end = query.end.map(|time| time.to_rfc3339());
+        let mut conditions = vec!["id > ?1".to_string()];
+        let mut params: Vec<&dyn ToSql> = vec![&after_id, &limit];
+        if let Some(ticker_ids) = &query.ticker_ids {
+            let ids: Vec<String> = ticker_ids.iter().map(|id| id.to_string()).collect();
+            conditions.push(format!("ticker_id IN ({})", ids.join(",")));
+        }
+        if let Some(start) = &start {
+            params.push(start);
+            conditions.push(format!("time >= ?{}", params.len()));
+        }
+        if let Some(end) = &end {
+            params.push(end);
+            conditions.push(format!("time <= ?{}", params.len()));
         }
+        let mut stmt = self
+            .conn
+            .prepare(&format!(
+                "SELECT {} FROM quotes WHERE {} ORDER BY id LIMIT ?2;",
+                QUOTE_COLUMNS,
+                conditions.join(" AND ")
+            ))
+            .map_err(|e| DataError::NotFound(e.to_string()))?;
+        let quotes_map = stmt
+            .query_map(&params, quote_from_row)
+            .map_err(|e| DataError::NotFound(e.to_string()))?;
+        let mut quotes = Vec::new();
+        for quote in quotes_map {
+            quotes.push(quote.map_err(|e| DataError::NotFound(e.to_string()))?);
+        }
+        Ok(quotes)
     }
 
-    fn set_rounding_digits(&mut self, currency: Currency, digits: i32) -> Result<(), DataError> {
-        self.conn
-            .execute(
-                "INSERT INTO rounding_digits (currency, digits) VALUES (?1, ?2)",
-                params![currency.to_string(), digits],
+    fn get_stale_tickers(&self, before: DateTime<Utc>) -> Result<Vec<Ticker>, DataError> {
+        self.query_ticker(
+            "last_quote_time IS NULL OR last_quote_time < ?1",
+            &[&before.to_rfc3339()],
+        )
+    }
+
+    fn get_quota_usage(&self, source: &str, month: QuotaMonth) -> Result<u64, DataError> {
+        let calls: i64 = self
+            .conn
+            .query_row(
+                "SELECT COALESCE(SUM(calls), 0) FROM quota_usage WHERE source=?1 AND month=?2",
+                params![source, month.to_string()],
+                |row| row.get(0),
             )
-            .map_err(|e| DataError::InsertFailed(e.to_string()))?;
-        Ok(())
+            .map_err(|e| DataError::NotFound(e.to_string()))?;
+        Ok(calls as u64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, RwLock};
+    use std::thread;
+
+    use rusqlite::Connection;
+
+    use crate::{OwnedSqliteDB, SharedSqliteDB};
+
+    use finql_data::{
+        refresh_ticker, Asset, AssetHandler, CachedQuoteHandler, CurrencyConverter, CurrencyError,
+        DistributionPolicy, QuoteCacheConfig, QuoteCacheStats, QuoteHandler, QuoteProvider,
+        QuoteProviderRegistry,
+    };
+
+    /// Store an asset with a single ticker and return the ticker id
+    fn init_ticker(conn: &Connection) -> usize {
+        let mut db = SqliteDB::new(conn);
+        db.init().unwrap();
+        let asset_id = db
+            .insert_asset(&Asset::new(None, "asset", None, None, None))
+            .unwrap();
+        db.insert_ticker(&make_ticker("TICK", asset_id, 1, TickerUsage::Both))
+            .unwrap()
+    }
+
+    /// Let one writer thread insert quotes while several reader threads read them
+    fn check_readers_and_writer(db: SharedSqliteDB, ticker_id: usize) {
+        // all threads share the same handler, readers only need the read lock
+        let db = Arc::new(RwLock::new(db));
+
+        let num_quotes = 20;
+        let writer = {
+            let db = Arc::clone(&db);
+            thread::spawn(move || {
+                for i in 0..num_quotes {
+                    db.write()
+                        .unwrap()
+                        .insert_quote(&Quote {
+                            id: None,
+                            ticker: ticker_id,
+                            price: 100.0 + i as f64,
+                            time: Utc.ymd(2020, 1, 1).and_hms(0, 0, 0)
+                                + chrono::Duration::days(i),
+                            volume: None,
+                            quality_score: None,
+                            source: None,
+                            open: None,
+                            high: None,
+                            low: None,
+                            bid: None,
+                            ask: None,
+                        })
+                        .unwrap();
+                }
+            })
+        };
+        let readers: Vec<_> = (0..4)
+            .map(|_| {
+                let db = Arc::clone(&db);
+                thread::spawn(move || {
+                    let mut seen = 0;
+                    while seen < num_quotes as usize {
+                        let db = db.read().unwrap();
+                        let quotes = db.get_all_quotes_for_ticker(ticker_id).unwrap();
+                        assert!(quotes.len() >= seen);
+                        seen = quotes.len();
+                        assert_eq!(db.get_ticker_by_id(ticker_id).unwrap().name, "TICK");
+                    }
+                })
+            })
+            .collect();
+        writer.join().unwrap();
+        for reader in readers {
+            reader.join().unwrap();
+        }
+
+        let db = db.read().unwrap();
+        let quotes = db.get_all_quotes_for_ticker(ticker_id).unwrap();
+        assert_eq!(quotes.len(), num_quotes as usize);
+        let eur = Currency::from_str("EUR").unwrap();
+        assert_eq!(QuoteReader::get_rounding_digits(&*db, eur), 2);
+    }
+
+    #[test]
+    fn concurrent_readers_and_writer() {
+        let conn = Connection::open(":memory:").unwrap();
+        let ticker_id = init_ticker(&conn);
+        check_readers_and_writer(SharedSqliteDB::new(OwnedSqliteDB { conn }), ticker_id);
+    }
+
+    #[test]
+    fn concurrent_readers_with_own_connections() {
+        let path = std::env::temp_dir().join(format!("finql_readers_{}.db", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let conn = Connection::open(&path).unwrap();
+        let ticker_id = init_ticker(&conn);
+        let readers = (0..3)
+            .map(|_| OwnedSqliteDB {
+                conn: Connection::open(&path).unwrap(),
+            })
+            .collect();
+        check_readers_and_writer(
+            SharedSqliteDB::with_readers(OwnedSqliteDB { conn }, readers),
+            ticker_id,
+        );
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    fn make_ticker(name: &str, asset: usize, priority: i32, usage: TickerUsage) -> Ticker {
+        Ticker {
+            id: None,
+            name: name.to_string(),
+            asset,
+            source: "manual".to_string(),
+            priority,
+            currency: Currency::from_str("EUR").unwrap(),
+            factor: 1.0,
+            source_url: None,
+            usage,
+            last_quote_time: None,
+            source_chain: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn update_quote_round_trip() {
+        let conn = Connection::open(":memory:").unwrap();
+        let mut db = SqliteDB::new(&conn);
+        db.init().unwrap();
+        let asset_id = db
+            .insert_asset(&Asset::new(None, "BASF AG", None, None, None))
+            .unwrap();
+        let ticker_id = db
+            .insert_ticker(&make_ticker("BAS.DE", asset_id, 1, TickerUsage::Both))
+            .unwrap();
+        let mut quote = Quote {
+            id: None,
+            ticker: ticker_id,
+            price: 67.35,
+            time: Utc.ymd(2020, 1, 15).and_hms(17, 30, 0),
+            volume: None,
+            quality_score: None,
+            source: None,
+            open: None,
+            high: None,
+            low: None,
+            bid: None,
+            ask: None,
+        };
+        quote.id = Some(db.insert_quote(&quote).unwrap());
+
+        let quote = Quote {
+            price: 68.5,
+            time: Utc.ymd(2020, 1, 16).and_hms(17, 30, 0),
+            volume: Some(1200.),
+            quality_score: Some(0.75),
+            source: Some("eod".to_string()),
+            open: Some(67.5),
+            high: Some(69.25),
+            low: Some(67.0),
+            bid: Some(68.45),
+            ask: Some(68.55),
+            ..quote
+        };
+        db.update_quote(&quote).unwrap();
+        let stored = db.get_all_quotes_for_ticker(ticker_id).unwrap();
+        assert_eq!(stored.len(), 1);
+        let stored = &stored[0];
+        assert_eq!(stored.id, quote.id);
+        assert_eq!(stored.ticker, quote.ticker);
+        assert_eq!(stored.price, quote.price);
+        assert_eq!(stored.time, quote.time);
+        assert_eq!(stored.volume, quote.volume);
+        assert_eq!(stored.quality_score, quote.quality_score);
+        assert_eq!(stored.source, quote.source);
+        assert_eq!(stored.open, quote.open);
+        assert_eq!(stored.high, quote.high);
+        assert_eq!(stored.low, quote.low);
+        assert_eq!(stored.bid, quote.bid);
+        assert_eq!(stored.ask, quote.ask);
+    }
+
+    #[test]
+    fn update_ticker_round_trip() {
+        let conn = Connection::open(":memory:").unwrap();
+        let mut db = SqliteDB::new(&conn);
+        db.init().unwrap();
+        let asset_id = db
+            .insert_asset(&Asset::new(None, "BASF AG", None, None, None))
+            .unwrap();
+        let other_asset_id = db
+            .insert_asset(&Asset::new(None, "BASF SE", None, None, None))
+            .unwrap();
+        let mut ticker = make_ticker("BAS.DE", asset_id, 1, TickerUsage::Both);
+        ticker.id = Some(db.insert_ticker(&ticker).unwrap());
+
+        let ticker = Ticker {
+            name: "BAS.F".to_string(),
+            asset: other_asset_id,
+            source: "yahoo".to_string(),
+            priority: 3,
+            currency: Currency::from_str("USD").unwrap(),
+            factor: 0.01,
+            source_url: Some("https://example.com/BAS".to_string()),
+            usage: TickerUsage::Charting,
+            source_chain: vec!["yahoo".to_string(), "stooq".to_string()],
+            ..ticker
+        };
+        db.update_ticker(&ticker).unwrap();
+        let stored = db.get_ticker_by_id(ticker.id.unwrap()).unwrap();
+        assert_eq!(stored.id, ticker.id);
+        assert_eq!(stored.name, ticker.name);
+        assert_eq!(stored.asset, ticker.asset);
+        assert_eq!(stored.source, ticker.source);
+        assert_eq!(stored.priority, ticker.priority);
+        assert_eq!(stored.currency, ticker.currency);
+        assert_eq!(stored.factor, ticker.factor);
+        assert_eq!(stored.source_url, ticker.source_url);
+        assert_eq!(stored.usage, ticker.usage);
+        assert_eq!(stored.last_quote_time, None);
+        assert_eq!(stored.source_chain, ticker.source_chain);
+    }
+
+    #[test]
+    fn quotes_by_ticker_usage() {
+        let conn = Connection::open(":memory:").unwrap();
+        let mut db = SqliteDB::new(&conn);
+        db.init().unwrap();
+        let asset_id = db
+            .insert_asset(&Asset::new(None, "BASF AG", None, None, None))
+            .unwrap();
+        // the official close has the better (i.e. lower) priority
+        let eod_id = db
+            .insert_ticker(&make_ticker("BAS.DE", asset_id, 1, TickerUsage::Valuation))
+            .unwrap();
+        let realtime_id = db
+            .insert_ticker(&make_ticker("BAS.RT", asset_id, 2, TickerUsage::Charting))
+            .unwrap();
+        for (ticker, price, time) in &[
+            (eod_id, 67.35, Utc.ymd(2020, 1, 15).and_hms(17, 30, 0)),
+            (realtime_id, 67.80, Utc.ymd(2020, 1, 16).and_hms(10, 15, 0)),
+        ] {
+            db.insert_quote(&Quote {
+                id: None,
+                ticker: *ticker,
+                price: *price,
+                time: *time,
+                volume: None,
+                quality_score: None,
+                source: None,
+                open: None,
+                high: None,
+                low: None,
+                bid: None,
+                ask: None,
+            })
+            .unwrap();
+        }
+
+        let time = Utc.ymd(2020, 1, 16).and_hms(12, 0, 0);
+        let (quote, _) = db.get_last_quote_before_by_id(asset_id, time).unwrap();
+        assert_eq!(quote.ticker, eod_id);
+        let (quote, _) = db
+            .get_last_quote_before_for_usage("BASF AG", time, TickerUsage::Valuation)
+            .unwrap();
+        assert_eq!(quote.ticker, eod_id);
+        let (quote, _) = db
+            .get_last_quote_before_by_id_for_usage(asset_id, time, TickerUsage::Charting)
+            .unwrap();
+        assert_eq!(quote.ticker, realtime_id);
+
+        let ticker = db
+            .get_all_ticker_for_asset_and_usage(asset_id, TickerUsage::Charting)
+            .unwrap();
+        assert_eq!(ticker.len(), 1);
+        assert_eq!(ticker[0].usage, TickerUsage::Charting);
+        let ticker = db
+            .get_all_ticker_for_asset_and_usage(asset_id, TickerUsage::Both)
+            .unwrap();
+        assert_eq!(ticker.iter().map(|t| t.priority).collect::<Vec<_>>(), vec![1, 2]);
+    }
+
+    #[test]
+    fn cached_last_quote_time() {
+        let conn = Connection::open(":memory:").unwrap();
+        let mut db = SqliteDB::new(&conn);
+        db.init().unwrap();
+        let asset_id = db
+            .insert_asset(&Asset::new(None, "BASF AG", None, None, None))
+            .unwrap();
+        let eod_id = db
+            .insert_ticker(&make_ticker("BAS.DE", asset_id, 1, TickerUsage::Valuation))
+            .unwrap();
+        let realtime_id = db
+            .insert_ticker(&make_ticker("BAS.RT", asset_id, 2, TickerUsage::Charting))
+            .unwrap();
+        let make_quote = |ticker, day| Quote {
+            id: None,
+            ticker,
+            price: 67.35,
+            time: Utc.ymd(2020, 1, day).and_hms(17, 30, 0),
+            volume: None,
+            quality_score: None,
+            source: None,
+            open: None,
+            high: None,
+            low: None,
+            bid: None,
+            ask: None,
+        };
+        let latest_id = db.insert_quote(&make_quote(eod_id, 15)).unwrap();
+        // inserting an older quote keeps the cached time
+        let older_id = db.insert_quote(&make_quote(eod_id, 10)).unwrap();
+        let last_quote_time = |db: &mut SqliteDB| {
+            QuoteHandler::get_ticker_by_id(db, eod_id)
+                .unwrap()
+                .last_quote_time
+        };
+        assert_eq!(last_quote_time(&mut db), Some(make_quote(eod_id, 15).time));
+
+        let stale = db
+            .get_stale_tickers(Utc.ymd(2020, 1, 16).and_hms(0, 0, 0))
+            .unwrap();
+        assert_eq!(
+            stale.iter().map(|t| t.id.unwrap()).collect::<Vec<_>>(),
+            vec![eod_id, realtime_id]
+        );
+        let stale = db
+            .get_stale_tickers(Utc.ymd(2020, 1, 15).and_hms(0, 0, 0))
+            .unwrap();
+        assert_eq!(stale.len(), 1);
+        assert_eq!(stale[0].id, Some(realtime_id));
+
+        // moving or deleting quotes recalculates the cached time
+        let mut quote = make_quote(realtime_id, 12);
+        quote.id = Some(latest_id);
+        quote.price = 68.0;
+        db.update_quote(&quote).unwrap();
+        assert_eq!(last_quote_time(&mut db), Some(make_quote(eod_id, 10).time));
+        assert_eq!(
+            QuoteHandler::get_ticker_by_id(&mut db, realtime_id)
+                .unwrap()
+                .last_quote_time,
+            Some(quote.time)
+        );
+        assert_eq!(db.get_all_quotes_for_ticker(realtime_id).unwrap()[0].price, 68.0);
+        db.delete_quote(older_id).unwrap();
+        assert_eq!(last_quote_time(&mut db), None);
+    }
+
+    #[test]
+    fn quote_cache_invalidation() {
+        let conn = Connection::open(":memory:").unwrap();
+        let mut db = SqliteDB::new(&conn);
+        db.init().unwrap();
+        let asset_id = db
+            .insert_asset(&Asset::new(None, "BASF AG", None, None, None))
+            .unwrap();
+        let eod_id = db
+            .insert_ticker(&make_ticker("BAS.DE", asset_id, 1, TickerUsage::Both))
+            .unwrap();
+        let other_id = db
+            .insert_ticker(&make_ticker("BAS.F", asset_id, 2, TickerUsage::Both))
+            .unwrap();
+        let make_quote = |ticker, day, price| Quote {
+            id: None,
+            ticker,
+            price,
+            time: Utc.ymd(2020, 1, day).and_hms(17, 30, 0),
+            volume: None,
+            quality_score: None,
+            source: None,
+            open: None,
+            high: None,
+            low: None,
+            bid: None,
+            ask: None,
+        };
+        db.insert_quote(&make_quote(eod_id, 10, 60.0)).unwrap();
+        let mut db = CachedQuoteHandler::new(db, QuoteCacheConfig::default());
+        let time = Utc.ymd(2020, 1, 20).and_hms(12, 0, 0);
+        let last_price = |db: &mut CachedQuoteHandler<SqliteDB>| {
+            db.get_last_quote_before_by_id(asset_id, time).unwrap().0.price
+        };
+
+        assert_eq!(last_price(&mut db), 60.0);
+        // lookups within the same minute share the cache entry
+        let (quote, _) = db
+            .get_last_quote_before_by_id(asset_id, time + chrono::Duration::seconds(30))
+            .unwrap();
+        assert_eq!(quote.price, 60.0);
+        assert_eq!(db.stats(), QuoteCacheStats { hits: 1, misses: 1 });
+
+        let new_id = db.insert_quote(&make_quote(other_id, 15, 61.0)).unwrap();
+        assert_eq!(last_price(&mut db), 61.0);
+        let mut quote = make_quote(other_id, 15, 62.0);
+        quote.id = Some(new_id);
+        db.update_quote(&quote).unwrap();
+        assert_eq!(last_price(&mut db), 62.0);
+        db.delete_quote(new_id).unwrap();
+        assert_eq!(last_price(&mut db), 60.0);
+        assert_eq!(db.stats().misses, 4);
+
+        // a lower priority makes the other ticker's quote the last one
+        db.insert_quote(&make_quote(other_id, 10, 59.0)).unwrap();
+        assert_eq!(last_price(&mut db), 60.0);
+        let mut ticker = db.get_ticker_by_id(other_id).unwrap();
+        ticker.priority = 0;
+        db.update_ticker(&ticker).unwrap();
+        assert_eq!(last_price(&mut db), 59.0);
+        assert_eq!(db.delete_quotes_before(other_id, time).unwrap(), 1);
+        assert_eq!(last_price(&mut db), 60.0);
+
+        // latest quotes by ticker, including ticker without quotes
+        let latest = |db: &mut CachedQuoteHandler<SqliteDB>| {
+            db.get_latest_quotes_for_tickers(&[other_id, eod_id])
+                .unwrap()
+                .iter()
+                .map(|(id, quote)| (*id, quote.price))
+                .collect::<Vec<_>>()
+        };
+        let before = db.stats();
+        assert_eq!(latest(&mut db), vec![(eod_id, 60.0)]);
+        assert_eq!(latest(&mut db), vec![(eod_id, 60.0)]);
+        assert_eq!(db.stats().hits - before.hits, 2);
+        db.insert_quotes(&[make_quote(other_id, 16, 63.0)]).unwrap();
+        assert_eq!(latest(&mut db), vec![(other_id, 63.0), (eod_id, 60.0)]);
+        assert_eq!(db.delete_quotes_for_ticker(other_id).unwrap(), 1);
+        assert_eq!(latest(&mut db), vec![(eod_id, 60.0)]);
+
+        // changes bypassing the cache are not seen until the cache is cleared
+        assert_eq!(last_price(&mut db), 60.0);
+        let mut direct = SqliteDB::new(&conn);
+        direct.insert_quote(&make_quote(eod_id, 18, 64.0)).unwrap();
+        assert_eq!(last_price(&mut db), 60.0);
+        db.clear();
+        assert!(db.is_empty());
+        assert_eq!(last_price(&mut db), 64.0);
+    }
+
+    #[test]
+    fn store_quotes_in_transaction() {
+        let conn = Connection::open(":memory:").unwrap();
+        let mut db = SqliteDB::new(&conn);
+        db.init().unwrap();
+        let asset_id = db
+            .insert_asset(&Asset::new(None, "BASF AG", None, None, None))
+            .unwrap();
+        let ticker_id = db
+            .insert_ticker(&make_ticker("BAS.DE", asset_id, 1, TickerUsage::Both))
+            .unwrap();
+        let make_quote = |day, price| Quote {
+            id: None,
+            ticker: ticker_id,
+            price,
+            time: Utc.ymd(2020, 1, day).and_hms(17, 30, 0),
+            volume: None,
+            quality_score: None,
+            source: None,
+            open: None,
+            high: None,
+            low: None,
+            bid: None,
+            ask: None,
+        };
+        let first_id = db.insert_quote(&make_quote(2, 67.0)).unwrap();
+        let mut update = make_quote(2, 68.0);
+        update.id = Some(first_id);
+        let ids = db
+            .store_quotes(&[update.clone(), make_quote(3, 69.0)])
+            .unwrap();
+        assert_eq!(ids[0], first_id);
+        let quotes = QuoteHandler::get_all_quotes_for_ticker(&mut db, ticker_id).unwrap();
+        assert_eq!(
+            quotes.iter().map(|q| q.price).collect::<Vec<_>>(),
+            vec![68.0, 69.0]
+        );
+
+        // the invalid quote rolls back all others
+        update.price = 70.0;
+        assert!(db
+            .store_quotes(&[update, make_quote(4, 71.0), make_quote(5, -1.0)])
+            .is_err());
+        let quotes = QuoteHandler::get_all_quotes_for_ticker(&mut db, ticker_id).unwrap();
+        assert_eq!(
+            quotes.iter().map(|q| q.price).collect::<Vec<_>>(),
+            vec![68.0, 69.0]
+        );
+    }
+
+    #[test]
+    fn prefer_quotes_of_higher_quality() {
+        let conn = Connection::open(":memory:").unwrap();
+        let mut db = SqliteDB::new(&conn);
+        db.init().unwrap();
+        let asset_id = db
+            .insert_asset(&Asset::new(None, "BASF AG", None, None, None))
+            .unwrap();
+        let eod_id = db
+            .insert_ticker(&make_ticker("BAS.DE", asset_id, 1, TickerUsage::Valuation))
+            .unwrap();
+        let backup_id = db
+            .insert_ticker(&make_ticker("BAS.F", asset_id, 2, TickerUsage::Valuation))
+            .unwrap();
+        let time = Utc.ymd(2020, 1, 15).and_hms(17, 30, 0);
+        let eod_quote_id = db
+            .insert_quote(&Quote {
+                id: None,
+                ticker: eod_id,
+                price: 67.35,
+                time,
+                volume: None,
+                quality_score: Some(0.5),
+                source: None,
+                open: None,
+                high: None,
+                low: None,
+                bid: None,
+                ask: None,
+            })
+            .unwrap();
+        let mut backup_quote = Quote {
+            id: None,
+            ticker: backup_id,
+            price: 67.40,
+            time,
+            volume: None,
+            quality_score: Some(0.55),
+            source: None,
+            open: None,
+            high: None,
+            low: None,
+            bid: None,
+            ask: None,
+        };
+        backup_quote.id = Some(db.insert_quote(&backup_quote).unwrap());
+
+        // small quality advantages do not override the ticker priority
+        let (quote, _) = db.get_last_quote_before_by_id(asset_id, time).unwrap();
+        assert_eq!(quote.id, Some(eod_quote_id));
+        assert_eq!(quote.quality_score, Some(0.5));
+
+        backup_quote.quality_score = Some(0.9);
+        db.update_quote(&backup_quote).unwrap();
+        let (quote, _) = db.get_last_quote_before("BASF AG", time).unwrap();
+        assert_eq!(quote.id, backup_quote.id);
+        let (quote, _) = QuoteReader::get_last_quote_before_by_id(&db, asset_id, time).unwrap();
+        assert_eq!(quote.id, backup_quote.id);
+
+        let quotes = db.get_quotes_above_quality(backup_id, 0.9).unwrap();
+        assert_eq!(quotes.len(), 1);
+        assert!(db.get_quotes_above_quality(eod_id, 0.6).unwrap().is_empty());
+    }
+
+    #[test]
+    fn migrate_quality_score() {
+        let conn = Connection::open(":memory:").unwrap();
+        conn.execute(
+            "CREATE TABLE quotes (
+                id INTEGER PRIMARY KEY,
+                ticker_id INTEGER NOT NULL,
+                price REAL NOT NULL,
+                time TEXT NOT NULL,
+                volume REAL
+            );",
+            NO_PARAMS,
+        )
+        .unwrap();
+        conn.execute(
+            "INSERT INTO quotes (ticker_id, price, time) VALUES (1, 67.35, '2020-01-15T17:30:00+00:00')",
+            NO_PARAMS,
+        )
+        .unwrap();
+        let db = SqliteDB::new(&conn);
+        db.init().unwrap();
+        let quotes = db.get_all_quotes_for_ticker(1).unwrap();
+        assert_eq!(quotes.len(), 1);
+        assert_eq!(quotes[0].quality_score, None);
+    }
+
+    #[test]
+    fn migrate_ticker_usage() {
+        let conn = Connection::open(":memory:").unwrap();
+        conn.execute(
+            "CREATE TABLE ticker (
+                id INTEGER PRIMARY KEY,
+                name TEXT NOT NULL,
+                asset_id INTEGER NOT NULL,
+                source TEXT NOT NULL,
+                priority INTEGER NOT NULL,
+                currency TEXT NOT NULL,
+                factor REAL NOT NULL DEFAULT 1.0
+            );",
+            NO_PARAMS,
+        )
+        .unwrap();
+        conn.execute(
+            "INSERT INTO ticker (name, asset_id, source, priority, currency) VALUES ('BAS.DE', 1, 'manual', 1, 'EUR')",
+            NO_PARAMS,
+        )
+        .unwrap();
+        let db = SqliteDB::new(&conn);
+        db.init().unwrap();
+        let ticker = QuoteReader::get_all_ticker(&db).unwrap();
+        assert_eq!(ticker.len(), 1);
+        assert_eq!(ticker[0].usage, TickerUsage::Both);
+    }
+
+    #[test]
+    fn migrate_asset_reference_currency() {
+        let conn = Connection::open(":memory:").unwrap();
+        conn.execute(
+            "CREATE TABLE assets (
+                id INTEGER PRIMARY KEY,
+                name TEXT NOT NULL UNIQUE,
+                wkn TEXT UNIQUE,
+                isin TEXT UNIQUE,
+                note TEXT
+            )",
+            NO_PARAMS,
+        )
+        .unwrap();
+        conn.execute("INSERT INTO assets (name) VALUES ('BASF AG')", NO_PARAMS)
+            .unwrap();
+        let mut db = SqliteDB::new(&conn);
+        db.init().unwrap();
+        let mut asset = db.get_asset_by_id(1).unwrap();
+        assert_eq!(asset.reference_currency, None);
+        assert_eq!(asset.distribution_policy, Some(DistributionPolicy::Unknown));
+        asset.reference_currency = Some(Currency::from_str("EUR").unwrap());
+        db.update_asset(&asset).unwrap();
+        assert_eq!(
+            db.get_all_assets().unwrap()[0].reference_currency,
+            asset.reference_currency
+        );
+    }
+
+    /// Converter with fixed exchange rates from USD
+    struct UsdConverter;
+
+    impl CurrencyConverter for UsdConverter {
+        fn fx_rate(
+            &mut self,
+            foreign: Currency,
+            domestic: Currency,
+            _time: DateTime<Utc>,
+        ) -> Result<f64, CurrencyError> {
+            match (foreign.to_string().as_str(), domestic.to_string().as_str()) {
+                ("USD", "EUR") => Ok(0.8),
+                _ => Err(CurrencyError::ConversionFailed),
+            }
+        }
+    }
+
+    #[test]
+    fn best_quote_in_reference_currency() {
+        let conn = Connection::open(":memory:").unwrap();
+        let mut db = SqliteDB::new(&conn);
+        db.init().unwrap();
+        let eur = Currency::from_str("EUR").unwrap();
+        let usd = Currency::from_str("USD").unwrap();
+        let asset_id = db
+            .insert_asset(&Asset::new(None, "SAP SE", None, None, None))
+            .unwrap();
+        let xetra_id = db
+            .insert_ticker(&make_ticker("SAP.DE", asset_id, 2, TickerUsage::Both))
+            .unwrap();
+        let mut nyse = make_ticker("SAP", asset_id, 1, TickerUsage::Both);
+        nyse.currency = usd;
+        let nyse_id = db.insert_ticker(&nyse).unwrap();
+        let single_id = db
+            .insert_asset(&Asset::new(None, "BASF AG", None, None, None))
+            .unwrap();
+        db.insert_ticker(&make_ticker("BAS.DE", single_id, 1, TickerUsage::Both))
+            .unwrap();
+        for (ticker, price, day) in &[(nyse_id, 125.0, 4), (xetra_id, 101.0, 5)] {
+            db.insert_quote(&Quote {
+                id: None,
+                ticker: *ticker,
+                price: *price,
+                time: Utc.ymd(2021, 1, *day).and_hms(21, 0, 0),
+                volume: None,
+                quality_score: None,
+                source: None,
+                open: None,
+                high: None,
+                low: None,
+                bid: None,
+                ask: None,
+            })
+            .unwrap();
+        }
+
+        // without reference currency, the currency depends on the winning ticker
+        let time = Utc.ymd(2021, 1, 4).and_hms(23, 0, 0);
+        let (quote, currency) = db
+            .get_best_quote_before(asset_id, time, &mut UsdConverter)
+            .unwrap();
+        assert_eq!((quote.price, currency), (125.0, usd));
+        let mixed = db.get_assets_with_mixed_currencies().unwrap();
+        assert_eq!(mixed.len(), 1);
+        assert_eq!(mixed[0].id, Some(asset_id));
+
+        let asset = db.get_asset_by_id(asset_id).unwrap();
+        db.update_asset(&asset.with_reference_currency(eur)).unwrap();
+        assert!(db.get_assets_with_mixed_currencies().unwrap().is_empty());
+        let (quote, currency) = db
+            .get_best_quote_before(asset_id, time, &mut UsdConverter)
+            .unwrap();
+        assert_eq!((quote.price, currency, quote.ticker), (100.0, eur, nyse_id));
+        let time = Utc.ymd(2021, 1, 5).and_hms(23, 0, 0);
+        let (_, currency) = db
+            .get_best_quote_before(asset_id, time, &mut UsdConverter)
+            .unwrap();
+        assert_eq!(currency, eur);
+
+        // conversion into a currency without exchange rate fails
+        let asset = db.get_asset_by_id(asset_id).unwrap();
+        let chf = Currency::from_str("CHF").unwrap();
+        db.update_asset(&asset.with_reference_currency(chf)).unwrap();
+        assert!(matches!(
+            db.get_best_quote_before(asset_id, time, &mut UsdConverter),
+            Err(DataError::CurrencyMismatch(_))
+        ));
+    }
+
+    /// Provider returning a quote at 18:00 of each day of the requested period
+    struct DailyProvider;
+
+    impl QuoteProvider for DailyProvider {
+        fn fetch_quotes(
+            &self,
+            ticker: &Ticker,
+            start: NaiveDate,
+            end: NaiveDate,
+        ) -> Result<Vec<Quote>, DataError> {
+            let mut quotes = Vec::new();
+            let mut date = start;
+            while date <= end {
+                quotes.push(Quote {
+                    id: None,
+                    ticker: ticker.id.unwrap(),
+                    price: 10.0,
+                    time: Utc.from_utc_datetime(&date.and_hms(18, 0, 0)),
+                    volume: None,
+                    quality_score: None,
+                    source: None,
+                    open: None,
+                    high: None,
+                    low: None,
+                    bid: None,
+                    ask: None,
+                });
+                date = date.succ();
+            }
+            Ok(quotes)
+        }
+
+        fn supports_source(&self, source: &str) -> bool {
+            source == "manual"
+        }
+    }
+
+    #[test]
+    fn refresh_ticker_from_provider() {
+        let conn = Connection::open(":memory:").unwrap();
+        let mut db = SqliteDB::new(&conn);
+        db.init().unwrap();
+        let asset_id = db
+            .insert_asset(&Asset::new(None, "BASF AG", None, None, None))
+            .unwrap();
+        let mut ticker = make_ticker("BAS.DE", asset_id, 1, TickerUsage::Both);
+        ticker.factor = 2.0;
+        ticker.id = Some(db.insert_ticker(&ticker).unwrap());
+        let mut registry = QuoteProviderRegistry::new();
+        registry.register(Box::new(DailyProvider));
+        let day = |d| NaiveDate::from_ymd(2020, 1, d);
+
+        // without stored quotes, only the last day is fetched
+        let count = refresh_ticker(&ticker, &mut db, &registry, day(10)).unwrap();
+        assert_eq!(count, 1);
+        // afterwards, all days following the latest quote
+        let count = refresh_ticker(&ticker, &mut db, &registry, day(14)).unwrap();
+        assert_eq!(count, 4);
+        let count = refresh_ticker(&ticker, &mut db, &registry, day(14)).unwrap();
+        assert_eq!(count, 0);
+        let quotes = QuoteReader::get_all_quotes_for_ticker(&db, ticker.id.unwrap()).unwrap();
+        assert_eq!(quotes.len(), 5);
+        assert!(quotes.iter().all(|quote| quote.price == 20.0));
+
+        ticker.source = "yahoo".to_string();
+        let err = refresh_ticker(&ticker, &mut db, &registry, day(15));
+        assert!(matches!(err, Err(DataError::NotFound(_))));
+
+        // intraday quotes of a delayed source are stamped with the effective time
+        let mut delayed = make_ticker("BAS.F", asset_id, 2, TickerUsage::Both);
+        delayed.id = Some(db.insert_ticker(&delayed).unwrap());
+        registry.set_delay_minutes("manual", 15);
+        refresh_ticker(&delayed, &mut db, &registry, day(10)).unwrap();
+        refresh_ticker(&delayed, &mut db, &registry, day(12)).unwrap();
+        let times: Vec<_> = QuoteReader::get_all_quotes_for_ticker(&db, delayed.id.unwrap())
+            .unwrap()
+            .into_iter()
+            .map(|quote| quote.time)
+            .collect();
+        // quotes of previous days are end of day quotes, which are not delayed
+        assert_eq!(
+            times,
+            vec![
+                Utc.ymd(2020, 1, 10).and_hms(17, 45, 0),
+                Utc.ymd(2020, 1, 10).and_hms(18, 0, 0),
+                Utc.ymd(2020, 1, 11).and_hms(18, 0, 0),
+                Utc.ymd(2020, 1, 12).and_hms(17, 45, 0),
+            ]
+        );
     }
 }
@@ -0,0 +1,205 @@
+//! Implementation of sqlite3 report handler
+use rusqlite::types::Type;
+use rusqlite::{params, OptionalExtension, Row, NO_PARAMS};
+
+use finql_data::rebalancing::RebalancingDecision;
+use finql_data::report_definition::{JobRun, ReportDefinition, ReportHandler};
+use finql_data::DataError;
+
+use super::quote_handler::to_time;
+use super::SqliteDB;
+
+/// Construct a report definition from a row of the `report_definitions` table
+fn report_definition_from_row(row: &Row) -> rusqlite::Result<ReportDefinition> {
+    let payload: String = row.get(2)?;
+    let payload = serde_json::from_str(&payload)
+        .map_err(|e| rusqlite::Error::FromSqlConversionFailure(2, Type::Text, Box::new(e)))?;
+    Ok(ReportDefinition {
+        name: row.get(0)?,
+        version: row.get(1)?,
+        payload,
+    })
+}
+
+/// Job run as stored in the database
+struct RawJobRun {
+    id: i64,
+    job: String,
+    started: String,
+    finished: String,
+    success: bool,
+    message: Option<String>,
+}
+
+impl RawJobRun {
+    fn from_row(row: &Row) -> rusqlite::Result<RawJobRun> {
+        Ok(RawJobRun {
+            id: row.get(0)?,
+            job: row.get(1)?,
+            started: row.get(2)?,
+            finished: row.get(3)?,
+            success: row.get(4)?,
+            message: row.get(5)?,
+        })
+    }
+
+    fn into_job_run(self) -> Result<JobRun, DataError> {
+        Ok(JobRun {
+            id: Some(self.id as usize),
+            job: self.job,
+            started: to_time(&self.started)?,
+            finished: to_time(&self.finished)?,
+            success: self.success,
+            message: self.message,
+        })
+    }
+}
+
+impl ReportHandler for SqliteDB<'_> {
+    fn set_report_definition(&mut self, definition: &ReportDefinition) -> Result<(), DataError> {
+        self.conn
+            .execute(
+                "INSERT OR REPLACE INTO report_definitions (name, version, payload)
+                VALUES (?1, ?2, ?3)",
+                params![
+                    definition.name,
+                    definition.version,
+                    definition.payload.to_string()
+                ],
+            )
+            .map_err(|e| DataError::InsertFailed(e.to_string()))?;
+        Ok(())
+    }
+
+    fn get_report_definition(
+        &mut self,
+        name: &str,
+    ) -> Result<Option<ReportDefinition>, DataError> {
+        self.conn
+            .query_row(
+                "SELECT name, version, payload FROM report_definitions WHERE name=?1",
+                params![name],
+                report_definition_from_row,
+            )
+            .optional()
+            .map_err(|e| DataError::NotFound(e.to_string()))
+    }
+
+    fn get_all_report_definitions(&mut self) -> Result<Vec<ReportDefinition>, DataError> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT name, version, payload FROM report_definitions ORDER BY name")
+            .map_err(|e| DataError::NotFound(e.to_string()))?;
+        let definitions = stmt
+            .query_map(NO_PARAMS, report_definition_from_row)
+            .map_err(|e| DataError::NotFound(e.to_string()))?;
+        definitions
+            .collect::<rusqlite::Result<Vec<ReportDefinition>>>()
+            .map_err(|e| DataError::NotFound(e.to_string()))
+    }
+
+    fn delete_report_definition(&mut self, name: &str) -> Result<(), DataError> {
+        self.conn
+            .execute(
+                "DELETE FROM report_definitions WHERE name=?1;",
+                params![name],
+            )
+            .map_err(|e| DataError::DeleteFailed(e.to_string()))?;
+        Ok(())
+    }
+
+    fn insert_job_run(&mut self, run: &JobRun) -> Result<usize, DataError> {
+        self.conn
+            .execute(
+                "INSERT INTO job_runs (job, started, finished, success, message)
+                VALUES (?1, ?2, ?3, ?4, ?5)",
+                params![
+                    run.job,
+                    run.started.to_rfc3339(),
+                    run.finished.to_rfc3339(),
+                    run.success,
+                    run.message
+                ],
+            )
+            .map_err(|e| DataError::InsertFailed(e.to_string()))?;
+        Ok(self.conn.last_insert_rowid() as usize)
+    }
+
+    fn get_job_runs(&mut self, job: &str) -> Result<Vec<JobRun>, DataError> {
+        let mut stmt = self
+            .conn
+            .prepare(
+                "SELECT id, job, started, finished, success, message FROM job_runs
+                WHERE job=?1 ORDER BY started DESC, id DESC",
+            )
+            .map_err(|e| DataError::NotFound(e.to_string()))?;
+        let runs = stmt
+            .query_map(params![job], RawJobRun::from_row)
+            .map_err(|e| DataError::NotFound(e.to_string()))?;
+        let mut job_runs = Vec::new();
+        for run in runs {
+            let run = run.map_err(|e| DataError::NotFound(e.to_string()))?;
+            job_runs.push(run.into_job_run()?);
+        }
+        Ok(job_runs)
+    }
+
+    fn insert_rebalancing_decision(
+        &mut self,
+        decision: &RebalancingDecision,
+    ) -> Result<usize, DataError> {
+        let proposal = serde_json::to_string(&decision.proposal)
+            .map_err(|e| DataError::InvalidData(e.to_string()))?;
+        self.conn
+            .execute(
+                "INSERT INTO rebalancing_decisions (proposal) VALUES (?1)",
+                params![proposal],
+            )
+            .map_err(|e| DataError::InsertFailed(e.to_string()))?;
+        let id = self.conn.last_insert_rowid();
+        for transaction_id in &decision.transaction_ids {
+            self.conn
+                .execute(
+                    "INSERT INTO rebalancing_transactions (decision_id, transaction_id)
+                    VALUES (?1, ?2)",
+                    params![id, *transaction_id as i64],
+                )
+                .map_err(|e| DataError::InsertFailed(e.to_string()))?;
+        }
+        Ok(id as usize)
+    }
+
+    fn get_rebalancing_decision(&mut self, id: usize) -> Result<RebalancingDecision, DataError> {
+        let proposal: String = self
+            .conn
+            .query_row(
+                "SELECT proposal FROM rebalancing_decisions WHERE id=?1",
+                params![id as i64],
+                |row| row.get(0),
+            )
+            .map_err(|e| DataError::NotFound(e.to_string()))?;
+        let proposal =
+            serde_json::from_str(&proposal).map_err(|e| DataError::InvalidData(e.to_string()))?;
+        let mut stmt = self
+            .conn
+            .prepare(
+                "SELECT transaction_id FROM rebalancing_transactions
+                WHERE decision_id=?1 ORDER BY transaction_id",
+            )
+            .map_err(|e| DataError::NotFound(e.to_string()))?;
+        let ids = stmt
+            .query_map(params![id as i64], |row| {
+                let id: i64 = row.get(0)?;
+                Ok(id as usize)
+            })
+            .map_err(|e| DataError::NotFound(e.to_string()))?;
+        let transaction_ids = ids
+            .collect::<rusqlite::Result<Vec<usize>>>()
+            .map_err(|e| DataError::NotFound(e.to_string()))?;
+        Ok(RebalancingDecision {
+            id: Some(id),
+            proposal,
+            transaction_ids,
+        })
+    }
+}
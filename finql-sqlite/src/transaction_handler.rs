@@ -1,33 +1,118 @@
 ///! Implementation of sqlite3 data handler
 
-use rusqlite::{params, NO_PARAMS};
+use std::str::FromStr;
 
-use finql_data::{DataError, TransactionHandler};
-use finql_data::transaction::Transaction;
+use chrono::NaiveDate;
+use rusqlite::{params, types::{ToSql, Type}, Row, NO_PARAMS};
 
-use super::raw_transaction::RawTransaction;
+use finql_data::{Currency, DataError, FeeSchedule, TransactionHandler};
+use finql_data::transaction::{LotSelection, RawTransaction, Transaction};
+
+use super::quote_handler::to_time;
 use super::SqliteDB;
 
+const TRANSACTION_COLUMNS: &str = "id, trans_type, asset_id, cash_amount, cash_currency, \
+    cash_date, related_trans, position, note, execution_meta, recorded_at";
+
+/// Format of the cash date, which is stored as text
+const DATE_FORMAT: &str = "%Y-%m-%d";
+
+/// Construct a raw transaction from a row containing the columns given by `TRANSACTION_COLUMNS`
+fn raw_transaction_from_row(row: &Row) -> rusqlite::Result<RawTransaction> {
+    let cash_date: String = row.get(5)?;
+    let cash_date = NaiveDate::parse_from_str(&cash_date, DATE_FORMAT)
+        .map_err(|e| rusqlite::Error::FromSqlConversionFailure(5, Type::Text, Box::new(e)))?;
+    let execution_meta: Option<String> = row.get(9)?;
+    let execution_meta = execution_meta
+        .map(|meta| serde_json::from_str(&meta))
+        .transpose()
+        .map_err(|e| rusqlite::Error::FromSqlConversionFailure(9, Type::Text, Box::new(e)))?;
+    let recorded_at: Option<String> = row.get(10)?;
+    let recorded_at = recorded_at
+        .map(|time| to_time(&time))
+        .transpose()
+        .map_err(|e| rusqlite::Error::FromSqlConversionFailure(10, Type::Text, Box::new(e)))?;
+    Ok(RawTransaction {
+        id: row.get(0)?,
+        trans_type: row.get(1)?,
+        asset: row.get(2)?,
+        cash_amount: row.get(3)?,
+        cash_currency: row.get(4)?,
+        cash_date,
+        related_trans: row.get(6)?,
+        position: row.get(7)?,
+        note: row.get(8)?,
+        execution_meta,
+        recorded_at,
+    })
+}
+
+/// Escape the wildcard characters of `LIKE` patterns, with `\` as escape character
+fn escape_like(pattern: &str) -> String {
+    pattern
+        .replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_")
+}
+
+impl SqliteDB<'_> {
+    /// Get all transactions matching the given SQL condition
+    fn query_transactions(
+        &self,
+        condition: &str,
+        params: &[&dyn ToSql],
+    ) -> Result<Vec<Transaction>, DataError> {
+        let mut stmt = self
+            .conn
+            .prepare(&format!(
+                "SELECT {} FROM transactions WHERE {};",
+                TRANSACTION_COLUMNS, condition
+            ))
+            .map_err(|e| DataError::NotFound(e.to_string()))?;
+        let transaction_map = stmt
+            .query_map(params, raw_transaction_from_row)
+            .map_err(|e| DataError::NotFound(e.to_string()))?;
+        let mut transactions = Vec::new();
+        for transaction in transaction_map {
+            transactions.push(
+                transaction
+                    .map_err(|e| DataError::NotFound(e.to_string()))?
+                    .to_transaction()?,
+            );
+        }
+        Ok(transactions)
+    }
+}
+
 /// Handler for globally available data
 impl TransactionHandler for SqliteDB<'_> {
     // insert, get, update and delete for transactions
     fn insert_transaction(&mut self, transaction: &Transaction) -> Result<usize, DataError> {
         let transaction = RawTransaction::from_transaction(transaction);
+        transaction.validate()?;
         self.conn
             .execute(
                 "INSERT INTO transactions (trans_type, asset_id, cash_amount, 
                 cash_currency, cash_date, related_trans, position,
-                note) 
-                VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8);",
+                note, execution_meta, recorded_at)
+                VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10);",
                 params![
                     transaction.trans_type,
                     transaction.asset,
                     transaction.cash_amount,
                     transaction.cash_currency,
-                    transaction.cash_date,
+                    transaction.cash_date.format(DATE_FORMAT).to_string(),
                     transaction.related_trans,
                     transaction.position,
-                    transaction.note
+                    transaction.note,
+                    transaction
+                        .execution_meta
+                        .as_ref()
+                        .map(|meta| meta.to_string()),
+                    transaction
+                        .recorded_at
+                        .unwrap_or_else(|| self.clock.now_utc())
+                        .to_rfc3339()
                 ],
             )
             .map_err(|e| DataError::InsertFailed(e.to_string()))?;
@@ -45,59 +130,19 @@ impl TransactionHandler for SqliteDB<'_> {
         let transaction = self
             .conn
             .query_row(
-                "SELECT trans_type, asset_id, 
-        cash_amount, cash_currency, cash_date, related_trans, position, note 
-        FROM transactions
-        WHERE id=?;",
+                &format!(
+                    "SELECT {} FROM transactions WHERE id=?;",
+                    TRANSACTION_COLUMNS
+                ),
                 params![id as i64],
-                |row| {
-                    Ok(RawTransaction {
-                        id: Some(id as i64),
-                        trans_type: row.get(0)?,
-                        asset: row.get(1)?,
-                        cash_amount: row.get(2)?,
-                        cash_currency: row.get(3)?,
-                        cash_date: row.get(4)?,
-                        related_trans: row.get(5)?,
-                        position: row.get(6)?,
-                        note: row.get(7)?,
-                    })
-                },
+                raw_transaction_from_row,
             )
             .map_err(|e| DataError::NotFound(e.to_string()))?;
-        let transaction = transaction.to_transaction()?;
-        Ok(transaction)
+        transaction.to_transaction()
     }
 
     fn get_all_transactions(&mut self) -> Result<Vec<Transaction>, DataError> {
-        let mut stmt = self
-            .conn
-            .prepare(
-                "SELECT id, trans_type, asset_id, 
-        cash_amount, cash_currency, cash_date, related_trans, position, note 
-        FROM transactions;",
-            )
-            .map_err(|e| DataError::NotFound(e.to_string()))?;
-        let transaction_map = stmt
-            .query_map(NO_PARAMS, |row| {
-                Ok(RawTransaction {
-                    id: row.get(0)?,
-                    trans_type: row.get(1)?,
-                    asset: row.get(2)?,
-                    cash_amount: row.get(3)?,
-                    cash_currency: row.get(4)?,
-                    cash_date: row.get(5)?,
-                    related_trans: row.get(6)?,
-                    position: row.get(7)?,
-                    note: row.get(8)?,
-                })
-            })
-            .map_err(|e| DataError::NotFound(e.to_string()))?;
-        let mut transactions = Vec::new();
-        for transaction in transaction_map {
-            transactions.push(transaction.unwrap().to_transaction()?);
-        }
-        Ok(transactions)
+        self.query_transactions("1=1", &[])
     }
 
     fn update_transaction(&mut self, transaction: &Transaction) -> Result<(), DataError> {
@@ -108,6 +153,8 @@ impl TransactionHandler for SqliteDB<'_> {
         }
         let id = transaction.id.unwrap() as i64;
         let transaction = RawTransaction::from_transaction(transaction);
+        transaction.validate()?;
+        // the time the transaction has been recorded is kept
         self.conn
             .execute(
                 "UPDATE transactions SET 
@@ -118,7 +165,8 @@ impl TransactionHandler for SqliteDB<'_> {
                 cash_date=?6,
                 related_trans=?7,
                 position=?8,
-                note=?9
+                note=?9,
+                execution_meta=?10
             WHERE id=?1;",
                 params![
                     id,
@@ -126,19 +174,341 @@ impl TransactionHandler for SqliteDB<'_> {
                     transaction.asset,
                     transaction.cash_amount,
                     transaction.cash_currency,
-                    transaction.cash_date,
+                    transaction.cash_date.format(DATE_FORMAT).to_string(),
                     transaction.related_trans,
                     transaction.position,
-                    transaction.note
+                    transaction.note,
+                    transaction
+                        .execution_meta
+                        .as_ref()
+                        .map(|meta| meta.to_string())
                 ],
             )
             .map_err(|e| DataError::InsertFailed(e.to_string()))?;
         Ok(())
     }
+    fn find_transaction_by_order_id(
+        &mut self,
+        order_id: &str,
+    ) -> Result<Vec<Transaction>, DataError> {
+        // sqlite has no JSON support by default, therefore the pre-selection by `LIKE` is
+        // refined by checking the parsed meta data
+        let pattern = format!("%{}%", escape_like(order_id));
+        let mut transactions = self.query_transactions(
+            "execution_meta LIKE ?1 ESCAPE '\\' ORDER BY id",
+            &[&pattern],
+        )?;
+        transactions.retain(|t| t.order_id().as_deref() == Some(order_id));
+        Ok(transactions)
+    }
+
     fn delete_transaction(&mut self, id: usize) -> Result<(), DataError> {
         self.conn
             .execute("DELETE FROM transactions WHERE id=?1;", params![id as i64])
             .map_err(|e| DataError::InsertFailed(e.to_string()))?;
         Ok(())
     }
+
+    fn insert_lot_selection(
+        &mut self,
+        sell_transaction_id: usize,
+        lots: &[LotSelection],
+    ) -> Result<(), DataError> {
+        for lot in lots {
+            self.conn
+                .execute(
+                    "INSERT INTO lot_selections (sell_trans_id, buy_trans_id, quantity)
+                    VALUES (?1, ?2, ?3);",
+                    params![
+                        sell_transaction_id as i64,
+                        lot.buy_transaction_id as i64,
+                        lot.quantity
+                    ],
+                )
+                .map_err(|e| DataError::InsertFailed(e.to_string()))?;
+        }
+        Ok(())
+    }
+
+    fn insert_transaction_with_lot_selection(
+        &mut self,
+        transaction: &Transaction,
+        lots: &[LotSelection],
+    ) -> Result<usize, DataError> {
+        self.conn
+            .execute_batch("BEGIN;")
+            .map_err(|e| DataError::DataAccessFailure(e.to_string()))?;
+        let result = self.insert_transaction(transaction).and_then(|id| {
+            self.insert_lot_selection(id, lots)?;
+            Ok(id)
+        });
+        match result {
+            Ok(id) => {
+                self.conn
+                    .execute_batch("COMMIT;")
+                    .map_err(|e| DataError::DataAccessFailure(e.to_string()))?;
+                Ok(id)
+            }
+            Err(err) => {
+                self.conn
+                    .execute_batch("ROLLBACK;")
+                    .map_err(|e| DataError::DataAccessFailure(e.to_string()))?;
+                Err(err)
+            }
+        }
+    }
+
+    fn get_lot_selection(
+        &mut self,
+        sell_transaction_id: usize,
+    ) -> Result<Vec<LotSelection>, DataError> {
+        let mut stmt = self
+            .conn
+            .prepare(
+                "SELECT buy_trans_id, quantity FROM lot_selections
+                WHERE sell_trans_id=? ORDER BY id;",
+            )
+            .map_err(|e| DataError::NotFound(e.to_string()))?;
+        let lot_map = stmt
+            .query_map(params![sell_transaction_id as i64], |row| {
+                let buy_transaction_id: i64 = row.get(0)?;
+                Ok(LotSelection {
+                    buy_transaction_id: buy_transaction_id as usize,
+                    quantity: row.get(1)?,
+                })
+            })
+            .map_err(|e| DataError::NotFound(e.to_string()))?;
+        let mut lots = Vec::new();
+        for lot in lot_map {
+            lots.push(lot.map_err(|e| DataError::NotFound(e.to_string()))?);
+        }
+        Ok(lots)
+    }
+
+    fn set_fee_schedule(&mut self, schedule: &FeeSchedule) -> Result<(), DataError> {
+        schedule.validate()?;
+        self.conn
+            .execute(
+                "INSERT OR REPLACE INTO fee_schedules (account_id, currency, rate_pa, min_fee,
+                max_fee, fixed_fee, billing_period, start_date)
+                VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8);",
+                params![
+                    schedule.account_id as i64,
+                    schedule.currency.to_string(),
+                    schedule.rate_pa,
+                    schedule.min_fee,
+                    schedule.max_fee,
+                    schedule.fixed_fee,
+                    schedule.billing_period,
+                    schedule.start.format(DATE_FORMAT).to_string()
+                ],
+            )
+            .map_err(|e| DataError::InsertFailed(e.to_string()))?;
+        Ok(())
+    }
+
+    fn get_fee_schedule(&mut self, account_id: usize) -> Result<Option<FeeSchedule>, DataError> {
+        let mut stmt = self
+            .conn
+            .prepare(
+                "SELECT currency, rate_pa, min_fee, max_fee, fixed_fee, billing_period, start_date
+                FROM fee_schedules WHERE account_id=?;",
+            )
+            .map_err(|e| DataError::NotFound(e.to_string()))?;
+        let mut rows = stmt
+            .query_map(params![account_id as i64], |row| {
+                let currency: String = row.get(0)?;
+                let currency = Currency::from_str(&currency).map_err(|e| {
+                    rusqlite::Error::FromSqlConversionFailure(0, Type::Text, Box::new(e))
+                })?;
+                let start: String = row.get(6)?;
+                let start = NaiveDate::parse_from_str(&start, DATE_FORMAT).map_err(|e| {
+                    rusqlite::Error::FromSqlConversionFailure(6, Type::Text, Box::new(e))
+                })?;
+                Ok(FeeSchedule {
+                    account_id,
+                    currency,
+                    rate_pa: row.get(1)?,
+                    min_fee: row.get(2)?,
+                    max_fee: row.get(3)?,
+                    fixed_fee: row.get(4)?,
+                    billing_period: row.get(5)?,
+                    start,
+                })
+            })
+            .map_err(|e| DataError::NotFound(e.to_string()))?;
+        rows.next()
+            .transpose()
+            .map_err(|e| DataError::NotFound(e.to_string()))
+    }
+
+    fn delete_fee_schedule(&mut self, account_id: usize) -> Result<(), DataError> {
+        self.conn
+            .execute(
+                "DELETE FROM fee_schedules WHERE account_id=?1;",
+                params![account_id as i64],
+            )
+            .map_err(|e| DataError::DeleteFailed(e.to_string()))?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    use chrono::Utc;
+    use rusqlite::Connection;
+    use serde_json::json;
+
+    use finql_data::cash_flow::CashFlow;
+    use finql_data::clock::MockClock;
+    use finql_data::currency::Currency;
+    use finql_data::transaction::TransactionType;
+
+    fn fill(order_id: &str, position: f64) -> Transaction {
+        let mut trade = Transaction {
+            id: None,
+            transaction_type: TransactionType::Asset {
+                asset_id: 1,
+                position,
+            },
+            cash_flow: CashFlow::new(
+                -10.0 * position,
+                Currency::from_str("EUR").unwrap(),
+                NaiveDate::from_ymd(2021, 3, 2),
+            ),
+            note: None,
+            execution_meta: None,
+            recorded_at: None,
+        };
+        trade
+            .set_execution_meta(&json!({
+                "order_id": order_id,
+                "venue": {"mic": "XETR", "name": "Xetra"},
+                "executions": [{"time": "2021-03-02T09:00:01Z", "quantity": position}],
+            }))
+            .unwrap();
+        trade
+    }
+
+    #[test]
+    fn execution_meta_round_trip() {
+        let conn = Connection::open(":memory:").unwrap();
+        let mut db = SqliteDB::new(&conn);
+        db.init().unwrap();
+        let first = fill("A_1", 5.0);
+        let first_id = db.insert_transaction(&first).unwrap();
+        let second_id = db.insert_transaction(&fill("A_1", 3.0)).unwrap();
+        db.insert_transaction(&fill("AB1", 2.0)).unwrap();
+        let mut plain = fill("C1", 1.0);
+        plain.execution_meta = None;
+        let plain_id = db.insert_transaction(&plain).unwrap();
+
+        let stored = db.get_transaction_by_id(first_id).unwrap();
+        assert_eq!(stored.execution_meta, first.execution_meta);
+        assert_eq!(stored.execution_meta.unwrap()["venue"]["mic"], "XETR");
+        assert!(db
+            .get_transaction_by_id(plain_id)
+            .unwrap()
+            .execution_meta
+            .is_none());
+
+        // wildcards in the order id must not match other orders
+        let fills = db.find_transaction_by_order_id("A_1").unwrap();
+        let ids: Vec<_> = fills.iter().map(|t| t.id.unwrap()).collect();
+        assert_eq!(ids, vec![first_id, second_id]);
+        assert!(db.find_transaction_by_order_id("C1").unwrap().is_empty());
+
+        let mut update = db.get_transaction_by_id(second_id).unwrap();
+        update.set_execution_meta(&json!({"order_id": 42})).unwrap();
+        db.update_transaction(&update).unwrap();
+        assert_eq!(db.find_transaction_by_order_id("A_1").unwrap().len(), 1);
+        assert_eq!(db.find_transaction_by_order_id("42").unwrap().len(), 1);
+    }
+
+    #[test]
+    fn lot_selection_stored_with_transaction() {
+        let conn = Connection::open(":memory:").unwrap();
+        let mut db = SqliteDB::new(&conn);
+        db.init().unwrap();
+        let buy_id = db.insert_transaction(&fill("A1", 5.0)).unwrap();
+        let lots = vec![LotSelection {
+            buy_transaction_id: buy_id,
+            quantity: 3.0,
+        }];
+        let sell_id = db
+            .insert_transaction_with_lot_selection(&fill("A2", -3.0), &lots)
+            .unwrap();
+        let stored = db.get_lot_selection(sell_id).unwrap();
+        assert_eq!(stored.len(), 1);
+        assert_eq!(stored[0].buy_transaction_id, buy_id);
+        assert_eq!(stored[0].quantity, 3.0);
+
+        // the sell is not stored if its lots can't be stored
+        conn.execute_batch("DROP TABLE lot_selections").unwrap();
+        assert!(db
+            .insert_transaction_with_lot_selection(&fill("A3", -1.0), &lots)
+            .is_err());
+        assert_eq!(db.get_all_transactions().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn recorded_at_kept_on_update() {
+        use chrono::TimeZone;
+
+        let conn = Connection::open(":memory:").unwrap();
+        let recorded_at = Utc.ymd(2021, 3, 15).and_hms(9, 30, 0);
+        let clock = MockClock::new(recorded_at);
+        let mut db = SqliteDB::with_clock(&conn, &clock);
+        db.init().unwrap();
+        let id = db.insert_transaction(&fill("A1", 5.0)).unwrap();
+        let mut stored = db.get_transaction_by_id(id).unwrap();
+        assert_eq!(stored.recorded_at, Some(recorded_at));
+        clock.advance(chrono::Duration::days(1));
+
+        stored.note = Some("corrected".to_string());
+        stored.recorded_at = Some(Utc.ymd(2021, 4, 1).and_hms(0, 0, 0));
+        db.update_transaction(&stored).unwrap();
+        let updated = db.get_transaction_by_id(id).unwrap();
+        assert_eq!(updated.note.as_deref(), Some("corrected"));
+        assert_eq!(updated.recorded_at, Some(recorded_at));
+    }
+
+    #[test]
+    fn migrate_recorded_at() {
+        use chrono::TimeZone;
+
+        let conn = Connection::open(":memory:").unwrap();
+        conn.execute(
+            "CREATE TABLE transactions (
+                id INTEGER PRIMARY KEY,
+                trans_type TEXT NOT NULL,
+                asset_id INTEGER,
+                cash_amount REAL NOT NULL,
+                cash_currency TXT NOT NULL,
+                cash_date TEXT NOT NULL,
+                related_trans KEY,
+                position REAL,
+                note TEXT
+            );",
+            NO_PARAMS,
+        )
+        .unwrap();
+        conn.execute(
+            "INSERT INTO transactions (trans_type, cash_amount, cash_currency, cash_date)
+            VALUES ('c', 100.0, 'EUR', '2021-03-02')",
+            NO_PARAMS,
+        )
+        .unwrap();
+        let mut db = SqliteDB::new(&conn);
+        db.init().unwrap();
+        let transactions = db.get_all_transactions().unwrap();
+        assert_eq!(transactions.len(), 1);
+        assert_eq!(
+            transactions[0].recorded_at,
+            Some(Utc.ymd(2021, 3, 2).and_hms(0, 0, 0))
+        );
+    }
 }
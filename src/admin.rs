@@ -0,0 +1,1376 @@
+//! Typed commands for common maintenance tasks of a finql database
+//!
+//! Each command is run by `Command::execute` and reports its result as `CommandOutcome`, i.e.
+//! the number and ids of objects concerned and warnings about problems that did not stop the
+//! command. All commands that write to the database or file system support a dry run,
+//! which reports what would be done without changing anything.
+
+use std::collections::{BTreeMap, HashMap};
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use chrono::{DateTime, Duration, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use finql_data::{
+    refresh_ticker_from_chain, Asset, DataError, DataItem, QuotaMonth, Quote, QuoteHandler,
+    QuoteProvider, QuoteProviderRegistry, SchemaHandler, Ticker, Transaction, TransactionHandler,
+    TransactionType, SCHEMA_VERSION,
+};
+
+use crate::date_time_helper::{Clock, SystemClock};
+use crate::diagnostics::{DiagnosticCode, Diagnostics, Severity};
+
+/// Error related to admin commands
+#[derive(Debug)]
+pub enum AdminError {
+    DBError(DataError),
+    IOError(std::io::Error),
+    SerializationFailed(serde_json::Error),
+    /// Backup file is corrupted or no finql backup at all
+    InvalidBackup(String),
+    /// Backup has been created with a newer schema version than supported
+    NewerSchemaVersion(u32),
+}
+
+impl fmt::Display for AdminError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::DBError(err) => write!(f, "database error: {}", err),
+            Self::IOError(err) => write!(f, "writing file failed: {}", err),
+            Self::SerializationFailed(err) => write!(f, "serialization failed: {}", err),
+            Self::InvalidBackup(err) => write!(f, "invalid backup file: {}", err),
+            Self::NewerSchemaVersion(version) => write!(
+                f,
+                "backup has schema version {}, but only versions up to {} are supported",
+                version, SCHEMA_VERSION
+            ),
+        }
+    }
+}
+
+impl Error for AdminError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::DBError(err) => Some(err),
+            Self::IOError(err) => Some(err),
+            Self::SerializationFailed(err) => Some(err),
+            Self::InvalidBackup(_) | Self::NewerSchemaVersion(_) => None,
+        }
+    }
+}
+
+impl From<DataError> for AdminError {
+    fn from(error: DataError) -> Self {
+        Self::DBError(error)
+    }
+}
+
+impl From<std::io::Error> for AdminError {
+    fn from(error: std::io::Error) -> Self {
+        Self::IOError(error)
+    }
+}
+
+impl From<serde_json::Error> for AdminError {
+    fn from(error: serde_json::Error) -> Self {
+        Self::SerializationFailed(error)
+    }
+}
+
+/// Rules for thinning out old quotes
+#[derive(Debug, Clone, Copy)]
+pub struct RetentionPolicy {
+    /// Quotes older than this are reduced to the last quote per ticker and day
+    pub intraday_for: Duration,
+    /// Quotes older than this are deleted, if given
+    pub delete_after: Option<Duration>,
+}
+
+/// Environment admin commands are executed in
+pub struct AdminContext {
+    /// Providers used to fetch quotes
+    pub registry: QuoteProviderRegistry,
+    /// Source of the current time, e.g. for retention policies
+    pub clock: Arc<dyn Clock>,
+    /// If set, commands only report what they would do
+    pub dry_run: bool,
+}
+
+impl AdminContext {
+    pub fn new(registry: QuoteProviderRegistry) -> AdminContext {
+        AdminContext {
+            registry,
+            clock: Arc::new(SystemClock),
+            dry_run: false,
+        }
+    }
+}
+
+/// Result of an admin command
+#[derive(Debug, Default)]
+pub struct CommandOutcome {
+    /// True if nothing has been changed since the command has been executed as dry run
+    pub dry_run: bool,
+    /// Number of records created, deleted or exported (or that would be in a dry run),
+    /// or number of problems found by a consistency check
+    pub count: usize,
+    /// Ids of the objects concerned, e.g. the updated ticker or deleted quotes
+    pub ids: Vec<usize>,
+    /// Problems that did not stop the command
+    pub diagnostics: Diagnostics,
+}
+
+/// Common maintenance operations
+#[derive(Debug, Clone)]
+pub enum Command {
+    /// Create missing tables and migrate existing ones, idempotent
+    InitDb,
+    /// Fetch quotes newer than the latest stored quote up to today for all ticker,
+    /// or only those of the given source; idempotent for a given day. Ticker falling back to
+    /// their source chain are reported. Sources whose monthly quota limit has been reached are
+    /// deferred, see `QuoteProviderRegistry::set_quota_limit`, and ticker without any other
+    /// source are skipped.
+    UpdateQuotes { source: Option<String> },
+    /// Fetch the quote history of a ticker between `start` and `end` (both inclusive),
+    /// skipping quotes already stored; idempotent. Quota limits apply as for `UpdateQuotes`.
+    BackfillTicker {
+        id: usize,
+        start: NaiveDate,
+        end: NaiveDate,
+    },
+    /// Delete quotes according to the retention policy; idempotent
+    ApplyRetention { policy: RetentionPolicy },
+    /// Check the database for inconsistent data, read-only
+    CheckConsistency,
+    /// Delete rows of settings tables superseded by a newer row with the same key, as
+    /// reported by `CheckConsistency`, and add the missing unique keys; idempotent
+    CollapseDuplicateSettings,
+    /// Delete quotes superseded by a newer quote of the same ticker and time, as reported by
+    /// `CheckConsistency`, and add the missing unique key; idempotent
+    CollapseDuplicateQuotes,
+    /// Rewrite currency codes of ticker and transactions stored in lower case or with
+    /// surrounding white space, as reported by `CheckConsistency`; idempotent
+    NormalizeCurrencies,
+    /// Write all assets, ticker, quotes and transactions to a JSON file; idempotent
+    ExportBackup { path: PathBuf },
+    /// Add all objects of a backup file to the database with new ids, after the integrity of
+    /// the backup has been verified. Backups of older schema versions are migrated.
+    ImportBackup { path: PathBuf },
+}
+
+/// Identifier of finql backup files
+const BACKUP_FORMAT: &str = "finql-backup";
+
+/// Header of a backup file
+#[derive(Debug, Serialize, Deserialize)]
+struct BackupHeader {
+    format: String,
+    /// Version of the layout of the backup payload, see `SCHEMA_VERSION`
+    schema_version: u32,
+    /// Version of finql that created the backup
+    crate_version: String,
+    exported_at: DateTime<Utc>,
+    /// SHA-256 of the canonical JSON serialization of the payload, see `canonical_json`
+    sha256: String,
+}
+
+/// Backup file, the payload is the serialization of `Backup`. Since keys are sorted and the
+/// export time is taken from the context's clock, exporting the same content at the same
+/// time results in identical files.
+#[derive(Debug, Serialize, Deserialize)]
+struct BackupFile {
+    header: BackupHeader,
+    payload: serde_json::Value,
+}
+
+/// Content of a backup file
+#[derive(Serialize, Deserialize)]
+struct Backup {
+    assets: Vec<Asset>,
+    ticker: Vec<Ticker>,
+    quotes: Vec<Quote>,
+    transactions: Vec<Transaction>,
+}
+
+impl Command {
+    pub fn execute<DB>(&self, db: &mut DB, ctx: &AdminContext) -> Result<CommandOutcome, AdminError>
+    where
+        DB: QuoteHandler + TransactionHandler + SchemaHandler,
+    {
+        let mut outcome = CommandOutcome {
+            dry_run: ctx.dry_run,
+            ..Default::default()
+        };
+        match self {
+            Self::InitDb => {
+                if ctx.dry_run {
+                    outcome.diagnostics.record(
+                        DiagnosticCode::Skipped,
+                        Severity::Info,
+                        Vec::new(),
+                        "schema changes are not checked in a dry run".to_string(),
+                    );
+                } else {
+                    db.init_schema()?;
+                }
+            }
+            Self::UpdateQuotes { source } => {
+                let tickers = match source {
+                    Some(source) => db.get_all_ticker_for_source(source)?,
+                    None => db.get_all_ticker()?,
+                };
+                let today = ctx.clock.now_utc().naive_utc().date();
+                for ticker in tickers {
+                    let ticker_id = ticker.get_id()?;
+                    let mut supported = false;
+                    for source in ticker.sources() {
+                        if ctx.registry.supports_source(source) {
+                            supported = true;
+                        } else {
+                            outcome.diagnostics.warn(
+                                DiagnosticCode::MissingQuoteProvider,
+                                vec![ticker_id],
+                                format!(
+                                    "no quote provider for ticker {} of source '{}'",
+                                    ticker_id, source
+                                ),
+                            );
+                        }
+                    }
+                    if !supported || !check_quota(db, ctx, &ticker, &mut outcome.diagnostics)? {
+                        continue;
+                    }
+                    if ctx.dry_run {
+                        outcome.ids.push(ticker_id);
+                        continue;
+                    }
+                    let refresh = refresh_ticker_from_chain(&ticker, db, &ctx.registry, today);
+                    flush_quota_usage(db, ctx)?;
+                    match refresh {
+                        Ok(refresh) => {
+                            if !refresh.failed_sources.is_empty() {
+                                let failed: Vec<String> = refresh
+                                    .failed_sources
+                                    .iter()
+                                    .map(|(source, err)| format!("{} ({})", source, err))
+                                    .collect();
+                                outcome.diagnostics.warn(
+                                    DiagnosticCode::QuoteSourceFallback,
+                                    vec![ticker_id],
+                                    format!(
+                                        "ticker {} updated from source '{}' after {} failed",
+                                        ticker_id,
+                                        refresh.source,
+                                        failed.join(", ")
+                                    ),
+                                );
+                            }
+                            if refresh.count > 0 {
+                                outcome.count += refresh.count;
+                                outcome.ids.push(ticker_id);
+                            }
+                        }
+                        Err(err) => outcome.diagnostics.record(
+                            DiagnosticCode::UpdateFailed,
+                            Severity::Error,
+                            vec![ticker_id],
+                            format!("update of ticker {} failed: {}", ticker_id, err),
+                        ),
+                    }
+                }
+            }
+            Self::BackfillTicker { id, start, end } => {
+                let ticker = db.get_ticker_by_id(*id)?;
+                if !check_quota(db, ctx, &ticker, &mut outcome.diagnostics)? {
+                    return Ok(outcome);
+                }
+                let stored: Vec<_> = db
+                    .get_all_quotes_for_ticker(*id)?
+                    .into_iter()
+                    .map(|quote| quote.time)
+                    .collect();
+                let fetched = ctx.registry.fetch_quotes(&ticker, *start, *end);
+                flush_quota_usage(db, ctx)?;
+                for mut quote in fetched? {
+                    if stored.contains(&quote.time) {
+                        continue;
+                    }
+                    outcome.count += 1;
+                    if !ctx.dry_run {
+                        quote.ticker = *id;
+                        quote.scale_prices(ticker.factor);
+                        outcome.ids.push(db.insert_quote(&quote)?);
+                    }
+                }
+            }
+            Self::ApplyRetention { policy } => {
+                let now = ctx.clock.now_utc();
+                let intraday_limit = now - policy.intraday_for;
+                for ticker in db.get_all_ticker()? {
+                    // last quote per day, for days before the intraday limit
+                    let mut daily: BTreeMap<NaiveDate, Quote> = BTreeMap::new();
+                    let mut expired = Vec::new();
+                    for quote in db.get_all_quotes_for_ticker(ticker.get_id()?)? {
+                        if policy
+                            .delete_after
+                            .is_some_and(|age| quote.time < now - age)
+                        {
+                            expired.push(quote.get_id()?);
+                        } else if quote.time < intraday_limit {
+                            let date = quote.time.naive_utc().date();
+                            match daily.get(&date) {
+                                Some(last) if last.time >= quote.time => {
+                                    expired.push(quote.get_id()?)
+                                }
+                                Some(last) => {
+                                    expired.push(last.get_id()?);
+                                    daily.insert(date, quote);
+                                }
+                                None => {
+                                    daily.insert(date, quote);
+                                }
+                            }
+                        }
+                    }
+                    outcome.ids.extend(expired);
+                }
+                outcome.ids.sort_unstable();
+                outcome.count = outcome.ids.len();
+                if !ctx.dry_run {
+                    for id in &outcome.ids {
+                        db.delete_quote(*id)?;
+                    }
+                }
+            }
+            Self::CheckConsistency => {
+                check_consistency(db, &mut outcome.diagnostics)?;
+                outcome.count = outcome.diagnostics.len();
+            }
+            Self::CollapseDuplicateSettings => {
+                let duplicates = if ctx.dry_run {
+                    db.find_duplicate_settings()?
+                } else {
+                    db.collapse_duplicate_settings()?
+                };
+                outcome.count = duplicates.len();
+                for duplicate in duplicates {
+                    outcome.ids.push(duplicate.id);
+                    outcome.diagnostics.record(
+                        DiagnosticCode::DuplicateSetting,
+                        Severity::Info,
+                        vec![duplicate.id],
+                        format!(
+                            "removed row {} of {} with duplicate key '{}'",
+                            duplicate.id, duplicate.table, duplicate.key
+                        ),
+                    );
+                }
+            }
+            Self::CollapseDuplicateQuotes => {
+                let duplicates = if ctx.dry_run {
+                    db.find_duplicate_quotes()?
+                } else {
+                    db.collapse_duplicate_quotes()?
+                };
+                outcome.count = duplicates.len();
+                for duplicate in duplicates {
+                    outcome.ids.push(duplicate.id);
+                    outcome.diagnostics.record(
+                        DiagnosticCode::DuplicateQuote,
+                        Severity::Info,
+                        vec![duplicate.id],
+                        format!(
+                            "removed quote {} of ticker {} with duplicate time {}",
+                            duplicate.id, duplicate.ticker_id, duplicate.time
+                        ),
+                    );
+                }
+            }
+            Self::NormalizeCurrencies => {
+                let unnormalized = if ctx.dry_run {
+                    db.find_unnormalized_currencies()?
+                } else {
+                    db.normalize_currencies()?
+                };
+                outcome.count = unnormalized.len();
+                for row in unnormalized {
+                    outcome.ids.push(row.id);
+                    outcome.diagnostics.record(
+                        DiagnosticCode::UnnormalizedCurrency,
+                        Severity::Info,
+                        vec![row.id],
+                        format!(
+                            "rewrote currency '{}' of row {} of {}",
+                            row.currency, row.id, row.table
+                        ),
+                    );
+                }
+            }
+            Self::ExportBackup { path } => {
+                let ticker = db.get_all_ticker()?;
+                let mut quotes = Vec::new();
+                for t in &ticker {
+                    quotes.extend(db.get_all_quotes_for_ticker(t.get_id()?)?);
+                }
+                let mut backup = Backup {
+                    assets: db.get_all_assets()?,
+                    ticker,
+                    quotes,
+                    transactions: db.get_all_transactions()?,
+                };
+                // sort by id to get the same file for the same content
+                backup.assets.sort_by_key(|asset| asset.id);
+                backup.ticker.sort_by_key(|ticker| ticker.id);
+                backup.quotes.sort_by_key(|quote| quote.id);
+                backup
+                    .transactions
+                    .sort_by_key(|transaction| transaction.id);
+                outcome.count = backup.len();
+                if !ctx.dry_run {
+                    let payload = canonical_json(&serde_json::to_value(&backup)?);
+                    let backup_file = BackupFile {
+                        header: BackupHeader {
+                            format: BACKUP_FORMAT.to_string(),
+                            schema_version: SCHEMA_VERSION,
+                            crate_version: env!("CARGO_PKG_VERSION").to_string(),
+                            exported_at: ctx.clock.now_utc(),
+                            sha256: sha256_hex(&payload),
+                        },
+                        payload: serde_json::from_str(&payload)?,
+                    };
+                    fs::write(path, serde_json::to_string_pretty(&backup_file)?)?;
+                }
+            }
+            Self::ImportBackup { path } => {
+                let backup = read_backup(&fs::read_to_string(path)?)?;
+                outcome.count = backup.len();
+                if !ctx.dry_run {
+                    import_backup(db, backup, &mut outcome)?;
+                }
+            }
+        }
+        Ok(outcome)
+    }
+}
+
+impl Backup {
+    /// Total number of objects
+    fn len(&self) -> usize {
+        self.assets.len() + self.ticker.len() + self.quotes.len() + self.transactions.len()
+    }
+}
+
+/// Defer the sources of the ticker whose quota usage in the current month (in UTC) has reached
+/// their soft limit, and resume those below it, e.g. after the month has changed. Returns
+/// false and records a diagnostic if all sources of the ticker are deferred.
+fn check_quota<DB>(
+    db: &mut DB,
+    ctx: &AdminContext,
+    ticker: &Ticker,
+    diagnostics: &mut Diagnostics,
+) -> Result<bool, DataError>
+where
+    DB: QuoteHandler + ?Sized,
+{
+    let month = QuotaMonth::of(ctx.clock.now_utc());
+    let mut available = false;
+    for source in ticker.sources() {
+        match ctx.registry.quota_limit(source) {
+            Some(limit) if db.get_quota_usage(source, month)? >= limit => {
+                ctx.registry.defer_source(source)
+            }
+            _ => {
+                ctx.registry.resume_source(source);
+                available = true;
+            }
+        }
+    }
+    if !available {
+        let ticker_id = ticker.get_id()?;
+        diagnostics.warn(
+            DiagnosticCode::QuotaExceeded,
+            vec![ticker_id],
+            format!(
+                "ticker {} skipped, quota of all its sources used up until {}",
+                ticker_id,
+                month.next()
+            ),
+        );
+    }
+    Ok(available)
+}
+
+/// Add the provider calls made since the last flush to the persisted quota usage of the
+/// current month
+fn flush_quota_usage<DB>(db: &mut DB, ctx: &AdminContext) -> Result<(), DataError>
+where
+    DB: QuoteHandler + ?Sized,
+{
+    let month = QuotaMonth::of(ctx.clock.now_utc());
+    for (source, calls) in ctx.registry.take_pending_calls() {
+        db.add_quota_usage(&source, month, calls)?;
+    }
+    Ok(())
+}
+
+/// Compact JSON serialization with object keys in lexicographical order, numbers are
+/// written in the shortest form that is parsed back to the same value
+pub(crate) fn canonical_json(value: &serde_json::Value) -> String {
+    match value {
+        serde_json::Value::Array(values) => {
+            let values: Vec<String> = values.iter().map(canonical_json).collect();
+            format!("[{}]", values.join(","))
+        }
+        serde_json::Value::Object(map) => {
+            let mut entries: Vec<(&String, &serde_json::Value)> = map.iter().collect();
+            entries.sort_by(|a, b| a.0.cmp(b.0));
+            let entries: Vec<String> = entries
+                .into_iter()
+                .map(|(key, value)| {
+                    format!(
+                        "{}:{}",
+                        serde_json::Value::from(key.as_str()),
+                        canonical_json(value)
+                    )
+                })
+                .collect();
+            format!("{{{}}}", entries.join(","))
+        }
+        _ => value.to_string(),
+    }
+}
+
+/// SHA-256 hash as lower case hex string
+pub(crate) fn sha256_hex(data: &str) -> String {
+    Sha256::digest(data.as_bytes())
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+/// Parse and verify a backup file. Backups without header have been created before schema
+/// version 1 and have no checksum, but the same payload.
+fn read_backup(content: &str) -> Result<Backup, AdminError> {
+    let value: serde_json::Value = serde_json::from_str(content)?;
+    if value.get("header").is_none() {
+        return Ok(serde_json::from_value(value)?);
+    }
+    let backup_file: BackupFile = serde_json::from_value(value)?;
+    let header = &backup_file.header;
+    if header.format != BACKUP_FORMAT {
+        return Err(AdminError::InvalidBackup(format!(
+            "unknown format '{}'",
+            header.format
+        )));
+    }
+    if header.schema_version > SCHEMA_VERSION {
+        return Err(AdminError::NewerSchemaVersion(header.schema_version));
+    }
+    if sha256_hex(&canonical_json(&backup_file.payload)) != header.sha256 {
+        return Err(AdminError::InvalidBackup(
+            "checksum does not match content".to_string(),
+        ));
+    }
+    Ok(serde_json::from_value(backup_file.payload)?)
+}
+
+/// Add all objects of the backup to the database. Since objects get new ids, all references
+/// are mapped to the new ids. References to objects not contained in the backup are removed.
+fn import_backup<DB>(
+    db: &mut DB,
+    mut backup: Backup,
+    outcome: &mut CommandOutcome,
+) -> Result<(), AdminError>
+where
+    DB: QuoteHandler + TransactionHandler,
+{
+    let mut asset_ids = HashMap::new();
+    for mut asset in backup.assets {
+        let old_id = asset.get_id()?;
+        asset.id = None;
+        asset_ids.insert(old_id, db.insert_asset(&asset)?);
+    }
+    let mut ticker_ids = HashMap::new();
+    for mut ticker in backup.ticker {
+        let old_id = ticker.get_id()?;
+        ticker.id = None;
+        ticker.asset = *asset_ids.get(&ticker.asset).ok_or_else(|| {
+            AdminError::InvalidBackup(format!("ticker {} of unknown asset", old_id))
+        })?;
+        ticker_ids.insert(old_id, db.insert_ticker(&ticker)?);
+    }
+    for mut quote in backup.quotes {
+        quote.id = None;
+        quote.ticker = *ticker_ids.get(&quote.ticker).ok_or_else(|| {
+            AdminError::InvalidBackup(format!("quote of unknown ticker {}", quote.ticker))
+        })?;
+        db.insert_quote(&quote)?;
+    }
+    // references to transactions are set after all transactions have been inserted
+    let mut transaction_ids = HashMap::new();
+    let mut references = Vec::new();
+    backup
+        .transactions
+        .sort_by_key(|transaction| transaction.id);
+    for mut transaction in backup.transactions {
+        let old_id = transaction.get_id()?;
+        transaction.id = None;
+        if let Some(asset_id) = transaction_asset_id(&transaction) {
+            transaction.set_asset_id(*asset_ids.get(&asset_id).ok_or_else(|| {
+                AdminError::InvalidBackup(format!(
+                    "transaction {} of unknown asset {}",
+                    old_id, asset_id
+                ))
+            })?);
+        }
+        let reference = take_transaction_ref(&mut transaction);
+        let new_id = db.insert_transaction(&transaction)?;
+        transaction_ids.insert(old_id, new_id);
+        if let Some(reference) = reference {
+            references.push((old_id, new_id, reference));
+        }
+    }
+    for (old_id, new_id, reference) in references {
+        match transaction_ids.get(&reference) {
+            Some(new_reference) => {
+                let mut transaction = db.get_transaction_by_id(new_id)?;
+                transaction.set_transaction_ref(*new_reference);
+                db.update_transaction(&transaction)?;
+            }
+            None => outcome.diagnostics.warn(
+                DiagnosticCode::InconsistentData,
+                vec![new_id],
+                format!(
+                    "reference of transaction {} (now {}) to unknown transaction {} removed",
+                    old_id, new_id, reference
+                ),
+            ),
+        }
+    }
+    Ok(())
+}
+
+/// Id of the asset the transaction refers to, if any
+fn transaction_asset_id(transaction: &Transaction) -> Option<usize> {
+    match transaction.transaction_type {
+        TransactionType::Asset { asset_id, .. }
+        | TransactionType::Dividend { asset_id }
+        | TransactionType::Interest { asset_id }
+        | TransactionType::Transfer { asset_id, .. } => Some(asset_id),
+        _ => None,
+    }
+}
+
+/// Remove the reference to another transaction and return it
+fn take_transaction_ref(transaction: &mut Transaction) -> Option<usize> {
+    match &mut transaction.transaction_type {
+        TransactionType::Tax { transaction_ref }
+        | TransactionType::Fee { transaction_ref }
+        | TransactionType::Transfer {
+            transfer_ref: transaction_ref,
+            ..
+        } => transaction_ref.take(),
+        _ => None,
+    }
+}
+
+/// Record all inconsistencies found in the database
+fn check_consistency<DB>(db: &mut DB, diagnostics: &mut Diagnostics) -> Result<(), DataError>
+where
+    DB: QuoteHandler + TransactionHandler + SchemaHandler,
+{
+    for duplicate in db.find_duplicate_settings()? {
+        diagnostics.warn(
+            DiagnosticCode::DuplicateSetting,
+            vec![duplicate.id],
+            format!(
+                "row {} of {} is superseded by a newer row with key '{}'",
+                duplicate.id, duplicate.table, duplicate.key
+            ),
+        );
+    }
+    for duplicate in db.find_duplicate_quotes()? {
+        diagnostics.warn(
+            DiagnosticCode::DuplicateQuote,
+            vec![duplicate.id],
+            format!(
+                "quote {} of ticker {} is superseded by a newer quote at time {}",
+                duplicate.id, duplicate.ticker_id, duplicate.time
+            ),
+        );
+    }
+    for row in db.find_unnormalized_currencies()? {
+        diagnostics.warn(
+            DiagnosticCode::UnnormalizedCurrency,
+            vec![row.id],
+            format!(
+                "row {} of {} has currency '{}' not stored in normal form",
+                row.id, row.table, row.currency
+            ),
+        );
+    }
+    let assets = db.get_all_assets()?;
+    for asset in &assets {
+        // records invalid frequencies
+        diagnostics.expected_quote_frequency(asset);
+    }
+    let mut inconsistent = |ids: Vec<usize>, message: String| {
+        diagnostics.warn(DiagnosticCode::InconsistentData, ids, message)
+    };
+    for asset in db.get_assets_with_mixed_currencies()? {
+        let asset_id = asset.get_id()?;
+        inconsistent(
+            vec![asset_id],
+            format!(
+                "asset {} has valuation ticker in different currencies, but no reference currency",
+                asset_id
+            ),
+        );
+    }
+    let asset_ids: Vec<usize> = assets.iter().filter_map(|asset| asset.id).collect();
+    for ticker in db.get_all_ticker()? {
+        let ticker_id = ticker.get_id()?;
+        if let Err(err) = ticker.validate() {
+            inconsistent(vec![ticker_id], format!("ticker {}: {}", ticker_id, err));
+        }
+        if !asset_ids.contains(&ticker.asset) {
+            inconsistent(
+                vec![ticker_id, ticker.asset],
+                format!(
+                    "ticker {} refers to unknown asset {}",
+                    ticker_id, ticker.asset
+                ),
+            );
+        }
+        for quote in db.get_all_quotes_for_ticker(ticker_id)? {
+            if let Err(err) = quote.validate() {
+                let quote_id = quote.get_id()?;
+                inconsistent(vec![quote_id], format!("quote {}: {}", quote_id, err));
+            }
+        }
+    }
+    let transactions = db.get_all_transactions()?;
+    let transaction_ids: Vec<usize> = transactions.iter().filter_map(|t| t.id).collect();
+    for transaction in &transactions {
+        let (asset_id, transaction_ref) = match transaction.transaction_type {
+            TransactionType::Cash => (None, None),
+            TransactionType::Asset { asset_id, .. }
+            | TransactionType::Dividend { asset_id }
+            | TransactionType::Interest { asset_id } => (Some(asset_id), None),
+            TransactionType::Tax { transaction_ref } | TransactionType::Fee { transaction_ref } => {
+                (None, transaction_ref)
+            }
+            TransactionType::Transfer {
+                asset_id,
+                transfer_ref,
+                ..
+            } => (Some(asset_id), transfer_ref),
+        };
+        let id = transaction.get_id()?;
+        if let Some(asset_id) = asset_id {
+            if !asset_ids.contains(&asset_id) {
+                inconsistent(
+                    vec![id, asset_id],
+                    format!("transaction {} refers to unknown asset {}", id, asset_id),
+                );
+            }
+        }
+        if let Some(transaction_ref) = transaction_ref {
+            if !transaction_ids.contains(&transaction_ref) {
+                inconsistent(
+                    vec![id, transaction_ref],
+                    format!(
+                        "transaction {} refers to unknown transaction {}",
+                        id, transaction_ref
+                    ),
+                );
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    use chrono::{TimeZone, Utc};
+    use rusqlite::Connection;
+
+    use finql_data::{AssetHandler, CashFlow, Currency, TickerUsage};
+    use finql_sqlite::SqliteDB;
+
+    use crate::date_time_helper::MockClock;
+
+    /// Provides a quote at 18:00 UTC for each day
+    struct DailyProvider {}
+
+    impl QuoteProvider for DailyProvider {
+        fn fetch_quotes(
+            &self,
+            ticker: &Ticker,
+            start: NaiveDate,
+            end: NaiveDate,
+        ) -> Result<Vec<Quote>, DataError> {
+            let mut quotes = Vec::new();
+            let mut date = start;
+            while date <= end {
+                quotes.push(Quote {
+                    id: None,
+                    ticker: ticker.get_id()?,
+                    price: 10.0,
+                    time: Utc.from_utc_datetime(&date.and_hms(18, 0, 0)),
+                    volume: None,
+                    quality_score: None,
+                    source: None,
+                    open: None,
+                    high: None,
+                    low: None,
+                    bid: None,
+                    ask: None,
+                });
+                date = date.succ();
+            }
+            Ok(quotes)
+        }
+
+        fn supports_source(&self, source: &str) -> bool {
+            source == "daily"
+        }
+    }
+
+    /// Knows no symbols at all
+    struct UnknownSymbolProvider {}
+
+    impl QuoteProvider for UnknownSymbolProvider {
+        fn fetch_quotes(
+            &self,
+            ticker: &Ticker,
+            _start: NaiveDate,
+            _end: NaiveDate,
+        ) -> Result<Vec<Quote>, DataError> {
+            Err(DataError::NotFound(format!("symbol {} not found", ticker.name)))
+        }
+
+        fn supports_source(&self, source: &str) -> bool {
+            source == "unknown"
+        }
+    }
+
+    fn context(dry_run: bool) -> AdminContext {
+        let mut registry = QuoteProviderRegistry::new();
+        registry.register(Box::new(DailyProvider {}));
+        registry.register(Box::new(UnknownSymbolProvider {}));
+        let mut ctx = AdminContext::new(registry);
+        ctx.clock = Arc::new(MockClock::new(Utc.ymd(2021, 3, 31).and_hms(20, 0, 0)));
+        ctx.dry_run = dry_run;
+        ctx
+    }
+
+    fn insert_ticker(db: &mut SqliteDB, name: &str, source: &str) -> usize {
+        let asset_id = db
+            .insert_asset(&Asset::new(None, name, None, None, None))
+            .unwrap();
+        db.insert_ticker(&Ticker {
+            id: None,
+            name: name.to_string(),
+            asset: asset_id,
+            source: source.to_string(),
+            priority: 1,
+            currency: Currency::from_str("EUR").unwrap(),
+            factor: 1.0,
+            source_url: None,
+            usage: TickerUsage::Both,
+            last_quote_time: None,
+            source_chain: Vec::new(),
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn init_db_is_idempotent() {
+        let conn = Connection::open(":memory:").unwrap();
+        let mut db = SqliteDB::new(&conn);
+        let ctx = context(false);
+        Command::InitDb.execute(&mut db, &ctx).unwrap();
+        let ticker_id = insert_ticker(&mut db, "BASF", "daily");
+        Command::InitDb.execute(&mut db, &ctx).unwrap();
+        assert_eq!(db.get_ticker_by_id(ticker_id).unwrap().name, "BASF");
+    }
+
+    #[test]
+    fn update_and_backfill_quotes() {
+        let conn = Connection::open(":memory:").unwrap();
+        let mut db = SqliteDB::new(&conn);
+        db.init().unwrap();
+        let daily = insert_ticker(&mut db, "BASF", "daily");
+        let manual = insert_ticker(&mut db, "Siemens", "manual");
+        let update = Command::UpdateQuotes { source: None };
+
+        let outcome = update.execute(&mut db, &context(true)).unwrap();
+        assert!(outcome.dry_run);
+        assert_eq!(outcome.ids, vec![daily]);
+        assert_eq!(outcome.diagnostics.len(), 1);
+        assert!(outcome
+            .diagnostics
+            .contains(DiagnosticCode::MissingQuoteProvider));
+        assert!(db.get_all_quotes_for_ticker(daily).unwrap().is_empty());
+
+        let outcome = update.execute(&mut db, &context(false)).unwrap();
+        assert_eq!(outcome.count, 1);
+        assert_eq!(outcome.ids, vec![daily]);
+        let outcome = update.execute(&mut db, &context(false)).unwrap();
+        assert_eq!(outcome.count, 0);
+        let outcome = Command::UpdateQuotes {
+            source: Some("manual".to_string()),
+        }
+        .execute(&mut db, &context(false))
+        .unwrap();
+        assert_eq!(outcome.diagnostics.len(), 1);
+        let missing = outcome.diagnostics.iter().next().unwrap();
+        assert_eq!(missing.code, DiagnosticCode::MissingQuoteProvider);
+        assert_eq!(missing.entity_ids, vec![manual]);
+
+        let backfill = Command::BackfillTicker {
+            id: daily,
+            start: NaiveDate::from_ymd(2021, 3, 1),
+            end: NaiveDate::from_ymd(2021, 3, 31),
+        };
+        let outcome = backfill.execute(&mut db, &context(true)).unwrap();
+        assert_eq!(outcome.count, 30);
+        assert_eq!(db.get_all_quotes_for_ticker(daily).unwrap().len(), 1);
+        let outcome = backfill.execute(&mut db, &context(false)).unwrap();
+        assert_eq!(outcome.count, 30);
+        assert_eq!(outcome.ids.len(), 30);
+        let outcome = backfill.execute(&mut db, &context(false)).unwrap();
+        assert_eq!(outcome.count, 0);
+        assert_eq!(db.get_all_quotes_for_ticker(daily).unwrap().len(), 31);
+
+        assert!(Command::BackfillTicker {
+            id: manual,
+            start: NaiveDate::from_ymd(2021, 3, 1),
+            end: NaiveDate::from_ymd(2021, 3, 31),
+        }
+        .execute(&mut db, &context(false))
+        .is_err());
+    }
+
+    #[test]
+    fn update_quotes_from_source_chain() {
+        let conn = Connection::open(":memory:").unwrap();
+        let mut db = SqliteDB::new(&conn);
+        db.init().unwrap();
+        let id = insert_ticker(&mut db, "BASF", "unknown");
+        let mut ticker = db.get_ticker_by_id(id).unwrap();
+        ticker.set_source_chain("unknown,manual,daily").unwrap();
+        db.update_ticker(&ticker).unwrap();
+
+        let outcome = Command::UpdateQuotes { source: None }
+            .execute(&mut db, &context(false))
+            .unwrap();
+        assert_eq!(outcome.count, 1);
+        assert_eq!(outcome.ids, vec![id]);
+        assert!(outcome
+            .diagnostics
+            .contains(DiagnosticCode::MissingQuoteProvider));
+        let fallback = outcome
+            .diagnostics
+            .iter()
+            .find(|d| d.code == DiagnosticCode::QuoteSourceFallback)
+            .unwrap();
+        assert_eq!(fallback.entity_ids, vec![id]);
+        assert!(fallback.message.contains("'daily'"));
+        let quotes = db.get_all_quotes_for_ticker(id).unwrap();
+        assert_eq!(quotes[0].source.as_deref(), Some("daily"));
+
+        // without fallback, the update fails
+        ticker.source_chain.clear();
+        db.update_ticker(&ticker).unwrap();
+        let outcome = Command::UpdateQuotes { source: None }
+            .execute(&mut db, &context(false))
+            .unwrap();
+        assert!(outcome.diagnostics.contains(DiagnosticCode::UpdateFailed));
+    }
+
+    #[test]
+    fn update_quotes_within_quota() {
+        let conn = Connection::open(":memory:").unwrap();
+        let mut db = SqliteDB::new(&conn);
+        db.init().unwrap();
+        let ids: Vec<usize> = ["BASF", "Bayer", "Siemens"]
+            .iter()
+            .map(|name| insert_ticker(&mut db, name, "daily"))
+            .collect();
+        let march = QuotaMonth {
+            year: 2021,
+            month: 3,
+        };
+        // used by an earlier run
+        db.add_quota_usage("daily", march, 1).unwrap();
+        let mut ctx = context(false);
+        ctx.registry.set_quota_limit("daily", 3);
+        let update = Command::UpdateQuotes { source: None };
+
+        // limit is reached after the second ticker
+        let outcome = update.execute(&mut db, &ctx).unwrap();
+        assert_eq!(outcome.ids, ids[..2].to_vec());
+        let skipped: Vec<usize> = outcome
+            .diagnostics
+            .iter()
+            .filter(|d| d.code == DiagnosticCode::QuotaExceeded)
+            .map(|d| d.entity_ids[0])
+            .collect();
+        assert_eq!(skipped, vec![ids[2]]);
+        assert_eq!(db.get_quota_usage("daily", march).unwrap(), 3);
+        assert!(db.get_all_quotes_for_ticker(ids[2]).unwrap().is_empty());
+
+        // backfills are deferred as well, without calling the provider
+        let outcome = Command::BackfillTicker {
+            id: ids[2],
+            start: NaiveDate::from_ymd(2021, 3, 1),
+            end: NaiveDate::from_ymd(2021, 3, 31),
+        }
+        .execute(&mut db, &ctx)
+        .unwrap();
+        assert_eq!(outcome.count, 0);
+        assert!(outcome.diagnostics.contains(DiagnosticCode::QuotaExceeded));
+        assert_eq!(db.get_quota_usage("daily", march).unwrap(), 3);
+
+        // the quota is reset with the start of the next month in UTC
+        ctx.clock = Arc::new(MockClock::new(Utc.ymd(2021, 4, 1).and_hms(0, 30, 0)));
+        let outcome = update.execute(&mut db, &ctx).unwrap();
+        assert_eq!(outcome.ids, ids);
+        assert!(!outcome.diagnostics.contains(DiagnosticCode::QuotaExceeded));
+        assert_eq!(db.get_quota_usage("daily", march).unwrap(), 3);
+        assert_eq!(db.get_quota_usage("daily", march.next()).unwrap(), 3);
+    }
+
+    #[test]
+    fn apply_retention_policy() {
+        let conn = Connection::open(":memory:").unwrap();
+        let mut db = SqliteDB::new(&conn);
+        db.init().unwrap();
+        let ticker = insert_ticker(&mut db, "BASF", "daily");
+        let mut ids = Vec::new();
+        for (day, hour) in &[(1, 10), (1, 18), (1, 12), (20, 10), (30, 10), (30, 12)] {
+            ids.push(
+                db.insert_quote(&Quote {
+                    id: None,
+                    ticker,
+                    price: 10.0,
+                    time: Utc.ymd(2021, 3, *day).and_hms(*hour, 0, 0),
+                    volume: None,
+                    quality_score: None,
+                    source: None,
+                    open: None,
+                    high: None,
+                    low: None,
+                    bid: None,
+                    ask: None,
+                })
+                .unwrap(),
+            );
+        }
+        let retention = Command::ApplyRetention {
+            policy: RetentionPolicy {
+                intraday_for: Duration::days(7),
+                delete_after: None,
+            },
+        };
+        let outcome = retention.execute(&mut db, &context(true)).unwrap();
+        assert_eq!(outcome.ids, vec![ids[0], ids[2]]);
+        assert_eq!(db.get_all_quotes_for_ticker(ticker).unwrap().len(), 6);
+        let outcome = retention.execute(&mut db, &context(false)).unwrap();
+        assert_eq!(outcome.count, 2);
+        assert_eq!(db.get_all_quotes_for_ticker(ticker).unwrap().len(), 4);
+        let outcome = retention.execute(&mut db, &context(false)).unwrap();
+        assert_eq!(outcome.count, 0);
+
+        let outcome = Command::ApplyRetention {
+            policy: RetentionPolicy {
+                intraday_for: Duration::days(7),
+                delete_after: Some(Duration::days(15)),
+            },
+        }
+        .execute(&mut db, &context(false))
+        .unwrap();
+        assert_eq!(outcome.ids, vec![ids[1]]);
+        assert_eq!(db.get_all_quotes_for_ticker(ticker).unwrap().len(), 3);
+    }
+
+    #[test]
+    fn collapse_duplicate_settings() {
+        let conn = Connection::open(":memory:").unwrap();
+        // rounding digits of a legacy database without unique key on the currency
+        conn.execute_batch(
+            "CREATE TABLE rounding_digits (
+                id INTEGER PRIMARY KEY,
+                currency TEXT NOT NULL,
+                digits INTEGER NOT NULL);
+            INSERT INTO rounding_digits (currency, digits) VALUES
+                ('JPY', 2), ('JPY', 0), ('XAU', 4);",
+        )
+        .unwrap();
+        let mut db = SqliteDB::new(&conn);
+        Command::InitDb.execute(&mut db, &context(false)).unwrap();
+        let outcome = Command::CheckConsistency
+            .execute(&mut db, &context(false))
+            .unwrap();
+        assert_eq!(outcome.count, 1);
+        assert!(outcome.diagnostics.contains(DiagnosticCode::DuplicateSetting));
+
+        let collapse = Command::CollapseDuplicateSettings;
+        let outcome = collapse.execute(&mut db, &context(true)).unwrap();
+        assert_eq!(outcome.ids, vec![1]);
+        assert_eq!(db.find_duplicate_settings().unwrap().len(), 1);
+        let outcome = collapse.execute(&mut db, &context(false)).unwrap();
+        assert_eq!((outcome.count, outcome.ids), (1, vec![1]));
+        let removed = outcome.diagnostics.iter().next().unwrap();
+        assert_eq!(removed.message, "removed row 1 of rounding_digits with duplicate key 'JPY'");
+        let jpy = Currency::from_str("JPY").unwrap();
+        assert_eq!(db.get_rounding_digits(jpy), 0);
+        assert_eq!(collapse.execute(&mut db, &context(false)).unwrap().count, 0);
+        let outcome = Command::CheckConsistency
+            .execute(&mut db, &context(false))
+            .unwrap();
+        assert_eq!(outcome.count, 0);
+    }
+
+    #[test]
+    fn collapse_duplicate_quotes() {
+        let conn = Connection::open(":memory:").unwrap();
+        let mut db = SqliteDB::new(&conn);
+        db.init().unwrap();
+        let ticker = insert_ticker(&mut db, "BASF", "daily");
+        // quotes of a legacy database without unique key on ticker and time
+        conn.execute_batch(&format!(
+            "DROP INDEX quotes_ticker_time_unique;
+            INSERT INTO quotes (ticker_id, price, time) VALUES
+                ({ticker}, 61.0, '2021-03-02T17:30:00+00:00'),
+                ({ticker}, 61.5, '2021-03-02T17:30:00+00:00');",
+            ticker = ticker
+        ))
+        .unwrap();
+        assert!(matches!(
+            Command::InitDb.execute(&mut db, &context(false)),
+            Err(AdminError::DBError(DataError::DuplicateQuotes(_)))
+        ));
+        let outcome = Command::CheckConsistency
+            .execute(&mut db, &context(false))
+            .unwrap();
+        assert_eq!(outcome.count, 1);
+        assert!(outcome.diagnostics.contains(DiagnosticCode::DuplicateQuote));
+
+        let collapse = Command::CollapseDuplicateQuotes;
+        let outcome = collapse.execute(&mut db, &context(true)).unwrap();
+        assert_eq!(outcome.ids, vec![1]);
+        assert_eq!(db.find_duplicate_quotes().unwrap().len(), 1);
+        let outcome = collapse.execute(&mut db, &context(false)).unwrap();
+        assert_eq!((outcome.count, outcome.ids), (1, vec![1]));
+        let quotes = db.get_all_quotes_for_ticker(ticker).unwrap();
+        assert_eq!(quotes.len(), 1);
+        assert_eq!(quotes[0].price, 61.5);
+        assert_eq!(collapse.execute(&mut db, &context(false)).unwrap().count, 0);
+        Command::InitDb.execute(&mut db, &context(false)).unwrap();
+    }
+
+    #[test]
+    fn normalize_currencies() {
+        let conn = Connection::open(":memory:").unwrap();
+        let mut db = SqliteDB::new(&conn);
+        db.init().unwrap();
+        let ticker = insert_ticker(&mut db, "BASF", "daily");
+        conn.execute_batch("UPDATE ticker SET currency='eur '").unwrap();
+        let outcome = Command::CheckConsistency
+            .execute(&mut db, &context(false))
+            .unwrap();
+        assert_eq!(outcome.count, 1);
+        let unnormalized = outcome.diagnostics.iter().next().unwrap();
+        assert_eq!(unnormalized.code, DiagnosticCode::UnnormalizedCurrency);
+        assert_eq!(unnormalized.entity_ids, vec![ticker]);
+
+        let normalize = Command::NormalizeCurrencies;
+        let outcome = normalize.execute(&mut db, &context(true)).unwrap();
+        assert_eq!(outcome.ids, vec![ticker]);
+        assert_eq!(db.find_unnormalized_currencies().unwrap().len(), 1);
+        let outcome = normalize.execute(&mut db, &context(false)).unwrap();
+        let rewritten = outcome.diagnostics.iter().next().unwrap();
+        assert_eq!(rewritten.message, "rewrote currency 'eur ' of row 1 of ticker");
+        assert_eq!(normalize.execute(&mut db, &context(false)).unwrap().count, 0);
+        let outcome = Command::CheckConsistency
+            .execute(&mut db, &context(false))
+            .unwrap();
+        assert_eq!(outcome.count, 0);
+    }
+
+    #[test]
+    fn check_consistency_and_export() {
+        let conn = Connection::open(":memory:").unwrap();
+        let mut db = SqliteDB::new(&conn);
+        db.init().unwrap();
+        let ticker = insert_ticker(&mut db, "BASF", "daily");
+        let eur = Currency::from_str("EUR").unwrap();
+        db.insert_transaction(&Transaction {
+            id: None,
+            transaction_type: TransactionType::Fee {
+                transaction_ref: Some(42),
+            },
+            cash_flow: CashFlow::new(-4.95, eur, NaiveDate::from_ymd(2021, 3, 2)),
+            note: None,
+            execution_meta: None,
+            recorded_at: None,
+        })
+        .unwrap();
+        let outcome = Command::CheckConsistency
+            .execute(&mut db, &context(false))
+            .unwrap();
+        assert_eq!(outcome.count, 1);
+        let inconsistency = outcome.diagnostics.iter().next().unwrap();
+        assert_eq!(inconsistency.code, DiagnosticCode::InconsistentData);
+        assert_eq!(inconsistency.entity_ids[1], 42);
+        assert!(inconsistency.message.contains("unknown transaction 42"));
+
+        Command::BackfillTicker {
+            id: ticker,
+            start: NaiveDate::from_ymd(2021, 3, 1),
+            end: NaiveDate::from_ymd(2021, 3, 5),
+        }
+        .execute(&mut db, &context(false))
+        .unwrap();
+        let path = std::env::temp_dir().join(format!("finql_backup_{}.json", std::process::id()));
+        let export = Command::ExportBackup { path: path.clone() };
+        let outcome = export.execute(&mut db, &context(true)).unwrap();
+        assert_eq!(outcome.count, 8);
+        assert!(!path.exists());
+        export.execute(&mut db, &context(false)).unwrap();
+        let first = fs::read_to_string(&path).unwrap();
+        export.execute(&mut db, &context(false)).unwrap();
+        let second = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(first, second);
+        let backup: serde_json::Value = serde_json::from_str(&first).unwrap();
+        assert_eq!(backup["payload"]["quotes"].as_array().unwrap().len(), 5);
+        assert_eq!(backup["header"]["schema_version"], SCHEMA_VERSION);
+        assert_eq!(backup["header"]["exported_at"], "2021-03-31T20:00:00Z");
+    }
+
+    #[test]
+    fn import_backup_with_new_ids() {
+        let conn = Connection::open(":memory:").unwrap();
+        let mut db = SqliteDB::new(&conn);
+        db.init().unwrap();
+        let eur = Currency::from_str("EUR").unwrap();
+        insert_ticker(&mut db, "Siemens", "manual");
+        let ticker = insert_ticker(&mut db, "BASF", "daily");
+        let basf = db.get_ticker_by_id(ticker).unwrap().asset;
+        Command::BackfillTicker {
+            id: ticker,
+            start: NaiveDate::from_ymd(2021, 3, 1),
+            end: NaiveDate::from_ymd(2021, 3, 2),
+        }
+        .execute(&mut db, &context(false))
+        .unwrap();
+        let transaction = |transaction_type, amount| Transaction {
+            id: None,
+            transaction_type,
+            cash_flow: CashFlow::new(amount, eur, NaiveDate::from_ymd(2021, 3, 2)),
+            note: None,
+            execution_meta: None,
+            recorded_at: None,
+        };
+        let buy = db
+            .insert_transaction(&transaction(
+                TransactionType::Asset {
+                    asset_id: basf,
+                    position: 10.0,
+                },
+                -100.0,
+            ))
+            .unwrap();
+        db.insert_transaction(&transaction(
+            TransactionType::Fee {
+                transaction_ref: Some(buy),
+            },
+            -4.95,
+        ))
+        .unwrap();
+        let path = std::env::temp_dir().join(format!("finql_import_{}.json", std::process::id()));
+        Command::ExportBackup { path: path.clone() }
+            .execute(&mut db, &context(false))
+            .unwrap();
+        let content = fs::read_to_string(&path).unwrap();
+
+        let conn = Connection::open(":memory:").unwrap();
+        let mut restored = SqliteDB::new(&conn);
+        restored.init().unwrap();
+        // shift all ids of the restored objects
+        insert_ticker(&mut restored, "Other", "manual");
+        let import = Command::ImportBackup { path: path.clone() };
+        let outcome = import.execute(&mut restored, &context(true)).unwrap();
+        assert_eq!(outcome.count, 8);
+        assert_eq!(restored.get_all_assets().unwrap().len(), 1);
+        let outcome = import.execute(&mut restored, &context(false)).unwrap();
+        assert!(outcome.diagnostics.is_empty());
+        let new_basf = restored.get_asset_by_id(basf + 1).unwrap();
+        assert_eq!(new_basf.name, "BASF");
+        let new_ticker = restored.get_ticker_by_id(ticker + 1).unwrap();
+        assert_eq!(new_ticker.asset, basf + 1);
+        assert_eq!(
+            restored
+                .get_all_quotes_for_ticker(ticker + 1)
+                .unwrap()
+                .len(),
+            2
+        );
+        let fee = restored.get_transaction_by_id(2).unwrap();
+        assert!(matches!(
+            fee.transaction_type,
+            TransactionType::Fee {
+                transaction_ref: Some(1)
+            }
+        ));
+        let buy = restored.get_transaction_by_id(1).unwrap();
+        assert!(matches!(
+            buy.transaction_type,
+            TransactionType::Asset { asset_id, .. } if asset_id == basf + 1
+        ));
+
+        // re-exporting the unchanged database results in an identical file
+        Command::ExportBackup { path: path.clone() }
+            .execute(&mut db, &context(false))
+            .unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), content);
+
+        // manipulated content
+        fs::write(&path, content.replace("\"BASF\"", "\"BASX\"")).unwrap();
+        assert!(matches!(
+            import.execute(&mut restored, &context(false)),
+            Err(AdminError::InvalidBackup(_))
+        ));
+        // newer schema
+        let mut backup: serde_json::Value = serde_json::from_str(&content).unwrap();
+        backup["header"]["schema_version"] = (SCHEMA_VERSION + 1).into();
+        fs::write(&path, backup.to_string()).unwrap();
+        match import.execute(&mut restored, &context(false)) {
+            Err(AdminError::NewerSchemaVersion(version)) => {
+                assert_eq!(version, SCHEMA_VERSION + 1)
+            }
+            _ => panic!("import of newer schema version must fail"),
+        }
+        // backup without header of schema version 0
+        fs::write(&path, backup["payload"].to_string()).unwrap();
+        let conn = Connection::open(":memory:").unwrap();
+        let mut legacy = SqliteDB::new(&conn);
+        legacy.init().unwrap();
+        let outcome = import.execute(&mut legacy, &context(false)).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(outcome.count, 8);
+        assert_eq!(legacy.get_all_transactions().unwrap().len(), 2);
+    }
+}
@@ -0,0 +1,38 @@
+//! Error type shared by the analytics functions, e.g. risk figures, benchmark comparisons or
+//! position sizing.
+
+use std::error::Error;
+use std::fmt;
+
+/// Error related to analytics, e.g. the calculation of risk figures or benchmark comparisons
+#[derive(Debug, Clone, PartialEq)]
+pub enum AnalyticsError {
+    /// No returns have been given
+    NoData,
+    /// Confidence level is not strictly between 0 and 1
+    InvalidConfidenceLevel(f64),
+    /// Returns contain values that are not finite
+    InvalidReturn(f64),
+    /// Input parameters are out of their valid range
+    InvalidInput(String),
+    /// Results that have been computed in different ways do not agree
+    Inconsistent(String),
+}
+
+impl fmt::Display for AnalyticsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NoData => write!(f, "no returns given"),
+            Self::InvalidConfidenceLevel(level) => write!(
+                f,
+                "confidence level must be between 0 and 1, but is {}",
+                level
+            ),
+            Self::InvalidReturn(value) => write!(f, "invalid return {}", value),
+            Self::InvalidInput(err) => write!(f, "invalid input: {}", err),
+            Self::Inconsistent(err) => write!(f, "inconsistent results: {}", err),
+        }
+    }
+}
+
+impl Error for AnalyticsError {}
@@ -0,0 +1,635 @@
+//! Archive the history of closed positions
+//!
+//! Quotes and transactions of positions closed long ago, e.g. after the tax lock period has
+//! passed, are rarely needed but slow down everyday queries. `archive_asset_history` moves
+//! them into a second database, which could be read together with the original one if
+//! required. The copy is verified before anything is deleted from the source database.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::error::Error;
+use std::fmt;
+
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::Serialize;
+
+use finql_data::{
+    Asset, DataError, DataHandler, DataItem, LotSelection, Quote, Ticker, Transaction,
+    TransactionType,
+};
+
+use crate::admin::{canonical_json, sha256_hex};
+
+/// Positions with smaller absolute values are considered closed
+const POSITION_TOLERANCE: f64 = 1e-10;
+
+/// Error related to archiving the history of an asset
+#[derive(Debug)]
+pub enum ArchiveError {
+    DBError(DataError),
+    SerializationFailed(serde_json::Error),
+    /// The position in the asset is still open at the cutoff date
+    PositionOpen(f64),
+    /// A transaction to be archived refers to a transaction that would be kept, or vice versa
+    DanglingReference(usize),
+    /// The archived copy differs from the original data, nothing has been deleted
+    VerificationFailed(String),
+}
+
+impl fmt::Display for ArchiveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::DBError(err) => write!(f, "database error: {}", err),
+            Self::SerializationFailed(err) => write!(f, "serialization failed: {}", err),
+            Self::PositionOpen(position) => write!(
+                f,
+                "position of {} is still open at the cutoff date",
+                position
+            ),
+            Self::DanglingReference(id) => write!(
+                f,
+                "transaction {} refers to or is referred to by a transaction not archived",
+                id
+            ),
+            Self::VerificationFailed(err) => write!(f, "verification of archive failed: {}", err),
+        }
+    }
+}
+
+impl Error for ArchiveError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::DBError(err) => Some(err),
+            Self::SerializationFailed(err) => Some(err),
+            Self::PositionOpen(_) | Self::DanglingReference(_) | Self::VerificationFailed(_) => {
+                None
+            }
+        }
+    }
+}
+
+impl From<DataError> for ArchiveError {
+    fn from(error: DataError) -> Self {
+        Self::DBError(error)
+    }
+}
+
+impl From<serde_json::Error> for ArchiveError {
+    fn from(error: serde_json::Error) -> Self {
+        Self::SerializationFailed(error)
+    }
+}
+
+/// Number of records and checksum of an asset's history, independent of the ids the records
+/// have in a particular database
+#[derive(Debug, Clone, PartialEq)]
+pub struct HistoryDigest {
+    pub quotes: usize,
+    pub transactions: usize,
+    pub lot_selections: usize,
+    /// SHA-256 hash of all records in canonical JSON, ignoring the order of the records
+    pub checksum: String,
+}
+
+impl HistoryDigest {
+    /// Calculate the digest of quotes, transactions and lot selections given as pairs of sell
+    /// transaction id and selected lot. Ids of quotes and transactions are ignored.
+    pub fn new(
+        quotes: &[Quote],
+        transactions: &[Transaction],
+        lot_selections: &[(usize, LotSelection)],
+    ) -> Result<HistoryDigest, ArchiveError> {
+        let mut records = Vec::new();
+        for quote in quotes {
+            let quote = Quote {
+                id: None,
+                ..quote.clone()
+            };
+            records.push(format!("quote:{}", canonical_record(&quote)?));
+        }
+        for transaction in transactions {
+            let transaction = Transaction {
+                id: None,
+                ..transaction.clone()
+            };
+            records.push(format!("transaction:{}", canonical_record(&transaction)?));
+        }
+        for (sell_id, lot) in lot_selections {
+            records.push(format!("lot:{}:{}", sell_id, canonical_record(lot)?));
+        }
+        records.sort();
+        Ok(HistoryDigest {
+            quotes: quotes.len(),
+            transactions: transactions.len(),
+            lot_selections: lot_selections.len(),
+            checksum: sha256_hex(&records.join("\n")),
+        })
+    }
+
+    /// Describe the first difference to another digest, or None if both are equal
+    fn difference(&self, other: &HistoryDigest) -> Option<String> {
+        if self.quotes != other.quotes {
+            Some(format!(
+                "{} quotes instead of {}",
+                other.quotes, self.quotes
+            ))
+        } else if self.transactions != other.transactions {
+            Some(format!(
+                "{} transactions instead of {}",
+                other.transactions, self.transactions
+            ))
+        } else if self.lot_selections != other.lot_selections {
+            Some(format!(
+                "{} lot selections instead of {}",
+                other.lot_selections, self.lot_selections
+            ))
+        } else if self.checksum != other.checksum {
+            Some("checksums differ".to_string())
+        } else {
+            None
+        }
+    }
+}
+
+fn canonical_record<T: Serialize>(record: &T) -> Result<String, ArchiveError> {
+    Ok(canonical_json(&serde_json::to_value(record)?))
+}
+
+/// Result of archiving the history of an asset
+#[derive(Debug)]
+pub struct ArchiveOutcome {
+    /// Id of the asset in the archive
+    pub archive_asset_id: usize,
+    /// Ids of the quotes deleted from the source database
+    pub quote_ids: Vec<usize>,
+    /// Ids of the transactions deleted from the source database
+    pub transaction_ids: Vec<usize>,
+    /// Digest of the archived history as stored in the archive
+    pub digest: HistoryDigest,
+}
+
+/// Move all quotes of the asset's ticker before the cutoff date and all transactions of the
+/// asset with cash flows before the cutoff date, together with the fees, taxes and transfers
+/// referring to them, into the archive. The asset and its ticker are added to the archive if
+/// they don't exist there yet, but kept in the source database.
+///
+/// The position in the asset must be closed at the cutoff date. The copy is compared with the
+/// original records by number and checksum, and only if both match, the archived quotes and
+/// transactions are deleted from the source database within a single database transaction.
+/// On any error, the source database is left unchanged, but records already copied remain in
+/// the archive.
+pub fn archive_asset_history(
+    db: &mut dyn DataHandler,
+    asset_id: usize,
+    before: NaiveDate,
+    archive: &mut dyn DataHandler,
+) -> Result<ArchiveOutcome, ArchiveError> {
+    let cutoff = DateTime::<Utc>::from_utc(before.and_hms(0, 0, 0), Utc);
+    let transactions = history_transactions(db.get_all_transactions()?, asset_id, before)?;
+    let position: f64 = transactions
+        .iter()
+        .map(|t| match t.transaction_type {
+            TransactionType::Asset { position, .. }
+            | TransactionType::Transfer { position, .. } => position,
+            _ => 0.0,
+        })
+        .sum();
+    if position.abs() > POSITION_TOLERANCE {
+        return Err(ArchiveError::PositionOpen(position));
+    }
+
+    let asset = db.get_asset_by_id(asset_id)?;
+    let archive_asset_id = archive.insert_asset_if_new(&Asset { id: None, ..asset }, false)?;
+
+    // Copy the quotes, the expected digest is built from the originals with the ids of
+    // the archive
+    let mut quote_ids = Vec::new();
+    let mut expected_quotes = Vec::new();
+    let mut archived_quotes = Vec::new();
+    for ticker in db.get_all_ticker_for_asset(asset_id)? {
+        let mut quotes = db.get_all_quotes_for_ticker(ticker.get_id()?)?;
+        quotes.retain(|quote| quote.time < cutoff);
+        let archive_ticker_id = archive.insert_if_new_ticker(&Ticker {
+            id: None,
+            asset: archive_asset_id,
+            last_quote_time: None,
+            ..ticker
+        })?;
+        for quote in &quotes {
+            quote_ids.push(quote.id.ok_or_else(|| {
+                DataError::InvalidData("quote without id found in database".to_string())
+            })?);
+        }
+        let quotes: Vec<Quote> = quotes
+            .into_iter()
+            .map(|quote| Quote {
+                id: None,
+                ticker: archive_ticker_id,
+                ..quote
+            })
+            .collect();
+        let new_ids: BTreeSet<usize> = archive.insert_quotes(&quotes)?.into_iter().collect();
+        expected_quotes.extend(quotes);
+        archived_quotes.extend(
+            archive
+                .get_all_quotes_for_ticker(archive_ticker_id)?
+                .into_iter()
+                .filter(|quote| matches!(quote.id, Some(id) if new_ids.contains(&id))),
+        );
+    }
+
+    // Copy the transactions in order of their ids, since they only refer to earlier ones
+    let mut transaction_ids = BTreeMap::new();
+    let mut expected_transactions = Vec::new();
+    let mut archived_transactions = Vec::new();
+    for transaction in &transactions {
+        let id = transaction.get_id()?;
+        let copy = Transaction {
+            id: None,
+            transaction_type: map_transaction_type(
+                &transaction.transaction_type,
+                archive_asset_id,
+                &transaction_ids,
+            )?,
+            ..transaction.clone()
+        };
+        let new_id = archive.insert_transaction(&copy)?;
+        transaction_ids.insert(id, new_id);
+        expected_transactions.push(copy);
+        archived_transactions.push(archive.get_transaction_by_id(new_id)?);
+    }
+
+    let mut expected_lots = Vec::new();
+    let mut archived_lots = Vec::new();
+    for (id, new_id) in &transaction_ids {
+        let lots = db.get_lot_selection(*id)?;
+        if lots.is_empty() {
+            continue;
+        }
+        let mut copies = Vec::new();
+        for lot in lots {
+            copies.push(LotSelection {
+                buy_transaction_id: *transaction_ids
+                    .get(&lot.buy_transaction_id)
+                    .ok_or(ArchiveError::DanglingReference(*id))?,
+                quantity: lot.quantity,
+            });
+        }
+        archive.insert_lot_selection(*new_id, &copies)?;
+        expected_lots.extend(copies.into_iter().map(|lot| (*new_id, lot)));
+        archived_lots.extend(
+            archive
+                .get_lot_selection(*new_id)?
+                .into_iter()
+                .map(|lot| (*new_id, lot)),
+        );
+    }
+
+    let expected = HistoryDigest::new(&expected_quotes, &expected_transactions, &expected_lots)?;
+    let digest = HistoryDigest::new(&archived_quotes, &archived_transactions, &archived_lots)?;
+    if let Some(difference) = expected.difference(&digest) {
+        return Err(ArchiveError::VerificationFailed(difference));
+    }
+
+    let transaction_ids: Vec<usize> = transaction_ids.keys().cloned().collect();
+    db.delete_history(&quote_ids, &transaction_ids)?;
+    Ok(ArchiveOutcome {
+        archive_asset_id,
+        quote_ids,
+        transaction_ids,
+        digest,
+    })
+}
+
+/// Select the transactions of the asset before the cutoff date and those referring to them,
+/// in order of their ids
+fn history_transactions(
+    mut transactions: Vec<Transaction>,
+    asset_id: usize,
+    before: NaiveDate,
+) -> Result<Vec<Transaction>, ArchiveError> {
+    transactions.sort_by_key(|t| t.id);
+    let mut selected = BTreeSet::new();
+    for transaction in &transactions {
+        let id = transaction.get_id()?;
+        let of_asset = match transaction.transaction_type {
+            TransactionType::Asset { asset_id: id, .. }
+            | TransactionType::Dividend { asset_id: id }
+            | TransactionType::Interest { asset_id: id }
+            | TransactionType::Transfer { asset_id: id, .. } => id == asset_id,
+            _ => false,
+        };
+        if (of_asset && transaction.cash_flow.date < before)
+            || matches!(reference(&transaction.transaction_type), Some(r) if selected.contains(&r))
+        {
+            selected.insert(id);
+        }
+    }
+    let mut history = Vec::new();
+    for transaction in transactions {
+        let id = transaction.get_id()?;
+        let is_selected = selected.contains(&id);
+        if let Some(r) = reference(&transaction.transaction_type) {
+            if selected.contains(&r) != is_selected {
+                return Err(ArchiveError::DanglingReference(id));
+            }
+        }
+        if is_selected {
+            history.push(transaction);
+        }
+    }
+    Ok(history)
+}
+
+/// Id of the transaction a transaction refers to, if any
+fn reference(transaction_type: &TransactionType) -> Option<usize> {
+    match transaction_type {
+        TransactionType::Tax { transaction_ref } | TransactionType::Fee { transaction_ref } => {
+            *transaction_ref
+        }
+        TransactionType::Transfer { transfer_ref, .. } => *transfer_ref,
+        _ => None,
+    }
+}
+
+/// Replace asset and transaction ids by the ids in the archive
+fn map_transaction_type(
+    transaction_type: &TransactionType,
+    archive_asset_id: usize,
+    transaction_ids: &BTreeMap<usize, usize>,
+) -> Result<TransactionType, ArchiveError> {
+    let map_ref = |r: &Option<usize>| -> Result<Option<usize>, ArchiveError> {
+        match r {
+            Some(id) => Ok(Some(
+                *transaction_ids
+                    .get(id)
+                    .ok_or(ArchiveError::DanglingReference(*id))?,
+            )),
+            None => Ok(None),
+        }
+    };
+    Ok(match transaction_type {
+        TransactionType::Cash => TransactionType::Cash,
+        TransactionType::Asset { position, .. } => TransactionType::Asset {
+            asset_id: archive_asset_id,
+            position: *position,
+        },
+        TransactionType::Dividend { .. } => TransactionType::Dividend {
+            asset_id: archive_asset_id,
+        },
+        TransactionType::Interest { .. } => TransactionType::Interest {
+            asset_id: archive_asset_id,
+        },
+        TransactionType::Tax { transaction_ref } => TransactionType::Tax {
+            transaction_ref: map_ref(transaction_ref)?,
+        },
+        TransactionType::Fee { transaction_ref } => TransactionType::Fee {
+            transaction_ref: map_ref(transaction_ref)?,
+        },
+        TransactionType::Transfer {
+            position,
+            transfer_ref,
+            ..
+        } => TransactionType::Transfer {
+            asset_id: archive_asset_id,
+            position: *position,
+            transfer_ref: map_ref(transfer_ref)?,
+        },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    use chrono::{TimeZone, Utc};
+    use rusqlite::Connection;
+
+    use finql_data::{
+        AssetHandler, CashFlow, Currency, QuoteHandler, TickerUsage, TransactionHandler,
+    };
+    use finql_sqlite::SqliteDB;
+
+    /// Asset with a ticker, a quote per year from 2015 to 2020, bought in 2015 with a fee and
+    /// sold in 2016, and bought again in 2019. Returns asset and ticker id.
+    fn history(db: &mut SqliteDB) -> (usize, usize) {
+        let eur = Currency::from_str("EUR").unwrap();
+        let asset_id = db
+            .insert_asset(&Asset::new(
+                None,
+                "BASF",
+                None,
+                Some("DE000BASF111".to_string()),
+                None,
+            ))
+            .unwrap();
+        let ticker_id = db
+            .insert_ticker(&Ticker {
+                id: None,
+                name: "BAS.DE".to_string(),
+                asset: asset_id,
+                source: "manual".to_string(),
+                priority: 1,
+                currency: eur,
+                factor: 1.0,
+                source_url: None,
+                usage: TickerUsage::Both,
+                last_quote_time: None,
+                source_chain: Vec::new(),
+            })
+            .unwrap();
+        for year in 2015..2021 {
+            db.insert_quote(&Quote {
+                id: None,
+                ticker: ticker_id,
+                price: 50.0 + year as f64 - 2015.0,
+                time: Utc.ymd(year, 6, 30).and_hms(18, 0, 0),
+                volume: Some(1000.0),
+                quality_score: None,
+                source: None,
+                open: None,
+                high: None,
+                low: None,
+                bid: None,
+                ask: None,
+            })
+            .unwrap();
+        }
+        let trade = |position: f64, amount: f64, date: NaiveDate| Transaction {
+            id: None,
+            transaction_type: TransactionType::Asset { asset_id, position },
+            cash_flow: CashFlow::new(amount, eur, date),
+            note: None,
+            execution_meta: None,
+            recorded_at: None,
+        };
+        let buy = db
+            .insert_transaction(&trade(10.0, -500.0, NaiveDate::from_ymd(2015, 7, 1)))
+            .unwrap();
+        db.insert_transaction(&Transaction {
+            id: None,
+            transaction_type: TransactionType::Fee {
+                transaction_ref: Some(buy),
+            },
+            cash_flow: CashFlow::new(-5.0, eur, NaiveDate::from_ymd(2015, 7, 1)),
+            note: Some("order fee".to_string()),
+            execution_meta: None,
+            recorded_at: None,
+        })
+        .unwrap();
+        let sell = db
+            .insert_transaction(&trade(-10.0, 510.0, NaiveDate::from_ymd(2016, 7, 1)))
+            .unwrap();
+        db.insert_lot_selection(
+            sell,
+            &[LotSelection {
+                buy_transaction_id: buy,
+                quantity: 10.0,
+            }],
+        )
+        .unwrap();
+        db.insert_transaction(&trade(5.0, -270.0, NaiveDate::from_ymd(2019, 7, 1)))
+            .unwrap();
+        (asset_id, ticker_id)
+    }
+
+    #[test]
+    fn archive_closed_position() {
+        let conn = Connection::open(":memory:").unwrap();
+        let mut db = SqliteDB::new(&conn);
+        db.init().unwrap();
+        let archive_conn = Connection::open(":memory:").unwrap();
+        let mut archive = SqliteDB::new(&archive_conn);
+        archive.init().unwrap();
+        let (asset_id, ticker_id) = history(&mut db);
+
+        let outcome = archive_asset_history(
+            &mut db,
+            asset_id,
+            NaiveDate::from_ymd(2017, 1, 1),
+            &mut archive,
+        )
+        .unwrap();
+        assert_eq!(outcome.quote_ids.len(), 2);
+        assert_eq!(outcome.transaction_ids.len(), 3);
+        assert_eq!(outcome.digest.lot_selections, 1);
+
+        // only the recent history is kept
+        assert_eq!(db.get_all_quotes_for_ticker(ticker_id).unwrap().len(), 4);
+        let kept = db.get_all_transactions().unwrap();
+        assert_eq!(kept.len(), 1);
+        assert_eq!(kept[0].cash_flow.date, NaiveDate::from_ymd(2019, 7, 1));
+        assert_eq!(db.get_asset_by_id(asset_id).unwrap().name, "BASF");
+
+        // the archive contains the old history with its references intact
+        let archive_ticker = archive
+            .get_all_ticker_for_asset(outcome.archive_asset_id)
+            .unwrap();
+        assert_eq!(archive_ticker.len(), 1);
+        let quotes = archive
+            .get_all_quotes_for_ticker(archive_ticker[0].get_id().unwrap())
+            .unwrap();
+        assert_eq!(quotes.len(), 2);
+        assert_fuzzy_eq!(quotes[1].price, 51.0, 1e-10);
+        let transactions = archive.get_all_transactions().unwrap();
+        assert_eq!(transactions.len(), 3);
+        let buy_id = transactions
+            .iter()
+            .find(|t| t.cash_flow.amount.amount == -500.0)
+            .unwrap()
+            .id;
+        assert!(transactions.iter().any(|t| matches!(
+            t.transaction_type,
+            TransactionType::Fee { transaction_ref } if transaction_ref == buy_id
+        )));
+        let sell_id = transactions
+            .iter()
+            .find(|t| t.cash_flow.amount.amount == 510.0)
+            .unwrap()
+            .get_id()
+            .unwrap();
+        let lots = archive.get_lot_selection(sell_id).unwrap();
+        assert_eq!(lots.len(), 1);
+        assert_eq!(Some(lots[0].buy_transaction_id), buy_id);
+    }
+
+    #[test]
+    fn archive_is_aborted_on_failure() {
+        let conn = Connection::open(":memory:").unwrap();
+        let mut db = SqliteDB::new(&conn);
+        db.init().unwrap();
+        let archive_conn = Connection::open(":memory:").unwrap();
+        let mut archive = SqliteDB::new(&archive_conn);
+        archive.init().unwrap();
+        let (asset_id, ticker_id) = history(&mut db);
+
+        // the position is still open at the end of 2019
+        let result = archive_asset_history(
+            &mut db,
+            asset_id,
+            NaiveDate::from_ymd(2020, 1, 1),
+            &mut archive,
+        );
+        assert!(matches!(result, Err(ArchiveError::PositionOpen(_))));
+        assert!(archive.get_all_assets().unwrap().is_empty());
+
+        // quotes already archived can't be copied again, nothing is deleted
+        let before = NaiveDate::from_ymd(2017, 1, 1);
+        let quotes = db.get_all_quotes_for_ticker(ticker_id).unwrap();
+        let archive_asset = archive
+            .insert_asset_if_new(&db.get_asset_by_id(asset_id).unwrap(), false)
+            .unwrap();
+        let mut ticker = db.get_ticker_by_id(ticker_id).unwrap();
+        ticker.id = None;
+        ticker.asset = archive_asset;
+        let archive_ticker = archive.insert_ticker(&ticker).unwrap();
+        archive
+            .insert_quote(&Quote {
+                id: None,
+                ticker: archive_ticker,
+                ..quotes[0].clone()
+            })
+            .unwrap();
+        let result = archive_asset_history(&mut db, asset_id, before, &mut archive);
+        assert!(matches!(result, Err(ArchiveError::DBError(_))));
+        assert_eq!(db.get_all_quotes_for_ticker(ticker_id).unwrap().len(), 6);
+        assert_eq!(db.get_all_transactions().unwrap().len(), 4);
+    }
+
+    #[test]
+    fn digest_detects_changes() {
+        let eur = Currency::from_str("EUR").unwrap();
+        let transaction = Transaction {
+            id: Some(1),
+            transaction_type: TransactionType::Cash,
+            cash_flow: CashFlow::new(100.0, eur, NaiveDate::from_ymd(2020, 1, 2)),
+            note: None,
+            execution_meta: None,
+            recorded_at: None,
+        };
+        let digest = HistoryDigest::new(&[], std::slice::from_ref(&transaction), &[]).unwrap();
+        // ids are ignored
+        let copy = Transaction {
+            id: Some(7),
+            ..transaction.clone()
+        };
+        let copy_digest = HistoryDigest::new(&[], &[copy], &[]).unwrap();
+        assert_eq!(digest.difference(&copy_digest), None);
+
+        let changed = Transaction {
+            cash_flow: CashFlow::new(100.01, eur, NaiveDate::from_ymd(2020, 1, 2)),
+            ..transaction
+        };
+        let changed_digest = HistoryDigest::new(&[], &[changed], &[]).unwrap();
+        assert_eq!(changed_digest.transactions, 1);
+        assert_eq!(
+            digest.difference(&changed_digest),
+            Some("checksums differ".to_string())
+        );
+        let empty = HistoryDigest::new(&[], &[], &[]).unwrap();
+        assert_eq!(
+            digest.difference(&empty),
+            Some("0 transactions instead of 1".to_string())
+        );
+    }
+}
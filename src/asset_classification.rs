@@ -0,0 +1,585 @@
+//! Batch classification of assets via an external mapping file.
+//!
+//! A mapping file assigns asset class, sector, country and currency exposure to assets
+//! identified by ISIN or, if no ISIN is given, by WKN. It is either given as CSV data with the
+//! columns of `CLASSIFICATION_CSV_HEADER` and a header line, where the currency exposure is
+//! written as e.g. `USD:0.6;EUR:0.4`, or as a JSON array of `ClassificationRecord`s, where the
+//! currency exposure is an object of weights by currency code. Empty fields leave the
+//! respective classification of an asset untouched.
+//!
+//! Mapping files can be created from the current classification of all assets by
+//! `export_classification`, edited and applied again by `apply_classification_csv`.
+
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt;
+use std::io::{Read, Write};
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+use finql_data::{Asset, AssetHandler, AssetIndex, Currency, CurrencyExposure, DataError};
+
+/// Column names of the header line of CSV mapping files
+pub const CLASSIFICATION_CSV_HEADER: [&str; 6] = [
+    "isin",
+    "wkn",
+    "asset_class",
+    "sector",
+    "country",
+    "currency_exposure",
+];
+
+/// Error related to the classification of assets
+#[derive(Debug)]
+pub enum ClassificationError {
+    DBError(DataError),
+    /// Reading or writing CSV data failed
+    CsvError(csv::Error),
+    /// Reading or writing JSON data failed
+    JsonError(serde_json::Error),
+    /// The mapping file contains invalid data
+    InvalidMapping(String),
+}
+
+impl fmt::Display for ClassificationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::DBError(_) => write!(f, "database error"),
+            Self::CsvError(_) => write!(f, "invalid CSV data"),
+            Self::JsonError(_) => write!(f, "invalid JSON data"),
+            Self::InvalidMapping(err) => write!(f, "invalid classification mapping: {}", err),
+        }
+    }
+}
+
+impl Error for ClassificationError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::DBError(err) => Some(err),
+            Self::CsvError(err) => Some(err),
+            Self::JsonError(err) => Some(err),
+            Self::InvalidMapping(_) => None,
+        }
+    }
+}
+
+impl From<DataError> for ClassificationError {
+    fn from(error: DataError) -> Self {
+        Self::DBError(error)
+    }
+}
+
+impl From<csv::Error> for ClassificationError {
+    fn from(error: csv::Error) -> Self {
+        Self::CsvError(error)
+    }
+}
+
+impl From<serde_json::Error> for ClassificationError {
+    fn from(error: serde_json::Error) -> Self {
+        Self::JsonError(error)
+    }
+}
+
+/// Format of a mapping file
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ClassificationFormat {
+    Csv,
+    Json,
+}
+
+/// Options of applying a mapping file
+#[derive(Debug, Clone)]
+pub struct ClassificationOptions {
+    pub format: ClassificationFormat,
+    /// Overwrite existing classifications which differ from the mapping file. Otherwise,
+    /// existing values are kept and reported as conflicts.
+    pub force: bool,
+}
+
+impl Default for ClassificationOptions {
+    fn default() -> ClassificationOptions {
+        ClassificationOptions {
+            format: ClassificationFormat::Csv,
+            force: false,
+        }
+    }
+}
+
+/// Classification of a single asset as given by a mapping file
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ClassificationRecord {
+    #[serde(default)]
+    pub isin: Option<String>,
+    #[serde(default)]
+    pub wkn: Option<String>,
+    #[serde(default)]
+    pub asset_class: Option<String>,
+    #[serde(default)]
+    pub sector: Option<String>,
+    #[serde(default)]
+    pub country: Option<String>,
+    /// Weights by currency code, which must add up to 1
+    #[serde(default)]
+    pub currency_exposure: Option<BTreeMap<String, f64>>,
+}
+
+impl ClassificationRecord {
+    /// Identifier the record is matched by, i.e. the ISIN or, if not given, the WKN
+    pub fn identifier(&self) -> Option<&str> {
+        self.isin.as_deref().or(self.wkn.as_deref())
+    }
+
+    fn find<'a>(&self, assets: &'a AssetIndex) -> Option<&'a Asset> {
+        match (&self.isin, &self.wkn) {
+            (Some(isin), _) => assets.get_by_isin(isin),
+            (None, Some(wkn)) => assets.get_by_wkn(wkn),
+            (None, None) => None,
+        }
+    }
+
+    /// Currency exposure of the given asset as specified by the record, if any
+    fn exposure(&self, asset_id: usize) -> Result<Option<CurrencyExposure>, ClassificationError> {
+        let weights = match &self.currency_exposure {
+            Some(weights) => weights,
+            None => return Ok(None),
+        };
+        let mut exposure = CurrencyExposure {
+            asset_id,
+            weights: Vec::with_capacity(weights.len()),
+        };
+        for (currency, weight) in weights {
+            let currency = Currency::from_str(currency).map_err(|_| {
+                ClassificationError::InvalidMapping(format!("invalid currency '{}'", currency))
+            })?;
+            exposure.weights.push((currency, *weight));
+        }
+        exposure
+            .validate()
+            .map_err(|e| ClassificationError::InvalidMapping(e.to_string()))?;
+        Ok(Some(exposure))
+    }
+}
+
+/// Existing classification of an asset which differs from the mapping file
+#[derive(Debug, Clone, PartialEq)]
+pub struct ClassificationConflict {
+    pub asset_id: usize,
+    /// Name of the conflicting field, e.g. `sector`
+    pub field: String,
+    pub existing: String,
+    pub requested: String,
+    /// Whether the existing value has been overwritten
+    pub overwritten: bool,
+}
+
+/// Result of applying a mapping file
+#[derive(Debug, Clone, Default)]
+pub struct ClassificationReport {
+    /// Ids of all assets whose classification has been changed
+    pub updated: Vec<usize>,
+    /// Identifiers of records that match no asset, in order of input
+    pub unmatched: Vec<String>,
+    pub conflicts: Vec<ClassificationConflict>,
+}
+
+fn empty_to_none(value: Option<&str>) -> Option<String> {
+    value
+        .filter(|value| !value.is_empty())
+        .map(|value| value.to_string())
+}
+
+/// Parse a currency exposure written as e.g. `USD:0.6;EUR:0.4`
+fn parse_exposure(exposure: &str) -> Result<BTreeMap<String, f64>, ClassificationError> {
+    let mut weights = BTreeMap::new();
+    for entry in exposure.split(';').map(str::trim).filter(|e| !e.is_empty()) {
+        let (currency, weight) = entry
+            .split_once(':')
+            .and_then(|(currency, weight)| Some((currency.trim(), weight.trim().parse().ok()?)))
+            .ok_or_else(|| {
+                ClassificationError::InvalidMapping(format!("invalid exposure '{}'", entry))
+            })?;
+        weights.insert(currency.to_string(), weight);
+    }
+    Ok(weights)
+}
+
+/// Write currency weights as e.g. `USD:0.6;EUR:0.4`
+fn format_weights<'a, C, I>(weights: I) -> String
+where
+    C: fmt::Display + 'a,
+    I: Iterator<Item = (C, &'a f64)>,
+{
+    weights
+        .map(|(currency, weight)| format!("{}:{}", currency, weight))
+        .collect::<Vec<_>>()
+        .join(";")
+}
+
+fn format_exposure(exposure: &CurrencyExposure) -> String {
+    format_weights(
+        exposure
+            .weights
+            .iter()
+            .map(|(currency, weight)| (currency, weight)),
+    )
+}
+
+/// Read all records of a CSV mapping file
+fn read_csv_records<R: Read>(reader: R) -> Result<Vec<ClassificationRecord>, ClassificationError> {
+    let mut reader = csv::ReaderBuilder::new()
+        .has_headers(true)
+        .trim(csv::Trim::All)
+        .from_reader(reader);
+    let mut records = Vec::new();
+    for (line, row) in reader.records().enumerate() {
+        let row = row?;
+        if row.len() != CLASSIFICATION_CSV_HEADER.len() {
+            return Err(ClassificationError::InvalidMapping(format!(
+                "wrong number of fields in line {}",
+                line + 2
+            )));
+        }
+        let currency_exposure = match row.get(5).filter(|e| !e.is_empty()) {
+            Some(exposure) => Some(parse_exposure(exposure)?),
+            None => None,
+        };
+        records.push(ClassificationRecord {
+            isin: empty_to_none(row.get(0)),
+            wkn: empty_to_none(row.get(1)),
+            asset_class: empty_to_none(row.get(2)),
+            sector: empty_to_none(row.get(3)),
+            country: empty_to_none(row.get(4)),
+            currency_exposure,
+        });
+    }
+    Ok(records)
+}
+
+/// Set a field to the requested value, unless it holds a different value already and
+/// overwriting is not forced. Returns true if the field has been changed.
+fn set_field(
+    field: &mut Option<String>,
+    requested: &Option<String>,
+    name: &str,
+    asset_id: usize,
+    force: bool,
+    conflicts: &mut Vec<ClassificationConflict>,
+) -> bool {
+    let requested = match requested {
+        Some(requested) => requested,
+        None => return false,
+    };
+    match field {
+        Some(existing) if existing == requested => false,
+        Some(existing) => {
+            conflicts.push(ClassificationConflict {
+                asset_id,
+                field: name.to_string(),
+                existing: existing.clone(),
+                requested: requested.clone(),
+                overwritten: force,
+            });
+            if force {
+                *field = Some(requested.clone());
+            }
+            force
+        }
+        None => {
+            *field = Some(requested.clone());
+            true
+        }
+    }
+}
+
+/// Apply the classification given by a mapping file in CSV or JSON format, as chosen by the
+/// options, to all assets matching by ISIN or WKN. All changes are stored within a single
+/// database transaction after the whole mapping file has been read and validated. Records
+/// not matching any asset and classifications conflicting with existing ones are listed in
+/// the report; conflicting values are only overwritten if `force` is set.
+pub fn apply_classification_csv<R: Read>(
+    db: &mut dyn AssetHandler,
+    reader: R,
+    options: &ClassificationOptions,
+) -> Result<ClassificationReport, ClassificationError> {
+    let records = match options.format {
+        ClassificationFormat::Csv => read_csv_records(reader)?,
+        ClassificationFormat::Json => serde_json::from_reader(reader)?,
+    };
+    let assets = AssetIndex::build(db)?;
+    let mut report = ClassificationReport::default();
+    // changed assets and exposures by asset id
+    let mut changed: BTreeMap<usize, Asset> = BTreeMap::new();
+    let mut exposures: BTreeMap<usize, CurrencyExposure> = BTreeMap::new();
+    for record in &records {
+        let identifier = record.identifier().ok_or_else(|| {
+            ClassificationError::InvalidMapping("record without ISIN or WKN".to_string())
+        })?;
+        let (id, asset) = match record.find(&assets).and_then(|a| Some((a.id?, a))) {
+            Some(found) => found,
+            None => {
+                report.unmatched.push(identifier.to_string());
+                continue;
+            }
+        };
+        let mut asset = changed.get(&id).unwrap_or(asset).clone();
+        let force = options.force;
+        let conflicts = &mut report.conflicts;
+        let mut updated = set_field(
+            &mut asset.asset_class,
+            &record.asset_class,
+            "asset_class",
+            id,
+            force,
+            conflicts,
+        );
+        updated |= set_field(
+            &mut asset.sector,
+            &record.sector,
+            "sector",
+            id,
+            force,
+            conflicts,
+        );
+        updated |= set_field(
+            &mut asset.country,
+            &record.country,
+            "country",
+            id,
+            force,
+            conflicts,
+        );
+        if updated {
+            changed.insert(id, asset);
+        }
+        if let Some(exposure) = record.exposure(id)? {
+            let existing = match exposures.get(&id) {
+                Some(pending) => Some(pending.clone()),
+                None => db.get_currency_exposure(id)?,
+            };
+            match existing {
+                Some(existing) if existing == exposure => {}
+                Some(existing) => {
+                    report.conflicts.push(ClassificationConflict {
+                        asset_id: id,
+                        field: "currency_exposure".to_string(),
+                        existing: format_exposure(&existing),
+                        requested: format_exposure(&exposure),
+                        overwritten: force,
+                    });
+                    if force {
+                        exposures.insert(id, exposure);
+                    }
+                }
+                None => {
+                    exposures.insert(id, exposure);
+                }
+            }
+        }
+    }
+    let changed: Vec<Asset> = changed.into_values().collect();
+    let exposures: Vec<CurrencyExposure> = exposures.into_values().collect();
+    db.update_assets(&changed, &exposures)?;
+    report.updated = changed
+        .iter()
+        .filter_map(|asset| asset.id)
+        .chain(exposures.iter().map(|exposure| exposure.asset_id))
+        .collect();
+    report.updated.sort_unstable();
+    report.updated.dedup();
+    Ok(report)
+}
+
+/// Write the classification of all assets having an ISIN or WKN as mapping file in the given
+/// format, ordered by asset name. Returns the number of assets written.
+pub fn export_classification<W: Write>(
+    db: &mut dyn AssetHandler,
+    writer: W,
+    format: ClassificationFormat,
+) -> Result<usize, ClassificationError> {
+    let mut records = Vec::new();
+    for asset in db.get_all_assets()? {
+        let id = match asset.id {
+            Some(id) if asset.isin.is_some() || asset.wkn.is_some() => id,
+            _ => continue,
+        };
+        let currency_exposure = db.get_currency_exposure(id)?.map(|exposure| {
+            exposure
+                .weights
+                .iter()
+                .map(|(currency, weight)| (currency.to_string(), *weight))
+                .collect()
+        });
+        records.push(ClassificationRecord {
+            isin: asset.isin,
+            wkn: asset.wkn,
+            asset_class: asset.asset_class,
+            sector: asset.sector,
+            country: asset.country,
+            currency_exposure,
+        });
+    }
+    match format {
+        ClassificationFormat::Csv => {
+            let mut writer = csv::Writer::from_writer(writer);
+            writer.write_record(CLASSIFICATION_CSV_HEADER)?;
+            for record in &records {
+                let exposure = record
+                    .currency_exposure
+                    .as_ref()
+                    .map(|weights| format_weights(weights.iter()))
+                    .unwrap_or_default();
+                let field = |value: &Option<String>| value.clone().unwrap_or_default();
+                writer.write_record([
+                    field(&record.isin),
+                    field(&record.wkn),
+                    field(&record.asset_class),
+                    field(&record.sector),
+                    field(&record.country),
+                    exposure,
+                ])?;
+            }
+            writer.flush().map_err(csv::Error::from)?;
+        }
+        ClassificationFormat::Json => serde_json::to_writer_pretty(writer, &records)?,
+    }
+    Ok(records.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use finql_data::QuoteHandler;
+    use finql_sqlite::SqliteDB;
+    use rusqlite::Connection;
+
+    fn currency(code: &str) -> Currency {
+        Currency::from_str(code).unwrap()
+    }
+
+    fn insert_assets(db: &mut dyn QuoteHandler) -> (usize, usize) {
+        let mut basf = Asset::new(
+            None,
+            "BASF SE",
+            Some("BASF11".to_string()),
+            Some("DE000BASF111".to_string()),
+            None,
+        );
+        basf.sector = Some("chemicals".to_string());
+        let basf = db.insert_asset(&basf).unwrap();
+        let world = Asset::new(None, "MSCI World", Some("A0RPWH".to_string()), None, None);
+        let world = db.insert_asset(&world).unwrap();
+        (basf, world)
+    }
+
+    #[test]
+    fn apply_csv_mapping() {
+        let conn = Connection::open(":memory:").unwrap();
+        let mut db = SqliteDB::new(&conn);
+        db.init().unwrap();
+        let (basf, world) = insert_assets(&mut db);
+
+        let mapping = "isin,wkn,asset_class,sector,country,currency_exposure
+            DE000BASF111,,equity,materials,DE,
+            ,A0RPWH,equity,,,USD:0.7;EUR:0.3
+            US0378331005,,equity,technology,US,\n";
+        let report =
+            apply_classification_csv(&mut db, mapping.as_bytes(), &Default::default()).unwrap();
+        assert_eq!(report.updated, vec![basf, world]);
+        assert_eq!(report.unmatched, vec!["US0378331005".to_string()]);
+        assert_eq!(report.conflicts.len(), 1);
+        assert_eq!(report.conflicts[0].field, "sector");
+        assert_eq!(report.conflicts[0].existing, "chemicals");
+        assert!(!report.conflicts[0].overwritten);
+
+        let asset = db.get_asset_by_id(basf).unwrap();
+        assert_eq!(asset.asset_class.as_deref(), Some("equity"));
+        assert_eq!(asset.sector.as_deref(), Some("chemicals"));
+        assert_eq!(asset.country.as_deref(), Some("DE"));
+        let exposure = db.get_currency_exposure(world).unwrap().unwrap();
+        assert_eq!(
+            exposure.weights,
+            vec![(currency("EUR"), 0.3), (currency("USD"), 0.7)]
+        );
+
+        let options = ClassificationOptions {
+            force: true,
+            ..Default::default()
+        };
+        let report = apply_classification_csv(&mut db, mapping.as_bytes(), &options).unwrap();
+        assert_eq!(report.updated, vec![basf]);
+        assert!(report.conflicts[0].overwritten);
+        let asset = db.get_asset_by_id(basf).unwrap();
+        assert_eq!(asset.sector.as_deref(), Some("materials"));
+    }
+
+    #[test]
+    fn invalid_mapping_changes_nothing() {
+        let conn = Connection::open(":memory:").unwrap();
+        let mut db = SqliteDB::new(&conn);
+        db.init().unwrap();
+        let (basf, world) = insert_assets(&mut db);
+
+        let mapping = r#"[
+            {"isin": "DE000BASF111", "country": "DE"},
+            {"wkn": "A0RPWH", "currency_exposure": {"USD": 0.7, "EUR": 0.2}}
+        ]"#;
+        let options = ClassificationOptions {
+            format: ClassificationFormat::Json,
+            force: false,
+        };
+        assert!(apply_classification_csv(&mut db, mapping.as_bytes(), &options).is_err());
+        assert_eq!(db.get_asset_by_id(basf).unwrap().country, None);
+        assert_eq!(db.get_currency_exposure(world).unwrap(), None);
+    }
+
+    #[test]
+    fn export_round_trip() {
+        let conn = Connection::open(":memory:").unwrap();
+        let mut db = SqliteDB::new(&conn);
+        db.init().unwrap();
+        let (basf, world) = insert_assets(&mut db);
+        let mapping = r#"[
+            {"isin": "DE000BASF111", "asset_class": "equity", "country": "DE"},
+            {"wkn": "A0RPWH", "currency_exposure": {"USD": 0.7, "JPY": 0.1, "EUR": 0.2}}
+        ]"#;
+        let options = ClassificationOptions {
+            format: ClassificationFormat::Json,
+            force: false,
+        };
+        apply_classification_csv(&mut db, mapping.as_bytes(), &options).unwrap();
+
+        for format in [ClassificationFormat::Csv, ClassificationFormat::Json] {
+            let mut exported = Vec::new();
+            assert_eq!(
+                export_classification(&mut db, &mut exported, format).unwrap(),
+                2
+            );
+
+            let conn = Connection::open(":memory:").unwrap();
+            let mut copy = SqliteDB::new(&conn);
+            copy.init().unwrap();
+            let (copy_basf, copy_world) = insert_assets(&mut copy);
+            let options = ClassificationOptions {
+                format,
+                force: true,
+            };
+            let report = apply_classification_csv(&mut copy, &exported[..], &options).unwrap();
+            assert!(report.unmatched.is_empty());
+            let asset = copy.get_asset_by_id(copy_basf).unwrap();
+            let original = db.get_asset_by_id(basf).unwrap();
+            assert_eq!(asset.asset_class, original.asset_class);
+            assert_eq!(asset.sector, original.sector);
+            assert_eq!(asset.country, original.country);
+            assert_eq!(
+                copy.get_currency_exposure(copy_world)
+                    .unwrap()
+                    .unwrap()
+                    .weights,
+                db.get_currency_exposure(world).unwrap().unwrap().weights
+            );
+        }
+    }
+}
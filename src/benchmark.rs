@@ -0,0 +1,343 @@
+//! Comparison of the returns of a holding with the returns of a benchmark, e.g. an index.
+//!
+//! Returns are measured between consecutive dates in the currency the holding is valued in.
+//! Holdings hedged to a currency, see `Asset::hedged_to`, are compared with the benchmark's
+//! returns hedged to the same currency as approximated by `returns::hedged_returns`. Otherwise,
+//! the benchmark is converted into the holding's currency, i.e. its returns include the
+//! exchange rate returns.
+
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::Serialize;
+
+use finql_data::{Currency, CurrencyConverter, DataError, QuoteHandler};
+
+use crate::analytics_error::AnalyticsError;
+use crate::returns::{hedged_returns, simple_returns, total_return};
+
+/// Way the benchmark's returns are converted into the currency of the holding
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub enum CurrencyMode {
+    /// Benchmark prices are converted at the exchange rate of each date
+    Unhedged,
+    /// Exchange rate returns of the benchmark's currency are hedged, see `hedged_returns`
+    Hedged,
+}
+
+/// Returns of a holding and a benchmark over the same consecutive periods
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct BenchmarkComparison {
+    /// Currency the returns are measured in
+    pub currency: Currency,
+    pub mode: CurrencyMode,
+    pub holding_returns: Vec<f64>,
+    pub benchmark_returns: Vec<f64>,
+}
+
+impl BenchmarkComparison {
+    /// Returns of the holding in excess of the benchmark's returns per period
+    pub fn active_returns(&self) -> Vec<f64> {
+        self.holding_returns
+            .iter()
+            .zip(&self.benchmark_returns)
+            .map(|(holding, benchmark)| holding - benchmark)
+            .collect()
+    }
+
+    /// Total return of the holding in excess of the benchmark's total return
+    pub fn total_active_return(&self) -> f64 {
+        total_return(&self.holding_returns) - total_return(&self.benchmark_returns)
+    }
+
+    /// Compare prices of a holding in `currency` with prices of a benchmark in its local
+    /// currency at the same dates. The exchange rates are the prices of one unit of the local
+    /// currency in `currency` at these dates.
+    pub fn from_prices(
+        currency: Currency,
+        mode: CurrencyMode,
+        holding_prices: &[f64],
+        benchmark_prices: &[f64],
+        fx_rates: &[f64],
+    ) -> Result<BenchmarkComparison, AnalyticsError> {
+        if benchmark_prices.len() != holding_prices.len() || fx_rates.len() != holding_prices.len()
+        {
+            return Err(AnalyticsError::InvalidInput(format!(
+                "{} holding prices, but {} benchmark prices and {} exchange rates given",
+                holding_prices.len(),
+                benchmark_prices.len(),
+                fx_rates.len()
+            )));
+        }
+        let benchmark_returns = match mode {
+            CurrencyMode::Hedged => {
+                let local_returns: Vec<f64> =
+                    simple_returns(benchmark_prices.iter().copied()).collect();
+                let fx_returns: Vec<f64> = simple_returns(fx_rates.iter().copied()).collect();
+                hedged_returns(&local_returns, &fx_returns)?
+            }
+            CurrencyMode::Unhedged => simple_returns(
+                benchmark_prices
+                    .iter()
+                    .zip(fx_rates)
+                    .map(|(price, fx_rate)| price * fx_rate),
+            )
+            .collect(),
+        };
+        Ok(BenchmarkComparison {
+            currency,
+            mode,
+            holding_returns: simple_returns(holding_prices.iter().copied()).collect(),
+            benchmark_returns,
+        })
+    }
+}
+
+/// Prices of an asset at the end of each date, which must all be given in the same currency
+fn prices_at(
+    db: &mut dyn QuoteHandler,
+    asset_id: usize,
+    dates: &[NaiveDate],
+    currency_converter: &mut dyn CurrencyConverter,
+) -> Result<(Vec<f64>, Currency), DataError> {
+    let mut prices = Vec::with_capacity(dates.len());
+    let mut currency = None;
+    for date in dates {
+        let (quote, quote_currency) =
+            db.get_best_quote_before(asset_id, end_of_day(*date), currency_converter)?;
+        match currency {
+            Some(currency) if currency != quote_currency => {
+                return Err(DataError::CurrencyMismatch(format!(
+                    "prices of asset {} are given in {} and {}, a reference currency is required",
+                    asset_id, currency, quote_currency
+                )));
+            }
+            _ => currency = Some(quote_currency),
+        }
+        prices.push(quote.price);
+    }
+    let currency = currency.ok_or_else(|| DataError::NotFound("no dates given".to_string()))?;
+    Ok((prices, currency))
+}
+
+fn end_of_day(date: NaiveDate) -> DateTime<Utc> {
+    DateTime::<Utc>::from_utc(date.and_hms(23, 59, 59), Utc)
+}
+
+/// Compare the price returns of a holding with those of a benchmark between consecutive dates,
+/// which must be in ascending order. Prices are the best quotes on or before the end of each
+/// date. If the holding is hedged to a currency, the benchmark's returns are hedged to this
+/// currency, which must be the currency the holding is valued in.
+pub fn compare_with_benchmark(
+    db: &mut dyn QuoteHandler,
+    holding_id: usize,
+    benchmark_id: usize,
+    dates: &[NaiveDate],
+    currency_converter: &mut dyn CurrencyConverter,
+) -> Result<BenchmarkComparison, DataError> {
+    if dates.windows(2).any(|period| period[1] <= period[0]) {
+        return Err(DataError::InvalidData(
+            "dates must be in ascending order".to_string(),
+        ));
+    }
+    let holding = db.get_asset_by_id(holding_id)?;
+    let (holding_prices, currency) = prices_at(db, holding_id, dates, currency_converter)?;
+    if let Some(hedged_to) = holding.hedged_to {
+        if hedged_to != currency {
+            return Err(DataError::CurrencyMismatch(format!(
+                "asset {} is hedged to {}, but valued in {}",
+                holding_id, hedged_to, currency
+            )));
+        }
+    }
+    let (benchmark_prices, local_currency) =
+        prices_at(db, benchmark_id, dates, currency_converter)?;
+    let mut fx_rates = Vec::with_capacity(dates.len());
+    for date in dates {
+        let fx_rate = currency_converter
+            .fx_rate(local_currency, currency, end_of_day(*date))
+            .map_err(|e| {
+                DataError::CurrencyMismatch(format!(
+                    "no exchange rate from {} to {}: {}",
+                    local_currency, currency, e
+                ))
+            })?;
+        fx_rates.push(fx_rate);
+    }
+
+    let mode = match holding.hedged_to {
+        Some(_) => CurrencyMode::Hedged,
+        None => CurrencyMode::Unhedged,
+    };
+    BenchmarkComparison::from_prices(
+        currency,
+        mode,
+        &holding_prices,
+        &benchmark_prices,
+        &fx_rates,
+    )
+    .map_err(|e| DataError::InvalidData(e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    use chrono::TimeZone;
+    use rusqlite::Connection;
+
+    use finql_data::{Asset, AssetHandler, CurrencyError, Quote, Ticker, TickerUsage};
+    use finql_sqlite::SqliteDB;
+
+    fn ymd(month: u32, day: u32) -> NaiveDate {
+        NaiveDate::from_ymd(2021, month, day)
+    }
+
+    /// Price of one USD in EUR, changing from 0.80 in January to 0.84 in February and 0.8232
+    struct UsdEurConverter;
+
+    impl CurrencyConverter for UsdEurConverter {
+        fn fx_rate(
+            &mut self,
+            foreign: Currency,
+            domestic: Currency,
+            time: DateTime<Utc>,
+        ) -> Result<f64, CurrencyError> {
+            if foreign == domestic {
+                return Ok(1.0);
+            }
+            if (foreign.to_string().as_str(), domestic.to_string().as_str()) != ("USD", "EUR") {
+                return Err(CurrencyError::ConversionFailed);
+            }
+            let date = time.naive_utc().date();
+            Ok(if date < ymd(2, 1) {
+                0.8
+            } else if date < ymd(3, 1) {
+                0.84
+            } else {
+                0.8232
+            })
+        }
+    }
+
+    #[test]
+    fn hedged_and_unhedged_benchmark() {
+        let tol = 1e-12;
+        let eur = Currency::from_str("EUR").unwrap();
+        // benchmark returns 2% and -1% in USD, while the USD gains 5% and loses 2% in EUR
+        let index = [100., 102., 100.98];
+        let fx_rates = [0.8, 0.84, 0.8232];
+        let hedged_fund = [50., 51., 50.5];
+        let comparison = BenchmarkComparison::from_prices(
+            eur,
+            CurrencyMode::Hedged,
+            &hedged_fund,
+            &index,
+            &fx_rates,
+        )
+        .unwrap();
+        // same values as in the hand-computed example of `returns::hedged_returns`
+        assert_fuzzy_eq!(comparison.benchmark_returns[0], 0.021, tol);
+        assert_fuzzy_eq!(comparison.benchmark_returns[1], -0.0098, tol);
+        let active = comparison.active_returns();
+        assert_fuzzy_eq!(active[0], 0.02 - 0.021, tol);
+        assert_fuzzy_eq!(active[1], 50.5 / 51. - 1. + 0.0098, tol);
+        assert_fuzzy_eq!(comparison.total_active_return(), 0.01 - 0.0109942, tol);
+
+        // unhedged, the benchmark gains 7.1% and loses 2.98% in EUR
+        let unhedged_fund = [80., 85.68, 83.126736];
+        let comparison = BenchmarkComparison::from_prices(
+            eur,
+            CurrencyMode::Unhedged,
+            &unhedged_fund,
+            &index,
+            &fx_rates,
+        )
+        .unwrap();
+        assert_fuzzy_eq!(comparison.benchmark_returns[0], 0.071, tol);
+        assert_fuzzy_eq!(comparison.benchmark_returns[1], -0.0298, tol);
+        assert_fuzzy_eq!(comparison.total_active_return(), 0.0, tol);
+
+        assert!(BenchmarkComparison::from_prices(
+            eur,
+            CurrencyMode::Hedged,
+            &hedged_fund,
+            &index[..2],
+            &fx_rates,
+        )
+        .is_err());
+    }
+
+    /// Store an asset with a single quote in mid January
+    fn insert_asset(db: &mut SqliteDB, asset: Asset, currency: &str, price: f64) -> usize {
+        let name = asset.name.clone();
+        let asset_id = db.insert_asset(&asset).unwrap();
+        let ticker = db
+            .insert_ticker(&Ticker {
+                id: None,
+                name,
+                asset: asset_id,
+                source: "manual".to_string(),
+                priority: 1,
+                currency: Currency::from_str(currency).unwrap(),
+                factor: 1.0,
+                source_url: None,
+                usage: TickerUsage::Both,
+                last_quote_time: None,
+                source_chain: Vec::new(),
+            })
+            .unwrap();
+        db.insert_quote(&Quote {
+            id: None,
+            ticker,
+            price,
+            time: Utc.ymd(2021, 1, 15).and_hms(18, 0, 0),
+            volume: None,
+            quality_score: None,
+            source: None,
+            open: None,
+            high: None,
+            low: None,
+            bid: None,
+            ask: None,
+        })
+        .unwrap();
+        asset_id
+    }
+
+    #[test]
+    fn compare_with_stored_quotes() {
+        let tol = 1e-12;
+        let conn = Connection::open(":memory:").unwrap();
+        let mut db = SqliteDB::new(&conn);
+        db.init().unwrap();
+        let eur = Currency::from_str("EUR").unwrap();
+        let index = Asset::new(None, "S&P 500", None, None, None);
+        let index = insert_asset(&mut db, index, "USD", 4000.);
+        let hedged = Asset::new(None, "S&P 500 EUR hedged", None, None, None).with_hedged_to(eur);
+        let hedged = insert_asset(&mut db, hedged, "EUR", 50.);
+        let unhedged = Asset::new(None, "S&P 500 EUR", None, None, None);
+        let unhedged = insert_asset(&mut db, unhedged, "EUR", 80.);
+        // unchanged prices while the USD gains 5% in EUR
+        let dates = [ymd(1, 31), ymd(2, 28)];
+
+        let comparison =
+            compare_with_benchmark(&mut db, hedged, index, &dates, &mut UsdEurConverter).unwrap();
+        assert_eq!(comparison.currency, eur);
+        assert_eq!(comparison.mode, CurrencyMode::Hedged);
+        assert_fuzzy_eq!(comparison.benchmark_returns[0], 0.0, tol);
+        let comparison =
+            compare_with_benchmark(&mut db, unhedged, index, &dates, &mut UsdEurConverter).unwrap();
+        assert_eq!(comparison.mode, CurrencyMode::Unhedged);
+        assert_fuzzy_eq!(comparison.benchmark_returns[0], 0.05, tol);
+        assert_fuzzy_eq!(comparison.active_returns()[0], -0.05, tol);
+
+        // the hedge currency must be the currency the holding is valued in
+        let usd = Currency::from_str("USD").unwrap();
+        let asset = db.get_asset_by_id(hedged).unwrap().with_hedged_to(usd);
+        db.update_asset(&asset).unwrap();
+        assert!(matches!(
+            compare_with_benchmark(&mut db, hedged, index, &dates, &mut UsdEurConverter),
+            Err(DataError::CurrencyMismatch(_))
+        ));
+    }
+}
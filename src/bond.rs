@@ -9,7 +9,9 @@ use std::fmt;
 
 use finql_data::currency::Currency;
 use finql_data::cash_flow::CashFlow;
+use finql_data::InstrumentTerms;
 
+use crate::calendar::Calendar;
 use crate::day_adjust::DayAdjust;
 use crate::day_count_conv::{DayCountConv, DayCountConvError};
 use crate::fixed_income::FixedIncome;
@@ -24,6 +26,9 @@ pub enum BondError {
     DiscountingFailure(DiscountError),
     MissingCalendar,
     DayCountError(DayCountConvError),
+    /// The terms do not allow the requested calculation, e.g. a perpetuity growing faster
+    /// than it is discounted
+    InvalidTerms(String),
 }
 
 impl fmt::Display for BondError {
@@ -34,6 +39,7 @@ impl fmt::Display for BondError {
                 write!(f, "invalid day count convention in this context")
             }
             BondError::DiscountingFailure(_) => write!(f, "discounting cash flows failed"),
+            BondError::InvalidTerms(err) => write!(f, "invalid bond terms: {}", err),
         }
     }
 }
@@ -87,6 +93,15 @@ pub struct Bond {
     /// Smallest purchasable unit
     pub denomination: u32,
     volume: Option<f64>,
+    /// Number of business days coupons and redemption are paid after the end of the accrual
+    /// period. Without payment lag, the payment dates are adjusted by the business day rule.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    payment_lag: Option<u32>,
+}
+
+impl InstrumentTerms for Bond {
+    const KIND: &'static str = "bond";
+    const VERSION: u32 = 1;
 }
 
 /// Information regarding the issuer of an asset
@@ -137,6 +152,14 @@ impl Coupon {
 }
 
 impl Bond {
+    /// Payment date of a coupon whose accrual period ends at the given date
+    fn payment_date(&self, accrual_end: NaiveDate, cal: &Calendar) -> NaiveDate {
+        match self.payment_lag {
+            Some(lag) => cal.add_business_days(accrual_end, lag as i32),
+            None => self.business_day_rule.adjust_date(accrual_end, cal),
+        }
+    }
+
     /// Calculate first coupon period end date
     fn first_coupon_end(&self, start_date: NaiveDate) -> NaiveDate {
         if self.coupon.coupon_month() <= start_date.month() {
@@ -171,7 +194,7 @@ impl FixedIncome for Bond {
         let amount =
             position * (self.denomination as f64) * self.coupon.rate / 100. * year_fraction;
         let cal = market.get_calendar(&self.calendar)?;
-        let pay_date = self.business_day_rule.adjust_date(end_date, cal);
+        let pay_date = self.payment_date(end_date, cal);
         let cf = CashFlow::new(amount, self.currency, pay_date);
         cfs.push(cf);
         let maturity = self.maturity;
@@ -183,7 +206,7 @@ impl FixedIncome for Bond {
                 .year_fraction(start_date, end_date, start_date)?;
             let amount =
                 position * (self.denomination as f64) * self.coupon.rate / 100. * year_fraction;
-            let pay_date = self.business_day_rule.adjust_date(end_date, cal);
+            let pay_date = self.payment_date(end_date, cal);
             let cf = CashFlow::new(amount, self.currency, pay_date);
             cfs.push(cf);
         }
@@ -191,7 +214,7 @@ impl FixedIncome for Bond {
         let cf = CashFlow::new(
             position * (self.denomination as f64),
             self.currency,
-            self.business_day_rule.adjust_date(maturity, cal),
+            self.payment_date(maturity, cal),
         );
         cfs.push(cf);
 
@@ -249,8 +272,8 @@ mod tests {
             "denomination": 1000
         }"#;
         let bond: Bond = serde_json::from_str(&data).unwrap();
-        let mut conn = Connection::open(":memory:").unwrap();
-        let mut db = SqliteDB{ conn: &mut conn };
+        let conn = Connection::open(":memory:").unwrap();
+        let mut db = SqliteDB::new(&conn);
         db.init().unwrap();
         let market = Market::new(&mut db);
         let cash_flows = bond.rollout_cash_flows(1., &market).unwrap();
@@ -306,8 +329,8 @@ mod tests {
             "denomination": 1000
         }"#;
         let bond: Bond = serde_json::from_str(&data).unwrap();
-        let mut conn = Connection::open(":memory:").unwrap();
-        let mut db = SqliteDB{ conn: &mut conn };
+        let conn = Connection::open(":memory:").unwrap();
+        let mut db = SqliteDB::new(&conn);
         db.init().unwrap();
         let market = Market::new(&mut db);
         let cash_flows = bond.rollout_cash_flows(1., &market).unwrap();
@@ -327,4 +350,90 @@ mod tests {
         assert!(reference_cash_flows[3].fuzzy_cash_flows_cmp_eq(&cash_flows[3], tol));
         assert!(reference_cash_flows[4].fuzzy_cash_flows_cmp_eq(&cash_flows[4], tol));
     }
+
+    #[test]
+    fn cash_flow_rollout_with_payment_lag() {
+        let data = r#"{
+            "bond_type": "bond",
+            "currency": "EUR",
+            "coupon" : {
+                "coupon_type": "fixed",
+                "rate": 5,
+                "coupon_date": "01.04",
+                "period": "6M",
+                "day_count_convention": "act/365"
+            },
+            "business_day_rule": "modified",
+            "calendar": "TARGET",
+            "issue_date": "2019-10-01",
+            "maturity": "2021-10-01",
+            "denomination": 1000,
+            "payment_lag": 2
+        }"#;
+        let bond: Bond = serde_json::from_str(data).unwrap();
+        let conn = Connection::open(":memory:").unwrap();
+        let mut db = SqliteDB::new(&conn);
+        db.init().unwrap();
+        let market = Market::new(&mut db);
+        let cash_flows = bond.rollout_cash_flows(1., &market).unwrap();
+        let dates: Vec<NaiveDate> = cash_flows.iter().map(|cf| cf.date).collect();
+        assert_eq!(
+            dates,
+            vec![
+                NaiveDate::from_ymd(2020, 4, 3),
+                NaiveDate::from_ymd(2020, 10, 5),
+                // Good Friday and Easter Monday
+                NaiveDate::from_ymd(2021, 4, 7),
+                NaiveDate::from_ymd(2021, 10, 5),
+                NaiveDate::from_ymd(2021, 10, 5),
+            ]
+        );
+        // the lag does not change the accrual periods
+        let tol = 1e-11;
+        assert_fuzzy_eq!(cash_flows[0].amount.amount, 0.05 * 1000. * 183. / 365., tol);
+        assert_fuzzy_eq!(cash_flows[2].amount.amount, 0.05 * 1000. * 182. / 365., tol);
+    }
+
+    #[test]
+    fn store_bond_terms() {
+        use finql_data::{get_instrument, set_instrument, Asset, AssetHandler, OptionTerms};
+
+        let data = r#"{
+            "isin": "DE0001102333",
+            "bond_type": "bond",
+            "currency": "EUR",
+            "coupon" : {
+                "coupon_type": "fixed",
+                "rate": 1.75,
+                "coupon_date": "15.02",
+                "period": "1Y",
+                "day_count_convention": "icma"
+            },
+            "business_day_rule": "following",
+            "calendar": "TARGET",
+            "issue_date": "2014-01-10",
+            "maturity": "2024-02-15",
+            "denomination": 100
+        }"#;
+        let bond: Bond = serde_json::from_str(data).unwrap();
+        let conn = Connection::open(":memory:").unwrap();
+        let mut db = SqliteDB::new(&conn);
+        db.init().unwrap();
+        let asset_id = db
+            .insert_asset(&Asset::new(None, "Bund 2024", None, bond.isin.clone(), None))
+            .unwrap();
+        set_instrument(&mut db, asset_id, &bond).unwrap();
+        let stored: Bond = get_instrument(&mut db, asset_id).unwrap().unwrap();
+        assert_eq!(stored.isin, bond.isin);
+        assert!(db.get_option_terms(asset_id).unwrap().is_none());
+        assert!(get_instrument::<OptionTerms, _>(&mut db, asset_id).is_err());
+
+        let market = Market::new(&mut db);
+        let cash_flows = bond.rollout_cash_flows(1., &market).unwrap();
+        let stored_cash_flows = stored.rollout_cash_flows(1., &market).unwrap();
+        assert_eq!(stored_cash_flows.len(), 12);
+        for (cf, stored_cf) in cash_flows.iter().zip(stored_cash_flows.iter()) {
+            assert!(cf.fuzzy_cash_flows_cmp_eq(stored_cf, 1e-11));
+        }
+    }
 }
@@ -14,7 +14,7 @@ use serde::{Deserialize, Serialize};
 use std::collections::BTreeSet;
 
 /// Specifies the nth week of a month
-#[derive(Deserialize, Serialize, Debug, PartialEq)]
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq)]
 pub enum NthWeek {
     First,
     Second,
@@ -148,21 +148,7 @@ impl Calendar {
                 } => {
                     let (first, last) = Self::calc_first_and_last(start, end, first, last);
                     for year in first..last + 1 {
-                        let day = match nth {
-                            NthWeek::First => 1,
-                            NthWeek::Second => 8,
-                            NthWeek::Third => 15,
-                            NthWeek::Fourth => 22,
-                            NthWeek::Last => last_day_of_month(year, *month),
-                        };
-                        let mut date = NaiveDate::from_ymd(year, *month, day);
-                        while date.weekday() != *weekday {
-                            date = match nth {
-                                NthWeek::Last => date.pred(),
-                                _ => date.succ(),
-                            }
-                        }
-                        holidays.insert(date);
+                        holidays.insert(nth_weekday_of_month(year, *month, *weekday, *nth));
                     }
                 }
             }
@@ -191,6 +177,20 @@ impl Calendar {
         date
     }
 
+    /// Move the date by the given number of business days, i.e. to the `days`th next business
+    /// day for positive and the `days`th previous business day for negative `days`.
+    /// The date itself is not counted, even if it is a business day.
+    pub fn add_business_days(&self, mut date: NaiveDate, days: i32) -> NaiveDate {
+        for _ in 0..days.abs() {
+            date = if days < 0 {
+                self.prev_bday(date)
+            } else {
+                self.next_bday(date)
+            };
+        }
+        date
+    }
+
     fn calc_first_and_last(
         start: i32,
         end: i32,
@@ -243,6 +243,25 @@ pub fn last_day_of_month(year: i32, month: u32) -> u32 {
         .day()
 }
 
+/// Calculate the nth (or last) weekday of a given month, e.g. the third Friday
+pub fn nth_weekday_of_month(year: i32, month: u32, weekday: Weekday, nth: NthWeek) -> NaiveDate {
+    let day = match nth {
+        NthWeek::First => 1,
+        NthWeek::Second => 8,
+        NthWeek::Third => 15,
+        NthWeek::Fourth => 22,
+        NthWeek::Last => last_day_of_month(year, month),
+    };
+    let mut date = NaiveDate::from_ymd(year, month, day);
+    while date.weekday() != weekday {
+        date = match nth {
+            NthWeek::Last => date.pred(),
+            _ => date.succ(),
+        }
+    }
+    date
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -0,0 +1,332 @@
+//! Schedules of regular payment dates, e.g. of salaries, savings plans or fund distributions.
+//!
+//! A schedule either rolls out a fixed time period from its start date, e.g. every 21 business
+//! days, or refers to an anchor date within each month, e.g. the month end or the third Friday.
+//! The resulting dates are shifted by a number of business days of a calendar, e.g. to express
+//! payments 3 business days after month end, or otherwise adjusted to business days by a
+//! business day rule.
+
+use std::error::Error;
+use std::fmt;
+
+use chrono::{Datelike, NaiveDate, Weekday};
+
+use finql_data::{CashFlow, Currency};
+
+use crate::calendar::{last_day_of_month, nth_weekday_of_month, Calendar, NthWeek};
+use crate::day_adjust::DayAdjust;
+use crate::time_buckets::Bucket;
+use crate::time_period::TimePeriod;
+
+/// Error related to cash flow schedules
+#[derive(Debug)]
+pub enum ScheduleError {
+    /// The time period of the schedule does not move dates forward
+    NonPositivePeriod(TimePeriod),
+    /// Day of month out of the range from 1 to 31
+    InvalidDay(u32),
+}
+
+impl fmt::Display for ScheduleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NonPositivePeriod(period) => {
+                write!(f, "schedule period {} must be positive", period)
+            }
+            Self::InvalidDay(day) => write!(f, "invalid day of month {}", day),
+        }
+    }
+}
+
+impl Error for ScheduleError {}
+
+/// Reference date within a month
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MonthAnchor {
+    /// First calendar day of the month
+    MonthStart,
+    /// Last calendar day of the month
+    MonthEnd,
+    /// Given day of the month, moved to the last day of months with less days
+    Day(u32),
+    /// Nth weekday of the month, e.g. the third Friday
+    Weekday { nth: NthWeek, weekday: Weekday },
+}
+
+impl MonthAnchor {
+    /// Anchor date in the given month
+    pub fn date(&self, year: i32, month: u32) -> NaiveDate {
+        match self {
+            Self::MonthStart => NaiveDate::from_ymd(year, month, 1),
+            Self::MonthEnd => NaiveDate::from_ymd(year, month, last_day_of_month(year, month)),
+            Self::Day(day) => {
+                NaiveDate::from_ymd(year, month, (*day).min(last_day_of_month(year, month)))
+            }
+            Self::Weekday { nth, weekday } => nth_weekday_of_month(year, month, *weekday, *nth),
+        }
+    }
+}
+
+/// Rule generating the unadjusted dates of a schedule
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ScheduleRule {
+    /// Dates one, two, ... periods after the start date, e.g. `21B` for every 21 business days
+    Period(TimePeriod),
+    /// Anchor date of each month
+    Monthly(MonthAnchor),
+}
+
+/// Schedule of payment dates from `start` to `end`. Each date generated by the rule within
+/// this range is moved by `business_day_offset` business days, or, if the offset is zero,
+/// adjusted by the business day rule. Shifted dates may therefore fall after `end`, e.g. a
+/// payment 3 business days after the last month end.
+#[derive(Debug)]
+pub struct CashFlowSchedule {
+    pub start: NaiveDate,
+    pub end: NaiveDate,
+    pub rule: ScheduleRule,
+    /// Number of business days the payment is made after (or before, if negative) the date
+    /// given by the rule. The date itself is not counted, even if it is a business day.
+    pub business_day_offset: i32,
+    /// Rule to adjust dates which are not shifted by a business day offset
+    pub business_day_rule: DayAdjust,
+}
+
+impl CashFlowSchedule {
+    /// Schedule with dates every `period` after the start date
+    pub fn every(start: NaiveDate, end: NaiveDate, period: TimePeriod) -> CashFlowSchedule {
+        CashFlowSchedule::new(start, end, ScheduleRule::Period(period))
+    }
+
+    /// Schedule with the anchor date of each month
+    pub fn monthly(start: NaiveDate, end: NaiveDate, anchor: MonthAnchor) -> CashFlowSchedule {
+        CashFlowSchedule::new(start, end, ScheduleRule::Monthly(anchor))
+    }
+
+    fn new(start: NaiveDate, end: NaiveDate, rule: ScheduleRule) -> CashFlowSchedule {
+        CashFlowSchedule {
+            start,
+            end,
+            rule,
+            business_day_offset: 0,
+            business_day_rule: DayAdjust::Following,
+        }
+    }
+
+    /// Set the number of business days payments are shifted
+    pub fn business_day_offset(mut self, days: i32) -> CashFlowSchedule {
+        self.business_day_offset = days;
+        self
+    }
+
+    /// Set the rule to adjust unshifted dates to business days
+    pub fn business_day_rule(mut self, rule: DayAdjust) -> CashFlowSchedule {
+        self.business_day_rule = rule;
+        self
+    }
+
+    /// Unadjusted dates generated by the rule from start to end
+    fn rule_dates(&self, cal: &Calendar) -> Result<Vec<NaiveDate>, ScheduleError> {
+        let mut dates = Vec::new();
+        match self.rule {
+            ScheduleRule::Period(period) => {
+                let mut date = self.start;
+                loop {
+                    let next = period.add_to(date, Some(cal));
+                    if next <= date {
+                        return Err(ScheduleError::NonPositivePeriod(period));
+                    }
+                    if next > self.end {
+                        break;
+                    }
+                    dates.push(next);
+                    date = next;
+                }
+            }
+            ScheduleRule::Monthly(anchor) => {
+                if let MonthAnchor::Day(day) = anchor {
+                    if day == 0 || day > 31 {
+                        return Err(ScheduleError::InvalidDay(day));
+                    }
+                }
+                for month in Bucket::Month.bucket_range(self.start, self.end) {
+                    let first_day = month.first_day();
+                    let date = anchor.date(first_day.year(), first_day.month());
+                    if date >= self.start && date <= self.end {
+                        dates.push(date);
+                    }
+                }
+            }
+        }
+        Ok(dates)
+    }
+
+    /// Payment dates of the schedule, shifted or adjusted to business days of the calendar
+    pub fn dates(&self, cal: &Calendar) -> Result<Vec<NaiveDate>, ScheduleError> {
+        Ok(self
+            .rule_dates(cal)?
+            .into_iter()
+            .map(|date| {
+                if self.business_day_offset == 0 {
+                    self.business_day_rule.adjust_date(date, cal)
+                } else {
+                    cal.add_business_days(date, self.business_day_offset)
+                }
+            })
+            .collect())
+    }
+
+    /// Cash flows of a fixed amount at each payment date
+    pub fn cash_flows(
+        &self,
+        amount: f64,
+        currency: Currency,
+        cal: &Calendar,
+    ) -> Result<Vec<CashFlow>, ScheduleError> {
+        Ok(self
+            .dates(cal)?
+            .into_iter()
+            .map(|date| CashFlow::new(amount, currency, date))
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    use crate::market::generate_calendars;
+
+    fn target() -> Calendar {
+        generate_calendars().remove("TARGET").unwrap()
+    }
+
+    fn ymd(year: i32, month: u32, day: u32) -> NaiveDate {
+        NaiveDate::from_ymd(year, month, day)
+    }
+
+    fn year_2019(anchor: MonthAnchor) -> CashFlowSchedule {
+        CashFlowSchedule::monthly(ymd(2019, 1, 1), ymd(2019, 12, 31), anchor)
+    }
+
+    #[test]
+    fn business_days_after_month_end() {
+        let cal = target();
+        let dates = year_2019(MonthAnchor::MonthEnd)
+            .business_day_offset(3)
+            .dates(&cal)
+            .unwrap();
+        let expected = vec![
+            ymd(2019, 2, 5),
+            ymd(2019, 3, 5),
+            ymd(2019, 4, 3),
+            // Labour Day
+            ymd(2019, 5, 6),
+            ymd(2019, 6, 5),
+            ymd(2019, 7, 3),
+            ymd(2019, 8, 5),
+            ymd(2019, 9, 4),
+            ymd(2019, 10, 3),
+            ymd(2019, 11, 5),
+            ymd(2019, 12, 4),
+            // New Year's Day
+            ymd(2020, 1, 6),
+        ];
+        assert_eq!(dates, expected);
+
+        // last business day of each month
+        let dates = year_2019(MonthAnchor::MonthEnd)
+            .business_day_rule(DayAdjust::Preceding)
+            .dates(&cal)
+            .unwrap();
+        assert_eq!(dates[2], ymd(2019, 3, 29));
+        assert_eq!(dates[7], ymd(2019, 8, 30));
+        assert_eq!(dates[11], ymd(2019, 12, 31));
+    }
+
+    #[test]
+    fn third_friday_and_consecutive_holidays() {
+        let cal = target();
+        let third_friday = MonthAnchor::Weekday {
+            nth: NthWeek::Third,
+            weekday: Weekday::Fri,
+        };
+        let dates = year_2019(third_friday).dates(&cal).unwrap();
+        let days: Vec<(u32, u32)> = dates.iter().map(|d| (d.month(), d.day())).collect();
+        assert_eq!(
+            days,
+            vec![
+                (1, 18),
+                (2, 15),
+                (3, 15),
+                // Good Friday, followed by the weekend and Easter Monday
+                (4, 23),
+                (5, 17),
+                (6, 21),
+                (7, 19),
+                (8, 16),
+                (9, 20),
+                (10, 18),
+                (11, 15),
+                (12, 20)
+            ]
+        );
+
+        // one business day after Christmas Eve skips both Christmas holidays
+        let dates = year_2019(MonthAnchor::Day(24))
+            .business_day_offset(1)
+            .dates(&cal)
+            .unwrap();
+        assert_eq!(dates[11], ymd(2019, 12, 27));
+        let dates = year_2019(MonthAnchor::Day(27))
+            .business_day_offset(-1)
+            .dates(&cal)
+            .unwrap();
+        assert_eq!(dates[11], ymd(2019, 12, 24));
+    }
+
+    #[test]
+    fn anchors_in_short_months() {
+        let cal = target();
+        // the 31st is moved to the end of shorter months
+        let dates = year_2019(MonthAnchor::Day(31)).dates(&cal).unwrap();
+        assert_eq!(dates.len(), 12);
+        assert_eq!(dates[1], ymd(2019, 2, 28));
+        // 30th of June is a Sunday
+        assert_eq!(dates[5], ymd(2019, 7, 1));
+        // offset exceeding the business days of February
+        let schedule =
+            CashFlowSchedule::monthly(ymd(2019, 2, 1), ymd(2019, 3, 31), MonthAnchor::MonthStart)
+                .business_day_offset(25);
+        assert_eq!(
+            schedule.dates(&cal).unwrap(),
+            vec![ymd(2019, 3, 8), ymd(2019, 4, 5)]
+        );
+        assert!(year_2019(MonthAnchor::Day(32)).dates(&cal).is_err());
+    }
+
+    #[test]
+    fn every_21_business_days() {
+        let cal = target();
+        let period = TimePeriod::from_str("21B").unwrap();
+        let schedule = CashFlowSchedule::every(ymd(2019, 1, 2), ymd(2019, 5, 31), period);
+        let eur = Currency::from_str("EUR").unwrap();
+        let cash_flows = schedule.cash_flows(1500., eur, &cal).unwrap();
+        let dates: Vec<NaiveDate> = cash_flows.iter().map(|cf| cf.date).collect();
+        assert_eq!(
+            dates,
+            vec![
+                ymd(2019, 1, 31),
+                ymd(2019, 3, 1),
+                ymd(2019, 4, 1),
+                ymd(2019, 5, 3)
+            ]
+        );
+        assert!(cash_flows.iter().all(|cf| cf.amount.amount == 1500.));
+
+        let backwards = TimePeriod::from_str("-1M").unwrap();
+        let schedule = CashFlowSchedule::every(ymd(2019, 1, 2), ymd(2019, 5, 31), backwards);
+        assert!(schedule.dates(&cal).is_err());
+    }
+}
@@ -0,0 +1,286 @@
+//! Price series of several assets prepared for charts.
+//!
+//! Daily prices (see `quote_matrix`) are resampled to the requested frequency by taking the
+//! last price of each asset within each calendar period. All assets share the same dates:
+//! one per period that contains a quote of any asset, i.e. the last day with a quote within
+//! that period. Periods without any quote, e.g. weekends for daily charts, are omitted.
+//!
+//! Series may be normalized to start at 100 at a base date. An asset without a quote on or
+//! before the base date, e.g. because its history starts later, is normalized to 100 at its
+//! first quote instead, which is given as its base date.
+
+use std::collections::BTreeMap;
+use std::ops::RangeInclusive;
+
+use chrono::NaiveDate;
+use serde::Serialize;
+
+use finql_data::{DataError, QuoteReader};
+
+use crate::quote_matrix::load_quote_matrix;
+use crate::time_buckets::{Bucket, BucketKey};
+
+/// Value of a normalized series at its base date
+pub const NORMALIZED_BASE: f64 = 100.;
+
+/// How to fill dates on which an asset has no quote, but other assets have
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub enum FillPolicy {
+    /// Repeat the last known price, dates before the first quote remain empty
+    Forward,
+    /// Leave the value empty
+    Null,
+}
+
+/// Values of a single asset, aligned to the dates of the chart
+#[derive(Debug, Clone, Serialize)]
+pub struct ChartSeries<T> {
+    pub asset_id: usize,
+    /// Date the series has been normalized at, if normalization has been requested
+    /// and the asset has any quote
+    pub base_date: Option<NaiveDate>,
+    /// Value at each date of the chart, `None` (`null` if serialized) if unknown
+    pub values: Vec<Option<T>>,
+}
+
+/// Aligned series of several assets
+#[derive(Debug, Clone, Serialize)]
+pub struct ChartData<T = f64> {
+    /// Dates shared by all series, in ascending order
+    pub dates: Vec<NaiveDate>,
+    /// Series in the order of the requested assets
+    pub series: Vec<ChartSeries<T>>,
+}
+
+impl ChartData<f64> {
+    /// Convert values to single precision, which is sufficient for display and reduces the
+    /// size of serialized data
+    pub fn to_f32(&self) -> ChartData<f32> {
+        ChartData {
+            dates: self.dates.clone(),
+            series: self
+                .series
+                .iter()
+                .map(|s| ChartSeries {
+                    asset_id: s.asset_id,
+                    base_date: s.base_date,
+                    values: s.values.iter().map(|v| v.map(|v| v as f32)).collect(),
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Base date and value of a series of (date, price) pairs in ascending order of dates
+fn base_value(prices: &[(NaiveDate, f64)], base_date: NaiveDate) -> Option<(NaiveDate, f64)> {
+    prices
+        .iter()
+        .take_while(|(date, _)| *date <= base_date)
+        .last()
+        .or_else(|| prices.first())
+        .copied()
+}
+
+/// Load the prices of the given assets in `range`, resampled to periods of `frequency`. If
+/// `normalize` is given, each series is scaled to `NORMALIZED_BASE` at that date, see module
+/// documentation. If none of the assets has a quote in `range`, the chart has no dates.
+pub fn chart_series(
+    db: &dyn QuoteReader,
+    asset_ids: &[usize],
+    range: RangeInclusive<NaiveDate>,
+    frequency: Bucket,
+    normalize: Option<NaiveDate>,
+    fill: FillPolicy,
+) -> Result<ChartData, DataError> {
+    let matrix = load_quote_matrix(db, asset_ids, range)?;
+
+    // last date with a quote of any asset within each period
+    let mut periods: BTreeMap<BucketKey, NaiveDate> = BTreeMap::new();
+    for date in &matrix.dates {
+        periods.insert(frequency.bucket_of(*date), *date);
+    }
+    let dates: Vec<NaiveDate> = periods.values().copied().collect();
+
+    let mut series = Vec::new();
+    for (asset_index, asset_id) in asset_ids.iter().enumerate() {
+        let prices: Vec<(NaiveDate, f64)> = matrix
+            .dates
+            .iter()
+            .enumerate()
+            .filter_map(|(j, date)| matrix.price(asset_index, j).map(|p| (*date, p)))
+            .collect();
+        let base = normalize.and_then(|date| base_value(&prices, date));
+        let scale = base.map_or(1., |(_, price)| NORMALIZED_BASE / price);
+
+        let mut last_price: Option<(BucketKey, f64)> = None;
+        let mut prices = prices.into_iter().peekable();
+        let mut values = Vec::with_capacity(dates.len());
+        for date in &dates {
+            let period = frequency.bucket_of(*date);
+            while let Some((_, price)) = prices.next_if(|(d, _)| d <= date) {
+                last_price = Some((period, price));
+            }
+            let value = match (last_price, fill) {
+                (Some((_, price)), FillPolicy::Forward) => Some(price),
+                (Some((last_period, price)), FillPolicy::Null) if last_period == period => {
+                    Some(price)
+                }
+                _ => None,
+            };
+            values.push(value.map(|price| price * scale));
+        }
+        series.push(ChartSeries {
+            asset_id: *asset_id,
+            base_date: base.map(|(date, _)| date),
+            values,
+        });
+    }
+    Ok(ChartData { dates, series })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    use chrono::{TimeZone, Utc};
+    use rusqlite::Connection;
+
+    use finql_data::{Asset, AssetHandler, Currency, Quote, QuoteHandler, Ticker, TickerUsage};
+    use finql_sqlite::SqliteDB;
+
+    fn insert_asset(db: &mut SqliteDB, name: &str, quotes: &[(u32, u32, f64)]) -> usize {
+        let asset_id = db
+            .insert_asset(&Asset::new(None, name, None, None, None))
+            .unwrap();
+        let ticker = db
+            .insert_ticker(&Ticker {
+                id: None,
+                name: name.to_string(),
+                asset: asset_id,
+                source: "manual".to_string(),
+                priority: 1,
+                currency: Currency::from_str("EUR").unwrap(),
+                factor: 1.0,
+                source_url: None,
+                usage: TickerUsage::Both,
+                last_quote_time: None,
+                source_chain: Vec::new(),
+            })
+            .unwrap();
+        for (month, day, price) in quotes {
+            db.insert_quote(&Quote {
+                id: None,
+                ticker,
+                price: *price,
+                time: Utc.ymd(2020, *month, *day).and_hms(17, 30, 0),
+                volume: None,
+                quality_score: None,
+                source: None,
+                open: None,
+                high: None,
+                low: None,
+                bid: None,
+                ask: None,
+            })
+            .unwrap();
+        }
+        asset_id
+    }
+
+    #[test]
+    fn monthly_normalized_chart() {
+        let conn = Connection::open(":memory:").unwrap();
+        let mut db = SqliteDB::new(&conn);
+        db.init().unwrap();
+        let basf = insert_asset(
+            &mut db,
+            "BASF AG",
+            &[(1, 2, 50.), (1, 31, 55.), (2, 28, 60.), (4, 15, 40.)],
+        );
+        // listed in February, no quotes in April
+        let ipo = insert_asset(&mut db, "IPO AG", &[(2, 10, 20.), (3, 30, 25.)]);
+        let start = NaiveDate::from_ymd(2020, 1, 1);
+        let range = start..=NaiveDate::from_ymd(2020, 6, 30);
+        let base_date = NaiveDate::from_ymd(2020, 1, 15);
+
+        let chart = chart_series(
+            &db,
+            &[basf, ipo],
+            range.clone(),
+            Bucket::Month,
+            Some(base_date),
+            FillPolicy::Forward,
+        )
+        .unwrap();
+        let expected_dates = vec![
+            NaiveDate::from_ymd(2020, 1, 31),
+            NaiveDate::from_ymd(2020, 2, 28),
+            NaiveDate::from_ymd(2020, 3, 30),
+            NaiveDate::from_ymd(2020, 4, 15),
+        ];
+        assert_eq!(chart.dates, expected_dates);
+        assert_eq!(
+            chart.series[0].base_date,
+            Some(NaiveDate::from_ymd(2020, 1, 2))
+        );
+        assert_eq!(
+            chart.series[0].values,
+            vec![Some(110.), Some(120.), Some(120.), Some(80.)]
+        );
+        assert_eq!(
+            chart.series[1].base_date,
+            Some(NaiveDate::from_ymd(2020, 2, 10))
+        );
+        assert_eq!(
+            chart.series[1].values,
+            vec![None, Some(100.), Some(125.), Some(125.)]
+        );
+
+        let chart = chart_series(
+            &db,
+            &[basf, ipo],
+            range,
+            Bucket::Month,
+            None,
+            FillPolicy::Null,
+        )
+        .unwrap();
+        assert_eq!(chart.series[0].base_date, None);
+        assert_eq!(
+            chart.series[0].values,
+            vec![Some(55.), Some(60.), None, Some(40.)]
+        );
+        assert_eq!(
+            chart.series[1].values,
+            vec![None, Some(20.), Some(25.), None]
+        );
+
+        let json = serde_json::to_value(chart.to_f32()).unwrap();
+        assert_eq!(json["dates"][0], "2020-01-31");
+        assert_eq!(json["series"][1]["values"][0], serde_json::Value::Null);
+        assert_eq!(json["series"][1]["values"][2], 25.);
+    }
+
+    #[test]
+    fn chart_without_data() {
+        let conn = Connection::open(":memory:").unwrap();
+        let mut db = SqliteDB::new(&conn);
+        db.init().unwrap();
+        let basf = insert_asset(&mut db, "BASF AG", &[(1, 2, 50.)]);
+        let range = NaiveDate::from_ymd(2020, 6, 1)..=NaiveDate::from_ymd(2020, 6, 30);
+        let chart = chart_series(
+            &db,
+            &[basf],
+            range,
+            Bucket::Day,
+            Some(NaiveDate::from_ymd(2020, 6, 1)),
+            FillPolicy::Forward,
+        )
+        .unwrap();
+        assert!(chart.dates.is_empty());
+        assert_eq!(chart.series.len(), 1);
+        assert_eq!(chart.series[0].base_date, None);
+        assert!(chart.series[0].values.is_empty());
+    }
+}
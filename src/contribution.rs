@@ -0,0 +1,401 @@
+//! Savings analytics based on cash deposits and withdrawals.
+//!
+//! Contributions are all cash transactions (deposits are positive, withdrawals negative) in the
+//! currency of the scope. Other transaction types, e.g. trades, income, or transfers of positions
+//! between accounts, move money only within the portfolio and are therefore no contributions.
+//! Cash transactions in other currencies are not converted, but listed as ignored.
+
+use std::error::Error;
+use std::fmt;
+
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+
+use finql_data::{CashAmount, Currency, DataError, TransactionHandler, TransactionType};
+
+use crate::money_format::{format_cash, CurrencyDisplay, MoneyFormatOptions};
+use crate::time_buckets::Bucket;
+
+/// Maximum number of months considered when projecting the time to reach the target amount
+const MAX_PROJECTION_MONTHS: u32 = 1200;
+
+/// Error related to the creation of contribution reports
+#[derive(Debug)]
+pub enum ContributionError {
+    DBError(DataError),
+    InvalidRange,
+}
+
+impl fmt::Display for ContributionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::DBError(_) => write!(f, "database error"),
+            Self::InvalidRange => write!(f, "start of period must not be after its end"),
+        }
+    }
+}
+
+impl Error for ContributionError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::DBError(err) => Some(err),
+            Self::InvalidRange => None,
+        }
+    }
+}
+
+impl From<DataError> for ContributionError {
+    fn from(error: DataError) -> Self {
+        Self::DBError(error)
+    }
+}
+
+/// Savings goal used to project the time until the target amount is reached
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct SavingsTarget {
+    pub amount: f64,
+    /// Assumed annual return of the savings, e.g. 0.05 for 5%
+    pub annual_return: f64,
+}
+
+/// Settings defining which transactions are analyzed and how
+#[derive(Debug, Clone)]
+pub struct ContributionScope {
+    /// Only cash transactions in this currency are considered
+    pub currency: Currency,
+    /// Monthly net income to calculate the savings rate, if given
+    pub monthly_income: Option<f64>,
+    pub target: Option<SavingsTarget>,
+}
+
+/// Contributions of a single month
+#[derive(Debug, Clone, Serialize)]
+pub struct MonthlyContribution {
+    /// First day of the month
+    pub month: NaiveDate,
+    pub deposits: f64,
+    /// Sum of withdrawals, as negative number
+    pub withdrawals: f64,
+    pub net: f64,
+}
+
+/// Contribution report for a given period
+#[derive(Debug, Clone, Serialize)]
+pub struct ContributionReport {
+    pub start: NaiveDate,
+    pub end: NaiveDate,
+    pub currency: Currency,
+    /// All months of the period, including months without contributions
+    pub months: Vec<MonthlyContribution>,
+    pub total: f64,
+    pub average_monthly: f64,
+    /// Net contributions of the last (up to) twelve months of the period relative to the
+    /// income of those months, if a monthly income is given
+    pub trailing_savings_rate: Option<f64>,
+    /// Longest number of consecutive months with positive net contributions
+    pub longest_streak: usize,
+    /// Net contributions of all months up to the end of the period
+    pub balance: f64,
+    /// Number of months after the end of the period until the target amount is reached, if a
+    /// target is given and it is reached within 100 years
+    pub months_to_target: Option<u32>,
+    /// Ids of cash transactions in the period that are not in the scope's currency
+    pub ignored_transactions: Vec<usize>,
+}
+
+/// Number of months until `balance` grows to `target` with monthly contributions of `contribution`
+/// paid at the end of each month and compounded with the given annual return
+pub fn months_to_target(balance: f64, contribution: f64, target: &SavingsTarget) -> Option<u32> {
+    let monthly_return = (1.0 + target.annual_return).powf(1.0 / 12.0) - 1.0;
+    let mut value = balance;
+    for month in 0..=MAX_PROJECTION_MONTHS {
+        if value >= target.amount {
+            return Some(month);
+        }
+        value = value * (1.0 + monthly_return) + contribution;
+    }
+    None
+}
+
+impl ContributionReport {
+    fn new(start: NaiveDate, end: NaiveDate, currency: Currency) -> ContributionReport {
+        let months = Bucket::Month
+            .bucket_range(start, end)
+            .iter()
+            .map(|key| MonthlyContribution {
+                month: key.first_day(),
+                deposits: 0.0,
+                withdrawals: 0.0,
+                net: 0.0,
+            })
+            .collect();
+        ContributionReport {
+            start,
+            end,
+            currency,
+            months,
+            total: 0.0,
+            average_monthly: 0.0,
+            trailing_savings_rate: None,
+            longest_streak: 0,
+            balance: 0.0,
+            months_to_target: None,
+            ignored_transactions: Vec::new(),
+        }
+    }
+
+    fn add_contribution(&mut self, date: NaiveDate, amount: f64) {
+        let month = Bucket::Month.bucket_of(date).first_day();
+        if let Some(entry) = self.months.iter_mut().find(|m| m.month == month) {
+            if amount >= 0.0 {
+                entry.deposits += amount;
+            } else {
+                entry.withdrawals += amount;
+            }
+            entry.net += amount;
+        }
+    }
+
+    /// Calculate the statistics from the monthly contributions
+    fn summarize(&mut self, scope: &ContributionScope) {
+        self.total = self.months.iter().map(|m| m.net).sum();
+        self.average_monthly = self.total / self.months.len() as f64;
+        let trailing = &self.months[self.months.len().saturating_sub(12)..];
+        self.trailing_savings_rate =
+            scope
+                .monthly_income
+                .filter(|income| *income > 0.0)
+                .map(|income| {
+                    trailing.iter().map(|m| m.net).sum::<f64>() / (income * trailing.len() as f64)
+                });
+        let mut streak = 0;
+        for month in &self.months {
+            if month.net > 0.0 {
+                streak += 1;
+                self.longest_streak = self.longest_streak.max(streak);
+            } else {
+                streak = 0;
+            }
+        }
+        self.months_to_target = scope
+            .target
+            .and_then(|target| months_to_target(self.balance, self.average_monthly, &target));
+    }
+}
+
+impl fmt::Display for ContributionReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "Contributions from {} to {} in {}",
+            self.start, self.end, self.currency
+        )?;
+        // the currency is given in the header already
+        let opts = MoneyFormatOptions {
+            currency_display: CurrencyDisplay::None,
+            ..MoneyFormatOptions::default()
+        };
+        let amount = |amount| {
+            format_cash(
+                &CashAmount {
+                    amount,
+                    currency: self.currency,
+                },
+                &opts,
+            )
+        };
+        for month in &self.months {
+            writeln!(
+                f,
+                "{}: {:>12} ({} deposits, {} withdrawals)",
+                month.month.format("%Y-%m"),
+                amount(month.net),
+                amount(month.deposits),
+                amount(month.withdrawals)
+            )?;
+        }
+        writeln!(f, "Total:           {}", amount(self.total))?;
+        writeln!(f, "Monthly average: {}", amount(self.average_monthly))?;
+        if let Some(rate) = self.trailing_savings_rate {
+            writeln!(f, "Savings rate:    {:.1}%", 100.0 * rate)?;
+        }
+        writeln!(f, "Longest streak:  {} months", self.longest_streak)?;
+        match self.months_to_target {
+            Some(months) => writeln!(f, "Target reached:  in {} months", months)?,
+            None => writeln!(f, "Target reached:  -")?,
+        }
+        if !self.ignored_transactions.is_empty() {
+            writeln!(
+                f,
+                "Ignored {} cash transactions in other currencies",
+                self.ignored_transactions.len()
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Create the contribution report for all cash transactions with dates in the period `start`
+/// to `end` (both inclusive). Months are calendar months, the first and last month may be
+/// partial.
+pub fn contribution_report(
+    db: &mut dyn TransactionHandler,
+    scope: &ContributionScope,
+    start: NaiveDate,
+    end: NaiveDate,
+) -> Result<ContributionReport, ContributionError> {
+    if start > end {
+        return Err(ContributionError::InvalidRange);
+    }
+    let mut report = ContributionReport::new(start, end, scope.currency);
+    let mut transactions = db.get_all_transactions()?;
+    transactions.sort_by_key(|t| (t.cash_flow.date, t.id));
+    for transaction in transactions {
+        let date = transaction.cash_flow.date;
+        if date > end {
+            break;
+        }
+        if let TransactionType::Cash = transaction.transaction_type {
+            let amount = transaction.cash_flow.amount;
+            if amount.currency != scope.currency {
+                if date >= start {
+                    if let Some(id) = transaction.id {
+                        report.ignored_transactions.push(id);
+                    }
+                }
+                continue;
+            }
+            report.balance += amount.amount;
+            if date >= start {
+                report.add_contribution(date, amount.amount);
+            }
+        }
+    }
+    report.summarize(scope);
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    use finql_data::{CashFlow, Transaction};
+    use finql_sqlite::SqliteDB;
+    use rusqlite::Connection;
+
+    fn transaction(
+        transaction_type: TransactionType,
+        amount: f64,
+        currency: &str,
+        date: NaiveDate,
+    ) -> Transaction {
+        Transaction {
+            id: None,
+            transaction_type,
+            cash_flow: CashFlow::new(amount, Currency::from_str(currency).unwrap(), date),
+            note: None,
+            execution_meta: None,
+            recorded_at: None,
+        }
+    }
+
+    #[test]
+    fn projection_to_target() {
+        let target = SavingsTarget {
+            amount: 12_000.0,
+            annual_return: 0.0,
+        };
+        assert_eq!(months_to_target(0.0, 1000.0, &target), Some(12));
+        assert_eq!(months_to_target(12_000.0, 0.0, &target), Some(0));
+        assert_eq!(months_to_target(0.0, -100.0, &target), None);
+        let growing = SavingsTarget {
+            amount: 12_000.0,
+            annual_return: 0.1,
+        };
+        assert_eq!(months_to_target(6_000.0, 0.0, &growing), Some(88));
+        assert_eq!(months_to_target(0.0, 1000.0, &growing), Some(12));
+    }
+
+    #[test]
+    fn contributions_with_withdrawal_month() {
+        let conn = Connection::open(":memory:").unwrap();
+        let mut db = SqliteDB::new(&conn);
+        db.init().unwrap();
+        let date = |m, d| NaiveDate::from_ymd(2020, m, d);
+        for trans in &[
+            // before the period, counts only for the balance
+            transaction(
+                TransactionType::Cash,
+                5000.0,
+                "EUR",
+                NaiveDate::from_ymd(2019, 12, 1),
+            ),
+            transaction(TransactionType::Cash, 1000.0, "EUR", date(1, 15)),
+            transaction(TransactionType::Cash, 500.0, "EUR", date(2, 1)),
+            transaction(TransactionType::Cash, 500.0, "EUR", date(2, 15)),
+            transaction(TransactionType::Cash, 1000.0, "EUR", date(3, 1)),
+            transaction(TransactionType::Cash, 300.0, "EUR", date(4, 1)),
+            transaction(TransactionType::Cash, -2000.0, "EUR", date(4, 20)),
+            transaction(TransactionType::Cash, 1000.0, "EUR", date(5, 1)),
+            transaction(TransactionType::Cash, 1000.0, "USD", date(5, 2)),
+            transaction(
+                TransactionType::Transfer {
+                    asset_id: 1,
+                    position: 10.0,
+                    transfer_ref: None,
+                },
+                0.0,
+                "EUR",
+                date(5, 3),
+            ),
+            transaction(
+                TransactionType::Dividend { asset_id: 1 },
+                50.0,
+                "EUR",
+                date(5, 4),
+            ),
+            transaction(TransactionType::Cash, 1000.0, "EUR", date(7, 1)),
+        ] {
+            db.insert_transaction(trans).unwrap();
+        }
+        let scope = ContributionScope {
+            currency: Currency::from_str("EUR").unwrap(),
+            monthly_income: Some(4000.0),
+            target: Some(SavingsTarget {
+                amount: 10_000.0,
+                annual_return: 0.0,
+            }),
+        };
+        let report = contribution_report(&mut db, &scope, date(1, 1), date(6, 30)).unwrap();
+        assert_eq!(report.months.len(), 6);
+        let net: Vec<f64> = report.months.iter().map(|m| m.net).collect();
+        assert_eq!(net, vec![1000.0, 1000.0, 1000.0, -1700.0, 1000.0, 0.0]);
+        assert_eq!(report.months[3].deposits, 300.0);
+        assert_eq!(report.months[3].withdrawals, -2000.0);
+        assert_fuzzy_eq!(report.total, 2300.0, 1e-10);
+        assert_fuzzy_eq!(report.average_monthly, 2300.0 / 6.0, 1e-10);
+        assert_fuzzy_eq!(
+            report.trailing_savings_rate.unwrap(),
+            2300.0 / 24_000.0,
+            1e-10
+        );
+        assert_eq!(report.longest_streak, 3);
+        assert_fuzzy_eq!(report.balance, 7300.0, 1e-10);
+        // (10000 - 7300) / (2300 / 6) = 7.04
+        assert_eq!(report.months_to_target, Some(8));
+        assert_eq!(report.ignored_transactions.len(), 1);
+
+        let rendered = report.to_string();
+        assert!(
+            rendered.contains("2020-04:    -1,700.00 (300.00 deposits, -2,000.00 withdrawals)\n")
+        );
+        assert!(rendered.contains("Savings rate:    9.6%\n"));
+        assert!(rendered.contains("Longest streak:  3 months\n"));
+
+        assert!(matches!(
+            contribution_report(&mut db, &scope, date(2, 1), date(1, 1)),
+            Err(ContributionError::InvalidRange)
+        ));
+    }
+}
@@ -0,0 +1,675 @@
+//! Look-through currency exposure of a portfolio.
+//!
+//! The currency an asset is quoted in does not necessarily reflect its economic currency
+//! exposure, e.g. a world equity fund quoted in EUR is mostly exposed to USD. Therefore, a
+//! breakdown of the currency exposure can be stored per asset. The report uses this breakdown
+//! where available and falls back to the currency of the asset's quote otherwise. Assets
+//! valued by fallback or with outdated quotes are marked in the report.
+
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt;
+use std::io::Read;
+use std::str::FromStr;
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+use finql_data::{
+    AssetIndex, CashAmount, Currency, CurrencyConverter, CurrencyError, CurrencyExposure,
+    DataError, IdentifierPriority, QuoteHandler,
+};
+
+use crate::analytics_error::AnalyticsError;
+use crate::diagnostics::{DiagnosticCode, Diagnostics, QuoteFreshness, Severity};
+use crate::money_format::{format_cash, MoneyFormatOptions};
+use crate::portfolio_var::{risk_contributions, RiskContribution, RiskContributionReport};
+
+/// Error related to currency exposures
+#[derive(Debug)]
+pub enum CurrencyExposureError {
+    CurrencyConversion(CurrencyError),
+    DBError(DataError),
+    /// Import of currency exposures failed due to invalid input data
+    ImportFailed(String),
+}
+
+impl fmt::Display for CurrencyExposureError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::CurrencyConversion(_) => write!(f, "conversion to base currency failed"),
+            Self::DBError(_) => write!(f, "database error"),
+            Self::ImportFailed(err) => write!(f, "import of currency exposures failed: {}", err),
+        }
+    }
+}
+
+impl Error for CurrencyExposureError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::CurrencyConversion(err) => Some(err),
+            Self::DBError(err) => Some(err),
+            Self::ImportFailed(_) => None,
+        }
+    }
+}
+
+impl From<CurrencyError> for CurrencyExposureError {
+    fn from(error: CurrencyError) -> Self {
+        Self::CurrencyConversion(error)
+    }
+}
+
+impl From<DataError> for CurrencyExposureError {
+    fn from(error: DataError) -> Self {
+        Self::DBError(error)
+    }
+}
+
+/// Import currency exposures from CSV data with the columns `asset`, `currency` and `weight`
+/// and a header line. Assets are identified by the kinds of identifiers given by `priority`,
+/// e.g. `IdentifierPriority(vec![IdentifierKind::Isin, IdentifierKind::Name])` to match by ISIN
+/// or, if no asset with that ISIN exists, by name. Each asset may span several lines, one per
+/// currency. All exposures are validated before any of them is stored, existing breakdowns of
+/// the imported assets are replaced. Returns the number of assets whose exposure has been
+/// imported.
+pub fn import_currency_exposures<R: Read>(
+    db: &mut dyn QuoteHandler,
+    csv_data: R,
+    priority: &IdentifierPriority,
+) -> Result<usize, CurrencyExposureError> {
+    let assets = AssetIndex::build(db)?;
+    let mut reader = csv::ReaderBuilder::new()
+        .has_headers(true)
+        .trim(csv::Trim::All)
+        .from_reader(csv_data);
+    let mut exposures: Vec<CurrencyExposure> = Vec::new();
+    for (line, record) in reader.records().enumerate() {
+        let record = record.map_err(|e| CurrencyExposureError::ImportFailed(e.to_string()))?;
+        let field = |idx: usize| {
+            record.get(idx).ok_or_else(|| {
+                CurrencyExposureError::ImportFailed(format!("missing field in line {}", line + 2))
+            })
+        };
+        let asset = field(0)?;
+        let asset_id = assets
+            .find(asset, priority)
+            .map_err(|e| CurrencyExposureError::ImportFailed(format!("line {}: {}", line + 2, e)))?
+            .and_then(|asset| asset.id)
+            .ok_or_else(|| {
+                CurrencyExposureError::ImportFailed(format!("unknown asset '{}'", asset))
+            })?;
+        let currency = Currency::from_str(field(1)?)?;
+        let weight: f64 = field(2)?.parse().map_err(|_| {
+            CurrencyExposureError::ImportFailed(format!("invalid weight in line {}", line + 2))
+        })?;
+        match exposures.iter_mut().find(|e| e.asset_id == asset_id) {
+            Some(exposure) => exposure.weights.push((currency, weight)),
+            None => exposures.push(CurrencyExposure {
+                asset_id,
+                weights: vec![(currency, weight)],
+            }),
+        }
+    }
+    for exposure in &exposures {
+        exposure.validate()?;
+    }
+    for exposure in &exposures {
+        db.set_currency_exposure(exposure)?;
+    }
+    Ok(exposures.len())
+}
+
+/// Currency exposure of a single position
+#[derive(Debug, Clone, Serialize)]
+pub struct AssetExposure {
+    pub asset_id: usize,
+    pub name: String,
+    /// Value of the position in base currency
+    pub value: CashAmount,
+    /// Weights per currency the value is split into
+    pub weights: Vec<(Currency, f64)>,
+    /// True if no exposure breakdown is available and the quote currency has been used instead
+    pub fallback: bool,
+    /// Freshness of the quote the position has been valued with, relative to the asset's
+    /// expected quote frequency
+    pub freshness: QuoteFreshness,
+    /// Contribution of the position to the portfolio risk, if it has been joined onto the report
+    pub risk: Option<RiskContribution>,
+}
+
+/// Look-through currency exposure of a list of positions
+#[derive(Debug, Clone, Serialize)]
+pub struct CurrencyExposureReport {
+    pub time: DateTime<Utc>,
+    pub base_currency: Currency,
+    pub assets: Vec<AssetExposure>,
+    /// Exposure in base currency, indexed by currency code
+    pub exposure: BTreeMap<String, CashAmount>,
+    /// Total value of all positions in base currency
+    pub total: CashAmount,
+}
+
+/// Create the look-through currency exposure report for the given positions, given as pairs of
+/// asset id and position. Positions are valued by the last quote on or before `time`, in the
+/// asset's reference currency if it has one, and converted to `base_currency` with the
+/// exchange rates at `time`. Assets without quotes of their own that represent an underlying,
+/// e.g. ADRs, are valued by the quote of the underlying. Outdated quotes and assets valued by
+/// fallback are recorded in `diagnostics`.
+pub fn currency_exposure_report(
+    db: &mut dyn QuoteHandler,
+    positions: &[(usize, f64)],
+    base_currency: Currency,
+    time: DateTime<Utc>,
+    currency_converter: &mut dyn CurrencyConverter,
+    diagnostics: &mut Diagnostics,
+) -> Result<CurrencyExposureReport, CurrencyExposureError> {
+    let mut report = CurrencyExposureReport {
+        time,
+        base_currency,
+        assets: Vec::new(),
+        exposure: BTreeMap::new(),
+        total: CashAmount {
+            amount: 0.0,
+            currency: base_currency,
+        },
+    };
+    for (asset_id, position) in positions {
+        let asset = db.get_asset_by_id(*asset_id)?;
+        let (quote, quote_currency) =
+            match db.get_best_quote_before(*asset_id, time, currency_converter) {
+                Ok(quote) => quote,
+                Err(err) => match db.get_asset_representation(*asset_id)? {
+                    Some(representation) => {
+                        let quote = db.get_best_quote_via_underlying_before(
+                            *asset_id,
+                            time,
+                            currency_converter,
+                        )?;
+                        diagnostics.record(
+                            DiagnosticCode::UnderlyingPrice,
+                            Severity::Info,
+                            vec![*asset_id, representation.underlying_id],
+                            format!(
+                                "no quote for asset {}, valued by the quote of its underlying {}",
+                                asset_id, representation.underlying_id
+                            ),
+                        );
+                        quote
+                    }
+                    None => return Err(err.into()),
+                },
+            };
+        let expected_frequency = diagnostics.expected_quote_frequency(&asset);
+        let freshness = diagnostics.check_quote_freshness(
+            *asset_id,
+            quote.time,
+            time,
+            expected_frequency.as_ref(),
+        );
+        let fx_rate = if quote_currency == base_currency {
+            1.0
+        } else {
+            currency_converter.fx_rate(quote_currency, base_currency, time)?
+        };
+        let value = position * quote.price * fx_rate;
+        let (weights, fallback) = match db.get_currency_exposure(*asset_id)? {
+            Some(exposure) => (exposure.weights, false),
+            None => {
+                diagnostics.warn(
+                    DiagnosticCode::ExposureFallback,
+                    vec![*asset_id],
+                    format!(
+                        "no currency exposure of asset {} stored, quote currency {} used",
+                        asset_id, quote_currency
+                    ),
+                );
+                (vec![(quote_currency, 1.0)], true)
+            }
+        };
+        for (currency, weight) in &weights {
+            report
+                .exposure
+                .entry(currency.to_string())
+                .or_insert(CashAmount {
+                    amount: 0.0,
+                    currency: base_currency,
+                })
+                .amount += weight * value;
+        }
+        report.total.amount += value;
+        report.assets.push(AssetExposure {
+            asset_id: *asset_id,
+            name: asset.name,
+            value: CashAmount {
+                amount: value,
+                currency: base_currency,
+            },
+            weights,
+            fallback,
+            freshness,
+            risk: None,
+        });
+    }
+    Ok(report)
+}
+
+impl CurrencyExposureReport {
+    /// Join the contribution of each position to the portfolio risk onto the report, based on
+    /// the positions' values in base currency. `returns` must contain the returns of each asset
+    /// of the report for the same periods. See `portfolio_var::risk_contributions` for the
+    /// meaning of `confidence_level` and `flag_share`.
+    pub fn join_risk_contributions(
+        &mut self,
+        returns: &BTreeMap<usize, Vec<f64>>,
+        confidence_level: f64,
+        flag_share: f64,
+    ) -> Result<RiskContributionReport, AnalyticsError> {
+        let positions: Vec<(usize, f64)> = self
+            .assets
+            .iter()
+            .map(|asset| (asset.asset_id, asset.value.amount))
+            .collect();
+        let asset_returns = self
+            .assets
+            .iter()
+            .map(|asset| {
+                returns.get(&asset.asset_id).cloned().ok_or_else(|| {
+                    AnalyticsError::InvalidInput(format!("no returns of asset {}", asset.name))
+                })
+            })
+            .collect::<Result<Vec<Vec<f64>>, AnalyticsError>>()?;
+        let risk = risk_contributions(&positions, &asset_returns, confidence_level, flag_share)?;
+        for asset in &mut self.assets {
+            asset.risk = risk.contribution_of(asset.asset_id).cloned();
+        }
+        Ok(risk)
+    }
+}
+
+/// Write amount rounded according to the currency's rounding convention
+fn write_amount(f: &mut fmt::Formatter<'_>, amount: &CashAmount) -> fmt::Result {
+    write!(f, "{}", format_cash(amount, &MoneyFormatOptions::default()))
+}
+
+impl fmt::Display for CurrencyExposureReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "Look-through currency exposure at {} in {}",
+            self.time.format("%Y-%m-%d"),
+            self.base_currency
+        )?;
+        for (currency, amount) in &self.exposure {
+            write!(f, "{:<5}", format!("{}:", currency))?;
+            write_amount(f, amount)?;
+            if self.total.amount != 0.0 {
+                write!(f, " ({:.1}%)", 100.0 * amount.amount / self.total.amount)?;
+            }
+            writeln!(f)?;
+        }
+        let fallback: Vec<&AssetExposure> = self.assets.iter().filter(|a| a.fallback).collect();
+        if !fallback.is_empty() {
+            writeln!(f, "Assets without exposure breakdown, quote currency used:")?;
+            for asset in fallback {
+                writeln!(f, "  {} ({})", asset.name, asset.weights[0].0)?;
+            }
+        }
+        let outdated: Vec<&AssetExposure> = self
+            .assets
+            .iter()
+            .filter(|a| a.freshness != QuoteFreshness::Fresh)
+            .collect();
+        if !outdated.is_empty() {
+            writeln!(f, "Assets valued with outdated quotes:")?;
+            for asset in outdated {
+                writeln!(f, "  {} ({})", asset.name, asset.freshness)?;
+            }
+        }
+        let flagged: Vec<(&str, &RiskContribution)> = self
+            .assets
+            .iter()
+            .filter_map(|a| a.risk.as_ref().map(|risk| (a.name.as_str(), risk)))
+            .filter(|(_, risk)| risk.flagged)
+            .collect();
+        if !flagged.is_empty() {
+            writeln!(f, "Positions with a high contribution to risk:")?;
+            for (name, risk) in flagged {
+                writeln!(
+                    f,
+                    "  {} (volatility {:.1}%, VaR {:.1}%)",
+                    name,
+                    100.0 * risk.volatility_share,
+                    100.0 * risk.var_share
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    use finql_data::{
+        Asset, AssetHandler, AssetRepresentation, IdentifierKind, Quote, Ticker, TickerUsage,
+    };
+    use finql_sqlite::SqliteDB;
+    use rusqlite::Connection;
+
+    use crate::fx_rates::SimpleCurrencyConverter;
+
+    fn insert_asset_with_quote(
+        db: &mut SqliteDB,
+        name: &str,
+        isin: &str,
+        price: f64,
+        currency: Currency,
+    ) -> usize {
+        let asset_id = db
+            .insert_asset(&Asset::new(None, name, None, Some(isin.to_string()), None))
+            .unwrap();
+        let ticker = db
+            .insert_ticker(&Ticker {
+                id: None,
+                name: isin.to_string(),
+                asset: asset_id,
+                source: "manual".to_string(),
+                priority: 1,
+                currency,
+                factor: 1.0,
+                source_url: None,
+                usage: TickerUsage::Both,
+                last_quote_time: None,
+                source_chain: Vec::new(),
+            })
+            .unwrap();
+        db.insert_quote(&Quote {
+            id: None,
+            ticker,
+            price,
+            time: Utc.ymd(2021, 1, 4).and_hms(17, 30, 0),
+            volume: None,
+            quality_score: None,
+            source: None,
+            open: None,
+            high: None,
+            low: None,
+            bid: None,
+            ask: None,
+        })
+        .unwrap();
+        asset_id
+    }
+
+    #[test]
+    fn look_through_exposure() {
+        let conn = Connection::open(":memory:").unwrap();
+        let mut db = SqliteDB::new(&conn);
+        db.init().unwrap();
+        let eur = Currency::from_str("EUR").unwrap();
+        let usd = Currency::from_str("USD").unwrap();
+        let world = insert_asset_with_quote(&mut db, "World ETF", "IE00B4L5Y983", 50.0, eur);
+        let apple = insert_asset_with_quote(&mut db, "Apple Inc.", "US0378331005", 100.0, usd);
+        let basf = insert_asset_with_quote(&mut db, "BASF AG", "DE000BASF111", 60.0, eur);
+
+        let isin_or_name = IdentifierPriority(vec![IdentifierKind::Isin, IdentifierKind::Name]);
+        let csv_data = "asset, currency, weight
+IE00B4L5Y983, USD, 0.7
+IE00B4L5Y983, EUR, 0.2
+World ETF, JPY, 0.1
+";
+        assert_eq!(
+            import_currency_exposures(&mut db, csv_data.as_bytes(), &isin_or_name).unwrap(),
+            1
+        );
+        let exposure = db.get_currency_exposure(world).unwrap().unwrap();
+        assert_eq!(exposure.weights.len(), 3);
+        assert!(db.get_currency_exposure(basf).unwrap().is_none());
+
+        // weights not adding up to 1 are rejected, nothing is stored
+        let csv_data = "asset,currency,weight
+BASF AG,EUR,0.9
+US0378331005,USD,1.0
+";
+        assert!(matches!(
+            import_currency_exposures(&mut db, csv_data.as_bytes(), &isin_or_name),
+            Err(CurrencyExposureError::DBError(DataError::InvalidData(_)))
+        ));
+        assert!(db.get_currency_exposure(apple).unwrap().is_none());
+
+        // names differing in case and legal form only match in fuzzy mode
+        let csv_data = "asset,currency,weight\napple, USD, 1.0\n";
+        assert!(matches!(
+            import_currency_exposures(&mut db, csv_data.as_bytes(), &IdentifierPriority::strict()),
+            Err(CurrencyExposureError::ImportFailed(_))
+        ));
+        assert_eq!(
+            import_currency_exposures(&mut db, csv_data.as_bytes(), &IdentifierPriority::fuzzy())
+                .unwrap(),
+            1
+        );
+        db.delete_currency_exposure(apple).unwrap();
+
+        let mut converter = SimpleCurrencyConverter::new();
+        converter.insert_fx_rate(usd, eur, 0.8);
+        let mut diagnostics = Diagnostics::new();
+        let report = currency_exposure_report(
+            &mut db,
+            &[(world, 10.0), (apple, 5.0), (basf, 5.0)],
+            eur,
+            Utc.ymd(2021, 1, 5).and_hms(0, 0, 0),
+            &mut converter,
+            &mut diagnostics,
+        )
+        .unwrap();
+        let tol = 1e-10;
+        assert_fuzzy_eq!(report.total.amount, 1200.0, tol);
+        assert_fuzzy_eq!(report.exposure["USD"].amount, 750.0, tol);
+        assert_fuzzy_eq!(report.exposure["EUR"].amount, 400.0, tol);
+        assert_fuzzy_eq!(report.exposure["JPY"].amount, 50.0, tol);
+        assert!(!report.assets[0].fallback);
+        assert!(report.assets[1].fallback && report.assets[2].fallback);
+        let fallback: Vec<_> = diagnostics
+            .iter()
+            .filter(|d| d.code == DiagnosticCode::ExposureFallback)
+            .map(|d| d.entity_ids[0])
+            .collect();
+        assert_eq!(fallback, vec![apple, basf]);
+        assert!(!diagnostics.contains(DiagnosticCode::StaleQuote));
+
+        // quotes are more than a week old
+        let mut diagnostics = Diagnostics::new();
+        currency_exposure_report(
+            &mut db,
+            &[(world, 10.0)],
+            eur,
+            Utc.ymd(2021, 1, 12).and_hms(0, 0, 0),
+            &mut converter,
+            &mut diagnostics,
+        )
+        .unwrap();
+        assert_eq!(diagnostics.len(), 1);
+        assert!(diagnostics.contains(DiagnosticCode::StaleQuote));
+
+        let rendered = report.to_string();
+        assert!(rendered.contains("USD: 750.00 EUR (62.5%)\n"));
+        assert!(rendered.contains("  Apple Inc. (USD)\n  BASF AG (EUR)\n"));
+        assert!(!rendered.contains("World ETF"));
+
+        let mut report = report;
+        let mut returns = BTreeMap::new();
+        returns.insert(world, vec![0.01, -0.01, 0.01, -0.01]);
+        returns.insert(apple, vec![0.02, -0.02, 0.02, -0.02]);
+        assert!(report.join_risk_contributions(&returns, 0.75, 0.5).is_err());
+        returns.insert(basf, vec![0.; 4]);
+        let risk = report.join_risk_contributions(&returns, 0.75, 0.5).unwrap();
+        // world and apple are perfectly correlated, weighted 500/1200 and 400/1200
+        let world_risk = report.assets[0].risk.as_ref().unwrap();
+        assert_fuzzy_eq!(world_risk.volatility_share, 5. / 13., tol);
+        assert_fuzzy_eq!(
+            report.assets[1].risk.as_ref().unwrap().var_share,
+            8. / 13.,
+            tol
+        );
+        assert_eq!(report.assets[2].risk.as_ref().unwrap().volatility_share, 0.);
+        assert_fuzzy_eq!(risk.var, 13.0, tol);
+        assert!(report
+            .to_string()
+            .contains("Positions with a high contribution to risk:\n  Apple Inc. (volatility 61.5%, VaR 61.5%)\n"));
+
+        db.delete_currency_exposure(world).unwrap();
+        assert!(db.get_currency_exposure(world).unwrap().is_none());
+    }
+
+    #[test]
+    fn adr_valued_by_underlying() {
+        let conn = Connection::open(":memory:").unwrap();
+        let mut db = SqliteDB::new(&conn);
+        db.init().unwrap();
+        let eur = Currency::from_str("EUR").unwrap();
+        let usd = Currency::from_str("USD").unwrap();
+        let jpy = Currency::from_str("JPY").unwrap();
+        let share =
+            insert_asset_with_quote(&mut db, "Toyota Motor Corp", "JP3633400001", 2000.0, jpy);
+        let adr = db
+            .insert_asset(&Asset::new(None, "Toyota Motor Corp ADR", None, None, None))
+            .unwrap();
+        db.insert_ticker(&Ticker {
+            id: None,
+            name: "TM".to_string(),
+            asset: adr,
+            source: "manual".to_string(),
+            priority: 1,
+            currency: usd,
+            factor: 1.0,
+            source_url: None,
+            usage: TickerUsage::Both,
+            last_quote_time: None,
+            source_chain: Vec::new(),
+        })
+        .unwrap();
+        db.set_asset_representation(&AssetRepresentation {
+            asset_id: adr,
+            underlying_id: share,
+            ratio: 2.0,
+        })
+        .unwrap();
+
+        let mut converter = SimpleCurrencyConverter::new();
+        converter.insert_fx_rate(jpy, usd, 0.009);
+        converter.insert_fx_rate(usd, eur, 0.8);
+        let mut diagnostics = Diagnostics::new();
+        let report = currency_exposure_report(
+            &mut db,
+            &[(adr, 10.0)],
+            eur,
+            Utc.ymd(2021, 1, 5).and_hms(0, 0, 0),
+            &mut converter,
+            &mut diagnostics,
+        )
+        .unwrap();
+        // 10 ADRs representing 2 shares at 2000 JPY each, converted via the ADR's currency USD
+        let tol = 1e-10;
+        assert_fuzzy_eq!(report.total.amount, 288.0, tol);
+        assert_fuzzy_eq!(report.exposure["USD"].amount, 288.0, tol);
+        let underlying_price: Vec<_> = diagnostics
+            .iter()
+            .filter(|d| d.code == DiagnosticCode::UnderlyingPrice)
+            .map(|d| d.entity_ids.clone())
+            .collect();
+        assert_eq!(underlying_price, vec![vec![adr, share]]);
+
+        // after a 2:1 split of the underlying, each ADR represents twice as many shares
+        let ticker = db.get_all_ticker_for_asset(share).unwrap()[0].id.unwrap();
+        db.insert_quote(&Quote {
+            id: None,
+            ticker,
+            price: 1000.0,
+            time: Utc.ymd(2021, 1, 6).and_hms(17, 30, 0),
+            volume: None,
+            quality_score: None,
+            source: None,
+            open: None,
+            high: None,
+            low: None,
+            bid: None,
+            ask: None,
+        })
+        .unwrap();
+        let adjusted = db.adjust_representations_for_split(share, 2.0).unwrap();
+        assert_eq!(adjusted.len(), 1);
+        assert_fuzzy_eq!(adjusted[0].ratio, 4.0, tol);
+        let report = currency_exposure_report(
+            &mut db,
+            &[(adr, 10.0)],
+            eur,
+            Utc.ymd(2021, 1, 7).and_hms(0, 0, 0),
+            &mut converter,
+            &mut Diagnostics::new(),
+        )
+        .unwrap();
+        assert_fuzzy_eq!(report.total.amount, 288.0, tol);
+
+        // neither quotes nor an underlying
+        db.delete_asset_representation(adr).unwrap();
+        assert!(currency_exposure_report(
+            &mut db,
+            &[(adr, 10.0)],
+            eur,
+            Utc.ymd(2021, 1, 7).and_hms(0, 0, 0),
+            &mut converter,
+            &mut Diagnostics::new(),
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn quote_age_by_expected_frequency() {
+        let conn = Connection::open(":memory:").unwrap();
+        let mut db = SqliteDB::new(&conn);
+        db.init().unwrap();
+        let eur = Currency::from_str("EUR").unwrap();
+        let etf = insert_asset_with_quote(&mut db, "World ETF", "IE00B4L5Y983", 50.0, eur);
+        let fund = insert_asset_with_quote(&mut db, "Real estate fund", "DE0009807016", 40.0, eur);
+        let placement = insert_asset_with_quote(&mut db, "Private placement", "XX01", 1.0, eur);
+        for (asset_id, frequency) in &[(fund, "1W"), (placement, "3M")] {
+            let mut asset = db.get_asset_by_id(*asset_id).unwrap();
+            asset.expected_quote_frequency = Some(frequency.to_string());
+            db.update_asset(&asset).unwrap();
+        }
+
+        // quotes are more than a week old
+        let mut diagnostics = Diagnostics::new();
+        let report = currency_exposure_report(
+            &mut db,
+            &[(etf, 1.0), (fund, 1.0), (placement, 1.0)],
+            eur,
+            Utc.ymd(2021, 1, 12).and_hms(0, 0, 0),
+            &mut SimpleCurrencyConverter::new(),
+            &mut diagnostics,
+        )
+        .unwrap();
+        let freshness: Vec<QuoteFreshness> = report.assets.iter().map(|a| a.freshness).collect();
+        assert_eq!(
+            freshness,
+            vec![
+                QuoteFreshness::Stale,
+                QuoteFreshness::Acceptable,
+                QuoteFreshness::Fresh
+            ]
+        );
+        let stale: Vec<usize> = diagnostics
+            .iter()
+            .filter(|d| d.code == DiagnosticCode::StaleQuote)
+            .map(|d| d.entity_ids[0])
+            .collect();
+        assert_eq!(stale, vec![etf]);
+        let rendered = report.to_string();
+        assert!(rendered.contains(
+            "Assets valued with outdated quotes:\n  World ETF (stale)\n  Real estate fund (acceptable)\n"
+        ));
+    }
+}
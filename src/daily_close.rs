@@ -0,0 +1,305 @@
+//! End-of-day close of a finql database, meant to be run once every evening
+//!
+//! `run_daily_close` fetches the latest quotes, fills gaps in the recent quote history, checks
+//! the database for inconsistencies and records the run as job run `daily_close`. Steps are
+//! independent of each other: if a step fails, the remaining steps are run anyway and the
+//! failure is reported with the step. All steps are idempotent, running the close twice on
+//! the same day does not store anything twice.
+
+use std::time::Instant;
+
+use chrono::Duration;
+
+use finql_data::{DataError, DataItem, JobRun, QuoteProvider, ReportHandler, SchemaHandler};
+
+use crate::admin::{AdminContext, AdminError, Command, CommandOutcome};
+use crate::diagnostics::{DiagnosticCode, Severity};
+
+/// Name of the job runs of the daily close
+pub const DAILY_CLOSE_JOB: &str = "daily_close";
+
+/// Settings of the daily close
+#[derive(Debug, Clone, Copy)]
+pub struct DailyCloseConfig {
+    /// Number of days before today checked for missing quotes
+    pub backfill_days: u32,
+}
+
+impl Default for DailyCloseConfig {
+    fn default() -> Self {
+        DailyCloseConfig { backfill_days: 7 }
+    }
+}
+
+/// Result of a single step of the daily close
+#[derive(Debug)]
+pub struct StepReport {
+    pub name: &'static str,
+    /// Wall clock time spent on the step
+    pub duration: std::time::Duration,
+    pub result: Result<CommandOutcome, AdminError>,
+}
+
+impl StepReport {
+    /// Check whether the step has been completed without errors. Warnings, e.g. about
+    /// ticker without quote provider, are no failure.
+    pub fn is_success(&self) -> bool {
+        match &self.result {
+            Ok(outcome) => outcome.diagnostics.max_severity() != Some(Severity::Error),
+            Err(_) => false,
+        }
+    }
+
+    /// Error messages of the step, if it failed
+    fn errors(&self) -> Vec<String> {
+        match &self.result {
+            Ok(outcome) => outcome
+                .diagnostics
+                .iter()
+                .filter(|d| d.severity == Severity::Error)
+                .map(|d| format!("{}: {}", self.name, d.message))
+                .collect(),
+            Err(err) => vec![format!("{}: {}", self.name, err)],
+        }
+    }
+}
+
+/// Result of the daily close
+#[derive(Debug)]
+pub struct DailyCloseReport {
+    /// Id of the job run recorded for this close
+    pub job_run_id: usize,
+    /// All steps in the order they have been run
+    pub steps: Vec<StepReport>,
+    /// True if all steps succeeded
+    pub success: bool,
+}
+
+impl DailyCloseReport {
+    /// Get the report of a step by name
+    pub fn step(&self, name: &str) -> Option<&StepReport> {
+        self.steps.iter().find(|step| step.name == name)
+    }
+}
+
+/// Run a step and measure its duration
+fn run_step<F>(name: &'static str, step: F) -> StepReport
+where
+    F: FnOnce() -> Result<CommandOutcome, AdminError>,
+{
+    let start = Instant::now();
+    let result = step();
+    StepReport {
+        name,
+        duration: start.elapsed(),
+        result,
+    }
+}
+
+/// Fill gaps in the quotes of the last `days` days of all ticker with a quote provider for
+/// their source. Failures of single ticker are recorded and don't stop the backfill of the
+/// other ticker.
+fn backfill_recent_quotes<DB>(
+    db: &mut DB,
+    ctx: &AdminContext,
+    days: u32,
+) -> Result<CommandOutcome, AdminError>
+where
+    DB: ReportHandler + SchemaHandler,
+{
+    let end = ctx.clock.now_utc().naive_utc().date();
+    let start = end - Duration::days(days as i64);
+    let mut outcome = CommandOutcome {
+        dry_run: ctx.dry_run,
+        ..Default::default()
+    };
+    for ticker in db.get_all_ticker()? {
+        let id = ticker.get_id()?;
+        if !ctx.registry.supports_source(&ticker.source) {
+            continue;
+        }
+        match (Command::BackfillTicker { id, start, end }).execute(db, ctx) {
+            Ok(backfill) => {
+                outcome.count += backfill.count;
+                outcome.ids.extend(backfill.ids);
+            }
+            Err(err) => outcome.diagnostics.record(
+                DiagnosticCode::UpdateFailed,
+                Severity::Error,
+                vec![id],
+                format!("backfill of ticker {} failed: {}", id, err),
+            ),
+        }
+    }
+    Ok(outcome)
+}
+
+/// Run the end-of-day close and record it as job run `DAILY_CLOSE_JOB`. Only failing to
+/// record the job run is returned as error, failures of the steps are part of the report.
+pub fn run_daily_close<DB>(
+    db: &mut DB,
+    ctx: &AdminContext,
+    config: &DailyCloseConfig,
+) -> Result<DailyCloseReport, DataError>
+where
+    DB: ReportHandler + SchemaHandler,
+{
+    let started = ctx.clock.now_utc();
+    let steps = vec![
+        run_step("update_quotes", || {
+            Command::UpdateQuotes { source: None }.execute(db, ctx)
+        }),
+        run_step("backfill_quotes", || {
+            backfill_recent_quotes(db, ctx, config.backfill_days)
+        }),
+        run_step("check_consistency", || {
+            Command::CheckConsistency.execute(db, ctx)
+        }),
+    ];
+    let errors: Vec<String> = steps.iter().flat_map(StepReport::errors).collect();
+    let success = errors.is_empty();
+    let job_run_id = db.insert_job_run(&JobRun {
+        id: None,
+        job: DAILY_CLOSE_JOB.to_string(),
+        started,
+        finished: ctx.clock.now_utc(),
+        success,
+        message: if success {
+            None
+        } else {
+            Some(errors.join("; "))
+        },
+    })?;
+    Ok(DailyCloseReport {
+        job_run_id,
+        steps,
+        success,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::str::FromStr;
+    use std::sync::Arc;
+
+    use chrono::{NaiveDate, TimeZone, Utc};
+    use rusqlite::Connection;
+
+    use finql_data::{Currency, Quote, QuoteHandler, QuoteProviderRegistry, Ticker, TickerUsage};
+    use finql_sqlite::SqliteDB;
+
+    use crate::date_time_helper::MockClock;
+    use crate::test_utils::load_fixture_db;
+
+    /// Provides a quote at 18:00 UTC for each day, fails for ticker named `broken`
+    struct MockProvider {}
+
+    impl QuoteProvider for MockProvider {
+        fn fetch_quotes(
+            &self,
+            ticker: &Ticker,
+            start: NaiveDate,
+            end: NaiveDate,
+        ) -> Result<Vec<Quote>, DataError> {
+            if ticker.name == "broken" {
+                return Err(DataError::NotFound("service unavailable".to_string()));
+            }
+            let mut quotes = Vec::new();
+            let mut date = start;
+            while date <= end {
+                quotes.push(Quote {
+                    id: None,
+                    ticker: ticker.get_id()?,
+                    price: 50.0,
+                    time: Utc.from_utc_datetime(&date.and_hms(18, 0, 0)),
+                    volume: None,
+                    quality_score: None,
+                    source: None,
+                    open: None,
+                    high: None,
+                    low: None,
+                    bid: None,
+                    ask: None,
+                });
+                date = date.succ();
+            }
+            Ok(quotes)
+        }
+
+        fn supports_source(&self, source: &str) -> bool {
+            source == "mock"
+        }
+    }
+
+    fn ticker(name: &str, asset: usize) -> Ticker {
+        Ticker {
+            id: None,
+            name: name.to_string(),
+            asset,
+            source: "mock".to_string(),
+            priority: 1,
+            currency: Currency::from_str("EUR").unwrap(),
+            factor: 1.0,
+            source_url: None,
+            usage: TickerUsage::Both,
+            last_quote_time: None,
+            source_chain: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn daily_close_is_idempotent() {
+        let conn = Connection::open(":memory:").unwrap();
+        let mut db = SqliteDB::new(&conn);
+        load_fixture_db(&mut db, "portfolio.json");
+        let basf = db.insert_ticker(&ticker("BAS.DE", 1)).unwrap();
+        let broken = db.insert_ticker(&ticker("broken", 2)).unwrap();
+
+        let mut registry = QuoteProviderRegistry::new();
+        registry.register(Box::new(MockProvider {}));
+        let mut ctx = AdminContext::new(registry);
+        ctx.clock = Arc::new(MockClock::new(Utc.ymd(2021, 3, 31).and_hms(20, 0, 0)));
+        let config = DailyCloseConfig { backfill_days: 5 };
+
+        let report = run_daily_close(&mut db, &ctx, &config).unwrap();
+        assert_eq!(report.steps.len(), 3);
+        // the broken ticker fails both quote steps, but the consistency check is run anyway
+        assert!(!report.success);
+        assert!(!report.step("update_quotes").unwrap().is_success());
+        assert!(!report.step("backfill_quotes").unwrap().is_success());
+        assert!(report.step("check_consistency").unwrap().is_success());
+        let backfill = report.step("backfill_quotes").unwrap();
+        let outcome = backfill.result.as_ref().unwrap();
+        assert_eq!(outcome.count, 5);
+        assert_eq!(
+            outcome.diagnostics.iter().next().unwrap().entity_ids,
+            vec![broken]
+        );
+        assert_eq!(db.get_all_quotes_for_ticker(basf).unwrap().len(), 6);
+
+        let second = run_daily_close(&mut db, &ctx, &config).unwrap();
+        let update = second.step("update_quotes").unwrap();
+        assert_eq!(update.result.as_ref().unwrap().count, 0);
+        let backfill = second.step("backfill_quotes").unwrap();
+        assert_eq!(backfill.result.as_ref().unwrap().count, 0);
+        assert_eq!(db.get_all_quotes_for_ticker(basf).unwrap().len(), 6);
+
+        let runs = db.get_job_runs(DAILY_CLOSE_JOB).unwrap();
+        assert_eq!(runs.len(), 2);
+        assert_eq!(runs[0].id, Some(second.job_run_id));
+        assert!(!runs[0].success);
+        let message = runs[0].message.as_deref().unwrap();
+        assert!(message.starts_with("update_quotes: update of ticker"));
+        assert!(message.contains("backfill_quotes: backfill of ticker"));
+
+        // without failing ticker, the close succeeds
+        db.delete_ticker(broken).unwrap();
+        let report = run_daily_close(&mut db, &ctx, &config).unwrap();
+        assert!(report.success);
+        assert!(db.get_job_runs(DAILY_CLOSE_JOB).unwrap()[0]
+            .message
+            .is_none());
+    }
+}
@@ -51,9 +51,12 @@ pub fn date_time_from_str(
     Ok(DateTime::from(time))
 }
 
+pub use finql_data::clock::{Clock, MockClock, SystemClock};
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use chrono::FixedOffset;
 
     #[test]
     fn test_unix_to_date_time() {
@@ -85,4 +88,54 @@ mod tests {
         let date_string = date.format("%Y-%m-%d %H:%M:%S").to_string();
         assert_eq!("2020-02-10 18:00:00", &date_string);
     }
+
+    #[test]
+    fn mock_clock() {
+        let clock = MockClock::new(Utc.ymd(2020, 12, 31).and_hms(23, 30, 0));
+        let utc = FixedOffset::east(0);
+        let cet = FixedOffset::east(3600);
+        assert_eq!(clock.today(utc), NaiveDate::from_ymd(2020, 12, 31));
+        assert_eq!(clock.today(cet), NaiveDate::from_ymd(2021, 1, 1));
+        clock.advance(chrono::Duration::hours(1));
+        assert_eq!(clock.now_utc(), Utc.ymd(2021, 1, 1).and_hms(0, 30, 0));
+        clock.set(Utc.ymd(2020, 1, 1).and_hms(0, 0, 0));
+        assert_eq!(clock.today(utc), NaiveDate::from_ymd(2020, 1, 1));
+    }
+
+    /// Collect all rust source files below the given directory
+    fn source_files(dir: &std::path::Path, files: &mut Vec<std::path::PathBuf>) {
+        for entry in std::fs::read_dir(dir).unwrap() {
+            let path = entry.unwrap().path();
+            if path.is_dir() {
+                source_files(&path, files);
+            } else if path.extension() == Some(std::ffi::OsStr::new("rs")) {
+                files.push(path);
+            }
+        }
+    }
+
+    #[test]
+    fn no_direct_now_calls_in_library_code() {
+        let root = std::path::Path::new(env!("CARGO_MANIFEST_DIR"));
+        let mut files = Vec::new();
+        for dir in &["src", "finql-data/src", "finql-sqlite/src", "finql-postgres/src"] {
+            source_files(&root.join(dir), &mut files);
+        }
+        let allowed = root.join("finql-data/src/clock.rs");
+        for file in files.iter().filter(|file| **file != allowed) {
+            let source = std::fs::read_to_string(file).unwrap();
+            // test code may use the system time
+            let library_code = source.split("#[cfg(test)]").next().unwrap();
+            // match the paths only to find calls as well as functions passed by name, e.g.
+            // `unwrap_or_else(Utc::now)`
+            for call in &["Utc::now", "Local::now", "Utc::today", "Local::today"] {
+                assert!(
+                    !library_code.contains(call),
+                    "{} calls {}, use a Clock instead",
+                    file.display(),
+                    call
+                );
+            }
+        }
+    }
 }
@@ -24,14 +24,14 @@ impl DayAdjust {
         match self {
             DayAdjust::None => date,
             DayAdjust::Following => {
-                if cal.is_holiday(date) {
+                if !cal.is_business_day(date) {
                     cal.next_bday(date)
                 } else {
                     date
                 }
             }
             DayAdjust::Preceding => {
-                if cal.is_holiday(date) {
+                if !cal.is_business_day(date) {
                     cal.prev_bday(date)
                 } else {
                     date
@@ -134,4 +134,28 @@ mod tests {
             NaiveDate::from_ymd(2019, 11, 30)
         );
     }
+
+    #[test]
+    fn adjust_weekends() {
+        use chrono::Weekday;
+
+        let holidays = vec![
+            Holiday::WeekDay(Weekday::Sat),
+            Holiday::WeekDay(Weekday::Sun),
+        ];
+        let cal = Calendar::calc_calendar(&holidays, 2019, 2019);
+        let saturday = NaiveDate::from_ymd(2019, 11, 30);
+        assert_eq!(
+            DayAdjust::Following.adjust_date(saturday, &cal),
+            NaiveDate::from_ymd(2019, 12, 2)
+        );
+        assert_eq!(
+            DayAdjust::Preceding.adjust_date(saturday, &cal),
+            NaiveDate::from_ymd(2019, 11, 29)
+        );
+        assert_eq!(
+            DayAdjust::Modified.adjust_date(saturday, &cal),
+            NaiveDate::from_ymd(2019, 11, 29)
+        );
+    }
 }
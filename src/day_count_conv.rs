@@ -123,7 +123,7 @@ impl DayCountConv {
 
     /// Implementation of 30/360 day count method
     fn calc_30_360(start: NaiveDate, end: NaiveDate) -> f64 {
-        let yf = (end.year() - start.year()) as f64 + (end.month() - start.month()) as f64 / 12.;
+        let yf = (end.year() - start.year()) as f64 + (end.month() as i32 - start.month() as i32) as f64 / 12.;
         let start_day = std::cmp::min(start.day(), 30) as i32;
         let end_day = if start_day == 30 && end.day() == 31 {
             30
@@ -136,7 +136,7 @@ impl DayCountConv {
     /// Implementation of 30E/360 day count method
     fn calc_30_e_360(start: NaiveDate, end: NaiveDate) -> f64 {
         (end.year() - start.year()) as f64
-            + (end.month() - start.month()) as f64 / 12.
+            + (end.month() as i32 - start.month() as i32) as f64 / 12.
             + (std::cmp::min(end.day(), 30) as i32 - std::cmp::min(start.day(), 30) as i32) as f64
                 / 360.
     }
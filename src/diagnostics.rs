@@ -0,0 +1,401 @@
+//! Structured diagnostics about fallbacks and skipped data
+//!
+//! Functions that continue with a fallback instead of failing, e.g. valuing a position with
+//! an outdated quote, record a `Diagnostic` in the `Diagnostics` collector passed to them.
+//! The caller decides whether to show, log or ignore them. With the features `log` or
+//! `tracing`, every recorded diagnostic is forwarded to the respective crate as well.
+
+use std::fmt;
+use std::str::FromStr;
+
+use chrono::{DateTime, Duration, Utc};
+use serde::Serialize;
+
+use finql_data::Asset;
+
+use crate::time_period::TimePeriod;
+
+/// Severity of a diagnostic
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+pub enum Severity {
+    /// Expected behaviour worth knowing, e.g. a model price used for lack of quotes
+    Info,
+    /// Result may be inaccurate, e.g. because of outdated data
+    Warning,
+    /// Part of the work could not be done, e.g. a failed update of a single ticker
+    Error,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Info => write!(f, "info"),
+            Self::Warning => write!(f, "warning"),
+            Self::Error => write!(f, "error"),
+        }
+    }
+}
+
+/// Kind of fallback or problem a diagnostic reports
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum DiagnosticCode {
+    /// Latest quote used for a valuation is older than the configured maximum age
+    StaleQuote,
+    /// No currency exposure breakdown stored, the quote currency has been used instead
+    ExposureFallback,
+    /// No market quote available, the price has been calculated by a pricing model
+    ModelPrice,
+    /// No quote provider is registered for the source of a ticker
+    MissingQuoteProvider,
+    /// Update of a single ticker failed, the remaining ticker have been updated
+    UpdateFailed,
+    /// Inconsistent data found in the database
+    InconsistentData,
+    /// Operation has been skipped, e.g. schema changes in a dry run
+    Skipped,
+    /// No risk-free rate series configured for a currency, a rate of zero has been used instead
+    MissingRiskFreeRate,
+    /// Stored quote has been deleted or replaced, e.g. to repair an outlier
+    QuoteRepaired,
+    /// Tiny position left over by rounding errors has been treated as zero
+    PositionResidual,
+    /// Row of a settings table superseded by a newer row with the same key
+    DuplicateSetting,
+    /// Quote superseded by a newer quote of the same ticker and time
+    DuplicateQuote,
+    /// Primary source of a ticker failed, quotes have been fetched from its source chain
+    QuoteSourceFallback,
+    /// Currency code stored in lower case or with surrounding white space
+    UnnormalizedCurrency,
+    /// No market quote available, the price has been derived from the quote of the underlying
+    /// asset the asset represents
+    UnderlyingPrice,
+    /// Monthly quota of provider calls of a source used up, the source has been deferred
+    QuotaExceeded,
+}
+
+impl fmt::Display for DiagnosticCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::StaleQuote => write!(f, "stale_quote"),
+            Self::ExposureFallback => write!(f, "exposure_fallback"),
+            Self::ModelPrice => write!(f, "model_price"),
+            Self::MissingQuoteProvider => write!(f, "missing_quote_provider"),
+            Self::UpdateFailed => write!(f, "update_failed"),
+            Self::InconsistentData => write!(f, "inconsistent_data"),
+            Self::Skipped => write!(f, "skipped"),
+            Self::MissingRiskFreeRate => write!(f, "missing_risk_free_rate"),
+            Self::QuoteRepaired => write!(f, "quote_repaired"),
+            Self::PositionResidual => write!(f, "position_residual"),
+            Self::DuplicateSetting => write!(f, "duplicate_setting"),
+            Self::DuplicateQuote => write!(f, "duplicate_quote"),
+            Self::QuoteSourceFallback => write!(f, "quote_source_fallback"),
+            Self::UnnormalizedCurrency => write!(f, "unnormalized_currency"),
+            Self::UnderlyingPrice => write!(f, "underlying_price"),
+            Self::QuotaExceeded => write!(f, "quota_exceeded"),
+        }
+    }
+}
+
+/// A single diagnostic message
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Diagnostic {
+    pub code: DiagnosticCode,
+    pub severity: Severity,
+    /// Ids of the objects concerned, e.g. asset or ticker ids, as described by the message
+    pub entity_ids: Vec<usize>,
+    pub message: String,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} [{}]: {}", self.severity, self.code, self.message)
+    }
+}
+
+/// Default maximum age of quotes before they are reported as stale
+const DEFAULT_STALE_QUOTE_DAYS: i64 = 7;
+
+/// Default factor on the expected quote frequency of an asset up to which quotes count as
+/// acceptable, e.g. a weekly quote is acceptable up to 10.5 days
+const DEFAULT_QUOTE_AGE_GRACE: f64 = 1.5;
+
+/// Freshness of the quote a position has been valued with
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum QuoteFreshness {
+    /// Quote is not older than expected
+    Fresh,
+    /// Quote is older than expected, but within the grace period
+    Acceptable,
+    /// Quote is outdated, a `StaleQuote` diagnostic has been recorded
+    Stale,
+}
+
+impl fmt::Display for QuoteFreshness {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Fresh => write!(f, "fresh"),
+            Self::Acceptable => write!(f, "acceptable"),
+            Self::Stale => write!(f, "stale"),
+        }
+    }
+}
+
+/// Collector of diagnostics
+#[derive(Debug, Clone, Serialize)]
+pub struct Diagnostics {
+    diagnostics: Vec<Diagnostic>,
+    /// Quotes older than this at the time they are used for are reported as stale
+    #[serde(skip)]
+    pub stale_quote_age: Duration,
+    /// Quotes of assets with expected quote frequency are stale if older than the frequency
+    /// times this factor
+    #[serde(skip)]
+    pub quote_age_grace: f64,
+}
+
+impl Default for Diagnostics {
+    fn default() -> Self {
+        Diagnostics {
+            diagnostics: Vec::new(),
+            stale_quote_age: Duration::days(DEFAULT_STALE_QUOTE_DAYS),
+            quote_age_grace: DEFAULT_QUOTE_AGE_GRACE,
+        }
+    }
+}
+
+impl Diagnostics {
+    pub fn new() -> Diagnostics {
+        Diagnostics::default()
+    }
+
+    /// Set the maximum age of quotes before they are reported as stale
+    pub fn with_stale_quote_age(mut self, age: Duration) -> Diagnostics {
+        self.stale_quote_age = age;
+        self
+    }
+
+    /// Set the factor on the expected quote frequency up to which quotes are acceptable
+    pub fn with_quote_age_grace(mut self, grace: f64) -> Diagnostics {
+        self.quote_age_grace = grace;
+        self
+    }
+
+    /// Record a diagnostic and forward it to `log` or `tracing`, if enabled
+    pub fn record(
+        &mut self,
+        code: DiagnosticCode,
+        severity: Severity,
+        entity_ids: Vec<usize>,
+        message: String,
+    ) {
+        let diagnostic = Diagnostic {
+            code,
+            severity,
+            entity_ids,
+            message,
+        };
+        #[cfg(feature = "log")]
+        {
+            let level = match severity {
+                Severity::Info => log::Level::Info,
+                Severity::Warning => log::Level::Warn,
+                Severity::Error => log::Level::Error,
+            };
+            log::log!(level, "{}", diagnostic);
+        }
+        #[cfg(feature = "tracing")]
+        match severity {
+            Severity::Info => tracing::info!(code = %code, "{}", diagnostic.message),
+            Severity::Warning => tracing::warn!(code = %code, "{}", diagnostic.message),
+            Severity::Error => tracing::error!(code = %code, "{}", diagnostic.message),
+        }
+        self.diagnostics.push(diagnostic);
+    }
+
+    /// Record a diagnostic of severity `Warning`
+    pub fn warn(&mut self, code: DiagnosticCode, entity_ids: Vec<usize>, message: String) {
+        self.record(code, Severity::Warning, entity_ids, message);
+    }
+
+    /// Record a `StaleQuote` warning if the quote of the given asset taken at `quote_time`
+    /// is older than the maximum quote age at `time`
+    pub fn check_quote_age(
+        &mut self,
+        asset_id: usize,
+        quote_time: DateTime<Utc>,
+        time: DateTime<Utc>,
+    ) {
+        self.check_quote_freshness(asset_id, quote_time, time, None);
+    }
+
+    /// Classify the quote of the given asset taken at `quote_time` as used at `time`. Without
+    /// expected frequency, quotes up to the maximum quote age are fresh, all others are stale.
+    /// Otherwise, quotes are fresh within one period of the frequency and acceptable within
+    /// the grace period. A `StaleQuote` warning is recorded for stale quotes.
+    pub fn check_quote_freshness(
+        &mut self,
+        asset_id: usize,
+        quote_time: DateTime<Utc>,
+        time: DateTime<Utc>,
+        expected_frequency: Option<&TimePeriod>,
+    ) -> QuoteFreshness {
+        let age = time - quote_time;
+        let freshness = match expected_frequency {
+            None if age > self.stale_quote_age => QuoteFreshness::Stale,
+            None => QuoteFreshness::Fresh,
+            Some(period) => {
+                let quote_date = quote_time.naive_utc().date();
+                let expected_age = period.add_to(quote_date, None) - quote_date;
+                let grace_age = Duration::seconds(
+                    (expected_age.num_seconds() as f64 * self.quote_age_grace) as i64,
+                );
+                if age <= expected_age {
+                    QuoteFreshness::Fresh
+                } else if age <= grace_age {
+                    QuoteFreshness::Acceptable
+                } else {
+                    QuoteFreshness::Stale
+                }
+            }
+        };
+        if freshness == QuoteFreshness::Stale {
+            let expectation = match expected_frequency {
+                Some(period) => format!(", quotes are expected every {}", period),
+                None => String::new(),
+            };
+            self.warn(
+                DiagnosticCode::StaleQuote,
+                vec![asset_id],
+                format!(
+                    "latest quote of asset {} at {} is outdated at {}{}",
+                    asset_id, quote_time, time, expectation
+                ),
+            );
+        }
+        freshness
+    }
+
+    /// Expected quote frequency of the asset, if any. Invalid frequencies are recorded as
+    /// inconsistent data and ignored, i.e. the global maximum quote age applies.
+    pub fn expected_quote_frequency(&mut self, asset: &Asset) -> Option<TimePeriod> {
+        let frequency = asset.expected_quote_frequency.as_deref()?;
+        match TimePeriod::from_str(frequency) {
+            Ok(period) => Some(period),
+            Err(err) => {
+                let asset_id = asset.id.unwrap_or_default();
+                self.warn(
+                    DiagnosticCode::InconsistentData,
+                    vec![asset_id],
+                    format!(
+                        "invalid expected quote frequency '{}' of asset {}: {}",
+                        frequency, asset_id, err
+                    ),
+                );
+                None
+            }
+        }
+    }
+
+    /// Check whether any diagnostic with the given code has been recorded
+    pub fn contains(&self, code: DiagnosticCode) -> bool {
+        self.diagnostics.iter().any(|d| d.code == code)
+    }
+
+    pub fn iter(&self) -> std::slice::Iter<'_, Diagnostic> {
+        self.diagnostics.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.diagnostics.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.diagnostics.is_empty()
+    }
+
+    /// Highest severity of all recorded diagnostics
+    pub fn max_severity(&self) -> Option<Severity> {
+        self.diagnostics.iter().map(|d| d.severity).max()
+    }
+}
+
+impl IntoIterator for Diagnostics {
+    type Item = Diagnostic;
+    type IntoIter = std::vec::IntoIter<Diagnostic>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.diagnostics.into_iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn collect_diagnostics() {
+        let mut diagnostics = Diagnostics::new().with_stale_quote_age(Duration::days(3));
+        assert!(diagnostics.is_empty());
+        assert_eq!(diagnostics.max_severity(), None);
+        let time = Utc.ymd(2021, 3, 8).and_hms(18, 0, 0);
+        diagnostics.check_quote_age(1, time - Duration::days(3), time);
+        assert!(diagnostics.is_empty());
+        diagnostics.check_quote_age(1, time - Duration::days(4), time);
+        diagnostics.record(
+            DiagnosticCode::ModelPrice,
+            Severity::Info,
+            vec![2],
+            "option priced by model".to_string(),
+        );
+        assert_eq!(diagnostics.len(), 2);
+        assert!(diagnostics.contains(DiagnosticCode::StaleQuote));
+        assert!(!diagnostics.contains(DiagnosticCode::UpdateFailed));
+        assert_eq!(diagnostics.max_severity(), Some(Severity::Warning));
+        let stale = diagnostics.iter().next().unwrap();
+        assert_eq!(stale.entity_ids, vec![1]);
+        assert_eq!(
+            stale.to_string(),
+            "warning [stale_quote]: latest quote of asset 1 at 2021-03-04 18:00:00 UTC \
+            is outdated at 2021-03-08 18:00:00 UTC"
+        );
+    }
+
+    #[test]
+    fn quote_freshness_by_expected_frequency() {
+        let mut diagnostics = Diagnostics::new();
+        let time = Utc.ymd(2021, 3, 8).and_hms(18, 0, 0);
+        let weekly = TimePeriod::from_str("1W").unwrap();
+        let mut freshness = |days| {
+            diagnostics.check_quote_freshness(1, time - Duration::days(days), time, Some(&weekly))
+        };
+        assert_eq!(freshness(6), QuoteFreshness::Fresh);
+        assert_eq!(freshness(7), QuoteFreshness::Fresh);
+        assert_eq!(freshness(10), QuoteFreshness::Acceptable);
+        assert_eq!(freshness(11), QuoteFreshness::Stale);
+        assert_eq!(diagnostics.len(), 1);
+        assert!(diagnostics
+            .iter()
+            .next()
+            .unwrap()
+            .message
+            .ends_with("quotes are expected every 1W"));
+
+        // without expected frequency, the global maximum age applies
+        let mut diagnostics = Diagnostics::new();
+        let freshness = diagnostics.check_quote_freshness(1, time - Duration::days(6), time, None);
+        assert_eq!(freshness, QuoteFreshness::Fresh);
+        let freshness = diagnostics.check_quote_freshness(1, time - Duration::days(8), time, None);
+        assert_eq!(freshness, QuoteFreshness::Stale);
+
+        let mut asset = Asset::new(Some(3), "Real estate fund", None, None, None);
+        assert_eq!(diagnostics.expected_quote_frequency(&asset), None);
+        asset.expected_quote_frequency = Some("1W".to_string());
+        assert_eq!(diagnostics.expected_quote_frequency(&asset), Some(weekly));
+        asset.expected_quote_frequency = Some("weekly".to_string());
+        assert_eq!(diagnostics.expected_quote_frequency(&asset), None);
+        assert!(diagnostics.contains(DiagnosticCode::InconsistentData));
+    }
+}
@@ -0,0 +1,540 @@
+//! Dividend per share history, dividend yield and dividend growth of assets.
+//!
+//! Dividends are inferred from the dividend transactions: the dividend per share of a payment
+//! is the (gross) amount paid divided by the position held before the payment date. Since the
+//! position may have been closed between ex-date and payment date, the last position held
+//! within `EX_DATE_LOOKBACK_DAYS` before the payment is used in this case.
+//!
+//! Assets with irregular or suspended payments still get all figures, but are marked by
+//! the `DividendCoverage` of the result instead of returning NaN. Accumulating funds, which
+//! reinvest their income, are never expected to pay dividends and are marked as such.
+
+use std::str::FromStr;
+
+use chrono::{DateTime, Duration, NaiveDate, Utc};
+use serde::Serialize;
+
+use finql_data::{
+    Currency, DataError, QuoteHandler, Transaction, TransactionHandler, TransactionType,
+};
+
+use crate::time_period::TimePeriod;
+
+/// Number of days before a payment date in which a position closed before the payment
+/// is still considered to be entitled to the dividend
+pub const EX_DATE_LOOKBACK_DAYS: i64 = 90;
+
+/// Positions below this threshold are considered to be closed
+const POSITION_TOLERANCE: f64 = 1e-10;
+
+/// Single dividend payment per share
+#[derive(Debug, Clone, Serialize)]
+pub struct DividendPayment {
+    /// Id of the dividend transaction
+    pub transaction_id: Option<usize>,
+    pub date: NaiveDate,
+    pub dividend_per_share: f64,
+    pub currency: Currency,
+}
+
+/// How reliable the dividend figures of an asset are
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub enum DividendCoverage {
+    /// Same number of payments in the last twelve months as in the twelve months before
+    Regular,
+    /// Number of payments differs from the year before, e.g. due to a skipped or special
+    /// dividend, or there is no history to compare with
+    Irregular,
+    /// No payments in the last twelve months, but earlier ones
+    Suspended,
+    /// No dividends have been paid at all
+    NoDividends,
+    /// Accumulating fund, which reinvests its income instead of paying dividends
+    Accumulating,
+}
+
+/// Dividend expected to be paid in the future, see `project_dividends`
+#[derive(Debug, Clone, Serialize)]
+pub struct ProjectedDividend {
+    pub asset_id: usize,
+    pub date: NaiveDate,
+    pub dividend_per_share: f64,
+    /// Position held at the time of the projection
+    pub position: f64,
+    /// Expected payment, i.e. dividend per share times position
+    pub gross: f64,
+    pub currency: Currency,
+}
+
+/// Trailing dividend yield and dividend growth of an asset
+#[derive(Debug, Clone, Serialize)]
+pub struct YieldInfo {
+    pub asset_id: usize,
+    pub as_of: NaiveDate,
+    /// Sum of dividends per share paid in the twelve months up to `as_of`
+    pub ttm_dividend_per_share: f64,
+    /// Number of payments in the twelve months up to `as_of`
+    pub ttm_payments: usize,
+    /// Latest price on or before `as_of`
+    pub price: f64,
+    pub currency: Currency,
+    /// Trailing dividend per share divided by price
+    pub dividend_yield: f64,
+    /// Annual growth rate of the trailing dividend per share over the last three years,
+    /// `None` if no dividends have been paid in the twelve months three years ago
+    pub growth_3y: Option<f64>,
+    /// Same as `growth_3y` for the last five years
+    pub growth_5y: Option<f64>,
+    pub coverage: DividendCoverage,
+}
+
+/// Date `years` years before the given date
+fn years_before(date: NaiveDate, years: usize) -> NaiveDate {
+    TimePeriod::from_str(&format!("{}Y", years))
+        .unwrap()
+        .sub_from(date, None)
+}
+
+/// Date `years` years after the given date
+fn years_after(date: NaiveDate, years: usize) -> NaiveDate {
+    TimePeriod::from_str(&format!("{}Y", years))
+        .unwrap()
+        .add_to(date, None)
+}
+
+/// Position held at the end of each date with a change of position, ordered by date
+fn position_history(transactions: &[Transaction], asset_id: usize) -> Vec<(NaiveDate, f64)> {
+    let mut changes: Vec<(NaiveDate, f64)> = transactions
+        .iter()
+        .filter_map(|t| match t.transaction_type {
+            TransactionType::Asset {
+                asset_id: id,
+                position,
+            }
+            | TransactionType::Transfer {
+                asset_id: id,
+                position,
+                ..
+            } if id == asset_id => Some((t.cash_flow.date, position)),
+            _ => None,
+        })
+        .collect();
+    changes.sort_by_key(|(date, _)| *date);
+    let mut history: Vec<(NaiveDate, f64)> = Vec::new();
+    let mut total = 0.;
+    for (date, position) in changes {
+        total += position;
+        match history.last_mut() {
+            Some((last_date, last_position)) if *last_date == date => *last_position = total,
+            _ => history.push((date, total)),
+        }
+    }
+    history
+}
+
+/// Position entitled to a dividend paid at `date`, see module documentation
+fn entitled_position(history: &[(NaiveDate, f64)], date: NaiveDate) -> Option<f64> {
+    let earliest = date - Duration::days(EX_DATE_LOOKBACK_DAYS);
+    let mut position = None;
+    for (change_date, total) in history.iter().take_while(|(d, _)| *d < date) {
+        if total.abs() > POSITION_TOLERANCE {
+            position = Some(*total);
+        } else if *change_date < earliest {
+            position = None;
+        }
+    }
+    position
+}
+
+/// Get all dividends per share paid for an asset up to and including `as_of`, ordered by date
+pub fn dividend_history(
+    db: &mut dyn TransactionHandler,
+    asset_id: usize,
+    as_of: NaiveDate,
+) -> Result<Vec<DividendPayment>, DataError> {
+    let transactions = db.get_all_transactions()?;
+    let history = position_history(&transactions, asset_id);
+    let mut payments = Vec::new();
+    for trans in &transactions {
+        match trans.transaction_type {
+            TransactionType::Dividend { asset_id: id }
+                if id == asset_id && trans.cash_flow.date <= as_of =>
+            {
+                let date = trans.cash_flow.date;
+                let position = entitled_position(&history, date).ok_or_else(|| {
+                    DataError::InvalidTransaction(format!(
+                        "no position of asset {} entitled to the dividend paid at {}",
+                        asset_id, date
+                    ))
+                })?;
+                payments.push(DividendPayment {
+                    transaction_id: trans.id,
+                    date,
+                    dividend_per_share: trans.cash_flow.amount.amount / position,
+                    currency: trans.cash_flow.amount.currency,
+                });
+            }
+            _ => {}
+        }
+    }
+    payments.sort_by_key(|payment| (payment.date, payment.transaction_id));
+    Ok(payments)
+}
+
+/// Sum and number of dividends per share paid in the twelve months up to `end`
+fn trailing_dividends(payments: &[DividendPayment], end: NaiveDate) -> (f64, usize) {
+    let start = years_before(end, 1);
+    payments
+        .iter()
+        .filter(|p| p.date > start && p.date <= end)
+        .fold((0., 0), |(sum, count), p| {
+            (sum + p.dividend_per_share, count + 1)
+        })
+}
+
+/// Annual growth rate of the trailing dividends over the given number of years
+fn dividend_growth(payments: &[DividendPayment], as_of: NaiveDate, years: usize) -> Option<f64> {
+    let (current, _) = trailing_dividends(payments, as_of);
+    let (past, _) = trailing_dividends(payments, years_before(as_of, years));
+    if past > 0. {
+        Some((current / past).powf(1. / years as f64) - 1.)
+    } else {
+        None
+    }
+}
+
+/// Calculate the trailing twelve months dividend yield of an asset at the given date, i.e. the
+/// dividends per share paid within the last year divided by the latest price, and the annual
+/// growth rates of the trailing dividends over three and five years. Dividends and price must
+/// be given in the same currency. Dividend transactions of accumulating funds are ignored.
+pub fn dividend_yield<DB: QuoteHandler + TransactionHandler>(
+    db: &mut DB,
+    asset_id: usize,
+    as_of: NaiveDate,
+) -> Result<YieldInfo, DataError> {
+    let time = DateTime::<Utc>::from_utc(as_of.and_hms(23, 59, 59), Utc);
+    let (quote, currency) = db.get_last_quote_before_by_id(asset_id, time)?;
+    if db.get_asset_by_id(asset_id)?.is_accumulating() {
+        return Ok(YieldInfo {
+            asset_id,
+            as_of,
+            ttm_dividend_per_share: 0.,
+            ttm_payments: 0,
+            price: quote.price,
+            currency,
+            dividend_yield: 0.,
+            growth_3y: None,
+            growth_5y: None,
+            coverage: DividendCoverage::Accumulating,
+        });
+    }
+    let payments = dividend_history(db, asset_id, as_of)?;
+    if let Some(payment) = payments.iter().find(|p| p.currency != currency) {
+        return Err(DataError::CurrencyMismatch(format!(
+            "dividend of asset {} paid in {}, but quoted in {}",
+            asset_id, payment.currency, currency
+        )));
+    }
+    let (ttm_dividend_per_share, ttm_payments) = trailing_dividends(&payments, as_of);
+    let (_, previous_payments) = trailing_dividends(&payments, years_before(as_of, 1));
+    let coverage = if payments.is_empty() {
+        DividendCoverage::NoDividends
+    } else if ttm_payments == 0 {
+        DividendCoverage::Suspended
+    } else if ttm_payments == previous_payments {
+        DividendCoverage::Regular
+    } else {
+        DividendCoverage::Irregular
+    };
+    Ok(YieldInfo {
+        asset_id,
+        as_of,
+        ttm_dividend_per_share,
+        ttm_payments,
+        price: quote.price,
+        currency,
+        dividend_yield: ttm_dividend_per_share / quote.price,
+        growth_3y: dividend_growth(&payments, as_of, 3),
+        growth_5y: dividend_growth(&payments, as_of, 5),
+        coverage,
+    })
+}
+
+/// Calculate the dividend yields of the assets of the given positions, given as pairs of asset
+/// id and position, e.g. as optional analytics of a valuation. Assets that have never paid a
+/// dividend are reported as `None` and need no quote, except for accumulating funds, which are
+/// reported with `DividendCoverage::Accumulating`.
+pub fn position_dividend_yields<DB: QuoteHandler + TransactionHandler>(
+    db: &mut DB,
+    positions: &[(usize, f64)],
+    as_of: NaiveDate,
+) -> Result<Vec<(usize, Option<YieldInfo>)>, DataError> {
+    let mut yields = Vec::new();
+    for (asset_id, _) in positions {
+        let has_dividends = db.get_asset_by_id(*asset_id)?.is_accumulating()
+            || !dividend_history(db, *asset_id, as_of)?.is_empty();
+        let yield_info = if has_dividends {
+            Some(dividend_yield(db, *asset_id, as_of)?)
+        } else {
+            None
+        };
+        yields.push((*asset_id, yield_info));
+    }
+    Ok(yields)
+}
+
+/// Project the dividends of all assets held at `as_of` which will be paid after `as_of` up to
+/// (including) `horizon`, ordered by date. Each payment of the twelve months up to `as_of` is
+/// assumed to recur annually at the same day with the same dividend per share, paid on the
+/// position held at `as_of`. Accumulating funds are never expected to pay dividends.
+pub fn project_dividends(
+    db: &mut dyn TransactionHandler,
+    as_of: NaiveDate,
+    horizon: NaiveDate,
+) -> Result<Vec<ProjectedDividend>, DataError> {
+    let transactions = db.get_all_transactions()?;
+    let mut asset_ids: Vec<usize> = transactions
+        .iter()
+        .filter_map(|t| match t.transaction_type {
+            TransactionType::Dividend { asset_id } => Some(asset_id),
+            _ => None,
+        })
+        .collect();
+    asset_ids.sort_unstable();
+    asset_ids.dedup();
+
+    let mut projected = Vec::new();
+    for asset_id in asset_ids {
+        let position = position_history(&transactions, asset_id)
+            .into_iter()
+            .take_while(|(date, _)| *date <= as_of)
+            .last()
+            .map_or(0., |(_, position)| position);
+        if position.abs() <= POSITION_TOLERANCE || db.get_asset_by_id(asset_id)?.is_accumulating() {
+            continue;
+        }
+        let start = years_before(as_of, 1);
+        for payment in dividend_history(db, asset_id, as_of)?
+            .into_iter()
+            .filter(|p| p.date > start)
+        {
+            let mut years = 1;
+            let mut date = years_after(payment.date, years);
+            while date <= horizon {
+                if date > as_of {
+                    projected.push(ProjectedDividend {
+                        asset_id,
+                        date,
+                        dividend_per_share: payment.dividend_per_share,
+                        position,
+                        gross: payment.dividend_per_share * position,
+                        currency: payment.currency,
+                    });
+                }
+                years += 1;
+                date = years_after(payment.date, years);
+            }
+        }
+    }
+    projected.sort_by_key(|p| (p.date, p.asset_id));
+    Ok(projected)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    use rusqlite::Connection;
+
+    use finql_data::{
+        Asset, AssetHandler, CashFlow, DistributionPolicy, Quote, Ticker, TickerUsage,
+    };
+    use finql_sqlite::SqliteDB;
+
+    fn transaction(transaction_type: TransactionType, amount: f64, date: NaiveDate) -> Transaction {
+        Transaction {
+            id: None,
+            transaction_type,
+            cash_flow: CashFlow::new(amount, Currency::from_str("EUR").unwrap(), date),
+            note: None,
+            execution_meta: None,
+            recorded_at: None,
+        }
+    }
+
+    /// Store an asset bought at the beginning of 2015 at the price of its only quote
+    fn insert_asset(db: &mut SqliteDB, name: &str, position: f64, price: f64) -> usize {
+        let asset_id = db
+            .insert_asset(&Asset::new(None, name, None, None, None))
+            .unwrap();
+        let ticker_id = db
+            .insert_ticker(&Ticker {
+                id: None,
+                asset: asset_id,
+                name: name.to_string(),
+                currency: Currency::from_str("EUR").unwrap(),
+                source: "manual".to_string(),
+                priority: 1,
+                factor: 1.0,
+                source_url: None,
+                usage: TickerUsage::Both,
+                last_quote_time: None,
+                source_chain: Vec::new(),
+            })
+            .unwrap();
+        db.insert_quote(&Quote {
+            id: None,
+            ticker: ticker_id,
+            price,
+            time: Utc.ymd(2015, 1, 2).and_hms(18, 0, 0),
+            volume: None,
+            quality_score: None,
+            source: None,
+            open: None,
+            high: None,
+            low: None,
+            bid: None,
+            ask: None,
+        })
+        .unwrap();
+        db.insert_transaction(&transaction(
+            TransactionType::Asset { asset_id, position },
+            -position * price,
+            NaiveDate::from_ymd(2015, 1, 2),
+        ))
+        .unwrap();
+        asset_id
+    }
+
+    fn insert_dividend(db: &mut SqliteDB, asset_id: usize, amount: f64, date: NaiveDate) {
+        db.insert_transaction(&transaction(
+            TransactionType::Dividend { asset_id },
+            amount,
+            date,
+        ))
+        .unwrap();
+    }
+
+    #[test]
+    fn quarterly_and_annual_payers() {
+        let tol = 1e-10;
+        let conn = Connection::open(":memory:").unwrap();
+        let mut db = SqliteDB::new(&conn);
+        db.init().unwrap();
+        let as_of = NaiveDate::from_ymd(2020, 12, 31);
+
+        // quarterly dividends of 0.25 per share in 2017, growing by 0.01 per year,
+        // the position is doubled in 2019
+        let quarterly = insert_asset(&mut db, "Quarterly", 100., 50.);
+        db.insert_transaction(&transaction(
+            TransactionType::Asset {
+                asset_id: quarterly,
+                position: 100.,
+            },
+            -5000.,
+            NaiveDate::from_ymd(2019, 1, 2),
+        ))
+        .unwrap();
+        for year in 2017..=2020 {
+            let dps = 0.25 + 0.01 * (year - 2017) as f64;
+            let position = if year >= 2019 { 200. } else { 100. };
+            for month in &[3, 6, 9, 12] {
+                let date = NaiveDate::from_ymd(year, *month, 15);
+                insert_dividend(&mut db, quarterly, dps * position, date);
+            }
+        }
+        let info = dividend_yield(&mut db, quarterly, as_of).unwrap();
+        assert_eq!(info.coverage, DividendCoverage::Regular);
+        assert_eq!(info.ttm_payments, 4);
+        assert_fuzzy_eq!(info.ttm_dividend_per_share, 1.12, tol);
+        assert_fuzzy_eq!(info.dividend_yield, 1.12 / 50., tol);
+        assert_fuzzy_eq!(
+            info.growth_3y.unwrap(),
+            (1.12_f64 / 1.0).powf(1. / 3.) - 1.,
+            tol
+        );
+        assert!(info.growth_5y.is_none());
+        // no history to compare the first year of payments with
+        let first_year = NaiveDate::from_ymd(2017, 12, 31);
+        let info = dividend_yield(&mut db, quarterly, first_year).unwrap();
+        assert_eq!(info.coverage, DividendCoverage::Irregular);
+        assert!(info.growth_3y.is_none());
+
+        // annual dividend of 2 per share, which has been skipped in 2020
+        let annual = insert_asset(&mut db, "Annual", 10., 40.);
+        for year in 2015..=2019 {
+            insert_dividend(&mut db, annual, 20., NaiveDate::from_ymd(year, 5, 10));
+        }
+        let info = dividend_yield(&mut db, annual, NaiveDate::from_ymd(2019, 12, 31)).unwrap();
+        assert_eq!(info.coverage, DividendCoverage::Regular);
+        assert_fuzzy_eq!(info.dividend_yield, 2. / 40., tol);
+        assert_fuzzy_eq!(info.growth_3y.unwrap(), 0., tol);
+        let info = dividend_yield(&mut db, annual, as_of).unwrap();
+        assert_eq!(info.coverage, DividendCoverage::Suspended);
+        assert_eq!(info.ttm_payments, 0);
+        assert_fuzzy_eq!(info.dividend_yield, 0., tol);
+        assert_fuzzy_eq!(info.growth_3y.unwrap(), -1., tol);
+        assert_fuzzy_eq!(info.growth_5y.unwrap(), -1., tol);
+
+        let none = insert_asset(&mut db, "Growth", 5., 100.);
+        let info = dividend_yield(&mut db, none, as_of).unwrap();
+        assert_eq!(info.coverage, DividendCoverage::NoDividends);
+        assert!(info.growth_3y.is_none());
+
+        let yields =
+            position_dividend_yields(&mut db, &[(quarterly, 200.), (none, 5.)], as_of).unwrap();
+        assert_eq!(yields.len(), 2);
+        assert_eq!(yields[0].1.as_ref().unwrap().ttm_payments, 4);
+        assert!(yields[1].1.is_none());
+    }
+
+    #[test]
+    fn accumulating_fund() {
+        let conn = Connection::open(":memory:").unwrap();
+        let mut db = SqliteDB::new(&conn);
+        db.init().unwrap();
+        let as_of = NaiveDate::from_ymd(2020, 12, 31);
+        let fund = insert_asset(&mut db, "World ETF (Acc)", 20., 60.);
+        let asset = db.get_asset_by_id(fund).unwrap();
+        db.update_asset(&asset.with_distribution_policy(DistributionPolicy::Accumulating))
+            .unwrap();
+        let info = dividend_yield(&mut db, fund, as_of).unwrap();
+        assert_eq!(info.coverage, DividendCoverage::Accumulating);
+        assert_eq!(info.ttm_payments, 0);
+        assert_fuzzy_eq!(info.dividend_yield, 0., 1e-10);
+        assert_fuzzy_eq!(info.price, 60., 1e-10);
+
+        let yields = position_dividend_yields(&mut db, &[(fund, 20.)], as_of).unwrap();
+        assert_eq!(
+            yields[0].1.as_ref().unwrap().coverage,
+            DividendCoverage::Accumulating
+        );
+    }
+
+    #[test]
+    fn dividend_of_closed_position() {
+        let conn = Connection::open(":memory:").unwrap();
+        let mut db = SqliteDB::new(&conn);
+        db.init().unwrap();
+        let asset_id = insert_asset(&mut db, "Annual", 10., 40.);
+        db.insert_transaction(&transaction(
+            TransactionType::Asset {
+                asset_id,
+                position: -10.,
+            },
+            400.,
+            NaiveDate::from_ymd(2020, 5, 1),
+        ))
+        .unwrap();
+        // paid after the position has been sold, but within the look back period
+        insert_dividend(&mut db, asset_id, 20., NaiveDate::from_ymd(2020, 5, 10));
+        let as_of = NaiveDate::from_ymd(2020, 12, 31);
+        let history = dividend_history(&mut db, asset_id, as_of).unwrap();
+        assert_eq!(history.len(), 1);
+        assert_fuzzy_eq!(history[0].dividend_per_share, 2., 1e-10);
+
+        // without any position at all, the dividend per share is unknown
+        insert_dividend(&mut db, asset_id, 20., NaiveDate::from_ymd(2020, 11, 10));
+        assert!(dividend_history(&mut db, asset_id, as_of).is_err());
+    }
+}
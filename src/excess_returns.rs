@@ -0,0 +1,370 @@
+//! Return statistics in excess of a risk-free rate.
+//!
+//! Since money market rates differ between currencies, excess returns are calculated with
+//! respect to the risk-free rate of the currency an asset or a portfolio sleeve is valued in.
+//! The rates are taken from fixing series stored as quotes of a rate asset, e.g. €STR for EUR
+//! and SOFR for USD, configured per currency in `RiskFreeProxies`. For the period between two
+//! valuation dates, the fixing on or before the period's start accrues linearly with the year
+//! fraction given by the day count convention of the series.
+
+use std::collections::BTreeMap;
+
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+
+use finql_data::{Currency, CurrencyConverter, DataError, QuoteHandler};
+
+use crate::analytics_error::AnalyticsError;
+use crate::day_count_conv::DayCountConv;
+use crate::diagnostics::{DiagnosticCode, Diagnostics};
+use crate::returns::simple_returns;
+
+/// Fixing series used as risk-free rate of a currency
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RiskFreeSeries {
+    /// Name of the asset whose quotes are the fixings, given as annual rates in decimal
+    /// notation, e.g. 0.005 for 0.5%
+    pub rate_asset: String,
+    /// Day count convention of the fixings, usually Act/360 or Act/365
+    pub day_count: DayCountConv,
+}
+
+/// Settings mapping currencies to the fixing series used as their risk-free rate
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RiskFreeProxies {
+    /// Series indexed by currency code
+    series: BTreeMap<String, RiskFreeSeries>,
+}
+
+impl RiskFreeProxies {
+    pub fn new() -> RiskFreeProxies {
+        RiskFreeProxies::default()
+    }
+
+    /// Use the quotes of the given rate asset as risk-free rate of `currency`
+    pub fn with_series(
+        mut self,
+        currency: Currency,
+        rate_asset: &str,
+        day_count: DayCountConv,
+    ) -> RiskFreeProxies {
+        self.series.insert(
+            currency.to_string(),
+            RiskFreeSeries {
+                rate_asset: rate_asset.to_string(),
+                day_count,
+            },
+        );
+        self
+    }
+
+    /// Fixing series configured for a currency, if any
+    pub fn get(&self, currency: Currency) -> Option<&RiskFreeSeries> {
+        self.series.get(&currency.to_string())
+    }
+
+    /// Risk-free returns of the periods between consecutive dates, which must be in ascending
+    /// order. If no series is configured for the currency, all returns are zero and a
+    /// `MissingRiskFreeRate` warning is recorded.
+    pub fn period_returns(
+        &self,
+        db: &mut dyn QuoteHandler,
+        currency: Currency,
+        dates: &[NaiveDate],
+        diagnostics: &mut Diagnostics,
+    ) -> Result<Vec<f64>, DataError> {
+        let periods = dates.len().saturating_sub(1);
+        let series = match self.get(currency) {
+            Some(series) => series,
+            None => {
+                diagnostics.warn(
+                    DiagnosticCode::MissingRiskFreeRate,
+                    Vec::new(),
+                    format!(
+                        "no risk-free rate configured for {}, excess returns are calculated over zero",
+                        currency
+                    ),
+                );
+                return Ok(vec![0.0; periods]);
+            }
+        };
+        let mut returns = Vec::with_capacity(periods);
+        for period in dates.windows(2) {
+            let time = DateTime::<Utc>::from_utc(period[0].and_hms(23, 59, 59), Utc);
+            let (fixing, _) = db.get_last_quote_before(&series.rate_asset, time)?;
+            let year_fraction = series
+                .day_count
+                .year_fraction(period[0], period[1], None, None)
+                .map_err(|e| DataError::InvalidData(e.to_string()))?;
+            returns.push(fixing.price * year_fraction);
+        }
+        Ok(returns)
+    }
+}
+
+/// Statistics of the returns of an asset or a portfolio sleeve between consecutive valuation
+/// dates. All figures refer to the length of these periods and are not annualized.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ReturnStatistics {
+    /// Currency the returns are measured in, which determines the risk-free rate
+    pub currency: Currency,
+    pub periods: usize,
+    pub mean_return: f64,
+    pub mean_risk_free_return: f64,
+    pub mean_excess_return: f64,
+    /// Sample standard deviation of the excess returns
+    pub excess_volatility: f64,
+    /// Mean excess return per unit of volatility, not defined for less than two periods or
+    /// constant excess returns
+    pub sharpe_ratio: Option<f64>,
+}
+
+impl ReturnStatistics {
+    /// Calculate statistics from the returns of consecutive periods and the risk-free returns
+    /// of the same periods
+    pub fn new(
+        currency: Currency,
+        returns: &[f64],
+        risk_free_returns: &[f64],
+    ) -> Result<ReturnStatistics, AnalyticsError> {
+        if returns.is_empty() {
+            return Err(AnalyticsError::NoData);
+        }
+        if returns.len() != risk_free_returns.len() {
+            return Err(AnalyticsError::InvalidInput(format!(
+                "{} returns, but {} risk-free returns given",
+                returns.len(),
+                risk_free_returns.len()
+            )));
+        }
+        if let Some(value) = returns
+            .iter()
+            .chain(risk_free_returns)
+            .find(|r| !r.is_finite())
+        {
+            return Err(AnalyticsError::InvalidReturn(*value));
+        }
+        let periods = returns.len();
+        let mean = |values: &[f64]| values.iter().sum::<f64>() / values.len() as f64;
+        let excess_returns: Vec<f64> = returns
+            .iter()
+            .zip(risk_free_returns)
+            .map(|(r, rf)| r - rf)
+            .collect();
+        let mean_excess_return = mean(&excess_returns);
+        let excess_volatility = if periods > 1 {
+            (excess_returns
+                .iter()
+                .map(|r| (r - mean_excess_return).powi(2))
+                .sum::<f64>()
+                / (periods - 1) as f64)
+                .sqrt()
+        } else {
+            0.0
+        };
+        let sharpe_ratio = if excess_volatility > 0.0 {
+            Some(mean_excess_return / excess_volatility)
+        } else {
+            None
+        };
+        Ok(ReturnStatistics {
+            currency,
+            periods,
+            mean_return: mean(returns),
+            mean_risk_free_return: mean(risk_free_returns),
+            mean_excess_return,
+            excess_volatility,
+            sharpe_ratio,
+        })
+    }
+}
+
+/// Return statistics of an asset's price between consecutive dates, in excess of the risk-free
+/// rate of the currency the asset is valued in. Prices are the best quotes on or before the end
+/// of the respective day, as for `returns::asset_cagr`.
+pub fn asset_return_statistics(
+    db: &mut dyn QuoteHandler,
+    asset_id: usize,
+    dates: &[NaiveDate],
+    proxies: &RiskFreeProxies,
+    currency_converter: &mut dyn CurrencyConverter,
+    diagnostics: &mut Diagnostics,
+) -> Result<ReturnStatistics, DataError> {
+    let mut prices = Vec::with_capacity(dates.len());
+    let mut currency = None;
+    for date in dates {
+        let time = DateTime::<Utc>::from_utc(date.and_hms(23, 59, 59), Utc);
+        let (quote, quote_currency) =
+            db.get_best_quote_before(asset_id, time, currency_converter)?;
+        match currency {
+            Some(currency) if currency != quote_currency => {
+                return Err(DataError::CurrencyMismatch(format!(
+                    "prices are given in {} and {}, a reference currency is required",
+                    currency, quote_currency
+                )));
+            }
+            _ => currency = Some(quote_currency),
+        }
+        prices.push((*date, quote.price));
+    }
+    let currency = currency.ok_or_else(|| DataError::NotFound("no dates given".to_string()))?;
+    currency_return_statistics(db, currency, &prices, proxies, diagnostics)
+}
+
+/// Return statistics of a portfolio sleeve held in a single currency, given by its market
+/// values in ascending order of their dates, in excess of the currency's risk-free rate
+pub fn currency_return_statistics(
+    db: &mut dyn QuoteHandler,
+    currency: Currency,
+    valuations: &[(NaiveDate, f64)],
+    proxies: &RiskFreeProxies,
+    diagnostics: &mut Diagnostics,
+) -> Result<ReturnStatistics, DataError> {
+    let dates: Vec<NaiveDate> = valuations.iter().map(|(date, _)| *date).collect();
+    if dates.windows(2).any(|period| period[1] <= period[0]) {
+        return Err(DataError::InvalidData(
+            "valuations must be in ascending order of dates".to_string(),
+        ));
+    }
+    let returns: Vec<f64> = simple_returns(valuations.iter().map(|(_, value)| *value)).collect();
+    let risk_free_returns = proxies.period_returns(db, currency, &dates, diagnostics)?;
+    ReturnStatistics::new(currency, &returns, &risk_free_returns)
+        .map_err(|e| DataError::InvalidData(e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    use rusqlite::Connection;
+    use std::str::FromStr;
+
+    use finql_data::{Asset, Quote, Ticker, TickerUsage};
+    use finql_sqlite::SqliteDB;
+
+    /// Store an asset with a single ticker and quotes of the given prices and dates
+    fn insert_series(
+        db: &mut dyn QuoteHandler,
+        name: &str,
+        currency: Currency,
+        quotes: &[(NaiveDate, f64)],
+    ) -> usize {
+        let asset_id = db
+            .insert_asset(&Asset::new(None, name, None, None, None))
+            .unwrap();
+        let ticker_id = db
+            .insert_ticker(&Ticker {
+                id: None,
+                asset: asset_id,
+                name: name.to_string(),
+                currency,
+                source: "manual".to_string(),
+                priority: 1,
+                factor: 1.0,
+                source_url: None,
+                usage: TickerUsage::Both,
+                last_quote_time: None,
+                source_chain: Vec::new(),
+            })
+            .unwrap();
+        for (date, price) in quotes {
+            db.insert_quote(&Quote {
+                id: None,
+                ticker: ticker_id,
+                price: *price,
+                time: Utc.from_utc_datetime(&date.and_hms(18, 0, 0)),
+                volume: None,
+                quality_score: None,
+                source: None,
+                open: None,
+                high: None,
+                low: None,
+                bid: None,
+                ask: None,
+            })
+            .unwrap();
+        }
+        asset_id
+    }
+
+    #[test]
+    fn excess_returns_per_currency() {
+        let tol = 1e-12;
+        let conn = Connection::open(":memory:").unwrap();
+        let mut db = SqliteDB::new(&conn);
+        db.init().unwrap();
+        let eur = Currency::from_str("EUR").unwrap();
+        let usd = Currency::from_str("USD").unwrap();
+        let chf = Currency::from_str("CHF").unwrap();
+        let start = NaiveDate::from_ymd(2021, 1, 1);
+        insert_series(&mut db, "ESTR", eur, &[(start, 0.036)]);
+        insert_series(&mut db, "SOFR", usd, &[(start, 0.073)]);
+        let dates = [
+            NaiveDate::from_ymd(2021, 1, 4),
+            NaiveDate::from_ymd(2021, 1, 14),
+            NaiveDate::from_ymd(2021, 1, 24),
+        ];
+        let valuations = [(dates[0], 100.), (dates[1], 102.), (dates[2], 101.)];
+        let proxies = RiskFreeProxies::new()
+            .with_series(eur, "ESTR", DayCountConv::Act360)
+            .with_series(usd, "SOFR", DayCountConv::Act365);
+
+        let mut diagnostics = Diagnostics::new();
+        let eur_stats =
+            currency_return_statistics(&mut db, eur, &valuations, &proxies, &mut diagnostics)
+                .unwrap();
+        let usd_stats =
+            currency_return_statistics(&mut db, usd, &valuations, &proxies, &mut diagnostics)
+                .unwrap();
+        assert!(diagnostics.is_empty());
+        // equal values, but different risk-free rates: 10 days accrued by Act/360 and Act/365
+        assert_eq!(eur_stats.currency, eur);
+        assert_eq!(eur_stats.periods, 2);
+        assert_fuzzy_eq!(eur_stats.mean_return, usd_stats.mean_return, tol);
+        assert_fuzzy_eq!(eur_stats.mean_risk_free_return, 0.001, tol);
+        assert_fuzzy_eq!(usd_stats.mean_risk_free_return, 0.002, tol);
+        assert_fuzzy_eq!(
+            eur_stats.mean_excess_return - usd_stats.mean_excess_return,
+            0.001,
+            tol
+        );
+        let returns: [f64; 2] = [0.02, 101. / 102. - 1.];
+        let excess = [returns[0] - 0.001, returns[1] - 0.001];
+        let mean_excess = (excess[0] + excess[1]) / 2.;
+        let volatility = (excess[0] - excess[1]).abs() / 2_f64.sqrt();
+        assert_fuzzy_eq!(eur_stats.mean_excess_return, mean_excess, tol);
+        assert_fuzzy_eq!(eur_stats.excess_volatility, volatility, tol);
+        assert_fuzzy_eq!(
+            eur_stats.sharpe_ratio.unwrap(),
+            mean_excess / volatility,
+            tol
+        );
+
+        // sleeve in a currency without rate series falls back to zero
+        let valuations = [(dates[0], 1000.), (dates[1], 1010.)];
+        let stats =
+            currency_return_statistics(&mut db, chf, &valuations, &proxies, &mut diagnostics)
+                .unwrap();
+        assert_fuzzy_eq!(stats.mean_excess_return, 0.01, tol);
+        assert_eq!(stats.sharpe_ratio, None);
+        assert!(diagnostics.contains(DiagnosticCode::MissingRiskFreeRate));
+        assert_eq!(diagnostics.len(), 1);
+    }
+
+    #[test]
+    fn invalid_return_statistics_input() {
+        let eur = Currency::from_str("EUR").unwrap();
+        assert_eq!(
+            ReturnStatistics::new(eur, &[], &[]),
+            Err(AnalyticsError::NoData)
+        );
+        assert!(ReturnStatistics::new(eur, &[0.01, 0.02], &[0.0]).is_err());
+        assert!(matches!(
+            ReturnStatistics::new(eur, &[0.01], &[f64::NAN]),
+            Err(AnalyticsError::InvalidReturn(_))
+        ));
+        let stats = ReturnStatistics::new(eur, &[0.01], &[0.0]).unwrap();
+        assert_eq!(stats.excess_volatility, 0.0);
+        assert_eq!(stats.sharpe_ratio, None);
+    }
+}
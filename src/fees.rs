@@ -0,0 +1,309 @@
+//! Accrual of account fees, e.g. custody fees charged periodically by a broker.
+//!
+//! The fees are charged per billing period as given by the `FeeSchedule` of the account. The
+//! percentage fee is based on the average of the daily values of the account within the billing
+//! period, where each position is valued at the last quote on or before the respective day.
+//! Transactions are not assigned to accounts yet, therefore the value of an account is the value
+//! of all asset positions stored in the database.
+//!
+//! Each fee transaction is tagged by a note identifying the account and the billing period,
+//! which allows to skip billing periods for which the fee has already been booked.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::str::FromStr;
+
+use chrono::NaiveDate;
+
+use finql_data::{
+    CashFlow, DataError, FeeSchedule, QuoteHandler, Transaction, TransactionHandler,
+    TransactionType,
+};
+
+use crate::time_period::TimePeriod;
+use crate::withdrawal::PriceHistory;
+
+/// Note used to tag the fee transaction of an account for the billing period from `start` to
+/// `end` (both inclusive)
+pub fn fee_note(account_id: usize, start: NaiveDate, end: NaiveDate) -> String {
+    format!("[account fee {} {}..{}]", account_id, start, end)
+}
+
+/// Calculate the fees of all complete billing periods of the account ending on or before
+/// `up_to` which have not been booked yet. The fee transactions are stored to the database
+/// unless `dry_run` is set, in which case they are only returned as drafts without id.
+pub fn accrue_account_fees<DB: QuoteHandler + TransactionHandler>(
+    db: &mut DB,
+    account_id: usize,
+    up_to: NaiveDate,
+    dry_run: bool,
+) -> Result<Vec<Transaction>, DataError> {
+    let schedule = db.get_fee_schedule(account_id)?.ok_or_else(|| {
+        DataError::NotFound(format!("no fee schedule for account {}", account_id))
+    })?;
+    let period = TimePeriod::from_str(&schedule.billing_period)
+        .map_err(|err| DataError::InvalidData(err.to_string()))?;
+    let frequency = period.frequency().map_err(|_| {
+        DataError::InvalidData(format!(
+            "billing period {} is not a fraction of a year",
+            schedule.billing_period
+        ))
+    })?;
+
+    let transactions = db.get_all_transactions()?;
+    let booked: HashSet<String> = transactions
+        .iter()
+        .filter(|t| matches!(t.transaction_type, TransactionType::Fee { .. }))
+        .filter_map(|t| t.note.clone())
+        .collect();
+    let mut holdings = Holdings::new(&transactions);
+    let mut prices = HashMap::new();
+
+    let mut fees = Vec::new();
+    let mut start = schedule.start;
+    loop {
+        let next_start = period.add_to(start, None);
+        if next_start <= start {
+            return Err(DataError::InvalidData(format!(
+                "billing period {} must be positive",
+                schedule.billing_period
+            )));
+        }
+        let end = next_start.pred();
+        if end > up_to {
+            break;
+        }
+        let note = fee_note(account_id, start, end);
+        if !booked.contains(&note) {
+            let average_value =
+                average_value(db, &schedule, &mut holdings, &mut prices, start, end)?;
+            let fee = period_fee(&schedule, frequency, average_value);
+            if fee > 0. {
+                fees.push(Transaction {
+                    id: None,
+                    transaction_type: TransactionType::Fee {
+                        transaction_ref: None,
+                    },
+                    cash_flow: CashFlow::new(-fee, schedule.currency, end),
+                    note: Some(note),
+                    execution_meta: None,
+                    recorded_at: None,
+                });
+            }
+        }
+        start = next_start;
+    }
+
+    if !dry_run {
+        for fee in fees.iter_mut() {
+            fee.id = Some(db.insert_transaction(fee)?);
+        }
+    }
+    Ok(fees)
+}
+
+/// Fee of a billing period, given the average value of the account
+fn period_fee(schedule: &FeeSchedule, frequency: u16, average_value: f64) -> f64 {
+    let mut fee = schedule.rate_pa / frequency as f64 * average_value;
+    if let Some(max_fee) = schedule.max_fee {
+        fee = fee.min(max_fee);
+    }
+    // The minimum fee is only charged if there are holdings at all
+    if let Some(min_fee) = schedule.min_fee {
+        if average_value > 0. {
+            fee = fee.max(min_fee);
+        }
+    }
+    fee + schedule.fixed_fee
+}
+
+/// Average of the daily values of the holdings from `start` to `end` (both inclusive)
+fn average_value<DB: QuoteHandler>(
+    db: &mut DB,
+    schedule: &FeeSchedule,
+    holdings: &mut Holdings,
+    prices: &mut HashMap<usize, PriceHistory>,
+    start: NaiveDate,
+    end: NaiveDate,
+) -> Result<f64, DataError> {
+    let mut total = 0.;
+    let mut days = 0;
+    let mut date = start;
+    while date <= end {
+        for (asset_id, position) in holdings.at(date) {
+            if !prices.contains_key(asset_id) {
+                let history = PriceHistory::load(db, *asset_id, schedule.currency)?;
+                prices.insert(*asset_id, history);
+            }
+            total += position * prices[asset_id].price(*asset_id, date)?;
+        }
+        days += 1;
+        date = date.succ();
+    }
+    Ok(total / days as f64)
+}
+
+/// Asset positions over time, evaluated for increasing dates
+struct Holdings {
+    /// Changes of positions in chronological order
+    changes: Vec<(NaiveDate, usize, f64)>,
+    /// Number of changes already applied to `positions`
+    applied: usize,
+    positions: BTreeMap<usize, f64>,
+}
+
+impl Holdings {
+    fn new(transactions: &[Transaction]) -> Holdings {
+        let mut changes: Vec<(NaiveDate, usize, f64)> = transactions
+            .iter()
+            .filter_map(|t| match t.transaction_type {
+                TransactionType::Asset { asset_id, position }
+                | TransactionType::Transfer {
+                    asset_id, position, ..
+                } => Some((t.cash_flow.date, asset_id, position)),
+                _ => None,
+            })
+            .collect();
+        changes.sort_by_key(|(date, _, _)| *date);
+        Holdings {
+            changes,
+            applied: 0,
+            positions: BTreeMap::new(),
+        }
+    }
+
+    /// Positions at the end of the given date, which must not be before any previously
+    /// requested date
+    fn at(&mut self, date: NaiveDate) -> &BTreeMap<usize, f64> {
+        while let Some((change_date, asset_id, position)) = self.changes.get(self.applied) {
+            if *change_date > date {
+                break;
+            }
+            *self.positions.entry(*asset_id).or_insert(0.) += position;
+            self.applied += 1;
+        }
+        self.positions.retain(|_, position| position.abs() > 1e-10);
+        &self.positions
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{TimeZone, Utc};
+    use rusqlite::Connection;
+
+    use finql_data::{Asset, AssetHandler, Currency, Quote, Ticker, TickerUsage};
+    use finql_sqlite::SqliteDB;
+
+    fn eur() -> Currency {
+        Currency::from_str("EUR").unwrap()
+    }
+
+    fn buy(db: &mut SqliteDB, asset_id: usize, position: f64, date: NaiveDate) {
+        db.insert_transaction(&Transaction {
+            id: None,
+            transaction_type: TransactionType::Asset { asset_id, position },
+            cash_flow: CashFlow::new(-10. * position, eur(), date),
+            note: None,
+            execution_meta: None,
+            recorded_at: None,
+        })
+        .unwrap();
+    }
+
+    fn schedule(min_fee: Option<f64>, max_fee: Option<f64>) -> FeeSchedule {
+        FeeSchedule {
+            account_id: 1,
+            currency: eur(),
+            rate_pa: 0.004,
+            min_fee,
+            max_fee,
+            fixed_fee: 0.,
+            billing_period: "3M".to_string(),
+            start: NaiveDate::from_ymd(2020, 1, 1),
+        }
+    }
+
+    #[test]
+    fn accrue_fees_with_changing_holdings() {
+        let tol = 1e-6;
+        let conn = Connection::open(":memory:").unwrap();
+        let mut db = SqliteDB::new(&conn);
+        db.init().unwrap();
+        let asset_id = db
+            .insert_asset(&Asset::new(None, "A", None, None, None))
+            .unwrap();
+        let ticker = db
+            .insert_ticker(&Ticker {
+                id: None,
+                name: "A".to_string(),
+                asset: asset_id,
+                source: "manual".to_string(),
+                priority: 1,
+                currency: eur(),
+                factor: 1.0,
+                source_url: None,
+                usage: TickerUsage::Both,
+                last_quote_time: None,
+                source_chain: Vec::new(),
+            })
+            .unwrap();
+        db.insert_quote(&Quote {
+            id: None,
+            ticker,
+            price: 10.,
+            time: Utc.ymd(2019, 12, 31).and_hms(17, 0, 0),
+            volume: None,
+            quality_score: None,
+            source: None,
+            open: None,
+            high: None,
+            low: None,
+            bid: None,
+            ask: None,
+        })
+        .unwrap();
+        // 100 units held during the whole first quarter (91 days), 900 units added for the
+        // last 31 days of the second quarter (91 days)
+        buy(&mut db, asset_id, 100., NaiveDate::from_ymd(2019, 12, 1));
+        buy(&mut db, asset_id, 900., NaiveDate::from_ymd(2020, 5, 31));
+
+        db.set_fee_schedule(&schedule(None, None)).unwrap();
+        let up_to = NaiveDate::from_ymd(2020, 7, 15);
+        let drafts = accrue_account_fees(&mut db, 1, up_to, true).unwrap();
+        assert_eq!(drafts.len(), 2);
+        assert!(drafts.iter().all(|fee| fee.id.is_none()));
+        assert_fuzzy_eq!(drafts[0].cash_flow.amount.amount, -1., tol);
+        assert_eq!(drafts[0].cash_flow.date, NaiveDate::from_ymd(2020, 3, 31));
+        let average = 1000. + 9000. * 31. / 91.;
+        assert_fuzzy_eq!(drafts[1].cash_flow.amount.amount, -0.001 * average, tol);
+        assert_eq!(
+            drafts[1].note.as_deref(),
+            Some("[account fee 1 2020-04-01..2020-06-30]")
+        );
+        // dry run does not book anything
+        assert_eq!(db.get_all_transactions().unwrap().len(), 2);
+
+        let booked = accrue_account_fees(&mut db, 1, up_to, false).unwrap();
+        assert_eq!(booked.len(), 2);
+        assert!(booked.iter().all(|fee| fee.id.is_some()));
+        assert_eq!(db.get_all_transactions().unwrap().len(), 4);
+        // already booked periods are skipped
+        let again = accrue_account_fees(&mut db, 1, up_to, false).unwrap();
+        assert!(again.is_empty());
+        let next = accrue_account_fees(&mut db, 1, NaiveDate::from_ymd(2020, 9, 30), true).unwrap();
+        assert_eq!(next.len(), 1);
+        assert_fuzzy_eq!(next[0].cash_flow.amount.amount, -10., tol);
+
+        // minimum and maximum fees per period
+        let mut bounded = schedule(Some(2.), Some(4.));
+        bounded.account_id = 2;
+        bounded.fixed_fee = 0.5;
+        db.set_fee_schedule(&bounded).unwrap();
+        let drafts = accrue_account_fees(&mut db, 2, up_to, true).unwrap();
+        assert_fuzzy_eq!(drafts[0].cash_flow.amount.amount, -2.5, tol);
+        assert_fuzzy_eq!(drafts[1].cash_flow.amount.amount, -4.5, tol);
+
+        assert!(accrue_account_fees(&mut db, 3, up_to, true).is_err());
+    }
+}
@@ -0,0 +1,726 @@
+//! Amortizing loans, e.g. mortgages, with roll-out of the payment schedule
+//! split into interest and principal repayments and reconciliation of the
+//! schedule against the cash transactions actually booked.
+
+use std::error::Error;
+use std::fmt;
+
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+
+use finql_data::cash_flow::CashFlow;
+use finql_data::currency::Currency;
+use finql_data::{DataError, InstrumentTerms, TransactionHandler, TransactionType};
+
+use crate::calendar::Calendar;
+use crate::day_adjust::DayAdjust;
+use crate::day_count_conv::{DayCountConv, DayCountConvError};
+use crate::fixed_income::FixedIncome;
+use crate::market::{Market, MarketError};
+use crate::rates::DiscountError;
+use crate::time_period::TimePeriod;
+
+/// Balances below this threshold are considered to be fully repaid
+const BALANCE_TOLERANCE: f64 = 1e-8;
+/// Maximum number of days a booked payment may deviate from the scheduled payment date
+const PAYMENT_DATE_TOLERANCE: i64 = 3;
+/// Maximum deviation of a booked payment from the scheduled amount
+const PAYMENT_AMOUNT_TOLERANCE: f64 = 0.005;
+
+/// Error related to loans
+#[derive(Debug)]
+pub enum LoanError {
+    DiscountingFailure(DiscountError),
+    MissingCalendar,
+    DayCountError(DayCountConvError),
+    /// The payment period must be positive and correspond to a fixed number of payments per year
+    InvalidPaymentPeriod,
+    DBError(DataError),
+}
+
+impl fmt::Display for LoanError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LoanError::MissingCalendar => write!(f, "unknown calendar"),
+            LoanError::DayCountError(_) => {
+                write!(f, "invalid day count convention in this context")
+            }
+            LoanError::DiscountingFailure(_) => write!(f, "discounting cash flows failed"),
+            LoanError::InvalidPaymentPeriod => {
+                write!(f, "payment period is not positive or has no fixed frequency per year")
+            }
+            LoanError::DBError(_) => write!(f, "database error"),
+        }
+    }
+}
+
+impl Error for LoanError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            LoanError::DayCountError(err) => Some(err),
+            LoanError::DiscountingFailure(err) => Some(err),
+            LoanError::DBError(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<DayCountConvError> for LoanError {
+    fn from(error: DayCountConvError) -> Self {
+        LoanError::DayCountError(error)
+    }
+}
+
+impl From<MarketError> for LoanError {
+    fn from(_: MarketError) -> Self {
+        LoanError::MissingCalendar
+    }
+}
+
+impl From<DiscountError> for LoanError {
+    fn from(error: DiscountError) -> Self {
+        LoanError::DiscountingFailure(error)
+    }
+}
+
+impl From<DataError> for LoanError {
+    fn from(error: DataError) -> Self {
+        LoanError::DBError(error)
+    }
+}
+
+/// Method of repaying the principal
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq)]
+pub enum Amortization {
+    /// Constant installment of interest and principal
+    #[serde(rename = "annuity")]
+    Annuity,
+    /// Constant principal repayment, interest is paid on top
+    #[serde(rename = "linear")]
+    Linear,
+}
+
+/// Effect of extra repayments (and of rate changes) on the remaining schedule
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq)]
+pub enum ExtraRepaymentEffect {
+    /// Regular payments stay the same, the loan is repaid earlier
+    #[serde(rename = "duration")]
+    ReduceDuration,
+    /// Regular payments are reduced, the loan is repaid at maturity
+    #[serde(rename = "installment")]
+    ReduceInstallment,
+}
+
+/// New interest rate (in percent p.a.) effective from the given date on
+#[derive(Deserialize, Serialize, Debug, Clone, Copy)]
+pub struct RateChange {
+    pub date: NaiveDate,
+    pub rate: f64,
+}
+
+/// Unscheduled repayment of principal
+#[derive(Deserialize, Serialize, Debug, Clone, Copy)]
+pub struct ExtraRepayment {
+    pub date: NaiveDate,
+    pub amount: f64,
+}
+
+/// Amortizing loan, e.g. a mortgage
+#[derive(Deserialize, Serialize, Debug)]
+pub struct Loan {
+    pub currency: Currency,
+    /// Amount paid out at start date
+    pub principal: f64,
+    /// Initial interest rate in percent p.a.
+    pub rate: f64,
+    /// Changes of the interest rate during the life time of the loan
+    #[serde(default)]
+    pub rate_changes: Vec<RateChange>,
+    pub amortization: Amortization,
+    /// Time between two regular payments, e.g. "1M" for monthly payments
+    pub payment_period: TimePeriod,
+    pub start_date: NaiveDate,
+    pub maturity: NaiveDate,
+    pub business_day_rule: DayAdjust,
+    pub calendar: String,
+    pub day_count_convention: DayCountConv,
+    #[serde(default)]
+    pub extra_repayments: Vec<ExtraRepayment>,
+    pub extra_repayment_effect: ExtraRepaymentEffect,
+}
+
+impl InstrumentTerms for Loan {
+    const KIND: &'static str = "loan";
+    const VERSION: u32 = 1;
+}
+
+/// Single payment of the loan schedule
+#[derive(Debug, Clone, Copy)]
+pub struct LoanPayment {
+    /// Payment date, adjusted by the business day rule for regular payments
+    pub date: NaiveDate,
+    pub interest: f64,
+    pub principal: f64,
+    /// Outstanding balance after this payment
+    pub balance: f64,
+    /// True for extra repayments, false for regular payments
+    pub extra_repayment: bool,
+}
+
+impl LoanPayment {
+    /// Total amount paid
+    pub fn amount(&self) -> f64 {
+        self.interest + self.principal
+    }
+}
+
+/// Kind of discrepancy between scheduled and booked loan payment
+#[derive(Debug, Clone, PartialEq)]
+pub enum LoanPaymentIssue {
+    /// No cash transaction found around the scheduled payment date
+    Missed,
+    /// Cash transaction found around the scheduled payment date, but with a different amount
+    Mismatched {
+        transaction_id: Option<usize>,
+        amount: f64,
+    },
+}
+
+/// Scheduled loan payment that could not be reconciled against the booked transactions
+#[derive(Debug, Clone)]
+pub struct LoanPaymentDiscrepancy {
+    pub date: NaiveDate,
+    /// Expected cash amount (negative, since paid by the borrower)
+    pub expected: f64,
+    pub issue: LoanPaymentIssue,
+}
+
+impl Loan {
+    /// Interest rate in percent p.a. that is effective at the given date
+    fn rate_at(&self, date: NaiveDate) -> f64 {
+        let mut rate = self.rate;
+        let mut last_change = None;
+        for change in &self.rate_changes {
+            if change.date <= date && last_change.map_or(true, |d| change.date >= d) {
+                rate = change.rate;
+                last_change = Some(change.date);
+            }
+        }
+        rate
+    }
+
+    /// Interest accrued on a constant balance between `start` and `end`, honouring rate changes
+    fn interest_between(
+        &self,
+        balance: f64,
+        start: NaiveDate,
+        end: NaiveDate,
+        roll_date: NaiveDate,
+    ) -> Result<f64, DayCountConvError> {
+        let mut change_dates: Vec<NaiveDate> = self
+            .rate_changes
+            .iter()
+            .map(|c| c.date)
+            .filter(|d| *d > start && *d < end)
+            .collect();
+        change_dates.sort();
+        change_dates.push(end);
+        let mut interest = 0.;
+        let mut accrual_start = start;
+        for accrual_end in change_dates {
+            let year_fraction = self.day_count_convention.year_fraction(
+                accrual_start,
+                accrual_end,
+                Some(roll_date),
+                Some(self.payment_period),
+            )?;
+            interest += balance * self.rate_at(accrual_start) / 100. * year_fraction;
+            accrual_start = accrual_end;
+        }
+        Ok(interest)
+    }
+
+    /// Regular payment per period; the full installment for annuities,
+    /// the principal repayment for linear amortization
+    fn installment(&self, balance: f64, rate: f64, periods: usize, frequency: u16) -> f64 {
+        let periods = periods.max(1);
+        let period_rate = rate / 100. / frequency as f64;
+        match self.amortization {
+            Amortization::Annuity if period_rate.abs() > 1e-12 => {
+                balance * period_rate / (1. - (1. + period_rate).powi(-(periods as i32)))
+            }
+            _ => balance / periods as f64,
+        }
+    }
+
+    /// Unadjusted end dates of all regular payment periods. Each date is derived from the
+    /// start date directly, so that month-end start dates don't drift to earlier days.
+    /// The payment period must be positive, otherwise the maturity is never reached.
+    fn period_end_dates(&self) -> Vec<NaiveDate> {
+        let mut dates = Vec::new();
+        let mut date = self.start_date;
+        let mut periods = 0;
+        while date < self.maturity {
+            periods += 1;
+            date = self
+                .payment_period
+                .times(periods)
+                .add_to(self.start_date, None)
+                .min(self.maturity);
+            dates.push(date);
+        }
+        dates
+    }
+
+    /// Roll out all payments; regular payment dates are adjusted if a calendar is given.
+    /// Rate changes and, depending on `extra_repayment_effect`, extra repayments trigger
+    /// a recalculation of the regular payment over the remaining regular periods.
+    fn schedule(&self, cal: Option<&Calendar>) -> Result<Vec<LoanPayment>, LoanError> {
+        if !self.payment_period.is_positive() {
+            return Err(LoanError::InvalidPaymentPeriod);
+        }
+        let frequency = self
+            .payment_period
+            .frequency()
+            .map_err(|_| LoanError::InvalidPaymentPeriod)?;
+        let period_ends = self.period_end_dates();
+        let mut extra_repayments = self.extra_repayments.clone();
+        extra_repayments.sort_by_key(|repayment| repayment.date);
+
+        let mut payments = Vec::new();
+        let mut balance = self.principal;
+        let mut rate = self.rate_at(self.start_date);
+        let mut installment = self.installment(balance, rate, period_ends.len(), frequency);
+        let mut recalculate = false;
+        let mut period_start = self.start_date;
+        for (i, &period_end) in period_ends.iter().enumerate() {
+            if balance <= BALANCE_TOLERANCE {
+                break;
+            }
+            let current_rate = self.rate_at(period_start);
+            if recalculate || current_rate != rate {
+                rate = current_rate;
+                installment = self.installment(balance, rate, period_ends.len() - i, frequency);
+                recalculate = false;
+            }
+
+            let mut interest = 0.;
+            let mut accrual_start = period_start;
+            for extra in extra_repayments
+                .iter()
+                .filter(|e| e.date > period_start && e.date <= period_end)
+            {
+                interest += self.interest_between(balance, accrual_start, extra.date, period_start)?;
+                accrual_start = extra.date;
+                let principal = extra.amount.min(balance);
+                balance -= principal;
+                payments.push(LoanPayment {
+                    date: extra.date,
+                    interest: 0.,
+                    principal,
+                    balance,
+                    extra_repayment: true,
+                });
+                recalculate = self.extra_repayment_effect == ExtraRepaymentEffect::ReduceInstallment;
+            }
+            interest += self.interest_between(balance, accrual_start, period_end, period_start)?;
+
+            let principal = if i + 1 == period_ends.len() {
+                balance
+            } else {
+                match self.amortization {
+                    Amortization::Annuity => (installment - interest).max(0.).min(balance),
+                    Amortization::Linear => installment.min(balance),
+                }
+            };
+            balance -= principal;
+            let date = match cal {
+                Some(cal) => self.business_day_rule.adjust_date(period_end, cal),
+                None => period_end,
+            };
+            payments.push(LoanPayment {
+                date,
+                interest,
+                principal,
+                balance,
+                extra_repayment: false,
+            });
+            period_start = period_end;
+        }
+        Ok(payments)
+    }
+
+    /// Roll out all loan payments split into interest and principal repayment
+    pub fn payment_schedule(&self, market: &Market) -> Result<Vec<LoanPayment>, LoanError> {
+        let cal = market.get_calendar(&self.calendar)?;
+        self.schedule(Some(cal))
+    }
+
+    /// Outstanding balance at the end of the given date, i.e. after all payments due on or before that day
+    pub fn outstanding_balance(&self, date: NaiveDate, market: &Market) -> Result<f64, LoanError> {
+        let repaid: f64 = self
+            .payment_schedule(market)?
+            .iter()
+            .filter(|p| p.date <= date)
+            .map(|p| p.principal)
+            .sum();
+        Ok((self.principal - repaid).max(0.))
+    }
+}
+
+impl FixedIncome for Loan {
+    type Error = LoanError;
+
+    /// Convert loan in stream of cash flows, from the perspective of the lender.
+    /// Interest and principal of each payment are returned as separate cash flows.
+    fn rollout_cash_flows(
+        &self,
+        position: f64,
+        market: &Market,
+    ) -> Result<Vec<CashFlow>, LoanError> {
+        let mut cfs = Vec::new();
+        for payment in self.payment_schedule(market)? {
+            if payment.interest != 0. {
+                cfs.push(CashFlow::new(
+                    position * payment.interest,
+                    self.currency,
+                    payment.date,
+                ));
+            }
+            cfs.push(CashFlow::new(
+                position * payment.principal,
+                self.currency,
+                payment.date,
+            ));
+        }
+        Ok(cfs)
+    }
+
+    fn accrued_interest(&self, today: NaiveDate) -> Result<f64, LoanError> {
+        if today <= self.start_date || today >= self.maturity {
+            return Ok(0.);
+        }
+        let mut balance = self.principal;
+        let mut accrual_start = self.start_date;
+        let mut roll_date = self.start_date;
+        let mut interest = 0.;
+        for payment in self.schedule(None)? {
+            if payment.date > today {
+                break;
+            }
+            if payment.extra_repayment {
+                // interest up to the extra repayment is still due with the next regular payment
+                interest += self.interest_between(balance, accrual_start, payment.date, roll_date)?;
+            } else {
+                interest = 0.;
+                roll_date = payment.date;
+            }
+            accrual_start = payment.date;
+            balance = payment.balance;
+        }
+        interest += self.interest_between(balance, accrual_start, today, roll_date)?;
+        Ok(interest)
+    }
+}
+
+/// Reconcile the payment schedule of a loan, seen from the perspective of the borrower,
+/// against the cash transactions booked in the database.
+/// Every scheduled payment due until `today` is matched with an unused cash transaction
+/// in the loan's currency booked within a few days around the payment date.
+/// Payments without any such transaction are reported as missed, payments for which
+/// only transactions with a different amount are found are reported as mismatched.
+pub fn reconcile_loan(
+    db: &mut dyn TransactionHandler,
+    loan: &Loan,
+    market: &Market,
+    today: NaiveDate,
+) -> Result<Vec<LoanPaymentDiscrepancy>, LoanError> {
+    let mut cash_transactions: Vec<(Option<usize>, NaiveDate, f64)> = db
+        .get_all_transactions()?
+        .into_iter()
+        .filter(|t| matches!(t.transaction_type, TransactionType::Cash))
+        .filter(|t| t.cash_flow.amount.currency == loan.currency)
+        .map(|t| (t.id, t.cash_flow.date, t.cash_flow.amount.amount))
+        .collect();
+    let mut discrepancies = Vec::new();
+    for payment in loan.payment_schedule(market)? {
+        if payment.date > today {
+            break;
+        }
+        let expected = -payment.amount();
+        let mut candidates: Vec<usize> = (0..cash_transactions.len())
+            .filter(|&i| {
+                (cash_transactions[i].1 - payment.date).num_days().abs() <= PAYMENT_DATE_TOLERANCE
+            })
+            .collect();
+        candidates.sort_by_key(|&i| (cash_transactions[i].1 - payment.date).num_days().abs());
+        let matching = candidates
+            .iter()
+            .find(|&&i| (cash_transactions[i].2 - expected).abs() <= PAYMENT_AMOUNT_TOLERANCE);
+        let issue = match (matching, candidates.first()) {
+            (Some(&i), _) => {
+                cash_transactions.remove(i);
+                continue;
+            }
+            (None, Some(&i)) => {
+                let (transaction_id, _, amount) = cash_transactions.remove(i);
+                LoanPaymentIssue::Mismatched {
+                    transaction_id,
+                    amount,
+                }
+            }
+            (None, None) => LoanPaymentIssue::Missed,
+        };
+        discrepancies.push(LoanPaymentDiscrepancy {
+            date: payment.date,
+            expected,
+            issue,
+        });
+    }
+    Ok(discrepancies)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+    use rusqlite::Connection;
+
+    use finql_data::{Transaction, TransactionHandler};
+    use finql_sqlite::SqliteDB;
+
+    use super::*;
+
+    fn mortgage(amortization: &str, effect: &str) -> Loan {
+        let data = format!(
+            r#"{{
+            "currency": "EUR",
+            "principal": 120000,
+            "rate": 3,
+            "amortization": "{}",
+            "payment_period": "1M",
+            "start_date": "2020-01-01",
+            "maturity": "2030-01-01",
+            "business_day_rule": "none",
+            "calendar": "TARGET",
+            "day_count_convention": "30/360",
+            "extra_repayment_effect": "{}"
+        }}"#,
+            amortization, effect
+        );
+        serde_json::from_str(&data).unwrap()
+    }
+
+    fn annuity(balance: f64, rate: f64, periods: i32) -> f64 {
+        let r = rate / 1200.;
+        balance * r / (1. - (1. + r).powi(-periods))
+    }
+
+    fn regular(payments: &[LoanPayment]) -> Vec<LoanPayment> {
+        payments.iter().filter(|p| !p.extra_repayment).cloned().collect()
+    }
+
+    #[test]
+    fn annuity_rollout() {
+        let tol = 1e-8;
+        let loan = mortgage("annuity", "duration");
+        let conn = Connection::open(":memory:").unwrap();
+        let mut db = SqliteDB::new(&conn);
+        db.init().unwrap();
+        let market = Market::new(&mut db);
+
+        let payments = loan.payment_schedule(&market).unwrap();
+        assert_eq!(payments.len(), 120);
+        let installment = annuity(120000., 3., 120);
+        for payment in &payments {
+            assert_fuzzy_eq!(payment.amount(), installment, tol);
+        }
+        assert_fuzzy_eq!(payments[0].interest, 300., tol);
+        assert_fuzzy_eq!(payments[119].balance, 0., tol);
+
+        let cash_flows = loan.rollout_cash_flows(-1., &market).unwrap();
+        assert_eq!(cash_flows.len(), 240);
+        let principal: f64 = payments.iter().map(|p| p.principal).sum();
+        assert_fuzzy_eq!(principal, 120000., tol);
+
+        let balance = loan
+            .outstanding_balance(NaiveDate::from_ymd(2020, 3, 1), &market)
+            .unwrap();
+        assert_fuzzy_eq!(balance, payments[1].balance, tol);
+        let accrued = loan.accrued_interest(NaiveDate::from_ymd(2020, 3, 16)).unwrap();
+        assert_fuzzy_eq!(accrued, payments[1].balance * 0.03 * 15. / 360., tol);
+    }
+
+    #[test]
+    fn reject_non_positive_payment_period() {
+        let mut loan = mortgage("annuity", "duration");
+        for period in &["-1M", "0M", "-1Y"] {
+            loan.payment_period = TimePeriod::from_str(period).unwrap();
+            assert!(matches!(loan.schedule(None), Err(LoanError::InvalidPaymentPeriod)));
+        }
+    }
+
+    #[test]
+    fn month_end_payment_dates() {
+        let mut loan = mortgage("linear", "duration");
+        loan.start_date = NaiveDate::from_ymd(2021, 1, 31);
+        loan.maturity = NaiveDate::from_ymd(2021, 5, 31);
+        let dates: Vec<NaiveDate> = loan.schedule(None).unwrap().iter().map(|p| p.date).collect();
+        assert_eq!(
+            dates,
+            vec![
+                NaiveDate::from_ymd(2021, 2, 28),
+                NaiveDate::from_ymd(2021, 3, 31),
+                NaiveDate::from_ymd(2021, 4, 30),
+                NaiveDate::from_ymd(2021, 5, 31),
+            ]
+        );
+    }
+
+    #[test]
+    fn annuity_with_rate_change() {
+        let tol = 1e-8;
+        let mut loan = mortgage("annuity", "duration");
+        loan.rate_changes.push(RateChange {
+            date: NaiveDate::from_ymd(2025, 1, 1),
+            rate: 4.,
+        });
+        let payments = loan.schedule(None).unwrap();
+        assert_eq!(payments.len(), 120);
+        assert_fuzzy_eq!(payments[59].amount(), annuity(120000., 3., 120), tol);
+        let new_installment = annuity(payments[59].balance, 4., 60);
+        assert_fuzzy_eq!(payments[60].amount(), new_installment, tol);
+        assert_fuzzy_eq!(payments[60].interest, payments[59].balance * 0.04 / 12., tol);
+        assert_fuzzy_eq!(payments[119].amount(), new_installment, tol);
+        assert_fuzzy_eq!(payments[119].balance, 0., tol);
+    }
+
+    #[test]
+    fn annuity_with_extra_repayment() {
+        let tol = 1e-8;
+        let installment = annuity(120000., 3., 120);
+        let extra = ExtraRepayment {
+            date: NaiveDate::from_ymd(2022, 1, 1),
+            amount: 10000.,
+        };
+
+        let mut loan = mortgage("annuity", "duration");
+        loan.extra_repayments.push(extra);
+        let payments = loan.schedule(None).unwrap();
+        assert!(payments[23].extra_repayment);
+        let payments = regular(&payments);
+        assert!(payments.len() < 120);
+        assert_fuzzy_eq!(payments[30].amount(), installment, tol);
+        assert_fuzzy_eq!(payments.last().unwrap().balance, 0., tol);
+
+        let mut loan = mortgage("annuity", "installment");
+        loan.extra_repayments.push(extra);
+        let payments = regular(&loan.schedule(None).unwrap());
+        assert_eq!(payments.len(), 120);
+        let reduced = annuity(payments[23].balance, 3., 96);
+        assert!(reduced < installment);
+        assert_fuzzy_eq!(payments[24].amount(), reduced, tol);
+        assert_fuzzy_eq!(payments[119].amount(), reduced, tol);
+        assert_fuzzy_eq!(payments[119].balance, 0., tol);
+    }
+
+    #[test]
+    fn linear_with_extra_repayment() {
+        let tol = 1e-8;
+        let mut loan = mortgage("linear", "installment");
+        loan.extra_repayments.push(ExtraRepayment {
+            date: NaiveDate::from_ymd(2020, 2, 16),
+            amount: 10000.,
+        });
+        let payments = loan.schedule(None).unwrap();
+        assert_eq!(payments.len(), 121);
+        assert_fuzzy_eq!(payments[0].principal, 1000., tol);
+        assert_fuzzy_eq!(payments[0].interest, 300., tol);
+        assert!(payments[1].extra_repayment);
+        // interest of the second period is accrued on the reduced balance after the extra repayment
+        assert_fuzzy_eq!(
+            payments[2].interest,
+            119000. * 0.03 * 15. / 360. + 109000. * 0.03 * 15. / 360.,
+            tol
+        );
+        assert_fuzzy_eq!(payments[2].principal, 1000., tol);
+        assert_fuzzy_eq!(payments[3].principal, 108000. / 118., tol);
+        assert_fuzzy_eq!(payments[120].balance, 0., tol);
+    }
+
+    #[test]
+    fn reconcile_mortgage_payments() {
+        let loan = mortgage("annuity", "duration");
+        let conn = Connection::open(":memory:").unwrap();
+        let mut db = SqliteDB::new(&conn);
+        db.init().unwrap();
+        let market = Market::new(&mut db);
+        let payments = loan.payment_schedule(&market).unwrap();
+
+        let trans_conn = Connection::open(":memory:").unwrap();
+        let mut trans_db = SqliteDB::new(&trans_conn);
+        trans_db.init().unwrap();
+        let eur = Currency::from_str("EUR").unwrap();
+        let mut book = |amount: f64, date: NaiveDate| {
+            trans_db
+                .insert_transaction(&Transaction {
+                    id: None,
+                    transaction_type: TransactionType::Cash,
+                    cash_flow: CashFlow::new(amount, eur, date),
+                    note: None,
+                    execution_meta: None,
+                    recorded_at: None,
+                })
+                .unwrap()
+        };
+        // first payment booked a day late, second one missing, third one wrong amount
+        book(-payments[0].amount(), NaiveDate::from_ymd(2020, 2, 3));
+        let wrong_id = book(-500., payments[2].date);
+        book(-payments[3].amount(), payments[3].date);
+
+        let discrepancies =
+            reconcile_loan(&mut trans_db, &loan, &market, payments[3].date).unwrap();
+        assert_eq!(discrepancies.len(), 2);
+        assert_eq!(discrepancies[0].date, payments[1].date);
+        assert_eq!(discrepancies[0].issue, LoanPaymentIssue::Missed);
+        assert_eq!(
+            discrepancies[1].issue,
+            LoanPaymentIssue::Mismatched {
+                transaction_id: Some(wrong_id),
+                amount: -500.
+            }
+        );
+    }
+
+    #[test]
+    fn store_loan_terms() {
+        use finql_data::{get_instrument, set_instrument, Asset, AssetHandler};
+
+        let mut loan = mortgage("annuity", "installment");
+        loan.rate_changes.push(RateChange {
+            date: NaiveDate::from_ymd(2025, 1, 1),
+            rate: 4.,
+        });
+        loan.extra_repayments.push(ExtraRepayment {
+            date: NaiveDate::from_ymd(2022, 1, 1),
+            amount: 10000.,
+        });
+        let conn = Connection::open(":memory:").unwrap();
+        let mut db = SqliteDB::new(&conn);
+        db.init().unwrap();
+        let asset_id = db
+            .insert_asset(&Asset::new(None, "Mortgage", None, None, None))
+            .unwrap();
+        set_instrument(&mut db, asset_id, &loan).unwrap();
+        let stored: Loan = get_instrument(&mut db, asset_id).unwrap().unwrap();
+        assert_eq!(stored.rate_changes.len(), 1);
+        assert_eq!(stored.extra_repayment_effect, ExtraRepaymentEffect::ReduceInstallment);
+
+        let payments = loan.schedule(None).unwrap();
+        let stored_payments = stored.schedule(None).unwrap();
+        assert_eq!(stored_payments.len(), payments.len());
+        for (payment, stored_payment) in payments.iter().zip(stored_payments.iter()) {
+            assert_eq!(stored_payment.date, payment.date);
+            assert_fuzzy_eq!(stored_payment.amount(), payment.amount(), 1e-10);
+        }
+    }
+}
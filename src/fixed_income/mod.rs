@@ -12,6 +12,13 @@ use crate::day_count_conv::DayCountConv;
 use crate::market::Market;
 use crate::rates::{Compounding, DiscountError, Discounter, FlatRate};
 
+pub mod loan;
+pub mod perpetual;
+pub mod zero_coupon;
+pub use loan::Loan;
+pub use perpetual::PerpetualBond;
+pub use zero_coupon::ZeroCouponBond;
+
 
 /// Get all future cash flows with respect to a given date
 pub fn get_cash_flows_after(cash_flows: &Vec<CashFlow>, date: NaiveDate) -> Vec<CashFlow> {
@@ -24,6 +31,45 @@ pub fn get_cash_flows_after(cash_flows: &Vec<CashFlow>, date: NaiveDate) -> Vec<
     new_cash_flows
 }
 
+/// Macaulay duration of the cash flows after `today`, i.e. the average time until payment
+/// in years weighted by the present values of the cash flows. Times are measured by the
+/// given day count convention, present values by the discounter.
+pub fn macaulay_duration(
+    cash_flows: &[CashFlow],
+    discounter: &dyn Discounter,
+    today: NaiveDate,
+    day_count_conv: DayCountConv,
+) -> Result<f64, DiscountError> {
+    let mut value = 0.;
+    let mut weighted_time = 0.;
+    for cf in cash_flows.iter().filter(|cf| cf.date > today) {
+        let present_value = discounter.discount_cash_flow(cf, today)?.amount;
+        let time = day_count_conv
+            .year_fraction(today, cf.date, None, None)
+            .map_err(|_| DiscountError)?;
+        value += present_value;
+        weighted_time += time * present_value;
+    }
+    if value == 0. {
+        return Ok(0.);
+    }
+    Ok(weighted_time / value)
+}
+
+/// Modified duration, i.e. the relative change of the present value per unit change of the
+/// yield, derived from the Macaulay duration and the yield with the given compounding.
+/// Both durations coincide for continuous compounding; for simple compounding this holds
+/// only approximately for short maturities.
+pub fn modified_duration(macaulay_duration: f64, yield_rate: f64, compounding: Compounding) -> f64 {
+    match compounding {
+        Compounding::Annual => macaulay_duration / (1. + yield_rate),
+        Compounding::SemiAnnual => macaulay_duration / (1. + yield_rate / 2.),
+        Compounding::Quarterly => macaulay_duration / (1. + yield_rate / 4.),
+        Compounding::Monthly => macaulay_duration / (1. + yield_rate / 12.),
+        Compounding::Simple | Compounding::Continuous => macaulay_duration,
+    }
+}
+
 pub trait FixedIncome {
     type Error: std::convert::From<DiscountError>;
 
@@ -170,8 +216,8 @@ mod tests {
 
         let fx_rate = 81.2345;
         // temporary storage for fx rates
-        let mut conn = Connection::open(":memory:").unwrap();
-        let mut fx_db = SqliteDB{ conn: &mut conn };
+        let conn = Connection::open(":memory:").unwrap();
+        let mut fx_db = SqliteDB::new(&conn);
         fx_db.init().unwrap();
         insert_fx_quote(fx_rate, eur, jpy, time, &mut fx_db).unwrap();
         fx_db.set_rounding_digits(jpy, 0).unwrap();
@@ -0,0 +1,379 @@
+//! Perpetual bonds, which pay coupons without ever being redeemed. Coupons may be level or
+//! grow by a constant rate per coupon period.
+
+use chrono::{Datelike, NaiveDate};
+use serde::{Deserialize, Serialize};
+
+use finql_data::cash_flow::CashFlow;
+use finql_data::currency::Currency;
+use finql_data::InstrumentTerms;
+
+use crate::bond::BondError;
+use crate::day_adjust::DayAdjust;
+use crate::day_count_conv::DayCountConv;
+use crate::fixed_income::{get_cash_flows_after, FixedIncome};
+use crate::market::Market;
+use crate::rates::convert::growth_factor;
+use crate::rates::{Compounding, Discounter, FlatRate};
+use crate::time_period::TimePeriod;
+
+fn default_horizon_years() -> u32 {
+    100
+}
+
+/// Bond paying coupons forever, without redemption
+#[derive(Deserialize, Serialize, Debug)]
+pub struct PerpetualBond {
+    /// International security identification number
+    pub isin: Option<String>,
+    pub currency: Currency,
+    /// Start of the first coupon period
+    pub issue_date: NaiveDate,
+    /// Smallest purchasable unit, the coupon rate refers to
+    pub denomination: u32,
+    /// Initial coupon rate in percent p.a.
+    pub rate: f64,
+    /// Growth of the coupon from one coupon period to the next, e.g. 0.01 for 1%
+    #[serde(default)]
+    pub growth_rate: f64,
+    /// (Unadjusted) end date of the first coupon period
+    pub first_coupon_date: NaiveDate,
+    pub period: TimePeriod,
+    pub business_day_rule: DayAdjust,
+    pub calendar: String,
+    /// Day count convention the yield refers to
+    pub day_count_convention: DayCountConv,
+    /// Compounding method the yield refers to
+    pub compounding: Compounding,
+    /// Number of years after the issue date coupons are rolled out
+    #[serde(default = "default_horizon_years")]
+    pub horizon_years: u32,
+}
+
+impl InstrumentTerms for PerpetualBond {
+    const KIND: &'static str = "perpetual_bond";
+    const VERSION: u32 = 1;
+}
+
+impl PerpetualBond {
+    fn frequency(&self) -> Result<f64, BondError> {
+        self.period
+            .frequency()
+            .map(|frequency| frequency as f64)
+            .map_err(|_| {
+                BondError::InvalidTerms(format!("coupon period {} is not periodic", self.period))
+            })
+    }
+
+    /// Amount of the n-th coupon (starting with 0) of a single bond
+    fn coupon(&self, n: i32, frequency: f64) -> f64 {
+        (self.denomination as f64) * self.rate / 100. / frequency * (1. + self.growth_rate).powi(n)
+    }
+
+    /// Number and unadjusted end date of the coupon period `date` falls into, a coupon date
+    /// belongs to the period ending at that date
+    fn coupon_period_of(&self, date: NaiveDate) -> (i32, NaiveDate, NaiveDate) {
+        let mut n = 0;
+        let mut start = self.issue_date;
+        let mut end = self.first_coupon_date;
+        while date > end {
+            start = end;
+            end = self.period.add_to(start, None);
+            n += 1;
+        }
+        (n, start, end)
+    }
+
+    /// Roll out the coupons of all coupon periods ending on or before `horizon`
+    pub fn rollout_cash_flows_until(
+        &self,
+        position: f64,
+        horizon: NaiveDate,
+        market: &Market,
+    ) -> Result<Vec<CashFlow>, BondError> {
+        let frequency = self.frequency()?;
+        let cal = market.get_calendar(&self.calendar)?;
+        let mut cfs = Vec::new();
+        let mut n = 0;
+        let mut end_date = self.first_coupon_date;
+        while end_date <= horizon {
+            cfs.push(CashFlow::new(
+                position * self.coupon(n, frequency),
+                self.currency,
+                self.business_day_rule.adjust_date(end_date, cal),
+            ));
+            end_date = self.period.add_to(end_date, None);
+            n += 1;
+        }
+        Ok(cfs)
+    }
+
+    /// Present value of a single bond at `today` as perpetuity, i.e. by the analytic formula
+    /// `C * (1 + r) / (r - g)` for the value at the next coupon date, where `C` is the next
+    /// coupon, `r` the yield per coupon period and `g` the growth rate of the coupons. This
+    /// value is discounted to `today` with the bond's day count convention and compounding.
+    /// Coupon dates are not adjusted to business days. Fails if the coupons grow at least
+    /// as fast as they are discounted, since the value is infinite then.
+    pub fn price_from_yield(
+        &self,
+        yield_rate: f64,
+        today: NaiveDate,
+        market: &Market,
+    ) -> Result<f64, BondError> {
+        // the calendar is not needed, but must be valid for consistency with the rollout
+        market.get_calendar(&self.calendar)?;
+        let frequency = self.frequency()?;
+        let period_rate = growth_factor(yield_rate, 1. / frequency, self.compounding)
+            .map_err(|err| BondError::InvalidTerms(err.to_string()))?
+            - 1.;
+        if period_rate <= self.growth_rate {
+            return Err(BondError::InvalidTerms(format!(
+                "growth rate {} must be lower than the yield {} per coupon period",
+                self.growth_rate, period_rate
+            )));
+        }
+        let (mut n, _, mut next_coupon_date) = self.coupon_period_of(today);
+        if next_coupon_date == today {
+            // the coupon due today is not part of the price anymore
+            n += 1;
+            next_coupon_date = self.period.add_to(next_coupon_date, None);
+        }
+        let value_at_next_coupon =
+            self.coupon(n, frequency) * (1. + period_rate) / (period_rate - self.growth_rate);
+        let rate = FlatRate::new(
+            yield_rate,
+            self.day_count_convention,
+            self.compounding,
+            self.currency,
+        );
+        Ok(value_at_next_coupon * rate.discount_factor(today, next_coupon_date))
+    }
+
+    /// Present value of a single bond at `today` by discounting all coupons rolled out
+    /// until `horizon`, as numeric cross-check of `price_from_yield`. The result converges to
+    /// the analytic value as the horizon increases.
+    pub fn price_from_yield_until(
+        &self,
+        yield_rate: f64,
+        today: NaiveDate,
+        horizon: NaiveDate,
+        market: &Market,
+    ) -> Result<f64, BondError> {
+        let rate = FlatRate::new(
+            yield_rate,
+            self.day_count_convention,
+            self.compounding,
+            self.currency,
+        );
+        let cash_flows =
+            get_cash_flows_after(&self.rollout_cash_flows_until(1., horizon, market)?, today);
+        Ok(rate.discount_cash_flow_stream(&cash_flows, today)?.amount)
+    }
+
+    /// End of the rollout of `rollout_cash_flows`, i.e. the issue date shifted by the horizon
+    fn horizon(&self) -> NaiveDate {
+        let year = self.issue_date.year() + self.horizon_years as i32;
+        self.issue_date
+            .with_year(year)
+            // 29th of February
+            .unwrap_or_else(|| NaiveDate::from_ymd(year, 2, 28))
+    }
+}
+
+impl FixedIncome for PerpetualBond {
+    type Error = BondError;
+
+    /// Roll out the coupons of the periods ending within `horizon_years` after the issue date,
+    /// since the bond has no maturity
+    fn rollout_cash_flows(
+        &self,
+        position: f64,
+        market: &Market,
+    ) -> Result<Vec<CashFlow>, BondError> {
+        self.rollout_cash_flows_until(position, self.horizon(), market)
+    }
+
+    fn accrued_interest(&self, today: NaiveDate) -> Result<f64, BondError> {
+        if today <= self.issue_date {
+            return Ok(0.);
+        }
+        let (n, start_date, end_date) = self.coupon_period_of(today);
+        if today == end_date {
+            return Ok(0.);
+        }
+        let fraction = today.signed_duration_since(start_date).num_days() as f64
+            / end_date.signed_duration_since(start_date).num_days() as f64;
+        Ok(self.coupon(n, self.frequency()?) * fraction)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rusqlite::Connection;
+
+    use finql_data::{get_instrument, set_instrument, Asset, AssetHandler};
+    use finql_sqlite::SqliteDB;
+
+    use super::*;
+    use crate::fixed_income::macaulay_duration;
+
+    fn perpetual(rate: f64, growth_rate: f64, period: &str, compounding: &str) -> PerpetualBond {
+        let data = format!(
+            r#"{{
+            "isin": "XS0000000001",
+            "currency": "EUR",
+            "issue_date": "2020-03-15",
+            "denomination": 100,
+            "rate": {},
+            "growth_rate": {},
+            "first_coupon_date": "2021-03-15",
+            "period": "{}",
+            "business_day_rule": "none",
+            "calendar": "TARGET",
+            "day_count_convention": "30/360",
+            "compounding": "{}"
+        }}"#,
+            rate, growth_rate, period, compounding
+        );
+        serde_json::from_str(&data).unwrap()
+    }
+
+    #[test]
+    fn perpetual_rollout_and_accrued_interest() {
+        let tol = 1e-10;
+        let conn = Connection::open(":memory:").unwrap();
+        let mut db = SqliteDB::new(&conn);
+        db.init().unwrap();
+        let market = Market::new(&mut db);
+
+        let bond = perpetual(5., 0.01, "1Y", "annual");
+        assert_eq!(bond.horizon_years, 100);
+        let cash_flows = bond.rollout_cash_flows(2., &market).unwrap();
+        assert_eq!(cash_flows.len(), 100);
+        assert_eq!(cash_flows[0].date, NaiveDate::from_ymd(2021, 3, 15));
+        assert_eq!(cash_flows[99].date, NaiveDate::from_ymd(2120, 3, 15));
+        assert_fuzzy_eq!(cash_flows[0].amount.amount, 10., tol);
+        assert_fuzzy_eq!(cash_flows[1].amount.amount, 10.1, tol);
+
+        assert_eq!(
+            bond.accrued_interest(NaiveDate::from_ymd(2020, 3, 1))
+                .unwrap(),
+            0.
+        );
+        assert_eq!(
+            bond.accrued_interest(NaiveDate::from_ymd(2021, 3, 15))
+                .unwrap(),
+            0.
+        );
+        // second coupon of 5.05, 184 of 365 days accrued
+        assert_fuzzy_eq!(
+            bond.accrued_interest(NaiveDate::from_ymd(2021, 9, 15))
+                .unwrap(),
+            5.05 * 184. / 365.,
+            tol
+        );
+    }
+
+    #[test]
+    fn perpetual_pricing() {
+        let tol = 1e-8;
+        let conn = Connection::open(":memory:").unwrap();
+        let mut db = SqliteDB::new(&conn);
+        db.init().unwrap();
+        let market = Market::new(&mut db);
+        let coupon_date = NaiveDate::from_ymd(2021, 3, 15);
+        let mid_period = NaiveDate::from_ymd(2021, 9, 15);
+        let far_horizon = NaiveDate::from_ymd(3021, 3, 15);
+
+        // level perpetuities yielding their coupon rate are priced at par on coupon dates
+        let level = perpetual(5., 0., "1Y", "annual");
+        assert_fuzzy_eq!(
+            level.price_from_yield(0.05, coupon_date, &market).unwrap(),
+            100.,
+            tol
+        );
+        // 105 / 1.05^0.5 half a year before the next coupon
+        assert_fuzzy_eq!(
+            level.price_from_yield(0.05, mid_period, &market).unwrap(),
+            102.46950766,
+            tol
+        );
+        let semi_annual = perpetual(4., 0., "6M", "semi-annual");
+        assert_fuzzy_eq!(
+            semi_annual
+                .price_from_yield(0.04, coupon_date, &market)
+                .unwrap(),
+            100.,
+            tol
+        );
+        // next coupon 5.05, 5.05 / (0.05 - 0.01)
+        let growing = perpetual(5., 0.01, "1Y", "annual");
+        assert_fuzzy_eq!(
+            growing
+                .price_from_yield(0.05, coupon_date, &market)
+                .unwrap(),
+            126.25,
+            tol
+        );
+        for (bond, today) in [
+            (&level, coupon_date),
+            (&level, mid_period),
+            (&semi_annual, coupon_date),
+            (&growing, mid_period),
+        ] {
+            let yield_rate = bond.rate / 100.;
+            assert_fuzzy_eq!(
+                bond.price_from_yield(yield_rate, today, &market).unwrap(),
+                bond.price_from_yield_until(yield_rate, today, far_horizon, &market)
+                    .unwrap(),
+                tol
+            );
+        }
+        assert!(matches!(
+            growing.price_from_yield(0.005, coupon_date, &market),
+            Err(BondError::InvalidTerms(_))
+        ));
+
+        // (1 + y) / y for level perpetuities on coupon dates
+        let rate = FlatRate::new(
+            0.05,
+            DayCountConv::D30_360,
+            Compounding::Annual,
+            level.currency,
+        );
+        let cash_flows = level
+            .rollout_cash_flows_until(1., far_horizon, &market)
+            .unwrap();
+        assert_fuzzy_eq!(
+            macaulay_duration(&cash_flows, &rate, coupon_date, DayCountConv::D30_360).unwrap(),
+            21.,
+            tol
+        );
+    }
+
+    #[test]
+    fn store_perpetual_terms() {
+        let bond = perpetual(4.5, 0.02, "3M", "quarterly");
+        let conn = Connection::open(":memory:").unwrap();
+        let mut db = SqliteDB::new(&conn);
+        db.init().unwrap();
+        let asset_id = db
+            .insert_asset(&Asset::new(
+                None,
+                "Perpetual",
+                None,
+                bond.isin.clone(),
+                None,
+            ))
+            .unwrap();
+        set_instrument(&mut db, asset_id, &bond).unwrap();
+        let stored: PerpetualBond = get_instrument(&mut db, asset_id).unwrap().unwrap();
+        assert_eq!(
+            serde_json::to_value(&stored).unwrap(),
+            serde_json::to_value(&bond).unwrap()
+        );
+        assert!(
+            get_instrument::<crate::fixed_income::ZeroCouponBond, _>(&mut db, asset_id).is_err()
+        );
+    }
+}
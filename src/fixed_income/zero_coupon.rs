@@ -0,0 +1,189 @@
+//! Zero-coupon bonds, which pay no coupons but only the redemption at maturity and are
+//! issued at a discount instead
+
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+
+use finql_data::cash_flow::CashFlow;
+use finql_data::currency::Currency;
+use finql_data::InstrumentTerms;
+
+use crate::bond::BondError;
+use crate::day_adjust::DayAdjust;
+use crate::day_count_conv::DayCountConv;
+use crate::fixed_income::{get_cash_flows_after, FixedIncome};
+use crate::market::Market;
+use crate::rates::{Compounding, Discounter, FlatRate};
+
+/// Bond without coupons, redeemed at its denomination at maturity
+#[derive(Deserialize, Serialize, Debug)]
+pub struct ZeroCouponBond {
+    /// International security identification number
+    pub isin: Option<String>,
+    pub currency: Currency,
+    pub issue_date: NaiveDate,
+    pub maturity: NaiveDate,
+    /// Smallest purchasable unit, which is also the redemption amount
+    pub denomination: u32,
+    pub business_day_rule: DayAdjust,
+    pub calendar: String,
+    /// Day count convention the yield refers to
+    pub day_count_convention: DayCountConv,
+    /// Compounding method the yield refers to
+    pub compounding: Compounding,
+}
+
+impl InstrumentTerms for ZeroCouponBond {
+    const KIND: &'static str = "zero_coupon_bond";
+    const VERSION: u32 = 1;
+}
+
+impl ZeroCouponBond {
+    /// Present value of a single bond at `today`, discounted with the given yield using
+    /// the bond's day count convention and compounding. Zero after redemption.
+    pub fn price_from_yield(
+        &self,
+        yield_rate: f64,
+        today: NaiveDate,
+        market: &Market,
+    ) -> Result<f64, BondError> {
+        let rate = FlatRate::new(
+            yield_rate,
+            self.day_count_convention,
+            self.compounding,
+            self.currency,
+        );
+        let cash_flows = get_cash_flows_after(&self.rollout_cash_flows(1., market)?, today);
+        Ok(rate.discount_cash_flow_stream(&cash_flows, today)?.amount)
+    }
+}
+
+impl FixedIncome for ZeroCouponBond {
+    type Error = BondError;
+
+    /// The only cash flow is the redemption at maturity
+    fn rollout_cash_flows(
+        &self,
+        position: f64,
+        market: &Market,
+    ) -> Result<Vec<CashFlow>, BondError> {
+        let cal = market.get_calendar(&self.calendar)?;
+        Ok(vec![CashFlow::new(
+            position * (self.denomination as f64),
+            self.currency,
+            self.business_day_rule.adjust_date(self.maturity, cal),
+        )])
+    }
+
+    /// Zero-coupon bonds accrue no interest, their price includes the accretion
+    fn accrued_interest(&self, _today: NaiveDate) -> Result<f64, BondError> {
+        Ok(0.)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rusqlite::Connection;
+
+    use finql_data::{get_instrument, set_instrument, Asset, AssetHandler};
+    use finql_sqlite::SqliteDB;
+
+    use super::*;
+    use crate::fixed_income::{macaulay_duration, modified_duration};
+
+    fn zero_bond(compounding: &str) -> ZeroCouponBond {
+        let data = format!(
+            r#"{{
+            "isin": "DE0001030559",
+            "currency": "EUR",
+            "issue_date": "2020-01-15",
+            "maturity": "2030-01-15",
+            "denomination": 100,
+            "business_day_rule": "none",
+            "calendar": "TARGET",
+            "day_count_convention": "act/365",
+            "compounding": "{}"
+        }}"#,
+            compounding
+        );
+        serde_json::from_str(&data).unwrap()
+    }
+
+    #[test]
+    fn zero_coupon_pricing() {
+        let tol = 1e-8;
+        let conn = Connection::open(":memory:").unwrap();
+        let mut db = SqliteDB::new(&conn);
+        db.init().unwrap();
+        let market = Market::new(&mut db);
+        let today = NaiveDate::from_ymd(2020, 1, 15);
+
+        let bond = zero_bond("annual");
+        let cash_flows = bond.rollout_cash_flows(2., &market).unwrap();
+        assert_eq!(cash_flows.len(), 1);
+        assert_eq!(cash_flows[0].date, NaiveDate::from_ymd(2030, 1, 15));
+        assert_fuzzy_eq!(cash_flows[0].amount.amount, 200., tol);
+        assert_eq!(
+            bond.accrued_interest(NaiveDate::from_ymd(2025, 6, 1))
+                .unwrap(),
+            0.
+        );
+
+        // 3653 days to maturity, i.e. 100 / 1.03^(3653/365)
+        assert_fuzzy_eq!(
+            bond.price_from_yield(0.03, today, &market).unwrap(),
+            74.39131599,
+            tol
+        );
+        let semi_annual = zero_bond("semi-annual");
+        assert_fuzzy_eq!(
+            semi_annual.price_from_yield(0.03, today, &market).unwrap(),
+            74.22887250,
+            tol
+        );
+        let after_redemption = NaiveDate::from_ymd(2030, 2, 1);
+        assert_eq!(
+            bond.price_from_yield(0.03, after_redemption, &market)
+                .unwrap(),
+            0.
+        );
+
+        let rate = FlatRate::new(
+            0.03,
+            DayCountConv::Act365,
+            Compounding::Annual,
+            bond.currency,
+        );
+        let duration = macaulay_duration(&cash_flows, &rate, today, DayCountConv::Act365).unwrap();
+        assert_fuzzy_eq!(duration, 3653. / 365., 1e-12);
+        assert_fuzzy_eq!(
+            modified_duration(duration, 0.03, Compounding::Annual),
+            3653. / 365. / 1.03,
+            1e-12
+        );
+    }
+
+    #[test]
+    fn store_zero_coupon_terms() {
+        let bond = zero_bond("continuous");
+        let conn = Connection::open(":memory:").unwrap();
+        let mut db = SqliteDB::new(&conn);
+        db.init().unwrap();
+        let asset_id = db
+            .insert_asset(&Asset::new(
+                None,
+                "Zero 2030",
+                None,
+                bond.isin.clone(),
+                None,
+            ))
+            .unwrap();
+        set_instrument(&mut db, asset_id, &bond).unwrap();
+        let stored: ZeroCouponBond = get_instrument(&mut db, asset_id).unwrap().unwrap();
+        assert_eq!(
+            serde_json::to_value(&stored).unwrap(),
+            serde_json::to_value(&bond).unwrap()
+        );
+        assert!(get_instrument::<crate::bond::Bond, _>(&mut db, asset_id).is_err());
+    }
+}
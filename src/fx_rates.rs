@@ -3,7 +3,7 @@
 use std::collections::HashMap;
 use chrono::{DateTime, Utc};
 
-use finql_data::{Asset, Currency, CurrencyConverter, CurrencyError, DataError, QuoteHandler, Quote, Ticker};
+use finql_data::{Asset, Currency, CurrencyConverter, CurrencyError, DataError, QuoteHandler, Quote, Ticker, TickerUsage};
 
 /// Calculate foreign exchange rates by reading data from quotes table
 pub fn get_fx_rate(
@@ -39,6 +39,13 @@ pub fn insert_fx_quote(
             wkn: None,
             isin: None,
             note: None,
+            reference_currency: None,
+            distribution_policy: None,
+            hedged_to: None,
+            asset_class: None,
+            sector: None,
+            country: None,
+            expected_quote_frequency: None,
         })
         .unwrap();
     let currency_pair = format!("{}/{}", foreign, base);
@@ -51,6 +58,10 @@ pub fn insert_fx_quote(
             priority: 10,
             currency: base,
             factor: 1.0,
+            source_url: None,
+            usage: TickerUsage::Both,
+            last_quote_time: None,
+            source_chain: Vec::new(),
         })
         .unwrap();
     let _ = quotes.insert_quote(&Quote {
@@ -59,6 +70,13 @@ pub fn insert_fx_quote(
         price: fx_rate,
         time,
         volume: None,
+        quality_score: None,
+        source: None,
+        open: None,
+        high: None,
+        low: None,
+        bid: None,
+        ask: None,
     });
     // Insert inverse fx quote
     let base_id = quotes
@@ -68,6 +86,13 @@ pub fn insert_fx_quote(
             wkn: None,
             isin: None,
             note: None,
+            reference_currency: None,
+            distribution_policy: None,
+            hedged_to: None,
+            asset_class: None,
+            sector: None,
+            country: None,
+            expected_quote_frequency: None,
         })
         .unwrap();
     let currency_pair = format!("{}/{}", base, foreign);
@@ -80,6 +105,10 @@ pub fn insert_fx_quote(
             priority: 10,
             currency: foreign,
             factor: 1.0,
+            source_url: None,
+            usage: TickerUsage::Both,
+            last_quote_time: None,
+            source_chain: Vec::new(),
         })
         .unwrap();
     let _ = quotes.insert_quote(&Quote {
@@ -88,6 +117,13 @@ pub fn insert_fx_quote(
         price: 1.0 / fx_rate,
         time,
         volume: None,
+        quality_score: None,
+        source: None,
+        open: None,
+        high: None,
+        low: None,
+        bid: None,
+        ask: None,
     });
     Ok(())
 }
@@ -150,8 +186,8 @@ mod tests {
 
     #[test]
     fn test_get_fx_rate() {
-        let mut conn = Connection::open(":memory:").unwrap();
-        let mut db = SqliteDB{ conn: &mut conn };
+        let conn = Connection::open(":memory:").unwrap();
+        let mut db = SqliteDB::new(&conn);
         db.init().unwrap();
         prepare_db(&mut db);
         let tol = 1.0e-8;
@@ -0,0 +1,379 @@
+//! Income report summarizing dividends, interest, taxes and fees of a period.
+//!
+//! All amounts are converted to a single base currency using the exchange rates
+//! at the payment dates. Since converted amounts hide in which currency a payment was
+//! made, each category keeps sub-totals per original currency in addition.
+//!
+//! Reports are deterministic for a given set of transactions: payments are processed
+//! ordered by date and transaction id, and the time of creation is taken from a `Clock`.
+
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt;
+
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::Serialize;
+
+use finql_data::{
+    CashAmount, Currency, CurrencyConverter, CurrencyError, DataError, Transaction,
+    TransactionHandler, TransactionType,
+};
+
+use crate::date_time_helper::Clock;
+use crate::money_format::{format_cash, MoneyFormatOptions};
+
+/// Error related to the creation of income reports
+#[derive(Debug)]
+pub enum IncomeReportError {
+    CurrencyConversion(CurrencyError),
+    DBError(DataError),
+}
+
+impl fmt::Display for IncomeReportError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::CurrencyConversion(_) => write!(f, "conversion to base currency failed"),
+            Self::DBError(_) => write!(f, "database error"),
+        }
+    }
+}
+
+impl Error for IncomeReportError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::CurrencyConversion(err) => Some(err),
+            Self::DBError(err) => Some(err),
+        }
+    }
+}
+
+impl From<CurrencyError> for IncomeReportError {
+    fn from(error: CurrencyError) -> Self {
+        Self::CurrencyConversion(error)
+    }
+}
+
+impl From<DataError> for IncomeReportError {
+    fn from(error: DataError) -> Self {
+        Self::DBError(error)
+    }
+}
+
+/// Category of income (or expense) listed in the income report
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+pub enum IncomeCategory {
+    Dividend,
+    Interest,
+    Tax,
+    Fee,
+}
+
+impl IncomeCategory {
+    /// Category of a transaction, transactions that are no income or expense have no category
+    pub fn of_transaction(transaction: &Transaction) -> Option<IncomeCategory> {
+        match transaction.transaction_type {
+            TransactionType::Dividend { .. } => Some(Self::Dividend),
+            TransactionType::Interest { .. } => Some(Self::Interest),
+            TransactionType::Tax { .. } => Some(Self::Tax),
+            TransactionType::Fee { .. } => Some(Self::Fee),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for IncomeCategory {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Dividend => write!(f, "Dividends"),
+            Self::Interest => write!(f, "Interest"),
+            Self::Tax => write!(f, "Taxes"),
+            Self::Fee => write!(f, "Fees"),
+        }
+    }
+}
+
+/// Total of a single category
+#[derive(Debug, Clone, Serialize)]
+pub struct CategoryTotal {
+    /// Total converted to the base currency of the report
+    pub total: CashAmount,
+    /// Sub-totals in original currency, indexed by currency code
+    pub by_currency: BTreeMap<String, CashAmount>,
+}
+
+/// Income report for a given period
+#[derive(Debug, Clone, Serialize)]
+pub struct IncomeReport {
+    pub start: NaiveDate,
+    pub end: NaiveDate,
+    pub base_currency: Currency,
+    pub generated_at: DateTime<Utc>,
+    pub categories: BTreeMap<IncomeCategory, CategoryTotal>,
+}
+
+/// Exchange rates to base currency, cached per currency and payment date
+struct FxRateCache<'a> {
+    converter: &'a mut dyn CurrencyConverter,
+    base_currency: Currency,
+    rates: BTreeMap<(String, NaiveDate), f64>,
+}
+
+impl FxRateCache<'_> {
+    fn fx_rate(&mut self, currency: Currency, date: NaiveDate) -> Result<f64, CurrencyError> {
+        if currency == self.base_currency {
+            return Ok(1.0);
+        }
+        let key = (currency.to_string(), date);
+        if let Some(rate) = self.rates.get(&key) {
+            return Ok(*rate);
+        }
+        let time = DateTime::<Utc>::from_utc(date.and_hms(0, 0, 0), Utc);
+        let rate = self.converter.fx_rate(currency, self.base_currency, time)?;
+        self.rates.insert(key, rate);
+        Ok(rate)
+    }
+}
+
+impl IncomeReport {
+    /// Create an empty report
+    pub fn new(
+        start: NaiveDate,
+        end: NaiveDate,
+        base_currency: Currency,
+        generated_at: DateTime<Utc>,
+    ) -> IncomeReport {
+        IncomeReport {
+            start,
+            end,
+            base_currency,
+            generated_at,
+            categories: BTreeMap::new(),
+        }
+    }
+
+    /// Add a payment of the given category, converted with the given rate to base currency
+    fn add_payment(&mut self, category: IncomeCategory, amount: CashAmount, fx_rate: f64) {
+        let base_currency = self.base_currency;
+        let category_total = self
+            .categories
+            .entry(category)
+            .or_insert_with(|| CategoryTotal {
+                total: CashAmount {
+                    amount: 0.0,
+                    currency: base_currency,
+                },
+                by_currency: BTreeMap::new(),
+            });
+        category_total.total.amount += fx_rate * amount.amount;
+        category_total
+            .by_currency
+            .entry(amount.currency.to_string())
+            .or_insert(CashAmount {
+                amount: 0.0,
+                currency: amount.currency,
+            })
+            .amount += amount.amount;
+    }
+}
+
+/// Write amount rounded according to the currency's rounding convention
+fn write_amount(f: &mut fmt::Formatter<'_>, amount: &CashAmount) -> fmt::Result {
+    write!(f, "{}", format_cash(amount, &MoneyFormatOptions::default()))
+}
+
+impl fmt::Display for IncomeReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "Income from {} to {} in {}",
+            self.start, self.end, self.base_currency
+        )?;
+        for (category, total) in &self.categories {
+            write!(f, "{:<12}", format!("{}:", category))?;
+            write_amount(f, &total.total)?;
+            let is_base_only = total.by_currency.len() == 1
+                && total
+                    .by_currency
+                    .values()
+                    .all(|a| a.currency == self.base_currency);
+            if !is_base_only {
+                write!(f, " (")?;
+                for (i, amount) in total.by_currency.values().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write_amount(f, amount)?;
+                }
+                write!(f, ")")?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+/// Create the income report for all transactions with payment dates in the period `start` to `end`
+/// (both inclusive). Payments in foreign currencies are converted to `base_currency` with the
+/// exchange rates at the payment date. The time of creation of the report is taken from `clock`.
+pub fn income_report(
+    db: &mut dyn TransactionHandler,
+    start: NaiveDate,
+    end: NaiveDate,
+    base_currency: Currency,
+    currency_converter: &mut dyn CurrencyConverter,
+    clock: &dyn Clock,
+) -> Result<IncomeReport, IncomeReportError> {
+    let mut report = IncomeReport::new(start, end, base_currency, clock.now_utc());
+    let mut fx_rates = FxRateCache {
+        converter: currency_converter,
+        base_currency,
+        rates: BTreeMap::new(),
+    };
+    let mut transactions = db.get_all_transactions()?;
+    transactions.sort_by_key(|t| (t.cash_flow.date, t.id));
+    for transaction in transactions {
+        let date = transaction.cash_flow.date;
+        if date < start || date > end {
+            continue;
+        }
+        if let Some(category) = IncomeCategory::of_transaction(&transaction) {
+            let amount = transaction.cash_flow.amount;
+            let fx_rate = fx_rates.fx_rate(amount.currency, date)?;
+            report.add_payment(category, amount, fx_rate);
+        }
+    }
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    use std::str::FromStr;
+
+    use finql_data::CashFlow;
+    use finql_sqlite::SqliteDB;
+    use rusqlite::Connection;
+
+    use crate::date_time_helper::{MockClock, SystemClock};
+    use crate::fx_rates::SimpleCurrencyConverter;
+    use crate::test_utils::{assert_golden, load_fixture_db};
+
+    /// Converter with a fixed rate, counting the number of requests
+    struct CountingConverter {
+        fx_rate: f64,
+        requests: usize,
+    }
+
+    impl CurrencyConverter for CountingConverter {
+        fn fx_rate(
+            &mut self,
+            _foreign_currency: Currency,
+            _domestic_currency: Currency,
+            _time: DateTime<Utc>,
+        ) -> Result<f64, CurrencyError> {
+            self.requests += 1;
+            Ok(self.fx_rate)
+        }
+    }
+
+    fn transaction(
+        transaction_type: TransactionType,
+        amount: f64,
+        currency: &str,
+        day: u32,
+    ) -> Transaction {
+        Transaction {
+            id: None,
+            transaction_type,
+            cash_flow: CashFlow::new(
+                amount,
+                Currency::from_str(currency).unwrap(),
+                NaiveDate::from_ymd(2020, 3, day),
+            ),
+            note: None,
+            execution_meta: None,
+            recorded_at: None,
+        }
+    }
+
+    #[test]
+    fn income_by_currency() {
+        let conn = Connection::open(":memory:").unwrap();
+        let mut db = SqliteDB::new(&conn);
+        db.init().unwrap();
+        let eur = Currency::from_str("EUR").unwrap();
+        let fee = TransactionType::Fee {
+            transaction_ref: None,
+        };
+        for trans in &[
+            transaction(TransactionType::Cash, 1000.0, "EUR", 1),
+            transaction(TransactionType::Interest { asset_id: 1 }, 10.0, "EUR", 2),
+            transaction(fee, -2.0, "EUR", 3),
+            transaction(fee, -4.0, "USD", 3),
+            transaction(fee, -6.0, "USD", 3),
+            transaction(fee, -1.0, "USD", 4),
+            // outside of period
+            transaction(fee, -100.0, "USD", 31),
+        ] {
+            db.insert_transaction(trans).unwrap();
+        }
+
+        let mut converter = CountingConverter {
+            fx_rate: 0.5,
+            requests: 0,
+        };
+        let report = income_report(
+            &mut db,
+            NaiveDate::from_ymd(2020, 3, 1),
+            NaiveDate::from_ymd(2020, 3, 30),
+            eur,
+            &mut converter,
+            &SystemClock,
+        )
+        .unwrap();
+        // one request per foreign currency and date
+        assert_eq!(converter.requests, 2);
+        assert_eq!(report.categories.len(), 2);
+
+        let fees = &report.categories[&IncomeCategory::Fee];
+        assert_fuzzy_eq!(fees.total.amount, -7.5, 1e-10);
+        assert_fuzzy_eq!(fees.by_currency["EUR"].amount, -2.0, 1e-10);
+        assert_fuzzy_eq!(fees.by_currency["USD"].amount, -11.0, 1e-10);
+        let interest = &report.categories[&IncomeCategory::Interest];
+        assert_fuzzy_eq!(interest.total.amount, 10.0, 1e-10);
+
+        let rendered = report.to_string();
+        assert!(rendered.contains("Interest:   10.00 EUR\n"));
+        assert!(rendered.contains("Fees:       -7.50 EUR (-2.00 EUR, -11.00 USD)\n"));
+    }
+
+    #[test]
+    fn income_report_golden() {
+        let conn = Connection::open(":memory:").unwrap();
+        let mut db = SqliteDB::new(&conn);
+        load_fixture_db(&mut db, "portfolio.json");
+        let eur = Currency::from_str("EUR").unwrap();
+        let usd = Currency::from_str("USD").unwrap();
+        let mut converter = SimpleCurrencyConverter::new();
+        converter.insert_fx_rate(usd, eur, 0.9);
+        let clock = MockClock::new(Utc.ymd(2021, 1, 4).and_hms(9, 0, 0));
+        let report = income_report(
+            &mut db,
+            NaiveDate::from_ymd(2020, 1, 1),
+            NaiveDate::from_ymd(2020, 12, 31),
+            eur,
+            &mut converter,
+            &clock,
+        )
+        .unwrap();
+        let rendered = report.to_string();
+        assert_golden(
+            "income_report",
+            &serde_json::json!({
+                "report": report,
+                "rendered": rendered.lines().collect::<Vec<_>>(),
+            }),
+        );
+    }
+}
@@ -0,0 +1,81 @@
+//! Position sizing by the Kelly criterion.
+//!
+//! The Kelly criterion gives the fraction of capital to be allocated to a trade that
+//! maximizes the expected logarithmic growth of capital, given the probability of winning
+//! and the ratio of the average win to the average loss.
+
+use crate::analytics_error::AnalyticsError;
+
+/// Optimal fraction of capital to bet according to the Kelly criterion, i.e. `p - (1-p)/b`,
+/// where `p` is the probability of winning and `b` the ratio of the average win to the
+/// average loss. Since a trade without positive edge should not be entered at all, a
+/// negative Kelly fraction is returned as 0.
+pub fn kelly_fraction(win_probability: f64, win_loss_ratio: f64) -> Result<f64, AnalyticsError> {
+    if !(0. ..=1.).contains(&win_probability) {
+        return Err(AnalyticsError::InvalidInput(format!(
+            "win probability must be between 0 and 1, but is {}",
+            win_probability
+        )));
+    }
+    if win_loss_ratio.is_nan() || win_loss_ratio <= 0. {
+        return Err(AnalyticsError::InvalidInput(format!(
+            "win/loss ratio must be positive, but is {}",
+            win_loss_ratio
+        )));
+    }
+    let kelly = win_probability - (1. - win_probability) / win_loss_ratio;
+    Ok(kelly.max(0.))
+}
+
+/// Fraction of the full Kelly bet, e.g. half Kelly for a `fraction` of 0.5, which reduces
+/// the volatility of the capital at the cost of a lower expected growth rate
+pub fn fractional_kelly(
+    win_probability: f64,
+    win_loss_ratio: f64,
+    fraction: f64,
+) -> Result<f64, AnalyticsError> {
+    if !(0. ..=1.).contains(&fraction) {
+        return Err(AnalyticsError::InvalidInput(format!(
+            "Kelly fraction must be between 0 and 1, but is {}",
+            fraction
+        )));
+    }
+    Ok(fraction * kelly_fraction(win_probability, win_loss_ratio)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn kelly_position_size() {
+        let tol = 1e-12;
+        // 60% win probability at even odds
+        assert_fuzzy_eq!(kelly_fraction(0.6, 1.).unwrap(), 0.2, tol);
+        assert_fuzzy_eq!(kelly_fraction(0.5, 2.).unwrap(), 0.25, tol);
+        assert_fuzzy_eq!(fractional_kelly(0.5, 2., 0.5).unwrap(), 0.125, tol);
+        // no edge or negative edge: don't trade
+        assert_eq!(kelly_fraction(0.5, 1.).unwrap(), 0.);
+        assert_eq!(kelly_fraction(0.3, 1.).unwrap(), 0.);
+        assert_eq!(kelly_fraction(0., 3.).unwrap(), 0.);
+        assert_eq!(fractional_kelly(0.3, 1., 0.5).unwrap(), 0.);
+        // certain win: bet everything
+        assert_eq!(kelly_fraction(1., 0.5).unwrap(), 1.);
+        assert_eq!(fractional_kelly(1., 0.5, 1.).unwrap(), 1.);
+    }
+
+    #[test]
+    fn invalid_kelly_input() {
+        for (p, b) in &[(-0.1, 1.), (1.1, 1.), (f64::NAN, 1.), (0.5, 0.), (0.5, -1.)] {
+            assert!(matches!(
+                kelly_fraction(*p, *b),
+                Err(AnalyticsError::InvalidInput(_))
+            ));
+        }
+        assert!(matches!(
+            fractional_kelly(0.6, 1., 1.5),
+            Err(AnalyticsError::InvalidInput(_))
+        ));
+        assert!(fractional_kelly(0.6, f64::NAN, 0.5).is_err());
+    }
+}
@@ -38,19 +38,51 @@
 pub mod macros;
 
 // module exports
+pub mod admin;
+pub mod analytics_error;
+pub mod archive;
+pub mod asset_classification;
+pub mod benchmark;
 pub mod bond;
 pub mod calendar;
+pub mod cash_flow_schedule;
+pub mod chart_series;
+pub mod contribution;
 pub mod coupon_date;
+pub mod currency_exposure;
+pub mod daily_close;
 pub mod date_time_helper;
 pub mod day_adjust;
 pub mod day_count_conv;
+pub mod diagnostics;
+pub mod dividends;
+pub mod excess_returns;
+pub mod fees;
 pub mod fixed_income;
 pub mod fx_rates;
 pub mod helpers;
+pub mod income;
+pub mod kelly_criterion;
 pub mod market;
 pub mod market_quotes;
+pub mod money_format;
+pub mod options;
 pub mod portfolio;
+pub mod portfolio_var;
+pub mod quote_matrix;
+pub mod quote_csv;
+pub mod quote_outliers;
 pub mod rates;
+pub mod rebalancing;
+pub mod returns;
+pub mod rolling_statistics;
+pub mod saved_reports;
+pub mod tax;
+pub mod time_buckets;
 pub mod time_period;
+pub mod withdrawal;
 
 pub use market::Market;
+
+#[cfg(test)]
+mod test_utils;
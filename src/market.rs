@@ -142,7 +142,7 @@ impl<'a> Market<'a> {
 }
 
 /// Generate fixed set of some calendars for testing purposes only
-fn generate_calendars() -> BTreeMap<String, Calendar> {
+pub(crate) fn generate_calendars() -> BTreeMap<String, Calendar> {
     let mut calendars = BTreeMap::new();
     let uk_settlement_holidays = vec![
         // Saturdays
@@ -283,17 +283,17 @@ fn generate_calendars() -> BTreeMap<String, Calendar> {
             first: Some(2000),
             last: None,
         },
-        // Labour Day
+        // Christmas Day
         Holiday::YearlyDay {
-            month: 5,
-            day: 1,
-            first: Some(2000),
+            month: 12,
+            day: 25,
+            first: None,
             last: None,
         },
-        // Labour Day
+        // St. Stephen's Day
         Holiday::YearlyDay {
-            month: 5,
-            day: 1,
+            month: 12,
+            day: 26,
             first: Some(2000),
             last: None,
         },
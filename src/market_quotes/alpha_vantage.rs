@@ -1,3 +1,5 @@
+use std::sync::Arc;
+
 use chrono::{DateTime, Utc, Duration};
 use async_trait::async_trait;
 use tokio_compat_02::FutureExt;
@@ -7,16 +9,23 @@ use alpha_vantage as alpha;
 use finql_data::{Quote, Ticker};
 
 use super::{MarketQuoteError, MarketQuoteProvider};
-use crate::date_time_helper::date_time_from_str_standard;
+use crate::date_time_helper::{date_time_from_str_standard, Clock, SystemClock};
 
 pub struct AlphaVantage {
     connector: alpha::user::APIKey,
+    clock: Arc<dyn Clock>,
 }
 
 impl AlphaVantage {
     pub fn new(token: String) -> AlphaVantage {
+        Self::with_clock(token, Arc::new(SystemClock))
+    }
+
+    /// Create new provider that uses the given clock to determine the current time
+    pub fn with_clock(token: String, clock: Arc<dyn Clock>) -> AlphaVantage {
         AlphaVantage {
             connector: alpha::set_api(&token),
+            clock,
         }
     }
 }
@@ -38,6 +47,13 @@ impl MarketQuoteProvider for AlphaVantage {
             price: alpha_quote.price(),
             time,
             volume: Some(alpha_quote.volume() as f64),
+            quality_score: None,
+            source: None,
+            open: None,
+            high: None,
+            low: None,
+            bid: None,
+            ask: None,
         })
     }
     /// Fetch historic quotes between start and end date
@@ -47,7 +63,7 @@ impl MarketQuoteProvider for AlphaVantage {
         start: DateTime<Utc>,
         end: DateTime<Utc>,
     ) -> Result<Vec<Quote>, MarketQuoteError> {
-        let now = Utc::now();
+        let now = self.clock.now_utc();
         // This estimate is conservative, since we expect less business days than calendar
         // days, but to be on the conservative side, we use calendar days
         let output_size = if now.signed_duration_since(start) > Duration::days(100) {
@@ -77,6 +93,13 @@ impl MarketQuoteProvider for AlphaVantage {
                     price: quote.close(),
                     time,
                     volume: Some(quote.volume() as f64),
+                    quality_score: None,
+                    source: None,
+                    open: None,
+                    high: None,
+                    low: None,
+                    bid: None,
+                    ask: None,
                 })
             }
         }
@@ -93,6 +116,8 @@ mod tests {
     use finql_data::Currency;
 
     use super::*;
+
+    use finql_data::TickerUsage;
     use crate::market_quotes::MarketDataSource;
 
     #[test]
@@ -107,6 +132,10 @@ mod tests {
             source: "alphavantage".to_string(),
             priority: 1,
             factor: 1.0,
+            source_url: None,
+            usage: TickerUsage::Both,
+            last_quote_time: None,
+            source_chain: Vec::new(),
         };
         let quote = block_on(alpha.fetch_latest_quote(&ticker)).unwrap();
         assert!(quote.price != 0.0);
@@ -124,6 +153,10 @@ mod tests {
             source: MarketDataSource::AlphaVantage.to_string(),
             priority: 1,
             factor: 1.0,
+            source_url: None,
+            usage: TickerUsage::Both,
+            last_quote_time: None,
+            source_chain: Vec::new(),
         };
         let start = Utc.ymd(2020, 1, 1).and_hms_milli(0, 0, 0, 0);
         let end = Utc.ymd(2020, 1, 31).and_hms_milli(23, 59, 59, 999);
@@ -1,8 +1,9 @@
 /// A tool to fetch prices by parsing comdirect web page
 use super::{MarketQuoteError, MarketQuoteProvider};
-use crate::date_time_helper::date_time_from_str;
+use crate::date_time_helper::{date_time_from_str, Clock, SystemClock};
 use finql_data::{Quote, Ticker};
 use chrono::{DateTime, Utc};
+use std::sync::Arc;
 use scraper::{Html, Selector};
 use async_trait::async_trait;
 use tokio_compat_02::FutureExt;
@@ -21,15 +22,22 @@ pub struct Comdirect {
     hurl1: String,
     hurl2: String,
     hurl3: String,
+    clock: Arc<dyn Clock>,
 }
 
 impl Comdirect {
     pub fn new() -> Comdirect {
+        Self::with_clock(Arc::new(SystemClock))
+    }
+
+    /// Create new provider that takes the time of latest quotes from the given clock
+    pub fn with_clock(clock: Arc<dyn Clock>) -> Comdirect {
         Comdirect{
             url: "https://www.comdirect.de/inf/aktien/detail/uebersicht.html?ID_NOTATION=".to_string(),
             hurl1: "https://www.comdirect.de/inf/kursdaten/historic.csv?DATETIME_TZ_END_RANGE_FORMATED=".to_string(),
             hurl2: "&DATETIME_TZ_START_RANGE_FORMATED=".to_string(),
             hurl3: "&INTERVALL=16&SHOW_CORPORATE_ACTION=1&WITH_EARNINGS=false&ID_NOTATION=".to_string(),
+            clock,
         }
     }
 
@@ -158,13 +166,20 @@ impl MarketQuoteProvider for Comdirect {
     async fn fetch_latest_quote(&self, ticker: &Ticker) -> Result<Quote, MarketQuoteError> {
         let codi = Comdirect::new();
         let price = codi.get_latest_quote(&ticker.name).await?;
-        let time = Utc::now();
+        let time = self.clock.now_utc();
         Ok(Quote {
             id: None,
             ticker: ticker.id.unwrap(),
             price,
             time: time,
             volume: None,
+            quality_score: None,
+            source: None,
+            open: None,
+            high: None,
+            low: None,
+            bid: None,
+            ask: None,
         })
     }
     /// Fetch historic quotes between start and end date
@@ -185,6 +200,13 @@ impl MarketQuoteProvider for Comdirect {
                 price: quote.close,
                 time: quote.date,
                 volume: quote.volume,
+                quality_score: None,
+                source: None,
+                open: None,
+                high: None,
+                low: None,
+                bid: None,
+                ask: None,
             })
         }
         Ok(quotes)
@@ -198,6 +220,7 @@ mod tests {
     use tokio_test::block_on;
     use finql_data::Currency;
     use super::*;
+    use finql_data::TickerUsage;
     use crate::market_quotes::MarketDataSource;
     
     #[test]
@@ -212,6 +235,10 @@ mod tests {
             source: MarketDataSource::Comdirect.to_string(),
             priority: 1,
             factor: 1.0,
+            source_url: None,
+            usage: TickerUsage::Both,
+            last_quote_time: None,
+            source_chain: Vec::new(),
         };
         let quote = block_on(codi.fetch_latest_quote(&ticker)).unwrap();
         assert!(quote.price != 0.0);
@@ -229,6 +256,10 @@ mod tests {
             source: MarketDataSource::Comdirect.to_string(),
             priority: 1,
             factor: 1.0,
+            source_url: None,
+            usage: TickerUsage::Both,
+            last_quote_time: None,
+            source_chain: Vec::new(),
         };
         let start = Utc.ymd(2020, 1, 1).and_hms_milli(0, 0, 0, 0);
         let end = Utc.ymd(2020, 1, 31).and_hms_milli(23, 59, 59, 999);
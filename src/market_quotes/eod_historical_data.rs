@@ -1,23 +1,63 @@
+use std::str::FromStr;
+
 use async_trait::async_trait;
 use chrono::{DateTime, Utc};
 use eodhistoricaldata_api as eod_api;
-use finql_data::{Quote, Ticker};
+use serde::Deserialize;
+use tokio_compat_02::FutureExt;
+use finql_data::{Currency, Quote, Ticker};
 
-use super::{MarketQuoteError, MarketQuoteProvider};
+use super::{MarketQuoteError, MarketQuoteProvider, SymbolCandidate};
 use crate::date_time_helper::{date_time_from_str_standard, unix_to_date_time};
 
+const EOD_SEARCH_URL: &str = "https://eodhistoricaldata.com/api/search/";
+
 pub struct EODHistData {
     connector: eod_api::EodHistConnector,
+    token: String,
 }
 
 impl EODHistData {
     pub fn new(token: String) -> EODHistData {
         EODHistData {
-            connector: eod_api::EodHistConnector::new(token),
+            connector: eod_api::EodHistConnector::new(token.clone()),
+            token,
         }
     }
 }
 
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct EODSearchResult {
+    code: String,
+    exchange: String,
+    name: String,
+    #[serde(rename = "Type")]
+    asset_type: Option<String>,
+    currency: Option<String>,
+    #[serde(rename = "ISIN")]
+    isin: Option<String>,
+}
+
+/// Parse the response of the search API, symbols are given as `code.exchange`
+fn parse_search_response(body: &str) -> Result<Vec<SymbolCandidate>, MarketQuoteError> {
+    let results: Vec<EODSearchResult> = serde_json::from_str(body)
+        .map_err(|e| MarketQuoteError::FetchFailed(e.to_string()))?;
+    Ok(results
+        .into_iter()
+        .map(|result| SymbolCandidate {
+            symbol: format!("{}.{}", result.code, result.exchange),
+            name: result.name,
+            exchange: Some(result.exchange),
+            currency: result
+                .currency
+                .and_then(|currency| Currency::from_str(&currency).ok()),
+            asset_type: result.asset_type,
+            isin: result.isin,
+        })
+        .collect())
+}
+
 #[async_trait]
 impl MarketQuoteProvider for EODHistData {
     /// Fetch latest quote
@@ -35,6 +75,13 @@ impl MarketQuoteProvider for EODHistData {
             price: eod_quote.close,
             time,
             volume: Some(eod_quote.volume as f64),
+            quality_score: None,
+            source: None,
+            open: None,
+            high: None,
+            low: None,
+            bid: None,
+            ask: None,
         })
     }
 
@@ -69,11 +116,43 @@ impl MarketQuoteProvider for EODHistData {
                     price,
                     time,
                     volume,
+                    quality_score: None,
+                    source: None,
+                    open: None,
+                    high: None,
+                    low: None,
+                    bid: None,
+                    ask: None,
                 })
             }
         }
         Ok(quotes)
     }
+    /// Search for symbols via the search API
+    async fn search_symbols(&self, query: &str) -> Result<Vec<SymbolCandidate>, MarketQuoteError> {
+        let mut url = reqwest::Url::parse(EOD_SEARCH_URL)
+            .map_err(|e| MarketQuoteError::FetchFailed(e.to_string()))?;
+        url.path_segments_mut()
+            .map_err(|_| MarketQuoteError::FetchFailed("invalid search url".to_string()))?
+            .pop_if_empty()
+            .push(query);
+        url.query_pairs_mut().append_pair("api_token", &self.token);
+        let resp = reqwest::get(url)
+            .compat()
+            .await
+            .map_err(|e| MarketQuoteError::FetchFailed(e.to_string()))?;
+        if !resp.status().is_success() {
+            return Err(MarketQuoteError::FetchFailed(
+                "unexpected server response".to_string(),
+            ));
+        }
+        let body = resp
+            .text()
+            .compat()
+            .await
+            .map_err(|e| MarketQuoteError::FetchFailed(e.to_string()))?;
+        parse_search_response(&body)
+    }
 }
 
 #[cfg(test)]
@@ -86,6 +165,8 @@ mod tests {
     use finql_data::Currency;
 
     use super::*;
+
+    use finql_data::TickerUsage;
     use crate::market_quotes::MarketDataSource;
 
     #[test]
@@ -100,11 +181,38 @@ mod tests {
             source: MarketDataSource::EodHistData.to_string(),
             priority: 1,
             factor: 1.0,
+            source_url: None,
+            usage: TickerUsage::Both,
+            last_quote_time: None,
+            source_chain: Vec::new(),
         };
         let quote = block_on(eod.fetch_latest_quote(&ticker)).unwrap();
         assert!(quote.price != 0.0);
     }
 
+    #[test]
+    fn parse_eod_search_response() {
+        let body = r#"[
+            {"Code":"AAPL","Exchange":"US","Name":"Apple Inc","Type":"Common Stock","Country":"USA",
+             "Currency":"USD","ISIN":"US0378331005","previousClose":135.37,"previousCloseDate":"2021-03-05"},
+            {"Code":"APC","Exchange":"XETRA","Name":"Apple Inc","Type":"Common Stock","Country":"Germany",
+             "Currency":"EUR","ISIN":"US0378331005","previousClose":112.1,"previousCloseDate":"2021-03-05"},
+            {"Code":"AAPL-X","Exchange":"CC","Name":"Apple Token","Type":"Currency","Country":"Unknown",
+             "Currency":"n/a","ISIN":null}
+        ]"#;
+        let candidates = parse_search_response(body).unwrap();
+        assert_eq!(candidates.len(), 3);
+        assert_eq!(candidates[0].symbol, "AAPL.US");
+        assert_eq!(candidates[0].name, "Apple Inc");
+        assert_eq!(candidates[0].currency, Some(Currency::from_str("USD").unwrap()));
+        assert_eq!(candidates[0].isin.as_deref(), Some("US0378331005"));
+        assert_eq!(candidates[1].symbol, "APC.XETRA");
+        assert_eq!(candidates[1].asset_type.as_deref(), Some("Common Stock"));
+        // unknown currencies are ignored
+        assert!(candidates[2].currency.is_none());
+        assert!(candidates[2].isin.is_none());
+    }
+
     #[test]
     fn test_eod_fetch_history() {
         let token = "OeAFFmMliFG5orCUuwAKQ8l4WWFQ67YX".to_string();
@@ -117,6 +225,10 @@ mod tests {
             source: MarketDataSource::EodHistData.to_string(),
             priority: 1,
             factor: 1.0,
+            source_url: None,
+            usage: TickerUsage::Both,
+            last_quote_time: None,
+            source_chain: Vec::new(),
         };
         let start = Utc.ymd(2020, 1, 1).and_hms_milli(0, 0, 0, 0);
         let end = Utc.ymd(2020, 1, 31).and_hms_milli(23, 59, 59, 999);
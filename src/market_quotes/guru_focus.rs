@@ -37,6 +37,13 @@ impl MarketQuoteProvider for GuruFocus {
             price: quote.price.into(),
             time,
             volume: Some(quote.todays_volume.into()),
+            quality_score: None,
+            source: None,
+            open: None,
+            high: None,
+            low: None,
+            bid: None,
+            ask: None,
         })
     }
     /// Fetch historic quotes between start and end date
@@ -67,6 +74,13 @@ impl MarketQuoteProvider for GuruFocus {
                 price: *price,
                 time,
                 volume: None,
+                quality_score: None,
+                source: None,
+                open: None,
+                high: None,
+                low: None,
+                bid: None,
+                ask: None,
             })
         }
         Ok(quotes)
@@ -76,6 +90,7 @@ impl MarketQuoteProvider for GuruFocus {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use finql_data::TickerUsage;
     use finql_data::Currency;
     use chrono::offset::TimeZone;
     use crate::market_quotes::MarketDataSource;
@@ -95,6 +110,10 @@ mod tests {
             source: MarketDataSource::GuruFocus.to_string(),
             priority: 1,
             factor: 1.0,
+            source_url: None,
+            usage: TickerUsage::Both,
+            last_quote_time: None,
+            source_chain: Vec::new(),
         };
         let quote = block_on(gf.fetch_latest_quote(&ticker)).unwrap();
         assert!(quote.price != 0.0);
@@ -112,6 +131,10 @@ mod tests {
             source: MarketDataSource::GuruFocus.to_string(),
             priority: 1,
             factor: 1.0,
+            source_url: None,
+            usage: TickerUsage::Both,
+            last_quote_time: None,
+            source_chain: Vec::new(),
         };
         let start = Utc.ymd(2020, 1, 1).and_hms_milli(0, 0, 0, 0);
         let end = Utc.ymd(2020, 1, 31).and_hms_milli(23, 59, 59, 999);
@@ -5,21 +5,26 @@ use chrono::{DateTime, Utc};
 use async_trait::async_trait;
 use serde::{Deserialize, Serialize};
 
-use finql_data::QuoteHandler;
-use finql_data::quote::{Quote, Ticker};
+use finql_data::{Asset, Currency, QuoteHandler};
+use finql_data::quote::{Quote, Ticker, TickerUsage};
 
 
 pub mod alpha_vantage;
 pub mod comdirect;
 pub mod eod_historical_data;
 pub mod guru_focus;
+pub mod stooq;
 pub mod yahoo;
+#[cfg(feature = "webhook")]
+pub mod webhook;
 
 #[derive(Debug)]
 pub enum MarketQuoteError {
     StoringFailed(String),
     FetchFailed(String),
     ParseDateFailed(chrono::format::ParseError),
+    /// The requested operation is not supported by the market data provider
+    Unsupported,
 }
 
 impl std::error::Error for MarketQuoteError {
@@ -43,13 +48,14 @@ impl fmt::Display for MarketQuoteError {
             Self::StoringFailed(err) => write!(f, "storing quote in database failed: {}", err),
             Self::FetchFailed(err) => write!(f, "fetching quote(s) from provider failed: {}", err),
             Self::ParseDateFailed(_) => write!(f, "parsing a quote date failed"),
+            Self::Unsupported => write!(f, "operation is not supported by market data provider"),
         }
     }
 }
 
 /// General interface for market data quotes provider
 #[async_trait]
-pub trait MarketQuoteProvider {
+pub trait MarketQuoteProvider: Sync {
     /// Fetch latest quote
     async fn fetch_latest_quote(&self, ticker: &Ticker) -> Result<Quote, MarketQuoteError>;
     /// Fetch historic quotes between start and end date
@@ -59,6 +65,63 @@ pub trait MarketQuoteProvider {
         start: DateTime<Utc>,
         end: DateTime<Utc>,
     ) -> Result<Vec<Quote>, MarketQuoteError>;
+    /// Search for symbols matching the query, e.g. a company name or ISIN
+    async fn search_symbols(&self, _query: &str) -> Result<Vec<SymbolCandidate>, MarketQuoteError> {
+        Err(MarketQuoteError::Unsupported)
+    }
+}
+
+/// Symbol found by a market data provider's search
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SymbolCandidate {
+    /// Symbol as required by the provider to fetch quotes
+    pub symbol: String,
+    pub name: String,
+    pub exchange: Option<String>,
+    pub currency: Option<Currency>,
+    /// Type of asset, e.g. equity or fund, as classified by the provider
+    pub asset_type: Option<String>,
+    pub isin: Option<String>,
+}
+
+/// Search for symbols matching the query with the given provider
+pub async fn search_symbols(
+    provider: &dyn MarketQuoteProvider,
+    query: &str,
+) -> Result<Vec<SymbolCandidate>, MarketQuoteError> {
+    provider.search_symbols(query).await
+}
+
+/// Create asset and ticker for a symbol candidate, if they don't exist yet.
+/// If the provider did not report a currency, `default_currency` is used.
+pub fn insert_symbol_candidate(
+    db: &mut dyn QuoteHandler,
+    candidate: &SymbolCandidate,
+    source: MarketDataSource,
+    default_currency: Currency,
+) -> Result<Ticker, MarketQuoteError> {
+    let asset = Asset::new(None, &candidate.name, None, candidate.isin.clone(), None);
+    let asset_id = db
+        .insert_asset_if_new(&asset, false)
+        .map_err(|e| MarketQuoteError::StoringFailed(e.to_string()))?;
+    let mut ticker = Ticker {
+        id: None,
+        asset: asset_id,
+        name: candidate.symbol.clone(),
+        currency: candidate.currency.unwrap_or(default_currency),
+        source: source.to_string(),
+        priority: 10,
+        factor: 1.0,
+        source_url: None,
+        usage: TickerUsage::Both,
+        last_quote_time: None,
+        source_chain: Vec::new(),
+    };
+    let ticker_id = db
+        .insert_if_new_ticker(&ticker)
+        .map_err(|e| MarketQuoteError::StoringFailed(e.to_string()))?;
+    ticker.id = Some(ticker_id);
+    Ok(ticker)
 }
 
 pub async fn update_ticker(
@@ -67,8 +130,8 @@ pub async fn update_ticker(
     db: &mut dyn QuoteHandler,
 ) -> Result<(), MarketQuoteError> {
     let mut quote = provider.fetch_latest_quote(&ticker).await?;
-    quote.price *= ticker.factor;
-    db.insert_quote(&quote)
+    quote.scale_prices(ticker.factor);
+    db.insert_quote_if_new(&quote)
         .map_err(|e| MarketQuoteError::StoringFailed(e.to_string()))?;
     Ok(())
 }
@@ -82,8 +145,8 @@ pub async fn update_ticker_history(
 ) -> Result<(), MarketQuoteError> {
     let mut quotes = provider.fetch_quote_history(ticker, start, end).await?;
     for mut quote in &mut quotes {
-        quote.price *= ticker.factor;
-        db.insert_quote(&quote)
+        quote.scale_prices(ticker.factor);
+        db.insert_quote_if_new(quote)
             .map_err(|e| MarketQuoteError::StoringFailed(e.to_string()))?;
     }
     Ok(())
@@ -98,6 +161,7 @@ pub enum MarketDataSource {
     EodHistData,
     AlphaVantage,
     Comdirect,
+    Stooq,
 }
 
 #[derive(Debug, Clone)]
@@ -120,6 +184,7 @@ impl FromStr for MarketDataSource {
             "eodhistdata" => Ok(Self::EodHistData),
             "alpha_vantage" => Ok(Self::AlphaVantage),
             "comdirect" => Ok(Self::Comdirect),
+            "stooq" => Ok(Self::Stooq),
             _ => Err(ParseMarketDataSourceError {}),
         }
     }
@@ -134,6 +199,7 @@ impl fmt::Display for MarketDataSource {
             Self::EodHistData => write!(f, "eodhistdata"),
             Self::AlphaVantage => write!(f, "alpha_vantage"),
             Self::Comdirect => write!(f, "comdirect"),
+            Self::Stooq => write!(f, "stooq"),
         }
     }
 }
@@ -151,6 +217,7 @@ impl MarketDataSource {
             Self::AlphaVantage => Some(Box::new(
                 alpha_vantage::AlphaVantage::new(token))),
             Self::Comdirect => Some(Box::new(comdirect::Comdirect::new())),
+            Self::Stooq => Some(Box::new(stooq::Stooq::new())),
             _ => None,
         }
     }
@@ -167,6 +234,7 @@ mod tests {
     use rand::Rng;
 
     use finql_data::asset::Asset;
+    use finql_data::asset_handler::AssetHandler;
     use finql_data::currency::Currency;
     use finql_data::quote_handler::QuoteHandler;
     use finql_sqlite::SqliteDB;
@@ -184,6 +252,13 @@ mod tests {
                 price: 1.23,
                 time: Utc.ymd(2020, 1, 1).and_hms_milli(0, 0, 0, 0),
                 volume: None,
+                quality_score: None,
+                source: None,
+                open: None,
+                high: None,
+                low: None,
+                bid: None,
+                ask: None,
             })
         }
 
@@ -204,6 +279,13 @@ mod tests {
                     price,
                     time: date,
                     volume: None,
+                    quality_score: None,
+                    source: None,
+                    open: None,
+                    high: None,
+                    low: None,
+                    bid: None,
+                    ask: None,
                 });
                 date = date + Duration::days(1);
                 price *= (0.0001 + 0.2 * rng.gen::<f64>()).exp();
@@ -220,6 +302,13 @@ mod tests {
                 wkn: None,
                 isin: None,
                 note: None,
+                reference_currency: None,
+                distribution_policy: None,
+                hedged_to: None,
+                asset_class: None,
+                sector: None,
+                country: None,
+                expected_quote_frequency: None,
             })
             .unwrap();
 
@@ -231,6 +320,10 @@ mod tests {
             source: "manual".to_string(),
             priority: 1,
             factor: 1.0,
+            source_url: None,
+            usage: TickerUsage::Both,
+            last_quote_time: None,
+            source_chain: Vec::new(),
         };
         let ticker_id = db.insert_ticker(&ticker).unwrap();
         ticker.id = Some(ticker_id);
@@ -239,21 +332,58 @@ mod tests {
 
     #[test]
     fn test_fetch_latest_quote() {
-        let mut conn = Connection::open(":memory:").unwrap();
-        let mut db = SqliteDB{ conn: &mut conn };
+        let conn = Connection::open(":memory:").unwrap();
+        let mut db = SqliteDB::new(&conn);
         db.init().unwrap();
         let ticker = prepare_db(&mut db);
         let provider = DummyProvider {};
         block_on(update_ticker(&provider, &ticker, &mut db)).unwrap();
+        // fetching the same quote again keeps the stored one
+        block_on(update_ticker(&provider, &ticker, &mut db)).unwrap();
         let quotes = db.get_all_quotes_for_ticker(ticker.id.unwrap()).unwrap();
         assert_eq!(quotes.len(), 1);
         assert_eq!(quotes[0].price, 1.23);
     }
 
+    #[test]
+    fn search_symbols_default_unsupported() {
+        let provider = DummyProvider {};
+        match block_on(search_symbols(&provider, "Apple")) {
+            Err(MarketQuoteError::Unsupported) => {}
+            _ => panic!("expected unsupported search"),
+        }
+    }
+
+    #[test]
+    fn insert_symbol_candidate_once() {
+        let conn = Connection::open(":memory:").unwrap();
+        let mut db = SqliteDB::new(&conn);
+        db.init().unwrap();
+        let eur = Currency::from_str("EUR").unwrap();
+        let candidate = SymbolCandidate {
+            symbol: "BAS.DE".to_string(),
+            name: "BASF SE".to_string(),
+            exchange: Some("GER".to_string()),
+            currency: None,
+            asset_type: Some("EQUITY".to_string()),
+            isin: Some("DE000BASF111".to_string()),
+        };
+        let ticker = insert_symbol_candidate(&mut db, &candidate, MarketDataSource::Yahoo, eur).unwrap();
+        assert_eq!(ticker.currency, eur);
+        assert_eq!(ticker.source, "yahoo");
+        let asset = db.get_asset_by_id(ticker.asset).unwrap();
+        assert_eq!(asset.isin.as_deref(), Some("DE000BASF111"));
+        // inserting the same candidate again must not create duplicates
+        let again = insert_symbol_candidate(&mut db, &candidate, MarketDataSource::Yahoo, eur).unwrap();
+        assert_eq!(again.id, ticker.id);
+        assert_eq!(again.asset, ticker.asset);
+        assert_eq!(db.get_all_ticker().unwrap().len(), 1);
+    }
+
     #[test]
     fn test_fetch_quote_history() {
-        let mut conn = Connection::open(":memory:").unwrap();
-        let mut db = SqliteDB{ conn: &mut conn };
+        let conn = Connection::open(":memory:").unwrap();
+        let mut db = SqliteDB::new(&conn);
         db.init().unwrap();
         let ticker = prepare_db(&mut db);
         let provider = DummyProvider {};
@@ -263,5 +393,13 @@ mod tests {
         let quotes = db.get_all_quotes_for_ticker(ticker.id.unwrap()).unwrap();
         assert_eq!(quotes.len(), 31);
         assert_eq!(quotes[0].price, 1.23);
+
+        // an overlapping update only adds the missing quotes
+        let start = Utc.ymd(2020, 1, 15).and_hms_milli(0, 0, 0, 0);
+        let end = Utc.ymd(2020, 2, 15).and_hms_milli(23, 59, 59, 999);
+        block_on(update_ticker_history(&provider, &ticker, &mut db, start, end)).unwrap();
+        let updated = db.get_all_quotes_for_ticker(ticker.id.unwrap()).unwrap();
+        assert_eq!(updated.len(), 46);
+        assert_eq!(updated[14].price, quotes[14].price);
     }
 }
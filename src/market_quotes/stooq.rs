@@ -0,0 +1,302 @@
+/// Keyless provider of end-of-day quotes using the CSV download of stooq.com
+///
+/// Stooq symbols consist of the local symbol and a market suffix, e.g. `aapl.us` or `sap.de`.
+/// Since Stooq provides daily bars only, quotes are placed at the closing time of the
+/// exchange and the latest quote is the close of the most recent trading day.
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use chrono::{DateTime, Duration, FixedOffset, NaiveDate, NaiveTime, TimeZone, Utc};
+use tokio_compat_02::FutureExt;
+
+use finql_data::{Quote, Ticker};
+
+use super::{MarketQuoteError, MarketQuoteProvider};
+use crate::date_time_helper::{Clock, SystemClock};
+
+/// Market suffixes used by Stooq, indexed by the country code of the ISIN
+const MARKET_SUFFIXES: [(&str, &str); 6] = [
+    ("US", "us"),
+    ("DE", "de"),
+    ("GB", "uk"),
+    ("JP", "jp"),
+    ("HK", "hk"),
+    ("HU", "hu"),
+];
+
+/// Daily bar as provided by Stooq
+#[derive(Debug, Clone, PartialEq)]
+pub struct StooqBar {
+    pub date: NaiveDate,
+    pub open: f64,
+    pub high: f64,
+    pub low: f64,
+    pub close: f64,
+    pub volume: Option<f64>,
+}
+
+pub struct Stooq {
+    url: String,
+    clock: Arc<dyn Clock>,
+}
+
+impl Stooq {
+    pub fn new() -> Stooq {
+        Self::with_clock(Arc::new(SystemClock))
+    }
+
+    /// Create new provider that uses the given clock to determine the current time
+    pub fn with_clock(clock: Arc<dyn Clock>) -> Stooq {
+        Stooq {
+            url: "https://stooq.com/q/d/l/".to_string(),
+            clock,
+        }
+    }
+
+    /// Derive the Stooq symbol from a local symbol and the ISIN of the asset, e.g.
+    /// `AAPL` and `US0378331005` give `aapl.us`. Symbols that already carry a market
+    /// suffix are kept. Polish securities are listed without suffix. Returns `None`
+    /// if the country of the ISIN is not supported.
+    pub fn symbol_for_isin(symbol: &str, isin: &str) -> Option<String> {
+        let symbol = symbol.to_lowercase();
+        if symbol.contains('.') {
+            return Some(symbol);
+        }
+        let country = isin.get(0..2)?.to_uppercase();
+        if country == "PL" {
+            return Some(symbol);
+        }
+        MARKET_SUFFIXES
+            .iter()
+            .find(|(code, _)| *code == country)
+            .map(|(_, suffix)| format!("{}.{}", symbol, suffix))
+    }
+
+    /// Regular closing time of the exchange given by the market suffix of a Stooq symbol,
+    /// as local time and the exchange's standard offset to UTC (daylight saving time is ignored)
+    fn closing_time(symbol: &str) -> (NaiveTime, FixedOffset) {
+        let hour = 3600;
+        match symbol.rsplit('.').next() {
+            Some("us") => (NaiveTime::from_hms(16, 0, 0), FixedOffset::west(5 * hour)),
+            Some("de") => (NaiveTime::from_hms(17, 30, 0), FixedOffset::east(hour)),
+            Some("uk") => (NaiveTime::from_hms(16, 30, 0), FixedOffset::east(0)),
+            Some("jp") => (NaiveTime::from_hms(15, 0, 0), FixedOffset::east(9 * hour)),
+            Some("hk") => (NaiveTime::from_hms(16, 0, 0), FixedOffset::east(8 * hour)),
+            Some("hu") => (NaiveTime::from_hms(17, 0, 0), FixedOffset::east(hour)),
+            // Warsaw stock exchange, symbols without suffix
+            _ => (NaiveTime::from_hms(17, 0, 0), FixedOffset::east(hour)),
+        }
+    }
+
+    /// Time of the quote of a daily bar of the given symbol
+    fn bar_time(symbol: &str, date: NaiveDate) -> DateTime<Utc> {
+        let (close, offset) = Self::closing_time(symbol);
+        offset
+            .from_local_datetime(&date.and_time(close))
+            .unwrap()
+            .with_timezone(&Utc)
+    }
+
+    /// Parse CSV data with columns Date, Open, High, Low, Close and optionally Volume.
+    /// If no data is available, Stooq responds with a short message or an HTML page
+    /// instead, which results in an error.
+    pub fn parse_csv(text: &str) -> Result<Vec<StooqBar>, MarketQuoteError> {
+        if !text.trim_start().starts_with("Date,") {
+            return Err(MarketQuoteError::FetchFailed(format!(
+                "no data: {}",
+                text.lines().next().unwrap_or_default().trim()
+            )));
+        }
+        let mut reader = csv::ReaderBuilder::new()
+            .has_headers(true)
+            .flexible(true)
+            .from_reader(text.trim_start().as_bytes());
+        let num = |record: &csv::StringRecord, idx: usize| -> Result<f64, MarketQuoteError> {
+            record
+                .get(idx)
+                .and_then(|field| field.trim().parse().ok())
+                .ok_or_else(|| {
+                    MarketQuoteError::FetchFailed(format!("invalid number in line {:?}", record))
+                })
+        };
+        let mut bars = Vec::new();
+        for record in reader.records() {
+            let record = record.map_err(|e| MarketQuoteError::FetchFailed(e.to_string()))?;
+            let date = NaiveDate::parse_from_str(record.get(0).unwrap_or_default(), "%F")?;
+            bars.push(StooqBar {
+                date,
+                open: num(&record, 1)?,
+                high: num(&record, 2)?,
+                low: num(&record, 3)?,
+                close: num(&record, 4)?,
+                volume: num(&record, 5).ok(),
+            });
+        }
+        Ok(bars)
+    }
+
+    /// Get daily bars of the given symbol between the given dates
+    pub async fn get_bars(
+        &self,
+        symbol: &str,
+        start: NaiveDate,
+        end: NaiveDate,
+    ) -> Result<Vec<StooqBar>, MarketQuoteError> {
+        let url = format!(
+            "{}?s={}&d1={}&d2={}&i=d",
+            self.url,
+            symbol.to_lowercase(),
+            start.format("%Y%m%d"),
+            end.format("%Y%m%d")
+        );
+        let resp = reqwest::get(&url)
+            .compat()
+            .await
+            .map_err(|_| MarketQuoteError::FetchFailed("request failed".to_string()))?;
+        if !resp.status().is_success() {
+            return Err(MarketQuoteError::FetchFailed(
+                "unexpected server response".to_string(),
+            ));
+        }
+        let body = resp
+            .text()
+            .await
+            .map_err(|_| MarketQuoteError::FetchFailed("couldn't extract body".to_string()))?;
+        Self::parse_csv(&body)
+    }
+
+    fn to_quote(ticker: &Ticker, bar: &StooqBar) -> Quote {
+        Quote {
+            id: None,
+            ticker: ticker.id.unwrap(),
+            price: bar.close,
+            time: Self::bar_time(&ticker.name.to_lowercase(), bar.date),
+            volume: bar.volume,
+            quality_score: None,
+            source: None,
+            open: None,
+            high: None,
+            low: None,
+            bid: None,
+            ask: None,
+        }
+    }
+}
+
+impl Default for Stooq {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[async_trait]
+impl MarketQuoteProvider for Stooq {
+    /// Fetch latest quote, which is the close of the most recent trading day, since
+    /// Stooq provides no intraday data
+    async fn fetch_latest_quote(&self, ticker: &Ticker) -> Result<Quote, MarketQuoteError> {
+        let today = self.clock.now_utc().naive_utc().date();
+        // look back far enough to cover holidays
+        let bars = self
+            .get_bars(&ticker.name, today - Duration::days(14), today)
+            .await?;
+        bars.last()
+            .map(|bar| Self::to_quote(ticker, bar))
+            .ok_or_else(|| MarketQuoteError::FetchFailed("no recent quote".to_string()))
+    }
+
+    /// Fetch historic quotes between start and end date
+    async fn fetch_quote_history(
+        &self,
+        ticker: &Ticker,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+    ) -> Result<Vec<Quote>, MarketQuoteError> {
+        let bars = self
+            .get_bars(
+                &ticker.name,
+                start.naive_utc().date(),
+                end.naive_utc().date(),
+            )
+            .await?;
+        Ok(bars
+            .iter()
+            .map(|bar| Self::to_quote(ticker, bar))
+            .filter(|quote| quote.time >= start && quote.time <= end)
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_stooq_csv() {
+        let input = "Date,Open,High,Low,Close,Volume
+2021-03-01,123.75,127.93,122.79,127.79,116307892
+2021-03-02,128.41,128.72,125.01,125.12,102260945
+2021-03-03,124.81,125.71,121.84,122.06,112966340
+";
+        let bars = Stooq::parse_csv(input).unwrap();
+        assert_eq!(bars.len(), 3);
+        assert_eq!(bars[1].date, NaiveDate::from_ymd(2021, 3, 2));
+        assert_eq!(bars[1].close, 125.12);
+        assert_eq!(bars[2].volume, Some(112966340.0));
+
+        // indices come without volume
+        let input = "Date,Open,High,Low,Close
+2021-03-01,13843.31,14034.58,13843.31,14012.82
+";
+        let bars = Stooq::parse_csv(input).unwrap();
+        assert_eq!(bars[0].volume, None);
+        assert_eq!(bars[0].high, 14034.58);
+    }
+
+    #[test]
+    fn test_stooq_no_data() {
+        assert!(matches!(
+            Stooq::parse_csv("No data"),
+            Err(MarketQuoteError::FetchFailed(_))
+        ));
+        let html = "<!DOCTYPE html>\n<html><head><title>Stooq</title></head></html>";
+        assert!(matches!(
+            Stooq::parse_csv(html),
+            Err(MarketQuoteError::FetchFailed(_))
+        ));
+    }
+
+    #[test]
+    fn test_stooq_symbols_and_times() {
+        assert_eq!(
+            Stooq::symbol_for_isin("AAPL", "US0378331005"),
+            Some("aapl.us".to_string())
+        );
+        assert_eq!(
+            Stooq::symbol_for_isin("SAP", "DE0007164600"),
+            Some("sap.de".to_string())
+        );
+        assert_eq!(
+            Stooq::symbol_for_isin("BP", "GB0007980591"),
+            Some("bp.uk".to_string())
+        );
+        assert_eq!(
+            Stooq::symbol_for_isin("PKN", "PLPKN0000018"),
+            Some("pkn".to_string())
+        );
+        assert_eq!(
+            Stooq::symbol_for_isin("SAP.DE", "DE0007164600"),
+            Some("sap.de".to_string())
+        );
+        assert_eq!(Stooq::symbol_for_isin("XYZ", "ZZ0000000000"), None);
+
+        let date = NaiveDate::from_ymd(2021, 3, 2);
+        assert_eq!(
+            Stooq::bar_time("aapl.us", date),
+            Utc.ymd(2021, 3, 2).and_hms(21, 0, 0)
+        );
+        assert_eq!(
+            Stooq::bar_time("sap.de", date),
+            Utc.ymd(2021, 3, 2).and_hms(16, 30, 0)
+        );
+    }
+}
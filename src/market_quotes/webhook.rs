@@ -0,0 +1,585 @@
+//! Receiver for quotes pushed via HTTP by external services, e.g. price alerts of TradingView
+//! or brokers. Requires the `webhook` feature.
+//!
+//! Quotes are sent as `POST` requests to any path, with the shared secret in the header
+//! `X-Webhook-Token` and a JSON body like
+//!
+//! ```json
+//! {"symbol": "AAPL", "price": 123.45, "timestamp": "2021-03-01T15:30:00Z", "currency": "USD"}
+//! ```
+//!
+//! The symbol must be the name of a ticker with source `webhook` and the currency must be the
+//! ticker's currency. The timestamp is given in RFC 3339 format. As for other providers, the
+//! price is multiplied by the ticker's factor before it is stored.
+//!
+//! Accepted quotes are answered with `201 Created`. Rejected requests are answered with a
+//! 4xx status code, or 500 if the quote could not be stored, and a body like
+//! `{"error": "unknown_symbol", "message": "no ticker for symbol 'AAPL'"}`.
+
+use std::convert::Infallible;
+use std::error::Error;
+use std::fmt;
+use std::net::{SocketAddr, TcpListener};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+
+use chrono::{DateTime, Utc};
+use hyper::body::HttpBody;
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Method, Request, Response, Server, StatusCode};
+use serde::{Deserialize, Serialize};
+use tokio::sync::oneshot;
+
+use finql_data::{Currency, DataError, DataItem, Quote, QuoteHandler, Ticker};
+
+/// Source of the ticker whose quotes are received by the webhook
+pub const WEBHOOK_SOURCE: &str = "webhook";
+
+/// Header containing the shared secret
+pub const TOKEN_HEADER: &str = "x-webhook-token";
+
+/// Maximum size of a request body in bytes
+const MAX_BODY_SIZE: usize = 64 * 1024;
+
+/// Destination of the quotes received by the webhook, e.g. a database
+pub trait QuoteSink: Send + Sync {
+    /// Get the ticker of the given source whose name is the given symbol, if there is any
+    fn get_ticker_by_source_symbol(
+        &self,
+        source: &str,
+        symbol: &str,
+    ) -> Result<Option<Ticker>, DataError>;
+
+    /// Store a received quote
+    fn push_quote(&self, quote: Quote) -> Result<(), DataError>;
+}
+
+/// Quote handler shared between threads, received quotes are inserted into the database
+impl<T: QuoteHandler + Send> QuoteSink for Mutex<T> {
+    fn get_ticker_by_source_symbol(
+        &self,
+        source: &str,
+        symbol: &str,
+    ) -> Result<Option<Ticker>, DataError> {
+        self.lock()
+            .map_err(|e| DataError::DataAccessFailure(e.to_string()))?
+            .get_ticker_by_source_symbol(source, symbol)
+    }
+
+    fn push_quote(&self, quote: Quote) -> Result<(), DataError> {
+        self.lock()
+            .map_err(|e| DataError::DataAccessFailure(e.to_string()))?
+            .insert_quote(&quote)?;
+        Ok(())
+    }
+}
+
+impl<S: QuoteSink + ?Sized> QuoteSink for Arc<S> {
+    fn get_ticker_by_source_symbol(
+        &self,
+        source: &str,
+        symbol: &str,
+    ) -> Result<Option<Ticker>, DataError> {
+        (**self).get_ticker_by_source_symbol(source, symbol)
+    }
+
+    fn push_quote(&self, quote: Quote) -> Result<(), DataError> {
+        (**self).push_quote(quote)
+    }
+}
+
+/// Payload of a webhook request
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WebhookQuote {
+    pub symbol: String,
+    pub price: f64,
+    pub timestamp: DateTime<Utc>,
+    pub currency: Currency,
+}
+
+/// Body of the response to a rejected request
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WebhookErrorBody {
+    /// Machine readable error code, e.g. `unknown_symbol`
+    pub error: String,
+    pub message: String,
+}
+
+/// Number of requests handled by the webhook, by result
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct WebhookMetrics {
+    pub accepted: u64,
+    /// Requests with missing or wrong token
+    pub unauthorized: u64,
+    /// Requests with wrong method or a body that is not a valid payload
+    pub malformed: u64,
+    pub unknown_symbol: u64,
+    /// Quotes with invalid price or a currency other than the ticker's currency
+    pub invalid_quote: u64,
+    /// Quotes that could not be stored
+    pub failed: u64,
+}
+
+/// Counters shared between the server and the handle
+#[derive(Default)]
+struct Counters {
+    accepted: AtomicU64,
+    unauthorized: AtomicU64,
+    malformed: AtomicU64,
+    unknown_symbol: AtomicU64,
+    invalid_quote: AtomicU64,
+    failed: AtomicU64,
+}
+
+/// Reasons to reject a request
+enum Rejection {
+    Unauthorized,
+    MethodNotAllowed,
+    Malformed(String),
+    UnknownSymbol(String),
+    InvalidQuote(String),
+    SinkFailed(String),
+}
+
+impl Rejection {
+    fn status(&self) -> StatusCode {
+        match self {
+            Self::Unauthorized => StatusCode::UNAUTHORIZED,
+            Self::MethodNotAllowed => StatusCode::METHOD_NOT_ALLOWED,
+            Self::Malformed(_) => StatusCode::BAD_REQUEST,
+            Self::UnknownSymbol(_) => StatusCode::NOT_FOUND,
+            Self::InvalidQuote(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            Self::SinkFailed(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn body(&self) -> WebhookErrorBody {
+        let (error, message) = match self {
+            Self::Unauthorized => ("unauthorized", "missing or invalid token".to_string()),
+            Self::MethodNotAllowed => ("method_not_allowed", "quotes must be posted".to_string()),
+            Self::Malformed(err) => ("malformed_payload", err.clone()),
+            Self::UnknownSymbol(symbol) => (
+                "unknown_symbol",
+                format!("no ticker for symbol '{}'", symbol),
+            ),
+            Self::InvalidQuote(err) => ("invalid_quote", err.clone()),
+            Self::SinkFailed(err) => ("storing_failed", err.clone()),
+        };
+        WebhookErrorBody {
+            error: error.to_string(),
+            message,
+        }
+    }
+
+    fn counter<'a>(&self, counters: &'a Counters) -> &'a AtomicU64 {
+        match self {
+            Self::Unauthorized => &counters.unauthorized,
+            Self::MethodNotAllowed | Self::Malformed(_) => &counters.malformed,
+            Self::UnknownSymbol(_) => &counters.unknown_symbol,
+            Self::InvalidQuote(_) => &counters.invalid_quote,
+            Self::SinkFailed(_) => &counters.failed,
+        }
+    }
+}
+
+/// Error starting or running the webhook server
+#[derive(Debug)]
+pub enum WebhookError {
+    Io(std::io::Error),
+    Server(hyper::Error),
+    /// The server thread panicked
+    Panicked,
+}
+
+impl fmt::Display for WebhookError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(err) => write!(f, "webhook server i/o error: {}", err),
+            Self::Server(err) => write!(f, "webhook server failed: {}", err),
+            Self::Panicked => write!(f, "webhook server thread panicked"),
+        }
+    }
+}
+
+impl Error for WebhookError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::Io(err) => Some(err),
+            Self::Server(err) => Some(err),
+            Self::Panicked => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for WebhookError {
+    fn from(error: std::io::Error) -> Self {
+        Self::Io(error)
+    }
+}
+
+impl From<hyper::Error> for WebhookError {
+    fn from(error: hyper::Error) -> Self {
+        Self::Server(error)
+    }
+}
+
+/// State shared by all requests
+struct WebhookState {
+    token: String,
+    sink: Box<dyn QuoteSink>,
+    counters: Arc<Counters>,
+}
+
+/// Compare tokens in time independent of the position of the first difference
+fn tokens_match(given: &[u8], expected: &[u8]) -> bool {
+    given.len() == expected.len()
+        && given
+            .iter()
+            .zip(expected)
+            .fold(0, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
+
+/// Read the request body, which must not exceed `MAX_BODY_SIZE`
+async fn read_body(mut body: Body) -> Result<Vec<u8>, Rejection> {
+    let mut bytes = Vec::new();
+    while let Some(chunk) = body.data().await {
+        let chunk = chunk.map_err(|e| Rejection::Malformed(e.to_string()))?;
+        if bytes.len() + chunk.len() > MAX_BODY_SIZE {
+            return Err(Rejection::Malformed(format!(
+                "payload exceeds {} bytes",
+                MAX_BODY_SIZE
+            )));
+        }
+        bytes.extend_from_slice(&chunk);
+    }
+    Ok(bytes)
+}
+
+async fn receive_quote(state: &WebhookState, request: Request<Body>) -> Result<(), Rejection> {
+    if request.method() != Method::POST {
+        return Err(Rejection::MethodNotAllowed);
+    }
+    let authorized = request
+        .headers()
+        .get(TOKEN_HEADER)
+        .is_some_and(|token| tokens_match(token.as_bytes(), state.token.as_bytes()));
+    if !authorized {
+        return Err(Rejection::Unauthorized);
+    }
+    let body = read_body(request.into_body()).await?;
+    let payload: WebhookQuote =
+        serde_json::from_slice(&body).map_err(|e| Rejection::Malformed(e.to_string()))?;
+    let ticker = state
+        .sink
+        .get_ticker_by_source_symbol(WEBHOOK_SOURCE, &payload.symbol)
+        .map_err(|e| Rejection::SinkFailed(e.to_string()))?
+        .ok_or_else(|| Rejection::UnknownSymbol(payload.symbol.clone()))?;
+    if ticker.currency != payload.currency {
+        return Err(Rejection::InvalidQuote(format!(
+            "ticker '{}' is quoted in {}, not in {}",
+            ticker.name, ticker.currency, payload.currency
+        )));
+    }
+    let quote = Quote {
+        id: None,
+        ticker: ticker
+            .get_id()
+            .map_err(|e| Rejection::SinkFailed(e.to_string()))?,
+        price: payload.price * ticker.factor,
+        time: payload.timestamp,
+        volume: None,
+        quality_score: None,
+        source: None,
+        open: None,
+        high: None,
+        low: None,
+        bid: None,
+        ask: None,
+    };
+    quote
+        .validate()
+        .map_err(|e| Rejection::InvalidQuote(e.to_string()))?;
+    state
+        .sink
+        .push_quote(quote)
+        .map_err(|e| Rejection::SinkFailed(e.to_string()))
+}
+
+async fn handle(
+    state: Arc<WebhookState>,
+    request: Request<Body>,
+) -> Result<Response<Body>, Infallible> {
+    let (status, body) = match receive_quote(&state, request).await {
+        Ok(()) => {
+            state.counters.accepted.fetch_add(1, Ordering::Relaxed);
+            (StatusCode::CREATED, Body::empty())
+        }
+        Err(rejection) => {
+            rejection
+                .counter(&state.counters)
+                .fetch_add(1, Ordering::Relaxed);
+            let body = serde_json::to_string(&rejection.body()).unwrap_or_default();
+            (rejection.status(), Body::from(body))
+        }
+    };
+    let mut response = Response::new(body);
+    *response.status_mut() = status;
+    if status != StatusCode::CREATED {
+        response.headers_mut().insert(
+            hyper::header::CONTENT_TYPE,
+            hyper::header::HeaderValue::from_static("application/json"),
+        );
+    }
+    Ok(response)
+}
+
+/// HTTP server receiving quotes
+pub struct QuoteWebhook;
+
+impl QuoteWebhook {
+    /// Start receiving quotes at the given address in a background thread and push them into
+    /// `sink`. Only requests with the given `token` in the header `X-Webhook-Token` are
+    /// accepted. Requests are handled one at a time. The server runs until the returned
+    /// handle is shut down or dropped.
+    pub fn serve<S: QuoteSink + 'static>(
+        addr: SocketAddr,
+        token: &str,
+        sink: S,
+    ) -> Result<WebhookHandle, WebhookError> {
+        let listener = TcpListener::bind(addr)?;
+        listener.set_nonblocking(true)?;
+        let local_addr = listener.local_addr()?;
+        let counters = Arc::new(Counters::default());
+        let state = Arc::new(WebhookState {
+            token: token.to_string(),
+            sink: Box::new(sink),
+            counters: counters.clone(),
+        });
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()?;
+        let server = {
+            let _context = runtime.enter();
+            Server::from_tcp(listener)?
+        };
+        let (shutdown, shutdown_signal) = oneshot::channel::<()>();
+        let server = server
+            .serve(make_service_fn(move |_connection| {
+                let state = state.clone();
+                async move {
+                    Ok::<_, Infallible>(service_fn(move |request| handle(state.clone(), request)))
+                }
+            }))
+            .with_graceful_shutdown(async {
+                shutdown_signal.await.ok();
+            });
+        let thread =
+            std::thread::spawn(move || runtime.block_on(server).map_err(WebhookError::from));
+        Ok(WebhookHandle {
+            local_addr,
+            counters,
+            shutdown: Some(shutdown),
+            thread: Some(thread),
+        })
+    }
+}
+
+/// Handle of a running webhook server
+pub struct WebhookHandle {
+    local_addr: SocketAddr,
+    counters: Arc<Counters>,
+    shutdown: Option<oneshot::Sender<()>>,
+    thread: Option<JoinHandle<Result<(), WebhookError>>>,
+}
+
+impl WebhookHandle {
+    /// Address the server is listening at, e.g. to find out the port assigned for port 0
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    /// Number of requests handled so far, by result
+    pub fn metrics(&self) -> WebhookMetrics {
+        let count = |counter: &AtomicU64| counter.load(Ordering::Relaxed);
+        WebhookMetrics {
+            accepted: count(&self.counters.accepted),
+            unauthorized: count(&self.counters.unauthorized),
+            malformed: count(&self.counters.malformed),
+            unknown_symbol: count(&self.counters.unknown_symbol),
+            invalid_quote: count(&self.counters.invalid_quote),
+            failed: count(&self.counters.failed),
+        }
+    }
+
+    /// Stop the server after the requests in progress have been answered
+    pub fn shutdown(mut self) -> Result<(), WebhookError> {
+        self.stop()
+    }
+
+    fn stop(&mut self) -> Result<(), WebhookError> {
+        if let Some(shutdown) = self.shutdown.take() {
+            // the server has already stopped if the receiver is gone
+            shutdown.send(()).ok();
+        }
+        match self.thread.take() {
+            Some(thread) => thread.join().map_err(|_| WebhookError::Panicked)?,
+            None => Ok(()),
+        }
+    }
+}
+
+impl Drop for WebhookHandle {
+    fn drop(&mut self) {
+        self.stop().ok();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Read, Write};
+    use std::net::TcpStream;
+    use std::str::FromStr;
+
+    use chrono::TimeZone;
+
+    use finql_data::TickerUsage;
+
+    /// Sink with a fixed list of ticker collecting all received quotes
+    struct TestSink {
+        ticker: Vec<Ticker>,
+        quotes: Mutex<Vec<Quote>>,
+    }
+
+    impl QuoteSink for TestSink {
+        fn get_ticker_by_source_symbol(
+            &self,
+            source: &str,
+            symbol: &str,
+        ) -> Result<Option<Ticker>, DataError> {
+            Ok(self
+                .ticker
+                .iter()
+                .find(|t| t.source == source && t.name == symbol)
+                .cloned())
+        }
+
+        fn push_quote(&self, quote: Quote) -> Result<(), DataError> {
+            self.quotes.lock().unwrap().push(quote);
+            Ok(())
+        }
+    }
+
+    fn make_ticker(id: usize, name: &str, source: &str) -> Ticker {
+        Ticker {
+            id: Some(id),
+            asset: id,
+            name: name.to_string(),
+            currency: Currency::from_str("USD").unwrap(),
+            source: source.to_string(),
+            priority: 1,
+            factor: 1.0,
+            source_url: None,
+            usage: TickerUsage::Both,
+            last_quote_time: None,
+            source_chain: Vec::new(),
+        }
+    }
+
+    /// Send a request and return the status code and body of the response
+    fn request(addr: SocketAddr, method: &str, token: Option<&str>, body: &str) -> (u16, String) {
+        let mut stream = TcpStream::connect(addr).unwrap();
+        let token = token
+            .map(|token| format!("X-Webhook-Token: {}\r\n", token))
+            .unwrap_or_default();
+        write!(
+            stream,
+            "{} /quotes HTTP/1.1\r\nHost: localhost\r\nContent-Type: application/json\r\n\
+            {}Content-Length: {}\r\nConnection: close\r\n\r\n{}",
+            method,
+            token,
+            body.len(),
+            body
+        )
+        .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        let status = response[9..12].parse().unwrap();
+        let body = response.split_once("\r\n\r\n").map_or("", |(_, body)| body);
+        (status, body.to_string())
+    }
+
+    fn error_code(body: &str) -> String {
+        serde_json::from_str::<WebhookErrorBody>(body)
+            .unwrap()
+            .error
+    }
+
+    #[test]
+    fn receive_quotes_via_webhook() {
+        let sink = Arc::new(TestSink {
+            ticker: vec![
+                make_ticker(1, "AAPL", WEBHOOK_SOURCE),
+                make_ticker(2, "MSFT", "yahoo"),
+            ],
+            quotes: Mutex::new(Vec::new()),
+        });
+        let webhook =
+            QuoteWebhook::serve("127.0.0.1:0".parse().unwrap(), "secret", sink.clone()).unwrap();
+        let addr = webhook.local_addr();
+        let payload = |symbol: &str, price: f64, currency: &str| {
+            format!(
+                r#"{{"symbol": "{}", "price": {}, "timestamp": "2021-03-01T15:30:00Z", "currency": "{}"}}"#,
+                symbol, price, currency
+            )
+        };
+
+        let (status, _) = request(addr, "POST", Some("secret"), &payload("AAPL", 121.5, "USD"));
+        assert_eq!(status, 201);
+        let quotes = sink.quotes.lock().unwrap().clone();
+        assert_eq!(quotes.len(), 1);
+        assert_eq!(quotes[0].ticker, 1);
+        assert_eq!(quotes[0].price, 121.5);
+        assert_eq!(quotes[0].time, Utc.ymd(2021, 3, 1).and_hms(15, 30, 0));
+
+        let valid = payload("AAPL", 121.5, "USD");
+        let (status, body) = request(addr, "POST", Some("guess"), &valid);
+        assert_eq!((status, error_code(&body).as_str()), (401, "unauthorized"));
+        let (status, _) = request(addr, "POST", None, &valid);
+        assert_eq!(status, 401);
+        let (status, body) = request(addr, "POST", Some("secret"), r#"{"symbol": "AAPL"}"#);
+        assert_eq!(
+            (status, error_code(&body).as_str()),
+            (400, "malformed_payload")
+        );
+        let (status, _) = request(addr, "GET", Some("secret"), "");
+        assert_eq!(status, 405);
+        // ticker of other sources are not updated by the webhook
+        let (status, body) = request(addr, "POST", Some("secret"), &payload("MSFT", 1., "USD"));
+        assert_eq!(
+            (status, error_code(&body).as_str()),
+            (404, "unknown_symbol")
+        );
+        let (status, body) = request(addr, "POST", Some("secret"), &payload("AAPL", 1., "EUR"));
+        assert_eq!((status, error_code(&body).as_str()), (422, "invalid_quote"));
+        let (status, _) = request(addr, "POST", Some("secret"), &payload("AAPL", -1., "USD"));
+        assert_eq!(status, 422);
+
+        assert_eq!(
+            webhook.metrics(),
+            WebhookMetrics {
+                accepted: 1,
+                unauthorized: 2,
+                malformed: 2,
+                unknown_symbol: 1,
+                invalid_quote: 2,
+                failed: 0,
+            }
+        );
+        assert_eq!(sink.quotes.lock().unwrap().len(), 1);
+        webhook.shutdown().unwrap();
+        assert!(TcpStream::connect(addr).is_err());
+    }
+}
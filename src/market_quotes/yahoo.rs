@@ -1,12 +1,57 @@
-use super::{MarketQuoteError, MarketQuoteProvider};
+use super::{MarketQuoteError, MarketQuoteProvider, SymbolCandidate};
 use crate::date_time_helper::unix_to_date_time;
 use finql_data::{Quote, Ticker};
 use chrono::{DateTime, Utc};
+use serde::Deserialize;
 use yahoo_finance_api as yahoo;
 use async_trait::async_trait;
+use tokio_compat_02::FutureExt;
+
+const YAHOO_SEARCH_URL: &str = "https://query2.finance.yahoo.com/v1/finance/search";
 
 pub struct Yahoo {}
 
+#[derive(Deserialize)]
+struct YahooSearchResponse {
+    quotes: Vec<YahooSearchQuote>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct YahooSearchQuote {
+    symbol: String,
+    #[serde(rename = "shortname")]
+    short_name: Option<String>,
+    #[serde(rename = "longname")]
+    long_name: Option<String>,
+    exchange: Option<String>,
+    quote_type: Option<String>,
+}
+
+/// Parse the response of yahoo's search endpoint; yahoo does not provide the quote currency
+fn parse_search_response(body: &str) -> Result<Vec<SymbolCandidate>, MarketQuoteError> {
+    let response: YahooSearchResponse = serde_json::from_str(body)
+        .map_err(|e| MarketQuoteError::FetchFailed(e.to_string()))?;
+    Ok(response
+        .quotes
+        .into_iter()
+        .map(|quote| {
+            let symbol = quote.symbol;
+            SymbolCandidate {
+                name: quote
+                    .long_name
+                    .or(quote.short_name)
+                    .unwrap_or_else(|| symbol.clone()),
+                symbol,
+                exchange: quote.exchange,
+                currency: None,
+                asset_type: quote.quote_type,
+                isin: None,
+            }
+        })
+        .collect())
+}
+
 #[async_trait]
 impl MarketQuoteProvider for Yahoo {
     /// Fetch latest quote
@@ -25,6 +70,13 @@ impl MarketQuoteProvider for Yahoo {
             price: quote.close,
             time: unix_to_date_time(quote.timestamp),
             volume: Some(quote.volume as f64),
+            quality_score: None,
+            source: None,
+            open: None,
+            high: None,
+            low: None,
+            bid: None,
+            ask: None,
         })
     }
     /// Fetch historic quotes between start and end date
@@ -52,10 +104,40 @@ impl MarketQuoteProvider for Yahoo {
                 price: quote.close,
                 time,
                 volume,
+                quality_score: None,
+                source: None,
+                open: None,
+                high: None,
+                low: None,
+                bid: None,
+                ask: None,
             })
         }
         Ok(quotes)
     }
+    /// Search for symbols via yahoo's search endpoint
+    async fn search_symbols(&self, query: &str) -> Result<Vec<SymbolCandidate>, MarketQuoteError> {
+        let url = reqwest::Url::parse_with_params(
+            YAHOO_SEARCH_URL,
+            &[("q", query), ("quotesCount", "10"), ("newsCount", "0")],
+        )
+        .map_err(|e| MarketQuoteError::FetchFailed(e.to_string()))?;
+        let resp = reqwest::get(url)
+            .compat()
+            .await
+            .map_err(|e| MarketQuoteError::FetchFailed(e.to_string()))?;
+        if !resp.status().is_success() {
+            return Err(MarketQuoteError::FetchFailed(
+                "unexpected server response".to_string(),
+            ));
+        }
+        let body = resp
+            .text()
+            .compat()
+            .await
+            .map_err(|e| MarketQuoteError::FetchFailed(e.to_string()))?;
+        parse_search_response(&body)
+    }
 }
 
 #[cfg(test)]
@@ -68,6 +150,7 @@ mod tests {
     
     use crate::market_quotes::MarketDataSource;
     use super::*;
+    use finql_data::TickerUsage;
  
     #[test]
     fn test_yahoo_fetch_quote() {
@@ -80,11 +163,36 @@ mod tests {
             source: MarketDataSource::Yahoo.to_string(),
             priority: 1,
             factor: 1.0,
+            source_url: None,
+            usage: TickerUsage::Both,
+            last_quote_time: None,
+            source_chain: Vec::new(),
         };
         let quote = block_on(yahoo.fetch_latest_quote(&ticker)).unwrap();
         assert!(quote.price != 0.0);
     }
 
+    #[test]
+    fn parse_yahoo_search_response() {
+        let body = r#"{"explains":[],"count":2,"quotes":[
+            {"exchange":"NMS","shortname":"Apple Inc.","quoteType":"EQUITY","symbol":"AAPL",
+             "index":"quotes","score":1005519.0,"typeDisp":"Equity","longname":"Apple Inc.",
+             "exchDisp":"NASDAQ","sector":"Technology","industry":"Consumer Electronics","isYahooFinance":true},
+            {"exchange":"GER","shortname":"APPLE INC","quoteType":"EQUITY","symbol":"APC.DE",
+             "index":"quotes","score":20085.0,"typeDisp":"Equity","exchDisp":"XETRA","isYahooFinance":true}
+        ],"news":[]}"#;
+        let candidates = parse_search_response(body).unwrap();
+        assert_eq!(candidates.len(), 2);
+        assert_eq!(candidates[0].symbol, "AAPL");
+        assert_eq!(candidates[0].name, "Apple Inc.");
+        assert_eq!(candidates[0].exchange.as_deref(), Some("NMS"));
+        assert_eq!(candidates[0].asset_type.as_deref(), Some("EQUITY"));
+        assert!(candidates[0].currency.is_none());
+        // fall back to short name if there is no long name
+        assert_eq!(candidates[1].name, "APPLE INC");
+        assert!(parse_search_response("not json").is_err());
+    }
+
     #[test]
     fn test_yahoo_fetch_history() {
         let yahoo = Yahoo {};
@@ -96,6 +204,10 @@ mod tests {
             source: MarketDataSource::Yahoo.to_string(),
             priority: 1,
             factor: 1.0,
+            source_url: None,
+            usage: TickerUsage::Both,
+            last_quote_time: None,
+            source_chain: Vec::new(),
         };
         let start = Utc.ymd(2020, 1, 1).and_hms_milli(0, 0, 0, 0);
         let end = Utc.ymd(2020, 1, 31).and_hms_milli(23, 59, 59, 999);
@@ -0,0 +1,520 @@
+//! Formatting and parsing of cash amounts for human readers.
+//!
+//! Amounts are rounded to the number of digits of their currency, taken from the rounding
+//! conventions of the format options if given there, e.g. as stored in the database by
+//! `QuoteHandler::set_rounding_digits`, or from the currency's default otherwise. Separators
+//! follow the locale, e.g. `1,234.56` in English and `1.234,56` in German.
+//!
+//! Parsing accepts both conventions, but rejects input whose separators are inconsistent or
+//! which can't be read unambiguously, e.g. `1,234` could be either 1234 or 1.234.
+
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+
+use finql_data::cash_flow::round2digits;
+use finql_data::{CashAmount, Currency, QuoteHandler};
+
+use crate::time_buckets::Locale;
+
+/// Currency symbols known for formatting and parsing, other currencies are shown by code
+const SYMBOLS: [(&str, &str); 5] = [
+    ("EUR", "€"),
+    ("USD", "$"),
+    ("GBP", "£"),
+    ("JPY", "¥"),
+    ("INR", "₹"),
+];
+
+/// How the currency of an amount is shown
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CurrencyDisplay {
+    /// ISO code, e.g. `EUR`
+    Code,
+    /// Symbol, e.g. `€`, or the code for currencies without known symbol
+    Symbol,
+    /// Amount only
+    None,
+}
+
+/// Where the currency is placed relative to the number
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CurrencyPosition {
+    Before,
+    After,
+}
+
+/// How negative amounts are shown
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SignStyle {
+    /// Leading minus sign, e.g. `-1,234.56 EUR`
+    Minus,
+    /// Accounting style, e.g. `(1,234.56 EUR)`
+    Parentheses,
+}
+
+/// Options of `format_cash`
+#[derive(Debug, Clone)]
+pub struct MoneyFormatOptions {
+    /// Locale deciding about decimal and thousands separators
+    pub locale: Locale,
+    pub currency_display: CurrencyDisplay,
+    pub currency_position: CurrencyPosition,
+    pub sign_style: SignStyle,
+    /// Separate groups of thousands
+    pub grouping: bool,
+    /// Rounding digits by currency code, overriding the currency's default
+    pub rounding: BTreeMap<String, i32>,
+}
+
+impl Default for MoneyFormatOptions {
+    fn default() -> MoneyFormatOptions {
+        MoneyFormatOptions {
+            locale: Locale::En,
+            currency_display: CurrencyDisplay::Code,
+            currency_position: CurrencyPosition::After,
+            sign_style: SignStyle::Minus,
+            grouping: true,
+            rounding: BTreeMap::new(),
+        }
+    }
+}
+
+impl MoneyFormatOptions {
+    /// Use the rounding digits of the given currencies as stored in the database
+    pub fn with_rounding_from(
+        mut self,
+        db: &mut dyn QuoteHandler,
+        currencies: &[Currency],
+    ) -> MoneyFormatOptions {
+        for currency in currencies {
+            self.rounding
+                .insert(currency.to_string(), db.get_rounding_digits(*currency));
+        }
+        self
+    }
+
+    /// Number of digits amounts in the given currency are rounded to
+    pub fn digits(&self, currency: Currency) -> i32 {
+        self.rounding
+            .get(&currency.to_string())
+            .copied()
+            .unwrap_or_else(|| currency.rounding_digits())
+    }
+}
+
+/// Decimal and thousands separator of a locale
+fn separators(locale: Locale) -> (char, char) {
+    match locale {
+        Locale::En => ('.', ','),
+        Locale::De => (',', '.'),
+    }
+}
+
+fn symbol(currency: Currency) -> Option<&'static str> {
+    let code = currency.to_string();
+    SYMBOLS
+        .iter()
+        .find(|(c, _)| *c == code)
+        .map(|(_, symbol)| *symbol)
+}
+
+/// Insert the thousands separator into a string of digits
+fn group_digits(digits: &str, separator: char) -> String {
+    let mut grouped = String::with_capacity(digits.len() + digits.len() / 3);
+    for (i, digit) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i) % 3 == 0 {
+            grouped.push(separator);
+        }
+        grouped.push(digit);
+    }
+    grouped
+}
+
+/// Format a cash amount according to the given options, e.g. `-1,234.56 EUR`, `€1,234.56` or
+/// `(1.234,56 €)`
+pub fn format_cash(amount: &CashAmount, opts: &MoneyFormatOptions) -> String {
+    let digits = opts.digits(amount.currency).max(0);
+    let value = round2digits(amount.amount, digits);
+    let (decimal_separator, group_separator) = separators(opts.locale);
+    let number = format!("{:.*}", digits as usize, value.abs());
+    let (integer, fraction) = match number.split_once('.') {
+        Some((integer, fraction)) => (integer, Some(fraction)),
+        None => (number.as_str(), None),
+    };
+    let mut number = if opts.grouping {
+        group_digits(integer, group_separator)
+    } else {
+        integer.to_string()
+    };
+    if let Some(fraction) = fraction {
+        number.push(decimal_separator);
+        number.push_str(fraction);
+    }
+
+    let code = amount.currency.to_string();
+    let text = match (opts.currency_display, opts.currency_position) {
+        (CurrencyDisplay::None, _) => number,
+        (CurrencyDisplay::Symbol, CurrencyPosition::Before) => match symbol(amount.currency) {
+            Some(symbol) => format!("{}{}", symbol, number),
+            None => format!("{} {}", code, number),
+        },
+        (CurrencyDisplay::Symbol, CurrencyPosition::After) => {
+            format!("{} {}", number, symbol(amount.currency).unwrap_or(&code))
+        }
+        (CurrencyDisplay::Code, CurrencyPosition::Before) => format!("{} {}", code, number),
+        (CurrencyDisplay::Code, CurrencyPosition::After) => format!("{} {}", number, code),
+    };
+    // amounts rounded to zero are shown without sign
+    if value < 0.0 {
+        match opts.sign_style {
+            SignStyle::Minus => format!("-{}", text),
+            SignStyle::Parentheses => format!("({})", text),
+        }
+    } else {
+        text
+    }
+}
+
+/// Error related to parsing cash amounts
+#[derive(Debug, Clone, PartialEq)]
+pub enum ParseCashError {
+    Empty,
+    /// The input contains characters which are not part of an amount
+    InvalidNumber(String),
+    /// The separators of the input are inconsistent or could be read in different ways
+    Ambiguous(String),
+    InvalidCurrency(String),
+    /// The input contains different currencies, e.g. a symbol and a differing code
+    CurrencyConflict(String),
+}
+
+impl fmt::Display for ParseCashError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Empty => write!(f, "no amount given"),
+            Self::InvalidNumber(s) => write!(f, "invalid amount '{}'", s),
+            Self::Ambiguous(s) => write!(f, "ambiguous amount '{}'", s),
+            Self::InvalidCurrency(s) => write!(f, "invalid currency '{}'", s),
+            Self::CurrencyConflict(s) => write!(f, "conflicting currencies in '{}'", s),
+        }
+    }
+}
+
+impl Error for ParseCashError {}
+
+/// Remove a currency symbol or code from the start or the end of the input
+fn take_currency(input: &str) -> Result<(&str, Option<Currency>), ParseCashError> {
+    for (code, symbol) in SYMBOLS.iter() {
+        let rest = input
+            .strip_prefix(symbol)
+            .or_else(|| input.strip_suffix(symbol));
+        if let Some(rest) = rest {
+            return Ok((rest.trim(), Currency::from_str(code).ok()));
+        }
+    }
+    let leading = input.len() - input.trim_start_matches(char::is_alphabetic).len();
+    let trailing = input.len() - input.trim_end_matches(char::is_alphabetic).len();
+    let (code, rest) = if leading > 0 {
+        (&input[..leading], &input[leading..])
+    } else if trailing > 0 {
+        (
+            &input[input.len() - trailing..],
+            &input[..input.len() - trailing],
+        )
+    } else {
+        return Ok((input, None));
+    };
+    let currency =
+        Currency::from_str(code).map_err(|_| ParseCashError::InvalidCurrency(code.to_string()))?;
+    Ok((rest.trim(), Some(currency)))
+}
+
+/// Check that groups of thousands consist of one to three leading digits followed by groups of
+/// exactly three digits
+fn valid_groups(integer: &str, separator: char) -> bool {
+    let mut groups = integer.split(separator);
+    let first = groups.next().unwrap_or_default();
+    (1..=3).contains(&first.len()) && groups.all(|group| group.len() == 3)
+}
+
+/// Parse the number part of an amount, given with either separator convention
+fn parse_number(number: &str, original: &str) -> Result<f64, ParseCashError> {
+    let invalid = || ParseCashError::InvalidNumber(original.to_string());
+    let ambiguous = || ParseCashError::Ambiguous(original.to_string());
+    if number.is_empty()
+        || !number
+            .chars()
+            .all(|c| c.is_ascii_digit() || c == '.' || c == ',')
+    {
+        return Err(invalid());
+    }
+    let dots = number.matches('.').count();
+    let commas = number.matches(',').count();
+    let (integer, fraction, group_separator) = match (dots, commas) {
+        (0, 0) => (number, "", None),
+        (_, 0) | (0, _) => {
+            let separator = if dots > 0 { '.' } else { ',' };
+            let count = dots + commas;
+            let (integer, fraction) = number.rsplit_once(separator).ok_or_else(invalid)?;
+            if count > 1 {
+                // several separators of one kind can only group thousands
+                (number, "", Some(separator))
+            } else if fraction.is_empty() {
+                return Err(invalid());
+            } else if fraction.len() == 3 && integer != "0" {
+                return Err(ambiguous());
+            } else {
+                (integer, fraction, None)
+            }
+        }
+        _ => {
+            // the last separator is the decimal separator, which must occur only once
+            let decimal = if number.rfind('.') > number.rfind(',') {
+                '.'
+            } else {
+                ','
+            };
+            let group = if decimal == '.' { ',' } else { '.' };
+            let (integer, fraction) = number.rsplit_once(decimal).ok_or_else(invalid)?;
+            if integer.contains(decimal) {
+                return Err(ambiguous());
+            }
+            (integer, fraction, Some(group))
+        }
+    };
+    if let Some(separator) = group_separator {
+        if !valid_groups(integer, separator) {
+            return Err(ambiguous());
+        }
+    }
+    if integer.is_empty() || fraction.contains(['.', ',']) {
+        return Err(invalid());
+    }
+    let integer: String = integer.chars().filter(char::is_ascii_digit).collect();
+    let number = if fraction.is_empty() {
+        integer
+    } else {
+        format!("{}.{}", integer, fraction)
+    };
+    number.parse().map_err(|_| invalid())
+}
+
+/// Parse a cash amount like `€1.234,56`, `1,234.56 USD`, `-12,50` or `(12.50 EUR)`. The currency
+/// is given by a symbol or ISO code before or after the number, `default_currency` is used if
+/// none is given. Negative amounts are marked by a minus sign or by parentheses.
+pub fn parse_cash(s: &str, default_currency: Currency) -> Result<CashAmount, ParseCashError> {
+    let mut input = s.trim();
+    if input.is_empty() {
+        return Err(ParseCashError::Empty);
+    }
+    let mut negative = false;
+    if let Some(inner) = input
+        .strip_prefix('(')
+        .and_then(|rest| rest.strip_suffix(')'))
+    {
+        negative = true;
+        input = inner.trim();
+    }
+    // the minus sign may precede or follow a leading currency, e.g. `-$12` or `$-12`
+    let mut currency = None;
+    for _ in 0..2 {
+        if let Some(rest) = input.strip_prefix('-') {
+            if negative {
+                return Err(ParseCashError::InvalidNumber(s.to_string()));
+            }
+            negative = true;
+            input = rest.trim_start();
+        }
+        if currency.is_none() {
+            let (rest, leading) = take_currency(input)?;
+            currency = leading;
+            input = rest;
+        }
+    }
+    // a code on the other side of the number, e.g. `€12.50 EUR`
+    let (rest, other_currency) = take_currency(input)?;
+    let currency = match (currency, other_currency) {
+        (Some(c1), Some(c2)) if c1 != c2 => {
+            return Err(ParseCashError::CurrencyConflict(s.to_string()))
+        }
+        (c1, c2) => c1.or(c2).unwrap_or(default_currency),
+    };
+    let amount = parse_number(rest, s)?;
+    Ok(CashAmount {
+        amount: if negative { -amount } else { amount },
+        currency,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Input which fails to parse, with a check of the expected error
+    type InvalidInput = (&'static str, fn(&ParseCashError) -> bool);
+
+    fn currency(code: &str) -> Currency {
+        Currency::from_str(code).unwrap()
+    }
+
+    fn cash(amount: f64, code: &str) -> CashAmount {
+        CashAmount {
+            amount,
+            currency: currency(code),
+        }
+    }
+
+    #[test]
+    fn format_amounts() {
+        let en = MoneyFormatOptions::default();
+        let de = MoneyFormatOptions {
+            locale: Locale::De,
+            currency_display: CurrencyDisplay::Symbol,
+            ..Default::default()
+        };
+        let accounting = MoneyFormatOptions {
+            currency_display: CurrencyDisplay::Symbol,
+            currency_position: CurrencyPosition::Before,
+            sign_style: SignStyle::Parentheses,
+            ..Default::default()
+        };
+        let cases = [
+            (cash(1234.567, "EUR"), &en, "1,234.57 EUR"),
+            (cash(-1234567.5, "USD"), &en, "-1,234,567.50 USD"),
+            (cash(999.999, "EUR"), &en, "1,000.00 EUR"),
+            (cash(-0.001, "EUR"), &en, "0.00 EUR"),
+            (cash(1234.4, "JPY"), &en, "1,234 JPY"),
+            (cash(1234.56, "EUR"), &de, "1.234,56 €"),
+            (cash(-12.5, "CHF"), &de, "-12,50 CHF"),
+            (cash(-1234.56, "USD"), &accounting, "($1,234.56)"),
+            (cash(12.0, "CHF"), &accounting, "CHF 12.00"),
+        ];
+        for (amount, opts, expected) in cases.iter() {
+            assert_eq!(format_cash(amount, opts), *expected);
+        }
+
+        let mut opts = MoneyFormatOptions {
+            grouping: false,
+            currency_display: CurrencyDisplay::None,
+            ..Default::default()
+        };
+        opts.rounding.insert("EUR".to_string(), 3);
+        assert_eq!(format_cash(&cash(-1234.5678, "EUR"), &opts), "-1234.568");
+    }
+
+    #[test]
+    fn parse_amounts() {
+        let eur = currency("EUR");
+        let valid = [
+            ("€1.234,56", 1234.56, "EUR"),
+            ("1,234.56 USD", 1234.56, "USD"),
+            ("-12,50", -12.5, "EUR"),
+            ("12.50", 12.5, "EUR"),
+            ("12", 12.0, "EUR"),
+            ("0", 0.0, "EUR"),
+            ("  7,5  ", 7.5, "EUR"),
+            ("1.234.567", 1234567.0, "EUR"),
+            ("1,234,567", 1234567.0, "EUR"),
+            ("1.234.567,89", 1234567.89, "EUR"),
+            ("1,234,567.89", 1234567.89, "EUR"),
+            ("0,125", 0.125, "EUR"),
+            ("1,2345", 1.2345, "EUR"),
+            ("$12.99", 12.99, "USD"),
+            ("-$12.99", -12.99, "USD"),
+            ("$-12.99", -12.99, "USD"),
+            ("£1,000.00", 1000.0, "GBP"),
+            ("¥1,000,000", 1000000.0, "JPY"),
+            ("1.234,56 €", 1234.56, "EUR"),
+            ("-1.234,56 €", -1234.56, "EUR"),
+            ("USD 99.95", 99.95, "USD"),
+            ("USD99.95", 99.95, "USD"),
+            ("99.95USD", 99.95, "USD"),
+            ("chf 10,10", 10.1, "CHF"),
+            ("(1,234.56 USD)", -1234.56, "USD"),
+            ("($1,234.56)", -1234.56, "USD"),
+            ("(12,50)", -12.5, "EUR"),
+            ("€12.50 EUR", 12.5, "EUR"),
+            ("- 12,50 EUR", -12.5, "EUR"),
+        ];
+        for (input, amount, code) in valid.iter() {
+            let parsed = parse_cash(input, eur)
+                .unwrap_or_else(|e| panic!("parsing '{}' failed: {}", input, e));
+            assert_eq!(parsed, cash(*amount, code), "parsing '{}'", input);
+        }
+
+        let invalid: [InvalidInput; 19] = [
+            ("", |e| *e == ParseCashError::Empty),
+            ("   ", |e| *e == ParseCashError::Empty),
+            ("1,234", |e| matches!(e, ParseCashError::Ambiguous(_))),
+            ("1.234 €", |e| matches!(e, ParseCashError::Ambiguous(_))),
+            ("1,234.567,89", |e| {
+                matches!(e, ParseCashError::Ambiguous(_))
+            }),
+            ("1.23,456.78", |e| matches!(e, ParseCashError::Ambiguous(_))),
+            ("12,34.56", |e| matches!(e, ParseCashError::Ambiguous(_))),
+            ("1234,567.8", |e| matches!(e, ParseCashError::Ambiguous(_))),
+            ("1.2.3", |e| matches!(e, ParseCashError::Ambiguous(_))),
+            ("1,,000", |e| matches!(e, ParseCashError::Ambiguous(_))),
+            (",50", |e| matches!(e, ParseCashError::InvalidNumber(_))),
+            ("12,", |e| matches!(e, ParseCashError::InvalidNumber(_))),
+            ("12a", |e| matches!(e, ParseCashError::InvalidCurrency(_))),
+            ("EUR", |e| matches!(e, ParseCashError::InvalidNumber(_))),
+            ("1 234", |e| matches!(e, ParseCashError::InvalidNumber(_))),
+            ("--12", |e| matches!(e, ParseCashError::InvalidNumber(_))),
+            ("(-12)", |e| matches!(e, ParseCashError::InvalidNumber(_))),
+            ("$12 EUR", |e| {
+                matches!(e, ParseCashError::CurrencyConflict(_))
+            }),
+            ("USD 12 EUR", |e| {
+                matches!(e, ParseCashError::CurrencyConflict(_))
+            }),
+        ];
+        for (input, is_expected) in invalid.iter() {
+            match parse_cash(input, eur) {
+                Ok(amount) => panic!("'{}' parsed as {:?}", input, amount),
+                Err(err) => assert!(
+                    is_expected(&err),
+                    "parsing '{}' failed with {:?}",
+                    input,
+                    err
+                ),
+            }
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        let eur = currency("EUR");
+        let styles = [
+            MoneyFormatOptions::default(),
+            MoneyFormatOptions {
+                locale: Locale::De,
+                currency_display: CurrencyDisplay::Symbol,
+                sign_style: SignStyle::Parentheses,
+                ..Default::default()
+            },
+            MoneyFormatOptions {
+                currency_display: CurrencyDisplay::Symbol,
+                currency_position: CurrencyPosition::Before,
+                ..Default::default()
+            },
+        ];
+        for amount in [
+            cash(-1234567.89, "USD"),
+            cash(0.5, "GBP"),
+            cash(42.0, "EUR"),
+        ] {
+            for opts in styles.iter() {
+                let formatted = format_cash(&amount, opts);
+                assert_eq!(
+                    parse_cash(&formatted, eur).unwrap(),
+                    amount,
+                    "{}",
+                    formatted
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,646 @@
+//! Valuation of european options on assets with the Black-Scholes model.
+//!
+//! Option assets are identified by their `OptionTerms` stored in the database.
+//! The spot price is taken from the latest quote of the underlying asset, the volatility
+//! is either given manually or implied from the latest stored quote of the option itself.
+//! Option quotes and values always refer to a single option, i.e. already include the
+//! contract size.
+
+use std::error::Error;
+use std::fmt;
+
+use chrono::{DateTime, Utc};
+
+use finql_data::{Currency, DataError, OptionTerms, OptionType, QuoteHandler};
+
+use crate::day_count_conv::{DayCountConv, DayCountConvError};
+use crate::diagnostics::{DiagnosticCode, Diagnostics, Severity};
+
+/// Error related to the valuation of options
+#[derive(Debug)]
+pub enum OptionError {
+    /// No volatility could be found that reproduces the given option price
+    NoImpliedVolatility,
+    /// Underlying and option are quoted in different currencies
+    CurrencyMismatch,
+    DayCountError(DayCountConvError),
+    DBError(DataError),
+}
+
+impl fmt::Display for OptionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NoImpliedVolatility => write!(f, "option price implies no valid volatility"),
+            Self::CurrencyMismatch => {
+                write!(f, "option and underlying are quoted in different currencies")
+            }
+            Self::DayCountError(_) => write!(f, "calculation of time to expiry failed"),
+            Self::DBError(_) => write!(f, "database error"),
+        }
+    }
+}
+
+impl Error for OptionError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::DayCountError(err) => Some(err),
+            Self::DBError(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<DayCountConvError> for OptionError {
+    fn from(error: DayCountConvError) -> Self {
+        Self::DayCountError(error)
+    }
+}
+
+impl From<DataError> for OptionError {
+    fn from(error: DataError) -> Self {
+        Self::DBError(error)
+    }
+}
+
+/// Source of the volatility used for option valuation
+#[derive(Debug, Clone, Copy)]
+pub enum Volatility {
+    /// Volatility given as decimal, e.g. 0.2 for 20% p.a.
+    Manual(f64),
+    /// Volatility implied from the latest stored quote of the option
+    Implied,
+}
+
+/// Market parameters required for option valuation besides the spot price
+#[derive(Debug, Clone, Copy)]
+pub struct OptionMarketParameters {
+    /// Risk free rate, continuously compounded
+    pub rate: f64,
+    /// Dividend yield of the underlying, continuously compounded
+    pub dividend_yield: f64,
+    pub volatility: Volatility,
+}
+
+/// Sensitivities of the option value
+#[derive(Debug, Clone, Copy)]
+pub struct Greeks {
+    /// Change of option value per unit change of the spot price
+    pub delta: f64,
+    /// Change of option value per unit change of the volatility
+    pub vega: f64,
+}
+
+/// Result of an option valuation
+#[derive(Debug, Clone, Copy)]
+pub struct OptionValuation {
+    pub value: f64,
+    pub currency: Currency,
+    /// Not available for expired options
+    pub greeks: Option<Greeks>,
+    /// Expired options are valued at their intrinsic value and should be closed
+    pub expired: bool,
+}
+
+/// Price of one unit of an asset
+#[derive(Debug, Clone, Copy)]
+pub struct AssetPrice {
+    pub price: f64,
+    pub currency: Currency,
+    /// Set if the price was derived from the option model instead of a market quote
+    pub option_valuation: Option<OptionValuation>,
+    /// Time the market quote is effective, i.e. excluding any delay of its source
+    pub quote_time: Option<DateTime<Utc>>,
+}
+
+/// Time of the oldest market quote used for a set of prices, i.e. the valuation reflects
+/// prices as of this time. None, if no price is based on market quotes.
+pub fn prices_as_of(prices: &[AssetPrice]) -> Option<DateTime<Utc>> {
+    prices.iter().filter_map(|price| price.quote_time).min()
+}
+
+/// Cumulative distribution function of the standard normal distribution
+/// (double precision approximation by Hart, 1968)
+pub(crate) fn norm_cdf(x: f64) -> f64 {
+    let x_abs = x.abs();
+    let c = if x_abs > 37. {
+        0.
+    } else {
+        let e = (-x_abs * x_abs / 2.).exp();
+        if x_abs < 7.07106781186547 {
+            let mut b = 3.52624965998911e-02 * x_abs + 0.700383064443688;
+            b = b * x_abs + 6.37396220353165;
+            b = b * x_abs + 33.912866078383;
+            b = b * x_abs + 112.079291497871;
+            b = b * x_abs + 221.213596169931;
+            b = b * x_abs + 220.206867912376;
+            let c = e * b;
+            b = 8.83883476483184e-02 * x_abs + 1.75566716318264;
+            b = b * x_abs + 16.064177579207;
+            b = b * x_abs + 86.7807322029461;
+            b = b * x_abs + 296.564248779674;
+            b = b * x_abs + 637.333633378831;
+            b = b * x_abs + 793.826512519948;
+            b = b * x_abs + 440.413735824752;
+            c / b
+        } else {
+            let mut b = x_abs + 0.65;
+            b = x_abs + 4. / b;
+            b = x_abs + 3. / b;
+            b = x_abs + 2. / b;
+            b = x_abs + 1. / b;
+            e / b / 2.506628274631
+        }
+    };
+    if x > 0. {
+        1. - c
+    } else {
+        c
+    }
+}
+
+/// Density of the standard normal distribution
+pub(crate) fn norm_pdf(x: f64) -> f64 {
+    (-x * x / 2.).exp() / (2. * std::f64::consts::PI).sqrt()
+}
+
+/// Value of an option at expiry
+pub fn intrinsic_value(option_type: OptionType, spot: f64, strike: f64) -> f64 {
+    match option_type {
+        OptionType::Call => (spot - strike).max(0.),
+        OptionType::Put => (strike - spot).max(0.),
+    }
+}
+
+/// Black-Scholes value and greeks of a european option on one unit of the underlying,
+/// with time to expiry `t` given as year fraction and all rates continuously compounded.
+pub fn black_scholes(
+    option_type: OptionType,
+    spot: f64,
+    strike: f64,
+    t: f64,
+    rate: f64,
+    dividend_yield: f64,
+    volatility: f64,
+) -> (f64, Greeks) {
+    let forward_df = (-dividend_yield * t).exp();
+    let df = (-rate * t).exp();
+    if t <= 0. || volatility <= 0. {
+        let forward = spot * forward_df / df;
+        let value = df * intrinsic_value(option_type, forward, strike);
+        let delta = match option_type {
+            OptionType::Call if forward > strike => forward_df,
+            OptionType::Put if forward < strike => -forward_df,
+            _ => 0.,
+        };
+        return (value, Greeks { delta, vega: 0. });
+    }
+    let vol_sqrt_t = volatility * t.sqrt();
+    let d1 = ((spot / strike).ln() + (rate - dividend_yield + volatility * volatility / 2.) * t)
+        / vol_sqrt_t;
+    let d2 = d1 - vol_sqrt_t;
+    let vega = spot * forward_df * norm_pdf(d1) * t.sqrt();
+    match option_type {
+        OptionType::Call => (
+            spot * forward_df * norm_cdf(d1) - strike * df * norm_cdf(d2),
+            Greeks {
+                delta: forward_df * norm_cdf(d1),
+                vega,
+            },
+        ),
+        OptionType::Put => (
+            strike * df * norm_cdf(-d2) - spot * forward_df * norm_cdf(-d1),
+            Greeks {
+                delta: -forward_df * norm_cdf(-d1),
+                vega,
+            },
+        ),
+    }
+}
+
+/// Volatility that reproduces the given option price (per unit of underlying) in the Black-Scholes model.
+/// Since the option value is monotonous in the volatility, the root is found by bisection.
+pub fn implied_volatility(
+    option_type: OptionType,
+    price: f64,
+    spot: f64,
+    strike: f64,
+    t: f64,
+    rate: f64,
+    dividend_yield: f64,
+) -> Result<f64, OptionError> {
+    let value = |vol: f64| black_scholes(option_type, spot, strike, t, rate, dividend_yield, vol).0;
+    let mut low = 1e-8;
+    let mut high = 5.;
+    if t <= 0. || price < value(low) || price > value(high) {
+        return Err(OptionError::NoImpliedVolatility);
+    }
+    while high - low > 1e-12 {
+        let mid = (low + high) / 2.;
+        if value(mid) < price {
+            low = mid;
+        } else {
+            high = mid;
+        }
+    }
+    Ok((low + high) / 2.)
+}
+
+/// Time to expiry as year fraction, zero for expired options
+fn time_to_expiry(terms: &OptionTerms, time: DateTime<Utc>) -> Result<f64, DayCountConvError> {
+    let today = time.naive_utc().date();
+    if today >= terms.expiry {
+        Ok(0.)
+    } else {
+        DayCountConv::Act365.year_fraction(today, terms.expiry, None, None)
+    }
+}
+
+/// Value a single option at the given time. The spot price is taken from the latest quote
+/// of the underlying. Expired options are valued at intrinsic value and flagged as expired.
+pub fn value_option(
+    db: &mut dyn QuoteHandler,
+    terms: &OptionTerms,
+    time: DateTime<Utc>,
+    params: &OptionMarketParameters,
+) -> Result<OptionValuation, OptionError> {
+    let (spot_quote, currency) = db.get_last_quote_before_by_id(terms.underlying_id, time)?;
+    let spot = spot_quote.price;
+    let t = time_to_expiry(terms, time)?;
+    if t <= 0. {
+        return Ok(OptionValuation {
+            value: terms.contract_size * intrinsic_value(terms.option_type, spot, terms.strike),
+            currency,
+            greeks: None,
+            expired: true,
+        });
+    }
+    let volatility = match params.volatility {
+        Volatility::Manual(volatility) => volatility,
+        Volatility::Implied => {
+            let (option_quote, option_currency) =
+                db.get_last_quote_before_by_id(terms.asset_id, time)?;
+            if option_currency != currency {
+                return Err(OptionError::CurrencyMismatch);
+            }
+            let (spot_quote, _) =
+                db.get_last_quote_before_by_id(terms.underlying_id, option_quote.time)?;
+            implied_volatility(
+                terms.option_type,
+                option_quote.price / terms.contract_size,
+                spot_quote.price,
+                terms.strike,
+                time_to_expiry(terms, option_quote.time)?,
+                params.rate,
+                params.dividend_yield,
+            )?
+        }
+    };
+    let (value, greeks) = black_scholes(
+        terms.option_type,
+        spot,
+        terms.strike,
+        t,
+        params.rate,
+        params.dividend_yield,
+        volatility,
+    );
+    Ok(OptionValuation {
+        value: terms.contract_size * value,
+        currency,
+        greeks: Some(Greeks {
+            delta: terms.contract_size * greeks.delta,
+            vega: terms.contract_size * greeks.vega,
+        }),
+        expired: false,
+    })
+}
+
+/// Price of one unit of an asset for portfolio valuation. The latest market quote is used if available,
+/// otherwise, if option terms are stored for the asset, the option is valued with the Black-Scholes model.
+/// Quotes outdated with respect to the asset's expected quote frequency and model prices are
+/// recorded in `diagnostics`.
+pub fn get_asset_price(
+    db: &mut dyn QuoteHandler,
+    asset_id: usize,
+    time: DateTime<Utc>,
+    params: &OptionMarketParameters,
+    diagnostics: &mut Diagnostics,
+) -> Result<AssetPrice, OptionError> {
+    let quote_err = match db.get_last_quote_before_by_id(asset_id, time) {
+        Ok((quote, currency)) => {
+            let asset = db.get_asset_by_id(asset_id)?;
+            let expected_frequency = diagnostics.expected_quote_frequency(&asset);
+            diagnostics.check_quote_freshness(
+                asset_id,
+                quote.time,
+                time,
+                expected_frequency.as_ref(),
+            );
+            return Ok(AssetPrice {
+                price: quote.price,
+                currency,
+                option_valuation: None,
+                quote_time: Some(quote.time),
+            });
+        }
+        Err(err) => err,
+    };
+    match db.get_option_terms(asset_id)? {
+        Some(terms) => {
+            let valuation = value_option(db, &terms, time, params)?;
+            diagnostics.record(
+                DiagnosticCode::ModelPrice,
+                Severity::Info,
+                vec![asset_id],
+                format!(
+                    "no quote for asset {}, valued by the option pricing model",
+                    asset_id
+                ),
+            );
+            Ok(AssetPrice {
+                price: valuation.value,
+                currency: valuation.currency,
+                option_valuation: Some(valuation),
+                quote_time: None,
+            })
+        }
+        None => Err(quote_err.into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use chrono::{NaiveDate, TimeZone};
+    use rusqlite::Connection;
+
+    use finql_data::{
+        refresh_ticker, Asset, AssetHandler, Quote, QuoteProvider, QuoteProviderRegistry, Ticker,
+        TickerUsage,
+    };
+    use finql_sqlite::SqliteDB;
+
+    use super::*;
+
+    #[test]
+    fn normal_distribution() {
+        let tol = 1e-14;
+        assert_fuzzy_eq!(norm_cdf(0.), 0.5, tol);
+        assert_fuzzy_eq!(norm_cdf(1.), 0.841344746068543, tol);
+        assert_fuzzy_eq!(norm_cdf(-1.96), 0.0249978951482204, tol);
+        assert_fuzzy_eq!(norm_cdf(2.5) + norm_cdf(-2.5), 1., tol);
+    }
+
+    #[test]
+    fn black_scholes_reference_values() {
+        // Example from Hull, Options, Futures and other Derivatives
+        let tol = 1e-4;
+        let (call, call_greeks) = black_scholes(OptionType::Call, 42., 40., 0.5, 0.1, 0., 0.2);
+        let (put, put_greeks) = black_scholes(OptionType::Put, 42., 40., 0.5, 0.1, 0., 0.2);
+        assert_fuzzy_eq!(call, 4.7594, tol);
+        assert_fuzzy_eq!(put, 0.8086, tol);
+        assert_fuzzy_eq!(call_greeks.delta - put_greeks.delta, 1., 1e-14);
+        assert_fuzzy_eq!(call_greeks.vega, put_greeks.vega, 1e-14);
+
+        // put-call parity with dividend yield
+        let (call, _) = black_scholes(OptionType::Call, 100., 95., 1.5, 0.02, 0.03, 0.25);
+        let (put, _) = black_scholes(OptionType::Put, 100., 95., 1.5, 0.02, 0.03, 0.25);
+        assert_fuzzy_eq!(
+            call - put,
+            100. * (-0.03 * 1.5_f64).exp() - 95. * (-0.02 * 1.5_f64).exp(),
+            1e-12
+        );
+
+        // vega by finite differences
+        let h = 1e-6;
+        let (up, greeks) = black_scholes(OptionType::Call, 100., 95., 1.5, 0.02, 0.03, 0.25 + h);
+        let (down, _) = black_scholes(OptionType::Call, 100., 95., 1.5, 0.02, 0.03, 0.25 - h);
+        assert_fuzzy_eq!((up - down) / (2. * h), greeks.vega, 1e-5);
+    }
+
+    #[test]
+    fn implied_volatility_roundtrip() {
+        let tol = 1e-10;
+        for option_type in &[OptionType::Call, OptionType::Put] {
+            let (price, _) = black_scholes(*option_type, 100., 110., 0.75, 0.01, 0.02, 0.3);
+            let vol = implied_volatility(*option_type, price, 100., 110., 0.75, 0.01, 0.02).unwrap();
+            assert_fuzzy_eq!(vol, 0.3, tol);
+        }
+        assert!(implied_volatility(OptionType::Call, 200., 100., 110., 0.75, 0.01, 0.02).is_err());
+    }
+
+    #[test]
+    fn option_valuation_from_db() {
+        let tol = 1e-8;
+        let conn = Connection::open(":memory:").unwrap();
+        let mut db = SqliteDB::new(&conn);
+        db.init().unwrap();
+        let eur = Currency::from_str("EUR").unwrap();
+        let add_asset = |db: &mut SqliteDB, name: &str| {
+            let asset_id = db.insert_asset(&Asset::new(None, name, None, None, None)).unwrap();
+            db.insert_ticker(&Ticker {
+                id: None,
+                name: name.to_string(),
+                asset: asset_id,
+                priority: 10,
+                currency: eur,
+                source: "manual".to_string(),
+                factor: 1.0,
+                source_url: None,
+                usage: TickerUsage::Both,
+                last_quote_time: None,
+                source_chain: Vec::new(),
+            })
+            .unwrap()
+        };
+        let underlying_ticker = add_asset(&mut db, "BASF");
+        let underlying_id = db.get_ticker_by_id(underlying_ticker).unwrap().asset;
+        let quote_time = Utc.ymd(2020, 6, 1).and_hms(18, 0, 0);
+        db.insert_quote(&Quote {
+            id: None,
+            ticker: underlying_ticker,
+            price: 50.,
+            time: quote_time,
+            volume: None,
+            quality_score: None,
+            source: None,
+            open: None,
+            high: None,
+            low: None,
+            bid: None,
+            ask: None,
+        })
+        .unwrap();
+        let option_id = db
+            .insert_asset(&Asset::new(None, "BASF Call 52", None, None, None))
+            .unwrap();
+        let terms = OptionTerms {
+            asset_id: option_id,
+            underlying_id,
+            strike: 52.,
+            expiry: NaiveDate::from_ymd(2021, 6, 1),
+            option_type: OptionType::Call,
+            contract_size: 100.,
+        };
+        db.insert_option_terms(&terms).unwrap();
+        let stored_terms = db.get_option_terms(option_id).unwrap().unwrap();
+        assert_eq!(stored_terms.expiry, terms.expiry);
+        assert_eq!(stored_terms.option_type, OptionType::Call);
+        assert!(db.get_option_terms(underlying_id).unwrap().is_none());
+
+        // no quote for the option, use model price
+        let params = OptionMarketParameters {
+            rate: 0.01,
+            dividend_yield: 0.03,
+            volatility: Volatility::Manual(0.25),
+        };
+        let time = Utc.ymd(2020, 6, 2).and_hms(18, 0, 0);
+        let mut diagnostics = Diagnostics::new();
+        let price = get_asset_price(&mut db, option_id, time, &params, &mut diagnostics).unwrap();
+        assert!(diagnostics.contains(DiagnosticCode::ModelPrice));
+        assert_eq!(diagnostics.max_severity(), Some(Severity::Info));
+        let t = 364. / 365.;
+        let (unit_value, greeks) =
+            black_scholes(OptionType::Call, 50., 52., t, 0.01, 0.03, 0.25);
+        assert_fuzzy_eq!(price.price, 100. * unit_value, tol);
+        let valuation = price.option_valuation.unwrap();
+        assert!(!valuation.expired);
+        assert_fuzzy_eq!(valuation.greeks.unwrap().delta, 100. * greeks.delta, tol);
+
+        // expired option is valued at intrinsic value
+        let time = Utc.ymd(2021, 6, 2).and_hms(18, 0, 0);
+        let price = get_asset_price(&mut db, option_id, time, &params, &mut diagnostics).unwrap();
+        let valuation = price.option_valuation.unwrap();
+        assert!(valuation.expired);
+        assert!(valuation.greeks.is_none());
+        assert_fuzzy_eq!(price.price, 0., tol);
+
+        // volatility implied from option quote
+        let mut option_ticker = db.get_ticker_by_id(underlying_ticker).unwrap();
+        option_ticker.id = None;
+        option_ticker.name = "BASF Call 52".to_string();
+        option_ticker.asset = option_id;
+        let option_ticker = db.insert_ticker(&option_ticker).unwrap();
+        let t_quote = 365. / 365.;
+        let (quoted, _) = black_scholes(OptionType::Call, 50., 52., t_quote, 0.01, 0.03, 0.3);
+        db.insert_quote(&Quote {
+            id: None,
+            ticker: option_ticker,
+            price: 100. * quoted,
+            time: quote_time,
+            volume: None,
+            quality_score: None,
+            source: None,
+            open: None,
+            high: None,
+            low: None,
+            bid: None,
+            ask: None,
+        })
+        .unwrap();
+        let params = OptionMarketParameters {
+            volatility: Volatility::Implied,
+            ..params
+        };
+        let time = Utc.ymd(2020, 6, 2).and_hms(18, 0, 0);
+        let valuation = value_option(&mut db, &terms, time, &params).unwrap();
+        let (unit_value, _) = black_scholes(OptionType::Call, 50., 52., t, 0.01, 0.03, 0.3);
+        assert_fuzzy_eq!(valuation.value, 100. * unit_value, 1e-6);
+        // with a market quote available, the quote is used
+        let mut diagnostics = Diagnostics::new();
+        let price = get_asset_price(&mut db, option_id, time, &params, &mut diagnostics).unwrap();
+        assert!(price.option_valuation.is_none());
+        assert_fuzzy_eq!(price.price, 100. * quoted, tol);
+        assert!(diagnostics.is_empty());
+        // but flagged if outdated
+        let time = Utc.ymd(2020, 6, 30).and_hms(18, 0, 0);
+        get_asset_price(&mut db, option_id, time, &params, &mut diagnostics).unwrap();
+        assert!(diagnostics.contains(DiagnosticCode::StaleQuote));
+        assert!(!diagnostics.contains(DiagnosticCode::ModelPrice));
+    }
+
+    /// Provider of the given source returning a quote stamped with a fixed fetch time
+    struct IntradayProvider {
+        source: &'static str,
+    }
+
+    impl QuoteProvider for IntradayProvider {
+        fn fetch_quotes(
+            &self,
+            ticker: &Ticker,
+            _start: NaiveDate,
+            _end: NaiveDate,
+        ) -> Result<Vec<Quote>, DataError> {
+            Ok(vec![Quote {
+                id: None,
+                ticker: ticker.id.unwrap(),
+                price: 10.,
+                time: Utc.ymd(2021, 3, 1).and_hms(17, 35, 0),
+                volume: None,
+                quality_score: None,
+                source: None,
+                open: None,
+                high: None,
+                low: None,
+                bid: None,
+                ask: None,
+            }])
+        }
+
+        fn supports_source(&self, source: &str) -> bool {
+            source == self.source
+        }
+    }
+
+    #[test]
+    fn prices_as_of_delayed_quotes() {
+        let conn = Connection::open(":memory:").unwrap();
+        let mut db = SqliteDB::new(&conn);
+        db.init().unwrap();
+        let mut registry = QuoteProviderRegistry::new();
+        registry.register(Box::new(IntradayProvider { source: "yahoo" }));
+        registry.register(Box::new(IntradayProvider { source: "comdirect" }));
+        registry.set_delay_minutes("yahoo", 15);
+        let today = NaiveDate::from_ymd(2021, 3, 1);
+        let mut asset_ids = Vec::new();
+        for (name, source) in &[("BASF", "yahoo"), ("Siemens", "comdirect")] {
+            let asset_id = db.insert_asset(&Asset::new(None, name, None, None, None)).unwrap();
+            let mut ticker = Ticker {
+                id: None,
+                name: name.to_string(),
+                asset: asset_id,
+                source: source.to_string(),
+                priority: 1,
+                currency: Currency::from_str("EUR").unwrap(),
+                factor: 1.0,
+                source_url: None,
+                usage: TickerUsage::Both,
+                last_quote_time: None,
+                source_chain: Vec::new(),
+            };
+            ticker.id = Some(db.insert_ticker(&ticker).unwrap());
+            refresh_ticker(&ticker, &mut db, &registry, today).unwrap();
+            asset_ids.push(asset_id);
+        }
+
+        let params = OptionMarketParameters {
+            rate: 0.,
+            dividend_yield: 0.,
+            volatility: Volatility::Implied,
+        };
+        let time = Utc.ymd(2021, 3, 1).and_hms(17, 40, 0);
+        let mut diagnostics = Diagnostics::new();
+        let prices: Vec<AssetPrice> = asset_ids
+            .iter()
+            .map(|id| get_asset_price(&mut db, *id, time, &params, &mut diagnostics).unwrap())
+            .collect();
+        let delayed = Utc.ymd(2021, 3, 1).and_hms(17, 20, 0);
+        let realtime = Utc.ymd(2021, 3, 1).and_hms(17, 35, 0);
+        assert_eq!(prices[0].quote_time, Some(delayed));
+        assert_eq!(prices[1].quote_time, Some(realtime));
+        assert_eq!(prices_as_of(&prices), Some(delayed));
+        assert_eq!(prices_as_of(&prices[1..]), Some(realtime));
+        assert_eq!(prices_as_of(&[]), None);
+    }
+}
@@ -1,9 +1,786 @@
-///! Implementation of portfolio
+//! Implementation of portfolio and lot accounting of asset positions
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+
+use chrono::{DateTime, NaiveDate, Utc};
 use serde::{Deserialize, Serialize};
 
+use finql_data::{
+    CashAmount, Currency, DataError, LotSelection, Order, Transaction, TransactionHandler,
+    TransactionType,
+};
+
+use crate::diagnostics::{DiagnosticCode, Diagnostics, Severity};
+
+/// Positions below this threshold are considered to be closed
+const POSITION_TOLERANCE: f64 = 1e-10;
+
 /// Type of transaction
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Portfolio {
     id: u64,
     name: String,
 }
+
+/// Open (part of a) position originating from a single buy transaction
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Lot {
+    /// Id of the buy transaction
+    pub transaction_id: usize,
+    pub date: NaiveDate,
+    /// Position still open
+    pub position: f64,
+    /// Purchase price per unit
+    pub unit_price: f64,
+    pub currency: Currency,
+}
+
+/// Gain realized by closing (part of) a lot with a sell transaction
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RealizedGain {
+    pub sell_transaction_id: Option<usize>,
+    pub buy_transaction_id: usize,
+    pub quantity: f64,
+    pub gain: CashAmount,
+}
+
+/// Get all transactions of an asset up to and including the given date, ordered by date and id
+fn get_asset_transactions(
+    db: &mut dyn TransactionHandler,
+    asset_id: usize,
+    date: NaiveDate,
+) -> Result<Vec<Transaction>, DataError> {
+    let mut transactions: Vec<Transaction> = db
+        .get_all_transactions()?
+        .into_iter()
+        .filter(|t| match t.transaction_type {
+            TransactionType::Asset { asset_id: id, .. }
+            | TransactionType::Transfer { asset_id: id, .. } => id == asset_id,
+            _ => false,
+        })
+        .filter(|t| t.cash_flow.date <= date)
+        .collect();
+    transactions.sort_by_key(|t| (t.cash_flow.date, t.id));
+    Ok(transactions)
+}
+
+fn realize_gain(
+    lot: &mut Lot,
+    quantity: f64,
+    sell: &Transaction,
+    unit_proceeds: f64,
+) -> Result<RealizedGain, DataError> {
+    if lot.currency != sell.cash_flow.amount.currency {
+        return Err(DataError::CurrencyMismatch(format!(
+            "lot of transaction {} is in {}, but sell is in {}",
+            lot.transaction_id, lot.currency, sell.cash_flow.amount.currency
+        )));
+    }
+    lot.position -= quantity;
+    Ok(RealizedGain {
+        sell_transaction_id: sell.id,
+        buy_transaction_id: lot.transaction_id,
+        quantity,
+        gain: CashAmount {
+            amount: quantity * (unit_proceeds - lot.unit_price),
+            currency: lot.currency,
+        },
+    })
+}
+
+/// Close lots by a sell transaction, first the explicitly selected lots,
+/// the remaining position is closed in FIFO order.
+fn close_lots(
+    lots: &mut Vec<Lot>,
+    sell: &Transaction,
+    position: f64,
+    selection: &[LotSelection],
+) -> Result<Vec<RealizedGain>, DataError> {
+    let mut gains = Vec::new();
+    let mut remaining = -position;
+    let unit_proceeds = sell.cash_flow.amount.amount / remaining;
+    for selected in selection {
+        if selected.quantity <= 0. || selected.quantity.is_nan() {
+            return Err(DataError::InvalidTransaction(format!(
+                "lot of transaction {}: selected quantity {} is not positive",
+                selected.buy_transaction_id, selected.quantity
+            )));
+        }
+        let lot = lots
+            .iter_mut()
+            .find(|lot| lot.transaction_id == selected.buy_transaction_id)
+            .ok_or_else(|| {
+                DataError::InvalidTransaction(format!(
+                    "lot of transaction {} is not open",
+                    selected.buy_transaction_id
+                ))
+            })?;
+        if selected.quantity > lot.position + POSITION_TOLERANCE {
+            return Err(DataError::InvalidTransaction(format!(
+                "lot of transaction {} over-allocated: {} selected, but only {} available",
+                lot.transaction_id, selected.quantity, lot.position
+            )));
+        }
+        gains.push(realize_gain(lot, selected.quantity, sell, unit_proceeds)?);
+        remaining -= selected.quantity;
+    }
+    if remaining < -POSITION_TOLERANCE {
+        return Err(DataError::InvalidTransaction(format!(
+            "selected lots exceed the sold position by {}",
+            -remaining
+        )));
+    }
+    for lot in lots.iter_mut() {
+        if remaining <= POSITION_TOLERANCE {
+            break;
+        }
+        if lot.position <= POSITION_TOLERANCE {
+            continue;
+        }
+        let quantity = remaining.min(lot.position);
+        gains.push(realize_gain(lot, quantity, sell, unit_proceeds)?);
+        remaining -= quantity;
+    }
+    if remaining > POSITION_TOLERANCE {
+        return Err(DataError::InvalidTransaction(format!(
+            "sold position exceeds open position by {}",
+            remaining
+        )));
+    }
+    lots.retain(|lot| lot.position > POSITION_TOLERANCE);
+    Ok(gains)
+}
+
+/// Remove the given quantity from the open lots in FIFO order without realizing any gains.
+/// The removed parts of the lots are returned with their original purchase dates and prices.
+fn take_lots(
+    lots: &mut Vec<Lot>,
+    transfer: &Transaction,
+    quantity: f64,
+) -> Result<Vec<Lot>, DataError> {
+    let mut taken = Vec::new();
+    let mut remaining = quantity;
+    for lot in lots.iter_mut() {
+        if remaining <= POSITION_TOLERANCE {
+            break;
+        }
+        let part = remaining.min(lot.position);
+        lot.position -= part;
+        remaining -= part;
+        taken.push(Lot {
+            position: part,
+            ..lot.clone()
+        });
+    }
+    if remaining > POSITION_TOLERANCE {
+        return Err(DataError::InvalidTransaction(format!(
+            "transfer {:?} exceeds open position by {}",
+            transfer.id, remaining
+        )));
+    }
+    lots.retain(|lot| lot.position > POSITION_TOLERANCE);
+    Ok(taken)
+}
+
+/// Add the lots carried by the outgoing leg of a transfer to the open lots
+fn receive_lots(
+    lots: &mut Vec<Lot>,
+    in_transit: &mut HashMap<usize, Vec<Lot>>,
+    transfer: &Transaction,
+    position: f64,
+    transfer_ref: Option<usize>,
+) -> Result<(), DataError> {
+    let carried = transfer_ref
+        .and_then(|id| in_transit.remove(&id))
+        .ok_or_else(|| {
+            DataError::InvalidTransaction(format!(
+                "transfer {:?} has no matching outgoing leg",
+                transfer.id
+            ))
+        })?;
+    let carried_position: f64 = carried.iter().map(|lot| lot.position).sum();
+    if (carried_position - position).abs() > POSITION_TOLERANCE {
+        return Err(DataError::InvalidTransaction(format!(
+            "transfer {:?} receives {}, but {} have been sent",
+            transfer.id, position, carried_position
+        )));
+    }
+    lots.extend(carried);
+    lots.sort_by_key(|lot| (lot.date, lot.transaction_id));
+    Ok(())
+}
+
+/// Replay all transactions of an asset up to the given date
+fn replay_lots(
+    db: &mut dyn TransactionHandler,
+    asset_id: usize,
+    date: NaiveDate,
+) -> Result<(Vec<Lot>, Vec<RealizedGain>), DataError> {
+    let mut lots = Vec::new();
+    let mut gains = Vec::new();
+    // lots sent by outgoing transfer legs, indexed by the id of the outgoing leg
+    let mut in_transit = HashMap::new();
+    for trans in get_asset_transactions(db, asset_id, date)? {
+        if let TransactionType::Transfer {
+            position,
+            transfer_ref,
+            ..
+        } = trans.transaction_type
+        {
+            if position < 0. {
+                let taken = take_lots(&mut lots, &trans, -position)?;
+                in_transit.insert(trans.id.unwrap_or_default(), taken);
+            } else if position > 0. {
+                receive_lots(&mut lots, &mut in_transit, &trans, position, transfer_ref)?;
+            }
+        } else if let TransactionType::Asset { position, .. } = trans.transaction_type {
+            if position > 0. {
+                lots.push(Lot {
+                    transaction_id: trans.id.unwrap_or_default(),
+                    date: trans.cash_flow.date,
+                    position,
+                    unit_price: -trans.cash_flow.amount.amount / position,
+                    currency: trans.cash_flow.amount.currency,
+                });
+            } else if position < 0. {
+                let selection = match trans.id {
+                    Some(id) => db.get_lot_selection(id)?,
+                    None => Vec::new(),
+                };
+                gains.append(&mut close_lots(&mut lots, &trans, position, &selection)?);
+            }
+        }
+    }
+    Ok((lots, gains))
+}
+
+/// Get all open lots of an asset at the end of the given date
+pub fn get_open_lots(
+    db: &mut dyn TransactionHandler,
+    asset_id: usize,
+    date: NaiveDate,
+) -> Result<Vec<Lot>, DataError> {
+    Ok(replay_lots(db, asset_id, date)?.0)
+}
+
+/// Get all gains realized for an asset up to and including the given date.
+/// Explicitly selected lots are honoured, all other sells close lots in FIFO order.
+pub fn get_realized_gains(
+    db: &mut dyn TransactionHandler,
+    asset_id: usize,
+    date: NaiveDate,
+) -> Result<Vec<RealizedGain>, DataError> {
+    Ok(replay_lots(db, asset_id, date)?.1)
+}
+
+/// Store a sell transaction together with the lots it should close, either both or none.
+/// The selection is validated against the lots open at the date of the transaction
+/// and rejected if any lot is over-allocated or a selected quantity is not positive.
+pub fn insert_sell_with_lot_selection(
+    db: &mut dyn TransactionHandler,
+    transaction: &Transaction,
+    lots: &[LotSelection],
+) -> Result<usize, DataError> {
+    let (asset_id, position) = match transaction.transaction_type {
+        TransactionType::Asset { asset_id, position } if position < 0. => (asset_id, position),
+        _ => {
+            return Err(DataError::InvalidTransaction(
+                "lots can only be selected for sell transactions".to_string(),
+            ))
+        }
+    };
+    let mut open_lots = get_open_lots(db, asset_id, transaction.cash_flow.date)?;
+    close_lots(&mut open_lots, transaction, position, lots)?;
+    db.insert_transaction_with_lot_selection(transaction, lots)
+}
+
+/// Calculate the positions of all assets at the end of `effective_date` as they were known at
+/// `knowledge_time`, i.e. considering only transactions recorded by then. This reproduces the
+/// positions a past report has shown, even if corrections have been entered later.
+/// Positions are returned as pairs of asset id and position ordered by asset id, closed
+/// positions are omitted.
+pub fn calculate_positions_as_known_at(
+    db: &mut dyn TransactionHandler,
+    effective_date: NaiveDate,
+    knowledge_time: DateTime<Utc>,
+) -> Result<Vec<(usize, f64)>, DataError> {
+    let mut positions: BTreeMap<usize, f64> = BTreeMap::new();
+    for trans in db.get_all_transactions()? {
+        if trans.cash_flow.date > effective_date || !trans.is_known_at(knowledge_time) {
+            continue;
+        }
+        match trans.transaction_type {
+            TransactionType::Asset { asset_id, position }
+            | TransactionType::Transfer {
+                asset_id, position, ..
+            } => *positions.entry(asset_id).or_insert(0.) += position,
+            _ => {}
+        }
+    }
+    Ok(positions
+        .into_iter()
+        .filter(|(_, position)| position.abs() > POSITION_TOLERANCE)
+        .collect())
+}
+
+/// Add the hypothetical positions of pending orders to the given positions, given as pairs of
+/// asset id and position, e.g. to evaluate a report as if all pending orders were filled.
+/// Orders whose position can't be determined are ignored.
+pub fn positions_with_pending_orders(
+    positions: &[(usize, f64)],
+    orders: &[Order],
+) -> Vec<(usize, f64)> {
+    let mut positions = positions.to_vec();
+    for order in orders {
+        if let Some(position) = order.hypothetical_position() {
+            match positions.iter_mut().find(|(id, _)| *id == order.asset_id) {
+                Some((_, total)) => *total += position,
+                None => positions.push((order.asset_id, position)),
+            }
+        }
+    }
+    positions
+}
+
+/// Default threshold up to which positions are treated as zero
+pub const DEFAULT_POSITION_EPSILON: f64 = 1e-6;
+
+/// Status of a portfolio position
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum PositionStatus {
+    Long,
+    /// Negative position, which is either an intended short position or caused by a data error,
+    /// e.g. a missing buy transaction
+    ShortOrDataError,
+}
+
+impl fmt::Display for PositionStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Long => write!(f, "long"),
+            Self::ShortOrDataError => write!(f, "short or data error"),
+        }
+    }
+}
+
+/// Position of a single asset together with the transactions it results from
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PortfolioPosition {
+    pub asset_id: usize,
+    pub position: f64,
+    /// Ids of all asset and transfer transactions of the asset up to the date of the position
+    pub transaction_ids: Vec<usize>,
+}
+
+impl PortfolioPosition {
+    pub fn status(&self) -> PositionStatus {
+        if self.position < 0. {
+            PositionStatus::ShortOrDataError
+        } else {
+            PositionStatus::Long
+        }
+    }
+}
+
+/// Positions of all assets at a given date. Positions whose absolute value does not exceed
+/// `epsilon`, e.g. left over by rounding errors after many partial sells, are treated as zero
+/// and only listed as residuals.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PortfolioPositions {
+    pub date: NaiveDate,
+    pub epsilon: f64,
+    positions: Vec<PortfolioPosition>,
+    residuals: Vec<PortfolioPosition>,
+}
+
+impl PortfolioPositions {
+    /// All positions not treated as zero, ordered by asset id
+    pub fn positions(&self) -> &[PortfolioPosition] {
+        &self.positions
+    }
+
+    /// All positions suppressed because they do not exceed `epsilon`, ordered by asset id
+    pub fn residuals(&self) -> &[PortfolioPosition] {
+        &self.residuals
+    }
+
+    /// Negative positions, which are either short positions or caused by data errors
+    pub fn short_or_data_errors(&self) -> impl Iterator<Item = &PortfolioPosition> {
+        self.positions
+            .iter()
+            .filter(|p| p.status() == PositionStatus::ShortOrDataError)
+    }
+
+    /// Positions as pairs of asset id and position, e.g. as input to valuation reports
+    pub fn to_pairs(&self) -> Vec<(usize, f64)> {
+        self.positions
+            .iter()
+            .map(|p| (p.asset_id, p.position))
+            .collect()
+    }
+}
+
+impl fmt::Display for PortfolioPositions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Positions at {}", self.date)?;
+        for position in &self.positions {
+            if position.status() == PositionStatus::Long {
+                writeln!(f, "  asset {}: {}", position.asset_id, position.position)?;
+            }
+        }
+        let mut short = self.short_or_data_errors().peekable();
+        if short.peek().is_some() {
+            writeln!(f, "Short positions or data errors:")?;
+            for position in short {
+                writeln!(
+                    f,
+                    "  asset {}: {} (transactions {:?})",
+                    position.asset_id, position.position, position.transaction_ids
+                )?;
+            }
+        }
+        if !self.residuals.is_empty() {
+            writeln!(
+                f,
+                "{} residual positions below {} treated as zero",
+                self.residuals.len(),
+                self.epsilon
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Calculate the positions of all assets at the end of the given date. Positions whose absolute
+/// value does not exceed `epsilon` are treated as zero; unless they are exactly zero, they are
+/// listed as residuals and recorded as `PositionResidual` in `diagnostics`.
+pub fn calculate_portfolio_positions(
+    db: &mut dyn TransactionHandler,
+    date: NaiveDate,
+    epsilon: f64,
+    diagnostics: &mut Diagnostics,
+) -> Result<PortfolioPositions, DataError> {
+    let mut positions: BTreeMap<usize, PortfolioPosition> = BTreeMap::new();
+    let mut transactions: Vec<Transaction> = db
+        .get_all_transactions()?
+        .into_iter()
+        .filter(|t| t.cash_flow.date <= date)
+        .collect();
+    transactions.sort_by_key(|t| t.id);
+    for trans in transactions {
+        if let TransactionType::Asset { asset_id, position }
+        | TransactionType::Transfer {
+            asset_id, position, ..
+        } = trans.transaction_type
+        {
+            let total = positions.entry(asset_id).or_insert(PortfolioPosition {
+                asset_id,
+                position: 0.,
+                transaction_ids: Vec::new(),
+            });
+            total.position += position;
+            total.transaction_ids.extend(trans.id);
+        }
+    }
+    let mut portfolio = PortfolioPositions {
+        date,
+        epsilon,
+        positions: Vec::new(),
+        residuals: Vec::new(),
+    };
+    for (asset_id, position) in positions {
+        if position.position.abs() > epsilon {
+            portfolio.positions.push(position);
+        } else if position.position != 0. {
+            diagnostics.record(
+                DiagnosticCode::PositionResidual,
+                Severity::Info,
+                vec![asset_id],
+                format!(
+                    "residual position {} of asset {} at {} treated as zero",
+                    position.position, asset_id, date
+                ),
+            );
+            portfolio.residuals.push(position);
+        }
+    }
+    Ok(portfolio)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+    use rusqlite::Connection;
+
+    use finql_data::{Asset, AssetHandler, CashFlow};
+    use finql_sqlite::SqliteDB;
+
+    use super::*;
+
+    fn trade(asset_id: usize, position: f64, amount: f64, day: u32) -> Transaction {
+        Transaction {
+            id: None,
+            transaction_type: TransactionType::Asset { asset_id, position },
+            cash_flow: CashFlow::new(
+                amount,
+                Currency::from_str("EUR").unwrap(),
+                NaiveDate::from_ymd(2020, 1, day),
+            ),
+            note: None,
+            execution_meta: None,
+            recorded_at: None,
+        }
+    }
+
+    #[test]
+    fn sell_with_lot_selection() {
+        let tol = 1e-10;
+        let conn = Connection::open(":memory:").unwrap();
+        let mut db = SqliteDB::new(&conn);
+        db.init().unwrap();
+        let asset_id = db
+            .insert_asset(&Asset::new(None, "Admiral Group plc", None, None, None))
+            .unwrap();
+        let buy1 = db.insert_transaction(&trade(asset_id, 10., -1000., 2)).unwrap();
+        let buy2 = db.insert_transaction(&trade(asset_id, 10., -1500., 3)).unwrap();
+
+        let open_lots = get_open_lots(&mut db, asset_id, NaiveDate::from_ymd(2020, 1, 10)).unwrap();
+        assert_eq!(open_lots.len(), 2);
+        assert_fuzzy_eq!(open_lots[1].unit_price, 150., tol);
+
+        // over-allocation of second lot
+        let sell = trade(asset_id, -12., 2400., 10);
+        let err = insert_sell_with_lot_selection(
+            &mut db,
+            &sell,
+            &[LotSelection { buy_transaction_id: buy2, quantity: 11. }],
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains(&format!("lot of transaction {}", buy2)));
+        // selections must not add shares back to a lot
+        for quantity in &[0., -2.] {
+            let err = insert_sell_with_lot_selection(
+                &mut db,
+                &sell,
+                &[LotSelection { buy_transaction_id: buy2, quantity: *quantity }],
+            )
+            .unwrap_err();
+            assert!(err.to_string().contains("is not positive"));
+        }
+        assert_eq!(db.get_all_transactions().unwrap().len(), 2);
+
+        // sell 8 from second lot, remainder of 4 from first lot (FIFO)
+        insert_sell_with_lot_selection(
+            &mut db,
+            &sell,
+            &[LotSelection { buy_transaction_id: buy2, quantity: 8. }],
+        )
+        .unwrap();
+        let open_lots = get_open_lots(&mut db, asset_id, NaiveDate::from_ymd(2020, 1, 10)).unwrap();
+        assert_eq!(open_lots.len(), 2);
+        assert_eq!(open_lots[0].transaction_id, buy1);
+        assert_fuzzy_eq!(open_lots[0].position, 6., tol);
+        assert_fuzzy_eq!(open_lots[1].position, 2., tol);
+
+        let gains =
+            get_realized_gains(&mut db, asset_id, NaiveDate::from_ymd(2020, 1, 10)).unwrap();
+        assert_eq!(gains.len(), 2);
+        assert_eq!(gains[0].buy_transaction_id, buy2);
+        assert_fuzzy_eq!(gains[0].gain.amount, 8. * (200. - 150.), tol);
+        assert_eq!(gains[1].buy_transaction_id, buy1);
+        assert_fuzzy_eq!(gains[1].gain.amount, 4. * (200. - 100.), tol);
+
+        // before the sell, all lots are fully open
+        let open_lots = get_open_lots(&mut db, asset_id, NaiveDate::from_ymd(2020, 1, 9)).unwrap();
+        assert_fuzzy_eq!(open_lots[0].position + open_lots[1].position, 20., tol);
+    }
+
+    #[test]
+    fn transfer_carries_lots() {
+        let tol = 1e-10;
+        let conn = Connection::open(":memory:").unwrap();
+        let mut db = SqliteDB::new(&conn);
+        db.init().unwrap();
+        let asset_id = db
+            .insert_asset(&Asset::new(None, "Admiral Group plc", None, None, None))
+            .unwrap();
+        let buy1 = db.insert_transaction(&trade(asset_id, 10., -1000., 2)).unwrap();
+        let buy2 = db.insert_transaction(&trade(asset_id, 10., -1500., 3)).unwrap();
+
+        // move 15 units to another broker
+        let mut transfer = trade(asset_id, 0., 0., 5);
+        transfer.transaction_type = TransactionType::Transfer {
+            asset_id,
+            position: -15.,
+            transfer_ref: None,
+        };
+        let transfer_out = db.insert_transaction(&transfer).unwrap();
+        transfer.transaction_type = TransactionType::Transfer {
+            asset_id,
+            position: 15.,
+            transfer_ref: Some(transfer_out),
+        };
+        db.insert_transaction(&transfer).unwrap();
+
+        // the transfer itself realizes no gains and keeps the original lots
+        let date = NaiveDate::from_ymd(2020, 1, 5);
+        assert!(get_realized_gains(&mut db, asset_id, date).unwrap().is_empty());
+        let open_lots = get_open_lots(&mut db, asset_id, date).unwrap();
+        assert_eq!(open_lots.len(), 3);
+        assert_fuzzy_eq!(open_lots.iter().map(|lot| lot.position).sum::<f64>(), 20., tol);
+        assert_eq!(open_lots[0].transaction_id, buy1);
+        assert_eq!(open_lots[0].date, NaiveDate::from_ymd(2020, 1, 2));
+
+        // sell in the receiving account, gains are based on the original cost basis
+        db.insert_transaction(&trade(asset_id, -12., 2400., 10)).unwrap();
+        let date = NaiveDate::from_ymd(2020, 1, 10);
+        let gains = get_realized_gains(&mut db, asset_id, date).unwrap();
+        assert_eq!(gains.len(), 2);
+        assert_eq!(gains[0].buy_transaction_id, buy1);
+        assert_fuzzy_eq!(gains[0].quantity, 10., tol);
+        assert_fuzzy_eq!(gains[0].gain.amount, 10. * (200. - 100.), tol);
+        assert_eq!(gains[1].buy_transaction_id, buy2);
+        assert_fuzzy_eq!(gains[1].gain.amount, 2. * (200. - 150.), tol);
+        let open_lots = get_open_lots(&mut db, asset_id, date).unwrap();
+        assert_fuzzy_eq!(open_lots.iter().map(|lot| lot.position).sum::<f64>(), 8., tol);
+        assert!(open_lots.iter().all(|lot| lot.transaction_id == buy2));
+
+        // incoming leg without matching outgoing leg is rejected
+        transfer.transaction_type = TransactionType::Transfer {
+            asset_id,
+            position: 1.,
+            transfer_ref: None,
+        };
+        db.insert_transaction(&transfer).unwrap();
+        assert!(get_open_lots(&mut db, asset_id, date).is_err());
+    }
+
+    #[test]
+    fn pending_orders_as_positions() {
+        use chrono::{TimeZone, Utc};
+        use finql_data::{OrderSide, OrderSize, OrderState};
+
+        let eur = Currency::from_str("EUR").unwrap();
+        let time = Utc.ymd(2021, 3, 1).and_hms(9, 0, 0);
+        let buy = Order::new(1, OrderSide::Buy, OrderSize::Quantity(5.), eur, None, time);
+        let sell = Order::new(2, OrderSide::Sell, OrderSize::Amount(300.), eur, Some(100.), time);
+        let mut filled = buy.clone();
+        filled.state = OrderState::Filled;
+        let unpriced = Order::new(3, OrderSide::Buy, OrderSize::Amount(300.), eur, None, time);
+        let orders = [buy, sell, filled, unpriced];
+        let positions = positions_with_pending_orders(&[(1, 10.), (2, 10.)], &orders);
+        assert_eq!(positions, vec![(1, 15.), (2, 7.)]);
+    }
+
+    #[test]
+    fn positions_as_known_at() {
+        use chrono::TimeZone;
+
+        let conn = Connection::open(":memory:").unwrap();
+        let mut db = SqliteDB::new(&conn);
+        db.init().unwrap();
+        let asset_id = db
+            .insert_asset(&Asset::new(None, "Admiral Group plc", None, None, None))
+            .unwrap();
+        let recorded = |trans: Transaction, month, day| Transaction {
+            recorded_at: Some(Utc.ymd(2020, month, day).and_hms(18, 0, 0)),
+            ..trans
+        };
+        db.insert_transaction(&recorded(trade(asset_id, 10., -1000., 2), 1, 2))
+            .unwrap();
+        db.insert_transaction(&recorded(trade(asset_id, 5., -750., 5), 1, 5))
+            .unwrap();
+        // the second buy was only 3 shares, which is corrected a month later
+        db.insert_transaction(&recorded(trade(asset_id, -2., 300., 5), 2, 1))
+            .unwrap();
+
+        let end_of_january = NaiveDate::from_ymd(2020, 1, 31);
+        let report_time = Utc.ymd(2020, 1, 31).and_hms(20, 0, 0);
+        let positions =
+            calculate_positions_as_known_at(&mut db, end_of_january, report_time).unwrap();
+        assert_eq!(positions, vec![(asset_id, 15.)]);
+        let positions = calculate_positions_as_known_at(&mut db, end_of_january, Utc::now())
+            .unwrap();
+        assert_eq!(positions, vec![(asset_id, 13.)]);
+        let positions = calculate_positions_as_known_at(
+            &mut db,
+            NaiveDate::from_ymd(2020, 1, 3),
+            Utc::now(),
+        )
+        .unwrap();
+        assert_eq!(positions, vec![(asset_id, 10.)]);
+        // nothing was known before the first transaction has been entered
+        let positions = calculate_positions_as_known_at(
+            &mut db,
+            end_of_january,
+            Utc.ymd(2020, 1, 2).and_hms(12, 0, 0),
+        )
+        .unwrap();
+        assert!(positions.is_empty());
+    }
+
+    #[test]
+    fn residual_positions_after_partial_sells() {
+        let conn = Connection::open(":memory:").unwrap();
+        let mut db = SqliteDB::new(&conn);
+        db.init().unwrap();
+        let mut insert_asset = |name: &str| {
+            db.insert_asset(&Asset::new(None, name, None, None, None))
+                .unwrap()
+        };
+        let noisy = insert_asset("Noisy");
+        let rounded = insert_asset("Rounded");
+        let short = insert_asset("Short");
+        let long = insert_asset("Long");
+
+        // selling a position of 1 in ten parts leaves float noise
+        db.insert_transaction(&trade(noisy, 1., -100., 2)).unwrap();
+        for day in 3..13 {
+            db.insert_transaction(&trade(noisy, -0.1, 11., day)).unwrap();
+        }
+        // rounded position of a broker statement leaves a tiny negative residual
+        let rounded_buy = db.insert_transaction(&trade(rounded, 3., -30., 2)).unwrap();
+        let rounded_sell = db
+            .insert_transaction(&trade(rounded, -3.0000001, 33., 3))
+            .unwrap();
+        // sell without the buy being entered
+        db.insert_transaction(&trade(short, -5., 50., 4)).unwrap();
+        db.insert_transaction(&trade(long, 2.5, -25., 4)).unwrap();
+
+        let date = NaiveDate::from_ymd(2020, 1, 31);
+        let mut diagnostics = Diagnostics::new();
+        let epsilon = DEFAULT_POSITION_EPSILON;
+        let positions =
+            calculate_portfolio_positions(&mut db, date, epsilon, &mut diagnostics).unwrap();
+        assert_eq!(positions.to_pairs(), vec![(short, -5.), (long, 2.5)]);
+        let short_positions: Vec<usize> =
+            positions.short_or_data_errors().map(|p| p.asset_id).collect();
+        assert_eq!(short_positions, vec![short]);
+        assert_eq!(positions.positions()[1].status(), PositionStatus::Long);
+
+        let residuals = positions.residuals();
+        assert_eq!(residuals.len(), 2);
+        assert_eq!(residuals[0].asset_id, noisy);
+        assert!(residuals[0].position != 0.);
+        assert_eq!(residuals[0].transaction_ids.len(), 11);
+        assert_eq!(residuals[1].asset_id, rounded);
+        assert!(residuals[1].position < 0.);
+        assert_eq!(residuals[1].transaction_ids, vec![rounded_buy, rounded_sell]);
+        assert_eq!(diagnostics.len(), 2);
+        assert!(diagnostics
+            .iter()
+            .all(|d| d.code == DiagnosticCode::PositionResidual));
+
+        let report = positions.to_string();
+        assert!(report.contains("Short positions or data errors:"));
+        assert!(report.contains(&format!("  asset {}: -5 (transactions", short)));
+        assert!(report.contains("2 residual positions"));
+
+        // with a smaller epsilon, the negative residual shows up as data error
+        let mut diagnostics = Diagnostics::new();
+        let positions =
+            calculate_portfolio_positions(&mut db, date, 1e-9, &mut diagnostics).unwrap();
+        assert_eq!(positions.residuals().len(), 1);
+        assert_eq!(positions.short_or_data_errors().count(), 2);
+    }
+}
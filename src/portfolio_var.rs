@@ -0,0 +1,458 @@
+//! Value-at-Risk (VaR) and conditional Value-at-Risk (CVaR, also known as expected shortfall)
+//! of a portfolio.
+//!
+//! All figures are returned as positive amounts of loss, i.e. a VaR of 1000 at a confidence
+//! level of 99% means that losses exceeding 1000 are expected in only 1% of the periods the
+//! returns refer to. Returns are simple returns per period, e.g. as calculated by
+//! `returns::simple_returns`.
+//!
+//! Therefore, the parametric VaR is `-portfolio_value * (mean - z * std_dev)`, i.e. the
+//! negated value of the return at the quantile, like the historical VaR. The plain form
+//! `portfolio_value * (mean - z * std_dev)` would report losses as negative amounts.
+
+use serde::Serialize;
+
+use crate::analytics_error::AnalyticsError;
+use crate::options::{norm_cdf, norm_pdf};
+
+/// Returns sorted in ascending order and the number of returns in the tail
+/// beyond the percentile given by the confidence level
+fn sorted_tail(
+    returns: &[f64],
+    confidence_level: f64,
+) -> Result<(Vec<f64>, usize), AnalyticsError> {
+    if !(confidence_level > 0. && confidence_level < 1.) {
+        return Err(AnalyticsError::InvalidConfidenceLevel(confidence_level));
+    }
+    if returns.is_empty() {
+        return Err(AnalyticsError::NoData);
+    }
+    if let Some(value) = returns.iter().find(|r| !r.is_finite()) {
+        return Err(AnalyticsError::InvalidReturn(*value));
+    }
+    let mut sorted = returns.to_vec();
+    sorted.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    // the percentile is the k-th worst return, but at least the worst one;
+    // the tolerance avoids rounding up due to the binary representation of the level
+    let k = ((1. - confidence_level) * sorted.len() as f64 - 1e-9).ceil() as usize;
+    Ok((sorted, k.max(1)))
+}
+
+/// Value-at-Risk by historical simulation, i.e. the loss of the `(1 - confidence_level)`
+/// percentile of the given returns applied to the current portfolio value
+pub fn historical_var(
+    returns: &[f64],
+    confidence_level: f64,
+    portfolio_value: f64,
+) -> Result<f64, AnalyticsError> {
+    let (sorted, k) = sorted_tail(returns, confidence_level)?;
+    Ok(-portfolio_value * sorted[k - 1])
+}
+
+/// Value-at-Risk assuming normally distributed returns with the given mean and standard
+/// deviation, i.e. the loss `-portfolio_value * (mean - z * std_dev)`, where `z` is the
+/// quantile of the standard normal distribution at the confidence level. Returns `NaN`
+/// if the confidence level is not strictly between 0 and 1.
+pub fn parametric_var(mean: f64, std_dev: f64, confidence_level: f64, portfolio_value: f64) -> f64 {
+    let z = norm_inv(confidence_level);
+    -portfolio_value * (mean - z * std_dev)
+}
+
+/// Conditional Value-at-Risk (expected shortfall) by historical simulation, i.e. the average
+/// loss of all returns at or below the historical VaR threshold
+pub fn conditional_var(
+    returns: &[f64],
+    confidence_level: f64,
+    portfolio_value: f64,
+) -> Result<f64, AnalyticsError> {
+    let (sorted, k) = sorted_tail(returns, confidence_level)?;
+    let tail_mean = sorted[..k].iter().sum::<f64>() / k as f64;
+    Ok(-portfolio_value * tail_mean)
+}
+
+/// Sample standard deviations of the given return series and the matrix of their pairwise
+/// correlations. All series must be of the same length with at least two returns each.
+/// Series with zero volatility, e.g. cash, are uncorrelated to all others.
+pub fn volatilities_and_correlations(
+    returns: &[Vec<f64>],
+) -> Result<(Vec<f64>, Vec<Vec<f64>>), AnalyticsError> {
+    let periods = returns
+        .first()
+        .map(|r| r.len())
+        .ok_or(AnalyticsError::NoData)?;
+    if periods < 2 {
+        return Err(AnalyticsError::NoData);
+    }
+    if returns.iter().any(|r| r.len() != periods) {
+        return Err(AnalyticsError::InvalidInput(
+            "all return series must be of the same length".to_string(),
+        ));
+    }
+    if let Some(value) = returns.iter().flatten().find(|r| !r.is_finite()) {
+        return Err(AnalyticsError::InvalidReturn(*value));
+    }
+    let deviations: Vec<Vec<f64>> = returns
+        .iter()
+        .map(|r| {
+            let mean = r.iter().sum::<f64>() / periods as f64;
+            r.iter().map(|value| value - mean).collect()
+        })
+        .collect();
+    let covariance = |i: usize, j: usize| {
+        deviations[i]
+            .iter()
+            .zip(&deviations[j])
+            .map(|(x, y)| x * y)
+            .sum::<f64>()
+            / (periods - 1) as f64
+    };
+    let volatilities: Vec<f64> = (0..returns.len())
+        .map(|i| covariance(i, i).sqrt())
+        .collect();
+    let correlations = (0..returns.len())
+        .map(|i| {
+            (0..returns.len())
+                .map(|j| {
+                    if i == j {
+                        1.
+                    } else if volatilities[i] == 0. || volatilities[j] == 0. {
+                        0.
+                    } else {
+                        // rounding must not push perfectly correlated series beyond 1
+                        (covariance(i, j) / (volatilities[i] * volatilities[j])).clamp(-1., 1.)
+                    }
+                })
+                .collect()
+        })
+        .collect();
+    Ok((volatilities, correlations))
+}
+
+/// Contribution of a single position to the risk of the portfolio
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct RiskContribution {
+    pub asset_id: usize,
+    /// Share of the position in the total value of the portfolio
+    pub weight: f64,
+    /// Standard deviation of the position's returns
+    pub volatility: f64,
+    /// Change of the portfolio volatility per unit of weight added to the position
+    pub marginal_volatility: f64,
+    /// Share of the portfolio volatility contributed by the position; the shares of all
+    /// positions add up to one
+    pub volatility_share: f64,
+    /// Share of the historical VaR contributed by the position; the shares of all positions
+    /// add up to one
+    pub var_share: f64,
+    /// True if the position contributes more than the configured share to either the
+    /// volatility or the VaR of the portfolio
+    pub flagged: bool,
+}
+
+/// Risk figures of a portfolio broken down into the contributions of its positions
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct RiskContributionReport {
+    /// Standard deviation of the portfolio returns per period
+    pub volatility: f64,
+    /// Historical Value-at-Risk of the portfolio
+    pub var: f64,
+    pub positions: Vec<RiskContribution>,
+}
+
+impl RiskContributionReport {
+    /// Risk contribution of the position in the given asset, if there is any
+    pub fn contribution_of(&self, asset_id: usize) -> Option<&RiskContribution> {
+        self.positions.iter().find(|p| p.asset_id == asset_id)
+    }
+}
+
+/// Contribution of each position to the volatility and to the historical VaR of the portfolio
+///
+/// Positions are given as pairs of asset id and value, `returns` are the returns per period
+/// of the assets in the same order, e.g. of the time slice of interest. The volatility
+/// contribution is the weight times the marginal contribution to risk, based on the
+/// correlations and volatilities of the assets. The VaR contribution is each position's
+/// weighted return in the scenario determining the historical VaR. If the portfolio has no
+/// volatility or no return in that scenario, all shares are zero. Positions contributing
+/// more than `flag_share` to either figure are flagged.
+pub fn risk_contributions(
+    positions: &[(usize, f64)],
+    returns: &[Vec<f64>],
+    confidence_level: f64,
+    flag_share: f64,
+) -> Result<RiskContributionReport, AnalyticsError> {
+    if positions.len() != returns.len() {
+        return Err(AnalyticsError::InvalidInput(
+            "returns must be given for each position".to_string(),
+        ));
+    }
+    let total_value: f64 = positions.iter().map(|(_, value)| value).sum();
+    if !total_value.is_finite() || total_value == 0. {
+        return Err(AnalyticsError::InvalidInput(format!(
+            "total portfolio value must be finite and non-zero, but is {}",
+            total_value
+        )));
+    }
+    let (volatilities, correlations) = volatilities_and_correlations(returns)?;
+    let weights: Vec<f64> = positions
+        .iter()
+        .map(|(_, value)| value / total_value)
+        .collect();
+    // covariance of each asset with the portfolio
+    let portfolio_covariances: Vec<f64> = (0..weights.len())
+        .map(|i| {
+            (0..weights.len())
+                .map(|j| correlations[i][j] * volatilities[i] * volatilities[j] * weights[j])
+                .sum()
+        })
+        .collect();
+    let variance: f64 = weights
+        .iter()
+        .zip(&portfolio_covariances)
+        .map(|(w, c)| w * c)
+        .sum();
+    let volatility = variance.max(0.).sqrt();
+
+    let periods = returns[0].len();
+    let portfolio_returns: Vec<f64> = (0..periods)
+        .map(|t| weights.iter().zip(returns).map(|(w, r)| w * r[t]).sum())
+        .collect();
+    let (sorted, k) = sorted_tail(&portfolio_returns, confidence_level)?;
+    let var_return = sorted[k - 1];
+    // period of the VaR scenario; ties are equivalent since they have the same portfolio return
+    let scenario = portfolio_returns
+        .iter()
+        .position(|r| *r == var_return)
+        .unwrap();
+
+    let positions = positions
+        .iter()
+        .enumerate()
+        .map(|(i, (asset_id, _))| {
+            let marginal_volatility = if volatility > 0. {
+                portfolio_covariances[i] / volatility
+            } else {
+                0.
+            };
+            let volatility_share = if volatility > 0. {
+                weights[i] * marginal_volatility / volatility
+            } else {
+                0.
+            };
+            let var_share = if var_return != 0. {
+                weights[i] * returns[i][scenario] / var_return
+            } else {
+                0.
+            };
+            RiskContribution {
+                asset_id: *asset_id,
+                weight: weights[i],
+                volatility: volatilities[i],
+                marginal_volatility,
+                volatility_share,
+                var_share,
+                flagged: volatility_share > flag_share || var_share > flag_share,
+            }
+        })
+        .collect();
+    Ok(RiskContributionReport {
+        volatility,
+        var: -total_value * var_return,
+        positions,
+    })
+}
+
+/// Quantile function of the standard normal distribution, using the rational approximation
+/// by Acklam refined by one step of Halley's method
+fn norm_inv(p: f64) -> f64 {
+    if !(p > 0. && p < 1.) {
+        return f64::NAN;
+    }
+    const A: [f64; 6] = [
+        -3.969683028665376e+01,
+        2.209460984245205e+02,
+        -2.759285104469687e+02,
+        1.38357751867269e+02,
+        -3.066479806614716e+01,
+        2.506628277459239e+00,
+    ];
+    const B: [f64; 5] = [
+        -5.447609879822406e+01,
+        1.615858368580409e+02,
+        -1.556989798598866e+02,
+        6.680131188771972e+01,
+        -1.328068155288572e+01,
+    ];
+    const C: [f64; 6] = [
+        -7.784894002430293e-03,
+        -3.223964580411365e-01,
+        -2.400758277161838e+00,
+        -2.549732539343734e+00,
+        4.374664141464968e+00,
+        2.938163982698783e+00,
+    ];
+    const D: [f64; 4] = [
+        7.784695709041462e-03,
+        3.224671290700398e-01,
+        2.445134137142996e+00,
+        3.754408661907416e+00,
+    ];
+    const P_LOW: f64 = 0.02425;
+
+    let tail = |q: f64| {
+        (((((C[0] * q + C[1]) * q + C[2]) * q + C[3]) * q + C[4]) * q + C[5])
+            / ((((D[0] * q + D[1]) * q + D[2]) * q + D[3]) * q + 1.)
+    };
+    let x = if p < P_LOW {
+        tail((-2. * p.ln()).sqrt())
+    } else if p <= 1. - P_LOW {
+        let q = p - 0.5;
+        let r = q * q;
+        (((((A[0] * r + A[1]) * r + A[2]) * r + A[3]) * r + A[4]) * r + A[5]) * q
+            / (((((B[0] * r + B[1]) * r + B[2]) * r + B[3]) * r + B[4]) * r + 1.)
+    } else {
+        -tail((-2. * (1. - p).ln()).sqrt())
+    };
+    let e = norm_cdf(x) - p;
+    let u = e / norm_pdf(x);
+    x - u / (1. + x * u / 2.)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Returns -5.0%, -4.9%, ..., 4.9%
+    fn uniform_returns() -> Vec<f64> {
+        (0..100).rev().map(|i| (i as f64 - 50.) / 1000.).collect()
+    }
+
+    #[test]
+    fn historical_var_and_cvar() {
+        let tol = 1e-10;
+        let returns = uniform_returns();
+        // 5 worst returns in the tail at 95%, only the worst one at 99%
+        assert_fuzzy_eq!(historical_var(&returns, 0.95, 1e6).unwrap(), 46_000., tol);
+        assert_fuzzy_eq!(historical_var(&returns, 0.99, 1e6).unwrap(), 50_000., tol);
+        assert_fuzzy_eq!(conditional_var(&returns, 0.95, 1e6).unwrap(), 48_000., tol);
+        assert_fuzzy_eq!(conditional_var(&returns, 0.99, 1e6).unwrap(), 50_000., tol);
+    }
+
+    #[test]
+    fn parametric_var_normal_quantiles() {
+        let tol = 1e-6;
+        assert_fuzzy_eq!(norm_inv(0.5), 0., 1e-12);
+        assert_fuzzy_eq!(norm_inv(0.95), 1.6448536269514722, 1e-12);
+        assert_fuzzy_eq!(norm_inv(0.01), -2.3263478740408408, 1e-12);
+        assert_fuzzy_eq!(parametric_var(0., 0.02, 0.95, 1e6), 32_897.072539, tol);
+        assert_fuzzy_eq!(parametric_var(0.001, 0.02, 0.99, 1e6), 45_526.957481, tol);
+        assert!(parametric_var(0., 0.02, 1., 1e6).is_nan());
+    }
+
+    #[test]
+    fn parametric_var_reports_losses_as_positive_amounts() {
+        // the negated form of `portfolio_value * (mean - z * std_dev)`
+        let z = norm_inv(0.95);
+        assert_fuzzy_eq!(
+            parametric_var(0.001, 0.02, 0.95, 1e6),
+            -1e6 * (0.001 - z * 0.02),
+            1e-6
+        );
+        // same sign as the historical VaR of returns with about the same distribution
+        let returns: Vec<f64> = (0..100)
+            .map(|i| 0.001 + 0.02 * norm_inv((i as f64 + 0.5) / 100.))
+            .collect();
+        let historical = historical_var(&returns, 0.95, 1e6).unwrap();
+        let parametric = parametric_var(0.001, 0.02, 0.95, 1e6);
+        assert!(historical > 0. && parametric > 0.);
+        assert!((historical - parametric).abs() < 0.1 * parametric);
+    }
+
+    #[test]
+    fn risk_contributions_of_three_assets() {
+        let tol = 1e-10;
+        // A and B are uncorrelated, C is cash
+        let returns = vec![
+            vec![0.02, -0.02, 0.02, -0.02],
+            vec![0.01, -0.01, -0.01, 0.01],
+            vec![0., 0., 0., 0.],
+        ];
+        let (volatilities, correlations) = volatilities_and_correlations(&returns).unwrap();
+        assert_fuzzy_eq!(volatilities[0], 0.04 / 3f64.sqrt(), tol);
+        assert_fuzzy_eq!(volatilities[1], 0.02 / 3f64.sqrt(), tol);
+        assert_eq!(volatilities[2], 0.);
+        assert_fuzzy_eq!(correlations[0][1], 0., tol);
+        assert_eq!(correlations[0][2], 0.);
+        assert_eq!(correlations[2][2], 1.);
+
+        let positions = [(1, 50.), (2, 30.), (3, 20.)];
+        let report = risk_contributions(&positions, &returns, 0.75, 0.5).unwrap();
+        // variance 0.5^2 * 0.0016/3 + 0.3^2 * 0.0004/3 = 0.000436/3
+        assert_fuzzy_eq!(report.volatility, (0.000436f64 / 3.).sqrt(), tol);
+        let a = report.contribution_of(1).unwrap();
+        let b = report.contribution_of(2).unwrap();
+        let c = report.contribution_of(3).unwrap();
+        assert_fuzzy_eq!(a.weight, 0.5, tol);
+        assert_fuzzy_eq!(a.volatility_share, 100. / 109., tol);
+        assert_fuzzy_eq!(b.volatility_share, 9. / 109., tol);
+        assert_eq!(c.volatility_share, 0.);
+        assert_fuzzy_eq!(
+            a.marginal_volatility,
+            0.5 * 0.0016 / 3. / report.volatility,
+            tol
+        );
+        // the worst portfolio return is 0.5 * -2% + 0.3 * -1% = -1.3%
+        assert_fuzzy_eq!(report.var, 1.3, tol);
+        assert_fuzzy_eq!(a.var_share, 10. / 13., tol);
+        assert_fuzzy_eq!(b.var_share, 3. / 13., tol);
+        assert_eq!(c.var_share, 0.);
+        assert!(a.flagged);
+        assert!(!b.flagged);
+        assert!(!c.flagged);
+    }
+
+    #[test]
+    fn risk_contributions_of_degenerate_portfolios() {
+        let tol = 1e-10;
+        let returns = vec![0.02, -0.02, 0.01, -0.01];
+
+        let single =
+            risk_contributions(&[(1, 100.)], std::slice::from_ref(&returns), 0.75, 0.5).unwrap();
+        assert_fuzzy_eq!(single.positions[0].volatility_share, 1., tol);
+        assert_fuzzy_eq!(single.positions[0].var_share, 1., tol);
+        assert_fuzzy_eq!(single.volatility, single.positions[0].volatility, tol);
+
+        // shares of perfectly correlated assets are proportional to weight times volatility
+        let doubled: Vec<f64> = returns.iter().map(|r| 2. * r).collect();
+        let correlated =
+            risk_contributions(&[(1, 50.), (2, 50.)], &[returns, doubled], 0.75, 0.5).unwrap();
+        assert_fuzzy_eq!(correlated.positions[0].volatility_share, 1. / 3., tol);
+        assert_fuzzy_eq!(correlated.positions[1].volatility_share, 2. / 3., tol);
+        assert_fuzzy_eq!(correlated.positions[0].var_share, 1. / 3., tol);
+        assert_fuzzy_eq!(correlated.positions[1].var_share, 2. / 3., tol);
+
+        let cash = risk_contributions(&[(1, 100.)], &[vec![0.; 4]], 0.75, 0.5).unwrap();
+        assert_eq!(cash.volatility, 0.);
+        assert_eq!(cash.var, 0.);
+        assert_eq!(cash.positions[0].volatility_share, 0.);
+        assert_eq!(cash.positions[0].var_share, 0.);
+        assert!(!cash.positions[0].flagged);
+
+        assert!(risk_contributions(&[(1, 100.)], &[vec![0.01]], 0.75, 0.5).is_err());
+        let zero = vec![vec![0.; 2], vec![0.; 2]];
+        assert!(risk_contributions(&[(1, 100.), (2, -100.)], &zero, 0.75, 0.5).is_err());
+    }
+
+    #[test]
+    fn invalid_var_input() {
+        assert_eq!(historical_var(&[], 0.95, 1.), Err(AnalyticsError::NoData));
+        assert_eq!(
+            conditional_var(&[0.01], 1.5, 1.),
+            Err(AnalyticsError::InvalidConfidenceLevel(1.5))
+        );
+        assert!(matches!(
+            historical_var(&[0.01, f64::NAN], 0.95, 1.),
+            Err(AnalyticsError::InvalidReturn(_))
+        ));
+    }
+}
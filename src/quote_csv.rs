@@ -0,0 +1,730 @@
+//! Export and import of quotes in CSV format.
+//!
+//! The CSV data starts with a header line followed by one line per quote with the columns
+//! `ticker`, `source`, `currency`, `time`, `price`, `volume` and `quality_score`. Ticker are
+//! identified by name and source instead of their ids, which differ between databases. Times
+//! are given in RFC 3339 format in UTC, optional values are left empty if not set.
+//!
+//! Quotes are exported page by page, i.e. the memory required does not depend on the number
+//! of quotes, which allows exporting quote tables of any size. On import, missing ticker and
+//! assets may be created and duplicates are handled according to the import options.
+
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::io::{Read, Write};
+use std::str::FromStr;
+
+use chrono::{DateTime, NaiveDate, NaiveTime, SecondsFormat, Utc};
+
+use finql_data::{
+    Asset, Currency, DataError, Quote, QuoteHandler, QuoteQuery, Ticker, TickerUsage,
+};
+
+/// Column names of the header line
+pub const QUOTE_CSV_HEADER: [&str; 7] = [
+    "ticker",
+    "source",
+    "currency",
+    "time",
+    "price",
+    "volume",
+    "quality_score",
+];
+
+/// Number of quotes read from the database at once during export. The output is flushed and
+/// progress is reported after each page.
+pub const EXPORT_PAGE_SIZE: usize = 10_000;
+
+/// Error related to the export or import of quotes
+#[derive(Debug)]
+pub enum QuoteCsvError {
+    DBError(DataError),
+    /// Reading or writing CSV data failed
+    CsvError(csv::Error),
+    IoError(std::io::Error),
+    /// Import of quotes failed due to invalid input data
+    ImportFailed(String),
+}
+
+impl fmt::Display for QuoteCsvError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::DBError(_) => write!(f, "database error"),
+            Self::CsvError(_) => write!(f, "invalid CSV data"),
+            Self::IoError(_) => write!(f, "writing CSV data failed"),
+            Self::ImportFailed(err) => write!(f, "import of quotes failed: {}", err),
+        }
+    }
+}
+
+impl Error for QuoteCsvError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::DBError(err) => Some(err),
+            Self::CsvError(err) => Some(err),
+            Self::IoError(err) => Some(err),
+            Self::ImportFailed(_) => None,
+        }
+    }
+}
+
+impl From<DataError> for QuoteCsvError {
+    fn from(error: DataError) -> Self {
+        Self::DBError(error)
+    }
+}
+
+impl From<csv::Error> for QuoteCsvError {
+    fn from(error: csv::Error) -> Self {
+        Self::CsvError(error)
+    }
+}
+
+impl From<std::io::Error> for QuoteCsvError {
+    fn from(error: std::io::Error) -> Self {
+        Self::IoError(error)
+    }
+}
+
+fn optional_to_string(value: Option<f64>) -> String {
+    value.map_or_else(String::new, |v| v.to_string())
+}
+
+/// Write all quotes matching `filter` in order of their ids as CSV data to `writer`. If
+/// given, `progress` is called with the number of quotes written so far after each
+/// `EXPORT_PAGE_SIZE` quotes. Returns the number of quotes written.
+pub fn export_quotes_csv<W: Write>(
+    db: &mut dyn QuoteHandler,
+    writer: W,
+    filter: QuoteQuery,
+    mut progress: Option<&mut dyn FnMut(usize)>,
+) -> Result<usize, QuoteCsvError> {
+    let mut writer = csv::Writer::from_writer(writer);
+    writer.write_record(QUOTE_CSV_HEADER)?;
+    // name, source and currency by ticker id
+    let mut ticker: HashMap<usize, (String, String, String)> = HashMap::new();
+    let mut count = 0;
+    let mut after_id = None;
+    loop {
+        let quotes = db.get_quotes_page(&filter, after_id, EXPORT_PAGE_SIZE)?;
+        for quote in &quotes {
+            let (name, source, currency) = match ticker.entry(quote.ticker) {
+                Entry::Occupied(entry) => entry.into_mut(),
+                Entry::Vacant(entry) => {
+                    let t = db.get_ticker_by_id(quote.ticker)?;
+                    entry.insert((t.name, t.source, t.currency.to_string()))
+                }
+            };
+            writer.write_record([
+                name.as_str(),
+                source.as_str(),
+                currency.as_str(),
+                &quote.time.to_rfc3339_opts(SecondsFormat::AutoSi, true),
+                &quote.price.to_string(),
+                &optional_to_string(quote.volume),
+                &optional_to_string(quote.quality_score),
+            ])?;
+        }
+        count += quotes.len();
+        writer.flush()?;
+        if let Some(progress) = progress.as_mut() {
+            if !quotes.is_empty() {
+                progress(count);
+            }
+        }
+        if quotes.len() < EXPORT_PAGE_SIZE {
+            break;
+        }
+        after_id = quotes.last().and_then(|q| q.id);
+    }
+    Ok(count)
+}
+
+/// How to handle an imported quote if a quote of the same ticker and time is already stored
+/// or has been imported before
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DuplicatePolicy {
+    /// Keep the existing quote and skip the imported one
+    Skip,
+    /// Replace the existing quote by the imported one
+    Replace,
+    /// Reject the imported quote
+    Reject,
+}
+
+/// Options of the quote import
+#[derive(Debug, Clone)]
+pub struct QuoteImportOptions {
+    /// Create ticker which are not yet stored, which requires the column `currency`
+    pub create_ticker: bool,
+    /// Create the asset of a new ticker if no asset of that name exists. The asset name is
+    /// given by the optional column `asset`, or is the ticker name if not given.
+    pub create_assets: bool,
+    pub duplicates: DuplicatePolicy,
+    /// Time of day (in UTC) of quotes given by date only
+    pub default_time: NaiveTime,
+    /// Maximum number of quotes stored within a single database transaction
+    pub batch_size: usize,
+}
+
+impl Default for QuoteImportOptions {
+    fn default() -> QuoteImportOptions {
+        QuoteImportOptions {
+            create_ticker: false,
+            create_assets: false,
+            duplicates: DuplicatePolicy::Skip,
+            default_time: NaiveTime::from_hms(0, 0, 0),
+            batch_size: 1000,
+        }
+    }
+}
+
+/// Outcome of the import of a single line
+#[derive(Debug, Clone, PartialEq)]
+pub enum RowOutcome {
+    Inserted,
+    /// An existing quote has been replaced
+    Replaced,
+    /// Duplicate quote which has been skipped
+    Skipped,
+    /// Invalid quote, which has not been imported for the given reason
+    Rejected(String),
+}
+
+/// Result of a quote import
+#[derive(Debug, Clone, Default)]
+pub struct QuoteImportReport {
+    /// Outcome of each line in order of input, i.e. the outcome of line n (counting the header
+    /// as line 1) is at index n-2
+    pub rows: Vec<RowOutcome>,
+    /// Ids of the ticker created during import
+    pub created_ticker: Vec<usize>,
+    /// Ids of the assets created during import
+    pub created_assets: Vec<usize>,
+}
+
+impl QuoteImportReport {
+    fn count(&self, matches: impl Fn(&RowOutcome) -> bool) -> usize {
+        self.rows.iter().filter(|outcome| matches(outcome)).count()
+    }
+
+    pub fn inserted(&self) -> usize {
+        self.count(|outcome| *outcome == RowOutcome::Inserted)
+    }
+
+    pub fn replaced(&self) -> usize {
+        self.count(|outcome| *outcome == RowOutcome::Replaced)
+    }
+
+    pub fn skipped(&self) -> usize {
+        self.count(|outcome| *outcome == RowOutcome::Skipped)
+    }
+
+    pub fn rejected(&self) -> usize {
+        self.count(|outcome| matches!(outcome, RowOutcome::Rejected(_)))
+    }
+}
+
+/// Positions of the columns within a line
+struct Columns {
+    ticker: usize,
+    source: usize,
+    time: usize,
+    price: usize,
+    currency: Option<usize>,
+    asset: Option<usize>,
+    volume: Option<usize>,
+    quality_score: Option<usize>,
+}
+
+impl Columns {
+    fn from_header(header: &csv::StringRecord) -> Result<Columns, QuoteCsvError> {
+        let optional = |name: &str| header.iter().position(|column| column == name);
+        let required = |name: &str| {
+            optional(name)
+                .ok_or_else(|| QuoteCsvError::ImportFailed(format!("missing column '{}'", name)))
+        };
+        Ok(Columns {
+            ticker: required("ticker")?,
+            source: required("source")?,
+            time: required("time")?,
+            price: required("price")?,
+            currency: optional("currency"),
+            asset: optional("asset"),
+            volume: optional("volume"),
+            quality_score: optional("quality_score"),
+        })
+    }
+}
+
+/// Reason to reject a line, or failure of the whole import
+enum RowError {
+    Rejected(String),
+    Failed(QuoteCsvError),
+}
+
+impl From<DataError> for RowError {
+    fn from(error: DataError) -> Self {
+        Self::Failed(error.into())
+    }
+}
+
+fn reject<T>(reason: String) -> Result<T, RowError> {
+    Err(RowError::Rejected(reason))
+}
+
+/// Parse a time in RFC 3339 format or a date, which is combined with `default_time`
+fn parse_time(value: &str, default_time: NaiveTime) -> Option<DateTime<Utc>> {
+    match DateTime::parse_from_rfc3339(value) {
+        Ok(time) => Some(time.with_timezone(&Utc)),
+        Err(_) => NaiveDate::parse_from_str(value, "%Y-%m-%d")
+            .ok()
+            .map(|date| DateTime::from_utc(date.and_time(default_time), Utc)),
+    }
+}
+
+struct QuoteImporter<'a> {
+    db: &'a mut dyn QuoteHandler,
+    options: &'a QuoteImportOptions,
+    columns: Columns,
+    /// Ticker id by source and name
+    ticker: HashMap<(String, String), usize>,
+    /// Quotes not yet stored
+    batch: Vec<Quote>,
+    /// Position in `batch` by ticker id and time
+    batch_index: HashMap<(usize, DateTime<Utc>), usize>,
+    report: QuoteImportReport,
+}
+
+impl QuoteImporter<'_> {
+    /// Get the id of the ticker referred to by a line, creating ticker and asset if requested
+    fn ticker_id(&mut self, record: &csv::StringRecord) -> Result<usize, RowError> {
+        let get = |column: Option<usize>| {
+            column
+                .and_then(|column| record.get(column))
+                .filter(|value| !value.is_empty())
+        };
+        let name = get(Some(self.columns.ticker)).unwrap_or_default();
+        let source = get(Some(self.columns.source)).unwrap_or_default();
+        let key = (source.to_string(), name.to_string());
+        if let Some(id) = self.ticker.get(&key) {
+            return Ok(*id);
+        }
+        if let Some(id) = self
+            .db
+            .get_ticker_by_source_symbol(source, name)?
+            .and_then(|ticker| ticker.id)
+        {
+            self.ticker.insert(key, id);
+            return Ok(id);
+        }
+        if !self.options.create_ticker {
+            return reject(format!("unknown ticker '{}' of source '{}'", name, source));
+        }
+        let currency = match get(self.columns.currency).map(Currency::from_str) {
+            Some(Ok(currency)) => currency,
+            Some(Err(err)) => return reject(format!("invalid currency: {}", err)),
+            None => return reject(format!("currency of new ticker '{}' is missing", name)),
+        };
+        let mut ticker = Ticker {
+            id: None,
+            name: name.to_string(),
+            asset: 0,
+            source: source.to_string(),
+            priority: 1,
+            currency,
+            factor: 1.0,
+            source_url: None,
+            usage: TickerUsage::Both,
+            last_quote_time: None,
+            source_chain: Vec::new(),
+        };
+        if let Err(err) = ticker.validate() {
+            return reject(err.to_string());
+        }
+        let asset = Asset::new(
+            None,
+            get(self.columns.asset).unwrap_or(name),
+            None,
+            None,
+            None,
+        );
+        ticker.asset = match self.db.get_asset_id(&asset) {
+            Some(id) => id,
+            None if self.options.create_assets => {
+                let id = self.db.insert_asset(&asset)?;
+                self.report.created_assets.push(id);
+                id
+            }
+            None => return reject(format!("unknown asset '{}'", asset.name)),
+        };
+        let id = self.db.insert_ticker(&ticker)?;
+        self.report.created_ticker.push(id);
+        self.ticker.insert(key, id);
+        Ok(id)
+    }
+
+    fn parse_quote(&mut self, record: &csv::StringRecord) -> Result<Quote, RowError> {
+        let number = |name: &str, column: Option<usize>| -> Result<Option<f64>, RowError> {
+            match column.and_then(|column| record.get(column)) {
+                None | Some("") => Ok(None),
+                Some(value) => match value.parse() {
+                    Ok(value) => Ok(Some(value)),
+                    Err(_) => reject(format!("invalid {} '{}'", name, value)),
+                },
+            }
+        };
+        let time = record.get(self.columns.time).unwrap_or_default();
+        let time = match parse_time(time, self.options.default_time) {
+            Some(time) => time,
+            None => return reject(format!("invalid time '{}'", time)),
+        };
+        let price = match number("price", Some(self.columns.price))? {
+            Some(price) => price,
+            None => return reject("price is missing".to_string()),
+        };
+        let volume = number("volume", self.columns.volume)?;
+        let quality_score = number("quality score", self.columns.quality_score)?;
+        let quote = Quote {
+            id: None,
+            ticker: self.ticker_id(record)?,
+            price,
+            time,
+            volume,
+            quality_score,
+            source: None,
+            open: None,
+            high: None,
+            low: None,
+            bid: None,
+            ask: None,
+        };
+        match quote.validate() {
+            Ok(()) => Ok(quote),
+            Err(err) => reject(err.to_string()),
+        }
+    }
+
+    /// Add a quote to the current batch according to the duplicate policy
+    fn add(&mut self, mut quote: Quote) -> Result<RowOutcome, QuoteCsvError> {
+        let key = (quote.ticker, quote.time);
+        let pending = self.batch_index.get(&key).copied();
+        let stored = match pending {
+            Some(_) => None,
+            None => {
+                let query = QuoteQuery::new()
+                    .ticker_ids(&[quote.ticker])
+                    .start(quote.time)
+                    .end(quote.time);
+                self.db.get_quotes_page(&query, None, 1)?.pop()
+            }
+        };
+        if pending.is_some() || stored.is_some() {
+            match self.options.duplicates {
+                DuplicatePolicy::Skip => return Ok(RowOutcome::Skipped),
+                DuplicatePolicy::Reject => {
+                    return Ok(RowOutcome::Rejected(format!(
+                        "duplicate quote at {}",
+                        quote.time.to_rfc3339_opts(SecondsFormat::AutoSi, true)
+                    )))
+                }
+                DuplicatePolicy::Replace => {}
+            }
+        }
+        if let Some(idx) = pending {
+            quote.id = self.batch[idx].id;
+            self.batch[idx] = quote;
+            return Ok(RowOutcome::Replaced);
+        }
+        quote.id = stored.and_then(|stored| stored.id);
+        let outcome = match quote.id {
+            Some(_) => RowOutcome::Replaced,
+            None => RowOutcome::Inserted,
+        };
+        self.batch_index.insert(key, self.batch.len());
+        self.batch.push(quote);
+        if self.batch.len() >= self.options.batch_size {
+            self.store_batch()?;
+        }
+        Ok(outcome)
+    }
+
+    fn store_batch(&mut self) -> Result<(), QuoteCsvError> {
+        if !self.batch.is_empty() {
+            self.db.store_quotes(&self.batch)?;
+            self.batch.clear();
+            self.batch_index.clear();
+        }
+        Ok(())
+    }
+}
+
+/// Import quotes from CSV data with a header line naming the columns. Columns may be given in
+/// any order: `ticker`, `source`, `time` and `price` are required, `currency`, `asset`,
+/// `volume` and `quality_score` are optional and other columns are ignored. Times are given
+/// either in RFC 3339 format or as date (`YYYY-MM-DD`), which is combined with the default time
+/// of the options. Therefore, data written by `export_quotes_csv` can be imported.
+///
+/// Invalid lines are rejected and reported, while the import continues. Quotes are stored in
+/// batches of `QuoteImportOptions::batch_size`, each within a single database transaction. If
+/// storing a batch fails, the import is aborted, but batches stored before remain stored.
+pub fn import_quotes_csv<R: Read>(
+    db: &mut dyn QuoteHandler,
+    csv_data: R,
+    options: &QuoteImportOptions,
+) -> Result<QuoteImportReport, QuoteCsvError> {
+    if options.batch_size == 0 {
+        return Err(QuoteCsvError::ImportFailed(
+            "batch size must be positive".to_string(),
+        ));
+    }
+    let mut reader = csv::ReaderBuilder::new()
+        .has_headers(true)
+        .trim(csv::Trim::All)
+        .from_reader(csv_data);
+    let mut importer = QuoteImporter {
+        db,
+        options,
+        columns: Columns::from_header(reader.headers()?)?,
+        ticker: HashMap::new(),
+        batch: Vec::new(),
+        batch_index: HashMap::new(),
+        report: QuoteImportReport::default(),
+    };
+    let mut record = csv::StringRecord::new();
+    while reader.read_record(&mut record)? {
+        let outcome = match importer.parse_quote(&record) {
+            Ok(quote) => importer.add(quote)?,
+            Err(RowError::Rejected(reason)) => RowOutcome::Rejected(reason),
+            Err(RowError::Failed(err)) => return Err(err),
+        };
+        importer.report.rows.push(outcome);
+    }
+    importer.store_batch()?;
+    Ok(importer.report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use chrono::{Duration, TimeZone};
+    use rusqlite::Connection;
+
+    use finql_data::AssetHandler;
+    use finql_sqlite::SqliteDB;
+
+    /// Insert a ticker of the asset `BASF AG`, which is inserted first if not yet stored
+    fn insert_ticker(db: &mut SqliteDB, name: &str, source: &str) -> usize {
+        let basf = Asset::new(None, "BASF AG", None, None, None);
+        let asset = match db.get_asset_id(&basf) {
+            Some(id) => id,
+            None => db.insert_asset(&basf).unwrap(),
+        };
+        db.insert_ticker(&Ticker {
+            id: None,
+            name: name.to_string(),
+            asset,
+            source: source.to_string(),
+            priority: 1,
+            currency: Currency::from_str("EUR").unwrap(),
+            factor: 1.0,
+            source_url: None,
+            usage: TickerUsage::Both,
+            last_quote_time: None,
+            source_chain: Vec::new(),
+        })
+        .unwrap()
+    }
+
+    fn synthetic_quote(ticker: usize, i: usize) -> Quote {
+        Quote {
+            id: None,
+            ticker,
+            price: 50. + (i % 1000) as f64 / 7.,
+            time: Utc.ymd(2000, 1, 1).and_hms(0, 0, 0) + Duration::minutes(i as i64),
+            volume: if i % 2 == 0 { Some(i as f64) } else { None },
+            quality_score: if i % 3 == 0 { Some(0.5) } else { None },
+            source: None,
+            open: None,
+            high: None,
+            low: None,
+            bid: None,
+            ask: None,
+        }
+    }
+
+    #[test]
+    fn export_and_reimport_quotes() {
+        let rows = 100_000;
+        let conn = Connection::open(":memory:").unwrap();
+        let mut db = SqliteDB::new(&conn);
+        db.init().unwrap();
+        // same name, but different sources
+        let xetra = insert_ticker(&mut db, "BAS", "xetra");
+        let manual = insert_ticker(&mut db, "BAS", "manual");
+        for i in 0..rows {
+            let ticker = if i % 4 == 0 { manual } else { xetra };
+            db.insert_quote(&synthetic_quote(ticker, i)).unwrap();
+        }
+
+        let mut csv_data = Vec::new();
+        let mut reported = Vec::new();
+        let mut progress = |count| reported.push(count);
+        let exported = export_quotes_csv(
+            &mut db,
+            &mut csv_data,
+            QuoteQuery::new(),
+            Some(&mut progress),
+        )
+        .unwrap();
+        assert_eq!(exported, rows);
+        assert_eq!(reported.len(), rows / EXPORT_PAGE_SIZE);
+        assert_eq!(reported[0], EXPORT_PAGE_SIZE);
+        assert_eq!(reported.last(), Some(&rows));
+        let csv_text = String::from_utf8(csv_data).unwrap();
+        let mut lines = csv_text.lines();
+        assert_eq!(
+            lines.next(),
+            Some("ticker,source,currency,time,price,volume,quality_score")
+        );
+        assert_eq!(
+            lines.next(),
+            Some("BAS,manual,EUR,2000-01-01T00:00:00Z,50,0,0.5")
+        );
+
+        // ticker are created in a different order, i.e. ids differ from the exported database
+        let conn2 = Connection::open(":memory:").unwrap();
+        let mut db2 = SqliteDB::new(&conn2);
+        db2.init().unwrap();
+        let manual2 = insert_ticker(&mut db2, "BAS", "manual");
+        let xetra2 = insert_ticker(&mut db2, "BAS", "xetra");
+        let options = QuoteImportOptions {
+            batch_size: 5000,
+            ..QuoteImportOptions::default()
+        };
+        let report = import_quotes_csv(&mut db2, csv_text.as_bytes(), &options).unwrap();
+        assert_eq!(report.inserted(), rows);
+        let manual_quotes = QuoteHandler::get_all_quotes_for_ticker(&mut db2, manual2).unwrap();
+        let xetra_quotes = QuoteHandler::get_all_quotes_for_ticker(&mut db2, xetra2).unwrap();
+        assert_eq!(manual_quotes.len(), rows / 4);
+        assert_eq!(xetra_quotes.len(), rows - rows / 4);
+        for i in &[0, 1, 4, 3333, 54_321, rows - 1] {
+            let (quotes, idx) = if i % 4 == 0 {
+                (&manual_quotes, i / 4)
+            } else {
+                (&xetra_quotes, i - i / 4 - 1)
+            };
+            let expected = synthetic_quote(0, *i);
+            let quote = &quotes[idx];
+            assert_eq!(quote.time, expected.time);
+            assert_eq!(quote.price, expected.price);
+            assert_eq!(quote.volume, expected.volume);
+            assert_eq!(quote.quality_score, expected.quality_score);
+        }
+
+        // filter by ticker and time range
+        let start = Utc.ymd(2000, 1, 1).and_hms(0, 10, 0);
+        let filter = QuoteQuery::new()
+            .ticker_ids(&[xetra2])
+            .start(start)
+            .end(start + Duration::minutes(9));
+        let mut csv_data = Vec::new();
+        assert_eq!(
+            export_quotes_csv(&mut db2, &mut csv_data, filter, None).unwrap(),
+            8
+        );
+    }
+
+    #[test]
+    fn import_with_ticker_creation_and_duplicates() {
+        let conn = Connection::open(":memory:").unwrap();
+        let mut db = SqliteDB::new(&conn);
+        db.init().unwrap();
+        let xetra = insert_ticker(&mut db, "BAS", "xetra");
+        let csv_data = "time,ticker,source,currency,price,volume,asset
+            2020-01-02,BAS,xetra,,50.5,1000,
+            2020-01-02T18:30:00+01:00,BAS,xetra,,51,,
+            2020-01-03,SAP,xetra,EUR,120,,SAP SE
+            2020-01-03,SAP,xetra,EUR,-1,,SAP SE
+            2020-01-03,AAPL,nasdaq,,150,,
+            01/03/2020,BAS,xetra,,52,,
+            2020-01-03,BAS AG,stooq,EUR,52,,BASF AG
+            2020-01-02,BAS,xetra,,50.6,,";
+        let default_time = NaiveTime::from_hms(17, 30, 0);
+
+        // neither ticker nor assets are created, duplicates are skipped
+        let options = QuoteImportOptions {
+            default_time,
+            batch_size: 2,
+            ..QuoteImportOptions::default()
+        };
+        let report = import_quotes_csv(&mut db, csv_data.as_bytes(), &options).unwrap();
+        assert_eq!(report.rows[0], RowOutcome::Inserted);
+        // same time as the first line
+        assert_eq!(report.rows[1], RowOutcome::Skipped);
+        assert!(matches!(report.rows[2], RowOutcome::Rejected(_)));
+        assert!(matches!(report.rows[5], RowOutcome::Rejected(_)));
+        assert_eq!(report.rows[7], RowOutcome::Skipped);
+        assert_eq!(
+            (report.inserted(), report.skipped(), report.rejected()),
+            (1, 2, 5)
+        );
+        assert!(report.created_ticker.is_empty());
+        let quotes = QuoteHandler::get_all_quotes_for_ticker(&mut db, xetra).unwrap();
+        assert_eq!(quotes.len(), 1);
+        assert_eq!(quotes[0].time, Utc.ymd(2020, 1, 2).and_hms(17, 30, 0));
+        assert_eq!((quotes[0].price, quotes[0].volume), (50.5, Some(1000.)));
+
+        // ticker of existing or new assets are created, duplicates are replaced
+        let options = QuoteImportOptions {
+            create_ticker: true,
+            create_assets: true,
+            duplicates: DuplicatePolicy::Replace,
+            ..options
+        };
+        let report = import_quotes_csv(&mut db, csv_data.as_bytes(), &options).unwrap();
+        assert_eq!(report.rows[0], RowOutcome::Replaced);
+        assert_eq!(report.rows[2], RowOutcome::Inserted);
+        assert!(matches!(report.rows[3], RowOutcome::Rejected(_)));
+        // currency is required to create a ticker
+        assert!(matches!(report.rows[4], RowOutcome::Rejected(_)));
+        assert_eq!(report.rows[6], RowOutcome::Inserted);
+        assert_eq!(report.rows[7], RowOutcome::Replaced);
+        assert_eq!(report.created_ticker.len(), 2);
+        assert_eq!(report.created_assets.len(), 1);
+        let quotes = QuoteHandler::get_all_quotes_for_ticker(&mut db, xetra).unwrap();
+        assert_eq!(quotes.len(), 1);
+        assert_eq!((quotes[0].price, quotes[0].volume), (50.6, None));
+        let sap = db
+            .get_ticker_by_source_symbol("xetra", "SAP")
+            .unwrap()
+            .unwrap();
+        assert_eq!(db.get_asset_by_id(sap.asset).unwrap().name, "SAP SE");
+        let stooq = db
+            .get_ticker_by_source_symbol("stooq", "BAS AG")
+            .unwrap()
+            .unwrap();
+        assert_eq!(stooq.asset, db.get_ticker_by_id(xetra).unwrap().asset);
+
+        // duplicates are rejected
+        let options = QuoteImportOptions {
+            duplicates: DuplicatePolicy::Reject,
+            ..options
+        };
+        let report = import_quotes_csv(&mut db, csv_data.as_bytes(), &options).unwrap();
+        assert_eq!(report.rejected(), 8);
+
+        let invalid = QuoteImportOptions {
+            batch_size: 0,
+            ..options.clone()
+        };
+        assert!(import_quotes_csv(&mut db, csv_data.as_bytes(), &invalid).is_err());
+        assert!(matches!(
+            import_quotes_csv(&mut db, "ticker,time,price\n".as_bytes(), &options),
+            Err(QuoteCsvError::ImportFailed(_))
+        ));
+    }
+}
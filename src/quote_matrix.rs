@@ -0,0 +1,347 @@
+//! Date-aligned matrix of daily prices of multiple assets.
+//!
+//! Loading long histories of many assets is dominated by decoding database rows and
+//! parsing times. `load_quote_matrix` therefore fetches undecoded rows per asset and does
+//! the parsing and alignment in parallel if the `rayon` feature is enabled.
+//! `load_quote_matrix_sequential` decodes all quotes via the standard quote interface and
+//! serves as reference implementation; both return identical matrices.
+//!
+//! For each asset and day, the latest quote of that day (in UTC) is used. If several ticker
+//! have a quote at the very same time, the ticker with the best (i.e. lowest) priority wins.
+//! Only ticker usable for valuation are considered. Prices are given in the currency of the
+//! respective ticker.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::ops::RangeInclusive;
+
+use chrono::{DateTime, NaiveDate, Utc};
+#[cfg(feature = "rayon")]
+use rayon::prelude::*;
+
+use finql_data::{DataError, QuoteReader, RawQuote, TickerUsage};
+
+use crate::time_period::TimePeriod;
+
+/// Daily prices of several assets aligned to a common set of dates
+#[derive(Debug, Clone)]
+pub struct QuoteMatrix {
+    pub asset_ids: Vec<usize>,
+    /// All dates any of the assets has a quote for, in ascending order
+    pub dates: Vec<NaiveDate>,
+    /// `prices[i][j]` is the price of asset `i` at date `j`, or `NaN` if there is no quote
+    pub prices: Vec<Vec<f64>>,
+    /// `coverage[i][j]` is true if asset `i` has a quote at date `j`
+    pub coverage: Vec<Vec<bool>>,
+}
+
+impl QuoteMatrix {
+    /// Align the daily price series of the given assets
+    fn from_series(asset_ids: &[usize], series: Vec<BTreeMap<NaiveDate, f64>>) -> QuoteMatrix {
+        let dates: Vec<NaiveDate> = series
+            .iter()
+            .flat_map(|prices| prices.keys().copied())
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .collect();
+        let align = |prices: &BTreeMap<NaiveDate, f64>| {
+            dates
+                .iter()
+                .map(|date| prices.get(date).copied())
+                .collect::<Vec<_>>()
+        };
+        #[cfg(feature = "rayon")]
+        let rows: Vec<Vec<Option<f64>>> = series.par_iter().map(align).collect();
+        #[cfg(not(feature = "rayon"))]
+        let rows: Vec<Vec<Option<f64>>> = series.iter().map(align).collect();
+        QuoteMatrix {
+            asset_ids: asset_ids.to_vec(),
+            prices: rows
+                .iter()
+                .map(|row| row.iter().map(|p| p.unwrap_or(f64::NAN)).collect())
+                .collect(),
+            coverage: rows
+                .iter()
+                .map(|row| row.iter().map(Option::is_some).collect())
+                .collect(),
+            dates,
+        }
+    }
+
+    /// Price of the asset with the given index at the date with the given index, if any
+    pub fn price(&self, asset_index: usize, date_index: usize) -> Option<f64> {
+        if self.coverage[asset_index][date_index] {
+            Some(self.prices[asset_index][date_index])
+        } else {
+            None
+        }
+    }
+
+    /// Fraction of dates the asset with the given index has a quote for
+    pub fn coverage_ratio(&self, asset_index: usize) -> f64 {
+        if self.dates.is_empty() {
+            return 0.0;
+        }
+        let covered = self.coverage[asset_index].iter().filter(|c| **c).count();
+        covered as f64 / self.dates.len() as f64
+    }
+
+    /// Fraction of periods of the expected quote frequency the asset with the given index has
+    /// a quote in, e.g. of the weeks for an asset quoted weekly. Periods are counted from the
+    /// first date of the matrix. Without expected frequency, this is the `coverage_ratio`.
+    pub fn expected_coverage_ratio(
+        &self,
+        asset_index: usize,
+        expected_frequency: Option<&TimePeriod>,
+    ) -> f64 {
+        let (period, first, last) =
+            match (expected_frequency, self.dates.first(), self.dates.last()) {
+                (Some(period), Some(first), Some(last)) => (period, *first, *last),
+                _ => return self.coverage_ratio(asset_index),
+            };
+        let mut periods = 0;
+        let mut covered = 0;
+        let mut start = first;
+        while start <= last {
+            let end = period.add_to(start, None);
+            periods += 1;
+            if self
+                .dates
+                .iter()
+                .zip(&self.coverage[asset_index])
+                .any(|(date, c)| *c && *date >= start && *date < end)
+            {
+                covered += 1;
+            }
+            start = end;
+        }
+        covered as f64 / periods as f64
+    }
+}
+
+impl PartialEq for QuoteMatrix {
+    /// Matrices are equal if they agree on all covered prices, missing prices are ignored
+    fn eq(&self, other: &QuoteMatrix) -> bool {
+        self.asset_ids == other.asset_ids
+            && self.dates == other.dates
+            && self.coverage == other.coverage
+            && self
+                .prices
+                .iter()
+                .zip(&self.coverage)
+                .zip(&other.prices)
+                .all(|((row, covered), other_row)| {
+                    row.iter()
+                        .zip(covered)
+                        .zip(other_row)
+                        .all(|((p, c), q)| !*c || p == q)
+                })
+    }
+}
+
+/// Select the latest quote of each day, on equal times the one with the best priority
+fn daily_prices<I>(quotes: I) -> BTreeMap<NaiveDate, f64>
+where
+    I: Iterator<Item = (DateTime<Utc>, i32, f64)>,
+{
+    let mut best: BTreeMap<NaiveDate, (DateTime<Utc>, i32, f64)> = BTreeMap::new();
+    for (time, priority, price) in quotes {
+        let date = time.naive_utc().date();
+        let is_better = match best.get(&date) {
+            Some((best_time, best_priority, _)) => {
+                time > *best_time || (time == *best_time && priority < *best_priority)
+            }
+            None => true,
+        };
+        if is_better {
+            best.insert(date, (time, priority, price));
+        }
+    }
+    best.into_iter()
+        .map(|(date, (_, _, price))| (date, price))
+        .collect()
+}
+
+fn parse_raw_quotes(raw_quotes: &[RawQuote]) -> Result<BTreeMap<NaiveDate, f64>, DataError> {
+    let quotes = raw_quotes
+        .iter()
+        .map(|raw| {
+            let time = DateTime::parse_from_rfc3339(&raw.time)
+                .map_err(|e| DataError::InvalidData(format!("{}: {}", raw.time, e)))?;
+            Ok((time.with_timezone(&Utc), raw.priority, raw.price))
+        })
+        .collect::<Result<Vec<_>, DataError>>()?;
+    Ok(daily_prices(quotes.into_iter()))
+}
+
+/// Load the daily prices of the given assets for all dates in `range`. Rows are fetched
+/// undecoded per asset and parsed in parallel if the `rayon` feature is enabled.
+pub fn load_quote_matrix(
+    db: &dyn QuoteReader,
+    asset_ids: &[usize],
+    range: RangeInclusive<NaiveDate>,
+) -> Result<QuoteMatrix, DataError> {
+    let raw_quotes = asset_ids
+        .iter()
+        .map(|id| {
+            db.get_raw_quotes_for_asset(*id, TickerUsage::Valuation, *range.start(), *range.end())
+        })
+        .collect::<Result<Vec<_>, DataError>>()?;
+    #[cfg(feature = "rayon")]
+    let series = raw_quotes.par_iter();
+    #[cfg(not(feature = "rayon"))]
+    let series = raw_quotes.iter();
+    let series = series
+        .map(|raw| parse_raw_quotes(raw))
+        .collect::<Result<Vec<_>, DataError>>()?;
+    Ok(QuoteMatrix::from_series(asset_ids, series))
+}
+
+/// Load the daily prices of the given assets for all dates in `range` by decoding all quotes
+/// of all valuation ticker of each asset one after another
+pub fn load_quote_matrix_sequential(
+    db: &dyn QuoteReader,
+    asset_ids: &[usize],
+    range: RangeInclusive<NaiveDate>,
+) -> Result<QuoteMatrix, DataError> {
+    let mut series = Vec::new();
+    for asset_id in asset_ids {
+        let mut quotes = Vec::new();
+        for ticker in db.get_all_ticker_for_asset(*asset_id)? {
+            if !ticker.usage.serves(TickerUsage::Valuation) {
+                continue;
+            }
+            let ticker_id = ticker.id.ok_or_else(|| {
+                DataError::NotFound(format!("ticker '{}' has no id", ticker.name))
+            })?;
+            for quote in db.get_all_quotes_for_ticker(ticker_id)? {
+                if range.contains(&quote.time.naive_utc().date()) {
+                    quotes.push((quote.time, ticker.priority, quote.price));
+                }
+            }
+        }
+        series.push(daily_prices(quotes.into_iter()));
+    }
+    Ok(QuoteMatrix::from_series(asset_ids, series))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    use chrono::{Datelike, Duration, TimeZone};
+    use rusqlite::Connection;
+
+    use finql_data::{Asset, AssetHandler, Currency, Quote, QuoteHandler, Ticker};
+    use finql_sqlite::SqliteDB;
+
+    fn insert_ticker(db: &mut SqliteDB, asset: usize, priority: i32, usage: TickerUsage) -> usize {
+        db.insert_ticker(&Ticker {
+            id: None,
+            name: format!("T{}-{}", asset, priority),
+            asset,
+            source: "manual".to_string(),
+            priority,
+            currency: Currency::from_str("EUR").unwrap(),
+            factor: 1.0,
+            source_url: None,
+            usage,
+            last_quote_time: None,
+            source_chain: Vec::new(),
+        })
+        .unwrap()
+    }
+
+    fn insert_quote(db: &mut SqliteDB, ticker: usize, price: f64, time: DateTime<Utc>) {
+        db.insert_quote(&Quote {
+            id: None,
+            ticker,
+            price,
+            time,
+            volume: None,
+            quality_score: None,
+            source: None,
+            open: None,
+            high: None,
+            low: None,
+            bid: None,
+            ask: None,
+        })
+        .unwrap();
+    }
+
+    #[test]
+    fn aligned_quote_matrix() {
+        let conn = Connection::open(":memory:").unwrap();
+        let mut db = SqliteDB::new(&conn);
+        db.init().unwrap();
+        let basf = db
+            .insert_asset(&Asset::new(None, "BASF AG", None, None, None))
+            .unwrap();
+        let bmw = db
+            .insert_asset(&Asset::new(None, "BMW AG", None, None, None))
+            .unwrap();
+        let basf_eod = insert_ticker(&mut db, basf, 1, TickerUsage::Valuation);
+        let basf_other = insert_ticker(&mut db, basf, 2, TickerUsage::Both);
+        let basf_rt = insert_ticker(&mut db, basf, 3, TickerUsage::Charting);
+        let bmw_eod = insert_ticker(&mut db, bmw, 1, TickerUsage::Both);
+
+        let time = |day, hour| Utc.ymd(2020, 1, day).and_hms(hour, 0, 0);
+        insert_quote(&mut db, basf_eod, 60.0, time(2, 17));
+        // same time, worse priority
+        insert_quote(&mut db, basf_other, 61.0, time(2, 17));
+        // earlier on the same day
+        insert_quote(&mut db, basf_other, 59.0, time(3, 9));
+        insert_quote(&mut db, basf_eod, 62.0, time(3, 17));
+        // charting only
+        insert_quote(&mut db, basf_rt, 99.0, time(6, 17));
+        insert_quote(&mut db, bmw_eod, 70.0, time(3, 17));
+        insert_quote(&mut db, bmw_eod, 71.0, time(6, 17));
+        // out of range
+        insert_quote(&mut db, bmw_eod, 72.0, time(8, 17));
+
+        let range = NaiveDate::from_ymd(2020, 1, 1)..=NaiveDate::from_ymd(2020, 1, 7);
+        let matrix = load_quote_matrix(&db, &[basf, bmw], range.clone()).unwrap();
+        let dates: Vec<u32> = matrix.dates.iter().map(|d| d.day()).collect();
+        assert_eq!(dates, vec![2, 3, 6]);
+        assert_eq!(matrix.price(0, 0), Some(60.0));
+        assert_eq!(matrix.price(0, 1), Some(62.0));
+        assert_eq!(matrix.price(0, 2), None);
+        assert!(matrix.prices[0][2].is_nan());
+        assert_eq!(matrix.coverage[1], vec![false, true, true]);
+        assert_eq!(matrix.price(1, 2), Some(71.0));
+        assert_fuzzy_eq!(matrix.coverage_ratio(1), 2.0 / 3.0, 1e-12);
+
+        let sequential = load_quote_matrix_sequential(&db, &[basf, bmw], range).unwrap();
+        assert_eq!(matrix, sequential);
+    }
+
+    #[test]
+    fn coverage_by_expected_frequency() {
+        let start = NaiveDate::from_ymd(2021, 3, 1);
+        // daily quotes of the first asset on all working days of three weeks
+        let daily: BTreeMap<NaiveDate, f64> = (0..19)
+            .map(|day| start + Duration::days(day))
+            .filter(|date| date.weekday().number_from_monday() <= 5)
+            .map(|date| (date, 10.0))
+            .collect();
+        // weekly quotes of the second asset in the first and third week
+        let weekly: BTreeMap<NaiveDate, f64> = vec![
+            (start + Duration::days(4), 20.0),
+            (start + Duration::days(18), 21.0),
+        ]
+        .into_iter()
+        .collect();
+        let matrix = QuoteMatrix::from_series(&[1, 2], vec![daily, weekly]);
+        assert_eq!(matrix.dates.len(), 15);
+        assert_fuzzy_eq!(matrix.coverage_ratio(1), 2.0 / 15.0, 1e-12);
+        let week = TimePeriod::from_str("1W").unwrap();
+        assert_fuzzy_eq!(
+            matrix.expected_coverage_ratio(1, Some(&week)),
+            2.0 / 3.0,
+            1e-12
+        );
+        assert_fuzzy_eq!(matrix.expected_coverage_ratio(0, Some(&week)), 1.0, 1e-12);
+        assert_fuzzy_eq!(matrix.expected_coverage_ratio(1, None), 2.0 / 15.0, 1e-12);
+    }
+}
@@ -0,0 +1,347 @@
+//! Detection and repair of outliers in stored quote histories, e.g. single spikes caused by
+//! erroneous prices delivered by a quote provider.
+//!
+//! A quote is compared to its neighbours in time: if its distance to their median exceeds a
+//! multiple of their (scaled) median absolute deviation, it is reported as outlier candidate.
+//! Median and MAD are robust against the outlier itself and against level shifts, but a quote
+//! right after a stock split would still look like an outlier if most of its neighbours are
+//! pre-split quotes. Therefore, known split dates separate the history into segments and
+//! quotes are compared to neighbours within the same segment only.
+
+use chrono::NaiveDate;
+
+use finql_data::{DataError, Quote, QuoteHandler, QuoteProvider};
+
+use crate::diagnostics::{DiagnosticCode, Diagnostics, Severity};
+
+/// Factor making the median absolute deviation an estimator of the standard deviation
+/// for normally distributed values
+const MAD_SCALE: f64 = 1.4826;
+
+/// Quote suspected to be an outlier
+#[derive(Debug, Clone)]
+pub struct OutlierCandidate {
+    pub quote: Quote,
+    /// Median price of the neighbouring quotes
+    pub median: f64,
+    /// Distance to the median in units of the scaled median absolute deviation
+    pub score: f64,
+}
+
+/// Way to repair an outlier
+pub enum RepairStrategy<'a> {
+    /// Remove the quote from the database
+    Delete,
+    /// Replace the price by linear interpolation in time between the previous and the next quote
+    ReplaceWithInterpolation,
+    /// Replace price and volume by the quote fetched for the same day from the given provider
+    ReplaceWithSourceRefetch(&'a dyn QuoteProvider),
+}
+
+/// Median of a non-empty list of values
+fn median(values: &mut [f64]) -> f64 {
+    values.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    let n = values.len();
+    if n % 2 == 1 {
+        values[n / 2]
+    } else {
+        (values[n / 2 - 1] + values[n / 2]) / 2.
+    }
+}
+
+/// Find all quotes of a ticker deviating from the median of up to `window` quotes before and
+/// after them by more than `zscore_threshold` scaled median absolute deviations. Quotes are
+/// compared only to quotes on the same side of all `split_dates`, i.e. the first days with
+/// prices after a split. Quotes with less than two neighbours are never reported.
+pub fn find_quote_outliers(
+    db: &mut dyn QuoteHandler,
+    ticker_id: usize,
+    zscore_threshold: f64,
+    window: usize,
+    split_dates: &[NaiveDate],
+) -> Result<Vec<OutlierCandidate>, DataError> {
+    if zscore_threshold.is_nan() || zscore_threshold <= 0. || window == 0 {
+        return Err(DataError::InvalidData(
+            "threshold and window must be positive".to_string(),
+        ));
+    }
+    let mut quotes = db.get_all_quotes_for_ticker(ticker_id)?;
+    quotes.sort_by_key(|quote| quote.time);
+    let segment = |quote: &Quote| {
+        let date = quote.time.naive_utc().date();
+        split_dates.iter().filter(|split| **split <= date).count()
+    };
+    let mut candidates = Vec::new();
+    for (i, quote) in quotes.iter().enumerate() {
+        let start = i.saturating_sub(window);
+        let end = (i + window + 1).min(quotes.len());
+        let mut neighbours: Vec<f64> = (start..end)
+            .filter(|j| *j != i && segment(&quotes[*j]) == segment(quote))
+            .map(|j| quotes[j].price)
+            .collect();
+        if neighbours.len() < 2 {
+            continue;
+        }
+        let median_price = median(&mut neighbours);
+        let mut deviations: Vec<f64> = neighbours
+            .iter()
+            .map(|price| (price - median_price).abs())
+            .collect();
+        let scale = MAD_SCALE * median(&mut deviations);
+        let distance = (quote.price - median_price).abs();
+        let score = if scale > 0. {
+            distance / scale
+        } else if distance > 0. {
+            f64::INFINITY
+        } else {
+            0.
+        };
+        if score > zscore_threshold {
+            candidates.push(OutlierCandidate {
+                quote: quote.clone(),
+                median: median_price,
+                score,
+            });
+        }
+    }
+    Ok(candidates)
+}
+
+/// Linear interpolation of the price at the time of `quote` between the closest quotes of the
+/// same ticker before and after it
+fn interpolated_price(db: &mut dyn QuoteHandler, quote: &Quote) -> Result<f64, DataError> {
+    let quotes = db.get_all_quotes_for_ticker(quote.ticker)?;
+    let previous = quotes
+        .iter()
+        .filter(|q| q.time < quote.time)
+        .max_by_key(|q| q.time);
+    let next = quotes
+        .iter()
+        .filter(|q| q.time > quote.time)
+        .min_by_key(|q| q.time);
+    match (previous, next) {
+        (Some(previous), Some(next)) => {
+            let weight = (quote.time - previous.time).num_seconds() as f64
+                / (next.time - previous.time).num_seconds() as f64;
+            Ok(previous.price + weight * (next.price - previous.price))
+        }
+        _ => Err(DataError::NotFound(
+            "interpolation requires quotes before and after the outlier".to_string(),
+        )),
+    }
+}
+
+/// Repair a stored quote according to the given strategy. Each repair is recorded in
+/// `diagnostics` with the ids of the ticker and of the quote.
+pub fn repair_outlier(
+    db: &mut dyn QuoteHandler,
+    quote: &Quote,
+    strategy: RepairStrategy,
+    diagnostics: &mut Diagnostics,
+) -> Result<(), DataError> {
+    let quote_id = quote
+        .id
+        .ok_or_else(|| DataError::NotFound("not yet stored to database".to_string()))?;
+    let mut repaired = quote.clone();
+    let action = match strategy {
+        RepairStrategy::Delete => {
+            db.delete_quote(quote_id)?;
+            "deleted".to_string()
+        }
+        RepairStrategy::ReplaceWithInterpolation => {
+            repaired.price = interpolated_price(db, quote)?;
+            db.update_quote(&repaired)?;
+            format!("replaced by interpolated price {}", repaired.price)
+        }
+        RepairStrategy::ReplaceWithSourceRefetch(provider) => {
+            let ticker = db.get_ticker_by_id(quote.ticker)?;
+            let date = quote.time.naive_utc().date();
+            let fetched = provider
+                .fetch_quotes(&ticker, date, date)?
+                .into_iter()
+                .find(|q| q.time.naive_utc().date() == date)
+                .ok_or_else(|| {
+                    DataError::NotFound(format!("provider has no quote for {}", date))
+                })?;
+            repaired.price = fetched.price;
+            repaired.volume = fetched.volume;
+            db.update_quote(&repaired)?;
+            format!("replaced by refetched price {}", repaired.price)
+        }
+    };
+    diagnostics.record(
+        DiagnosticCode::QuoteRepaired,
+        Severity::Info,
+        vec![quote.ticker, quote_id],
+        format!(
+            "quote of ticker {} at {} with price {} {}",
+            quote.ticker, quote.time, quote.price, action
+        ),
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{DateTime, TimeZone, Utc};
+    use rusqlite::Connection;
+    use std::str::FromStr;
+
+    use finql_data::{Asset, Currency, Ticker, TickerUsage};
+    use finql_sqlite::SqliteDB;
+
+    /// Provider delivering a fixed price for any day
+    struct FixedProvider(f64);
+
+    impl QuoteProvider for FixedProvider {
+        fn fetch_quotes(
+            &self,
+            ticker: &Ticker,
+            start: NaiveDate,
+            _end: NaiveDate,
+        ) -> Result<Vec<Quote>, DataError> {
+            Ok(vec![Quote {
+                id: None,
+                ticker: ticker.id.unwrap(),
+                price: self.0,
+                time: DateTime::<Utc>::from_utc(start.and_hms(18, 0, 0), Utc),
+                volume: Some(1000.),
+                quality_score: None,
+                source: None,
+                open: None,
+                high: None,
+                low: None,
+                bid: None,
+                ask: None,
+            }])
+        }
+
+        fn supports_source(&self, _source: &str) -> bool {
+            true
+        }
+    }
+
+    /// Store a ticker with 30 daily quotes oscillating around 101, a spike on the 10th day
+    /// and a 2:1 split taking effect on the 28th day
+    fn insert_history(db: &mut dyn QuoteHandler) -> usize {
+        let asset_id = db
+            .insert_asset(&Asset::new(None, "BASF AG", None, None, None))
+            .unwrap();
+        let ticker_id = db
+            .insert_ticker(&Ticker {
+                id: None,
+                asset: asset_id,
+                name: "BAS.DE".to_string(),
+                currency: Currency::from_str("EUR").unwrap(),
+                source: "manual".to_string(),
+                priority: 1,
+                factor: 1.0,
+                source_url: None,
+                usage: TickerUsage::Both,
+                last_quote_time: None,
+                source_chain: Vec::new(),
+            })
+            .unwrap();
+        for day in 1..=30 {
+            let mut price = 100. + (day % 3) as f64;
+            if day == 10 {
+                price *= 3.;
+            }
+            if day >= 28 {
+                price /= 2.;
+            }
+            db.insert_quote(&Quote {
+                id: None,
+                ticker: ticker_id,
+                price,
+                time: Utc.ymd(2021, 3, day).and_hms(18, 0, 0),
+                volume: None,
+                quality_score: None,
+                source: None,
+                open: None,
+                high: None,
+                low: None,
+                bid: None,
+                ask: None,
+            })
+            .unwrap();
+        }
+        ticker_id
+    }
+
+    #[test]
+    fn find_spike_but_not_split() {
+        let conn = Connection::open(":memory:").unwrap();
+        let mut db = SqliteDB::new(&conn);
+        db.init().unwrap();
+        let ticker_id = insert_history(&mut db);
+        let split = NaiveDate::from_ymd(2021, 3, 28);
+
+        let candidates = find_quote_outliers(&mut db, ticker_id, 3., 3, &[split]).unwrap();
+        assert_eq!(candidates.len(), 1);
+        assert_eq!(
+            candidates[0].quote.time,
+            Utc.ymd(2021, 3, 10).and_hms(18, 0, 0)
+        );
+        assert_fuzzy_eq!(candidates[0].median, 101., 1e-12);
+
+        // without the split date, the first quote after the split looks like an outlier
+        let candidates = find_quote_outliers(&mut db, ticker_id, 3., 3, &[]).unwrap();
+        assert_eq!(candidates.len(), 2);
+        assert_eq!(
+            candidates[1].quote.time,
+            Utc.ymd(2021, 3, 28).and_hms(18, 0, 0)
+        );
+
+        assert!(find_quote_outliers(&mut db, ticker_id, 0., 3, &[]).is_err());
+    }
+
+    #[test]
+    fn repair_spike() {
+        let conn = Connection::open(":memory:").unwrap();
+        let mut db = SqliteDB::new(&conn);
+        db.init().unwrap();
+        let ticker_id = insert_history(&mut db);
+        let mut diagnostics = Diagnostics::new();
+        let find_spike = |db: &mut SqliteDB| {
+            find_quote_outliers(db, ticker_id, 3., 3, &[NaiveDate::from_ymd(2021, 3, 28)])
+                .unwrap()
+                .pop()
+        };
+
+        let spike = find_spike(&mut db).unwrap().quote;
+        repair_outlier(
+            &mut db,
+            &spike,
+            RepairStrategy::ReplaceWithInterpolation,
+            &mut diagnostics,
+        )
+        .unwrap();
+        assert!(find_spike(&mut db).is_none());
+        let quotes = db.get_all_quotes_for_ticker(ticker_id).unwrap();
+        let repaired = quotes.iter().find(|q| q.id == spike.id).unwrap();
+        // halfway between 100 on the 9th and 102 on the 11th
+        assert_fuzzy_eq!(repaired.price, 101., 1e-12);
+
+        repair_outlier(
+            &mut db,
+            repaired,
+            RepairStrategy::ReplaceWithSourceRefetch(&FixedProvider(101.5)),
+            &mut diagnostics,
+        )
+        .unwrap();
+        let quotes = db.get_all_quotes_for_ticker(ticker_id).unwrap();
+        let refetched = quotes.iter().find(|q| q.id == spike.id).unwrap();
+        assert_fuzzy_eq!(refetched.price, 101.5, 1e-12);
+        assert_eq!(refetched.volume, Some(1000.));
+
+        repair_outlier(&mut db, refetched, RepairStrategy::Delete, &mut diagnostics).unwrap();
+        assert_eq!(db.get_all_quotes_for_ticker(ticker_id).unwrap().len(), 29);
+
+        assert_eq!(diagnostics.len(), 3);
+        assert!(diagnostics.iter().all(
+            |d| d.code == DiagnosticCode::QuoteRepaired && d.entity_ids[1] == spike.id.unwrap()
+        ));
+    }
+}
@@ -0,0 +1,281 @@
+//! Conversion of interest rates between compounding methods and
+//! between zero rates and discount factors.
+//!
+//! All conversions are done via the growth factor of an investment of one unit
+//! over a time period `t` given as year fraction. Negative rates are supported as long
+//! as the growth factor stays positive, e.g. for annual compounding the rate must be
+//! larger than -100%.
+
+use std::error::Error;
+use std::fmt;
+
+use chrono::NaiveDate;
+
+use super::Compounding;
+use crate::day_count_conv::{DayCountConv, DayCountConvError};
+
+/// Error related to the conversion of interest rates
+#[derive(Debug)]
+pub enum RateConversionError {
+    /// The rate is too negative for the compounding method, i.e. the growth factor would not be positive
+    InvalidRate,
+    /// Discount factors must be positive
+    InvalidDiscountFactor,
+    /// A rate can't be derived from a discount factor for a time period of zero length
+    ZeroTimePeriod,
+    DayCountError(DayCountConvError),
+}
+
+impl fmt::Display for RateConversionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidRate => write!(f, "rate results in non-positive growth factor"),
+            Self::InvalidDiscountFactor => write!(f, "discount factor must be positive"),
+            Self::ZeroTimePeriod => write!(f, "rate is undefined for a time period of zero length"),
+            Self::DayCountError(_) => write!(f, "calculation of year fraction failed"),
+        }
+    }
+}
+
+impl Error for RateConversionError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::DayCountError(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<DayCountConvError> for RateConversionError {
+    fn from(error: DayCountConvError) -> Self {
+        Self::DayCountError(error)
+    }
+}
+
+/// Number of compounding periods per year, if the compounding method is periodic
+fn periods_per_year(compounding: Compounding) -> Option<f64> {
+    match compounding {
+        Compounding::Annual => Some(1.),
+        Compounding::SemiAnnual => Some(2.),
+        Compounding::Quarterly => Some(4.),
+        Compounding::Monthly => Some(12.),
+        Compounding::Simple | Compounding::Continuous => None,
+    }
+}
+
+/// Value of one unit invested at `rate` for a time period of year fraction `t`
+pub fn growth_factor(rate: f64, t: f64, compounding: Compounding) -> Result<f64, RateConversionError> {
+    match compounding {
+        Compounding::Continuous => Ok((rate * t).exp()),
+        Compounding::Simple => {
+            let growth = 1. + rate * t;
+            if growth > 0. {
+                Ok(growth)
+            } else {
+                Err(RateConversionError::InvalidRate)
+            }
+        }
+        _ => {
+            let m = periods_per_year(compounding).unwrap();
+            let base = 1. + rate / m;
+            if base > 0. {
+                Ok(base.powf(m * t))
+            } else {
+                Err(RateConversionError::InvalidRate)
+            }
+        }
+    }
+}
+
+/// Rate that yields the given growth factor over a time period of year fraction `t`
+pub fn rate_from_growth_factor(
+    growth: f64,
+    t: f64,
+    compounding: Compounding,
+) -> Result<f64, RateConversionError> {
+    if growth.is_nan() || growth <= 0. {
+        return Err(RateConversionError::InvalidDiscountFactor);
+    }
+    if t == 0. {
+        return Err(RateConversionError::ZeroTimePeriod);
+    }
+    match compounding {
+        Compounding::Continuous => Ok(growth.ln() / t),
+        Compounding::Simple => Ok((growth - 1.) / t),
+        _ => {
+            let m = periods_per_year(compounding).unwrap();
+            Ok(m * (growth.powf(1. / (m * t)) - 1.))
+        }
+    }
+}
+
+/// Calculate the discount factor for a time period of year fraction `t`
+pub fn discount_factor_from_rate(
+    rate: f64,
+    t: f64,
+    compounding: Compounding,
+) -> Result<f64, RateConversionError> {
+    Ok(1. / growth_factor(rate, t, compounding)?)
+}
+
+/// Calculate the zero rate that belongs to the discount factor `df` for a time period of year fraction `t`
+pub fn rate_from_discount_factor(
+    df: f64,
+    t: f64,
+    compounding: Compounding,
+) -> Result<f64, RateConversionError> {
+    if df.is_nan() || df <= 0. {
+        return Err(RateConversionError::InvalidDiscountFactor);
+    }
+    rate_from_growth_factor(1. / df, t, compounding)
+}
+
+/// Convert `rate` given with compounding method `from` into the equivalent rate with
+/// compounding method `to`, i.e. the rate that yields the same growth over one year.
+/// Please note that for simple compounding the equivalence holds only for the one year horizon.
+pub fn equivalent_rate(
+    rate: f64,
+    from: Compounding,
+    to: Compounding,
+) -> Result<f64, RateConversionError> {
+    rate_from_growth_factor(growth_factor(rate, 1., from)?, 1., to)
+}
+
+/// Calculate the discount factor from `start` to `end`, with year fraction given by `day_count_conv`
+pub fn discount_factor_between(
+    rate: f64,
+    start: NaiveDate,
+    end: NaiveDate,
+    day_count_conv: DayCountConv,
+    compounding: Compounding,
+) -> Result<f64, RateConversionError> {
+    let t = day_count_conv.year_fraction(start, end, None, None)?;
+    discount_factor_from_rate(rate, t, compounding)
+}
+
+/// Calculate the zero rate from `start` to `end` belonging to the discount factor `df`,
+/// with year fraction given by `day_count_conv`
+pub fn zero_rate_between(
+    df: f64,
+    start: NaiveDate,
+    end: NaiveDate,
+    day_count_conv: DayCountConv,
+    compounding: Compounding,
+) -> Result<f64, RateConversionError> {
+    let t = day_count_conv.year_fraction(start, end, None, None)?;
+    rate_from_discount_factor(df, t, compounding)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ALL_COMPOUNDINGS: [Compounding; 6] = [
+        Compounding::Simple,
+        Compounding::Annual,
+        Compounding::SemiAnnual,
+        Compounding::Quarterly,
+        Compounding::Monthly,
+        Compounding::Continuous,
+    ];
+
+    #[test]
+    fn continuous_annual_round_trip() {
+        let tol = 1e-14;
+        for rate in &[0.05, 0.0, -0.005, 0.2] {
+            let annual = equivalent_rate(*rate, Compounding::Continuous, Compounding::Annual).unwrap();
+            assert_fuzzy_eq!(annual, rate.exp() - 1., tol);
+            let back = equivalent_rate(annual, Compounding::Annual, Compounding::Continuous).unwrap();
+            assert_fuzzy_eq!(back, *rate, tol);
+        }
+    }
+
+    #[test]
+    fn equivalent_rates_closed_form() {
+        let tol = 1e-14;
+        let rate = 0.06;
+        assert_fuzzy_eq!(
+            equivalent_rate(rate, Compounding::SemiAnnual, Compounding::Annual).unwrap(),
+            1.03_f64.powi(2) - 1.,
+            tol
+        );
+        assert_fuzzy_eq!(
+            equivalent_rate(rate, Compounding::Annual, Compounding::Quarterly).unwrap(),
+            4. * (1.06_f64.powf(0.25) - 1.),
+            tol
+        );
+        assert_fuzzy_eq!(
+            equivalent_rate(rate, Compounding::Monthly, Compounding::Continuous).unwrap(),
+            12. * (1.005_f64).ln(),
+            tol
+        );
+        assert_fuzzy_eq!(
+            equivalent_rate(rate, Compounding::Simple, Compounding::Annual).unwrap(),
+            rate,
+            tol
+        );
+        for from in &ALL_COMPOUNDINGS {
+            for to in &ALL_COMPOUNDINGS {
+                let converted = equivalent_rate(rate, *from, *to).unwrap();
+                let back = equivalent_rate(converted, *to, *from).unwrap();
+                assert_fuzzy_eq!(back, rate, tol);
+            }
+        }
+    }
+
+    #[test]
+    fn discount_factor_round_trip() {
+        let tol = 1e-14;
+        let t = 2.5;
+        for rate in &[0.03, 0.0, -0.01] {
+            for compounding in &ALL_COMPOUNDINGS {
+                let df = discount_factor_from_rate(*rate, t, *compounding).unwrap();
+                let zero_rate = rate_from_discount_factor(df, t, *compounding).unwrap();
+                assert_fuzzy_eq!(zero_rate, *rate, tol);
+            }
+        }
+        assert_fuzzy_eq!(
+            discount_factor_from_rate(0.03, t, Compounding::Continuous).unwrap(),
+            (-0.03 * t).exp(),
+            tol
+        );
+        assert_fuzzy_eq!(
+            discount_factor_from_rate(0.03, t, Compounding::Simple).unwrap(),
+            1. / (1. + 0.03 * t),
+            tol
+        );
+        assert_fuzzy_eq!(
+            rate_from_discount_factor(1., t, Compounding::Annual).unwrap(),
+            0.,
+            tol
+        );
+    }
+
+    #[test]
+    fn invalid_input() {
+        assert!(growth_factor(-1., 1., Compounding::Annual).is_err());
+        assert!(growth_factor(-2.5, 1., Compounding::SemiAnnual).is_err());
+        assert!(growth_factor(-2., 1., Compounding::Continuous).is_ok());
+        assert!(rate_from_discount_factor(0., 1., Compounding::Annual).is_err());
+        assert!(rate_from_discount_factor(-0.5, 1., Compounding::Continuous).is_err());
+        assert!(rate_from_discount_factor(0.9, 0., Compounding::Continuous).is_err());
+        assert_fuzzy_eq!(
+            discount_factor_from_rate(0.05, 0., Compounding::Annual).unwrap(),
+            1.,
+            1e-14
+        );
+    }
+
+    #[test]
+    fn with_day_count_convention() {
+        let tol = 1e-14;
+        let start = NaiveDate::from_ymd(2020, 1, 1);
+        let end = NaiveDate::from_ymd(2021, 1, 1);
+        let df = discount_factor_between(0.05, start, end, DayCountConv::Act365, Compounding::Annual)
+            .unwrap();
+        assert_fuzzy_eq!(df, 1.05_f64.powf(-366. / 365.), tol);
+        let rate =
+            zero_rate_between(df, start, end, DayCountConv::Act365, Compounding::Annual).unwrap();
+        assert_fuzzy_eq!(rate, 0.05, tol);
+    }
+}
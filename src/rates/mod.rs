@@ -6,6 +6,8 @@ use finql_data::cash_flow::{CashAmount, CashFlow};
 
 use crate::day_count_conv::DayCountConv;
 
+pub mod convert;
+
 /// Methods for compounding interest rates
 #[derive(Deserialize, Serialize, Clone, Copy, Debug)]
 pub enum Compounding {
@@ -0,0 +1,506 @@
+//! Ex-post evaluation of rebalancing decisions.
+//!
+//! When a rebalancing proposal is accepted, `record_rebalancing_decision` stores a snapshot of
+//! the proposal and links the transactions that implemented it. Later, `evaluate_rebalancing`
+//! compares the realized path, i.e. the positions before the trades changed by the linked
+//! transactions, with the counterfactual path of keeping the positions before the trades.
+//! Both paths are valued with the quotes available at each point in time only, i.e. a quote
+//! is never used before its time. Income of the positions, e.g. dividends, is part of neither
+//! path unless it is linked to the decision.
+
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt;
+
+use chrono::{DateTime, NaiveDate, TimeZone, Utc};
+use serde::Serialize;
+
+use finql_data::{
+    Currency, CurrencyConverter, CurrencyError, DataError, RebalancingDecision,
+    RebalancingProposal, ReportHandler, Transaction, TransactionType,
+};
+
+use crate::time_period::TimePeriod;
+
+/// Error related to rebalancing decisions
+#[derive(Debug)]
+pub enum RebalancingError {
+    CurrencyConversion(CurrencyError),
+    DBError(DataError),
+    /// A transaction to be linked to a decision is not stored in the database
+    UnknownTransaction(usize),
+    /// A transaction to be linked to a decision took place before the proposal has been made
+    TransactionBeforeDecision(usize),
+    /// An asset has no quote on or before the time it must be valued at
+    MissingQuote {
+        asset_id: usize,
+        time: DateTime<Utc>,
+    },
+    /// The evaluation horizon does not end after the decision
+    InvalidHorizon,
+    /// Returns can't be calculated since the portfolio had no positive value before the trades
+    NoInitialValue,
+}
+
+impl fmt::Display for RebalancingError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::CurrencyConversion(_) => write!(f, "conversion to portfolio currency failed"),
+            Self::DBError(_) => write!(f, "database error"),
+            Self::UnknownTransaction(id) => write!(f, "transaction {} does not exist", id),
+            Self::TransactionBeforeDecision(id) => write!(
+                f,
+                "transaction {} took place before the rebalancing proposal",
+                id
+            ),
+            Self::MissingQuote { asset_id, time } => {
+                write!(f, "no quote for asset {} on or before {}", asset_id, time)
+            }
+            Self::InvalidHorizon => write!(f, "horizon must end after the decision"),
+            Self::NoInitialValue => write!(f, "portfolio had no value before the trades"),
+        }
+    }
+}
+
+impl Error for RebalancingError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::CurrencyConversion(err) => Some(err),
+            Self::DBError(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<CurrencyError> for RebalancingError {
+    fn from(error: CurrencyError) -> Self {
+        Self::CurrencyConversion(error)
+    }
+}
+
+impl From<DataError> for RebalancingError {
+    fn from(error: DataError) -> Self {
+        Self::DBError(error)
+    }
+}
+
+/// Values of both paths at a point in time in the portfolio currency
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct RebalancingPoint {
+    pub time: DateTime<Utc>,
+    pub realized: f64,
+    /// Value without rebalancing, i.e. of the positions and cash before the trades
+    pub counterfactual: f64,
+}
+
+/// Comparison of a rebalancing decision with not rebalancing
+#[derive(Debug, Clone, Serialize)]
+pub struct RebalancingEvaluation {
+    pub decision_id: usize,
+    pub currency: Currency,
+    /// Value of the positions and cash before the trades at the time of the proposal
+    pub initial_value: f64,
+    /// Both paths at the time of the proposal and at the end of each day until the end of the
+    /// horizon
+    pub series: Vec<RebalancingPoint>,
+    pub realized_return: f64,
+    pub counterfactual_return: f64,
+    /// Realized minus counterfactual return
+    pub return_difference: f64,
+    /// Fees and taxes of the linked transactions in the portfolio currency
+    pub costs: f64,
+    /// Costs relative to the initial value, i.e. the part of the return difference lost to
+    /// costs
+    pub cost_drag: f64,
+}
+
+/// Record an accepted rebalancing proposal together with the transactions that implemented it,
+/// which must be stored already and must not take place before the proposal has been made.
+/// Returns the id of the decision.
+pub fn record_rebalancing_decision(
+    db: &mut dyn ReportHandler,
+    proposal: &RebalancingProposal,
+    accepted_trades: &[usize],
+) -> Result<usize, RebalancingError> {
+    let mut transaction_ids = accepted_trades.to_vec();
+    transaction_ids.sort_unstable();
+    transaction_ids.dedup();
+    for id in &transaction_ids {
+        let transaction = db
+            .get_transaction_by_id(*id)
+            .map_err(|_| RebalancingError::UnknownTransaction(*id))?;
+        if transaction.cash_flow.date < proposal.time.naive_utc().date() {
+            return Err(RebalancingError::TransactionBeforeDecision(*id));
+        }
+    }
+    Ok(db.insert_rebalancing_decision(&RebalancingDecision {
+        id: None,
+        proposal: proposal.clone(),
+        transaction_ids,
+    })?)
+}
+
+/// End of the given day, transactions of that day are considered to have taken place before
+fn end_of_day(date: NaiveDate) -> DateTime<Utc> {
+    Utc.from_utc_datetime(&date.and_hms(23, 59, 59))
+}
+
+/// Value of positions and cash with the last quotes on or before `time`
+fn portfolio_value(
+    db: &mut dyn ReportHandler,
+    positions: &BTreeMap<usize, f64>,
+    cash: f64,
+    currency: Currency,
+    time: DateTime<Utc>,
+    currency_converter: &mut dyn CurrencyConverter,
+) -> Result<f64, RebalancingError> {
+    let mut value = cash;
+    for (asset_id, position) in positions {
+        if *position == 0.0 {
+            continue;
+        }
+        let (quote, quote_currency) =
+            db.get_last_quote_before_by_id(*asset_id, time)
+                .map_err(|_| RebalancingError::MissingQuote {
+                    asset_id: *asset_id,
+                    time,
+                })?;
+        let fx_rate = if quote_currency == currency {
+            1.0
+        } else {
+            currency_converter.fx_rate(quote_currency, currency, time)?
+        };
+        value += position * quote.price * fx_rate;
+    }
+    Ok(value)
+}
+
+/// Amount of a transaction's cash flow in the portfolio currency
+fn cash_amount(
+    transaction: &Transaction,
+    currency: Currency,
+    currency_converter: &mut dyn CurrencyConverter,
+) -> Result<f64, RebalancingError> {
+    let amount = transaction.cash_flow.amount;
+    if amount.currency == currency {
+        Ok(amount.amount)
+    } else {
+        let time = end_of_day(transaction.cash_flow.date);
+        Ok(amount.amount * currency_converter.fx_rate(amount.currency, currency, time)?)
+    }
+}
+
+/// Compare the realized path of a rebalancing decision with the counterfactual path without
+/// rebalancing from the time of the proposal until the end of `horizon`. The horizon must not
+/// be given in business days. Transactions are considered at the end of their day. Returns
+/// an error if an asset held on any of both paths has no quote on or before a time it must be
+/// valued at, since later quotes are never used.
+pub fn evaluate_rebalancing(
+    db: &mut dyn ReportHandler,
+    decision_id: usize,
+    horizon: TimePeriod,
+    currency_converter: &mut dyn CurrencyConverter,
+) -> Result<RebalancingEvaluation, RebalancingError> {
+    let decision = db.get_rebalancing_decision(decision_id)?;
+    let proposal = decision.proposal;
+    let start = proposal.time.naive_utc().date();
+    let end = horizon.add_to(start, None);
+    if end <= start {
+        return Err(RebalancingError::InvalidHorizon);
+    }
+    let mut transactions = Vec::new();
+    for id in &decision.transaction_ids {
+        transactions.push(db.get_transaction_by_id(*id)?);
+    }
+    transactions.sort_by_key(|t| t.cash_flow.date);
+
+    let initial_positions: BTreeMap<usize, f64> = proposal.positions.iter().cloned().collect();
+    let mut positions = initial_positions.clone();
+    let mut cash = proposal.cash;
+    let mut costs = 0.0;
+    let mut applied = 0;
+    // value at the time of the proposal and at the end of each following day
+    let mut times = vec![proposal.time];
+    let mut date = start;
+    while date <= end {
+        if end_of_day(date) > proposal.time {
+            times.push(end_of_day(date));
+        }
+        date = date.succ();
+    }
+    let mut series = Vec::new();
+    for time in times {
+        while applied < transactions.len()
+            && end_of_day(transactions[applied].cash_flow.date) <= time
+        {
+            let transaction = &transactions[applied];
+            let amount = cash_amount(transaction, proposal.currency, currency_converter)?;
+            cash += amount;
+            match transaction.transaction_type {
+                TransactionType::Asset { asset_id, position }
+                | TransactionType::Transfer {
+                    asset_id, position, ..
+                } => *positions.entry(asset_id).or_insert(0.0) += position,
+                TransactionType::Fee { .. } | TransactionType::Tax { .. } => costs -= amount,
+                _ => {}
+            }
+            applied += 1;
+        }
+        series.push(RebalancingPoint {
+            time,
+            realized: portfolio_value(
+                db,
+                &positions,
+                cash,
+                proposal.currency,
+                time,
+                currency_converter,
+            )?,
+            counterfactual: portfolio_value(
+                db,
+                &initial_positions,
+                proposal.cash,
+                proposal.currency,
+                time,
+                currency_converter,
+            )?,
+        });
+    }
+
+    let initial_value = series[0].counterfactual;
+    if initial_value <= 0.0 {
+        return Err(RebalancingError::NoInitialValue);
+    }
+    let last = series[series.len() - 1];
+    let realized_return = last.realized / initial_value - 1.0;
+    let counterfactual_return = last.counterfactual / initial_value - 1.0;
+    Ok(RebalancingEvaluation {
+        decision_id,
+        currency: proposal.currency,
+        initial_value,
+        series,
+        realized_return,
+        counterfactual_return,
+        return_difference: realized_return - counterfactual_return,
+        costs,
+        cost_drag: costs / initial_value,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    use finql_data::{
+        Asset, AssetHandler, CashFlow, Quote, QuoteHandler, RebalancingTrade, Ticker, TickerUsage,
+        TransactionHandler,
+    };
+    use finql_sqlite::SqliteDB;
+    use rusqlite::Connection;
+
+    use crate::fx_rates::SimpleCurrencyConverter;
+
+    fn time(day: u32, hour: u32) -> DateTime<Utc> {
+        Utc.ymd(2021, 3, day).and_hms(hour, 0, 0)
+    }
+
+    /// Insert an asset with the given quotes as pairs of day in March 2021 and price
+    fn insert_asset_with_quotes(db: &mut SqliteDB, name: &str, quotes: &[(u32, f64)]) -> usize {
+        let asset_id = db
+            .insert_asset(&Asset::new(None, name, None, None, None))
+            .unwrap();
+        let ticker = db
+            .insert_ticker(&Ticker {
+                id: None,
+                name: name.to_string(),
+                asset: asset_id,
+                source: "manual".to_string(),
+                priority: 1,
+                currency: Currency::from_str("EUR").unwrap(),
+                factor: 1.0,
+                source_url: None,
+                usage: TickerUsage::Both,
+                last_quote_time: None,
+                source_chain: Vec::new(),
+            })
+            .unwrap();
+        for (day, price) in quotes {
+            db.insert_quote(&Quote {
+                id: None,
+                ticker,
+                price: *price,
+                time: time(*day, 16),
+                volume: None,
+                quality_score: None,
+                source: None,
+                open: None,
+                high: None,
+                low: None,
+                bid: None,
+                ask: None,
+            })
+            .unwrap();
+        }
+        asset_id
+    }
+
+    fn insert_transaction(
+        db: &mut SqliteDB,
+        transaction_type: TransactionType,
+        amount: f64,
+        day: u32,
+    ) -> usize {
+        db.insert_transaction(&Transaction {
+            id: None,
+            transaction_type,
+            cash_flow: CashFlow::new(
+                amount,
+                Currency::from_str("EUR").unwrap(),
+                NaiveDate::from_ymd(2021, 3, day),
+            ),
+            note: None,
+            execution_meta: None,
+            recorded_at: None,
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn evaluate_against_no_rebalancing() {
+        let conn = Connection::open(":memory:").unwrap();
+        let mut db = SqliteDB::new(&conn);
+        db.init().unwrap();
+        // quote of day 5 is after the horizon and must not be used
+        let falling = insert_asset_with_quotes(
+            &mut db,
+            "Falling AG",
+            &[(1, 100.0), (2, 100.0), (3, 90.0), (4, 80.0), (5, 1000.0)],
+        );
+        let rising = insert_asset_with_quotes(
+            &mut db,
+            "Rising AG",
+            &[(1, 100.0), (2, 100.0), (3, 110.0), (4, 120.0)],
+        );
+        let proposal = RebalancingProposal {
+            time: time(1, 17),
+            currency: Currency::from_str("EUR").unwrap(),
+            positions: vec![(falling, 10.0)],
+            cash: 0.0,
+            trades: vec![
+                RebalancingTrade {
+                    asset_id: falling,
+                    position: -5.0,
+                },
+                RebalancingTrade {
+                    asset_id: rising,
+                    position: 5.0,
+                },
+            ],
+        };
+        let sell = insert_transaction(
+            &mut db,
+            TransactionType::Asset {
+                asset_id: falling,
+                position: -5.0,
+            },
+            500.0,
+            2,
+        );
+        let fee = insert_transaction(
+            &mut db,
+            TransactionType::Fee {
+                transaction_ref: Some(sell),
+            },
+            -5.0,
+            2,
+        );
+        let buy = insert_transaction(
+            &mut db,
+            TransactionType::Asset {
+                asset_id: rising,
+                position: 5.0,
+            },
+            -500.0,
+            2,
+        );
+        let decision_id =
+            record_rebalancing_decision(&mut db, &proposal, &[buy, sell, fee, sell]).unwrap();
+        assert_eq!(
+            db.get_rebalancing_decision(decision_id)
+                .unwrap()
+                .transaction_ids,
+            vec![sell, fee, buy]
+        );
+
+        let mut converter = SimpleCurrencyConverter::new();
+        let horizon = TimePeriod::from_str("3D").unwrap();
+        let evaluation =
+            evaluate_rebalancing(&mut db, decision_id, horizon, &mut converter).unwrap();
+        let values: Vec<(f64, f64)> = evaluation
+            .series
+            .iter()
+            .map(|point| (point.realized, point.counterfactual))
+            .collect();
+        assert_eq!(
+            values,
+            vec![
+                (1000.0, 1000.0),
+                (1000.0, 1000.0),
+                (995.0, 1000.0),
+                (995.0, 900.0),
+                (995.0, 800.0)
+            ]
+        );
+        assert_eq!(evaluation.series[0].time, time(1, 17));
+        assert_eq!(
+            evaluation.series[4].time,
+            end_of_day(NaiveDate::from_ymd(2021, 3, 4))
+        );
+        assert_eq!(evaluation.initial_value, 1000.0);
+        assert_fuzzy_eq!(evaluation.realized_return, -0.005, 1e-12);
+        assert_fuzzy_eq!(evaluation.counterfactual_return, -0.2, 1e-12);
+        assert_fuzzy_eq!(evaluation.return_difference, 0.195, 1e-12);
+        assert_fuzzy_eq!(evaluation.costs, 5.0, 1e-12);
+        assert_fuzzy_eq!(evaluation.cost_drag, 0.005, 1e-12);
+    }
+
+    #[test]
+    fn no_lookahead_and_invalid_links() {
+        let conn = Connection::open(":memory:").unwrap();
+        let mut db = SqliteDB::new(&conn);
+        db.init().unwrap();
+        let listed_later = insert_asset_with_quotes(&mut db, "New AG", &[(3, 50.0)]);
+        let proposal = RebalancingProposal {
+            time: time(2, 17),
+            currency: Currency::from_str("EUR").unwrap(),
+            positions: vec![(listed_later, 10.0)],
+            cash: 100.0,
+            trades: Vec::new(),
+        };
+        let early = insert_transaction(&mut db, TransactionType::Cash, 100.0, 1);
+        assert!(matches!(
+            record_rebalancing_decision(&mut db, &proposal, &[early]),
+            Err(RebalancingError::TransactionBeforeDecision(id)) if id == early
+        ));
+        assert!(matches!(
+            record_rebalancing_decision(&mut db, &proposal, &[early + 1]),
+            Err(RebalancingError::UnknownTransaction(_))
+        ));
+
+        // the asset can't be valued at the time of the proposal with later quotes
+        let decision_id = record_rebalancing_decision(&mut db, &proposal, &[]).unwrap();
+        let mut converter = SimpleCurrencyConverter::new();
+        let horizon = TimePeriod::from_str("1W").unwrap();
+        assert!(matches!(
+            evaluate_rebalancing(&mut db, decision_id, horizon, &mut converter),
+            Err(RebalancingError::MissingQuote { asset_id, time: t })
+                if asset_id == listed_later && t == time(2, 17)
+        ));
+        let horizon = TimePeriod::from_str("-1M").unwrap();
+        assert!(matches!(
+            evaluate_rebalancing(&mut db, decision_id, horizon, &mut converter),
+            Err(RebalancingError::InvalidHorizon)
+        ));
+    }
+}
@@ -0,0 +1,757 @@
+//! Iterator adapters transforming a series of prices into a series of returns.
+//!
+//! The adapters are lazy and can be chained with any other iterator adapter,
+//! e.g. to calculate the returns of a price history fetched from the database:
+//!
+//! ```
+//! use finql::returns::{log_returns, simple_returns};
+//!
+//! let prices = vec![100., 110., 99.];
+//! let returns: Vec<f64> = simple_returns(prices.iter().copied()).collect();
+//! assert_eq!(returns.len(), 2);
+//! assert!((returns[0] - 0.1).abs() < 1e-12);
+//! assert!((returns[1] + 0.1).abs() < 1e-12);
+//!
+//! let total: f64 = log_returns(prices).sum();
+//! assert!((total - (99_f64 / 100.).ln()).abs() < 1e-12);
+//! ```
+
+use std::fmt;
+
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::Serialize;
+
+use finql_data::{CurrencyConverter, DataError, QuoteHandler, Transaction, TransactionType};
+
+use crate::analytics_error::AnalyticsError;
+
+/// Iterator over simple returns `p[i]/p[i-1] - 1` of a price series
+#[derive(Debug, Clone)]
+pub struct SimpleReturns<I: Iterator<Item = f64>> {
+    prices: I,
+    last_price: Option<f64>,
+}
+
+/// Iterator over log returns `ln(p[i]/p[i-1])` of a price series
+#[derive(Debug, Clone)]
+pub struct LogReturns<I: Iterator<Item = f64>> {
+    prices: I,
+    last_price: Option<f64>,
+}
+
+/// Get the next pair of consecutive prices
+fn next_prices<I: Iterator<Item = f64>>(
+    prices: &mut I,
+    last_price: &mut Option<f64>,
+) -> Option<(f64, f64)> {
+    let previous = match last_price.take() {
+        Some(price) => price,
+        None => prices.next()?,
+    };
+    let current = prices.next()?;
+    *last_price = Some(current);
+    Some((previous, current))
+}
+
+/// Number of returns is one less than the number of (remaining) prices
+fn returns_size_hint<I: Iterator<Item = f64>>(
+    prices: &I,
+    last_price: Option<f64>,
+) -> (usize, Option<usize>) {
+    let (lower, upper) = prices.size_hint();
+    match last_price {
+        Some(_) => (lower, upper),
+        None => (lower.saturating_sub(1), upper.map(|n| n.saturating_sub(1))),
+    }
+}
+
+impl<I: Iterator<Item = f64>> Iterator for SimpleReturns<I> {
+    type Item = f64;
+
+    fn next(&mut self) -> Option<f64> {
+        let (previous, current) = next_prices(&mut self.prices, &mut self.last_price)?;
+        Some(current / previous - 1.)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        returns_size_hint(&self.prices, self.last_price)
+    }
+}
+
+impl<I: Iterator<Item = f64>> Iterator for LogReturns<I> {
+    type Item = f64;
+
+    fn next(&mut self) -> Option<f64> {
+        let (previous, current) = next_prices(&mut self.prices, &mut self.last_price)?;
+        Some((current / previous).ln())
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        returns_size_hint(&self.prices, self.last_price)
+    }
+}
+
+/// Transform a series of prices into the series of simple returns
+///
+/// ```
+/// use finql::returns::simple_returns;
+///
+/// let returns: Vec<f64> = simple_returns(vec![10., 12., 9.]).collect();
+/// assert!((returns[0] - 0.2).abs() < 1e-12);
+/// assert!((returns[1] + 0.25).abs() < 1e-12);
+/// ```
+pub fn simple_returns<I: IntoIterator<Item = f64>>(prices: I) -> SimpleReturns<I::IntoIter> {
+    SimpleReturns {
+        prices: prices.into_iter(),
+        last_price: None,
+    }
+}
+
+/// Transform a series of prices into the series of log returns
+///
+/// ```
+/// use finql::returns::log_returns;
+///
+/// let returns: Vec<f64> = log_returns(vec![10., 20.]).collect();
+/// assert!((returns[0] - 2_f64.ln()).abs() < 1e-12);
+/// ```
+pub fn log_returns<I: IntoIterator<Item = f64>>(prices: I) -> LogReturns<I::IntoIter> {
+    LogReturns {
+        prices: prices.into_iter(),
+        last_price: None,
+    }
+}
+
+/// Compound annual growth rate, i.e. the constant annual return `(end / start)^(1 / years) - 1`
+/// that turns `start_value` into `end_value` within the given number of years
+///
+/// ```
+/// use finql::returns::cagr;
+///
+/// // doubling within 2 years corresponds to about 41.4% per year
+/// let rate = cagr(100., 200., 2.).unwrap();
+/// assert!((rate - 0.414214).abs() < 1e-6);
+/// ```
+pub fn cagr(start_value: f64, end_value: f64, years: f64) -> Result<f64, AnalyticsError> {
+    if !(years > 0.0 && years.is_finite()) {
+        return Err(AnalyticsError::InvalidInput(
+            "years must be positive".to_string(),
+        ));
+    }
+    if !(start_value > 0.0 && start_value.is_finite()) {
+        return Err(AnalyticsError::InvalidInput(
+            "start value must be positive".to_string(),
+        ));
+    }
+    if !(end_value >= 0.0 && end_value.is_finite()) {
+        return Err(AnalyticsError::InvalidInput(
+            "end value must not be negative".to_string(),
+        ));
+    }
+    Ok((end_value / start_value).powf(1.0 / years) - 1.0)
+}
+
+/// Total return of consecutive periods, i.e. the chain-linked returns of the single periods
+///
+/// ```
+/// use finql::returns::total_return;
+///
+/// assert!((total_return(&[0.1, -0.1]) + 0.01).abs() < 1e-12);
+/// ```
+pub fn total_return(returns: &[f64]) -> f64 {
+    returns.iter().fold(1.0, |value, r| value * (1.0 + r)) - 1.0
+}
+
+/// Returns of foreign assets hedged against exchange rate risk, given their returns in the
+/// local currency of the assets and the exchange rate returns over the same periods, i.e. the
+/// returns of the price of one unit of the local currency in the hedge currency.
+///
+/// Unhedged, the return in the hedge currency is `(1 + r_local) * (1 + r_fx) - 1`. The hedge
+/// is assumed to sell the value at the start of each period forward at the current spot rate,
+/// which removes the exchange rate return on that value, i.e. the hedged return is
+/// approximated by `r_unhedged - r_fx = r_local + r_local * r_fx`. The cross term remains
+/// since gains or losses within the period are not hedged. The interest rate differential
+/// between both currencies, which is earned or paid by forwards in practice, is neglected.
+///
+/// ```
+/// use finql::returns::hedged_returns;
+///
+/// // local return of 2% while the local currency gains 5%
+/// let hedged = hedged_returns(&[0.02], &[0.05]).unwrap();
+/// assert!((hedged[0] - 0.021).abs() < 1e-12);
+/// ```
+pub fn hedged_returns(local_returns: &[f64], fx_returns: &[f64]) -> Result<Vec<f64>, AnalyticsError> {
+    if local_returns.len() != fx_returns.len() {
+        return Err(AnalyticsError::InvalidInput(format!(
+            "{} local returns, but {} exchange rate returns given",
+            local_returns.len(),
+            fx_returns.len()
+        )));
+    }
+    if let Some(value) = local_returns
+        .iter()
+        .chain(fx_returns)
+        .find(|r| !r.is_finite())
+    {
+        return Err(AnalyticsError::InvalidReturn(*value));
+    }
+    Ok(local_returns
+        .iter()
+        .zip(fx_returns)
+        .map(|(local, fx)| {
+            let unhedged = (1. + local) * (1. + fx) - 1.;
+            unhedged - fx
+        })
+        .collect())
+}
+
+/// Basis of performance figures, i.e. whether income like dividends or interest is included
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum ReturnBasis {
+    /// Change of market value only, income is ignored
+    Price,
+    /// Income is reinvested at the first valuation point on or after its payment
+    TotalReturn,
+}
+
+/// Return of a holding between two consecutive valuation points
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SubPeriodReturn {
+    pub start: NaiveDate,
+    pub end: NaiveDate,
+    /// Market value at `start`
+    pub begin_value: f64,
+    /// Sum of external flows after `start` up to (including) `end`, contributions are positive
+    pub flow: f64,
+    /// Income reinvested at `end`, always zero on the basis of `ReturnBasis::Price`
+    pub income: f64,
+    /// Market value at `end`, including the flows of the sub-period
+    pub end_value: f64,
+    /// `(end_value - flow + income) / begin_value - 1`
+    pub period_return: f64,
+}
+
+/// Time-weighted return together with the chain-linked sub-periods it has been calculated from
+#[derive(Debug, Clone, Serialize)]
+pub struct PerformanceReport {
+    pub basis: ReturnBasis,
+    /// Time-weighted return over all sub-periods
+    pub time_weighted_return: f64,
+    pub sub_periods: Vec<SubPeriodReturn>,
+}
+
+/// Maximum deviation of figures recomputed by `PerformanceReport::verify`
+const VERIFY_TOLERANCE: f64 = 1e-10;
+
+/// Check whether two figures agree within `VERIFY_TOLERANCE`, NaN never agrees
+fn agrees(a: f64, b: f64) -> bool {
+    (a - b).abs() <= VERIFY_TOLERANCE
+}
+
+impl PerformanceReport {
+    /// Recompute the return of each sub-period from its values and the time-weighted return
+    /// by chain-linking the sub-periods, and check that both agree with the reported figures
+    pub fn verify(&self) -> Result<(), AnalyticsError> {
+        let mut performance = 1.0;
+        for period in &self.sub_periods {
+            let period_return =
+                (period.end_value - period.flow + period.income) / period.begin_value - 1.0;
+            if !agrees(period_return, period.period_return) {
+                return Err(AnalyticsError::Inconsistent(format!(
+                    "return of sub-period from {} to {} is {}, but {} is reported",
+                    period.start, period.end, period_return, period.period_return
+                )));
+            }
+            performance *= 1.0 + period.period_return;
+        }
+        let chained = performance - 1.0;
+        if !agrees(chained, self.time_weighted_return) {
+            return Err(AnalyticsError::Inconsistent(format!(
+                "chain-linked return is {}, but {} is reported",
+                chained, self.time_weighted_return
+            )));
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for PerformanceReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{:<10} {:<10} {:>14} {:>14} {:>14} {:>14} {:>9}",
+            "Start", "End", "Begin value", "Flow", "Income", "End value", "Return"
+        )?;
+        for p in &self.sub_periods {
+            writeln!(
+                f,
+                "{:<10} {:<10} {:>14.2} {:>14.2} {:>14.2} {:>14.2} {:>8.2}%",
+                p.start,
+                p.end,
+                p.begin_value,
+                p.flow,
+                p.income,
+                p.end_value,
+                100.0 * p.period_return
+            )?;
+        }
+        writeln!(
+            f,
+            "Time-weighted return: {:.2}%",
+            100.0 * self.time_weighted_return
+        )
+    }
+}
+
+/// Sum of the amounts of all transactions matching `filter` paid after `start` up to
+/// (including) `end`
+fn sum_between<F>(transactions: &[Transaction], start: NaiveDate, end: NaiveDate, filter: F) -> f64
+where
+    F: Fn(&TransactionType) -> bool,
+{
+    transactions
+        .iter()
+        .filter(|t| {
+            filter(&t.transaction_type) && t.cash_flow.date > start && t.cash_flow.date <= end
+        })
+        .fold(0.0, |sum, t| sum + t.cash_flow.amount.amount)
+}
+
+/// Chain-linked sub-periods between consecutive valuation points, optionally adjusted for
+/// external flows given by cash transactions
+fn sub_period_returns(
+    valuations: &[(NaiveDate, f64)],
+    transactions: &[Transaction],
+    basis: ReturnBasis,
+    with_flows: bool,
+) -> Result<Vec<SubPeriodReturn>, AnalyticsError> {
+    if valuations.len() < 2 {
+        return Err(AnalyticsError::NoData);
+    }
+    if let Some((date, value)) = valuations
+        .iter()
+        .find(|(_, value)| !(*value > 0.0 && value.is_finite()))
+    {
+        return Err(AnalyticsError::InvalidInput(format!(
+            "market value {} at {} is not positive",
+            value, date
+        )));
+    }
+    let mut sub_periods = Vec::new();
+    for period in valuations.windows(2) {
+        let (start, begin_value) = period[0];
+        let (end, end_value) = period[1];
+        if end <= start {
+            return Err(AnalyticsError::InvalidInput(
+                "valuations must be in ascending order of dates".to_string(),
+            ));
+        }
+        let income = match basis {
+            ReturnBasis::Price => 0.0,
+            ReturnBasis::TotalReturn => sum_between(transactions, start, end, |t| {
+                matches!(
+                    t,
+                    TransactionType::Dividend { .. } | TransactionType::Interest { .. }
+                )
+            }),
+        };
+        let flow = if with_flows {
+            sum_between(transactions, start, end, |t| {
+                matches!(t, TransactionType::Cash)
+            })
+        } else {
+            0.0
+        };
+        sub_periods.push(SubPeriodReturn {
+            start,
+            end,
+            begin_value,
+            flow,
+            income,
+            end_value,
+            period_return: (end_value - flow + income) / begin_value - 1.0,
+        });
+    }
+    Ok(sub_periods)
+}
+
+/// Chain-link the returns of the sub-periods
+fn chain_link(sub_periods: &[SubPeriodReturn]) -> f64 {
+    sub_periods
+        .iter()
+        .fold(1.0, |performance, p| performance * (1.0 + p.period_return))
+        - 1.0
+}
+
+/// Time-weighted return of a holding between the first and the last valuation point.
+/// Valuations are market values of the holding in ascending order of their dates, the
+/// sub-period returns between consecutive valuation points are chain-linked.
+/// Depending on the return basis, dividend and interest transactions paid after a valuation
+/// point and up to (including) the next one are added to the market value of the latter,
+/// i.e. they are reinvested there. All other transactions are ignored and income must be
+/// given in the currency of the valuations.
+pub fn time_weighted_return(
+    valuations: &[(NaiveDate, f64)],
+    transactions: &[Transaction],
+    basis: ReturnBasis,
+) -> Result<f64, AnalyticsError> {
+    let sub_periods = sub_period_returns(valuations, transactions, basis, false)?;
+    Ok(chain_link(&sub_periods))
+}
+
+/// Time-weighted return of a portfolio with external flows, reported per sub-period.
+/// In addition to `time_weighted_return`, cash transactions are treated as contributions
+/// (positive amounts) or withdrawals (negative amounts) and are removed from the market value
+/// at the end of the sub-period they fall into. Therefore, a valuation should be given at each
+/// date of an external flow, such that sub-periods are delimited by flows.
+pub fn performance_report(
+    valuations: &[(NaiveDate, f64)],
+    transactions: &[Transaction],
+    basis: ReturnBasis,
+) -> Result<PerformanceReport, AnalyticsError> {
+    let sub_periods = sub_period_returns(valuations, transactions, basis, true)?;
+    Ok(PerformanceReport {
+        basis,
+        time_weighted_return: chain_link(&sub_periods),
+        sub_periods,
+    })
+}
+
+/// Compound annual growth rate of the price of an asset between two dates, i.e. on the basis of
+/// `ReturnBasis::Price`, since dividends are not taken into account. Prices are the last
+/// quotes on or before the end of the respective day, converted into the asset's reference
+/// currency if it has one. Years are counted as actual days / 365.25. Fails if the prices are
+/// given in different currencies, i.e. if the asset has ticker in several currencies, but no
+/// reference currency.
+pub fn asset_cagr(
+    db: &mut dyn QuoteHandler,
+    asset_id: usize,
+    start: NaiveDate,
+    end: NaiveDate,
+    currency_converter: &mut dyn CurrencyConverter,
+) -> Result<f64, DataError> {
+    let mut price_at = |db: &mut dyn QuoteHandler, date: NaiveDate| {
+        let time = DateTime::<Utc>::from_utc(date.and_hms(23, 59, 59), Utc);
+        db.get_best_quote_before(asset_id, time, currency_converter)
+    };
+    let (start_quote, start_currency) = price_at(db, start)?;
+    let (end_quote, end_currency) = price_at(db, end)?;
+    if start_currency != end_currency {
+        return Err(DataError::CurrencyMismatch(format!(
+            "prices are given in {} and {}, a reference currency is required",
+            start_currency, end_currency
+        )));
+    }
+    let years = end.signed_duration_since(start).num_days() as f64 / 365.25;
+    cagr(start_quote.price, end_quote.price, years)
+        .map_err(|e| DataError::InvalidData(e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rusqlite::Connection;
+
+    use std::str::FromStr;
+
+    use chrono::TimeZone;
+
+    use finql_data::{Asset, AssetHandler, CashFlow, Currency, Quote, Ticker, TickerUsage};
+    use finql_sqlite::SqliteDB;
+
+    use crate::fx_rates::SimpleCurrencyConverter;
+
+    const PRICES: [f64; 6] = [67.35, 68.29, 67.27, 66.27, 66.30, 65.73];
+
+    #[test]
+    fn simple_returns_roundtrip() {
+        let tol = 1e-12;
+        let returns: Vec<f64> = simple_returns(PRICES.iter().copied()).collect();
+        assert_eq!(returns.len(), PRICES.len() - 1);
+        let mut price = PRICES[0];
+        for (r, p) in returns.iter().zip(PRICES.iter().skip(1)) {
+            price *= 1. + r;
+            assert_fuzzy_eq!(price, *p, tol);
+        }
+    }
+
+    #[test]
+    fn log_returns_roundtrip() {
+        let tol = 1e-12;
+        let returns: Vec<f64> = log_returns(PRICES.iter().copied()).collect();
+        assert_eq!(returns.len(), PRICES.len() - 1);
+        let mut price = PRICES[0];
+        for (r, p) in returns.iter().zip(PRICES.iter().skip(1)) {
+            price *= r.exp();
+            assert_fuzzy_eq!(price, *p, tol);
+        }
+        let total: f64 = returns.iter().sum();
+        assert_fuzzy_eq!(total, (PRICES[5] / PRICES[0]).ln(), tol);
+    }
+
+    #[test]
+    fn compound_annual_growth_rate() {
+        let tol = 1e-10;
+        assert_fuzzy_eq!(cagr(100., 200., 2.).unwrap(), 2_f64.sqrt() - 1., tol);
+        assert_fuzzy_eq!(cagr(100., 110., 1.).unwrap(), 0.1, tol);
+        assert_fuzzy_eq!(cagr(100., 50., 0.5).unwrap(), -0.75, tol);
+        assert_fuzzy_eq!(cagr(100., 0., 3.).unwrap(), -1., tol);
+        assert_eq!(
+            cagr(100., 200., 0.),
+            Err(AnalyticsError::InvalidInput(
+                "years must be positive".to_string()
+            ))
+        );
+        assert!(cagr(0., 200., 1.).is_err());
+        assert!(cagr(100., f64::NAN, 1.).is_err());
+    }
+
+    fn income(transaction_type: TransactionType, amount: f64, date: NaiveDate) -> Transaction {
+        Transaction {
+            id: None,
+            transaction_type,
+            cash_flow: CashFlow::new(amount, Currency::from_str("EUR").unwrap(), date),
+            note: None,
+            execution_meta: None,
+            recorded_at: None,
+        }
+    }
+
+    #[test]
+    fn price_vs_total_return() {
+        let tol = 1e-12;
+        let valuations = vec![
+            (NaiveDate::from_ymd(2020, 1, 1), 1000.),
+            (NaiveDate::from_ymd(2020, 7, 1), 1000.),
+            (NaiveDate::from_ymd(2021, 1, 1), 1000.),
+        ];
+        let transactions = vec![
+            income(
+                TransactionType::Dividend { asset_id: 1 },
+                30.,
+                NaiveDate::from_ymd(2020, 5, 15),
+            ),
+            // paid before the first valuation point, not part of the period
+            income(
+                TransactionType::Dividend { asset_id: 1 },
+                25.,
+                NaiveDate::from_ymd(2020, 1, 1),
+            ),
+            // no income
+            income(
+                TransactionType::Fee {
+                    transaction_ref: None,
+                },
+                -10.,
+                NaiveDate::from_ymd(2020, 5, 15),
+            ),
+        ];
+        let price = time_weighted_return(&valuations, &transactions, ReturnBasis::Price).unwrap();
+        let total =
+            time_weighted_return(&valuations, &transactions, ReturnBasis::TotalReturn).unwrap();
+        assert_fuzzy_eq!(price, 0., tol);
+        assert_fuzzy_eq!(total, 0.03, tol);
+
+        // income reinvested at the next valuation point takes part in later price changes
+        let valuations = vec![
+            (NaiveDate::from_ymd(2020, 1, 1), 100.),
+            (NaiveDate::from_ymd(2020, 7, 1), 110.),
+            (NaiveDate::from_ymd(2021, 1, 1), 121.),
+        ];
+        let transactions = vec![income(
+            TransactionType::Interest { asset_id: 1 },
+            11.,
+            NaiveDate::from_ymd(2020, 7, 1),
+        )];
+        let price = time_weighted_return(&valuations, &transactions, ReturnBasis::Price).unwrap();
+        let total =
+            time_weighted_return(&valuations, &transactions, ReturnBasis::TotalReturn).unwrap();
+        assert_fuzzy_eq!(price, 0.21, tol);
+        assert_fuzzy_eq!(total, 1.21 * 1.1 - 1., tol);
+    }
+
+    #[test]
+    fn performance_with_external_flows() {
+        let tol = 1e-12;
+        let valuations = vec![
+            (NaiveDate::from_ymd(2020, 1, 1), 100.),
+            (NaiveDate::from_ymd(2020, 4, 1), 160.),
+            (NaiveDate::from_ymd(2020, 7, 1), 176.),
+            (NaiveDate::from_ymd(2020, 10, 1), 100.),
+        ];
+        let transactions = vec![
+            income(TransactionType::Cash, 50., NaiveDate::from_ymd(2020, 4, 1)),
+            income(
+                TransactionType::Dividend { asset_id: 1 },
+                4.,
+                NaiveDate::from_ymd(2020, 9, 15),
+            ),
+            income(
+                TransactionType::Cash,
+                -90.,
+                NaiveDate::from_ymd(2020, 10, 1),
+            ),
+        ];
+        let report =
+            performance_report(&valuations, &transactions, ReturnBasis::TotalReturn).unwrap();
+        assert_eq!(report.sub_periods.len(), 3);
+        let first = &report.sub_periods[0];
+        assert_eq!(first.start, NaiveDate::from_ymd(2020, 1, 1));
+        assert_eq!(first.end, NaiveDate::from_ymd(2020, 4, 1));
+        assert_fuzzy_eq!(first.begin_value, 100., tol);
+        assert_fuzzy_eq!(first.flow, 50., tol);
+        assert_fuzzy_eq!(first.end_value, 160., tol);
+        assert_fuzzy_eq!(first.period_return, 0.1, tol);
+        assert_fuzzy_eq!(report.sub_periods[1].period_return, 0.1, tol);
+        let last = &report.sub_periods[2];
+        assert_fuzzy_eq!(last.flow, -90., tol);
+        assert_fuzzy_eq!(last.income, 4., tol);
+        assert_fuzzy_eq!(last.period_return, 194. / 176. - 1., tol);
+        assert_fuzzy_eq!(report.time_weighted_return, 1.21 * 194. / 176. - 1., tol);
+        assert!(report.verify().is_ok());
+
+        // flows are ignored by the plain time-weighted return
+        let twr =
+            time_weighted_return(&valuations, &transactions, ReturnBasis::TotalReturn).unwrap();
+        assert_fuzzy_eq!(twr, 1.6 * 1.1 * 104. / 176. - 1., tol);
+
+        let rendered = report.to_string();
+        assert_eq!(rendered.lines().count(), 5);
+        assert!(rendered.contains("2020-04-01 2020-07-01         160.00           0.00"));
+        assert!(rendered.contains("Time-weighted return: 33.3"));
+
+        let mut tampered = report.clone();
+        tampered.sub_periods[1].end_value = 180.;
+        assert!(matches!(
+            tampered.verify(),
+            Err(AnalyticsError::Inconsistent(_))
+        ));
+        let mut tampered = report;
+        tampered.time_weighted_return = 0.3;
+        assert!(tampered.verify().is_err());
+    }
+
+    #[test]
+    fn invalid_time_weighted_return_input() {
+        let date = NaiveDate::from_ymd(2020, 1, 1);
+        assert_eq!(
+            time_weighted_return(&[(date, 100.)], &[], ReturnBasis::Price),
+            Err(AnalyticsError::NoData)
+        );
+        assert!(
+            time_weighted_return(&[(date, 100.), (date, 110.)], &[], ReturnBasis::Price).is_err()
+        );
+        assert!(time_weighted_return(
+            &[(date, 0.), (date.succ(), 110.)],
+            &[],
+            ReturnBasis::TotalReturn
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn asset_cagr_without_quotes() {
+        let conn = Connection::open(":memory:").unwrap();
+        let mut db = SqliteDB::new(&conn);
+        db.init().unwrap();
+        let asset_id = db
+            .insert_asset(&Asset::new(None, "BASF AG", None, None, None))
+            .unwrap();
+        let start = NaiveDate::from_ymd(2018, 1, 2);
+        let end = NaiveDate::from_ymd(2020, 1, 2);
+        let mut converter = SimpleCurrencyConverter::new();
+        assert!(asset_cagr(&mut db, asset_id, start, end, &mut converter)
+            .unwrap_err()
+            .is_not_found());
+    }
+
+    #[test]
+    fn asset_cagr_from_stored_quotes() {
+        let conn = Connection::open(":memory:").unwrap();
+        let mut db = SqliteDB::new(&conn);
+        db.init().unwrap();
+        let asset_id = db
+            .insert_asset(&Asset::new(None, "BASF AG", None, None, None))
+            .unwrap();
+        let ticker = db
+            .insert_ticker(&Ticker {
+                id: None,
+                asset: asset_id,
+                name: "BAS.DE".to_string(),
+                currency: Currency::from_str("EUR").unwrap(),
+                source: "manual".to_string(),
+                priority: 1,
+                factor: 1.0,
+                source_url: None,
+                usage: TickerUsage::Both,
+                last_quote_time: None,
+                source_chain: Vec::new(),
+            })
+            .unwrap();
+        for (time, price) in &[
+            (Utc.ymd(2017, 12, 29).and_hms(17, 30, 0), 90.),
+            (Utc.ymd(2018, 1, 2).and_hms(17, 30, 0), 100.),
+            (Utc.ymd(2020, 1, 2).and_hms(17, 30, 0), 121.),
+            (Utc.ymd(2020, 1, 3).and_hms(17, 30, 0), 130.),
+        ] {
+            db.insert_quote(&Quote {
+                id: None,
+                ticker,
+                price: *price,
+                time: *time,
+                volume: None,
+                quality_score: None,
+                source: None,
+                open: None,
+                high: None,
+                low: None,
+                bid: None,
+                ask: None,
+            })
+            .unwrap();
+        }
+        let mut converter = SimpleCurrencyConverter::new();
+
+        // the last quotes on or before each day are 100 and 121, two years of 730 days apart
+        let start = NaiveDate::from_ymd(2018, 1, 2);
+        let end = NaiveDate::from_ymd(2020, 1, 2);
+        let expected = 1.21_f64.powf(365.25 / 730.) - 1.;
+        let growth = asset_cagr(&mut db, asset_id, start, end, &mut converter).unwrap();
+        assert_fuzzy_eq!(growth, expected, 1e-12);
+        // days without quotes take the last quote before
+        let growth = asset_cagr(
+            &mut db,
+            asset_id,
+            NaiveDate::from_ymd(2018, 1, 1),
+            end,
+            &mut converter,
+        )
+        .unwrap();
+        assert_fuzzy_eq!(growth, (121_f64 / 90.).powf(365.25 / 731.) - 1., 1e-12);
+    }
+
+    #[test]
+    fn hedged_two_periods() {
+        let tol = 1e-12;
+        // local returns of 2% and -1% while the local currency gains 5% and loses 2%
+        let hedged = hedged_returns(&[0.02, -0.01], &[0.05, -0.02]).unwrap();
+        // unhedged: 1.02 * 1.05 - 1 = 7.1%, minus the exchange rate return of 5%
+        assert_fuzzy_eq!(hedged[0], 0.021, tol);
+        // unhedged: 0.99 * 0.98 - 1 = -2.98%, plus the exchange rate loss of 2%
+        assert_fuzzy_eq!(hedged[1], -0.0098, tol);
+        // over both periods 1.021 * 0.9902 - 1, close to the local return of 0.98%
+        assert_fuzzy_eq!(total_return(&hedged), 0.0109942, tol);
+
+        assert!(hedged_returns(&[0.02], &[0.05, 0.01]).is_err());
+        assert!(hedged_returns(&[f64::NAN], &[0.05]).is_err());
+    }
+
+    #[test]
+    fn short_price_series() {
+        assert_eq!(simple_returns(Vec::new()).count(), 0);
+        assert_eq!(log_returns(vec![1.]).count(), 0);
+        let returns = simple_returns(PRICES.iter().copied());
+        assert_eq!(returns.size_hint(), (5, Some(5)));
+        assert_eq!(returns.skip(2).size_hint(), (3, Some(3)));
+    }
+}
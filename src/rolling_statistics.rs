@@ -0,0 +1,152 @@
+//! Statistics over a rolling window of a series, e.g. the 20-day volatility of daily returns.
+//!
+//! All functions return a series of the same length as the input, where the value at index `i`
+//! refers to the window ending at `i`. The first `window - 1` values, for which the window is
+//! not filled yet, are `f64::NAN`. If the window is zero, all values are `f64::NAN`.
+//! Mean and variance are updated in constant time per step by a sliding variant of
+//! Welford's algorithm, i.e. the effort does not depend on the window size.
+
+/// Running mean and sum of squared deviations of the values of a sliding window
+struct Welford {
+    count: usize,
+    mean: f64,
+    m2: f64,
+}
+
+impl Welford {
+    fn new() -> Welford {
+        Welford {
+            count: 0,
+            mean: 0.,
+            m2: 0.,
+        }
+    }
+
+    /// Add a value, increasing the window size by one
+    fn push(&mut self, value: f64) {
+        self.count += 1;
+        let delta = value - self.mean;
+        self.mean += delta / self.count as f64;
+        self.m2 += delta * (value - self.mean);
+    }
+
+    /// Replace the oldest value of the window by a new one
+    fn replace(&mut self, old: f64, new: f64) {
+        let old_mean = self.mean;
+        self.mean += (new - old) / self.count as f64;
+        self.m2 += (new - old) * (new - self.mean + old - old_mean);
+        // rounding errors must not cause negative variances
+        self.m2 = self.m2.max(0.);
+    }
+
+    /// Sample variance, undefined for less than two values
+    fn variance(&self) -> f64 {
+        if self.count < 2 {
+            f64::NAN
+        } else {
+            self.m2 / (self.count - 1) as f64
+        }
+    }
+}
+
+/// Apply `statistic` to the state of each full window of the series
+fn rolling<F>(values: &[f64], window: usize, statistic: F) -> Vec<f64>
+where
+    F: Fn(&Welford) -> f64,
+{
+    let mut result = vec![f64::NAN; values.len()];
+    if window == 0 {
+        return result;
+    }
+    let mut state = Welford::new();
+    for (i, value) in values.iter().enumerate() {
+        if i < window {
+            state.push(*value);
+        } else {
+            state.replace(values[i - window], *value);
+        }
+        if i + 1 >= window {
+            result[i] = statistic(&state);
+        }
+    }
+    result
+}
+
+/// Rolling mean of the values in each window
+pub fn rolling_mean(values: &[f64], window: usize) -> Vec<f64> {
+    rolling(values, window, |state| state.mean)
+}
+
+/// Rolling sample standard deviation of the values in each window, which is `f64::NAN` for
+/// windows of size 1
+pub fn rolling_std(values: &[f64], window: usize) -> Vec<f64> {
+    rolling(values, window, |state| state.variance().sqrt())
+}
+
+/// Rolling Sharpe ratio, i.e. the mean excess return over `risk_free_daily` divided by the
+/// standard deviation of the returns in each window. Both refer to the period of the returns
+/// and are not annualized. Windows with constant returns have no Sharpe ratio (`f64::NAN`).
+pub fn rolling_sharpe(returns: &[f64], window: usize, risk_free_daily: f64) -> Vec<f64> {
+    rolling(returns, window, |state| {
+        let std_dev = state.variance().sqrt();
+        if std_dev > 0. {
+            (state.mean - risk_free_daily) / std_dev
+        } else {
+            f64::NAN
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RETURNS: [f64; 8] = [0.01, -0.02, 0.015, 0.003, -0.007, 0.012, 0.012, 0.012];
+
+    /// Mean and sample standard deviation calculated from scratch
+    fn mean_and_std(values: &[f64]) -> (f64, f64) {
+        let n = values.len() as f64;
+        let mean = values.iter().sum::<f64>() / n;
+        let variance = values.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / (n - 1.);
+        (mean, variance.sqrt())
+    }
+
+    #[test]
+    fn rolling_statistics_match_full_calculation() {
+        let tol = 1e-12;
+        let window = 3;
+        let means = rolling_mean(&RETURNS, window);
+        let std_devs = rolling_std(&RETURNS, window);
+        let sharpe = rolling_sharpe(&RETURNS, window, 0.001);
+        assert_eq!(means.len(), RETURNS.len());
+        assert_eq!(std_devs.len(), RETURNS.len());
+        assert_eq!(sharpe.len(), RETURNS.len());
+        for i in 0..window - 1 {
+            assert!(means[i].is_nan() && std_devs[i].is_nan() && sharpe[i].is_nan());
+        }
+        for i in window - 1..RETURNS.len() {
+            let (mean, std_dev) = mean_and_std(&RETURNS[i + 1 - window..=i]);
+            assert_fuzzy_eq!(means[i], mean, tol);
+            assert_fuzzy_eq!(std_devs[i], std_dev, tol);
+            if i < RETURNS.len() - 1 {
+                assert_fuzzy_eq!(sharpe[i], (mean - 0.001) / std_dev, 1e-9);
+            }
+        }
+        // the last window has constant returns
+        assert!(std_devs[7] < tol);
+        assert!(sharpe[7].is_nan());
+    }
+
+    #[test]
+    fn rolling_window_sizes() {
+        assert!(rolling_mean(&RETURNS, 0).iter().all(|v| v.is_nan()));
+        assert!(rolling_mean(&RETURNS, 9).iter().all(|v| v.is_nan()));
+        assert!(rolling_std(&[], 2).is_empty());
+        for (mean, value) in rolling_mean(&RETURNS, 1).iter().zip(RETURNS.iter()) {
+            assert_fuzzy_eq!(*mean, *value, 1e-12);
+        }
+        assert!(rolling_std(&RETURNS, 1).iter().all(|v| v.is_nan()));
+        let full = rolling_std(&RETURNS, RETURNS.len());
+        assert_fuzzy_eq!(full[7], mean_and_std(&RETURNS).1, 1e-12);
+    }
+}
@@ -0,0 +1,374 @@
+//! Report definitions saved by name, e.g. to create the same reports every month.
+//!
+//! A `ReportRequest` describes which report to create and with which settings. It is stored in
+//! the database by `save_report` as JSON tagged with `REPORT_REQUEST_VERSION`. Definitions of
+//! older versions are upgraded when loaded, definitions which can't be read, e.g. since they
+//! have been written by a newer version, are rejected with the reason. Periods are relative to
+//! the day a report is run, e.g. the last month.
+
+use std::error::Error;
+use std::fmt;
+
+use chrono::FixedOffset;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use finql_data::{Currency, CurrencyConverter, DataError, JobRun, ReportDefinition, ReportHandler};
+
+use crate::contribution::{
+    contribution_report, ContributionError, ContributionReport, ContributionScope, SavingsTarget,
+};
+use crate::date_time_helper::Clock;
+use crate::income::{income_report, IncomeReport, IncomeReportError};
+use crate::time_period::TimePeriod;
+
+/// Version of the layout of serialized report requests. Whenever the layout changes
+/// incompatibly, the version is increased and `upgrade_report_request` is extended accordingly.
+pub const REPORT_REQUEST_VERSION: u32 = 1;
+
+/// Error related to saved reports
+#[derive(Debug)]
+pub enum SavedReportError {
+    DBError(DataError),
+    NotFound(String),
+    /// The stored definition can't be read, e.g. due to an unknown version or field
+    IncompatibleDefinition(String),
+    Income(IncomeReportError),
+    Contribution(ContributionError),
+}
+
+impl fmt::Display for SavedReportError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::DBError(_) => write!(f, "database error"),
+            Self::NotFound(name) => write!(f, "no report definition named '{}'", name),
+            Self::IncompatibleDefinition(reason) => {
+                write!(f, "incompatible report definition: {}", reason)
+            }
+            Self::Income(_) => write!(f, "income report failed"),
+            Self::Contribution(_) => write!(f, "contribution report failed"),
+        }
+    }
+}
+
+impl Error for SavedReportError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::DBError(err) => Some(err),
+            Self::Income(err) => Some(err),
+            Self::Contribution(err) => Some(err),
+            Self::NotFound(_) | Self::IncompatibleDefinition(_) => None,
+        }
+    }
+}
+
+impl From<DataError> for SavedReportError {
+    fn from(error: DataError) -> Self {
+        Self::DBError(error)
+    }
+}
+
+impl From<IncomeReportError> for SavedReportError {
+    fn from(error: IncomeReportError) -> Self {
+        Self::Income(error)
+    }
+}
+
+impl From<ContributionError> for SavedReportError {
+    fn from(error: ContributionError) -> Self {
+        Self::Contribution(error)
+    }
+}
+
+/// Report to create with its settings. The period of the report ends at the day the report
+/// is run and starts `period` before.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "report", rename_all = "snake_case", deny_unknown_fields)]
+pub enum ReportRequest {
+    Income {
+        period: TimePeriod,
+        base_currency: Currency,
+    },
+    Contribution {
+        period: TimePeriod,
+        currency: Currency,
+        monthly_income: Option<f64>,
+        target: Option<SavingsTarget>,
+    },
+}
+
+/// Report created for a `ReportRequest`
+#[derive(Debug)]
+pub enum Report {
+    Income(IncomeReport),
+    Contribution(ContributionReport),
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Income(report) => write!(f, "{}", report),
+            Self::Contribution(report) => write!(f, "{}", report),
+        }
+    }
+}
+
+impl ReportRequest {
+    /// Create the requested report for the period ending at the current day of `clock`
+    pub fn run(
+        &self,
+        db: &mut dyn ReportHandler,
+        currency_converter: &mut dyn CurrencyConverter,
+        clock: &dyn Clock,
+    ) -> Result<Report, SavedReportError> {
+        let end = clock.today(FixedOffset::east(0));
+        match self {
+            Self::Income {
+                period,
+                base_currency,
+            } => Ok(Report::Income(income_report(
+                db,
+                period.sub_from(end, None),
+                end,
+                *base_currency,
+                currency_converter,
+                clock,
+            )?)),
+            Self::Contribution {
+                period,
+                currency,
+                monthly_income,
+                target,
+            } => {
+                let scope = ContributionScope {
+                    currency: *currency,
+                    monthly_income: *monthly_income,
+                    target: *target,
+                };
+                Ok(Report::Contribution(contribution_report(
+                    db,
+                    &scope,
+                    period.sub_from(end, None),
+                    end,
+                )?))
+            }
+        }
+    }
+}
+
+/// Convert a payload of the given version to the layout of the next version. No older
+/// versions exist yet, all are rejected.
+fn upgrade_report_request(version: u32, _payload: Value) -> Result<Value, SavedReportError> {
+    Err(SavedReportError::IncompatibleDefinition(format!(
+        "no upgrade of report definitions from version {} available",
+        version
+    )))
+}
+
+/// Convert a stored definition to a report request, upgrading older versions
+pub fn decode_report_request(
+    definition: ReportDefinition,
+) -> Result<ReportRequest, SavedReportError> {
+    if definition.version > REPORT_REQUEST_VERSION {
+        return Err(SavedReportError::IncompatibleDefinition(format!(
+            "'{}' has version {}, but only version {} is supported",
+            definition.name, definition.version, REPORT_REQUEST_VERSION
+        )));
+    }
+    let name = definition.name;
+    let mut payload = definition.payload;
+    for version in definition.version..REPORT_REQUEST_VERSION {
+        payload = upgrade_report_request(version, payload)?;
+    }
+    serde_json::from_value(payload)
+        .map_err(|e| SavedReportError::IncompatibleDefinition(format!("'{}': {}", name, e)))
+}
+
+/// Store a report request by name, replacing any definition with the same name
+pub fn save_report(
+    db: &mut dyn ReportHandler,
+    name: &str,
+    request: &ReportRequest,
+) -> Result<(), SavedReportError> {
+    let payload =
+        serde_json::to_value(request).map_err(|e| DataError::InvalidData(e.to_string()))?;
+    db.set_report_definition(&ReportDefinition {
+        name: name.to_string(),
+        version: REPORT_REQUEST_VERSION,
+        payload,
+    })?;
+    Ok(())
+}
+
+/// Load the report request saved by name
+pub fn load_report(
+    db: &mut dyn ReportHandler,
+    name: &str,
+) -> Result<ReportRequest, SavedReportError> {
+    match db.get_report_definition(name)? {
+        Some(definition) => decode_report_request(definition),
+        None => Err(SavedReportError::NotFound(name.to_string())),
+    }
+}
+
+/// Name of the job runs of a saved report
+pub fn report_job_name(name: &str) -> String {
+    format!("report:{}", name)
+}
+
+/// Load the report request saved by name and create the report. Each run is recorded as
+/// job run, failed runs with the error message.
+pub fn run_saved_report(
+    db: &mut dyn ReportHandler,
+    name: &str,
+    currency_converter: &mut dyn CurrencyConverter,
+    clock: &dyn Clock,
+) -> Result<Report, SavedReportError> {
+    let started = clock.now_utc();
+    let report =
+        load_report(db, name).and_then(|request| request.run(db, currency_converter, clock));
+    db.insert_job_run(&JobRun {
+        id: None,
+        job: report_job_name(name),
+        started,
+        finished: clock.now_utc(),
+        success: report.is_ok(),
+        message: report.as_ref().err().map(|err| err.to_string()),
+    })?;
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::str::FromStr;
+
+    use chrono::{NaiveDate, TimeZone, Utc};
+    use finql_data::{CashFlow, Transaction, TransactionHandler, TransactionType};
+    use finql_sqlite::SqliteDB;
+    use rusqlite::Connection;
+
+    use crate::date_time_helper::MockClock;
+    use crate::fx_rates::SimpleCurrencyConverter;
+
+    fn cash(amount: f64, month: u32, day: u32) -> Transaction {
+        Transaction {
+            id: None,
+            transaction_type: TransactionType::Cash,
+            cash_flow: CashFlow::new(
+                amount,
+                Currency::from_str("EUR").unwrap(),
+                NaiveDate::from_ymd(2021, month, day),
+            ),
+            note: None,
+            execution_meta: None,
+            recorded_at: None,
+        }
+    }
+
+    #[test]
+    fn run_saved_reports() {
+        let conn = Connection::open(":memory:").unwrap();
+        let mut db = SqliteDB::new(&conn);
+        db.init().unwrap();
+        for trans in &[cash(1000.0, 1, 15), cash(500.0, 2, 15), cash(-200.0, 3, 1)] {
+            db.insert_transaction(trans).unwrap();
+        }
+        let eur = Currency::from_str("EUR").unwrap();
+        let request = ReportRequest::Contribution {
+            period: TimePeriod::from_str("2M").unwrap(),
+            currency: eur,
+            monthly_income: None,
+            target: Some(SavingsTarget {
+                amount: 5000.0,
+                annual_return: 0.0,
+            }),
+        };
+        save_report(&mut db, "savings", &request).unwrap();
+        save_report(
+            &mut db,
+            "income",
+            &ReportRequest::Income {
+                period: TimePeriod::from_str("1Y").unwrap(),
+                base_currency: eur,
+            },
+        )
+        .unwrap();
+        assert_eq!(load_report(&mut db, "savings").unwrap(), request);
+
+        let clock = MockClock::new(Utc.ymd(2021, 3, 20).and_hms(18, 0, 0));
+        let mut converter = SimpleCurrencyConverter::new();
+        match run_saved_report(&mut db, "savings", &mut converter, &clock).unwrap() {
+            Report::Contribution(report) => {
+                assert_eq!(report.start, NaiveDate::from_ymd(2021, 1, 20));
+                assert_eq!(report.end, NaiveDate::from_ymd(2021, 3, 20));
+                assert_eq!(report.total, 300.0);
+            }
+            report => panic!("unexpected report {:?}", report),
+        }
+        assert!(matches!(
+            run_saved_report(&mut db, "income", &mut converter, &clock),
+            Ok(Report::Income(_))
+        ));
+        assert!(matches!(
+            run_saved_report(&mut db, "taxes", &mut converter, &clock),
+            Err(SavedReportError::NotFound(_))
+        ));
+
+        let runs = db.get_job_runs(&report_job_name("savings")).unwrap();
+        assert_eq!(runs.len(), 1);
+        assert!(runs[0].success);
+        let runs = db.get_job_runs(&report_job_name("taxes")).unwrap();
+        assert!(!runs[0].success);
+        assert_eq!(
+            runs[0].message.as_deref(),
+            Some("no report definition named 'taxes'")
+        );
+    }
+
+    #[test]
+    fn reject_incompatible_definitions() {
+        let definition = |version, payload| ReportDefinition {
+            name: "income".to_string(),
+            version,
+            payload,
+        };
+        let payload = serde_json::json!({
+            "report": "income",
+            "period": "1M",
+            "base_currency": "EUR",
+        });
+        assert_eq!(
+            decode_report_request(definition(1, payload.clone())).unwrap(),
+            ReportRequest::Income {
+                period: TimePeriod::from_str("1M").unwrap(),
+                base_currency: Currency::from_str("EUR").unwrap(),
+            }
+        );
+
+        let err = decode_report_request(definition(2, payload)).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "incompatible report definition: 'income' has version 2, but only version 1 is \
+            supported"
+        );
+        let err = decode_report_request(definition(
+            1,
+            serde_json::json!({
+                "report": "income",
+                "period": "1M",
+                "base_currency": "EUR",
+                "filters": ["dividends"],
+            }),
+        ))
+        .unwrap_err();
+        assert!(err.to_string().contains("unknown field `filters`"));
+        let err = decode_report_request(definition(
+            1,
+            serde_json::json!({"report": "income", "period": "1M"}),
+        ))
+        .unwrap_err();
+        assert!(err.to_string().contains("missing field `base_currency`"));
+    }
+}
@@ -0,0 +1,344 @@
+//! Simulation of the withholding tax on projected dividends.
+//!
+//! Capital income is taxed at a flat rate after deduction of an annual allowance, which is
+//! consumed by payments in chronological order and renewed at the beginning of each calendar
+//! year. A part of the dividends of some assets, e.g. equity funds, may be exempt from tax.
+//!
+//! The allowance already used in the current year is derived from the actual dividend,
+//! interest and tax transactions of that year: income which has not been taxed must have been
+//! covered by the allowance.
+
+use std::collections::BTreeMap;
+
+use chrono::{Datelike, NaiveDate};
+use serde::{Deserialize, Serialize};
+
+use finql_data::{Currency, DataError, TransactionHandler, TransactionType};
+
+use crate::dividends::project_dividends;
+use crate::time_buckets::{Bucket, BucketKey};
+
+/// Tax parameters of an investor, all amounts given in `currency`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TaxParameters {
+    pub currency: Currency,
+    /// Tax rate applied to taxable income, including surcharges like church tax
+    pub flat_rate: f64,
+    /// Tax free income per calendar year
+    pub annual_allowance: f64,
+    /// Fraction of income which is exempt from tax, by asset id
+    #[serde(default)]
+    pub partial_exemptions: BTreeMap<usize, f64>,
+}
+
+impl TaxParameters {
+    /// Fraction of the income of an asset which is exempt from tax, zero by default
+    pub fn partial_exemption(&self, asset_id: usize) -> f64 {
+        self.partial_exemptions
+            .get(&asset_id)
+            .copied()
+            .unwrap_or(0.)
+    }
+
+    /// Check that rates are between 0 and 1 and the allowance is not negative
+    pub fn validate(&self) -> Result<(), DataError> {
+        let is_rate = |rate: f64| (0. ..=1.).contains(&rate);
+        if !is_rate(self.flat_rate) {
+            return Err(DataError::InvalidData(format!(
+                "tax rate {} is not between 0 and 1",
+                self.flat_rate
+            )));
+        }
+        if !(self.annual_allowance >= 0. && self.annual_allowance.is_finite()) {
+            return Err(DataError::InvalidData(format!(
+                "allowance {} is negative",
+                self.annual_allowance
+            )));
+        }
+        if let Some((asset_id, exemption)) =
+            self.partial_exemptions.iter().find(|(_, e)| !is_rate(**e))
+        {
+            return Err(DataError::InvalidData(format!(
+                "partial exemption {} of asset {} is not between 0 and 1",
+                exemption, asset_id
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// Projected dividend payment after tax
+#[derive(Debug, Clone, Serialize)]
+pub struct NetPayment {
+    pub asset_id: usize,
+    pub date: NaiveDate,
+    pub gross: f64,
+    /// Part of the payment exempt from tax by partial exemption
+    pub exemption: f64,
+    /// Part of the payment covered by the annual allowance
+    pub allowance: f64,
+    pub tax: f64,
+    pub net: f64,
+}
+
+/// Projected income of a calendar month
+#[derive(Debug, Clone, Serialize)]
+pub struct MonthlyNetIncome {
+    pub year: i32,
+    pub month: u32,
+    pub gross: f64,
+    pub tax: f64,
+    pub net: f64,
+}
+
+/// Projected dividends after tax up to a given horizon
+#[derive(Debug, Clone, Serialize)]
+pub struct NetIncomeProjection {
+    pub as_of: NaiveDate,
+    pub horizon: NaiveDate,
+    pub currency: Currency,
+    /// Allowance used by actual income of the current year up to `as_of`
+    pub allowance_used_ytd: f64,
+    /// Projected payments in chronological order
+    pub payments: Vec<NetPayment>,
+    /// Totals per month in chronological order, months without payments are omitted
+    pub monthly: Vec<MonthlyNetIncome>,
+}
+
+/// Allowance used by the dividends and interest paid in the calendar year of `as_of` up to
+/// (including) `as_of`, i.e. taxable income for which no tax has been paid
+pub fn used_allowance(
+    db: &mut dyn TransactionHandler,
+    params: &TaxParameters,
+    as_of: NaiveDate,
+) -> Result<f64, DataError> {
+    params.validate()?;
+    let year_start = NaiveDate::from_ymd(as_of.year(), 1, 1);
+    let mut taxable_income = 0.;
+    let mut tax_paid = 0.;
+    for trans in db.get_all_transactions()? {
+        let date = trans.cash_flow.date;
+        if date < year_start || date > as_of {
+            continue;
+        }
+        let amount = match trans.transaction_type {
+            TransactionType::Dividend { .. }
+            | TransactionType::Interest { .. }
+            | TransactionType::Tax { .. } => trans.cash_flow.amount,
+            _ => continue,
+        };
+        if amount.currency != params.currency {
+            return Err(DataError::CurrencyMismatch(format!(
+                "transaction paid in {}, but taxes are calculated in {}",
+                amount.currency, params.currency
+            )));
+        }
+        match trans.transaction_type {
+            TransactionType::Dividend { asset_id } | TransactionType::Interest { asset_id } => {
+                taxable_income += amount.amount * (1. - params.partial_exemption(asset_id))
+            }
+            _ => tax_paid -= amount.amount,
+        }
+    }
+    let taxed_income = if params.flat_rate > 0. {
+        tax_paid / params.flat_rate
+    } else {
+        0.
+    };
+    Ok((taxable_income - taxed_income).clamp(0., params.annual_allowance))
+}
+
+/// Project the dividends paid after `as_of` up to (including) `horizon` (see
+/// `dividends::project_dividends`) and the tax withheld from each payment. The allowance is
+/// applied to the payments in chronological order, taking into account the allowance already
+/// used in the year of `as_of`.
+pub fn project_net_income(
+    db: &mut dyn TransactionHandler,
+    params: &TaxParameters,
+    as_of: NaiveDate,
+    horizon: NaiveDate,
+) -> Result<NetIncomeProjection, DataError> {
+    let allowance_used_ytd = used_allowance(db, params, as_of)?;
+    let mut year = Bucket::Year.bucket_of(as_of);
+    let mut remaining_allowance = params.annual_allowance - allowance_used_ytd;
+    let mut payments = Vec::new();
+    let mut monthly: BTreeMap<BucketKey, MonthlyNetIncome> = BTreeMap::new();
+    for dividend in project_dividends(db, as_of, horizon)? {
+        if dividend.currency != params.currency {
+            return Err(DataError::CurrencyMismatch(format!(
+                "dividend of asset {} paid in {}, but taxes are calculated in {}",
+                dividend.asset_id, dividend.currency, params.currency
+            )));
+        }
+        if !year.contains(dividend.date) {
+            year = Bucket::Year.bucket_of(dividend.date);
+            remaining_allowance = params.annual_allowance;
+        }
+        let gross = dividend.gross;
+        let exemption = gross * params.partial_exemption(dividend.asset_id);
+        let allowance = (gross - exemption).clamp(0., remaining_allowance);
+        remaining_allowance -= allowance;
+        let tax = (gross - exemption - allowance).max(0.) * params.flat_rate;
+        let payment = NetPayment {
+            asset_id: dividend.asset_id,
+            date: dividend.date,
+            gross,
+            exemption,
+            allowance,
+            tax,
+            net: gross - tax,
+        };
+        let month = Bucket::Month.bucket_of(dividend.date);
+        let total = monthly.entry(month).or_insert(MonthlyNetIncome {
+            year: dividend.date.year(),
+            month: dividend.date.month(),
+            gross: 0.,
+            tax: 0.,
+            net: 0.,
+        });
+        total.gross += payment.gross;
+        total.tax += payment.tax;
+        total.net += payment.net;
+        payments.push(payment);
+    }
+    Ok(NetIncomeProjection {
+        as_of,
+        horizon,
+        currency: params.currency,
+        allowance_used_ytd,
+        payments,
+        monthly: monthly.into_values().collect(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    use rusqlite::Connection;
+
+    use finql_data::{Asset, AssetHandler, CashFlow, Transaction};
+    use finql_sqlite::SqliteDB;
+
+    fn transaction(transaction_type: TransactionType, amount: f64, date: NaiveDate) -> Transaction {
+        Transaction {
+            id: None,
+            transaction_type,
+            cash_flow: CashFlow::new(amount, Currency::from_str("EUR").unwrap(), date),
+            note: None,
+            execution_meta: None,
+            recorded_at: None,
+        }
+    }
+
+    #[test]
+    fn allowance_across_projected_payments() {
+        let tol = 1e-10;
+        let conn = Connection::open(":memory:").unwrap();
+        let mut db = SqliteDB::new(&conn);
+        db.init().unwrap();
+        let stock = db
+            .insert_asset(&Asset::new(None, "BASF AG", None, None, None))
+            .unwrap();
+        let fund = db
+            .insert_asset(&Asset::new(None, "Equity Fund", None, None, None))
+            .unwrap();
+        for (asset_id, position) in &[(stock, 100.), (fund, 50.)] {
+            db.insert_transaction(&transaction(
+                TransactionType::Asset {
+                    asset_id: *asset_id,
+                    position: *position,
+                },
+                -5000.,
+                NaiveDate::from_ymd(2019, 1, 2),
+            ))
+            .unwrap();
+        }
+        // last year's dividends, which are expected to recur
+        db.insert_transaction(&transaction(
+            TransactionType::Dividend { asset_id: stock },
+            330.,
+            NaiveDate::from_ymd(2019, 4, 20),
+        ))
+        .unwrap();
+        db.insert_transaction(&transaction(
+            TransactionType::Dividend { asset_id: fund },
+            200.,
+            NaiveDate::from_ymd(2019, 9, 10),
+        ))
+        .unwrap();
+        db.insert_transaction(&transaction(
+            TransactionType::Dividend { asset_id: fund },
+            200.,
+            NaiveDate::from_ymd(2019, 12, 10),
+        ))
+        .unwrap();
+        // interest of the current year, of which 50 has been taxed
+        db.insert_transaction(&transaction(
+            TransactionType::Interest { asset_id: stock },
+            400.,
+            NaiveDate::from_ymd(2020, 2, 1),
+        ))
+        .unwrap();
+        db.insert_transaction(&transaction(
+            TransactionType::Tax {
+                transaction_ref: None,
+            },
+            -12.5,
+            NaiveDate::from_ymd(2020, 2, 1),
+        ))
+        .unwrap();
+
+        let mut partial_exemptions = BTreeMap::new();
+        partial_exemptions.insert(fund, 0.3);
+        let params = TaxParameters {
+            currency: Currency::from_str("EUR").unwrap(),
+            flat_rate: 0.25,
+            annual_allowance: 801.,
+            partial_exemptions,
+        };
+        let as_of = NaiveDate::from_ymd(2020, 3, 1);
+        let horizon = NaiveDate::from_ymd(2021, 6, 30);
+        assert_fuzzy_eq!(used_allowance(&mut db, &params, as_of).unwrap(), 350., tol);
+
+        let projection = project_net_income(&mut db, &params, as_of, horizon).unwrap();
+        assert_fuzzy_eq!(projection.allowance_used_ytd, 350., tol);
+        let dates: Vec<NaiveDate> = projection.payments.iter().map(|p| p.date).collect();
+        assert_eq!(
+            dates,
+            vec![
+                NaiveDate::from_ymd(2020, 4, 20),
+                NaiveDate::from_ymd(2020, 9, 10),
+                NaiveDate::from_ymd(2020, 12, 10),
+                NaiveDate::from_ymd(2021, 4, 20),
+            ]
+        );
+        let p = &projection.payments;
+        // 451 of allowance left for 2020
+        assert_fuzzy_eq!(p[0].allowance, 330., tol);
+        assert_fuzzy_eq!(p[0].tax, 0., tol);
+        assert_fuzzy_eq!(p[1].exemption, 60., tol);
+        assert_fuzzy_eq!(p[1].allowance, 121., tol);
+        assert_fuzzy_eq!(p[1].tax, 19. * 0.25, tol);
+        assert_fuzzy_eq!(p[2].allowance, 0., tol);
+        assert_fuzzy_eq!(p[2].tax, 140. * 0.25, tol);
+        assert_fuzzy_eq!(p[2].net, 200. - 35., tol);
+        // allowance is renewed in 2021
+        assert_fuzzy_eq!(p[3].allowance, 330., tol);
+        assert_fuzzy_eq!(p[3].net, 330., tol);
+
+        assert_eq!(projection.monthly.len(), 4);
+        assert_eq!(
+            (projection.monthly[2].year, projection.monthly[2].month),
+            (2020, 12)
+        );
+        assert_fuzzy_eq!(projection.monthly[2].net, 165., tol);
+
+        let invalid = TaxParameters {
+            flat_rate: 1.5,
+            ..params
+        };
+        assert!(project_net_income(&mut db, &invalid, as_of, horizon).is_err());
+    }
+}
@@ -0,0 +1,139 @@
+//! Helpers for tests comparing report output against committed golden files.
+//!
+//! Fixtures are stored in `tests/fixtures`, golden files in `tests/golden`. Reports are
+//! serialized to JSON and normalized before comparison, i.e. non-deterministic fields like
+//! the time of creation are removed and object keys are sorted. To regenerate the golden
+//! files after an intended change of the output, run the tests with the environment
+//! variable `FINQL_UPDATE_GOLDEN` set.
+
+use std::fs;
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+use finql_data::{Asset, AssetHandler, Transaction, TransactionHandler};
+use finql_sqlite::SqliteDB;
+
+/// Environment variable that triggers regeneration of golden files
+pub const UPDATE_GOLDEN_ENV: &str = "FINQL_UPDATE_GOLDEN";
+
+/// Fields that differ between runs and are therefore removed before comparison
+const NON_DETERMINISTIC_FIELDS: [&str; 1] = ["generated_at"];
+
+/// Content of a fixture database file
+#[derive(Deserialize)]
+struct Fixture {
+    assets: Vec<Asset>,
+    transactions: Vec<Transaction>,
+}
+
+fn test_data_path(dir: &str, file_name: &str) -> PathBuf {
+    [env!("CARGO_MANIFEST_DIR"), "tests", dir, file_name]
+        .iter()
+        .collect()
+}
+
+/// Initialize the database and fill it with the assets and transactions of the given fixture file
+pub fn load_fixture_db(db: &mut SqliteDB, fixture: &str) {
+    let path = test_data_path("fixtures", fixture);
+    let data = fs::read_to_string(&path)
+        .unwrap_or_else(|e| panic!("failed to read fixture {}: {}", path.display(), e));
+    let fixture: Fixture = serde_json::from_str(&data)
+        .unwrap_or_else(|e| panic!("invalid fixture {}: {}", path.display(), e));
+    db.init().unwrap();
+    for asset in &fixture.assets {
+        db.insert_asset(asset).unwrap();
+    }
+    for transaction in &fixture.transactions {
+        db.insert_transaction(transaction).unwrap();
+    }
+}
+
+/// Remove non-deterministic fields and sort all object keys
+pub fn normalize_json(value: Value) -> Value {
+    match value {
+        Value::Object(map) => {
+            let mut entries: Vec<(String, Value)> = map
+                .into_iter()
+                .filter(|(key, _)| !NON_DETERMINISTIC_FIELDS.contains(&key.as_str()))
+                .map(|(key, value)| (key, normalize_json(value)))
+                .collect();
+            entries.sort_by(|a, b| a.0.cmp(&b.0));
+            Value::Object(entries.into_iter().collect::<Map<String, Value>>())
+        }
+        Value::Array(values) => Value::Array(values.into_iter().map(normalize_json).collect()),
+        value => value,
+    }
+}
+
+/// Line by line comparison of expected and actual output, listing only differing lines
+fn line_diff(expected: &str, actual: &str) -> String {
+    let expected: Vec<&str> = expected.lines().collect();
+    let actual: Vec<&str> = actual.lines().collect();
+    let mut diff = String::new();
+    for i in 0..expected.len().max(actual.len()) {
+        let (exp, act) = (expected.get(i), actual.get(i));
+        if exp != act {
+            if let Some(line) = exp {
+                diff.push_str(&format!("{:4} - {}\n", i + 1, line));
+            }
+            if let Some(line) = act {
+                diff.push_str(&format!("{:4} + {}\n", i + 1, line));
+            }
+        }
+    }
+    diff
+}
+
+/// Compare the normalized JSON representation of `output` with the golden file `name`
+pub fn assert_golden<T: Serialize>(name: &str, output: &T) {
+    let path = test_data_path("golden", &format!("{}.json", name));
+    let actual = normalize_json(serde_json::to_value(output).unwrap());
+    let actual = serde_json::to_string_pretty(&actual).unwrap() + "\n";
+    if std::env::var_os(UPDATE_GOLDEN_ENV).is_some() {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, actual).unwrap();
+        return;
+    }
+    let expected = fs::read_to_string(&path).unwrap_or_else(|e| {
+        panic!(
+            "failed to read golden file {}: {}\nrun with {} set to create it",
+            path.display(),
+            e,
+            UPDATE_GOLDEN_ENV
+        )
+    });
+    if expected != actual {
+        panic!(
+            "output differs from golden file {} (- expected, + actual):\n{}\
+             run with {} set to accept the new output",
+            path.display(),
+            line_diff(&expected, &actual),
+            UPDATE_GOLDEN_ENV
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn normalize_strips_and_sorts() {
+        let value = json!({
+            "b": [{"generated_at": "now", "z": 1, "a": 2}],
+            "generated_at": "2021-01-01T00:00:00Z",
+            "a": 1.5,
+        });
+        let normalized = serde_json::to_string(&normalize_json(value)).unwrap();
+        assert_eq!(normalized, r#"{"a":1.5,"b":[{"a":2,"z":1}]}"#);
+    }
+
+    #[test]
+    fn diff_lists_changed_lines() {
+        let diff = line_diff("a\nb\nc\n", "a\nx\nc\nd\n");
+        assert_eq!(diff, "   2 - b\n   2 + x\n   4 + d\n");
+    }
+}
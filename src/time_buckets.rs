@@ -0,0 +1,234 @@
+//! Bucketing of dates into calendar periods, e.g. to aggregate payments or values per month.
+//!
+//! Weeks follow ISO 8601, i.e. they start on Monday and belong to the year that contains
+//! their Thursday. Therefore, the first days of January may belong to the last week of the
+//! previous year, and the last days of December may belong to week 1 of the next year.
+
+use std::fmt;
+
+use chrono::{Datelike, Duration, NaiveDate, Weekday};
+use serde::{Deserialize, Serialize};
+
+/// Length of the calendar period dates are grouped by
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Bucket {
+    Day,
+    IsoWeek,
+    Month,
+    Quarter,
+    Year,
+}
+
+/// Identifies a single bucket, i.e. a specific calendar period
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum BucketKey {
+    Day(NaiveDate),
+    IsoWeek { year: i32, week: u32 },
+    Month { year: i32, month: u32 },
+    Quarter { year: i32, quarter: u32 },
+    Year(i32),
+}
+
+/// Language used for bucket labels
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Locale {
+    En,
+    De,
+}
+
+const MONTHS_EN: [&str; 12] = [
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+];
+const MONTHS_DE: [&str; 12] = [
+    "Jan", "Feb", "Mär", "Apr", "Mai", "Jun", "Jul", "Aug", "Sep", "Okt", "Nov", "Dez",
+];
+
+impl Bucket {
+    /// Bucket the given date belongs to
+    pub fn bucket_of(&self, date: NaiveDate) -> BucketKey {
+        match self {
+            Self::Day => BucketKey::Day(date),
+            Self::IsoWeek => {
+                let week = date.iso_week();
+                BucketKey::IsoWeek {
+                    year: week.year(),
+                    week: week.week(),
+                }
+            }
+            Self::Month => BucketKey::Month {
+                year: date.year(),
+                month: date.month(),
+            },
+            Self::Quarter => BucketKey::Quarter {
+                year: date.year(),
+                quarter: (date.month() - 1) / 3 + 1,
+            },
+            Self::Year => BucketKey::Year(date.year()),
+        }
+    }
+
+    /// All buckets from the one containing `start` up to the one containing `end` in
+    /// ascending order, including buckets no date of interest falls into.
+    /// The result is empty if `end` is before `start`.
+    pub fn bucket_range(&self, start: NaiveDate, end: NaiveDate) -> Vec<BucketKey> {
+        let mut buckets = Vec::new();
+        if end < start {
+            return buckets;
+        }
+        let last = self.bucket_of(end);
+        let mut key = self.bucket_of(start);
+        loop {
+            buckets.push(key);
+            if key == last {
+                break;
+            }
+            key = key.next();
+        }
+        buckets
+    }
+}
+
+impl BucketKey {
+    /// First day of the period
+    pub fn first_day(&self) -> NaiveDate {
+        match *self {
+            Self::Day(date) => date,
+            Self::IsoWeek { year, week } => NaiveDate::from_isoywd(year, week, Weekday::Mon),
+            Self::Month { year, month } => NaiveDate::from_ymd(year, month, 1),
+            Self::Quarter { year, quarter } => NaiveDate::from_ymd(year, 3 * quarter - 2, 1),
+            Self::Year(year) => NaiveDate::from_ymd(year, 1, 1),
+        }
+    }
+
+    /// Last day of the period
+    pub fn last_day(&self) -> NaiveDate {
+        self.next().first_day().pred()
+    }
+
+    /// Bucket directly following this one
+    pub fn next(&self) -> BucketKey {
+        match *self {
+            Self::Day(date) => Self::Day(date.succ()),
+            Self::IsoWeek { .. } => Bucket::IsoWeek.bucket_of(self.first_day() + Duration::days(7)),
+            Self::Month { year, month: 12 } => Self::Month {
+                year: year + 1,
+                month: 1,
+            },
+            Self::Month { year, month } => Self::Month {
+                year,
+                month: month + 1,
+            },
+            Self::Quarter { year, quarter: 4 } => Self::Quarter {
+                year: year + 1,
+                quarter: 1,
+            },
+            Self::Quarter { year, quarter } => Self::Quarter {
+                year,
+                quarter: quarter + 1,
+            },
+            Self::Year(year) => Self::Year(year + 1),
+        }
+    }
+
+    /// Check whether the date falls into this bucket
+    pub fn contains(&self, date: NaiveDate) -> bool {
+        date >= self.first_day() && date <= self.last_day()
+    }
+}
+
+/// Human readable label of a bucket, e.g. "2020-W53" or "Mar 2020" in English
+/// and "KW 53/2020" or "Mär 2020" in German
+pub fn bucket_label(key: &BucketKey, locale: Locale) -> String {
+    match (*key, locale) {
+        (BucketKey::Day(date), Locale::En) => date.format("%Y-%m-%d").to_string(),
+        (BucketKey::Day(date), Locale::De) => date.format("%d.%m.%Y").to_string(),
+        (BucketKey::IsoWeek { year, week }, Locale::En) => format!("{}-W{:02}", year, week),
+        (BucketKey::IsoWeek { year, week }, Locale::De) => format!("KW {:02}/{}", week, year),
+        (BucketKey::Month { year, month }, Locale::En) => {
+            format!("{} {}", MONTHS_EN[month as usize - 1], year)
+        }
+        (BucketKey::Month { year, month }, Locale::De) => {
+            format!("{} {}", MONTHS_DE[month as usize - 1], year)
+        }
+        (BucketKey::Quarter { year, quarter }, _) => format!("Q{} {}", quarter, year),
+        (BucketKey::Year(year), _) => year.to_string(),
+    }
+}
+
+impl fmt::Display for BucketKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", bucket_label(self, Locale::En))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn iso_weeks_around_new_year() {
+        let week = |y, m, d| {
+            Bucket::IsoWeek
+                .bucket_of(NaiveDate::from_ymd(y, m, d))
+                .to_string()
+        };
+        // 2020 has 53 weeks, the week of new year 2021 still belongs to 2020
+        assert_eq!(week(2020, 12, 28), "2020-W53");
+        assert_eq!(week(2021, 1, 3), "2020-W53");
+        assert_eq!(week(2021, 1, 4), "2021-W01");
+        // Dec 29, 2014 is a Monday and starts week 1 of 2015
+        assert_eq!(week(2014, 12, 28), "2014-W52");
+        assert_eq!(week(2014, 12, 29), "2015-W01");
+        assert_eq!(week(2015, 1, 3), "2015-W01");
+
+        let key = BucketKey::IsoWeek {
+            year: 2015,
+            week: 1,
+        };
+        assert_eq!(key.first_day(), NaiveDate::from_ymd(2014, 12, 29));
+        assert_eq!(key.last_day(), NaiveDate::from_ymd(2015, 1, 4));
+        assert!(key.contains(NaiveDate::from_ymd(2014, 12, 31)));
+
+        let weeks = Bucket::IsoWeek.bucket_range(
+            NaiveDate::from_ymd(2020, 12, 20),
+            NaiveDate::from_ymd(2021, 1, 10),
+        );
+        let labels: Vec<String> = weeks.iter().map(|k| bucket_label(k, Locale::En)).collect();
+        assert_eq!(labels, vec!["2020-W51", "2020-W52", "2020-W53", "2021-W01"]);
+        assert_eq!(bucket_label(&weeks[2], Locale::De), "KW 53/2020");
+    }
+
+    #[test]
+    fn quarter_and_month_boundaries() {
+        let quarter = |m, d| Bucket::Quarter.bucket_of(NaiveDate::from_ymd(2020, m, d));
+        assert_eq!(quarter(3, 31).to_string(), "Q1 2020");
+        assert_eq!(quarter(4, 1).to_string(), "Q2 2020");
+        assert_eq!(quarter(12, 31).to_string(), "Q4 2020");
+        let q4 = quarter(10, 1);
+        assert_eq!(q4.first_day(), NaiveDate::from_ymd(2020, 10, 1));
+        assert_eq!(q4.last_day(), NaiveDate::from_ymd(2020, 12, 31));
+        assert_eq!(q4.next().to_string(), "Q1 2021");
+
+        // empty buckets in between are included
+        let quarters = Bucket::Quarter.bucket_range(
+            NaiveDate::from_ymd(2020, 11, 15),
+            NaiveDate::from_ymd(2021, 7, 1),
+        );
+        assert_eq!(quarters.len(), 4);
+        assert_eq!(quarters[3].to_string(), "Q3 2021");
+
+        let feb = Bucket::Month.bucket_of(NaiveDate::from_ymd(2020, 2, 10));
+        assert_eq!(feb.last_day(), NaiveDate::from_ymd(2020, 2, 29));
+        let months = Bucket::Month.bucket_range(
+            NaiveDate::from_ymd(2019, 12, 31),
+            NaiveDate::from_ymd(2020, 3, 1),
+        );
+        assert_eq!(months.len(), 4);
+        assert_eq!(bucket_label(&months[0], Locale::En), "Dec 2019");
+        assert_eq!(bucket_label(&months[3], Locale::De), "Mär 2020");
+
+        let start = NaiveDate::from_ymd(2020, 5, 1);
+        assert!(Bucket::Year.bucket_range(start, start.pred()).is_empty());
+        assert_eq!(Bucket::Day.bucket_range(start, start).len(), 1);
+    }
+}
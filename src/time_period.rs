@@ -87,22 +87,10 @@ impl TimePeriod {
     /// Add time period to a given date.
     /// The function call will panic is the resulting year is out
     /// of the valid range or if not calendar is provided in case of BusinessDaily time periods
-    pub fn add_to(&self, mut date: NaiveDate, cal: Option<&Calendar>) -> NaiveDate {
+    pub fn add_to(&self, date: NaiveDate, cal: Option<&Calendar>) -> NaiveDate {
         match self.unit {
             TimePeriodUnit::Daily => date + Duration::days(self.num as i64),
-            TimePeriodUnit::BusinessDaily => {
-                let is_neg = self.num < 0;
-                let n = self.num.abs();
-                let cal = cal.unwrap();
-                for _ in 0..n {
-                    date = if is_neg {
-                        cal.prev_bday(date)
-                    } else {
-                        cal.next_bday(date)
-                    };
-                }
-                date
-            }
+            TimePeriodUnit::BusinessDaily => cal.unwrap().add_business_days(date, self.num),
             TimePeriodUnit::Weekly => date
                 .checked_add_signed(Duration::days(7 * self.num as i64))
                 .unwrap(),
@@ -148,6 +136,19 @@ impl TimePeriod {
         }
     }
 
+    /// Multiple of the time period, e.g. `3M` times 2 gives `6M`.
+    pub fn times(&self, factor: i32) -> TimePeriod {
+        TimePeriod {
+            num: factor * self.num,
+            unit: self.unit,
+        }
+    }
+
+    /// True if adding the time period moves a date forward
+    pub fn is_positive(&self) -> bool {
+        self.num > 0
+    }
+
     /// Returns the frequency per year, if this is possible,
     /// otherwise return error
     pub fn frequency(&self) -> Result<u16, TimePeriodError> {
@@ -387,6 +388,25 @@ mod tests {
         );
     }
 
+    #[test]
+    fn multiple_periods() {
+        let period = TimePeriod::from_str("1M").unwrap();
+        assert!(period.is_positive());
+        assert!(!period.inverse().is_positive());
+        assert!(!period.times(0).is_positive());
+        assert_eq!(period.times(3), TimePeriod::from_str("3M").unwrap());
+
+        // Multiples keep the original day where the target month allows it,
+        // unlike adding the period repeatedly.
+        let date = NaiveDate::from_ymd(2021, 1, 31);
+        assert_eq!(period.times(1).add_to(date, None), NaiveDate::from_ymd(2021, 2, 28));
+        assert_eq!(period.times(2).add_to(date, None), NaiveDate::from_ymd(2021, 3, 31));
+        assert_eq!(
+            period.add_to(period.add_to(date, None), None),
+            NaiveDate::from_ymd(2021, 3, 28)
+        );
+    }
+
     #[test]
     fn display_periods() {
         assert_eq!(format!("{}", TimePeriod::from_str("3M").unwrap()), "3M");
@@ -0,0 +1,636 @@
+//! Simulation of withdrawal plans, i.e. funding fixed monthly withdrawals by selling
+//! the holdings of a portfolio.
+//!
+//! The simulation starts with the cash balance and open lots of the assets in scope at the start
+//! date. Each month, cash is used and assets are sold as determined by the `WithdrawalOrder` to
+//! fund the withdrawal. Sales are charged with fees according to the `FeeModel`, and realized
+//! gains (lots are closed in FIFO order) are taxed with a flat tax rate. Fees and taxes are
+//! paid from the proceeds, i.e. assets are sold until the proceeds net of fees and taxes cover
+//! the withdrawal.
+//!
+//! Prices either follow the historical quotes stored in the database or are simulated by a
+//! seeded random walk starting at the historical prices of the start date.
+
+use std::collections::{BTreeMap, VecDeque};
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+
+use chrono::{DateTime, NaiveDate, TimeZone, Utc};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use finql_data::{Currency, DataError, DataItem, QuoteHandler, TickerUsage, TransactionHandler};
+
+use crate::portfolio::get_open_lots;
+use crate::time_period::TimePeriod;
+
+/// Amounts below this threshold are considered to be zero
+const AMOUNT_TOLERANCE: f64 = 1e-8;
+
+/// Error related to the simulation of withdrawal plans
+#[derive(Debug)]
+pub enum WithdrawalError {
+    DBError(DataError),
+    InvalidPlan(String),
+}
+
+impl fmt::Display for WithdrawalError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::DBError(_) => write!(f, "database error"),
+            Self::InvalidPlan(reason) => write!(f, "invalid withdrawal plan: {}", reason),
+        }
+    }
+}
+
+impl Error for WithdrawalError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::DBError(err) => Some(err),
+            Self::InvalidPlan(_) => None,
+        }
+    }
+}
+
+impl From<DataError> for WithdrawalError {
+    fn from(error: DataError) -> Self {
+        Self::DBError(error)
+    }
+}
+
+/// Assets and currency of the portfolio the withdrawals are funded from
+#[derive(Debug, Clone)]
+pub struct PortfolioScope {
+    pub asset_ids: Vec<usize>,
+    /// Currency of the withdrawals, all transactions and quotes must be in this currency
+    pub currency: Currency,
+}
+
+/// Order in which holdings are sold to fund withdrawals
+#[derive(Debug, Clone)]
+pub enum WithdrawalOrder {
+    /// Use cash first, then sell assets in the order given by the portfolio scope
+    CashFirst,
+    /// Use cash and sell assets proportionally to their current value
+    Proportional,
+    /// Use cash first, then sell the assets that exceed their target allocation (relative weight
+    /// by asset id) most. Target weights are normalized, assets without target weight are
+    /// sold first.
+    LargestOverweightFirst(BTreeMap<usize, f64>),
+}
+
+/// Fees charged per sale
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FeeModel {
+    pub fixed: f64,
+    /// Fee as fraction of the sold value
+    pub proportional: f64,
+}
+
+impl FeeModel {
+    fn fee(&self, value: f64) -> f64 {
+        self.fixed + self.proportional * value
+    }
+}
+
+/// Model for the prices of the assets during the simulation
+#[derive(Debug, Clone, Copy)]
+pub enum ReturnModel {
+    /// Prices are taken from the quotes stored in the database
+    Historical,
+    /// Prices start at the historical prices of the start date and evolve with independent,
+    /// normally distributed monthly log returns
+    MonteCarlo { mean: f64, std_dev: f64, seed: u64 },
+}
+
+/// Parameters of a withdrawal plan
+#[derive(Debug, Clone)]
+pub struct WithdrawalPlan {
+    pub monthly_amount: f64,
+    /// Date of the first withdrawal
+    pub start: NaiveDate,
+    /// Maximum number of monthly withdrawals
+    pub horizon: usize,
+    pub order: WithdrawalOrder,
+    pub fees: FeeModel,
+    /// Flat tax rate on realized gains
+    pub tax_rate: f64,
+}
+
+/// Result of a withdrawal plan simulation
+#[derive(Debug, Clone)]
+pub struct WithdrawalResult {
+    /// Date of the first withdrawal that could not be funded in full, if any
+    pub depletion_date: Option<NaiveDate>,
+    /// Portfolio value after each withdrawal
+    pub value_path: Vec<(NaiveDate, f64)>,
+    pub total_withdrawn: f64,
+    pub total_taxes: f64,
+    pub total_fees: f64,
+}
+
+/// Open position in a single asset
+struct Holding {
+    asset_id: usize,
+    /// Open lots as pairs of position and unit price, in FIFO order
+    lots: VecDeque<(f64, f64)>,
+    price: f64,
+    history: PriceHistory,
+}
+
+/// Result of selling a holding
+struct Sale {
+    net: f64,
+    fee: f64,
+    tax: f64,
+}
+
+impl Holding {
+    fn position(&self) -> f64 {
+        self.lots.iter().map(|(position, _)| position).sum()
+    }
+
+    fn value(&self) -> f64 {
+        self.position() * self.price
+    }
+
+    /// Proceeds, fees and taxes of selling the given value, without changing the holding
+    fn sale(&self, value: f64, fees: &FeeModel, tax_rate: f64) -> Sale {
+        let mut remaining = value / self.price;
+        let mut cost = 0.;
+        for (position, unit_price) in &self.lots {
+            let quantity = remaining.min(*position);
+            cost += quantity * unit_price;
+            remaining -= quantity;
+            if remaining <= 0. {
+                break;
+            }
+        }
+        let fee = fees.fee(value);
+        let tax = tax_rate * (value - cost).max(0.);
+        Sale {
+            net: value - fee - tax,
+            fee,
+            tax,
+        }
+    }
+
+    /// Sell the given value closing lots in FIFO order
+    fn sell(&mut self, value: f64, fees: &FeeModel, tax_rate: f64) -> Sale {
+        let sale = self.sale(value, fees, tax_rate);
+        let mut remaining = value / self.price;
+        while let Some((position, unit_price)) = self.lots.pop_front() {
+            if position > remaining + AMOUNT_TOLERANCE {
+                self.lots.push_front((position - remaining, unit_price));
+                break;
+            }
+            remaining -= position;
+        }
+        sale
+    }
+
+    /// Sell as much as required to receive the given net proceeds, or everything
+    /// if the holding is not sufficient
+    fn sell_net(&mut self, net: f64, fees: &FeeModel, tax_rate: f64) -> Sale {
+        let value = self.value();
+        if self.sale(value, fees, tax_rate).net <= net {
+            return self.sell(value, fees, tax_rate);
+        }
+        // net proceeds are increasing in the sold value, bisect up to full precision
+        let (mut low, mut high) = (0., value);
+        for _ in 0..f64::MANTISSA_DIGITS {
+            let mid = (low + high) / 2.;
+            if self.sale(mid, fees, tax_rate).net < net {
+                low = mid;
+            } else {
+                high = mid;
+            }
+        }
+        self.sell(high, fees, tax_rate)
+    }
+}
+
+/// State of the simulated portfolio
+struct SimulatedPortfolio {
+    cash: f64,
+    holdings: Vec<Holding>,
+    total_taxes: f64,
+    total_fees: f64,
+}
+
+impl SimulatedPortfolio {
+    fn value(&self) -> f64 {
+        self.cash + self.holdings.iter().map(Holding::value).sum::<f64>()
+    }
+
+    /// Sell holding `index` for the given net proceeds and add them to cash
+    fn raise_cash(&mut self, index: usize, net: f64, plan: &WithdrawalPlan) {
+        if net <= AMOUNT_TOLERANCE || self.holdings[index].value() <= AMOUNT_TOLERANCE {
+            return;
+        }
+        let sale = self.holdings[index].sell_net(net, &plan.fees, plan.tax_rate);
+        self.cash += sale.net;
+        self.total_fees += sale.fee;
+        self.total_taxes += sale.tax;
+    }
+
+    /// Sell assets to raise the cash required for the withdrawal of `amount`
+    fn fund(&mut self, amount: f64, plan: &WithdrawalPlan) {
+        let need = amount - self.cash;
+        if need <= AMOUNT_TOLERANCE {
+            return;
+        }
+        let values: Vec<f64> = self.holdings.iter().map(Holding::value).collect();
+        let asset_value: f64 = values.iter().sum();
+        match &plan.order {
+            WithdrawalOrder::CashFirst => {
+                for i in 0..self.holdings.len() {
+                    let need = amount - self.cash;
+                    self.raise_cash(i, need, plan);
+                }
+            }
+            WithdrawalOrder::Proportional => {
+                // cash only covers its share of the withdrawal
+                let total = self.cash + asset_value;
+                for (i, value) in values.iter().enumerate() {
+                    self.raise_cash(i, amount * value / total, plan);
+                }
+            }
+            WithdrawalOrder::LargestOverweightFirst(targets) => {
+                let total_target: f64 = targets.values().sum();
+                let remaining_value = asset_value - need;
+                let mut overweights: Vec<(usize, f64)> = self
+                    .holdings
+                    .iter()
+                    .zip(&values)
+                    .enumerate()
+                    .map(|(i, (holding, value))| {
+                        let target = targets.get(&holding.asset_id).copied().unwrap_or(0.);
+                        (i, value - target / total_target * remaining_value)
+                    })
+                    .collect();
+                overweights.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap());
+                let mut remaining = need;
+                for (i, overweight) in overweights {
+                    let net = remaining.min(overweight);
+                    if net <= AMOUNT_TOLERANCE {
+                        break;
+                    }
+                    let cash = self.cash;
+                    self.raise_cash(i, net, plan);
+                    remaining -= self.cash - cash;
+                }
+            }
+        }
+        // cover any shortfall, e.g. caused by fees exceeding a holding's value
+        for i in 0..self.holdings.len() {
+            let need = amount - self.cash;
+            self.raise_cash(i, need, plan);
+        }
+    }
+}
+
+/// Standard normally distributed random number (Box-Muller transform)
+fn standard_normal(rng: &mut StdRng) -> f64 {
+    let u1: f64 = 1. - rng.gen::<f64>();
+    let u2: f64 = rng.gen();
+    (-2. * u1.ln()).sqrt() * (2. * std::f64::consts::PI * u2).cos()
+}
+
+/// Quotes of an asset of all ticker usable for valuation
+pub(crate) struct PriceHistory {
+    /// Price and ticker priority by quote time
+    quotes: BTreeMap<DateTime<Utc>, (f64, i32)>,
+}
+
+impl PriceHistory {
+    pub(crate) fn load(
+        db: &mut dyn QuoteHandler,
+        asset_id: usize,
+        currency: Currency,
+    ) -> Result<PriceHistory, DataError> {
+        let mut quotes = BTreeMap::new();
+        for ticker in db.get_all_ticker_for_asset_and_usage(asset_id, TickerUsage::Valuation)? {
+            if ticker.currency != currency {
+                return Err(DataError::CurrencyMismatch(format!(
+                    "ticker {} is in {} instead of {}",
+                    ticker.name, ticker.currency, currency
+                )));
+            }
+            for quote in db.get_all_quotes_for_ticker(ticker.get_id()?)? {
+                let better = match quotes.get(&quote.time) {
+                    Some((_, priority)) => ticker.priority < *priority,
+                    None => true,
+                };
+                if better {
+                    quotes.insert(quote.time, (quote.price, ticker.priority));
+                }
+            }
+        }
+        Ok(PriceHistory { quotes })
+    }
+
+    /// Last price quoted on or before the given date
+    pub(crate) fn price(&self, asset_id: usize, date: NaiveDate) -> Result<f64, DataError> {
+        let time = Utc.from_utc_datetime(&date.and_hms(23, 59, 59));
+        self.quotes
+            .range(..=time)
+            .next_back()
+            .map(|(_, (price, _))| *price)
+            .ok_or_else(|| {
+                DataError::NotFound(format!("no quote for asset {} until {}", asset_id, date))
+            })
+    }
+}
+
+/// Set up the portfolio from cash balance and open lots at the start date
+fn initial_portfolio<DB: QuoteHandler + TransactionHandler>(
+    db: &mut DB,
+    scope: &PortfolioScope,
+    start: NaiveDate,
+) -> Result<SimulatedPortfolio, WithdrawalError> {
+    let mut cash = 0.;
+    for transaction in db.get_all_transactions()? {
+        if transaction.cash_flow.date > start {
+            continue;
+        }
+        if transaction.cash_flow.amount.currency != scope.currency {
+            return Err(DataError::CurrencyMismatch(format!(
+                "transaction {:?} is in {} instead of {}",
+                transaction.id, transaction.cash_flow.amount.currency, scope.currency
+            ))
+            .into());
+        }
+        cash += transaction.cash_flow.amount.amount;
+    }
+    let mut holdings = Vec::new();
+    for asset_id in &scope.asset_ids {
+        let lots = get_open_lots(db, *asset_id, start)?
+            .into_iter()
+            .map(|lot| (lot.position, lot.unit_price))
+            .collect();
+        let history = PriceHistory::load(db, *asset_id, scope.currency)?;
+        holdings.push(Holding {
+            asset_id: *asset_id,
+            lots,
+            price: history.price(*asset_id, start)?,
+            history,
+        });
+    }
+    Ok(SimulatedPortfolio {
+        cash,
+        holdings,
+        total_taxes: 0.,
+        total_fees: 0.,
+    })
+}
+
+/// Simulate monthly withdrawals of a fixed amount from the portfolio given by `scope`,
+/// starting at `plan.start` and ending after `plan.horizon` withdrawals or as soon as the
+/// portfolio is depleted.
+pub fn simulate_withdrawal_plan<DB: QuoteHandler + TransactionHandler>(
+    db: &mut DB,
+    scope: &PortfolioScope,
+    plan: &WithdrawalPlan,
+    model: ReturnModel,
+) -> Result<WithdrawalResult, WithdrawalError> {
+    if !(plan.monthly_amount > 0. && plan.monthly_amount.is_finite()) {
+        return Err(WithdrawalError::InvalidPlan(
+            "monthly amount must be positive".to_string(),
+        ));
+    }
+    if !(0. ..1.).contains(&plan.tax_rate) {
+        return Err(WithdrawalError::InvalidPlan(
+            "tax rate must be at least 0 and below 1".to_string(),
+        ));
+    }
+    if let WithdrawalOrder::LargestOverweightFirst(targets) = &plan.order {
+        if targets.values().sum::<f64>() <= 0. || targets.values().any(|t| *t < 0.) {
+            return Err(WithdrawalError::InvalidPlan(
+                "target weights must not be negative and sum up to a positive value".to_string(),
+            ));
+        }
+    }
+    let mut portfolio = initial_portfolio(db, scope, plan.start)?;
+    let mut rng = match model {
+        ReturnModel::MonteCarlo { seed, .. } => Some(StdRng::seed_from_u64(seed)),
+        ReturnModel::Historical => None,
+    };
+    let mut result = WithdrawalResult {
+        depletion_date: None,
+        value_path: Vec::new(),
+        total_withdrawn: 0.,
+        total_taxes: 0.,
+        total_fees: 0.,
+    };
+    for month in 0..plan.horizon {
+        let date = TimePeriod::from_str(&format!("{}M", month))
+            .unwrap()
+            .add_to(plan.start, None);
+        if month > 0 {
+            for holding in portfolio.holdings.iter_mut() {
+                holding.price = match (model, rng.as_mut()) {
+                    (ReturnModel::MonteCarlo { mean, std_dev, .. }, Some(rng)) => {
+                        holding.price * (mean + std_dev * standard_normal(rng)).exp()
+                    }
+                    _ => holding.history.price(holding.asset_id, date)?,
+                };
+            }
+        }
+        portfolio.fund(plan.monthly_amount, plan);
+        let withdrawal = plan.monthly_amount.min(portfolio.cash);
+        portfolio.cash -= withdrawal;
+        result.total_withdrawn += withdrawal;
+        result.value_path.push((date, portfolio.value()));
+        if withdrawal < plan.monthly_amount - AMOUNT_TOLERANCE {
+            result.depletion_date = Some(date);
+            break;
+        }
+    }
+    result.total_taxes = portfolio.total_taxes;
+    result.total_fees = portfolio.total_fees;
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rusqlite::Connection;
+
+    use finql_data::{Asset, AssetHandler, CashFlow, Quote, Ticker, Transaction, TransactionType};
+    use finql_sqlite::SqliteDB;
+
+    fn eur() -> Currency {
+        Currency::from_str("EUR").unwrap()
+    }
+
+    fn transaction(transaction_type: TransactionType, amount: f64) -> Transaction {
+        Transaction {
+            id: None,
+            transaction_type,
+            cash_flow: CashFlow::new(amount, eur(), NaiveDate::from_ymd(2020, 1, 2)),
+            note: None,
+            execution_meta: None,
+            recorded_at: None,
+        }
+    }
+
+    /// Buy `position` units at a unit price of 10 and add monthly quotes with the given prices
+    fn add_asset(db: &mut SqliteDB, name: &str, position: f64, prices: &[f64]) -> usize {
+        let asset_id = db
+            .insert_asset(&Asset::new(None, name, None, None, None))
+            .unwrap();
+        db.insert_transaction(&transaction(TransactionType::Cash, 10. * position))
+            .unwrap();
+        db.insert_transaction(&transaction(
+            TransactionType::Asset { asset_id, position },
+            -10. * position,
+        ))
+        .unwrap();
+        let ticker = db
+            .insert_ticker(&Ticker {
+                id: None,
+                name: name.to_string(),
+                asset: asset_id,
+                source: "manual".to_string(),
+                priority: 1,
+                currency: eur(),
+                factor: 1.0,
+                source_url: None,
+                usage: TickerUsage::Both,
+                last_quote_time: None,
+                source_chain: Vec::new(),
+            })
+            .unwrap();
+        for (month, price) in prices.iter().enumerate() {
+            db.insert_quote(&Quote {
+                id: None,
+                ticker,
+                price: *price,
+                time: Utc.ymd(2020, 2 + month as u32, 1).and_hms(17, 0, 0),
+                volume: None,
+                quality_score: None,
+                source: None,
+                open: None,
+                high: None,
+                low: None,
+                bid: None,
+                ask: None,
+            })
+            .unwrap();
+        }
+        asset_id
+    }
+
+    fn plan(monthly_amount: f64, order: WithdrawalOrder) -> WithdrawalPlan {
+        WithdrawalPlan {
+            monthly_amount,
+            start: NaiveDate::from_ymd(2020, 2, 1),
+            horizon: 6,
+            order,
+            fees: FeeModel::default(),
+            tax_rate: 0.,
+        }
+    }
+
+    #[test]
+    fn historical_withdrawal_plan() {
+        let tol = 1e-6;
+        let conn = Connection::open(":memory:").unwrap();
+        let mut db = SqliteDB::new(&conn);
+        db.init().unwrap();
+        let asset_id = add_asset(&mut db, "A", 100., &[10., 10., 20., 20., 20.]);
+        db.insert_transaction(&transaction(TransactionType::Cash, 100.))
+            .unwrap();
+        let scope = PortfolioScope {
+            asset_ids: vec![asset_id],
+            currency: eur(),
+        };
+
+        let mut plan = plan(300., WithdrawalOrder::CashFirst);
+        let result =
+            simulate_withdrawal_plan(&mut db, &scope, &plan, ReturnModel::Historical).unwrap();
+        let values: Vec<f64> = result.value_path.iter().map(|(_, v)| *v).collect();
+        // 100 cash + 1000 in asset, price doubles before the third withdrawal
+        assert_fuzzy_eq!(values[0], 800., tol);
+        assert_fuzzy_eq!(values[1], 500., tol);
+        assert_fuzzy_eq!(values[2], 700., tol);
+        // the remaining 100 do not suffice for the last withdrawal
+        assert_eq!(result.depletion_date, Some(NaiveDate::from_ymd(2020, 7, 1)));
+        assert_eq!(result.value_path.len(), 6);
+        assert_fuzzy_eq!(result.total_withdrawn, 1600., tol);
+
+        // fees and taxes on gains of 10 per unit sold at a price of 20
+        plan.fees.fixed = 1.;
+        plan.tax_rate = 0.25;
+        let result =
+            simulate_withdrawal_plan(&mut db, &scope, &plan, ReturnModel::Historical).unwrap();
+        assert!(result.total_fees > 0. && result.total_taxes > 0.);
+        let (date, value) = result.value_path[2];
+        assert_eq!(date, NaiveDate::from_ymd(2020, 4, 1));
+        // the first two sales include the fixed fee, at a price of 20 half of the
+        // proceeds are gains, i.e. taxes are 12.5% of the proceeds
+        let sold = (300. + 1.) / (20. * (1. - 0.125));
+        assert_fuzzy_eq!(value, (100. - 20.1 - 30.1 - sold) * 20., 1e-4);
+    }
+
+    #[test]
+    fn withdrawal_orders() {
+        let tol = 1e-6;
+        let conn = Connection::open(":memory:").unwrap();
+        let mut db = SqliteDB::new(&conn);
+        db.init().unwrap();
+        let a = add_asset(&mut db, "A", 60., &[10.; 6]);
+        let b = add_asset(&mut db, "B", 40., &[10.; 6]);
+        let scope = PortfolioScope {
+            asset_ids: vec![a, b],
+            currency: eur(),
+        };
+        let remaining_units = |result: &WithdrawalResult| result.value_path[0].1 / 10.;
+
+        let result = simulate_withdrawal_plan(
+            &mut db,
+            &scope,
+            &plan(100., WithdrawalOrder::Proportional),
+            ReturnModel::Historical,
+        )
+        .unwrap();
+        assert_fuzzy_eq!(remaining_units(&result), 90., tol);
+
+        // target 50/50 allocation, A is overweight by 100
+        let targets: BTreeMap<usize, f64> = vec![(a, 1.), (b, 1.)].into_iter().collect();
+        let mut overweight_plan = plan(100., WithdrawalOrder::LargestOverweightFirst(targets));
+        overweight_plan.horizon = 1;
+        let mut portfolio = initial_portfolio(&mut db, &scope, overweight_plan.start).unwrap();
+        portfolio.fund(100., &overweight_plan);
+        assert_fuzzy_eq!(portfolio.holdings[0].position(), 50., tol);
+        assert_fuzzy_eq!(portfolio.holdings[1].position(), 40., tol);
+    }
+
+    #[test]
+    fn seeded_monte_carlo_withdrawal_plan() {
+        let conn = Connection::open(":memory:").unwrap();
+        let mut db = SqliteDB::new(&conn);
+        db.init().unwrap();
+        let asset_id = add_asset(&mut db, "A", 100., &[10.]);
+        let scope = PortfolioScope {
+            asset_ids: vec![asset_id],
+            currency: eur(),
+        };
+        let mut plan = plan(50., WithdrawalOrder::CashFirst);
+        plan.horizon = 12;
+        let model = |seed| ReturnModel::MonteCarlo {
+            mean: 0.005,
+            std_dev: 0.05,
+            seed,
+        };
+        let first = simulate_withdrawal_plan(&mut db, &scope, &plan, model(42)).unwrap();
+        let second = simulate_withdrawal_plan(&mut db, &scope, &plan, model(42)).unwrap();
+        let other = simulate_withdrawal_plan(&mut db, &scope, &plan, model(7)).unwrap();
+        assert_eq!(first.value_path.len(), 12);
+        assert_eq!(first.value_path, second.value_path);
+        assert_ne!(first.value_path, other.value_path);
+        assert_eq!(first.depletion_date, None);
+    }
+}